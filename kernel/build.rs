@@ -2,15 +2,26 @@ fn main() {
     // Inform cargo to rerun if linker script changes
     println!("cargo:rerun-if-changed=linker.ld");
 
+    // doomgeneric is not in the repo: it is built only once its sources
+    // are copied to src/vendor/doomgeneric, and the kernel side of the
+    // bridge is behind cfg(doomgeneric)
+    println!("cargo:rustc-check-cfg=cfg(doomgeneric)");
+    println!("cargo:rerun-if-changed=src/vendor/doomgeneric");
+    println!("cargo:rerun-if-changed=src/doomgeneric/libc_compat.c");
+    if !std::path::Path::new("src/vendor/doomgeneric/doomgeneric.c").exists() {
+        return;
+    }
+    println!("cargo:rustc-cfg=doomgeneric");
+
     // Build doomgeneric C sources (vendor)
     let mut build = cc::Build::new();
     build.include("src/doomgeneric");
     build.include("src/vendor/doomgeneric");
-    // Add our glue
-    build.file("src/doomgeneric/doomgeneric_syscalls.c");
-    // Add ospab port glue
-    build.file("src/doomgeneric/doomgeneric_ospab.c");
-    // Add libc compat helpers (stdio/malloc wrappers)
+    // Same resolution as crate::doom's buffer, which DG_DrawFrame fills
+    build.define("DOOMGENERIC_RESX", "320");
+    build.define("DOOMGENERIC_RESY", "200");
+    // The platform callbacks (DG_*) are in Rust, in doomgeneric::ffi;
+    // this is the libc the engine expects (stdio, strings, ctype)
     build.file("src/doomgeneric/libc_compat.c");
     // Add all .c files from vendor doomgeneric
     // Recursively add all .c files under vendor/doomgeneric
@@ -24,7 +35,7 @@ fn main() {
                 if ext == "c" {
                     let name = path.file_name().unwrap().to_string_lossy().to_lowercase();
                     // Skip platform-specific/front-end files that require external libraries
-                    if name.contains("allegro") || name.contains("sdl") || name.contains("win") || name.contains("xlib") || name.contains("soso") || name.contains("sosox") || name.contains("emscripten") {
+                    if name.contains("allegro") || name.contains("sdl") || name.contains("win") || name.contains("xlib") || name.contains("soso") || name.contains("sosox") || name.contains("emscripten") || name.contains("linuxvt") {
                         continue;
                     }
                     build.file(path);
@@ -34,6 +45,14 @@ fn main() {
     }
     add_c_files(std::path::Path::new("src/vendor/doomgeneric"), &mut build);
 
+    // Kernel code: no red zone (interrupts land on the same stack), the
+    // kernel code model, no libc startup or stack protector
+    build.flag("-ffreestanding");
+    build.flag("-fno-stack-protector");
+    build.flag("-fno-pic");
+    build.flag("-mno-red-zone");
+    build.flag("-mcmodel=kernel");
+
     // Suppress warnings from vendor code
     build.flag("-w"); // Disable all warnings from C code
     build.flag("-Wno-unused-parameter");
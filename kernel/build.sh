@@ -28,7 +28,7 @@ USER_SHELL_DIR="/mnt/d/ospab-projects/ospab.os/user/shell"
 USER_SHELL_TARGET="$KERNEL_DIR/x86_64-ospab.json"
 if [ -d "$USER_SHELL_DIR" ]; then
     cd "$USER_SHELL_DIR"
-    cargo +nightly build --release -Z build-std=core --target "$USER_SHELL_TARGET"
+    cargo +nightly build --release -Z build-std=core,alloc --target "$USER_SHELL_TARGET"
     mkdir -p "$KERNEL_DIR/initrd/bin"
     cp "$USER_SHELL_DIR/target/x86_64-ospab/release/ospabshell" "$KERNEL_DIR/initrd/bin/ospabshell"
     cd "$KERNEL_DIR"
@@ -36,6 +36,21 @@ else
     echo "WARN: user shell not found at $USER_SHELL_DIR"
 fi
 
+echo "--- Building Coreutils ---"
+COREUTILS_DIR="$(dirname "$USER_SHELL_DIR")/coreutils"
+if [ -d "$COREUTILS_DIR" ]; then
+    cd "$COREUTILS_DIR"
+    cargo +nightly build --release -Z build-std=core,alloc --target "$USER_SHELL_TARGET"
+    mkdir -p "$KERNEL_DIR/initrd/bin"
+    for tool in ls cat echo wc rm rmdir xxd; do
+        cp "$COREUTILS_DIR/target/x86_64-ospab/release/$tool" "$KERNEL_DIR/initrd/bin/$tool"
+    done
+    cp "$COREUTILS_DIR/target/x86_64-ospab/release/xxd" "$KERNEL_DIR/initrd/bin/hexdump"
+    cd "$KERNEL_DIR"
+else
+    echo "WARN: coreutils not found at $COREUTILS_DIR"
+fi
+
 echo "--- Preparing ISO Root ---"
 rm -rf /tmp/iso_root
 # ВАЖНО: Создаем именно ту структуру, которую ищет Limine на твоих скринах
@@ -69,6 +84,12 @@ if [ -d "$KERNEL_DIR/initrd" ]; then
     tar --format=ustar -C "$KERNEL_DIR/initrd" -cf /tmp/iso_root/initrd/initrd.tar .
 fi
 
+# DOOM WADs are not in the repo: drop doom1.wad into kernel/wads/ and it
+# is shipped as a module, where `doom` finds it under /boot/modules
+if [ -d "$KERNEL_DIR/wads" ]; then
+    find "$KERNEL_DIR/wads" -maxdepth 1 -type f -iname "*.wad" -exec cp {} /tmp/iso_root/initrd/ \;
+fi
+
 # Добавляем все .sh/.bash файлы в initrd
 REPO_DIR="$(dirname "$KERNEL_DIR")"
 find "$REPO_DIR" -type f \( -name "*.sh" -o -name "*.bash" \) -print0 | while IFS= read -r -d '' f; do
@@ -81,6 +102,8 @@ for f in /tmp/iso_root/initrd/*; do
     if [ -f "$f" ]; then
         name="$(basename "$f")"
         echo "    module_path: boot():/initrd/$name" >> "$TMP_CONF"
+        # The kernel finds modules by this name (boot::limine::find_module)
+        echo "    module_cmdline: $name" >> "$TMP_CONF"
     fi
 done
 
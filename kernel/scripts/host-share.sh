@@ -0,0 +1,21 @@
+#!/bin/sh
+# Print QEMU -fw_cfg arguments that share a host directory with the guest.
+# The files show up read-only under /host, e.g.:
+#
+#   qemu-system-x86_64 -cdrom ospab-os.iso $(scripts/host-share.sh ../user/build)
+#
+# fw_cfg names are limited to 56 bytes ("opt/ospab/" included) and QEMU only
+# has a few dozen file slots, so keep the shared tree small.
+set -e
+
+dir=${1:?usage: host-share.sh <directory>}
+cd "$dir"
+find . -type f | sed 's|^\./||' | sort | while read -r path; do
+    name="opt/ospab/$path"
+    if [ ${#name} -gt 55 ]; then
+        echo "host-share: skipping $path (name too long)" >&2
+        continue
+    fi
+    printf -- '-fw_cfg name=%s,file=%s/%s ' "$name" "$(pwd)" "$path"
+done
+echo
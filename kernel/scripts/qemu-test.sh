@@ -0,0 +1,62 @@
+#!/bin/bash
+# Cargo runner (.cargo/config.toml): boot the kernel ELF given as $1 in
+# QEMU from a throwaway BIOS ISO.
+#
+# Test kernels (cargo test puts them in deps/) run without a display and
+# stop QEMU through the isa-debug-exit device (src/testing.rs): exit
+# status 33 means every test passed and becomes 0 here, anything else 1.
+# OSPAB_TEST_TIMEOUT (seconds, default 120) bounds a hung test run.
+set -euo pipefail
+
+KERNEL_DIR="$(cd "$(dirname "$0")/.." && pwd)"
+LIMINE_BIN_DIR="$KERNEL_DIR/tools/limine/bin"
+LIMINE_CD="$KERNEL_DIR/iso_build/boot/limine/limine-bios-cd.bin"
+TIMEOUT="${OSPAB_TEST_TIMEOUT:-120}"
+
+KERNEL="$1"
+shift
+
+WORK="$(mktemp -d)"
+trap 'rm -rf "$WORK"' EXIT
+
+mkdir -p "$WORK/iso/boot/limine"
+cp "$KERNEL" "$WORK/iso/boot/ospab-os"
+cp "$LIMINE_CD" "$LIMINE_BIN_DIR/limine-bios.sys" "$WORK/iso/boot/limine/"
+cat > "$WORK/iso/boot/limine/limine.conf" <<CONF
+timeout: 0
+serial: yes
+
+/ospabOS
+    protocol: limine
+    kernel_path: boot():/boot/ospab-os
+CONF
+
+xorriso -as mkisofs -R -J \
+    -b boot/limine/limine-bios-cd.bin \
+    -no-emul-boot -boot-load-size 4 -boot-info-table \
+    "$WORK/iso" -o "$WORK/ospab.iso" >/dev/null 2>&1
+if [ -x "$LIMINE_BIN_DIR/limine" ]; then
+    "$LIMINE_BIN_DIR/limine" bios-install "$WORK/ospab.iso" >/dev/null 2>&1
+fi
+
+QEMU_ARGS=(-cdrom "$WORK/ospab.iso" -m 256M -serial stdio
+    -device isa-debug-exit,iobase=0xf4,iosize=0x04)
+
+case "$KERNEL" in
+    */deps/*)
+        # Arguments after the binary are test filters, which a test
+        # kernel cannot take
+        set +e
+        timeout "$TIMEOUT" qemu-system-x86_64 "${QEMU_ARGS[@]}" -display none -no-reboot
+        status=$?
+        set -e
+        case $status in
+            33) exit 0 ;;
+            124) echo "qemu-test: no result after ${TIMEOUT}s" >&2; exit 1 ;;
+            *) exit 1 ;;
+        esac
+        ;;
+    *)
+        exec qemu-system-x86_64 "${QEMU_ARGS[@]}" "$@"
+        ;;
+esac
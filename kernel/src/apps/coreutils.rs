@@ -1,7 +1,9 @@
 //! Minimal coreutils implemented against the VFS service.
 
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
+use crate::fs::tar::{self, TarEntry};
 use crate::ipc::message::{FSRequest, FSResponse};
 use crate::services::vfs;
 
@@ -9,7 +11,7 @@ pub fn ls(path: &str) -> Result<Vec<String>, String> {
     let response = vfs::process_request(FSRequest::ListDir { path: path.to_string() });
     match response {
         FSResponse::DirListing(entries) => Ok(entries),
-        FSResponse::Error(msg) => Err(msg),
+        FSResponse::Error(e, _) => Err(e.to_string()),
         _ => Err("Unexpected response".to_string()),
     }
 }
@@ -17,8 +19,8 @@ pub fn ls(path: &str) -> Result<Vec<String>, String> {
 pub fn cat(path: &str) -> Result<Vec<u8>, String> {
     let response = vfs::process_request(FSRequest::ReadFile { path: path.to_string() });
     match response {
-        FSResponse::FileData(data) => Ok(data),
-        FSResponse::Error(msg) => Err(msg),
+        FSResponse::FileData(data) => Ok(data.into_vec()),
+        FSResponse::Error(e, _) => Err(e.to_string()),
         _ => Err("Unexpected response".to_string()),
     }
 }
@@ -27,7 +29,7 @@ pub fn mkdir(path: &str) -> Result<(), String> {
     let response = vfs::process_request(FSRequest::CreateDir { path: path.to_string() });
     match response {
         FSResponse::Success => Ok(()),
-        FSResponse::Error(msg) => Err(msg),
+        FSResponse::Error(e, _) => Err(e.to_string()),
         _ => Err("Unexpected response".to_string()),
     }
 }
@@ -40,7 +42,7 @@ pub fn cp(src: &str, dst: &str) -> Result<(), String> {
     });
     match response {
         FSResponse::Success => Ok(()),
-        FSResponse::Error(msg) => Err(msg),
+        FSResponse::Error(e, _) => Err(e.to_string()),
         _ => Err("Unexpected response".to_string()),
     }
 }
@@ -50,7 +52,81 @@ pub fn mv(src: &str, dst: &str) -> Result<(), String> {
     let response = vfs::process_request(FSRequest::Delete { path: src.to_string() });
     match response {
         FSResponse::Success => Ok(()),
-        FSResponse::Error(msg) => Err(msg),
+        FSResponse::Error(e, _) => Err(e.to_string()),
         _ => Err("Unexpected response".to_string()),
     }
 }
+
+pub fn rm(path: &str) -> Result<(), String> {
+    let response = vfs::process_request(FSRequest::Delete { path: path.to_string() });
+    match response {
+        FSResponse::Success => Ok(()),
+        FSResponse::Error(e, _) => Err(e.to_string()),
+        _ => Err("Unexpected response".to_string()),
+    }
+}
+
+/// Remove `path` and, for a directory, everything in it
+pub fn rm_tree(path: &str) -> Result<(), String> {
+    let response = vfs::process_request(FSRequest::DeleteTree { path: path.to_string() });
+    match response {
+        FSResponse::Success => Ok(()),
+        FSResponse::Error(e, _) => Err(e.to_string()),
+        _ => Err("Unexpected response".to_string()),
+    }
+}
+
+/// Make `path` a symbolic link to `target`
+pub fn symlink(target: &str, path: &str) -> Result<(), String> {
+    let response = vfs::process_request(FSRequest::Symlink { target: target.to_string(), path: path.to_string() });
+    match response {
+        FSResponse::Success => Ok(()),
+        FSResponse::Error(e, _) => Err(e.to_string()),
+        _ => Err("Unexpected response".to_string()),
+    }
+}
+
+/// Where the symbolic link at `path` points
+pub fn readlink(path: &str) -> Result<String, String> {
+    let response = vfs::process_request(FSRequest::ReadLink { path: path.to_string() });
+    match response {
+        FSResponse::LinkTarget(target) => Ok(target),
+        FSResponse::Error(e, _) => Err(e.to_string()),
+        _ => Err("Unexpected response".to_string()),
+    }
+}
+
+/// Pack the directory tree at `src` into a tar archive written to `dst`.
+/// Entry paths are relative to the parent of `src`.
+pub fn tar_dir(src: &str, dst: &str) -> Result<usize, String> {
+    let src = src.trim_end_matches('/');
+    let base = src.rsplit('/').next().unwrap_or(src);
+    let mut entries = Vec::new();
+    collect_tree(src, base, &mut entries)?;
+
+    let archive = tar::build_tar(&entries);
+    let response = vfs::process_request(FSRequest::WriteFile {
+        path: dst.to_string(),
+        data: archive,
+    });
+    match response {
+        FSResponse::Success => Ok(entries.len()),
+        FSResponse::Error(e, _) => Err(e.to_string()),
+        _ => Err("Unexpected response".to_string()),
+    }
+}
+
+fn collect_tree(path: &str, rel: &str, entries: &mut Vec<TarEntry>) -> Result<(), String> {
+    entries.push(TarEntry { path: rel.to_string(), data: Vec::new(), is_dir: true });
+    for name in ls(path)? {
+        let child = format!("{}/{}", path, name);
+        let child_rel = format!("{}/{}", rel, name);
+        // ListDir only succeeds on directories
+        if ls(&child).is_ok() {
+            collect_tree(&child, &child_rel, entries)?;
+        } else {
+            entries.push(TarEntry { path: child_rel, data: cat(&child)?, is_dir: false });
+        }
+    }
+    Ok(())
+}
@@ -1,3 +1,5 @@
 //! Userland-style utilities implemented in-kernel for now.
 
 pub mod coreutils;
+pub mod tomato;
+pub mod ymodem;
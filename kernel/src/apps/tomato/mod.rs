@@ -0,0 +1,453 @@
+//! tomato, the package manager, working on the VFS
+//!
+//! The repository index lives at /var/lib/tomato/available.toml, one table
+//! per package:
+//!
+//! ```text
+//! [hello]
+//! version = "1.0"
+//! description = "Prints a greeting"
+//! deps = ["libgreet", "base"]
+//! archive = "hello-1.0.tar"
+//! postinst = "mkdir /usr/share/hello"
+//! prerm = "echo removing hello"
+//! ```
+//!
+//! `archive` is a ustar file in /var/lib/tomato/packages (default
+//! `<name>-<version>.tar`) whose entries are unpacked under /usr, so
+//! `bin/hello` lands in /usr/bin/hello. Installed packages and their deps
+//! are kept in /var/lib/tomato/packages.txt like the hosted tomato-pm
+//! does, and the files each one put down in /var/lib/tomato/files/<name>.list.
+//!
+//! Packages installed only as dependencies are marked `auto`; removing a
+//! package others need takes `force`, and `autoremove` drops the `auto`
+//! ones nothing needs any more.
+//!
+//! `postinst` runs once a package is unpacked and `prerm` (kept in
+//! /var/lib/tomato/scripts) before its files are deleted, both through the
+//! shell's script runner. Hooks are plain command lines, and every command
+//! must be named in /etc/tomato/hooks.allow (or the default list when that
+//! file is missing). A refused or failing hook does not stop the install
+//! or removal; it is noted in /var/lib/tomato/journal.log.
+//!
+//! The TOML reader, the dependency resolver, the hook checks, the
+//! installed-package records and the paths above come from tomato-core,
+//! which tomato-pm is built on too. The database directories are laid out
+//! in the VFS at boot (see `services::vfs`).
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::apps::coreutils;
+use crate::fs::tar;
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+use tomato_core::installed::{self, Installed};
+use tomato_core::{hooks, paths, solver, toml, version};
+pub use tomato_core::version::Upgrade;
+
+/// A package in the repository index
+#[derive(Debug, Clone, Default)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub deps: Vec<String>,
+    /// Archive file name in /var/lib/tomato/packages
+    pub archive: String,
+    /// What changed in this version
+    pub changelog: String,
+    /// Run after the package is unpacked
+    pub postinst: String,
+    /// Run before the package's files are deleted
+    pub prerm: String,
+}
+
+/// Read the repository index
+pub fn index() -> Result<BTreeMap<String, Package>, String> {
+    let data = coreutils::cat(paths::INDEX).map_err(|e| format!("{}: {}", paths::INDEX, e))?;
+    let text = core::str::from_utf8(&data).map_err(|_| format!("{}: not UTF-8", paths::INDEX))?;
+    let parsed = toml::parse_toml(text).map_err(|e| format!("{}: {}", paths::INDEX, e))?;
+
+    let mut packages: BTreeMap<String, Package> = BTreeMap::new();
+    for (name, value) in parsed {
+        let Some(table) = value.as_table() else { continue };
+        let text = |field: &str| table.get(field).and_then(toml::Value::as_str).unwrap_or_default().to_string();
+        // An array, or the older "a, b" string
+        let deps = match table.get("deps") {
+            Some(toml::Value::String(list)) => list.split(',')
+                .map(|dep| dep.trim().to_string())
+                .filter(|dep| !dep.is_empty())
+                .collect(),
+            Some(value) => value.as_str_list().ok_or_else(|| format!("{}: {}.deps is not a list of names", paths::INDEX, name))?,
+            None => Vec::new(),
+        };
+        let version = text("version");
+        let archive = match text("archive") {
+            archive if archive.is_empty() => format!("{}-{}.tar", name, version),
+            archive => archive,
+        };
+        packages.insert(name.clone(), Package {
+            version,
+            description: text("description"),
+            deps,
+            archive,
+            changelog: text("changelog"),
+            postinst: text(hooks::POSTINST),
+            prerm: text(hooks::PRERM),
+            name,
+        });
+    }
+    Ok(packages)
+}
+
+/// Installed packages with their deps, in install order
+fn records() -> Vec<Installed> {
+    coreutils::cat(paths::INSTALLED)
+        .ok()
+        .and_then(|data| String::from_utf8(data).ok())
+        .map(|text| installed::parse_installed(&text))
+        .unwrap_or_default()
+}
+
+/// Names of the installed packages, in install order
+pub fn installed() -> Vec<String> {
+    records().into_iter().map(|package| package.name).collect()
+}
+
+fn save_installed(packages: &[Installed]) -> Result<(), String> {
+    write_file(paths::INSTALLED, installed::serialize_installed(packages).into_bytes())
+}
+
+fn write_file(path: &str, data: Vec<u8>) -> Result<(), String> {
+    match vfs::process_request(FSRequest::WriteFile { path: path.to_string(), data }) {
+        FSResponse::Success => Ok(()),
+        FSResponse::Error(e, _) => Err(format!("{}: {}", path, e)),
+        _ => Err(format!("{}: unexpected response", path)),
+    }
+}
+
+/// Create `path` and any missing parents
+fn mkdir_p(path: &str) -> Result<(), String> {
+    let mut current = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        current.push('/');
+        current.push_str(component);
+        if coreutils::ls(&current).is_err() {
+            coreutils::mkdir(&current).map_err(|e| format!("{}: {}", current, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Unpack `package`'s archive under /usr; returns the files written
+fn unpack(package: &Package) -> Result<Vec<String>, String> {
+    let archive_path = format!("{}/{}", paths::ARCHIVE_DIR, package.archive);
+    let archive = coreutils::cat(&archive_path).map_err(|e| format!("{}: {}", archive_path, e))?;
+
+    let mut files = Vec::new();
+    for entry in tar::index_tar(&archive) {
+        let relative = entry.path.trim_start_matches("./").trim_matches('/');
+        if relative.is_empty() || relative.split('/').any(|part| part == "..") {
+            continue;
+        }
+        let path = format!("{}/{}", paths::PREFIX, relative);
+        if entry.is_dir {
+            mkdir_p(&path)?;
+            continue;
+        }
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            mkdir_p(parent)?;
+        }
+        write_file(&path, entry.data.to_vec())?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// `name` and whatever it needs, dependencies first
+fn resolve(index: &BTreeMap<String, Package>, name: &str) -> Result<Vec<String>, String> {
+    let available: BTreeMap<String, Vec<String>> = index.iter()
+        .map(|(name, package)| (name.clone(), package.deps.clone()))
+        .collect();
+    solver::resolve_dependencies(name, &available)
+}
+
+/// Unpack `package` and record the files it put down, and its `prerm`
+fn unpack_recorded(package: &Package) -> Result<(), String> {
+    mkdir_p(paths::FILES_DIR)?;
+    let files = unpack(package)?;
+    write_file(&format!("{}/{}.list", paths::FILES_DIR, package.name), files.join("\n").into_bytes())?;
+    if !package.prerm.is_empty() {
+        mkdir_p(paths::SCRIPTS_DIR)?;
+        write_file(&format!("{}/{}.{}", paths::SCRIPTS_DIR, package.name, hooks::PRERM), package.prerm.clone().into_bytes())?;
+    }
+    Ok(())
+}
+
+/// Run `name`'s `hook` through the shell if the allowlist passes every
+/// command in it; a refused or failing hook is logged, not returned
+fn run_hook(name: &str, hook: &str, script: &str) {
+    if script.trim().is_empty() {
+        return;
+    }
+    let allowed = match coreutils::cat(paths::HOOKS_ALLOW) {
+        Ok(data) => hooks::parse_allowlist(&String::from_utf8_lossy(&data)),
+        Err(_) => hooks::default_allowlist(),
+    };
+    let outcome = hooks::check_script(script, &allowed).and_then(|_| {
+        match crate::shell::script::run(script, &[hook, name]) {
+            0 => Ok(()),
+            status => Err(format!("exited with status {}", status)),
+        }
+    });
+    if let Err(e) = outcome {
+        let mut log = coreutils::cat(paths::JOURNAL_LOG).unwrap_or_default();
+        log.extend_from_slice(format!("{} {} failed: {}\n", name, hook, e).as_bytes());
+        // The install or removal stands either way
+        let _ = write_file(paths::JOURNAL_LOG, log);
+    }
+}
+
+/// Run the `prerm` kept for installed package `name`
+fn run_prerm(name: &str) {
+    let path = format!("{}/{}.{}", paths::SCRIPTS_DIR, name, hooks::PRERM);
+    if let Ok(script) = coreutils::cat(&path) {
+        run_hook(name, hooks::PRERM, &String::from_utf8_lossy(&script));
+    }
+}
+
+/// Install `name` and whatever it depends on; returns the packages that
+/// were newly installed, in order
+pub fn install(name: &str) -> Result<Vec<String>, String> {
+    let index = index()?;
+    let order = resolve(&index, name)?;
+    let mut installed = records();
+    install_missing(&index, &order, Some(name), &mut installed)
+}
+
+/// Install the packages in `order` that are not yet in `installed`; all
+/// but `requested` are marked automatic
+fn install_missing(
+    index: &BTreeMap<String, Package>,
+    order: &[String],
+    requested: Option<&str>,
+    installed: &mut Vec<Installed>,
+) -> Result<Vec<String>, String> {
+    let mut added = Vec::new();
+    for package in order.iter().filter_map(|name| index.get(name)) {
+        if let Some(existing) = installed.iter_mut().find(|p| p.name == package.name) {
+            // Asked for by name now: no longer autoremovable
+            if requested == Some(package.name.as_str()) && existing.auto {
+                existing.auto = false;
+                save_installed(installed)?;
+            }
+            continue;
+        }
+        unpack_recorded(package)?;
+        run_hook(&package.name, hooks::POSTINST, &package.postinst);
+        installed.push(Installed {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            deps: package.deps.clone(),
+            auto: requested != Some(package.name.as_str()),
+        });
+        // Saved after each package so a failure later keeps what is done
+        save_installed(installed)?;
+        added.push(package.name.clone());
+    }
+    Ok(added)
+}
+
+/// Uninstall `name`, deleting the files it installed. Refuses while other
+/// installed packages depend on it unless `force` is set.
+pub fn remove(name: &str, force: bool) -> Result<(), String> {
+    let mut installed = records();
+    let position = installed.iter().position(|p| p.name == name)
+        .ok_or_else(|| format!("package {} not installed", name))?;
+    let dependents = installed::dependents(&installed, name);
+    if !dependents.is_empty() && !force {
+        return Err(format!("{} is needed by {} (use --force to remove anyway)", name, dependents.join(", ")));
+    }
+
+    run_prerm(name);
+    delete_files(name);
+    installed.remove(position);
+    save_installed(&installed)
+}
+
+/// Remove every automatically installed package nothing needs any more;
+/// returns their names
+pub fn autoremove() -> Result<Vec<String>, String> {
+    let mut installed = records();
+    let unused = installed::orphans(&installed);
+    for name in &unused {
+        run_prerm(name);
+        delete_files(name);
+    }
+    installed.retain(|p| !unused.contains(&p.name));
+    save_installed(&installed)?;
+    Ok(unused)
+}
+
+/// Delete the files recorded for `name`, the record itself and its `prerm`
+fn delete_files(name: &str) {
+    let _ = coreutils::rm(&format!("{}/{}.{}", paths::SCRIPTS_DIR, name, hooks::PRERM));
+    let list_path = format!("{}/{}.list", paths::FILES_DIR, name);
+    if let Ok(list) = coreutils::cat(&list_path) {
+        for file in String::from_utf8_lossy(&list).lines().filter(|line| !line.is_empty()) {
+            // Already gone is fine
+            let _ = coreutils::rm(file);
+        }
+        let _ = coreutils::rm(&list_path);
+    }
+}
+
+/// Fetch the index from `source`, or the one named in /etc/tomato/source,
+/// and install it as available.toml; returns how many packages it lists
+///
+/// Sources are VFS paths or `file://` URLs: there is no network stack to
+/// fetch `http://` ones yet.
+pub fn update(source: Option<&str>) -> Result<usize, String> {
+    let source = match source {
+        Some(source) => source.to_string(),
+        None => {
+            let data = coreutils::cat(paths::SOURCE).map_err(|e| format!("no source given and {}: {}", paths::SOURCE, e))?;
+            String::from_utf8_lossy(&data).trim().to_string()
+        }
+    };
+    if source.contains("://") && !source.starts_with("file://") {
+        return Err(format!("{}: only local sources are supported", source));
+    }
+    let path = source.trim_start_matches("file://");
+    let data = coreutils::cat(path).map_err(|e| format!("{}: {}", path, e))?;
+    let text = core::str::from_utf8(&data).map_err(|_| format!("{}: not UTF-8", path))?;
+    // Refuse to replace a working index with a broken one
+    toml::parse_toml(text).map_err(|e| format!("{}: {}", path, e))?;
+    write_file(paths::INDEX, data)?;
+    Ok(index()?.len())
+}
+
+/// Upgrades available for the installed packages, with the new index
+/// entry (for its changelog); applied unless `dry_run`. Dependencies the
+/// new versions add are installed as automatic packages.
+pub fn upgrade(dry_run: bool) -> Result<Vec<(Upgrade, Package)>, String> {
+    let index = index()?;
+    let versions: BTreeMap<String, String> = index.iter()
+        .map(|(name, package)| (name.clone(), package.version.clone()))
+        .collect();
+    let mut installed = records();
+    let planned: Vec<(Upgrade, Package)> = version::upgrades(&installed, &versions)
+        .into_iter()
+        .map(|upgrade| {
+            let package = index[&upgrade.name].clone();
+            (upgrade, package)
+        })
+        .collect();
+    if dry_run {
+        return Ok(planned);
+    }
+
+    for (_, package) in &planned {
+        delete_files(&package.name);
+        unpack_recorded(package)?;
+        if let Some(record) = installed.iter_mut().find(|p| p.name == package.name) {
+            record.version = package.version.clone();
+            record.deps = package.deps.clone();
+        }
+        save_installed(&installed)?;
+    }
+    for (_, package) in &planned {
+        let order = resolve(&index, &package.name)?;
+        install_missing(&index, &order, None, &mut installed)?;
+    }
+    Ok(planned)
+}
+
+/// Index entries whose name or description contains `query`
+pub fn search(query: &str) -> Result<Vec<Package>, String> {
+    Ok(index()?
+        .into_values()
+        .filter(|package| package.name.contains(query) || package.description.contains(query))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::toml::{parse_toml, serialize_toml, Table, Value};
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
+    fn get<'t>(table: &'t Table, path: &str) -> &'t Value {
+        let (last, parents) = path.rsplit_once('.').map_or((path, None), |(p, l)| (l, Some(p)));
+        let table = match parents {
+            Some(parents) => parents.split('.').fold(table, |t, part| t[part].as_table().unwrap()),
+            None => table,
+        };
+        &table[last]
+    }
+
+    #[test_case]
+    fn index_entries_parse_as_tables() {
+        let index = parse_toml("[hello]\nversion = \"1.0.2\"\ndeps = [\"libc\", \"term\"]\n").unwrap();
+        assert_eq!(get(&index, "hello.version"), &Value::String("1.0.2".to_string()));
+        assert_eq!(
+            get(&index, "hello.deps").as_str_list(),
+            Some(vec!["libc".to_string(), "term".to_string()])
+        );
+    }
+
+    #[test_case]
+    fn dotted_and_quoted_keys_nest() {
+        let table = parse_toml("[a.b]\nc.d = 1\n\"e.f\" = true\n").unwrap();
+        assert_eq!(get(&table, "a.b.c.d"), &Value::Integer(1));
+        assert_eq!(get(&table, "a.b").as_table().unwrap()["e.f"], Value::Boolean(true));
+    }
+
+    #[test_case]
+    fn arrays_of_tables_collect_each_header() {
+        let table = parse_toml("[[file]]\npath = \"/bin/a\"\n[[file]]\npath = \"/bin/b\"\n").unwrap();
+        let files = table["file"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].as_table().unwrap()["path"], Value::String("/bin/b".to_string()));
+    }
+
+    #[test_case]
+    fn strings_numbers_and_inline_tables() {
+        let text = "s = \"tab\\there \\u00e9\"\nl = 'C:\\dir'\nm = \"\"\"\none\ntwo\"\"\"\n\
+                    n = -1_000\nh = 0xff\nf = 2.5\nt = { x = 1, y = \"z\" }\n";
+        let table = parse_toml(text).unwrap();
+        assert_eq!(table["s"], Value::String("tab\there é".to_string()));
+        assert_eq!(table["l"], Value::String("C:\\dir".to_string()));
+        assert_eq!(table["m"], Value::String("one\ntwo".to_string()));
+        assert_eq!(table["n"], Value::Integer(-1000));
+        assert_eq!(table["h"], Value::Integer(255));
+        assert_eq!(table["f"], Value::Float(2.5));
+        assert_eq!(get(&table, "t.y"), &Value::String("z".to_string()));
+    }
+
+    #[test_case]
+    fn comments_and_blank_lines_are_skipped() {
+        let table = parse_toml("# index\n\n[a] # first\nb = 1 # one\n\n").unwrap();
+        assert_eq!(get(&table, "a.b"), &Value::Integer(1));
+    }
+
+    #[test_case]
+    fn errors_name_the_line() {
+        let error = |text: &str| parse_toml(text).unwrap_err();
+        assert_eq!(error("a = 1\nb = \"open"), "line 2: unterminated string");
+        assert_eq!(error("a = 1\na = 2\n"), "line 2: duplicate key a");
+        assert_eq!(error("[a\n"), "line 1: expected ']' after table name");
+        assert_eq!(error("a = \n"), "line 1: expected a value");
+        assert_eq!(error("a = 1 2\n"), "line 1: unexpected '2'");
+    }
+
+    #[test_case]
+    fn serialized_tables_parse_back() {
+        let text = "top = \"x\"\n[pkg]\nname = \"hello\"\ndeps = [\"a\"]\n[[pkg.file]]\npath = \"/bin/hello\"\n";
+        let table = parse_toml(text).unwrap();
+        let again: String = serialize_toml(&table);
+        assert_eq!(parse_toml(&again).unwrap(), table);
+    }
+}
@@ -0,0 +1,349 @@
+//! YMODEM (and plain XMODEM-CRC) file transfer over COM1.
+//!
+//! Backs the `rz`/`sz` shell commands, so files can be pushed into the
+//! VFS from the host through QEMU's serial console, e.g. with
+//! `sb file.wad` (lrzsz) or minicom's YMODEM upload. While a transfer
+//! runs the port is in raw mode and serial logging is suppressed.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::drivers::{keyboard, serial, timer};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_MODE: u8 = b'C';
+/// Padding used by senders to fill the last block
+const SUB: u8 = 0x1A;
+
+const MAX_RETRIES: usize = 10;
+/// How long to keep asking the host to start sending
+const START_TIMEOUT_MS: u64 = 60_000;
+const START_INTERVAL_MS: u64 = 3_000;
+const BYTE_TIMEOUT_MS: u64 = 1_000;
+const ACK_TIMEOUT_MS: u64 = 10_000;
+
+pub struct ReceivedFile {
+    /// Name from the YMODEM header; plain XMODEM transfers have none
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+enum Packet {
+    Block { number: u8, data: Vec<u8> },
+    Eot,
+}
+
+enum PacketError {
+    Timeout,
+    Corrupt,
+    Cancelled,
+}
+
+/// Receive one or more files. Returns once the sender ends the batch.
+pub fn receive() -> Result<Vec<ReceivedFile>, String> {
+    serial::set_raw_mode(true);
+    drain_input();
+    let result = receive_batch();
+    if result.is_err() {
+        cancel();
+    }
+    serial::set_raw_mode(false);
+    result
+}
+
+/// Send a single file as a YMODEM batch
+pub fn send(name: &str, data: &[u8]) -> Result<(), String> {
+    serial::set_raw_mode(true);
+    drain_input();
+    let result = send_batch(name, data);
+    if result.is_err() {
+        cancel();
+    }
+    serial::set_raw_mode(false);
+    result
+}
+
+fn receive_batch() -> Result<Vec<ReceivedFile>, String> {
+    let mut files = Vec::new();
+    loop {
+        let (number, block) = match request_packet()? {
+            Packet::Block { number, data } => (number, data),
+            Packet::Eot => {
+                put(ACK);
+                continue;
+            }
+        };
+        match number {
+            // Plain XMODEM: a single file without a header
+            1 => {
+                files.push(receive_file(None, None, block, false)?);
+                return Ok(files);
+            }
+            0 => {
+                put(ACK);
+                // Empty header closes the batch
+                let (name, size) = match parse_header(&block) {
+                    Some(header) => header,
+                    None => return Ok(files),
+                };
+                match request_packet()? {
+                    Packet::Block { number: 1, data } => {
+                        files.push(receive_file(Some(name), size, data, true)?);
+                    }
+                    // Empty file: no data blocks at all
+                    Packet::Eot => {
+                        put(ACK);
+                        files.push(ReceivedFile { name: Some(name), data: Vec::new() });
+                    }
+                    Packet::Block { .. } => return Err("unexpected block number".to_string()),
+                }
+            }
+            _ => return Err("unexpected block number".to_string()),
+        }
+    }
+}
+
+/// Keep sending 'C' until the sender answers
+fn request_packet() -> Result<Packet, String> {
+    let deadline = timer::get_uptime_ms() + START_TIMEOUT_MS;
+    while timer::get_uptime_ms() < deadline {
+        put(CRC_MODE);
+        match read_packet(START_INTERVAL_MS) {
+            Ok(packet) => return Ok(packet),
+            Err(PacketError::Cancelled) => return Err("cancelled by sender".to_string()),
+            Err(PacketError::Corrupt) => drain_input(),
+            Err(PacketError::Timeout) => {}
+        }
+        if user_aborted() {
+            return Err("aborted".to_string());
+        }
+    }
+    Err("timed out waiting for sender".to_string())
+}
+
+fn receive_file(name: Option<String>, size: Option<usize>, first: Vec<u8>, ymodem: bool)
+    -> Result<ReceivedFile, String>
+{
+    let mut data = first;
+    put(ACK);
+
+    let mut expected: u8 = 2;
+    let mut errors = 0;
+    loop {
+        match read_packet(ACK_TIMEOUT_MS) {
+            Ok(Packet::Block { number, data: block }) => {
+                if number == expected {
+                    data.extend_from_slice(&block);
+                    expected = expected.wrapping_add(1);
+                    errors = 0;
+                } else if number != expected.wrapping_sub(1) {
+                    // Anything but a retransmit of the last block is fatal
+                    return Err("lost synchronisation".to_string());
+                }
+                put(ACK);
+            }
+            Ok(Packet::Eot) => {
+                if ymodem {
+                    // YMODEM senders repeat EOT after a NAK
+                    put(NAK);
+                    match read_packet(ACK_TIMEOUT_MS) {
+                        Ok(Packet::Eot) => {}
+                        _ => return Err("missing final EOT".to_string()),
+                    }
+                }
+                put(ACK);
+                break;
+            }
+            Err(PacketError::Cancelled) => return Err("cancelled by sender".to_string()),
+            Err(_) => {
+                errors += 1;
+                if errors > MAX_RETRIES || user_aborted() {
+                    return Err("too many errors".to_string());
+                }
+                drain_input();
+                put(NAK);
+            }
+        }
+    }
+
+    match size {
+        Some(size) => data.truncate(size),
+        // Without a length the best we can do is drop the padding
+        None => {
+            while data.last() == Some(&SUB) {
+                data.pop();
+            }
+        }
+    }
+    Ok(ReceivedFile { name, data })
+}
+
+/// Parse a YMODEM block 0: `name\0size [mtime mode ...]`
+fn parse_header(block: &[u8]) -> Option<(String, Option<usize>)> {
+    let name_end = block.iter().position(|&b| b == 0)?;
+    if name_end == 0 {
+        return None;
+    }
+    let name = String::from_utf8_lossy(&block[..name_end]).into_owned();
+    let rest = &block[name_end + 1..];
+    let size = rest.iter()
+        .position(|&b| b == b' ' || b == 0)
+        .and_then(|end| core::str::from_utf8(&rest[..end]).ok())
+        .and_then(|s| s.parse::<usize>().ok());
+    Some((name, size))
+}
+
+fn send_batch(name: &str, data: &[u8]) -> Result<(), String> {
+    wait_for_start()?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(data.len().to_string().as_bytes());
+    send_block(0, &header)?;
+    wait_for_start()?;
+
+    for (i, chunk) in data.chunks(1024).enumerate() {
+        send_block((i + 1) as u8, chunk)?;
+    }
+
+    // EOT until acknowledged
+    let mut acked = false;
+    for _ in 0..MAX_RETRIES {
+        put(EOT);
+        match get(ACK_TIMEOUT_MS) {
+            Some(ACK) => {
+                acked = true;
+                break;
+            }
+            Some(CAN) => return Err("cancelled by receiver".to_string()),
+            _ => {}
+        }
+    }
+    if !acked {
+        return Err("no acknowledgement for EOT".to_string());
+    }
+
+    // Empty header ends the batch
+    wait_for_start()?;
+    send_block(0, &[])
+}
+
+/// Wait for the receiver's 'C'
+fn wait_for_start() -> Result<(), String> {
+    let deadline = timer::get_uptime_ms() + START_TIMEOUT_MS;
+    while timer::get_uptime_ms() < deadline {
+        match get(START_INTERVAL_MS) {
+            Some(CRC_MODE) => return Ok(()),
+            Some(CAN) => return Err("cancelled by receiver".to_string()),
+            Some(NAK) => return Err("receiver does not support CRC mode".to_string()),
+            _ => {}
+        }
+        if user_aborted() {
+            return Err("aborted".to_string());
+        }
+    }
+    Err("timed out waiting for receiver".to_string())
+}
+
+fn send_block(number: u8, payload: &[u8]) -> Result<(), String> {
+    let size = if payload.len() > 128 { 1024 } else { 128 };
+    let mut block = Vec::with_capacity(size + 5);
+    block.push(if size == 1024 { STX } else { SOH });
+    block.push(number);
+    block.push(!number);
+    block.extend_from_slice(payload);
+    // Header blocks are zero-padded, data blocks use SUB
+    block.resize(3 + size, if number == 0 { 0 } else { SUB });
+    let crc = crc16(&block[3..]);
+    block.extend_from_slice(&crc.to_be_bytes());
+
+    for _ in 0..MAX_RETRIES {
+        serial::write_bytes(&block);
+        match get(ACK_TIMEOUT_MS) {
+            Some(ACK) => return Ok(()),
+            Some(CAN) => return Err("cancelled by receiver".to_string()),
+            _ => {}
+        }
+        if user_aborted() {
+            return Err("aborted".to_string());
+        }
+    }
+    Err("too many retries".to_string())
+}
+
+fn read_packet(timeout_ms: u64) -> Result<Packet, PacketError> {
+    let size = match get(timeout_ms).ok_or(PacketError::Timeout)? {
+        SOH => 128,
+        STX => 1024,
+        EOT => return Ok(Packet::Eot),
+        CAN => {
+            return match get(BYTE_TIMEOUT_MS) {
+                Some(CAN) => Err(PacketError::Cancelled),
+                _ => Err(PacketError::Corrupt),
+            };
+        }
+        _ => return Err(PacketError::Corrupt),
+    };
+
+    let number = get(BYTE_TIMEOUT_MS).ok_or(PacketError::Timeout)?;
+    let complement = get(BYTE_TIMEOUT_MS).ok_or(PacketError::Timeout)?;
+    let mut data = Vec::with_capacity(size);
+    for _ in 0..size {
+        data.push(get(BYTE_TIMEOUT_MS).ok_or(PacketError::Timeout)?);
+    }
+    let hi = get(BYTE_TIMEOUT_MS).ok_or(PacketError::Timeout)?;
+    let lo = get(BYTE_TIMEOUT_MS).ok_or(PacketError::Timeout)?;
+
+    if number != !complement || crc16(&data) != u16::from_be_bytes([hi, lo]) {
+        return Err(PacketError::Corrupt);
+    }
+    Ok(Packet::Block { number, data })
+}
+
+/// CRC-16/XMODEM (poly 0x1021, init 0)
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn get(timeout_ms: u64) -> Option<u8> {
+    let deadline = timer::get_uptime_ms() + timeout_ms;
+    loop {
+        if let Some(byte) = serial::poll_input() {
+            return Some(byte);
+        }
+        if timer::get_uptime_ms() >= deadline {
+            return None;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+fn put(byte: u8) {
+    serial::write_bytes(&[byte]);
+}
+
+/// Discard whatever is left on the line (after a corrupt block)
+fn drain_input() {
+    while get(50).is_some() {}
+}
+
+fn cancel() {
+    serial::write_bytes(&[CAN; 5]);
+}
+
+/// Any key on the local keyboard stops the transfer
+fn user_aborted() -> bool {
+    keyboard::try_read_key().is_some()
+}
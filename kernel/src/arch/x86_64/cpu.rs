@@ -0,0 +1,203 @@
+//! What the processor is and can do, from CPUID
+//!
+//! `init` reads the feature bits once at boot; `has` answers from them, so
+//! optional kernel paths (no-execute pages, 1 GiB pages, ...) can check a
+//! feature cheaply before using it. `info` reads the rest (vendor, model)
+//! again for /proc/cpuinfo, `lscpu` and `osinfo`.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Optional CPU features the kernel reports or depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Fpu,
+    Tsc,
+    Pae,
+    Apic,
+    Pge,
+    Fxsr,
+    Sse,
+    Sse2,
+    Sse3,
+    Ssse3,
+    Sse4_1,
+    Sse4_2,
+    Popcnt,
+    Xsave,
+    Avx,
+    Avx2,
+    Rdrand,
+    Hypervisor,
+    Smep,
+    Smap,
+    Syscall,
+    /// No-execute page bit
+    Nx,
+    /// 1 GiB pages
+    Pdpe1gb,
+    /// 64-bit mode
+    Lm,
+}
+
+/// Where each feature's bit is: (feature, name in /proc/cpuinfo, leaf,
+/// register, bit). Leaf 7 is read with subleaf 0.
+const FEATURES: &[(Feature, &str, u32, Reg, u32)] = &[
+    (Feature::Fpu, "fpu", 1, Reg::Edx, 0),
+    (Feature::Tsc, "tsc", 1, Reg::Edx, 4),
+    (Feature::Pae, "pae", 1, Reg::Edx, 6),
+    (Feature::Apic, "apic", 1, Reg::Edx, 9),
+    (Feature::Pge, "pge", 1, Reg::Edx, 13),
+    (Feature::Fxsr, "fxsr", 1, Reg::Edx, 24),
+    (Feature::Sse, "sse", 1, Reg::Edx, 25),
+    (Feature::Sse2, "sse2", 1, Reg::Edx, 26),
+    (Feature::Sse3, "sse3", 1, Reg::Ecx, 0),
+    (Feature::Ssse3, "ssse3", 1, Reg::Ecx, 9),
+    (Feature::Sse4_1, "sse4_1", 1, Reg::Ecx, 19),
+    (Feature::Sse4_2, "sse4_2", 1, Reg::Ecx, 20),
+    (Feature::Popcnt, "popcnt", 1, Reg::Ecx, 23),
+    (Feature::Xsave, "xsave", 1, Reg::Ecx, 26),
+    (Feature::Avx, "avx", 1, Reg::Ecx, 28),
+    (Feature::Rdrand, "rdrand", 1, Reg::Ecx, 30),
+    (Feature::Hypervisor, "hypervisor", 1, Reg::Ecx, 31),
+    (Feature::Avx2, "avx2", 7, Reg::Ebx, 5),
+    (Feature::Smep, "smep", 7, Reg::Ebx, 7),
+    (Feature::Smap, "smap", 7, Reg::Ebx, 20),
+    (Feature::Syscall, "syscall", 0x8000_0001, Reg::Edx, 11),
+    (Feature::Nx, "nx", 0x8000_0001, Reg::Edx, 20),
+    (Feature::Pdpe1gb, "pdpe1gb", 0x8000_0001, Reg::Edx, 26),
+    (Feature::Lm, "lm", 0x8000_0001, Reg::Edx, 29),
+];
+
+#[derive(Clone, Copy)]
+enum Reg {
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// One bit per entry of FEATURES, set by `init`
+static DETECTED: AtomicU64 = AtomicU64::new(0);
+
+/// The processor as CPUID describes it
+pub struct CpuInfo {
+    /// "GenuineIntel", "AuthenticAMD"
+    pub vendor: String,
+    /// The marketing name, when the CPU has one
+    pub brand: Option<String>,
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    /// Physical and virtual address bits
+    pub address_bits: Option<(u32, u32)>,
+    /// Names of the detected features, in FEATURES order
+    pub flags: Vec<&'static str>,
+}
+
+/// Whether CPUID `leaf` exists on this processor
+fn has_leaf(leaf: u32) -> bool {
+    let max = if leaf >= 0x8000_0000 { __cpuid(0x8000_0000).eax } else { __cpuid(0).eax };
+    leaf <= max
+}
+
+fn register(leaf: u32, reg: Reg) -> u32 {
+    let r = __cpuid_count(leaf, 0);
+    match reg {
+        Reg::Ebx => r.ebx,
+        Reg::Ecx => r.ecx,
+        Reg::Edx => r.edx,
+    }
+}
+
+/// Read the feature bits, and turn on the ones the kernel uses: the
+/// no-execute bit (EFER.NXE) when there is one
+pub fn init() {
+    let mut detected = 0u64;
+    for (i, &(_, _, leaf, reg, bit)) in FEATURES.iter().enumerate() {
+        if has_leaf(leaf) && register(leaf, reg) & (1 << bit) != 0 {
+            detected |= 1 << i;
+        }
+    }
+    DETECTED.store(detected, Ordering::Relaxed);
+
+    if has(Feature::Nx) {
+        use x86_64::registers::model_specific::{Efer, EferFlags};
+        unsafe {
+            let mut efer = Efer::read();
+            efer |= EferFlags::NO_EXECUTE_ENABLE;
+            Efer::write(efer);
+        }
+    }
+    crate::serial_println!("[CPU] {}", info().flags.join(" "));
+}
+
+/// Whether the processor has `feature`; false before `init`
+pub fn has(feature: Feature) -> bool {
+    FEATURES
+        .iter()
+        .position(|&(f, ..)| f == feature)
+        .map_or(false, |i| DETECTED.load(Ordering::Relaxed) & (1 << i) != 0)
+}
+
+/// Registers of a CPUID leaf as bytes, in the order the strings use
+fn cpuid_bytes(leaf: u32, regs: &[u8]) -> Vec<u8> {
+    let r = __cpuid(leaf);
+    regs.iter()
+        .flat_map(|&reg| {
+            match reg {
+                b'a' => r.eax,
+                b'b' => r.ebx,
+                b'c' => r.ecx,
+                _ => r.edx,
+            }
+            .to_le_bytes()
+        })
+        .collect()
+}
+
+/// Vendor, model and features
+pub fn info() -> CpuInfo {
+    let vendor = cpuid_bytes(0, b"bdc");
+    let brand = has_leaf(0x8000_0004).then(|| {
+        let bytes: Vec<u8> = (0x8000_0002..=0x8000_0004)
+            .flat_map(|leaf| cpuid_bytes(leaf, b"abcd"))
+            .take_while(|&b| b != 0)
+            .collect();
+        String::from_utf8_lossy(&bytes).trim().to_string()
+    });
+
+    // Family and model get the extended fields added as the SDM says
+    let signature = __cpuid(1).eax;
+    let base_family = (signature >> 8) & 0xF;
+    let family = if base_family == 0xF { base_family + ((signature >> 20) & 0xFF) } else { base_family };
+    let model = if base_family == 0x6 || base_family == 0xF {
+        ((signature >> 12) & 0xF0) | ((signature >> 4) & 0xF)
+    } else {
+        (signature >> 4) & 0xF
+    };
+
+    let address_bits = has_leaf(0x8000_0008).then(|| {
+        let bits = __cpuid(0x8000_0008).eax;
+        (bits & 0xFF, (bits >> 8) & 0xFF)
+    });
+
+    let detected = DETECTED.load(Ordering::Relaxed);
+    let flags = FEATURES
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| detected & (1 << i) != 0)
+        .map(|(_, &(_, name, ..))| name)
+        .collect();
+
+    CpuInfo {
+        vendor: String::from_utf8_lossy(&vendor).to_string(),
+        brand,
+        family,
+        model,
+        stepping: signature & 0xF,
+        address_bits,
+        flags,
+    }
+}
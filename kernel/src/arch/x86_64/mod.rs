@@ -1,3 +1,5 @@
+pub mod cpu;
+
 use core::arch::asm;
 use x86_64::structures::paging::{PageTable, OffsetPageTable};
 use x86_64::VirtAddr;
@@ -141,6 +143,8 @@ pub unsafe fn enter_user_mode_with_cr3(entry: u64, user_stack: u64, cr3: u64) ->
     let user_data = (selectors.user_data.0 | 3) as u64;
 
     asm!(
+        // The transition stack is shared; nothing may interrupt us on it
+        "cli",
         "lea rsp, [rip + {stack_base}]",
         "add rsp, {stack_size}",
         "mov cr3, {cr3}",
@@ -10,9 +10,14 @@ use alloc::vec::Vec;
 use alloc::format;
 use core::sync::atomic::{AtomicU32, Ordering};
 use spin::Mutex;
+use crate::ipc::message::FSRequest;
+use crate::services::vfs;
 
 pub static CURRENT_USER: AtomicU32 = AtomicU32::new(0); // 0 = root
 
+/// Environment of the current login session (HOME, USER, ...)
+static SESSION_ENV: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Permission {
     Read,
@@ -135,16 +140,50 @@ impl UserManager {
         Ok(id)
     }
 
+    /// The account `remove_user` would remove, or why it refuses to
+    pub fn removable(&self, name: &str) -> Result<&User, &'static str> {
+        let id = *self.users_by_name.get(name).ok_or("User does not exist")?;
+        if id == 0 {
+            return Err("Cannot delete root");
+        }
+        if id == CURRENT_USER.load(Ordering::Relaxed) {
+            return Err("User is currently logged in");
+        }
+        self.users.get(&id).ok_or("User does not exist")
+    }
+
+    /// Remove a user account. Root and the logged-in user cannot be removed.
+    pub fn remove_user(&mut self, name: &str) -> Result<User, &'static str> {
+        let id = self.removable(name)?.id;
+        self.users_by_name.remove(name);
+        self.users.remove(&id).ok_or("User does not exist")
+    }
+
     pub fn list_users(&self) -> Vec<&User> {
         self.users.values().collect()
     }
+
+    /// Render the user database in /etc/passwd format
+    pub fn passwd(&self) -> String {
+        let mut out = String::new();
+        for user in self.users.values() {
+            out.push_str(&format!("{}:x:{}:{}::{}:/bin/sh\n",
+                user.name, user.id, user.id, user.home_dir));
+        }
+        out
+    }
 }
 
 static USER_MANAGER: Mutex<UserManager> = Mutex::new(UserManager::new());
 
 pub fn init() {
     USER_MANAGER.lock().init();
+    sync_passwd();
+    if let Some(root) = current_user() {
+        set_environment(&root);
+    }
     crate::serial_print!(b"[AUTH] User authentication system initialized\r\n");
+    crate::ipc::bus::publish(crate::ipc::message::ServiceEvent::Ready("auth"));
 }
 
 pub fn authenticate(username: &str, password: &str) -> Option<User> {
@@ -159,8 +198,89 @@ pub fn switch_user(username: &str, password: &str) -> Result<(), &'static str> {
     USER_MANAGER.lock().switch_user(username, password)
 }
 
+/// Only root may add or remove accounts
+fn require_root() -> Result<(), &'static str> {
+    if current_user_id() == 0 {
+        Ok(())
+    } else {
+        Err("Permission denied")
+    }
+}
+
 pub fn add_user(name: &str, password: &str) -> Result<u32, &'static str> {
-    USER_MANAGER.lock().add_user(name, password)
+    require_root()?;
+    let id = USER_MANAGER.lock().add_user(name, password)?;
+    let _ = vfs::process_request(FSRequest::CreateDir {
+        path: format!("/home/{}", name),
+    });
+    sync_passwd();
+    Ok(id)
+}
+
+/// Check that root may remove `name` now, without removing it; the
+/// account as it stands if so
+pub fn check_remove_user(name: &str) -> Result<User, &'static str> {
+    require_root()?;
+    USER_MANAGER.lock().removable(name).cloned()
+}
+
+pub fn remove_user(name: &str) -> Result<User, &'static str> {
+    require_root()?;
+    let user = USER_MANAGER.lock().remove_user(name)?;
+    sync_passwd();
+    Ok(user)
+}
+
+/// Switch the session to `username`.
+///
+/// Unlike sudo this replaces the session's credentials outright. Root may
+/// switch to any user without a password; everyone else must supply the
+/// target user's password. The environment and cwd are reset to the
+/// target user's home.
+pub fn set_credentials(username: &str, password: Option<&str>) -> Result<User, &'static str> {
+    let user = {
+        let manager = USER_MANAGER.lock();
+        let target = manager.get_user_by_name(username).ok_or("Unknown user")?;
+        let caller_is_root = CURRENT_USER.load(Ordering::Relaxed) == 0;
+        if !caller_is_root && !password.map_or(false, |p| target.check_password(p)) {
+            return Err("Authentication failure");
+        }
+        target.clone()
+    };
+
+    CURRENT_USER.store(user.id, Ordering::Relaxed);
+    set_environment(&user);
+
+    let _ = vfs::process_request(FSRequest::CreateDir { path: user.home_dir.clone() });
+    let _ = vfs::process_request(FSRequest::ChangeDir { path: user.home_dir.clone() });
+    Ok(user)
+}
+
+fn set_environment(user: &User) {
+    let mut env = SESSION_ENV.lock();
+    env.clear();
+    env.insert("HOME".to_string(), user.home_dir.clone());
+    env.insert("USER".to_string(), user.name.clone());
+    env.insert("LOGNAME".to_string(), user.name.clone());
+    env.insert("SHELL".to_string(), "/bin/sh".to_string());
+    env.insert("PATH".to_string(), "/bin:/usr/bin".to_string());
+}
+
+pub fn getenv(key: &str) -> Option<String> {
+    SESSION_ENV.lock().get(key).cloned()
+}
+
+pub fn environment() -> Vec<(String, String)> {
+    SESSION_ENV.lock().iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+}
+
+/// Rewrite /etc/passwd from the in-kernel user database
+fn sync_passwd() {
+    let data = USER_MANAGER.lock().passwd().into_bytes();
+    let _ = vfs::process_request(FSRequest::WriteFile {
+        path: "/etc/passwd".to_string(),
+        data,
+    });
 }
 
 pub fn list_users() -> Vec<User> {
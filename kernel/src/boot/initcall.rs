@@ -0,0 +1,209 @@
+//! Parallel initialization of non-critical subsystems
+//!
+//! `_start` brings up everything the scheduler needs on its own and hands
+//! the rest to `start` as a table of init calls. Each call names the
+//! services it depends on; worker tasks run every call whose dependencies
+//! have announced `ServiceEvent::Ready` on the bus, so independent
+//! subsystems (network, initrd parsing, ...) overlap instead of delaying
+//! the prompt one after the other, and a call never starts just because
+//! another one returned without actually bringing its service up.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::ipc::bus;
+use crate::ipc::message::ServiceEvent;
+
+/// Upper bound on the number of init calls (they are tracked in a bitmask)
+const MAX_CALLS: usize = 64;
+
+/// Worker tasks started by `start`
+const WORKERS: usize = 2;
+
+/// How long calls may wait on services nobody brings up before they are
+/// given up on
+const STALL_TIMEOUT_MS: u64 = 5000;
+
+pub struct InitCall {
+    pub name: &'static str,
+    /// Services that must be ready first
+    pub deps: &'static [&'static str],
+    pub run: fn(),
+}
+
+struct State {
+    calls: &'static [InitCall],
+    started: u64,
+    done: u64,
+    /// Services currently ready, from the bus event log
+    ready: Vec<&'static str>,
+    /// Events consumed so far
+    cursor: usize,
+    /// When workers last found nothing to run with nothing running
+    stalled_since: Option<u64>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    calls: &[],
+    started: 0,
+    done: 0,
+    ready: Vec::new(),
+    cursor: 0,
+    stalled_since: None,
+});
+
+impl State {
+    fn bit(&self, name: &str) -> Option<u64> {
+        self.calls.iter().position(|c| c.name == name).map(|i| 1 << i)
+    }
+
+    fn all(&self) -> u64 {
+        if self.calls.len() == MAX_CALLS { !0 } else { (1 << self.calls.len()) - 1 }
+    }
+
+    /// Consume new readiness events from the bus
+    fn update(&mut self) {
+        let events = match bus::get() {
+            Some(bus) => bus.events_since(self.cursor),
+            None => return,
+        };
+        if events.is_empty() {
+            return;
+        }
+        self.cursor += events.len();
+        self.stalled_since = None;
+        for event in events {
+            self.ready.retain(|s| *s != event.service());
+            if let ServiceEvent::Ready(service) = event {
+                self.ready.push(service);
+            }
+        }
+    }
+
+    fn deps_ready(&self, call: &InitCall) -> bool {
+        call.deps.iter().all(|d| self.ready.contains(d))
+    }
+
+    /// Claim the next call whose dependencies are ready
+    fn claim(&mut self) -> Option<usize> {
+        self.update();
+        let index = (0..self.calls.len()).find(|&i| {
+            self.started & (1 << i) == 0 && self.deps_ready(&self.calls[i])
+        })?;
+        self.started |= 1 << index;
+        self.stalled_since = None;
+        Some(index)
+    }
+
+    /// Give up on the calls left if none can start and none is running
+    /// to bring up what they wait for
+    fn check_stall(&mut self) {
+        if self.started != self.done {
+            return;
+        }
+        let now = crate::drivers::timer::get_uptime_ms();
+        let since = *self.stalled_since.get_or_insert(now);
+        if now - since < STALL_TIMEOUT_MS {
+            return;
+        }
+        for (i, call) in self.calls.iter().enumerate() {
+            if self.started & (1 << i) != 0 {
+                continue;
+            }
+            for dep in call.deps.iter().filter(|d| !self.ready.contains(d)) {
+                crate::serial_println!("[INIT] {}: skipped, '{}' never became ready", call.name, dep);
+            }
+        }
+        self.started = self.all();
+        self.done = self.all();
+    }
+}
+
+/// Run `calls` on worker tasks; returns immediately
+pub fn start(calls: &'static [InitCall]) {
+    assert!(calls.len() <= MAX_CALLS, "initcall: too many init calls");
+
+    let mut state = STATE.lock();
+    state.calls = calls;
+    state.started = 0;
+    state.done = 0;
+    state.stalled_since = None;
+    drop(state);
+    for _ in 0..WORKERS.min(calls.len()) {
+        crate::task::spawn_kernel_task("initcall", worker);
+    }
+}
+
+fn worker() -> ! {
+    loop {
+        let next = {
+            let mut state = STATE.lock();
+            if state.started == state.all() {
+                break;
+            }
+            let calls = state.calls;
+            state.claim().map(|i| &calls[i])
+        };
+        match next {
+            Some(call) => {
+                (call.run)();
+                super::timeline::mark(call.name);
+                let mut state = STATE.lock();
+                let bit = state.bit(call.name).unwrap_or(0);
+                state.done |= bit;
+            }
+            // Everything left waits for a service to become ready
+            None => {
+                STATE.lock().check_stall();
+                crate::task::scheduler::yield_now();
+            }
+        }
+    }
+
+    crate::task::scheduler::SCHEDULER.lock().terminate_current(0);
+    loop {
+        crate::task::scheduler::yield_now();
+    }
+}
+
+/// Whether the init call `name` has finished
+pub fn is_done(name: &str) -> bool {
+    let state = STATE.lock();
+    state.bit(name).map_or(false, |bit| state.done & bit != 0)
+}
+
+/// Block until the init call `name` has finished
+pub fn wait(name: &str) {
+    while !is_done(name) {
+        crate::task::scheduler::yield_now();
+    }
+}
+
+/// Block until `service` is ready; false if the init calls are all
+/// finished (or given up on) without it becoming ready
+pub fn wait_ready(service: &str) -> bool {
+    loop {
+        if bus::is_ready(service) {
+            return true;
+        }
+        {
+            let state = STATE.lock();
+            if state.done == state.all() {
+                return bus::is_ready(service);
+            }
+        }
+        crate::task::scheduler::yield_now();
+    }
+}
+
+/// Block until every init call has finished
+pub fn wait_all() {
+    loop {
+        {
+            let state = STATE.lock();
+            if state.done == state.all() {
+                return;
+            }
+        }
+        crate::task::scheduler::yield_now();
+    }
+}
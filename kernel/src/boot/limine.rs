@@ -87,6 +87,20 @@ pub fn bootloader_info() -> Option<&'static BootloaderInfoResponse> {
     }
 }
 
+/// Bootloader name and version strings
+pub fn bootloader_name_version() -> Option<(&'static str, &'static str)> {
+    let info = bootloader_info()?;
+    unsafe { Some((c_str(info.name)?, c_str(info.version)?)) }
+}
+
+/// A NUL-terminated string the bootloader handed over
+unsafe fn c_str(ptr: *const c_char) -> Option<&'static str> {
+    if ptr.is_null() {
+        return None;
+    }
+    core::ffi::CStr::from_ptr(ptr).to_str().ok()
+}
+
 // ============================================================================
 // HHDM (Higher Half Direct Map) Request
 // ============================================================================
@@ -331,6 +345,50 @@ unsafe impl Sync for EntryPointRequest {}
 // The entry point request is optional and mainly used when you want
 // a different entry than what's specified in the ELF header.
 
+// ============================================================================
+// RSDP Request (ACPI tables)
+// ============================================================================
+
+#[repr(C)]
+pub struct RsdpResponse {
+    pub revision: u64,
+    /// HHDM address of the RSDP before base revision 3, physical after
+    pub address: u64,
+}
+
+#[repr(C)]
+pub struct RsdpRequest {
+    pub id: [u64; 4],
+    pub revision: u64,
+    pub response: *mut RsdpResponse,
+}
+
+unsafe impl Sync for RsdpRequest {}
+
+#[used]
+#[link_section = ".limine_requests"]
+static mut RSDP_REQUEST: RsdpRequest = RsdpRequest {
+    id: [
+        LIMINE_COMMON_MAGIC[0],
+        LIMINE_COMMON_MAGIC[1],
+        0xc5e77b6b397e7b43,
+        0x27637845accdcf3c,
+    ],
+    revision: 0,
+    response: ptr::null_mut(),
+};
+
+/// Address of the ACPI RSDP, as reported by the bootloader
+pub fn rsdp_address() -> Option<u64> {
+    unsafe {
+        if RSDP_REQUEST.response.is_null() {
+            None
+        } else {
+            Some((*RSDP_REQUEST.response).address)
+        }
+    }
+}
+
 // ============================================================================
 // Module Request (for Initrd/Files)
 // ============================================================================
@@ -443,6 +501,49 @@ pub fn get_module(index: usize) -> Option<&'static LimineFile> {
     }
 }
 
+/// A module with its names resolved
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    /// File name, the last component of `path`
+    pub name: &'static str,
+    /// Where the bootloader loaded it from, e.g. "/initrd/doom1.wad"
+    pub path: &'static str,
+    /// `module_cmdline` from limine.conf; the build sets it to the file name
+    pub cmdline: &'static str,
+    /// Module memory stays mapped for the lifetime of the kernel
+    pub data: &'static [u8],
+}
+
+impl LimineFile {
+    fn to_module(&'static self) -> Module {
+        let path = unsafe { c_str(self.path) }.unwrap_or("");
+        Module {
+            name: path.rsplit('/').next().unwrap_or(path),
+            path,
+            cmdline: unsafe { c_str(self.cmdline) }.unwrap_or(""),
+            data: unsafe { core::slice::from_raw_parts(self.address as *const u8, self.size as usize) },
+        }
+    }
+}
+
+/// Every module, in limine.conf order
+pub fn module_list() -> alloc::vec::Vec<Module> {
+    modules().into_iter().flatten().map(LimineFile::to_module).collect()
+}
+
+/// The module called `name`, by its command line or else its file name
+///
+/// Kernel code that needs a specific file at boot (a WAD, a font, a keymap)
+/// asks for it by name here instead of scanning the modules.
+pub fn find_module(name: &str) -> Option<Module> {
+    let modules = module_list();
+    modules
+        .iter()
+        .find(|module| module.cmdline == name)
+        .or_else(|| modules.iter().find(|module| module.name == name))
+        .copied()
+}
+
 /// Get module count
 pub fn module_count() -> usize {
     unsafe {
@@ -453,3 +554,64 @@ pub fn module_count() -> usize {
         resp.module_count as usize
     }
 }
+
+// ============================================================================
+// Kernel File Request (command line)
+// ============================================================================
+
+#[repr(C)]
+pub struct KernelFileResponse {
+    pub revision: u64,
+    pub kernel_file: *mut LimineFile,
+}
+
+#[repr(C)]
+pub struct KernelFileRequest {
+    pub id: [u64; 4],
+    pub revision: u64,
+    pub response: *mut KernelFileResponse,
+}
+
+unsafe impl Sync for KernelFileRequest {}
+
+#[used]
+#[link_section = ".limine_requests"]
+static mut KERNEL_FILE_REQUEST: KernelFileRequest = KernelFileRequest {
+    id: [
+        LIMINE_COMMON_MAGIC[0],
+        LIMINE_COMMON_MAGIC[1],
+        0xad97e90e83f1ed67,
+        0x31eb5d1c5ff23b69,
+    ],
+    revision: 0,
+    response: ptr::null_mut(),
+};
+
+/// Kernel command line from the bootloader config (`cmdline:`), if any
+pub fn kernel_cmdline() -> Option<&'static str> {
+    unsafe {
+        if KERNEL_FILE_REQUEST.response.is_null() {
+            return None;
+        }
+        let file = (*KERNEL_FILE_REQUEST.response).kernel_file;
+        if file.is_null() {
+            return None;
+        }
+        c_str((*file).cmdline)
+    }
+}
+
+/// The kernel ELF as the bootloader loaded it from disk, symbol table
+/// included; stays mapped like the modules
+pub fn kernel_file() -> Option<&'static [u8]> {
+    unsafe {
+        if KERNEL_FILE_REQUEST.response.is_null() {
+            return None;
+        }
+        let file = (*KERNEL_FILE_REQUEST.response).kernel_file;
+        if file.is_null() || (*file).address.is_null() {
+            return None;
+        }
+        Some(core::slice::from_raw_parts((*file).address as *const u8, (*file).size as usize))
+    }
+}
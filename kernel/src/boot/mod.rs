@@ -2,6 +2,8 @@
 //!
 //! This module contains the Limine boot protocol definitions and request structures.
 
+pub mod initcall;
 pub mod limine;
+pub mod timeline;
 
 pub use limine::*;
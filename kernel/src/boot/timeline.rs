@@ -0,0 +1,86 @@
+//! Boot timeline
+//!
+//! `_start` and the subsystem init functions call `mark` when a stage has
+//! finished; the `boottime` command turns the marks into per-stage
+//! durations. Marks are raw TSC readings kept in a fixed table, so they
+//! work before the heap and the timer exist. The TSC is calibrated against
+//! the timer tick afterwards, once jiffies have been counting for a while.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const MAX_STAGES: usize = 48;
+
+struct Timeline {
+    /// TSC at kernel entry
+    start: u64,
+    stages: [(&'static str, u64); MAX_STAGES],
+    count: usize,
+    /// TSC when the timer started ticking (jiffies = 0)
+    clock_ref: Option<u64>,
+}
+
+static TIMELINE: Mutex<Timeline> = Mutex::new(Timeline {
+    start: 0,
+    stages: [("", 0); MAX_STAGES],
+    count: 0,
+    clock_ref: None,
+});
+
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Record the kernel entry time; call first thing in `_start`
+pub fn start() {
+    TIMELINE.lock().start = rdtsc();
+}
+
+/// Record that `stage` has just finished
+pub fn mark(stage: &'static str) {
+    let now = rdtsc();
+    let mut timeline = TIMELINE.lock();
+    if timeline.count < MAX_STAGES {
+        let index = timeline.count;
+        timeline.stages[index] = (stage, now);
+        timeline.count += 1;
+    }
+}
+
+/// Remember the TSC at the moment jiffies start counting from zero
+pub fn start_clock() {
+    TIMELINE.lock().clock_ref = Some(rdtsc());
+}
+
+/// TSC ticks per microsecond, measured against the tick since `start_clock`.
+/// None until at least 100ms of jiffies have passed.
+fn ticks_per_us(timeline: &Timeline) -> Option<u64> {
+    let clock_ref = timeline.clock_ref?;
+    let elapsed_ms = crate::drivers::timer::get_uptime_ms();
+    if elapsed_ms < 100 {
+        return None;
+    }
+    let ticks = rdtsc().saturating_sub(clock_ref) / (elapsed_ms * 1000);
+    if ticks == 0 { None } else { Some(ticks) }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stage {
+    pub name: &'static str,
+    pub duration_us: u64,
+}
+
+/// Per-stage durations in boot order, or None if the TSC is not calibrated yet
+pub fn stages() -> Option<Vec<Stage>> {
+    let timeline = TIMELINE.lock();
+    let ticks_per_us = ticks_per_us(&timeline)?;
+    let mut previous = timeline.start;
+    let stages = timeline.stages[..timeline.count].iter()
+        .map(|&(name, tsc)| {
+            let duration_us = tsc.saturating_sub(previous) / ticks_per_us;
+            previous = tsc;
+            Stage { name, duration_us }
+        })
+        .collect();
+    Some(stages)
+}
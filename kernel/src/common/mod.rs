@@ -1,2 +1,4 @@
 // Shared types and utilities
-pub mod types;
\ No newline at end of file
+pub mod types;
+pub mod json;
+pub mod palette;
@@ -0,0 +1,156 @@
+// Color palettes (themes) shared by everything that draws
+//
+// Code that picks a color asks `current()` for it instead of hard-coding
+// RGB values, so `theme set` recolors the console, status bars, editor
+// highlighting, windows and the panic screen together. Colors are RGB
+// (0xRRGGBB).
+//
+// The default palette uses the Okabe-Ito colors, which stay distinct for
+// the common forms of color blindness; meaning is never carried by a
+// red/green difference alone.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Editor highlighting colors
+#[derive(Debug, Clone, Copy)]
+pub struct Syntax {
+    pub keyword: u32,
+    pub string: u32,
+    pub comment: u32,
+    pub number: u32,
+    pub type_name: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Console text
+    pub foreground: u32,
+    pub background: u32,
+    /// Status and help bars (grape, DOOM)
+    pub status_fg: u32,
+    pub status_bg: u32,
+    pub syntax: Syntax,
+    /// Window decorations
+    pub window_border: u32,
+    pub window_title: u32,
+    pub window_title_focused: u32,
+    pub window_title_text: u32,
+    /// Panic message text and screen
+    pub panic_fg: u32,
+    pub panic_bg: u32,
+    /// Box behind the panic message
+    pub panic_box: u32,
+}
+
+pub const DEFAULT: Palette = Palette {
+    name: "default",
+    description: "Okabe-Ito colors, safe for common color blindness",
+    foreground: 0xE0E0E0,
+    background: 0x000000,
+    status_fg: 0x000000,
+    status_bg: 0x56B4E9,
+    syntax: Syntax {
+        keyword: 0x56B4E9,
+        string: 0xE69F00,
+        comment: 0x999999,
+        number: 0xCC79A7,
+        type_name: 0xF0E442,
+    },
+    window_border: 0x808080,
+    window_title: 0x404040,
+    window_title_focused: 0x0072B2,
+    window_title_text: 0xFFFFFF,
+    panic_fg: 0xFFFFFF,
+    panic_bg: 0xD55E00,
+    panic_box: 0x000000,
+};
+
+pub const HIGH_CONTRAST: Palette = Palette {
+    name: "high-contrast",
+    description: "Pure white on black, yellow highlights",
+    foreground: 0xFFFFFF,
+    background: 0x000000,
+    status_fg: 0x000000,
+    status_bg: 0xFFFF00,
+    syntax: Syntax {
+        keyword: 0xFFFF00,
+        string: 0x00FFFF,
+        comment: 0xC0C0C0,
+        number: 0xFF80FF,
+        type_name: 0xFFFFFF,
+    },
+    window_border: 0xFFFFFF,
+    window_title: 0x000000,
+    window_title_focused: 0x0000A0,
+    window_title_text: 0xFFFFFF,
+    panic_fg: 0xFFFFFF,
+    panic_bg: 0x000000,
+    panic_box: 0xFFFFFF,
+};
+
+pub const DEUTERANOPIA: Palette = Palette {
+    name: "deuteranopia",
+    description: "Blue and orange only, no red/green pairs",
+    foreground: 0xF0F0F0,
+    background: 0x101020,
+    status_fg: 0xFFFFFF,
+    status_bg: 0x0072B2,
+    syntax: Syntax {
+        keyword: 0x56B4E9,
+        string: 0xE69F00,
+        comment: 0x8C8C8C,
+        number: 0xF0E442,
+        type_name: 0x9DB9FF,
+    },
+    window_border: 0x8C8C8C,
+    window_title: 0x303048,
+    window_title_focused: 0x0072B2,
+    window_title_text: 0xFFFFFF,
+    panic_fg: 0x000000,
+    panic_bg: 0xE69F00,
+    panic_box: 0xFFFFFF,
+};
+
+/// The colors ospabOS shipped with before themes
+pub const CLASSIC: Palette = Palette {
+    name: "classic",
+    description: "White on black with a red panic screen",
+    foreground: 0xFFFFFF,
+    background: 0x000000,
+    status_fg: 0xFFFFFF,
+    status_bg: 0x000000,
+    syntax: Syntax {
+        keyword: 0x00AAFF,
+        string: 0x00CC00,
+        comment: 0x808080,
+        number: 0xFF5555,
+        type_name: 0xFFFF55,
+    },
+    window_border: 0x808080,
+    window_title: 0x404040,
+    window_title_focused: 0x2050A0,
+    window_title_text: 0xFFFFFF,
+    panic_fg: 0xFF0000,
+    panic_bg: 0x8B0000,
+    panic_box: 0xFFFFFF,
+};
+
+pub const PALETTES: &[Palette] = &[DEFAULT, HIGH_CONTRAST, DEUTERANOPIA, CLASSIC];
+
+/// Index into `PALETTES`
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Palette in use
+pub fn current() -> &'static Palette {
+    &PALETTES[CURRENT.load(Ordering::Relaxed)]
+}
+
+/// Switch to the palette called `name`; callers repaint what they show
+/// (see `framebuffer::apply_palette`)
+pub fn select(name: &str) -> Result<&'static Palette, &'static str> {
+    let index = PALETTES.iter().position(|palette| palette.name == name).ok_or("unknown theme")?;
+    CURRENT.store(index, Ordering::Relaxed);
+    Ok(&PALETTES[index])
+}
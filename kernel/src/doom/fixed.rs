@@ -0,0 +1,49 @@
+//! Fixed-point math for the renderer
+//!
+//! The kernel does not save SSE state across task switches, so DOOM
+//! sticks to integers the way the original did: lengths are 16.16 fixed
+//! point and angles are fine angles, FINEANGLES to the circle, counted
+//! counter-clockwise from east.
+
+pub const FRACBITS: u32 = 16;
+pub const FRACUNIT: i64 = 1 << FRACBITS;
+pub const FINEANGLES: u32 = 8192;
+
+/// pi / 2 in 2.30 fixed point
+const HALF_PI_Q30: i64 = 1_686_629_713;
+const ONE_Q30: i64 = 1 << 30;
+
+/// Sine of a fine angle, in 16.16
+pub fn sine(angle: u32) -> i64 {
+    let quarter = FINEANGLES / 4;
+    let angle = angle % FINEANGLES;
+    let (offset, negative) = match angle / quarter {
+        0 => (angle, false),
+        1 => (2 * quarter - angle, false),
+        2 => (angle - 2 * quarter, true),
+        _ => (4 * quarter - angle, true),
+    };
+    // Taylor series to x^9 in radians, good to 1/65536 over a quarter turn
+    let x = HALF_PI_Q30 * offset as i64 / quarter as i64;
+    let x2 = (x * x) >> 30;
+    let mut series = ONE_Q30 - x2 / 72;
+    series = ONE_Q30 - ((x2 * series) >> 30) / 42;
+    series = ONE_Q30 - ((x2 * series) >> 30) / 20;
+    series = ONE_Q30 - ((x2 * series) >> 30) / 6;
+    let value = ((x * series) >> 30) >> (30 - FRACBITS);
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Cosine of a fine angle, in 16.16
+pub fn cosine(angle: u32) -> i64 {
+    sine(angle.wrapping_add(FINEANGLES / 4))
+}
+
+/// Degrees (as in THINGS) to a fine angle
+pub fn from_degrees(degrees: u16) -> u32 {
+    (degrees as u32 % 360) * FINEANGLES / 360
+}
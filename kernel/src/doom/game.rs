@@ -0,0 +1,191 @@
+//! Walking around a map
+//!
+//! The player starts at the player 1 start and moves in steps: the shell's
+//! keyboard delivers key presses and repeats, not key state. Movement is
+//! stopped by one-sided and blocking linedefs, by steps higher than 24
+//! units and by openings lower than the player, and slides along the wall
+//! when only one axis is blocked. Using a manual door opens it at once.
+//! Shift runs, Alt turns the turning keys into sidesteps.
+//!
+//! Sound effects are short PC speaker tunes: a rising sweep for a door, a
+//! low grunt when the player walks into a wall and a shot for Ctrl, which
+//! has nothing to hit yet.
+
+use alloc::vec::Vec;
+
+use super::fixed::{cosine, from_degrees, sine, FINEANGLES, FRACBITS, FRACUNIT};
+use super::map::{Map, ML_BLOCKING};
+use super::render::{self, Hit};
+use super::DoomKeys;
+use crate::drivers::speaker::{self, Note};
+
+/// Eye height above the floor
+const VIEW_HEIGHT: i64 = 41 * FRACUNIT;
+const PLAYER_HEIGHT: i32 = 56;
+const PLAYER_RADIUS: i64 = 16 * FRACUNIT;
+/// Highest step the player climbs
+const MAX_STEP: i32 = 24;
+const MOVE_STEP: i64 = 16 * FRACUNIT;
+const TURN_STEP: u32 = FINEANGLES / 48;
+/// How far away a door can be used
+const USE_RANGE: i64 = 64 * FRACUNIT;
+/// Line specials of doors opened by hand
+const MANUAL_DOORS: &[u16] = &[1, 26, 27, 28, 31, 32, 33, 34, 117, 118];
+
+const SFX_DOOR: &[Note] = &[Note::new(150, 30), Note::new(200, 30), Note::new(260, 30), Note::new(330, 40)];
+const SFX_OOF: &[Note] = &[Note::new(110, 40), Note::new(80, 50)];
+const SFX_PISTOL: &[Note] = &[Note::new(900, 20), Note::new(400, 20), Note::new(180, 30)];
+
+pub struct Game {
+    pub map: Map,
+    /// Position, 16.16
+    x: i64,
+    y: i64,
+    angle: u32,
+    sector: usize,
+    automap: bool,
+    /// The last move was blocked; the grunt is not repeated while it stays so
+    bumped: bool,
+    /// Fire was held last frame; a shot per press
+    firing: bool,
+    /// Scratch space for ray casts, kept between frames
+    hits: Vec<Hit>,
+}
+
+impl Game {
+    pub fn new(map: Map) -> Result<Self, &'static str> {
+        let start = map.player_start().ok_or("map has no player start")?;
+        let (x, y) = ((start.x as i64) << FRACBITS, (start.y as i64) << FRACBITS);
+        let angle = from_degrees(start.angle);
+        let mut hits = Vec::new();
+        let sector = render::point_sector(&map, (x, y), &mut hits).ok_or("player start is outside the map")?;
+        Ok(Self { map, x, y, angle, sector, automap: false, bumped: false, firing: false, hits })
+    }
+
+    /// Apply one frame of input
+    pub fn update(&mut self, keys: &DoomKeys) {
+        if keys.map {
+            self.automap = !self.automap;
+        }
+        if keys.fire && !self.firing {
+            speaker::play(SFX_PISTOL);
+        }
+        self.firing = keys.fire;
+        let step = if keys.run { MOVE_STEP * 2 } else { MOVE_STEP };
+        let mut side = 0;
+        if keys.strafe {
+            side = match (keys.left, keys.right) {
+                (true, false) => step,
+                (false, true) => -step,
+                _ => 0,
+            };
+        } else {
+            if keys.left {
+                self.angle = (self.angle + TURN_STEP) % FINEANGLES;
+            }
+            if keys.right {
+                self.angle = (self.angle + FINEANGLES - TURN_STEP) % FINEANGLES;
+            }
+        }
+        let forward = match (keys.up, keys.down) {
+            (true, false) => step,
+            (false, true) => -step,
+            _ => 0,
+        };
+        if forward != 0 || side != 0 {
+            // Sidesteps go a quarter turn to the left of the view
+            let left = (self.angle + FINEANGLES / 4) % FINEANGLES;
+            let dx = (cosine(self.angle) * forward + cosine(left) * side) >> FRACBITS;
+            let dy = (sine(self.angle) * forward + sine(left) * side) >> FRACBITS;
+            // Slide along walls that block only one direction
+            let moved = self.try_move(dx, dy) || self.try_move(dx, 0) || self.try_move(0, dy);
+            if !moved && !self.bumped {
+                speaker::play(SFX_OOF);
+            }
+            self.bumped = !moved;
+        }
+        if keys.use_key {
+            self.use_line();
+        }
+    }
+
+    /// Move by (dx, dy) unless something is in the way
+    fn try_move(&mut self, dx: i64, dy: i64) -> bool {
+        if dx == 0 && dy == 0 {
+            return false;
+        }
+        // Look a body radius further, so walls are not walked into
+        let length = ((dx * dx + dy * dy) as u64).isqrt() as i64;
+        let reach = (dx + dx * PLAYER_RADIUS / length, dy + dy * PLAYER_RADIUS / length);
+        render::cast(&self.map, (self.x, self.y), reach, &mut self.hits);
+
+        let floor = self.map.sectors[self.sector].floor;
+        for hit in self.hits.iter().filter(|hit| hit.distance <= FRACUNIT) {
+            let line = &self.map.linedefs[hit.line];
+            let Some(beyond) = self.map.side_sector(line, !hit.front) else { return false };
+            if line.flags & ML_BLOCKING != 0 {
+                return false;
+            }
+            let beyond = &self.map.sectors[beyond];
+            if beyond.floor - floor > MAX_STEP || beyond.ceiling - beyond.floor.max(floor) < PLAYER_HEIGHT {
+                return false;
+            }
+        }
+
+        let (x, y) = (self.x + dx, self.y + dy);
+        let Some(sector) = render::point_sector(&self.map, (x, y), &mut self.hits) else { return false };
+        self.x = x;
+        self.y = y;
+        self.sector = sector;
+        true
+    }
+
+    /// Open the manual door in front of the player, if any
+    fn use_line(&mut self) {
+        let dir = (cosine(self.angle), sine(self.angle));
+        render::cast(&self.map, (self.x, self.y), dir, &mut self.hits);
+        let Some(hit) = self.hits.iter().min_by_key(|hit| hit.distance).copied() else { return };
+        if hit.distance > USE_RANGE {
+            return;
+        }
+        let line = &self.map.linedefs[hit.line];
+        if !MANUAL_DOORS.contains(&line.special) {
+            return;
+        }
+        let Some(door) = self.map.side_sector(line, !hit.front) else { return };
+
+        // Doors rise to 4 units below the lowest neighbouring ceiling
+        let lowest = self
+            .map
+            .linedefs
+            .iter()
+            .filter_map(|line| {
+                let front = self.map.side_sector(line, true)?;
+                let back = self.map.side_sector(line, false)?;
+                match (front == door, back == door) {
+                    (true, false) => Some(back),
+                    (false, true) => Some(front),
+                    _ => None,
+                }
+            })
+            .map(|sector| self.map.sectors[sector].ceiling)
+            .min();
+        if let Some(ceiling) = lowest {
+            let door = &mut self.map.sectors[door];
+            if door.ceiling < ceiling - 4 {
+                door.ceiling = ceiling - 4;
+                speaker::play(SFX_DOOR);
+            }
+        }
+    }
+
+    /// Draw the view, or the automap, into `frame`
+    pub fn render(&mut self, frame: &mut [u32]) {
+        if self.automap {
+            render::render_automap(&self.map, (self.x, self.y), self.angle, frame);
+        } else {
+            let eye = ((self.map.sectors[self.sector].floor as i64) << FRACBITS) + VIEW_HEIGHT;
+            render::render_view(&self.map, (self.x, self.y, eye), self.angle, frame, &mut self.hits);
+        }
+    }
+}
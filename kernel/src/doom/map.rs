@@ -0,0 +1,178 @@
+//! Map lumps
+//!
+//! A map is the marker lump plus the lumps after it. Only the geometry
+//! the renderer needs is loaded: vertices, the linedefs between them, the
+//! sidedefs giving each side of a linedef its sector and textures, the
+//! sectors' heights and light, and the things (of which only the player
+//! start matters so far). SEGS, SSECTORS, NODES, REJECT and BLOCKMAP are
+//! skipped.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::wad::{read_i16, read_name, read_u16, Wad};
+
+/// Lumps that belong to a map, in the order they follow the marker
+const MAP_LUMPS: &[&str] = &[
+    "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT", "BLOCKMAP",
+];
+
+/// Linedef flag: blocks the player even when two-sided
+pub const ML_BLOCKING: u16 = 1 << 0;
+/// Sidedef index meaning "no sidedef"
+const NO_SIDE: u16 = 0xFFFF;
+
+/// Thing type of the player 1 start
+pub const THING_PLAYER1: u16 = 1;
+
+#[derive(Clone, Copy)]
+pub struct Vertex {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub struct Linedef {
+    pub v1: usize,
+    pub v2: usize,
+    pub flags: u16,
+    pub special: u16,
+    pub tag: u16,
+    /// Sidedef on the right of v1 -> v2
+    pub front: usize,
+    /// Sidedef on the left, for two-sided lines
+    pub back: Option<usize>,
+}
+
+pub struct Sidedef {
+    pub upper: String,
+    pub lower: String,
+    pub middle: String,
+    pub sector: usize,
+}
+
+pub struct Sector {
+    pub floor: i32,
+    pub ceiling: i32,
+    pub floor_flat: String,
+    pub ceiling_flat: String,
+    pub light: u8,
+    pub special: u16,
+    pub tag: u16,
+}
+
+pub struct Thing {
+    pub x: i32,
+    pub y: i32,
+    /// Facing, in degrees counter-clockwise from east
+    pub angle: u16,
+    pub kind: u16,
+    pub flags: u16,
+}
+
+pub struct Map {
+    pub name: String,
+    pub vertexes: Vec<Vertex>,
+    pub linedefs: Vec<Linedef>,
+    pub sidedefs: Vec<Sidedef>,
+    pub sectors: Vec<Sector>,
+    pub things: Vec<Thing>,
+}
+
+/// Split lump `name` of the map into records of `size` bytes
+fn records<'a>(wad: &'a Wad, marker: usize, name: &str, size: usize) -> Result<impl Iterator<Item = &'a [u8]>, &'static str> {
+    let index = (marker + 1..marker + 1 + MAP_LUMPS.len())
+        .find(|&i| wad.lumps.get(i).is_some_and(|lump| lump.name == name))
+        .ok_or("map lump missing")?;
+    let data = wad.lump(index).ok_or("map lump missing")?;
+    Ok(data.chunks_exact(size))
+}
+
+impl Map {
+    /// Load map `name` (E1M1, MAP01, ...) from `wad`
+    pub fn load(wad: &Wad, name: &str) -> Result<Self, &'static str> {
+        let marker = wad.find(name).ok_or("no such map")?;
+        let truncated = "map lump truncated";
+
+        let vertexes: Vec<Vertex> = records(wad, marker, "VERTEXES", 4)?
+            .map(|r| Vertex { x: read_i16(r, 0).unwrap_or(0) as i32, y: read_i16(r, 2).unwrap_or(0) as i32 })
+            .collect();
+
+        let mut sectors = Vec::new();
+        for r in records(wad, marker, "SECTORS", 26)? {
+            sectors.push(Sector {
+                floor: read_i16(r, 0).ok_or(truncated)? as i32,
+                ceiling: read_i16(r, 2).ok_or(truncated)? as i32,
+                floor_flat: read_name(r, 4).ok_or(truncated)?,
+                ceiling_flat: read_name(r, 12).ok_or(truncated)?,
+                light: read_u16(r, 20).ok_or(truncated)?.min(255) as u8,
+                special: read_u16(r, 22).ok_or(truncated)?,
+                tag: read_u16(r, 24).ok_or(truncated)?,
+            });
+        }
+
+        let mut sidedefs = Vec::new();
+        for r in records(wad, marker, "SIDEDEFS", 30)? {
+            let sector = read_u16(r, 28).ok_or(truncated)? as usize;
+            if sector >= sectors.len() {
+                return Err("sidedef refers to a missing sector");
+            }
+            sidedefs.push(Sidedef {
+                upper: read_name(r, 4).ok_or(truncated)?,
+                lower: read_name(r, 12).ok_or(truncated)?,
+                middle: read_name(r, 20).ok_or(truncated)?,
+                sector,
+            });
+        }
+
+        let mut linedefs = Vec::new();
+        for r in records(wad, marker, "LINEDEFS", 14)? {
+            let v1 = read_u16(r, 0).ok_or(truncated)? as usize;
+            let v2 = read_u16(r, 2).ok_or(truncated)? as usize;
+            let front = read_u16(r, 10).ok_or(truncated)?;
+            let back = read_u16(r, 12).ok_or(truncated)?;
+            if v1 >= vertexes.len() || v2 >= vertexes.len() {
+                return Err("linedef refers to a missing vertex");
+            }
+            if front == NO_SIDE || front as usize >= sidedefs.len() {
+                return Err("linedef has no front sidedef");
+            }
+            let back = (back != NO_SIDE).then_some(back as usize);
+            if back.is_some_and(|back| back >= sidedefs.len()) {
+                return Err("linedef refers to a missing sidedef");
+            }
+            linedefs.push(Linedef {
+                v1,
+                v2,
+                flags: read_u16(r, 4).ok_or(truncated)?,
+                special: read_u16(r, 6).ok_or(truncated)?,
+                tag: read_u16(r, 8).ok_or(truncated)?,
+                front: front as usize,
+                back,
+            });
+        }
+
+        let mut things = Vec::new();
+        for r in records(wad, marker, "THINGS", 10)? {
+            things.push(Thing {
+                x: read_i16(r, 0).ok_or(truncated)? as i32,
+                y: read_i16(r, 2).ok_or(truncated)? as i32,
+                angle: read_u16(r, 4).ok_or(truncated)?,
+                kind: read_u16(r, 6).ok_or(truncated)?,
+                flags: read_u16(r, 8).ok_or(truncated)?,
+            });
+        }
+
+        Ok(Self { name: String::from(&wad.lumps[marker].name), vertexes, linedefs, sidedefs, sectors, things })
+    }
+
+    /// Where the player starts
+    pub fn player_start(&self) -> Option<&Thing> {
+        self.things.iter().find(|thing| thing.kind == THING_PLAYER1)
+    }
+
+    /// Sector of the given side of linedef `line`: its front side if `front`
+    pub fn side_sector(&self, line: &Linedef, front: bool) -> Option<usize> {
+        let side = if front { Some(line.front) } else { line.back };
+        side.map(|side| self.sidedefs[side].sector)
+    }
+}
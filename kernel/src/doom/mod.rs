@@ -3,6 +3,11 @@
 
 pub mod task; // v0.1.0: DOOM as background task
 pub mod v015; // v0.1.5: DOOM with syscalls and VMM
+pub mod wad; // WAD archive reader
+pub mod map; // Map lumps: THINGS, LINEDEFS, SIDEDEFS, VERTEXES, SECTORS
+mod fixed; // Fixed-point math
+mod render; // Raycasting view and automap
+mod game; // Player movement and doors
 
 use crate::drivers::framebuffer;
 use crate::drivers::timer;
@@ -10,7 +15,11 @@ use crate::drivers::keyboard;
 use core::sync::atomic::{AtomicBool, Ordering};
 use crate::services::vfs;
 use crate::ipc::message::{FSRequest, FSResponse};
+use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
+use game::Game;
+use spin::Mutex;
 
 static DOOM_RUNNING: AtomicBool = AtomicBool::new(false);
 
@@ -19,6 +28,17 @@ pub const DOOMGENERIC_RESX: usize = 320;
 pub const DOOMGENERIC_RESY: usize = 200;
 const DOOM_FONT_W: usize = 8;
 const DOOM_FONT_H: usize = 16;
+/// Frames a second the game loops aim for, the original game's tic rate
+const TARGET_FPS: u64 = 35;
+
+/// Where `doom` looks for an IWAD, in order; build.sh ships any WAD in
+/// kernel/wads/ as a module
+pub(crate) const WAD_PATHS: &[&str] = &[
+    "/boot/modules/doom1.wad",
+    "/boot/modules/doom.wad",
+    "/boot/modules/doom2.wad",
+    "/boot/modules/freedoom1.wad",
+];
 
 // Doom framebuffer (320x200x4 bytes RGBA)
 static mut DOOM_FRAMEBUFFER: [u32; DOOMGENERIC_RESX * DOOMGENERIC_RESY] = 
@@ -33,6 +53,8 @@ pub struct DoomKeys {
     pub fire: bool,     // Ctrl
     pub use_key: bool,  // Space
     pub strafe: bool,   // Alt
+    pub run: bool,      // Shift
+    pub map: bool,      // Tab
     pub escape: bool,
 }
 
@@ -46,6 +68,8 @@ impl DoomKeys {
             fire: false,
             use_key: false,
             strafe: false,
+            run: false,
+            map: false,
             escape: false,
         }
     }
@@ -61,55 +85,126 @@ pub fn init() {
     unsafe {
         DOOM_FRAMEBUFFER = [0; DOOMGENERIC_RESX * DOOMGENERIC_RESY];
     }
+    // The screen was cleared under us; draw the first frame whole
+    SCALER.lock().take();
 }
 
 /// Append message to /var/log/doom.log (best-effort)
 fn doom_log(msg: &str) {
     let path = String::from("/var/log/doom.log");
     match vfs::process_request(FSRequest::ReadFile { path: path.clone() }) {
-        FSResponse::FileData(mut data) => {
+        FSResponse::FileData(data) => {
+            let mut data = data.into_vec();
             data.extend_from_slice(msg.as_bytes());
             let _ = vfs::process_request(FSRequest::WriteFile { path: path.clone(), data });
         }
-        FSResponse::Error(_) => {
+        FSResponse::Error(..) => {
             let _ = vfs::process_request(FSRequest::WriteFile { path: path.clone(), data: msg.as_bytes().to_vec() });
         }
         _ => {}
     }
 }
 
-/// Draw Doom frame to screen
-pub fn draw_frame() {
-    let fb_info = framebuffer::get_info();
-    let fb_width = fb_info.width;
-    let fb_height = fb_info.height;
-    
-    // Scale Doom 320x200 to screen resolution
-    let scale_x = fb_width / DOOMGENERIC_RESX;
-    let scale_y = fb_height / DOOMGENERIC_RESY;
-    let scale = core::cmp::min(scale_x, scale_y).max(1);
-    
-    let offset_x = (fb_width - DOOMGENERIC_RESX * scale) / 2;
-    let offset_y = (fb_height - DOOMGENERIC_RESY * scale) / 2;
-    
-    unsafe {
-        for y in 0..DOOMGENERIC_RESY {
-            for x in 0..DOOMGENERIC_RESX {
-                let pixel = DOOM_FRAMEBUFFER[y * DOOMGENERIC_RESX + x];
-                
-                // Draw scaled pixel
-                for dy in 0..scale {
-                    for dx in 0..scale {
-                        let screen_x = offset_x + x * scale + dx;
-                        let screen_y = offset_y + y * scale + dy;
-                        framebuffer::set_pixel(screen_x, screen_y, pixel);
-                    }
-                }
+/// Load `map` (by default the first one) from the first WAD found
+fn load_game(map: Option<&str>) -> Result<Game, String> {
+    let (path, data) = WAD_PATHS
+        .iter()
+        .find_map(|path| match vfs::process_request(FSRequest::ReadFile { path: String::from(*path) }) {
+            FSResponse::FileData(data) => Some((*path, data)),
+            _ => None,
+        })
+        .ok_or_else(|| String::from("no WAD found (put doom1.wad in kernel/wads/ and rebuild)"))?;
+    let wad = wad::Wad::parse(data.into_vec()).map_err(|e| format!("{}: {}", path, e))?;
+    let name = match map {
+        Some(name) => name.to_ascii_uppercase(),
+        None => String::from(*wad.maps().first().ok_or_else(|| format!("{}: no maps", path))?),
+    };
+    let level = map::Map::load(&wad, &name).map_err(|e| format!("{}: {}", name, e))?;
+    doom_log(&format!(
+        "DOOM: {} from {}: {} linedefs, {} sectors, {} things\n",
+        name,
+        path,
+        level.linedefs.len(),
+        level.sectors.len(),
+        level.things.len()
+    ));
+    Game::new(level).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// The 320x200 buffer games render into
+fn frame_pixels() -> &'static mut [u32] {
+    unsafe { &mut *core::ptr::addr_of_mut!(DOOM_FRAMEBUFFER) }
+}
+
+/// How a 320x200 frame maps onto the screen, worked out once per screen
+/// size
+struct Scaler {
+    width: usize,
+    height: usize,
+    scale: usize,
+    offset_x: usize,
+    offset_y: usize,
+    /// Source column of each pixel of a scaled row
+    columns: Vec<u16>,
+    /// One scaled row, `columns.len()` pixels
+    row: Vec<u32>,
+    /// The frame drawn last; source rows that have not changed since are
+    /// skipped. Empty until the first frame.
+    last: Vec<u32>,
+}
+
+impl Scaler {
+    fn new(width: usize, height: usize) -> Self {
+        let scale = core::cmp::min(width / DOOMGENERIC_RESX, height / DOOMGENERIC_RESY).max(1);
+        let columns: Vec<u16> = (0..DOOMGENERIC_RESX * scale).map(|x| (x / scale) as u16).collect();
+        Scaler {
+            width,
+            height,
+            scale,
+            offset_x: width.saturating_sub(DOOMGENERIC_RESX * scale) / 2,
+            offset_y: height.saturating_sub(DOOMGENERIC_RESY * scale) / 2,
+            row: alloc::vec![0; columns.len()],
+            columns,
+            last: Vec::new(),
+        }
+    }
+
+    /// Draw the rows of `frame` that changed since the last one, each with
+    /// a single scanline blit
+    fn draw(&mut self, frame: &[u32]) {
+        for (y, source) in frame.chunks_exact(DOOMGENERIC_RESX).enumerate() {
+            if self.last.get(y * DOOMGENERIC_RESX..(y + 1) * DOOMGENERIC_RESX) == Some(source) {
+                continue;
             }
+            for (pixel, &column) in self.row.iter_mut().zip(&self.columns) {
+                *pixel = source[column as usize];
+            }
+            framebuffer::blit_scanline(self.offset_x, self.offset_y + y * self.scale, &self.row, self.scale);
         }
+        self.last.clear();
+        self.last.extend_from_slice(frame);
     }
 }
 
+static SCALER: Mutex<Option<Scaler>> = Mutex::new(None);
+
+/// Draw a 320x200 `frame` to the screen, scaled up by the largest whole
+/// factor that fits and centred
+pub(crate) fn blit_frame(frame: &[u32]) {
+    let info = framebuffer::get_info();
+    let mut scaler = SCALER.lock();
+    let scaler = match &mut *scaler {
+        Some(scaler) if scaler.width == info.width && scaler.height == info.height => scaler,
+        slot => slot.insert(Scaler::new(info.width, info.height)),
+    };
+    scaler.draw(frame);
+}
+
+/// Draw Doom frame to screen
+pub fn draw_frame() {
+    blit_frame(unsafe { &*core::ptr::addr_of!(DOOM_FRAMEBUFFER) });
+}
+
 /// Set pixel in Doom framebuffer
 pub fn set_pixel(x: usize, y: usize, color: u32) {
     if x < DOOMGENERIC_RESX && y < DOOMGENERIC_RESY {
@@ -136,6 +231,7 @@ pub fn process_input() {
                     'a' | 'A' => DOOM_KEYS.left = true,
                     'd' | 'D' => DOOM_KEYS.right = true,
                     ' ' => DOOM_KEYS.use_key = true,
+                    '\t' => DOOM_KEYS.map = true,
                     'q' | 'Q' => DOOM_KEYS.escape = true, // Q to quit
                     '\x1b' => DOOM_KEYS.escape = true,    // ESC
                     '\x03' => DOOM_KEYS.escape = true,    // Ctrl+C
@@ -145,6 +241,13 @@ pub fn process_input() {
             }
         }
     }
+    // Modifiers are state, not key presses
+    let mods = keyboard::modifiers();
+    unsafe {
+        DOOM_KEYS.fire = mods & keyboard::MOD_CTRL != 0;
+        DOOM_KEYS.strafe = mods & (keyboard::MOD_ALT | keyboard::MOD_ALTGR) != 0;
+        DOOM_KEYS.run = mods & keyboard::MOD_SHIFT != 0;
+    }
 }
 
 /// Clear keyboard state (call after frame)
@@ -157,6 +260,8 @@ pub fn clear_input() {
         DOOM_KEYS.fire = false;
         DOOM_KEYS.use_key = false;
         DOOM_KEYS.strafe = false;
+        DOOM_KEYS.run = false;
+        DOOM_KEYS.map = false;
         DOOM_KEYS.escape = false;
     }
 }
@@ -171,8 +276,21 @@ pub fn should_quit() -> bool {
     unsafe { DOOM_KEYS.escape }
 }
 
-/// Run Doom demo mode
-pub fn run_demo() {
+/// Load a game, or explain why the fire demo plays instead
+fn load_or_explain(map: Option<&str>) -> Option<Game> {
+    match load_game(map) {
+        Ok(game) => Some(game),
+        Err(e) => {
+            framebuffer::print(&format!("doom: {}\nShowing the demo instead.\n", e));
+            doom_log(&format!("DOOM: {}\n", e));
+            None
+        }
+    }
+}
+
+/// Run Doom full-screen: map `map` of the WAD if there is one, the demo
+/// otherwise
+pub fn run_demo(map: Option<&str>) {
     framebuffer::clear_screen();
     framebuffer::print("=== DOOM for ospabOS ===\n\n");
     
@@ -181,18 +299,23 @@ pub fn run_demo() {
     
     // Initialize Doom
     init();
+    let mut game = load_or_explain(map);
     
     framebuffer::clear_screen();
-    framebuffer::print("=== DOOM DEMO ===\n");
-    framebuffer::print("Controls: W/A/S/D - Move | Space - Action | Q - Exit\n\n");
+    match &game {
+        Some(game) => framebuffer::print(&format!("=== DOOM: {} ===\n", game.map.name)),
+        None => framebuffer::print("=== DOOM DEMO ===\n"),
+    }
+    framebuffer::print("Controls: W/S - Move | A/D - Turn | Space - Open doors | Tab - Map | Q - Exit\n\n");
     framebuffer::print("Press Q to exit anytime...\n\n");
     sleep_ticks(50);
     
     DOOM_RUNNING.store(true, Ordering::Relaxed);
-    doom_log("DOOM: demo start\n");
+    doom_log("DOOM: start\n");
+    let title = game.as_ref().map_or(String::from("DOOM DEMO"), |game| format!("DOOM {}", game.map.name));
     
-    // Demo animation (fire effect)
     let mut frame = 0u32;
+    let mut pacer = FramePacer::new();
     loop {
         // Check for exit (Q key or ESC)
         process_input();
@@ -201,15 +324,27 @@ pub fn run_demo() {
             break;
         }
         
-        // Draw fire effect demo
-        draw_fire_effect(frame);
+        // Draw the level (or the fire effect), showing the frame once it
+        // is complete
+        match game.as_mut() {
+            Some(game) => {
+                game.update(get_keys());
+                game.render(frame_pixels());
+            }
+            None => {
+                draw_fire_effect(frame);
+                draw_title();
+            }
+        }
+        framebuffer::begin_frame();
         draw_frame();
-        draw_status_bar(frame);
+        draw_status_bar(&title);
+        framebuffer::present();
         
         clear_input();
         frame = frame.wrapping_add(1);
         
-        sleep_ticks(1);
+        pacer.wait();
     }
     
     DOOM_RUNNING.store(false, Ordering::Relaxed);
@@ -218,6 +353,75 @@ pub fn run_demo() {
     doom_log("DOOM: exited\n");
 }
 
+/// Run Doom in a compositor window instead of the whole screen
+pub fn run_windowed(map: Option<&str>) {
+    use crate::ipc::message::{DisplayRequest, DisplayResponse};
+    use crate::services::display;
+
+    init();
+    let mut game = load_or_explain(map);
+    let owner = crate::task::scheduler::SCHEDULER.lock().current_pid();
+    let window = match display::process(DisplayRequest::Create {
+        owner,
+        title: String::from("DOOM"),
+        x: 40,
+        y: 40,
+        width: DOOMGENERIC_RESX as u32,
+        height: DOOMGENERIC_RESY as u32,
+    }) {
+        DisplayResponse::Created(window) => window,
+        DisplayResponse::Error(error, detail) => {
+            let reason = detail.unwrap_or_else(|| String::from(error.as_str()));
+            framebuffer::print(&alloc::format!("doom: cannot open a window: {}\n", reason));
+            return;
+        }
+        DisplayResponse::Success => return,
+    };
+    framebuffer::print("DOOM is running in a window (drag it by the title bar, Tab for the map, Q to exit)\n");
+
+    DOOM_RUNNING.store(true, Ordering::Relaxed);
+    doom_log("DOOM: windowed start\n");
+
+    let mut frame = 0u32;
+    let mut pacer = FramePacer::new();
+    loop {
+        process_input();
+        if should_quit() {
+            doom_log("DOOM: exit requested\n");
+            break;
+        }
+
+        match game.as_mut() {
+            Some(game) => {
+                game.update(get_keys());
+                game.render(frame_pixels());
+            }
+            None => draw_fire_effect(frame),
+        }
+        display::with_pixels(window, |pixels, _, _| {
+            pixels.copy_from_slice(unsafe { &*core::ptr::addr_of!(DOOM_FRAMEBUFFER) });
+        });
+        display::process(DisplayRequest::Damage {
+            window,
+            x: 0,
+            y: 0,
+            width: DOOMGENERIC_RESX as u32,
+            height: DOOMGENERIC_RESY as u32,
+        });
+        // The main loop is waiting on us, so follow the mouse here
+        crate::drivers::mouse::update_pointer();
+        display::poll();
+
+        clear_input();
+        frame = frame.wrapping_add(1);
+        pacer.wait();
+    }
+
+    DOOM_RUNNING.store(false, Ordering::Relaxed);
+    display::process(DisplayRequest::Destroy { window });
+    doom_log("DOOM: exited\n");
+}
+
 /// Show loading screen with progress bar
 fn show_loading_screen() {
     framebuffer::print("Loading DOOM...\n\n");
@@ -255,29 +459,56 @@ fn sleep_ticks(ticks: u64) {
     }
 }
 
+/// Keeps a game loop to `TARGET_FPS`
+struct FramePacer {
+    /// When the next frame is due, from `timer::now_ns`
+    next: u64,
+}
+
+impl FramePacer {
+    fn new() -> Self {
+        FramePacer { next: timer::now_ns() }
+    }
+
+    /// Wait until the next frame is due. A frame that ran over starts the
+    /// schedule afresh instead of rushing the ones after it.
+    fn wait(&mut self) {
+        const FRAME_NS: u64 = 1_000_000_000 / TARGET_FPS;
+        const TICK_NS: u64 = 1_000_000_000 / timer::TICKS_PER_SECOND;
+        self.next += FRAME_NS;
+        let now = timer::now_ns();
+        if now >= self.next {
+            self.next = now;
+            return;
+        }
+        // Sleep whole ticks, then spin out the rest between them
+        while timer::now_ns() + TICK_NS <= self.next {
+            x86_64::instructions::hlt();
+        }
+        while timer::now_ns() < self.next {
+            core::hint::spin_loop();
+        }
+    }
+}
+
 /// Draw status bar at bottom of screen
-fn draw_status_bar(frame: u32) {
+fn draw_status_bar(title: &str) {
     let fb_info = framebuffer::get_info();
     let status_y = fb_info.height - 20;
+    let status_bg = crate::common::palette::current().status_bg;
     
-    // Draw black background for status bar
-    for y in status_y..fb_info.height {
-        for x in 0..fb_info.width {
-            framebuffer::set_pixel(x, y, 0x000000);
-        }
-    }
+    // Draw the status bar background
+    framebuffer::fill_rect(0, status_y, fb_info.width, fb_info.height, status_bg);
     
-    // Draw status text (simplified - just frame counter and exit hint)
+    // Draw the title and the exit hint
     let status_text_y = status_y + 6;
-    let _fps = (frame % 60) as u8; // For future FPS display
-    
-    // Draw "FPS: XX" and "Press Q to exit"
-    draw_status_text(10, status_text_y, b"DOOM DEMO");
+    draw_status_text(10, status_text_y, title.as_bytes());
     draw_status_text(fb_info.width / 2 - 50, status_text_y, b"Press Q to EXIT");
 }
 
 /// Draw simple text on screen (for status bar)
 fn draw_status_text(x: usize, y: usize, text: &[u8]) {
+    let palette = crate::common::palette::current();
     let row = y / DOOM_FONT_H;
     let mut col = x / DOOM_FONT_W;
     for &ch in text {
@@ -285,7 +516,7 @@ fn draw_status_text(x: usize, y: usize, text: &[u8]) {
             col += 1;
             continue;
         }
-        framebuffer::draw_char_at(row, col, ch as char, 0xFFFFFF, 0x000000);
+        framebuffer::draw_char_at(row, col, ch as char, palette.status_fg, palette.status_bg);
         col += 1;
     }
 }
@@ -335,8 +566,10 @@ fn draw_fire_effect(frame: u32) {
             set_pixel(x, y, color);
         }
     }
-    
-    // Draw "DOOM" text in center
+}
+
+/// Draw "DOOM" over the middle of the full-screen demo
+fn draw_title() {
     let text = "D O O M";
     let text_x = DOOMGENERIC_RESX / 2 - text.len() * 4;
     let text_y = DOOMGENERIC_RESY / 2;
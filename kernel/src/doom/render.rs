@@ -0,0 +1,271 @@
+//! Software renderer for WAD maps
+//!
+//! Not DOOM's BSP renderer: every screen column casts a ray against every
+//! linedef, sorts the hits and walks them front to back like a portal
+//! renderer. Each hit draws the ceiling and floor of the sector in front
+//! of it, then the wall: a solid wall ends the column, a two-sided line
+//! draws its upper and lower steps and narrows the open window for the
+//! lines behind it. Walls and flats are flat colours picked from their
+//! texture names and shaded by sector light and distance. A few hundred
+//! linedefs times 320 columns is cheap enough at 320x200.
+//!
+//! The automap draws the linedefs from above, north up, centred on the
+//! player.
+
+use alloc::vec::Vec;
+
+use super::fixed::{cosine, sine, FRACBITS, FRACUNIT};
+use super::map::{Linedef, Map};
+use super::{DOOMGENERIC_RESX as WIDTH, DOOMGENERIC_RESY as HEIGHT};
+
+/// Distance from the eye to the projection plane, for a 90 degree field
+/// of view
+const PROJECTION: i64 = WIDTH as i64 / 2;
+/// Hits closer than this (16.16) are ignored rather than drawn huge
+const NEAR: i64 = FRACUNIT / 4;
+const SKY_FLAT: &str = "F_SKY1";
+const SKY: u32 = 0x5C8CC8;
+/// Map units per automap pixel
+const AUTOMAP_SCALE: i64 = 8;
+
+/// Wall colours, picked by texture name
+const WALL_TONES: &[u32] = &[0x8C8C8C, 0x7C6448, 0x6C5C4C, 0x9C8464, 0x5C6C54, 0x847464, 0x746C6C, 0x94704C];
+/// Floor and ceiling colours, picked by flat name
+const FLAT_TONES: &[u32] = &[0x545454, 0x5C4C3C, 0x4C4C44, 0x645444, 0x3C4C3C, 0x6C6C64];
+
+/// A ray crossing a linedef
+#[derive(Clone, Copy)]
+pub struct Hit {
+    /// Distance along the ray, 16.16 in units of the ray's length
+    pub distance: i64,
+    pub line: usize,
+    /// The ray comes from the linedef's front side
+    pub front: bool,
+}
+
+/// Every linedef crossed by the ray from `origin` along `dir` (both
+/// 16.16), unsorted, into `hits`
+pub fn cast(map: &Map, origin: (i64, i64), dir: (i64, i64), hits: &mut Vec<Hit>) {
+    hits.clear();
+    for (index, line) in map.linedefs.iter().enumerate() {
+        let a = map.vertexes[line.v1];
+        let b = map.vertexes[line.v2];
+        let edge = ((b.x - a.x) as i64, (b.y - a.y) as i64);
+        let rel = (((a.x as i64) << FRACBITS) - origin.0, ((a.y as i64) << FRACBITS) - origin.1);
+
+        // origin + t * dir = a + u * edge, by Cramer's rule
+        let mut denom = dir.0 * edge.1 - dir.1 * edge.0;
+        let side = rel.0 * edge.1 - rel.1 * edge.0;
+        let mut t_num = side;
+        let mut u_num = rel.0 * dir.1 - rel.1 * dir.0;
+        if denom == 0 {
+            continue;
+        }
+        if denom < 0 {
+            denom = -denom;
+            t_num = -t_num;
+            u_num = -u_num;
+        }
+        // u_num is scaled by one more FRACUNIT than denom
+        if t_num <= 0 || u_num < 0 || u_num as i128 > (denom as i128) << FRACBITS {
+            continue;
+        }
+        let distance = (((t_num as i128) << FRACBITS) / denom as i128) as i64;
+        hits.push(Hit { distance, line: index, front: side < 0 });
+    }
+}
+
+/// Sector containing the point (16.16), found from the nearest linedef
+/// east of it
+pub fn point_sector(map: &Map, point: (i64, i64), hits: &mut Vec<Hit>) -> Option<usize> {
+    cast(map, point, (FRACUNIT, 0), hits);
+    let hit = hits.iter().min_by_key(|hit| hit.distance)?;
+    map.side_sector(&map.linedefs[hit.line], hit.front)
+}
+
+fn name_hash(name: &str) -> usize {
+    name.bytes().fold(0usize, |hash, b| hash.wrapping_mul(31).wrapping_add(b as usize))
+}
+
+fn wall_tone(name: &str) -> u32 {
+    WALL_TONES[name_hash(name) % WALL_TONES.len()]
+}
+
+fn flat_tone(name: &str) -> u32 {
+    FLAT_TONES[name_hash(name) % FLAT_TONES.len()]
+}
+
+/// `color` at light level `light` (0..=255)
+fn shade(color: u32, light: i64) -> u32 {
+    let light = light.clamp(16, 255) as u32;
+    let channel = |shift: u32| (((color >> shift) & 0xFF) * light / 255) << shift;
+    channel(16) | channel(8) | channel(0)
+}
+
+/// Light of a wall `distance` (16.16) away, darker with distance like the
+/// original's diminishing light; walls running north-south or east-west
+/// are lightened or darkened a step for contrast
+fn wall_light(map: &Map, line: &Linedef, sector_light: u8, distance: i64) -> i64 {
+    let a = map.vertexes[line.v1];
+    let b = map.vertexes[line.v2];
+    let contrast = if a.x == b.x {
+        16
+    } else if a.y == b.y {
+        -16
+    } else {
+        0
+    };
+    sector_light as i64 + contrast - (distance >> FRACBITS) / 12
+}
+
+fn fill_column(frame: &mut [u32], x: usize, top: i64, bottom: i64, color: u32) {
+    let top = top.clamp(0, HEIGHT as i64) as usize;
+    let bottom = bottom.clamp(0, HEIGHT as i64) as usize;
+    for y in top..bottom {
+        frame[y * WIDTH + x] = color;
+    }
+}
+
+/// Draw the view from `eye` (16.16 x, y, z) facing `angle` into `frame`
+pub fn render_view(map: &Map, eye: (i64, i64, i64), angle: u32, frame: &mut [u32], hits: &mut Vec<Hit>) {
+    let dir = (cosine(angle), sine(angle));
+    // Points right; as long as dir, for a 90 degree field of view
+    let plane = (dir.1, -dir.0);
+    let half = HEIGHT as i64 / 2;
+    // Screen row of height `h` (map units) at `distance`
+    let project = |h: i32, distance: i64| {
+        let dz = ((h as i64) << FRACBITS) - eye.2;
+        (half - dz * PROJECTION / distance).clamp(-(1 << 20), 1 << 20)
+    };
+
+    for x in 0..WIDTH {
+        let camera = ((2 * x as i64 + 1 - WIDTH as i64) << FRACBITS) / WIDTH as i64;
+        // dir has unit length along the view, so hit distances come out
+        // perpendicular to the view plane, with no fisheye
+        let ray = (dir.0 + ((plane.0 * camera) >> FRACBITS), dir.1 + ((plane.1 * camera) >> FRACBITS));
+        cast(map, (eye.0, eye.1), ray, hits);
+        hits.sort_unstable_by_key(|hit| hit.distance);
+
+        let mut top = 0i64;
+        let mut bottom = HEIGHT as i64;
+        for hit in hits.iter().filter(|hit| hit.distance > NEAR) {
+            let line = &map.linedefs[hit.line];
+            let Some(front) = map.side_sector(line, hit.front) else { continue };
+            let front = &map.sectors[front];
+            let ceiling_y = project(front.ceiling, hit.distance);
+            let floor_y = project(front.floor, hit.distance);
+
+            let ceiling = if front.ceiling_flat == SKY_FLAT {
+                SKY
+            } else {
+                shade(flat_tone(&front.ceiling_flat), front.light as i64)
+            };
+            fill_column(frame, x, top, ceiling_y.min(bottom), ceiling);
+            fill_column(frame, x, floor_y.max(top), bottom, shade(flat_tone(&front.floor_flat), front.light as i64));
+
+            let light = wall_light(map, line, front.light, hit.distance);
+            let side = &map.sidedefs[if hit.front { line.front } else { line.back.unwrap_or(line.front) }];
+            let Some(back) = map.side_sector(line, !hit.front) else {
+                fill_column(frame, x, ceiling_y.max(top), floor_y.min(bottom), shade(wall_tone(&side.middle), light));
+                top = bottom;
+                break;
+            };
+            let back = &map.sectors[back];
+            let back_ceiling_y = project(back.ceiling, hit.distance);
+            let back_floor_y = project(back.floor, hit.distance);
+            if back.ceiling < front.ceiling {
+                // Between two skies the step is sky too
+                let color = if front.ceiling_flat == SKY_FLAT && back.ceiling_flat == SKY_FLAT {
+                    SKY
+                } else {
+                    shade(wall_tone(&side.upper), light)
+                };
+                fill_column(frame, x, ceiling_y.max(top), back_ceiling_y.min(bottom), color);
+            }
+            if back.floor > front.floor {
+                fill_column(frame, x, back_floor_y.max(top), floor_y.min(bottom), shade(wall_tone(&side.lower), light));
+            }
+            top = top.max(ceiling_y.max(back_ceiling_y));
+            bottom = bottom.min(floor_y.min(back_floor_y));
+            if top >= bottom {
+                break;
+            }
+        }
+        // Off the edge of the map
+        if top < bottom {
+            fill_column(frame, x, top, bottom, 0);
+        }
+    }
+}
+
+fn plot(frame: &mut [u32], x: i64, y: i64, color: u32) {
+    if (0..WIDTH as i64).contains(&x) && (0..HEIGHT as i64).contains(&y) {
+        frame[y as usize * WIDTH + x as usize] = color;
+    }
+}
+
+/// Bresenham, clipped per pixel
+fn draw_line(frame: &mut [u32], from: (i64, i64), to: (i64, i64), color: u32) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        plot(frame, x, y, color);
+        if (x, y) == to {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draw the automap around `center` (16.16) with the player facing
+/// `angle` into `frame`
+pub fn render_automap(map: &Map, center: (i64, i64), angle: u32, frame: &mut [u32]) {
+    frame.fill(0);
+    let to_screen = |x: i64, y: i64| {
+        (
+            WIDTH as i64 / 2 + (x - (center.0 >> FRACBITS)) / AUTOMAP_SCALE,
+            HEIGHT as i64 / 2 - (y - (center.1 >> FRACBITS)) / AUTOMAP_SCALE,
+        )
+    };
+    // Far off-screen lines are skipped rather than walked pixel by pixel
+    let on_screen = |(x, y): (i64, i64)| x.abs() < 4 * WIDTH as i64 && y.abs() < 4 * HEIGHT as i64;
+
+    for line in &map.linedefs {
+        let a = map.vertexes[line.v1];
+        let b = map.vertexes[line.v2];
+        let from = to_screen(a.x as i64, a.y as i64);
+        let to = to_screen(b.x as i64, b.y as i64);
+        if !on_screen(from) && !on_screen(to) {
+            continue;
+        }
+        // Walls red, steps brown, ceiling changes yellow, like the original
+        let front = &map.sectors[map.sidedefs[line.front].sector];
+        let color = match map.side_sector(line, false).map(|back| &map.sectors[back]) {
+            None => 0xFC0000,
+            Some(back) if back.floor != front.floor => 0xBC7C48,
+            Some(back) if back.ceiling != front.ceiling => 0xFCFC00,
+            Some(_) => continue,
+        };
+        draw_line(frame, from, to, color);
+    }
+
+    // The player as an arrow
+    let (x, y) = to_screen(center.0 >> FRACBITS, center.1 >> FRACBITS);
+    let length = 24 / AUTOMAP_SCALE + 4;
+    let tip = (x + ((cosine(angle) * length) >> FRACBITS), y - ((sine(angle) * length) >> FRACBITS));
+    draw_line(frame, (x, y), tip, 0xFFFFFF);
+    draw_line(frame, (x - 1, y), (x + 1, y), 0xFFFFFF);
+    draw_line(frame, (x, y - 1), (x, y + 1), 0xFFFFFF);
+}
@@ -90,36 +90,10 @@ impl DoomContext {
     
     /// Draw frame to screen
     pub fn draw_frame(&self) {
-        let fb_info = framebuffer::get_info();
-        let fb_width = fb_info.width;
-        let fb_height = fb_info.height;
-        
-        // Scale Doom 320x200 to screen resolution
-        let scale_x = fb_width / DOOMGENERIC_RESX;
-        let scale_y = fb_height / DOOMGENERIC_RESY;
-        let scale = core::cmp::min(scale_x, scale_y).max(1);
-        
-        let offset_x = (fb_width - DOOMGENERIC_RESX * scale) / 2;
-        let offset_y = (fb_height - DOOMGENERIC_RESY * scale) / 2;
-        
-        unsafe {
-            let doom_fb = self.framebuffer();
-            
-            for y in 0..DOOMGENERIC_RESY {
-                for x in 0..DOOMGENERIC_RESX {
-                    let pixel = *doom_fb.add(y * DOOMGENERIC_RESX + x);
-                    
-                    // Draw scaled pixel
-                    for dy in 0..scale {
-                        for dx in 0..scale {
-                            let screen_x = offset_x + x * scale + dx;
-                            let screen_y = offset_y + y * scale + dy;
-                            framebuffer::set_pixel(screen_x, screen_y, pixel);
-                        }
-                    }
-                }
-            }
-        }
+        let frame = unsafe { core::slice::from_raw_parts(self.framebuffer(), DOOMGENERIC_RESX * DOOMGENERIC_RESY) };
+        framebuffer::begin_frame();
+        super::blit_frame(frame);
+        framebuffer::present();
     }
     
     /// Update keyboard state
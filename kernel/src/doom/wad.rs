@@ -0,0 +1,106 @@
+//! WAD archives
+//!
+//! A WAD is a 12-byte header ("IWAD" or "PWAD", lump count, directory
+//! offset) followed by the lump data and a directory of 16-byte entries:
+//! offset, size and an 8-byte name padded with NULs. Lump names repeat, a
+//! map's lumps follow the marker lump named after it (E1M1, MAP01), so a
+//! lump is looked up either by name or by position after its marker.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const HEADER_SIZE: usize = 12;
+const ENTRY_SIZE: usize = 16;
+
+pub struct Lump {
+    pub name: String,
+    offset: usize,
+    size: usize,
+}
+
+pub struct Wad {
+    data: Vec<u8>,
+    pub lumps: Vec<Lump>,
+    /// IWAD (a full game) rather than a PWAD (a patch on top of one)
+    pub iwad: bool,
+}
+
+pub(super) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+pub(super) fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// An 8-byte name up to its first NUL, upper-cased as the engine does
+pub(super) fn read_name(data: &[u8], offset: usize) -> Option<String> {
+    let raw = data.get(offset..offset + 8)?;
+    let len = raw.iter().position(|&b| b == 0).unwrap_or(8);
+    Some(raw[..len].iter().map(|&b| b.to_ascii_uppercase() as char).collect())
+}
+
+impl Wad {
+    pub fn parse(data: Vec<u8>) -> Result<Self, &'static str> {
+        let iwad = match data.get(..4) {
+            Some(b"IWAD") => true,
+            Some(b"PWAD") => false,
+            _ => return Err("not a WAD file"),
+        };
+        if data.len() < HEADER_SIZE {
+            return Err("WAD header truncated");
+        }
+        let count = read_u32(&data, 4).ok_or("WAD header truncated")? as usize;
+        let directory = read_u32(&data, 8).ok_or("WAD header truncated")? as usize;
+        let end = count
+            .checked_mul(ENTRY_SIZE)
+            .and_then(|size| size.checked_add(directory))
+            .ok_or("WAD directory out of range")?;
+        if end > data.len() {
+            return Err("WAD directory out of range");
+        }
+
+        let mut lumps = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = directory + i * ENTRY_SIZE;
+            let offset = read_u32(&data, entry).ok_or("WAD directory truncated")? as usize;
+            let size = read_u32(&data, entry + 4).ok_or("WAD directory truncated")? as usize;
+            let name = read_name(&data, entry + 8).ok_or("WAD directory truncated")?;
+            // Markers have no data and may carry any offset
+            if size != 0 && offset.checked_add(size).map_or(true, |end| end > data.len()) {
+                return Err("WAD lump out of range");
+            }
+            lumps.push(Lump { name, offset, size });
+        }
+        Ok(Self { data, lumps, iwad })
+    }
+
+    /// Index of the last lump named `name`; later lumps override earlier ones
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.lumps.iter().rposition(|lump| lump.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Data of lump `index`
+    pub fn lump(&self, index: usize) -> Option<&[u8]> {
+        let lump = self.lumps.get(index)?;
+        if lump.size == 0 {
+            return Some(&[]);
+        }
+        self.data.get(lump.offset..lump.offset + lump.size)
+    }
+
+    /// Names of the maps in the WAD, in directory order
+    pub fn maps(&self) -> Vec<&str> {
+        self.lumps
+            .iter()
+            .enumerate()
+            // A map marker is followed by its THINGS lump
+            .filter(|(i, _)| self.lumps.get(i + 1).is_some_and(|next| next.name == "THINGS"))
+            .map(|(_, lump)| lump.name.as_str())
+            .collect()
+    }
+}
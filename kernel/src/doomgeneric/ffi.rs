@@ -0,0 +1,159 @@
+//! Platform callbacks for doomgeneric
+//!
+//! The engine renders into DG_ScreenBuffer, which it allocates itself at
+//! DOOMGENERIC_RESX x DOOMGENERIC_RESY (set to crate::doom's 320x200 by
+//! build.rs), and calls DG_DrawFrame when a frame is done. Input is asked
+//! for one event at a time until there are none left, once per tic. The
+//! keyboard driver hands out characters, not key state, so a key is
+//! pressed for one tic and released at the next, and a held key repeats
+//! at the typematic rate. The modifiers are state: Ctrl fires, Shift runs
+//! and Alt strafes for as long as they are held.
+
+#![allow(non_snake_case)]
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_uchar, CStr};
+use spin::Mutex;
+
+use crate::doom::{DOOMGENERIC_RESX, DOOMGENERIC_RESY};
+use crate::drivers::{framebuffer, keyboard, timer};
+
+extern "C" {
+    /// Allocated by doomgeneric_Create
+    static DG_ScreenBuffer: *mut u32;
+}
+
+// Key codes from doomkeys.h
+const KEY_RIGHTARROW: u8 = 0xAE;
+const KEY_LEFTARROW: u8 = 0xAC;
+const KEY_UPARROW: u8 = 0xAD;
+const KEY_DOWNARROW: u8 = 0xAF;
+const KEY_USE: u8 = 0xA2;
+const KEY_FIRE: u8 = 0xA3;
+const KEY_ESCAPE: u8 = 27;
+const KEY_ENTER: u8 = 13;
+const KEY_TAB: u8 = 9;
+const KEY_BACKSPACE: u8 = 0x7F;
+const KEY_RSHIFT: u8 = 0x80 + 0x36;
+const KEY_RALT: u8 = 0x80 + 0x38;
+
+/// Modifier bits and the DOOM keys they hold down
+const MODIFIER_KEYS: &[(u8, u8)] = &[
+    (keyboard::MOD_CTRL, KEY_FIRE),
+    (keyboard::MOD_SHIFT, KEY_RSHIFT),
+    (keyboard::MOD_ALT | keyboard::MOD_ALTGR, KEY_RALT),
+];
+
+struct Input {
+    /// Events of this tic not handed to the engine yet: (pressed, key)
+    events: VecDeque<(bool, u8)>,
+    /// Keys pressed this tic, released at the next
+    held: Vec<u8>,
+    /// Modifiers as of the last tic
+    mods: u8,
+}
+
+static INPUT: Mutex<Input> = Mutex::new(Input { events: VecDeque::new(), held: Vec::new(), mods: 0 });
+
+/// DOOM key for a character from the keyboard driver
+fn doom_key(c: char) -> Option<u8> {
+    let key = match c {
+        'w' | 'W' => KEY_UPARROW,
+        's' | 'S' => KEY_DOWNARROW,
+        'a' | 'A' => KEY_LEFTARROW,
+        'd' | 'D' => KEY_RIGHTARROW,
+        ' ' => KEY_USE,
+        'f' | 'F' => KEY_FIRE,
+        '\x1b' => KEY_ESCAPE,
+        '\n' | '\r' => KEY_ENTER,
+        '\t' => KEY_TAB,
+        '\x08' | '\x7f' => KEY_BACKSPACE,
+        // Menus and cheats take lower-case letters and digits
+        c if c.is_ascii_graphic() => c.to_ascii_lowercase() as u8,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[no_mangle]
+pub extern "C" fn DG_Init() {
+    // Nothing left over from the last game
+    let mut input = INPUT.lock();
+    input.events.clear();
+    input.held.clear();
+    input.mods = 0;
+    drop(input);
+    framebuffer::clear_screen();
+    crate::doom::init();
+}
+
+#[no_mangle]
+pub extern "C" fn DG_DrawFrame() {
+    let pixels = DOOMGENERIC_RESX * DOOMGENERIC_RESY;
+    unsafe {
+        if DG_ScreenBuffer.is_null() {
+            return;
+        }
+        core::ptr::copy_nonoverlapping(DG_ScreenBuffer, crate::doom::get_framebuffer(), pixels);
+    }
+    framebuffer::begin_frame();
+    crate::doom::draw_frame();
+    framebuffer::present();
+}
+
+#[no_mangle]
+pub extern "C" fn DG_SleepMs(ms: u32) {
+    let _ = crate::timers::sleep(crate::timers::ms_to_ticks(ms as u64));
+}
+
+#[no_mangle]
+pub extern "C" fn DG_GetTicksMs() -> u32 {
+    timer::get_uptime_ms() as u32
+}
+
+/// Next key event into `pressed` and `key`; 0 if there is none
+#[no_mangle]
+pub unsafe extern "C" fn DG_GetKey(pressed: *mut c_int, key: *mut c_uchar) -> c_int {
+    let mut input = INPUT.lock();
+    if let Some((down, k)) = input.events.pop_front() {
+        *pressed = down as c_int;
+        *key = k;
+        return 1;
+    }
+
+    // This tic's events are all out; queue the next tic's
+    let mut quit = false;
+    let Input { events, held, mods } = &mut *input;
+    events.extend(held.drain(..).map(|k| (false, k)));
+    while let Some(c) = keyboard::try_read_key() {
+        // The engine has its own quit, but Ctrl+C always works
+        if c == '\x03' {
+            quit = true;
+            break;
+        }
+        if let Some(k) = doom_key(c) {
+            events.push_back((true, k));
+            held.push(k);
+        }
+    }
+    let now = keyboard::modifiers();
+    for &(bits, k) in MODIFIER_KEYS {
+        if (now & bits != 0) != (*mods & bits != 0) {
+            events.push_back((now & bits != 0, k));
+        }
+    }
+    *mods = now;
+    if quit {
+        drop(input);
+        super::libc::exit(0);
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn DG_SetWindowTitle(title: *const c_char) {
+    if !title.is_null() {
+        crate::serial_println!("[DOOM] {}", CStr::from_ptr(title).to_string_lossy());
+    }
+}
@@ -0,0 +1,318 @@
+//! The kernel's half of doomgeneric's C library
+//!
+//! libc_compat.c has the parts that are plain C: stdio on top of
+//! in-memory files, strings, ctype. What needs the kernel is here: the
+//! heap, printf formatting (vsnprintf, which every printf in libc_compat.c
+//! ends up in), exit and the file hooks stdio is built on.
+//!
+//! Every block handed out by malloc is remembered, so what the engine did
+//! not free (its zone, mostly) is freed when it exits.
+
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, Layout};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_uint, c_void, CStr, VaList};
+use spin::Mutex;
+
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+
+/// malloc's alignment: enough for any C type
+const ALIGN: usize = 16;
+
+/// Live blocks: address to size
+static BLOCKS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.max(1), ALIGN).ok()
+}
+
+unsafe fn allocate(size: usize, zeroed: bool) -> *mut c_void {
+    let Some(layout) = layout(size) else { return core::ptr::null_mut() };
+    let ptr = if zeroed { alloc_zeroed(layout) } else { alloc(layout) };
+    if !ptr.is_null() {
+        BLOCKS.lock().insert(ptr as usize, size);
+    }
+    ptr.cast()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    allocate(size, false)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(total) => allocate(total, true),
+        None => core::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    let Some(&old) = BLOCKS.lock().get(&(ptr as usize)) else { return core::ptr::null_mut() };
+    let new = malloc(size);
+    if !new.is_null() {
+        core::ptr::copy_nonoverlapping(ptr.cast::<u8>(), new.cast::<u8>(), old.min(size));
+        free(ptr);
+    }
+    new
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    // Unknown pointers are ignored rather than corrupting the heap
+    let Some(size) = BLOCKS.lock().remove(&(ptr as usize)) else { return };
+    if let Some(layout) = layout(size) {
+        dealloc(ptr.cast(), layout);
+    }
+}
+
+/// Free every block still allocated
+fn release_all() {
+    let blocks = core::mem::take(&mut *BLOCKS.lock());
+    for (ptr, size) in blocks {
+        if let Some(layout) = layout(size) {
+            unsafe { dealloc(ptr as *mut u8, layout) };
+        }
+    }
+}
+
+/// End the engine's task
+#[no_mangle]
+pub extern "C" fn exit(status: c_int) -> ! {
+    release_all();
+    crate::task::scheduler::SCHEDULER.lock().terminate_current(status);
+    loop {
+        crate::task::scheduler::yield_now();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn abort() -> ! {
+    crate::serial_println!("[DOOM] abort()");
+    // The status a shell shows for SIGABRT
+    exit(134)
+}
+
+/// Text printed by stdout and stderr
+#[no_mangle]
+pub unsafe extern "C" fn DG_ConsoleWrite(text: *const c_char, len: usize) {
+    let bytes = core::slice::from_raw_parts(text.cast::<u8>(), len);
+    let text = String::from_utf8_lossy(bytes);
+    crate::drivers::framebuffer::print(&text);
+    crate::drivers::serial::write(&text);
+}
+
+/// File `path` read whole into a block from malloc, its length in `len`;
+/// NULL if it cannot be read
+#[no_mangle]
+pub unsafe extern "C" fn DG_VFS_ReadFile(path: *const c_char, len: *mut usize) -> *mut u8 {
+    let path = String::from(CStr::from_ptr(path).to_string_lossy());
+    let FSResponse::FileData(data) = vfs::process_request(FSRequest::ReadFile { path }) else {
+        return core::ptr::null_mut();
+    };
+    let buf = malloc(data.len()).cast::<u8>();
+    if !buf.is_null() {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+        *len = data.len();
+    }
+    buf
+}
+
+/// Replace file `path` with `len` bytes at `data`; 0 on success
+#[no_mangle]
+pub unsafe extern "C" fn DG_VFS_WriteFile(path: *const c_char, data: *const u8, len: usize) -> c_int {
+    let path = String::from(CStr::from_ptr(path).to_string_lossy());
+    let data = core::slice::from_raw_parts(data, len).to_vec();
+    match vfs::process_request(FSRequest::WriteFile { path, data }) {
+        FSResponse::Success => 0,
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn DG_VFS_Remove(path: *const c_char) -> c_int {
+    let path = String::from(CStr::from_ptr(path).to_string_lossy());
+    match vfs::process_request(FSRequest::Delete { path }) {
+        FSResponse::Success => 0,
+        _ => -1,
+    }
+}
+
+/// `value` in `radix`, most significant digit first
+fn digits(mut value: u64, radix: u64, upper: bool) -> Vec<u8> {
+    let table = if upper { b"0123456789ABCDEF" } else { b"0123456789abcdef" };
+    let mut out = Vec::new();
+    loop {
+        out.push(table[(value % radix) as usize]);
+        value /= radix;
+        if value == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    out
+}
+
+/// printf formatting: flags, width and precision (also as `*`), the
+/// h/l/ll/z/j/t lengths and d i u x X o c s p f %
+unsafe fn format(format: *const c_char, args: &mut VaList) -> Vec<u8> {
+    let fmt = CStr::from_ptr(format).to_bytes();
+    let mut out = Vec::with_capacity(fmt.len());
+    let mut i = 0;
+    while i < fmt.len() {
+        let c = fmt[i];
+        i += 1;
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        let (mut left, mut zero, mut plus, mut space, mut alt) = (false, false, false, false, false);
+        while let Some(&flag) = fmt.get(i) {
+            match flag {
+                b'-' => left = true,
+                b'0' => zero = true,
+                b'+' => plus = true,
+                b' ' => space = true,
+                b'#' => alt = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        let mut width = 0usize;
+        if fmt.get(i) == Some(&b'*') {
+            let value = args.arg::<c_int>();
+            left |= value < 0;
+            width = value.unsigned_abs() as usize;
+            i += 1;
+        }
+        while let Some(d @ b'0'..=b'9') = fmt.get(i) {
+            width = width * 10 + (d - b'0') as usize;
+            i += 1;
+        }
+        let mut precision = None;
+        if fmt.get(i) == Some(&b'.') {
+            i += 1;
+            let mut value = 0usize;
+            if fmt.get(i) == Some(&b'*') {
+                value = args.arg::<c_int>().max(0) as usize;
+                i += 1;
+            }
+            while let Some(d @ b'0'..=b'9') = fmt.get(i) {
+                value = value * 10 + (d - b'0') as usize;
+                i += 1;
+            }
+            precision = Some(value);
+        }
+        let mut long = false;
+        while let Some(&length) = fmt.get(i) {
+            match length {
+                b'l' | b'z' | b'j' | b't' => long = true,
+                b'h' | b'L' => {}
+                _ => break,
+            }
+            i += 1;
+        }
+        let Some(&conversion) = fmt.get(i) else { break };
+        i += 1;
+
+        let (prefix, mut body, numeric): (&[u8], Vec<u8>, bool) = match conversion {
+            b'd' | b'i' => {
+                let value = if long { args.arg::<i64>() } else { args.arg::<c_int>() as i64 };
+                let sign: &[u8] = if value < 0 {
+                    b"-"
+                } else if plus {
+                    b"+"
+                } else if space {
+                    b" "
+                } else {
+                    b""
+                };
+                (sign, digits(value.unsigned_abs(), 10, false), true)
+            }
+            b'u' | b'x' | b'X' | b'o' => {
+                let value = if long { args.arg::<u64>() } else { args.arg::<c_uint>() as u64 };
+                let (radix, prefix): (u64, &[u8]) = match conversion {
+                    b'x' if alt && value != 0 => (16, b"0x"),
+                    b'X' if alt && value != 0 => (16, b"0X"),
+                    b'x' | b'X' => (16, b""),
+                    b'o' if alt => (8, b"0"),
+                    b'o' => (8, b""),
+                    _ => (10, b""),
+                };
+                (prefix, digits(value, radix, conversion == b'X'), true)
+            }
+            b'p' => (b"0x", digits(args.arg::<*const c_void>() as u64, 16, false), true),
+            b'c' => (b"", alloc::vec![args.arg::<c_int>() as u8], false),
+            b's' => {
+                let ptr = args.arg::<*const c_char>();
+                let mut bytes = if ptr.is_null() { b"(null)".to_vec() } else { CStr::from_ptr(ptr).to_bytes().to_vec() };
+                if let Some(precision) = precision {
+                    bytes.truncate(precision);
+                }
+                (b"", bytes, false)
+            }
+            b'f' | b'F' | b'g' | b'G' | b'e' | b'E' => {
+                let value = args.arg::<f64>();
+                let text = alloc::format!("{:.*}", precision.unwrap_or(6), value);
+                (b"", text.into_bytes(), false)
+            }
+            b'%' => {
+                out.push(b'%');
+                continue;
+            }
+            other => {
+                out.extend_from_slice(&[b'%', other]);
+                continue;
+            }
+        };
+
+        // Integer precision is a minimum digit count, and turns off '0'
+        if numeric && conversion != b'p' {
+            if let Some(precision) = precision {
+                if body.len() < precision {
+                    let mut padded = alloc::vec![b'0'; precision - body.len()];
+                    padded.append(&mut body);
+                    body = padded;
+                }
+                zero = false;
+            }
+        }
+        let pad = width.saturating_sub(prefix.len() + body.len());
+        if left {
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(&body);
+            out.resize(out.len() + pad, b' ');
+        } else if zero && numeric {
+            out.extend_from_slice(prefix);
+            out.resize(out.len() + pad, b'0');
+            out.extend_from_slice(&body);
+        } else {
+            out.resize(out.len() + pad, b' ');
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(&body);
+        }
+    }
+    out
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vsnprintf(buf: *mut c_char, size: usize, fmt: *const c_char, mut args: VaList) -> c_int {
+    let text = format(fmt, &mut args);
+    if size > 0 && !buf.is_null() {
+        let len = text.len().min(size - 1);
+        core::ptr::copy_nonoverlapping(text.as_ptr(), buf.cast::<u8>(), len);
+        *buf.add(len) = 0;
+    }
+    text.len() as c_int
+}
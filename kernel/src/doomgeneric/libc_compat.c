@@ -0,0 +1,660 @@
+/*
+ * The plain-C half of doomgeneric's C library on ospabOS.
+ *
+ * The engine is compiled against the host's glibc headers, so this
+ * provides what those headers call: stdio, string and ctype functions,
+ * errno, and glibc's own names for some of them (__isoc99_sscanf,
+ * __ctype_b_loc, ...). Nothing here includes a system header.
+ *
+ * Files live in memory: fopen reads the whole file through the kernel's
+ * VFS, and a file opened for writing is written back on fclose. The heap,
+ * vsnprintf, exit and the file hooks are in Rust (doomgeneric::libc).
+ */
+
+#include <stdarg.h>
+#include <stddef.h>
+
+/* doomgeneric::libc */
+void *malloc(size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+void exit(int status);
+int vsnprintf(char *buf, size_t size, const char *format, va_list args);
+void DG_ConsoleWrite(const char *text, size_t len);
+unsigned char *DG_VFS_ReadFile(const char *path, size_t *len);
+int DG_VFS_WriteFile(const char *path, const unsigned char *data, size_t len);
+int DG_VFS_Remove(const char *path);
+
+/* compiler_builtins */
+size_t strlen(const char *s);
+void *memcpy(void *dst, const void *src, size_t n);
+void *memset(void *dst, int c, size_t n);
+
+#define EOF (-1)
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+#define ENOENT 2
+#define ENOSYS 38
+#define PATH_MAX 256
+
+typedef struct dg_file {
+    unsigned char *data;
+    size_t size;
+    size_t capacity;
+    size_t pos;
+    int writable;   /* written back to path on fclose */
+    int console;    /* stdout or stderr */
+    int eof;
+    char path[PATH_MAX];
+} FILE;
+
+static FILE stdin_file = { 0 };
+static FILE stdout_file = { .console = 1 };
+static FILE stderr_file = { .console = 1 };
+FILE *stdin = &stdin_file;
+FILE *stdout = &stdout_file;
+FILE *stderr = &stderr_file;
+
+static int libc_errno;
+
+int *__errno_location(void)
+{
+    return &libc_errno;
+}
+
+/* ---- strings ---- */
+
+int strcmp(const char *a, const char *b)
+{
+    while (*a && *a == *b) {
+        a++;
+        b++;
+    }
+    return (unsigned char)*a - (unsigned char)*b;
+}
+
+int strncmp(const char *a, const char *b, size_t n)
+{
+    for (; n; n--, a++, b++) {
+        if (*a != *b || !*a)
+            return (unsigned char)*a - (unsigned char)*b;
+    }
+    return 0;
+}
+
+int toupper(int c)
+{
+    return c >= 'a' && c <= 'z' ? c - 'a' + 'A' : c;
+}
+
+int tolower(int c)
+{
+    return c >= 'A' && c <= 'Z' ? c - 'A' + 'a' : c;
+}
+
+int strcasecmp(const char *a, const char *b)
+{
+    while (*a && tolower((unsigned char)*a) == tolower((unsigned char)*b)) {
+        a++;
+        b++;
+    }
+    return tolower((unsigned char)*a) - tolower((unsigned char)*b);
+}
+
+int strncasecmp(const char *a, const char *b, size_t n)
+{
+    for (; n; n--, a++, b++) {
+        int ca = tolower((unsigned char)*a), cb = tolower((unsigned char)*b);
+        if (ca != cb || !ca)
+            return ca - cb;
+    }
+    return 0;
+}
+
+char *strcpy(char *dst, const char *src)
+{
+    char *d = dst;
+    while ((*d++ = *src++))
+        ;
+    return dst;
+}
+
+char *strncpy(char *dst, const char *src, size_t n)
+{
+    size_t i = 0;
+    for (; i < n && src[i]; i++)
+        dst[i] = src[i];
+    for (; i < n; i++)
+        dst[i] = 0;
+    return dst;
+}
+
+char *strcat(char *dst, const char *src)
+{
+    strcpy(dst + strlen(dst), src);
+    return dst;
+}
+
+char *strncat(char *dst, const char *src, size_t n)
+{
+    char *d = dst + strlen(dst);
+    while (n-- && *src)
+        *d++ = *src++;
+    *d = 0;
+    return dst;
+}
+
+char *strdup(const char *s)
+{
+    size_t len = strlen(s) + 1;
+    char *copy = malloc(len);
+    if (copy)
+        memcpy(copy, s, len);
+    return copy;
+}
+
+char *strchr(const char *s, int c)
+{
+    for (;; s++) {
+        if (*s == (char)c)
+            return (char *)s;
+        if (!*s)
+            return NULL;
+    }
+}
+
+char *strrchr(const char *s, int c)
+{
+    const char *last = NULL;
+    for (;; s++) {
+        if (*s == (char)c)
+            last = s;
+        if (!*s)
+            return (char *)last;
+    }
+}
+
+char *strstr(const char *haystack, const char *needle)
+{
+    size_t len = strlen(needle);
+    for (; *haystack; haystack++) {
+        if (!strncmp(haystack, needle, len))
+            return (char *)haystack;
+    }
+    return len ? NULL : (char *)haystack;
+}
+
+/* ---- ctype, with glibc's tables for its macros ---- */
+
+int isspace(int c) { return c == ' ' || (c >= '\t' && c <= '\r'); }
+int isdigit(int c) { return c >= '0' && c <= '9'; }
+int isupper(int c) { return c >= 'A' && c <= 'Z'; }
+int islower(int c) { return c >= 'a' && c <= 'z'; }
+int isalpha(int c) { return isupper(c) || islower(c); }
+int isalnum(int c) { return isalpha(c) || isdigit(c); }
+int isxdigit(int c) { return isdigit(c) || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F'); }
+int isprint(int c) { return c >= 0x20 && c < 0x7F; }
+int isgraph(int c) { return c > 0x20 && c < 0x7F; }
+int iscntrl(int c) { return (c >= 0 && c < 0x20) || c == 0x7F; }
+int ispunct(int c) { return isgraph(c) && !isalnum(c); }
+
+/* glibc's class bits, as laid out on little-endian machines */
+enum {
+    CT_UPPER = 1 << 8,
+    CT_LOWER = 1 << 9,
+    CT_ALPHA = 1 << 10,
+    CT_DIGIT = 1 << 11,
+    CT_XDIGIT = 1 << 12,
+    CT_SPACE = 1 << 13,
+    CT_PRINT = 1 << 14,
+    CT_GRAPH = 1 << 15,
+    CT_BLANK = 1 << 0,
+    CT_CNTRL = 1 << 1,
+    CT_PUNCT = 1 << 2,
+    CT_ALNUM = 1 << 3,
+};
+
+/* Indexed from -128 (signed chars) to 255 */
+static unsigned short ctype_b[384];
+static int ctype_upper[384];
+static int ctype_lower[384];
+static const unsigned short *ctype_b_ptr;
+static const int *ctype_upper_ptr;
+static const int *ctype_lower_ptr;
+
+static void ctype_init(void)
+{
+    if (ctype_b_ptr)
+        return;
+    for (int i = 0; i < 384; i++) {
+        int c = i - 128;
+        unsigned short bits = 0;
+        if (c >= 0 && c < 0x80) {
+            bits |= isupper(c) ? CT_UPPER : 0;
+            bits |= islower(c) ? CT_LOWER : 0;
+            bits |= isalpha(c) ? CT_ALPHA : 0;
+            bits |= isdigit(c) ? CT_DIGIT : 0;
+            bits |= isxdigit(c) ? CT_XDIGIT : 0;
+            bits |= isspace(c) ? CT_SPACE : 0;
+            bits |= isprint(c) ? CT_PRINT : 0;
+            bits |= isgraph(c) ? CT_GRAPH : 0;
+            bits |= (c == ' ' || c == '\t') ? CT_BLANK : 0;
+            bits |= iscntrl(c) ? CT_CNTRL : 0;
+            bits |= ispunct(c) ? CT_PUNCT : 0;
+            bits |= isalnum(c) ? CT_ALNUM : 0;
+        }
+        ctype_b[i] = bits;
+        ctype_upper[i] = c >= 0 ? toupper(c) : c;
+        ctype_lower[i] = c >= 0 ? tolower(c) : c;
+    }
+    ctype_upper_ptr = ctype_upper + 128;
+    ctype_lower_ptr = ctype_lower + 128;
+    ctype_b_ptr = ctype_b + 128;
+}
+
+const unsigned short **__ctype_b_loc(void)
+{
+    ctype_init();
+    return &ctype_b_ptr;
+}
+
+const int **__ctype_toupper_loc(void)
+{
+    ctype_init();
+    return &ctype_upper_ptr;
+}
+
+const int **__ctype_tolower_loc(void)
+{
+    ctype_init();
+    return &ctype_lower_ptr;
+}
+
+/* ---- numbers ---- */
+
+long strtol(const char *s, char **end, int base)
+{
+    long value = 0;
+    int negative = 0;
+    while (isspace((unsigned char)*s))
+        s++;
+    if (*s == '-' || *s == '+')
+        negative = *s++ == '-';
+    if ((base == 0 || base == 16) && s[0] == '0' && (s[1] == 'x' || s[1] == 'X')) {
+        s += 2;
+        base = 16;
+    } else if (base == 0) {
+        base = s[0] == '0' ? 8 : 10;
+    }
+    for (;; s++) {
+        int digit;
+        if (isdigit((unsigned char)*s))
+            digit = *s - '0';
+        else if (isalpha((unsigned char)*s))
+            digit = tolower((unsigned char)*s) - 'a' + 10;
+        else
+            break;
+        if (digit >= base)
+            break;
+        value = value * base + digit;
+    }
+    if (end)
+        *end = (char *)s;
+    return negative ? -value : value;
+}
+
+unsigned long strtoul(const char *s, char **end, int base)
+{
+    return (unsigned long)strtol(s, end, base);
+}
+
+int atoi(const char *s)
+{
+    return (int)strtol(s, NULL, 10);
+}
+
+long atol(const char *s)
+{
+    return strtol(s, NULL, 10);
+}
+
+int abs(int value)
+{
+    return value < 0 ? -value : value;
+}
+
+/* ---- stdio ---- */
+
+int vsprintf(char *buf, const char *format, va_list args)
+{
+    return vsnprintf(buf, (size_t)-1 >> 1, format, args);
+}
+
+int snprintf(char *buf, size_t size, const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int len = vsnprintf(buf, size, format, args);
+    va_end(args);
+    return len;
+}
+
+int sprintf(char *buf, const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int len = vsprintf(buf, format, args);
+    va_end(args);
+    return len;
+}
+
+static int grow(FILE *file, size_t size)
+{
+    if (size <= file->capacity)
+        return 0;
+    size_t capacity = file->capacity ? file->capacity : 256;
+    while (capacity < size)
+        capacity *= 2;
+    unsigned char *data = realloc(file->data, capacity);
+    if (!data)
+        return -1;
+    file->data = data;
+    file->capacity = capacity;
+    return 0;
+}
+
+size_t fwrite(const void *ptr, size_t size, size_t count, FILE *file)
+{
+    size_t len = size * count;
+    if (!len)
+        return 0;
+    if (file->console) {
+        DG_ConsoleWrite(ptr, len);
+        return count;
+    }
+    if (!file->writable || grow(file, file->pos + len))
+        return 0;
+    memcpy(file->data + file->pos, ptr, len);
+    file->pos += len;
+    if (file->pos > file->size)
+        file->size = file->pos;
+    return count;
+}
+
+int vfprintf(FILE *file, const char *format, va_list args)
+{
+    char small[256];
+    va_list copy;
+    va_copy(copy, args);
+    int len = vsnprintf(small, sizeof(small), format, copy);
+    va_end(copy);
+    if (len < 0)
+        return len;
+    if ((size_t)len < sizeof(small)) {
+        fwrite(small, 1, len, file);
+        return len;
+    }
+    char *big = malloc(len + 1);
+    if (!big)
+        return -1;
+    vsnprintf(big, len + 1, format, args);
+    fwrite(big, 1, len, file);
+    free(big);
+    return len;
+}
+
+int fprintf(FILE *file, const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int len = vfprintf(file, format, args);
+    va_end(args);
+    return len;
+}
+
+int vprintf(const char *format, va_list args)
+{
+    return vfprintf(stdout, format, args);
+}
+
+int printf(const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int len = vfprintf(stdout, format, args);
+    va_end(args);
+    return len;
+}
+
+int fputs(const char *s, FILE *file)
+{
+    return fwrite(s, 1, strlen(s), file) ? 0 : EOF;
+}
+
+int puts(const char *s)
+{
+    fputs(s, stdout);
+    return fputs("\n", stdout);
+}
+
+int fputc(int c, FILE *file)
+{
+    unsigned char byte = (unsigned char)c;
+    return fwrite(&byte, 1, 1, file) ? byte : EOF;
+}
+
+int putc(int c, FILE *file)
+{
+    return fputc(c, file);
+}
+
+int putchar(int c)
+{
+    return fputc(c, stdout);
+}
+
+FILE *fopen(const char *path, const char *mode)
+{
+    FILE *file = malloc(sizeof(FILE));
+    if (!file)
+        return NULL;
+    memset(file, 0, sizeof(FILE));
+    strncpy(file->path, path, PATH_MAX - 1);
+
+    if (mode[0] == 'r' || mode[0] == 'a') {
+        size_t len = 0;
+        file->data = DG_VFS_ReadFile(path, &len);
+        if (file->data) {
+            file->size = file->capacity = len;
+        } else if (mode[0] == 'r') {
+            free(file);
+            libc_errno = ENOENT;
+            return NULL;
+        }
+    }
+    file->writable = mode[0] != 'r' || strchr(mode, '+') != NULL;
+    if (mode[0] == 'a')
+        file->pos = file->size;
+    return file;
+}
+
+int fflush(FILE *file)
+{
+    if (file && file->writable && file->path[0])
+        return DG_VFS_WriteFile(file->path, file->data, file->size) ? EOF : 0;
+    return 0;
+}
+
+int fclose(FILE *file)
+{
+    int result = fflush(file);
+    free(file->data);
+    free(file);
+    return result;
+}
+
+size_t fread(void *ptr, size_t size, size_t count, FILE *file)
+{
+    if (!size || file->console)
+        return 0;
+    size_t available = (file->size - file->pos) / size;
+    if (count > available) {
+        count = available;
+        file->eof = 1;
+    }
+    memcpy(ptr, file->data + file->pos, count * size);
+    file->pos += count * size;
+    return count;
+}
+
+int fseek(FILE *file, long offset, int whence)
+{
+    long base = whence == SEEK_CUR ? (long)file->pos : whence == SEEK_END ? (long)file->size : 0;
+    if (base + offset < 0 || (size_t)(base + offset) > file->size)
+        return -1;
+    file->pos = base + offset;
+    file->eof = 0;
+    return 0;
+}
+
+long ftell(FILE *file)
+{
+    return (long)file->pos;
+}
+
+int feof(FILE *file)
+{
+    return file->eof;
+}
+
+int fgetc(FILE *file)
+{
+    unsigned char byte;
+    return fread(&byte, 1, 1, file) ? byte : EOF;
+}
+
+char *fgets(char *buf, int size, FILE *file)
+{
+    int i = 0;
+    while (i < size - 1) {
+        int c = fgetc(file);
+        if (c == EOF)
+            break;
+        buf[i++] = (char)c;
+        if (c == '\n')
+            break;
+    }
+    if (!i)
+        return NULL;
+    buf[i] = 0;
+    return buf;
+}
+
+/*
+ * Enough scanf for the engine's config file: %d, %i, %x, %s and literal
+ * text, without widths or suppression.
+ */
+static int vsscanf_simple(const char *s, const char *format, va_list args)
+{
+    int assigned = 0;
+    while (*format) {
+        if (isspace((unsigned char)*format)) {
+            while (isspace((unsigned char)*s))
+                s++;
+            format++;
+            continue;
+        }
+        if (*format != '%') {
+            if (*s++ != *format++)
+                break;
+            continue;
+        }
+        format++;
+        char conversion = *format++;
+        while (isspace((unsigned char)*s))
+            s++;
+        if (!*s)
+            break;
+        if (conversion == 'd' || conversion == 'i' || conversion == 'x') {
+            char *end;
+            long value = strtol(s, &end, conversion == 'd' ? 10 : conversion == 'x' ? 16 : 0);
+            if (end == s)
+                break;
+            *va_arg(args, int *) = (int)value;
+            s = end;
+        } else if (conversion == 's') {
+            char *out = va_arg(args, char *);
+            while (*s && !isspace((unsigned char)*s))
+                *out++ = *s++;
+            *out = 0;
+        } else {
+            break;
+        }
+        assigned++;
+    }
+    return assigned;
+}
+
+int sscanf(const char *s, const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int assigned = vsscanf_simple(s, format, args);
+    va_end(args);
+    return assigned;
+}
+
+int __isoc99_sscanf(const char *s, const char *format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    int assigned = vsscanf_simple(s, format, args);
+    va_end(args);
+    return assigned;
+}
+
+/* ---- files and processes ---- */
+
+int remove(const char *path)
+{
+    return DG_VFS_Remove(path);
+}
+
+int rename(const char *from, const char *to)
+{
+    size_t len = 0;
+    unsigned char *data = DG_VFS_ReadFile(from, &len);
+    if (!data)
+        return -1;
+    int result = DG_VFS_WriteFile(to, data, len);
+    free(data);
+    return result ? result : DG_VFS_Remove(from);
+}
+
+int mkdir(const char *path, int mode)
+{
+    (void)path;
+    (void)mode;
+    /* Saves go to the current directory */
+    return 0;
+}
+
+char *getenv(const char *name)
+{
+    (void)name;
+    return NULL;
+}
+
+int system(const char *command)
+{
+    (void)command;
+    libc_errno = ENOSYS;
+    return -1;
+}
+
+void __assert_fail(const char *expr, const char *file, unsigned int line, const char *function)
+{
+    fprintf(stderr, "%s:%u: %s: assertion `%s' failed\n", file, line, function, expr);
+    exit(134);
+}
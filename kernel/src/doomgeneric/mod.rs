@@ -0,0 +1,59 @@
+//! doomgeneric bridge
+//!
+//! doomgeneric is the original DOOM engine cut down to a few platform
+//! callbacks. Its C sources are not in the repo: copied to
+//! src/vendor/doomgeneric, build.rs compiles them with libc_compat.c and
+//! sets `cfg(doomgeneric)`. `ffi` provides the callbacks (DG_*), backed by
+//! the framebuffer, timer and keyboard drivers, and `libc` the part of the
+//! C library that needs the kernel: the heap, printf formatting, exit and
+//! file loading.
+//!
+//! The engine runs as a kernel task of its own. It quits by calling
+//! exit(), which ends that task, while the shell waits for it.
+
+#[cfg(doomgeneric)]
+pub mod ffi;
+#[cfg(doomgeneric)]
+pub mod libc;
+
+#[cfg(doomgeneric)]
+extern "C" {
+    fn doomgeneric_Create(argc: core::ffi::c_int, argv: *mut *mut core::ffi::c_char);
+    fn doomgeneric_Tick();
+}
+
+/// Run the engine until it quits
+pub fn run() {
+    #[cfg(doomgeneric)]
+    {
+        let pid = crate::task::spawn_kernel_task("doomgeneric", engine_task);
+        let status = crate::task::wait_child_raw(pid);
+        crate::drivers::framebuffer::clear_screen();
+        crate::serial_println!("[DOOM] doomgeneric exited with status {:?}", status);
+    }
+    #[cfg(not(doomgeneric))]
+    crate::drivers::framebuffer::print("doomgeneric is not built in: copy its sources to src/vendor/doomgeneric\n");
+}
+
+#[cfg(doomgeneric)]
+fn engine_task() -> ! {
+    use alloc::vec::Vec;
+
+    // The engine is told where its IWAD is, as on any other system
+    let iwad = crate::doom::WAD_PATHS
+        .iter()
+        .copied()
+        .find(|path| crate::services::vfs::open(path, 0).is_ok())
+        .unwrap_or(crate::doom::WAD_PATHS[0]);
+    let mut args: Vec<Vec<u8>> = ["doom", "-iwad", iwad]
+        .iter()
+        .map(|arg| arg.bytes().chain(core::iter::once(0)).collect())
+        .collect();
+    let mut argv: Vec<*mut core::ffi::c_char> = args.iter_mut().map(|arg| arg.as_mut_ptr().cast()).collect();
+    unsafe {
+        doomgeneric_Create(argv.len() as core::ffi::c_int, argv.as_mut_ptr());
+        loop {
+            doomgeneric_Tick();
+        }
+    }
+}
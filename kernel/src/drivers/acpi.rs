@@ -0,0 +1,368 @@
+//! ACPI tables and fixed hardware
+//!
+//! Finds tables through the RSDP the bootloader hands over. The MADT
+//! describes the interrupt controllers (see `apic`), the HPET table the
+//! high precision timer and the MCFG where PCI configuration space is
+//! mapped (see `pci`).
+//!
+//! For power button events, `init` finds the FADT, switches the chipset to
+//! ACPI mode and enables the event. The button raises the SCI, a
+//! level-triggered IRQ (9 on PC chipsets); the handler clears the status
+//! bit and tells `power`.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use x86_64::instructions::port::Port;
+
+/// The only SCI line the IDT has an entry for
+pub const SCI_IRQ: u8 = 9;
+
+/// PM1 status/enable bit of the power button
+const PWRBTN: u16 = 1 << 8;
+/// PM1 control: SCI_EN, set once the chipset is in ACPI mode
+const SCI_EN: u16 = 1 << 0;
+
+// FADT field offsets (ACPI 1.0 layout, present in every revision)
+const FADT_SCI_INT: usize = 46;
+const FADT_SMI_CMD: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_EVT_BLK: usize = 56;
+const FADT_PM1B_EVT_BLK: usize = 60;
+const FADT_PM1A_CNT_BLK: usize = 64;
+const FADT_PM1_EVT_LEN: usize = 88;
+
+// MADT layout: local APIC address and flags, then variable-length entries
+const MADT_LOCAL_APIC: usize = 36;
+const MADT_FLAGS: usize = 40;
+const MADT_ENTRIES: usize = 44;
+const MADT_PCAT_COMPAT: u32 = 1 << 0;
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_ADDRESS: u8 = 5;
+
+/// HPET table: address field of the base address (a Generic Address)
+const HPET_ADDRESS: usize = 44;
+
+/// MCFG layout: 8 reserved bytes after the header, then 16-byte entries
+const MCFG_ENTRIES: usize = 44;
+const MCFG_ENTRY_LEN: usize = 16;
+
+/// PM1a/PM1b event block ports (status register first), 0 if absent
+static PM1A_EVT: AtomicU32 = AtomicU32::new(0);
+static PM1B_EVT: AtomicU32 = AtomicU32::new(0);
+
+#[repr(C, packed)]
+#[allow(dead_code)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // ACPI 2.0+
+    length: u32,
+    xsdt_address: u64,
+}
+
+#[repr(C, packed)]
+#[allow(dead_code)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+/// Physical address as a pointer through the HHDM
+fn phys<T>(addr: u64, hhdm: u64) -> *const T {
+    (addr + hhdm) as *const T
+}
+
+fn checksum_ok(ptr: *const u8, len: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// Find the table with `signature` through the RSDT or XSDT
+fn find_table(signature: &[u8; 4]) -> Option<*const u8> {
+    let hhdm = crate::boot::hhdm_offset()?;
+    let rsdp_addr = crate::boot::rsdp_address()?;
+    // Before base revision 3 Limine reports it already mapped
+    let rsdp_addr = if rsdp_addr >= hhdm { rsdp_addr - hhdm } else { rsdp_addr };
+    let rsdp: *const Rsdp = phys(rsdp_addr, hhdm);
+    let rsdp = unsafe { core::ptr::read_unaligned(rsdp) };
+    if &rsdp.signature != b"RSD PTR " {
+        return None;
+    }
+
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, 8)
+    } else {
+        (rsdp.rsdt_address as u64, 4)
+    };
+    let header: *const SdtHeader = phys(root, hhdm);
+    let length = unsafe { core::ptr::read_unaligned(header) }.length as usize;
+    let header_len = core::mem::size_of::<SdtHeader>();
+    let entries = (length.saturating_sub(header_len)) / entry_size;
+
+    for i in 0..entries {
+        let entry = phys::<u8>(root, hhdm).wrapping_add(header_len + i * entry_size);
+        let addr = unsafe {
+            if entry_size == 8 {
+                core::ptr::read_unaligned(entry as *const u64)
+            } else {
+                core::ptr::read_unaligned(entry as *const u32) as u64
+            }
+        };
+        let table: *const SdtHeader = phys(addr, hhdm);
+        let table_header = unsafe { core::ptr::read_unaligned(table) };
+        if &table_header.signature == signature
+            && checksum_ok(table as *const u8, table_header.length as usize)
+        {
+            return Some(table as *const u8);
+        }
+    }
+    None
+}
+
+fn read_u8(table: *const u8, offset: usize) -> u8 {
+    unsafe { table.add(offset).read() }
+}
+
+fn read_u16(table: *const u8, offset: usize) -> u16 {
+    unsafe { core::ptr::read_unaligned(table.add(offset) as *const u16) }
+}
+
+fn read_u32(table: *const u8, offset: usize) -> u32 {
+    unsafe { core::ptr::read_unaligned(table.add(offset) as *const u32) }
+}
+
+fn read_u64(table: *const u8, offset: usize) -> u64 {
+    unsafe { core::ptr::read_unaligned(table.add(offset) as *const u64) }
+}
+
+fn table_length(table: *const u8) -> usize {
+    unsafe { core::ptr::read_unaligned(table as *const SdtHeader) }.length as usize
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+    pub id: u8,
+    /// Physical address of its registers
+    pub address: u64,
+    /// First global system interrupt it serves
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ that is not wired to the GSI of the same number, or not
+/// edge-triggered active-high
+#[derive(Debug, Clone, Copy)]
+pub struct SourceOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// Interrupt controllers from the MADT
+#[derive(Debug, Clone)]
+pub struct Madt {
+    /// Physical address of every CPU's local APIC
+    pub local_apic: u64,
+    /// The legacy 8259 pair is present too
+    pub pcat_compat: bool,
+    /// APIC ids of the usable CPUs
+    pub cpus: Vec<u8>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<SourceOverride>,
+}
+
+/// Parse the MADT ("APIC")
+pub fn madt() -> Option<Madt> {
+    let table = find_table(b"APIC")?;
+    let length = table_length(table);
+    let mut madt = Madt {
+        local_apic: read_u32(table, MADT_LOCAL_APIC) as u64,
+        pcat_compat: read_u32(table, MADT_FLAGS) & MADT_PCAT_COMPAT != 0,
+        cpus: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    let mut offset = MADT_ENTRIES;
+    while offset + 2 <= length {
+        let kind = read_u8(table, offset);
+        let len = read_u8(table, offset + 1) as usize;
+        if len < 2 || offset + len > length {
+            break;
+        }
+        match kind {
+            // Flags: enabled, or online capable
+            ENTRY_LOCAL_APIC if len >= 8 && read_u32(table, offset + 4) & 0b11 != 0 => {
+                madt.cpus.push(read_u8(table, offset + 3));
+            }
+            ENTRY_IO_APIC if len >= 12 => madt.io_apics.push(IoApicEntry {
+                id: read_u8(table, offset + 2),
+                address: read_u32(table, offset + 4) as u64,
+                gsi_base: read_u32(table, offset + 8),
+            }),
+            // Bus 0 is ISA; polarity in bits 0-1, trigger mode in bits 2-3
+            // (0b11 = active low / level, 0b00 = the bus default)
+            ENTRY_SOURCE_OVERRIDE if len >= 10 && read_u8(table, offset + 2) == 0 => {
+                let flags = read_u16(table, offset + 8);
+                madt.overrides.push(SourceOverride {
+                    irq: read_u8(table, offset + 3),
+                    gsi: read_u32(table, offset + 4),
+                    active_low: flags & 0b11 == 0b11,
+                    level_triggered: (flags >> 2) & 0b11 == 0b11,
+                });
+            }
+            ENTRY_LOCAL_APIC_ADDRESS if len >= 12 => {
+                madt.local_apic = read_u64(table, offset + 4);
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+    Some(madt)
+}
+
+/// Physical address of the HPET registers, if the firmware describes one
+pub fn hpet_address() -> Option<u64> {
+    let table = find_table(b"HPET")?;
+    if table_length(table) < HPET_ADDRESS + 8 {
+        return None;
+    }
+    // Address space 0 is memory; the HPET is never in port space
+    if read_u8(table, HPET_ADDRESS - 4) != 0 {
+        return None;
+    }
+    Some(read_u64(table, HPET_ADDRESS)).filter(|&addr| addr != 0)
+}
+
+/// Memory-mapped PCI configuration space (ECAM) for one segment
+#[derive(Debug, Clone, Copy)]
+pub struct EcamRegion {
+    /// Physical address of the configuration space of `start_bus`
+    pub address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// The ECAM regions from the MCFG, if the firmware has one
+pub fn mcfg() -> Vec<EcamRegion> {
+    let Some(table) = find_table(b"MCFG") else { return Vec::new() };
+    let length = table_length(table);
+    let mut regions = Vec::new();
+    let mut offset = MCFG_ENTRIES;
+    while offset + MCFG_ENTRY_LEN <= length {
+        regions.push(EcamRegion {
+            address: read_u64(table, offset),
+            segment: read_u16(table, offset + 8),
+            start_bus: read_u8(table, offset + 10),
+            end_bus: read_u8(table, offset + 11),
+        });
+        offset += MCFG_ENTRY_LEN;
+    }
+    regions
+}
+
+/// Enable power button events
+///
+/// Call with interrupts enabled: switching to ACPI mode waits on the
+/// firmware.
+pub fn init() -> Result<(), &'static str> {
+    let fadt = find_table(b"FACP").ok_or("no FADT")?;
+    let sci = read_u16(fadt, FADT_SCI_INT);
+    if sci != SCI_IRQ as u16 {
+        return Err("SCI is not on IRQ 9");
+    }
+    let pm1a_evt = read_u32(fadt, FADT_PM1A_EVT_BLK);
+    let pm1b_evt = read_u32(fadt, FADT_PM1B_EVT_BLK);
+    let pm1a_cnt = read_u32(fadt, FADT_PM1A_CNT_BLK);
+    let evt_len = read_u8(fadt, FADT_PM1_EVT_LEN);
+    if pm1a_evt == 0 || pm1a_cnt == 0 || evt_len < 4 {
+        return Err("no PM1 event block");
+    }
+
+    unsafe {
+        // Hand the fixed events from the firmware (SMI) to the OS (SCI)
+        let mut cnt: Port<u16> = Port::new(pm1a_cnt as u16);
+        if cnt.read() & SCI_EN == 0 {
+            let smi_cmd = read_u32(fadt, FADT_SMI_CMD);
+            let enable = read_u8(fadt, FADT_ACPI_ENABLE);
+            if smi_cmd == 0 || enable == 0 {
+                return Err("cannot switch to ACPI mode");
+            }
+            Port::<u8>::new(smi_cmd as u16).write(enable);
+            let deadline = crate::drivers::timer::get_jiffies() + 300;
+            while cnt.read() & SCI_EN == 0 {
+                if crate::drivers::timer::get_jiffies() > deadline {
+                    return Err("firmware did not enable ACPI mode");
+                }
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    PM1A_EVT.store(pm1a_evt, Ordering::Relaxed);
+    PM1B_EVT.store(pm1b_evt, Ordering::Relaxed);
+
+    for block in [pm1a_evt, pm1b_evt] {
+        if block == 0 {
+            continue;
+        }
+        unsafe {
+            // Status bits are write-1-to-clear: drop a stale press first
+            Port::<u16>::new(block as u16).write(PWRBTN);
+            let mut enable: Port<u16> = Port::new(block as u16 + evt_len as u16 / 2);
+            let bits = enable.read();
+            enable.write(bits | PWRBTN);
+        }
+    }
+
+    crate::irq::register(SCI_IRQ, "acpi", sci_interrupt)?;
+    crate::interrupts::enable_irq(SCI_IRQ);
+    crate::serial_println!("[ACPI] Power button enabled (PM1a at {:#x})", pm1a_evt);
+    Ok(())
+}
+
+/// Whether `init` enabled power button events
+pub fn power_button_enabled() -> bool {
+    PM1A_EVT.load(Ordering::Relaxed) != 0
+}
+
+/// SCI handler: report a power button press; false if it was not that
+fn sci_interrupt() -> bool {
+    let pressed = handle_sci();
+    if pressed {
+        crate::power::request(crate::power::PowerEvent::PowerButton);
+    }
+    pressed
+}
+
+/// True if the power button was pressed since the last call
+fn handle_sci() -> bool {
+    let mut pressed = false;
+    for block in [&PM1A_EVT, &PM1B_EVT] {
+        let block = block.load(Ordering::Relaxed);
+        if block == 0 {
+            continue;
+        }
+        unsafe {
+            let mut status: Port<u16> = Port::new(block as u16);
+            if status.read() & PWRBTN != 0 {
+                status.write(PWRBTN);
+                pressed = true;
+            }
+        }
+    }
+    pressed
+}
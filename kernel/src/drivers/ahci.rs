@@ -0,0 +1,440 @@
+//! AHCI SATA controllers
+//!
+//! `init` looks the controllers up on the PCI bus (class 01:06, prog-if
+//! 01), takes them over from the firmware and brings up every port with a
+//! SATA disk behind it; each disk is registered as a block device. The
+//! HBA's registers sit in BAR5 (ABAR), reached through the HHDM.
+//!
+//! Every port gets one page holding its command list, the received-FIS
+//! area and the command table of slot 0, plus a few pages of bounce buffer
+//! the PRDT points at. Only slot 0 is used and completion is polled, so a
+//! port runs one command at a time under its lock; transfers longer than
+//! the bounce buffer are split. Disks must support 48-bit LBA and 512-byte
+//! logical sectors.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::sync::atomic::{fence, Ordering};
+use spin::Mutex;
+use super::block::{self, BlockDevice, BLOCK_SIZE};
+use super::pci::{self, Bar};
+
+const PAGE_SIZE: usize = 4096;
+
+// Generic host control registers
+const CAP: usize = 0x00;
+const GHC: usize = 0x04;
+const IS: usize = 0x08;
+const PI: usize = 0x0C;
+const CAP2: usize = 0x24;
+const BOHC: usize = 0x28;
+
+/// CAP: the HBA can address memory above 4 GiB
+const CAP_S64A: u32 = 1 << 31;
+/// GHC: AHCI enable, interrupt enable
+const GHC_AE: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+/// CAP2: BIOS/OS handoff is supported
+const CAP2_BOH: u32 = 1 << 0;
+/// BOHC: BIOS owned, OS owned semaphores
+const BOHC_BOS: u32 = 1 << 0;
+const BOHC_OOS: u32 = 1 << 1;
+
+/// Port registers, from the start of the port's block
+const PORTS: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PX_CLB: usize = 0x00;
+const PX_CLBU: usize = 0x04;
+const PX_FB: usize = 0x08;
+const PX_FBU: usize = 0x0C;
+const PX_IS: usize = 0x10;
+const PX_IE: usize = 0x14;
+const PX_CMD: usize = 0x18;
+const PX_TFD: usize = 0x20;
+const PX_SIG: usize = 0x24;
+const PX_SSTS: usize = 0x28;
+const PX_SERR: usize = 0x30;
+const PX_CI: usize = 0x38;
+
+// PxCMD bits
+const CMD_ST: u32 = 1 << 0;
+const CMD_SUD: u32 = 1 << 1;
+const CMD_POD: u32 = 1 << 2;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+/// PxIS: task file error
+const IS_TFES: u32 = 1 << 30;
+
+// PxTFD status bits
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+/// PxSSTS: a device is present and the link is up, and it is active
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+
+/// PxSIG of a SATA disk (as opposed to ATAPI, port multipliers, ...)
+const SIG_ATA: u32 = 0x0000_0101;
+
+// Layout of a port's page
+const COMMAND_LIST: usize = 0x000;
+const RECEIVED_FIS: usize = 0x400;
+const COMMAND_TABLE: usize = 0x800;
+const PRDT: usize = COMMAND_TABLE + 0x80;
+const PRD_SIZE: usize = 16;
+
+/// Pages of bounce buffer per port, one PRD each
+const BOUNCE_PAGES: usize = 8;
+/// Most sectors one command moves
+const MAX_SECTORS: usize = BOUNCE_PAGES * PAGE_SIZE / BLOCK_SIZE;
+
+/// Register host to device FIS and its length in dwords
+const FIS_H2D: u8 = 0x27;
+const FIS_H2D_DWORDS: u32 = 5;
+/// Command header: the command writes to the device
+const HEADER_WRITE: u32 = 1 << 6;
+/// H2D FIS: the FIS carries a command
+const FIS_COMMAND: u8 = 0x80;
+/// Device register: the address is an LBA
+const DEVICE_LBA: u8 = 1 << 6;
+
+// ATA commands
+const IDENTIFY_DEVICE: u8 = 0xEC;
+const READ_DMA_EXT: u8 = 0x25;
+const WRITE_DMA_EXT: u8 = 0x35;
+const FLUSH_CACHE_EXT: u8 = 0xEA;
+
+// IDENTIFY DEVICE words
+const ID_MODEL: core::ops::Range<usize> = 27..47;
+const ID_COMMAND_SETS: usize = 83;
+const ID_LBA48_SECTORS: usize = 100;
+/// Word 83: 48-bit addressing is supported
+const ID_LBA48: u16 = 1 << 10;
+
+const COMMAND_TIMEOUT_MS: u64 = 5000;
+const ENGINE_TIMEOUT_MS: u64 = 500;
+
+/// Spin until `done`, for at most `ms` milliseconds
+fn wait(ms: u64, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = crate::drivers::timer::now_ns() + ms * 1_000_000;
+    loop {
+        if done() {
+            return true;
+        }
+        if crate::drivers::timer::now_ns() > deadline {
+            return done();
+        }
+        core::hint::spin_loop();
+    }
+}
+
+fn hhdm() -> u64 {
+    crate::boot::hhdm_offset().unwrap_or(0)
+}
+
+/// A zeroed page for the HBA to DMA into, within its reach
+fn dma_page(wide: bool) -> Result<u64, &'static str> {
+    let page = crate::mem::physical::allocate_page().ok_or("out of memory")? as u64;
+    if !wide && page >= 1 << 32 {
+        crate::mem::physical::free_page(page as usize);
+        return Err("no memory below 4 GiB for a 32-bit HBA");
+    }
+    unsafe { core::ptr::write_bytes((page + hhdm()) as *mut u8, 0, PAGE_SIZE) };
+    Ok(page)
+}
+
+fn free_dma_pages(pages: &[u64]) {
+    for &page in pages {
+        crate::mem::physical::free_page(page as usize);
+    }
+}
+
+/// The registers of an HBA, through the HHDM
+#[derive(Clone, Copy)]
+struct Hba {
+    base: u64,
+}
+
+impl Hba {
+    fn read(&self, reg: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + reg as u64) as *const u32) }
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + reg as u64) as *mut u32, value) }
+    }
+}
+
+/// A port with its command memory set up
+struct Port {
+    hba: Hba,
+    number: usize,
+    /// Physical address of the command list / FIS / command table page
+    memory: u64,
+    bounce: [u64; BOUNCE_PAGES],
+}
+
+impl Port {
+    fn read(&self, reg: usize) -> u32 {
+        self.hba.read(PORTS + self.number * PORT_SIZE + reg)
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        self.hba.write(PORTS + self.number * PORT_SIZE + reg, value)
+    }
+
+    fn write_memory(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.memory + hhdm() + offset as u64) as *mut u32, value) }
+    }
+
+    /// Stop the command list and FIS receive engines
+    fn stop(&self) -> Result<(), &'static str> {
+        self.write(PX_CMD, self.read(PX_CMD) & !CMD_ST);
+        if !wait(ENGINE_TIMEOUT_MS, || self.read(PX_CMD) & CMD_CR == 0) {
+            return Err("command list engine does not stop");
+        }
+        self.write(PX_CMD, self.read(PX_CMD) & !CMD_FRE);
+        if !wait(ENGINE_TIMEOUT_MS, || self.read(PX_CMD) & CMD_FR == 0) {
+            return Err("FIS receive engine does not stop");
+        }
+        Ok(())
+    }
+
+    /// Stop the port and give its pages back. A port that will not stop
+    /// may still write to them, so they are kept then
+    fn release(self) {
+        if self.stop().is_ok() {
+            free_dma_pages(&[self.memory]);
+            free_dma_pages(&self.bounce);
+        }
+    }
+
+    /// Point the port at its memory and start it
+    fn start(&self) -> Result<(), &'static str> {
+        self.stop()?;
+        let list = self.memory + COMMAND_LIST as u64;
+        let fis = self.memory + RECEIVED_FIS as u64;
+        self.write(PX_CLB, list as u32);
+        self.write(PX_CLBU, (list >> 32) as u32);
+        self.write(PX_FB, fis as u32);
+        self.write(PX_FBU, (fis >> 32) as u32);
+        // Completion is polled
+        self.write(PX_IE, 0);
+        self.write(PX_SERR, !0);
+        self.write(PX_IS, !0);
+        self.write(PX_CMD, self.read(PX_CMD) | CMD_FRE | CMD_SUD | CMD_POD);
+        if !wait(COMMAND_TIMEOUT_MS, || self.read(PX_TFD) & (TFD_BSY | TFD_DRQ) == 0) {
+            return Err("device stays busy");
+        }
+        self.write(PX_CMD, self.read(PX_CMD) | CMD_ST);
+        Ok(())
+    }
+
+    /// Run an ATA command in slot 0, moving `bytes` through the bounce
+    /// buffer
+    fn issue(&self, command: u8, lba: u64, count: u16, bytes: usize, write: bool) -> Result<(), &'static str> {
+        if !wait(COMMAND_TIMEOUT_MS, || self.read(PX_TFD) & (TFD_BSY | TFD_DRQ) == 0) {
+            return Err("device stays busy");
+        }
+        let prds = bytes.div_ceil(PAGE_SIZE);
+        let table = self.memory + COMMAND_TABLE as u64;
+        let flags = FIS_H2D_DWORDS | if write { HEADER_WRITE } else { 0 };
+        self.write_memory(COMMAND_LIST, flags | (prds as u32) << 16);
+        self.write_memory(COMMAND_LIST + 4, 0);
+        self.write_memory(COMMAND_LIST + 8, table as u32);
+        self.write_memory(COMMAND_LIST + 12, (table >> 32) as u32);
+
+        let lba = lba.to_le_bytes();
+        let device = if command == IDENTIFY_DEVICE { 0 } else { DEVICE_LBA };
+        let count = count.to_le_bytes();
+        let fis = [
+            FIS_H2D, FIS_COMMAND, command, 0, lba[0], lba[1], lba[2], device, lba[3], lba[4], lba[5], 0, count[0],
+            count[1], 0, 0,
+        ];
+        for (i, word) in fis.chunks(4).enumerate() {
+            self.write_memory(COMMAND_TABLE + i * 4, u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+        }
+        for (i, &page) in self.bounce.iter().enumerate().take(prds) {
+            let len = (bytes - i * PAGE_SIZE).min(PAGE_SIZE);
+            let prd = PRDT + i * PRD_SIZE;
+            self.write_memory(prd, page as u32);
+            self.write_memory(prd + 4, (page >> 32) as u32);
+            self.write_memory(prd + 8, 0);
+            self.write_memory(prd + 12, len as u32 - 1);
+        }
+
+        fence(Ordering::SeqCst);
+        self.write(PX_IS, !0);
+        self.write(PX_CI, 1);
+        let finished = wait(COMMAND_TIMEOUT_MS, || self.read(PX_CI) & 1 == 0 || self.read(PX_IS) & IS_TFES != 0);
+        fence(Ordering::SeqCst);
+        if self.read(PX_IS) & IS_TFES != 0 || self.read(PX_TFD) & TFD_ERR != 0 {
+            // Restarting the engine clears the error for the next command
+            let _ = self.start();
+            return Err("AHCI command failed");
+        }
+        if !finished {
+            return Err("AHCI command timed out");
+        }
+        Ok(())
+    }
+
+    /// Copy the start of the bounce buffer into `buf`
+    fn copy_out(&self, buf: &mut [u8]) {
+        for (chunk, &page) in buf.chunks_mut(PAGE_SIZE).zip(&self.bounce) {
+            unsafe { core::ptr::copy_nonoverlapping((page + hhdm()) as *const u8, chunk.as_mut_ptr(), chunk.len()) };
+        }
+    }
+
+    /// Copy `buf` to the start of the bounce buffer
+    fn copy_in(&self, buf: &[u8]) {
+        for (chunk, &page) in buf.chunks(PAGE_SIZE).zip(&self.bounce) {
+            unsafe { core::ptr::copy_nonoverlapping(chunk.as_ptr(), (page + hhdm()) as *mut u8, chunk.len()) };
+        }
+    }
+}
+
+/// A SATA disk on an AHCI port
+struct Disk {
+    name: String,
+    model: String,
+    sectors: u64,
+    port: Mutex<Port>,
+}
+
+impl BlockDevice for Disk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn blocks(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        block::check_range(self.sectors, lba, buf.len())?;
+        let port = self.port.lock();
+        for (i, chunk) in buf.chunks_mut(MAX_SECTORS * BLOCK_SIZE).enumerate() {
+            let count = chunk.len() / BLOCK_SIZE;
+            port.issue(READ_DMA_EXT, lba + (i * MAX_SECTORS) as u64, count as u16, chunk.len(), false)?;
+            port.copy_out(chunk);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), &'static str> {
+        block::check_range(self.sectors, lba, buf.len())?;
+        let port = self.port.lock();
+        for (i, chunk) in buf.chunks(MAX_SECTORS * BLOCK_SIZE).enumerate() {
+            let count = chunk.len() / BLOCK_SIZE;
+            port.copy_in(chunk);
+            port.issue(WRITE_DMA_EXT, lba + (i * MAX_SECTORS) as u64, count as u16, chunk.len(), true)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.port.lock().issue(FLUSH_CACHE_EXT, 0, 0, 0, false)
+    }
+}
+
+/// Sector count and model of the disk behind `port`, from IDENTIFY DEVICE
+fn identify(port: &Port) -> Result<(u64, String), &'static str> {
+    let mut data = [0u8; BLOCK_SIZE];
+    port.issue(IDENTIFY_DEVICE, 0, 0, BLOCK_SIZE, false)?;
+    port.copy_out(&mut data);
+    let word = |i: usize| u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+    if word(ID_COMMAND_SETS) & ID_LBA48 == 0 {
+        return Err("disk has no 48-bit addressing");
+    }
+    let sectors = (0..4).fold(0u64, |sectors, i| sectors | (word(ID_LBA48_SECTORS + i) as u64) << (16 * i));
+    // ATA strings keep the first character of each pair in the high byte
+    let model: String = ID_MODEL.flat_map(|i| word(i).to_be_bytes()).map(|b| b as char).collect();
+    Ok((sectors, String::from(model.trim())))
+}
+
+/// Bring up port `number` and register the disk behind it, if any
+fn probe_port(hba: Hba, number: usize, wide: bool) -> Result<(), &'static str> {
+    let base = PORTS + number * PORT_SIZE;
+    let status = hba.read(base + PX_SSTS);
+    if status & 0xF != SSTS_DET_PRESENT || (status >> 8) & 0xF != SSTS_IPM_ACTIVE {
+        return Ok(());
+    }
+    let signature = hba.read(base + PX_SIG);
+    if signature != SIG_ATA {
+        crate::serial_println!("[AHCI] port {}: signature {:#010x}, not a SATA disk", number, signature);
+        return Ok(());
+    }
+
+    let memory = dma_page(wide)?;
+    let mut bounce = [0; BOUNCE_PAGES];
+    for i in 0..BOUNCE_PAGES {
+        match dma_page(wide) {
+            Ok(page) => bounce[i] = page,
+            Err(e) => {
+                free_dma_pages(&[memory]);
+                free_dma_pages(&bounce[..i]);
+                return Err(e);
+            }
+        }
+    }
+    let port = Port { hba, number, memory, bounce };
+    match port.start().and_then(|()| identify(&port)) {
+        Ok((sectors, model)) => {
+            block::register(Arc::new(Disk { name: block::next_disk_name(), model, sectors, port: Mutex::new(port) }));
+            Ok(())
+        }
+        Err(e) => {
+            port.release();
+            Err(e)
+        }
+    }
+}
+
+/// Take the HBA at `abar` over from the firmware and probe its ports
+fn probe_controller(device: &pci::Device, abar: u64) {
+    let hba = Hba { base: abar + hhdm() };
+    device.enable_bus_master();
+
+    if hba.read(CAP2) & CAP2_BOH != 0 {
+        hba.write(BOHC, hba.read(BOHC) | BOHC_OOS);
+        if !wait(ENGINE_TIMEOUT_MS, || hba.read(BOHC) & BOHC_BOS == 0) {
+            crate::serial_println!("[AHCI] {}: firmware keeps the HBA, taking it anyway", device.address);
+        }
+    }
+    hba.write(GHC, (hba.read(GHC) | GHC_AE) & !GHC_IE);
+    hba.write(IS, !0);
+
+    let wide = hba.read(CAP) & CAP_S64A != 0;
+    let implemented = hba.read(PI);
+    crate::serial_println!("[AHCI] {}: ports implemented {:#x}", device.address, implemented);
+    for number in (0..32).filter(|number| implemented & (1 << number) != 0) {
+        if let Err(e) = probe_port(hba, number, wide) {
+            crate::serial_println!("[AHCI] port {}: {}", number, e);
+        }
+    }
+}
+
+/// Bring up every AHCI controller on the PCI bus
+pub fn init() {
+    for device in pci::devices() {
+        if (device.class, device.subclass, device.prog_if) != (0x01, 0x06, 0x01) {
+            continue;
+        }
+        match device.bars[5] {
+            // Only the first 4 GiB are sure to be in the HHDM
+            Some(Bar::Memory { address, size, .. }) if address + size <= 1 << 32 => {
+                probe_controller(&device, address)
+            }
+            _ => crate::serial_println!("[AHCI] {}: no usable ABAR", device.address),
+        }
+    }
+    crate::ipc::bus::publish(crate::ipc::message::ServiceEvent::Ready("ahci"));
+}
@@ -0,0 +1,258 @@
+//! Local APIC and IO-APIC
+//!
+//! Once `init` succeeds, ISA IRQs go through the IO-APIC to the same
+//! vectors the PICs used (0x20 + irq), all delivered to the boot CPU, and
+//! every interrupt is acknowledged at the local APIC. The MADT's source
+//! overrides say which IO-APIC pin each ISA IRQ is wired to and how it is
+//! triggered; the SCI, for one, is usually level-triggered active-low.
+//!
+//! The local APIC timer can replace the PIT (see `drivers::timer`). It
+//! counts at the bus clock, which the CPU does not report, so it has to be
+//! measured against another timer first.
+//!
+//! Registers are reached through the HHDM: firmware puts them below 4 GiB,
+//! which Limine maps there at our base revision.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::registers::model_specific::Msr;
+
+use crate::drivers::acpi::Madt;
+
+/// Vector for spurious local APIC interrupts, which need no EOI
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// First vector of the ISA IRQs, as with the PICs
+const ISA_VECTOR_BASE: u8 = 0x20;
+const ISA_IRQS: usize = 16;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+/// IA32_APIC_BASE: the local APIC is enabled
+const APIC_GLOBAL_ENABLE: u64 = 1 << 11;
+
+// Local APIC register offsets
+const LAPIC_ID: usize = 0x020;
+const LAPIC_TPR: usize = 0x080;
+const LAPIC_EOI: usize = 0x0B0;
+const LAPIC_SVR: usize = 0x0F0;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_TIMER_INITIAL: usize = 0x380;
+const LAPIC_TIMER_CURRENT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3E0;
+
+/// SVR: software enable
+const SVR_ENABLE: u32 = 1 << 8;
+/// LVT and redirection entries: masked
+const MASKED: u32 = 1 << 16;
+/// LVT timer: periodic instead of one-shot
+const TIMER_PERIODIC: u32 = 1 << 17;
+/// Timer divide configuration for a divisor of 16
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+// IO-APIC: an index register and a data window
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+/// Redirection entry: active low
+const ACTIVE_LOW: u32 = 1 << 13;
+/// Redirection entry: level-triggered
+const LEVEL_TRIGGERED: u32 = 1 << 15;
+
+/// Virtual address of the local APIC, 0 while the PICs are in use
+static LAPIC: AtomicU64 = AtomicU64::new(0);
+
+struct IoApic {
+    base: u64,
+    gsi_base: u32,
+    pins: u32,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.base as usize + IOREGSEL) as *mut u32, reg);
+            core::ptr::read_volatile((self.base as usize + IOWIN) as *const u32)
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.base as usize + IOREGSEL) as *mut u32, reg);
+            core::ptr::write_volatile((self.base as usize + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn serves(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.pins
+    }
+
+    fn set_entry(&self, gsi: u32, low: u32, destination: u8) {
+        let reg = IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2;
+        // Mask while the two halves disagree
+        self.write(reg, MASKED);
+        self.write(reg + 1, (destination as u32) << 24);
+        self.write(reg, low);
+    }
+}
+
+/// Where an ISA IRQ arrives
+#[derive(Clone, Copy)]
+struct Route {
+    gsi: u32,
+    flags: u32,
+}
+
+struct Routing {
+    io_apics: Vec<IoApic>,
+    isa: [Route; ISA_IRQS],
+}
+
+static ROUTING: Mutex<Routing> = Mutex::new(Routing {
+    io_apics: Vec::new(),
+    isa: [Route { gsi: 0, flags: 0 }; ISA_IRQS],
+});
+
+fn lapic_read(base: u64, offset: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((base as usize + offset) as *const u32) }
+}
+
+fn lapic_write(base: u64, offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile((base as usize + offset) as *mut u32, value) }
+}
+
+fn has_apic() -> bool {
+    core::arch::x86_64::__cpuid(1).edx & (1 << 9) != 0
+}
+
+/// Enable the local APIC and take over the ISA IRQs, all masked. The
+/// caller masks the PICs afterwards.
+pub fn init(madt: &Madt) -> Result<(), &'static str> {
+    if !has_apic() {
+        return Err("CPU has no APIC");
+    }
+    if madt.io_apics.is_empty() {
+        return Err("no IO-APIC in the MADT");
+    }
+    let hhdm = crate::boot::hhdm_offset().ok_or("no HHDM")?;
+
+    let io_apics: Vec<IoApic> = madt
+        .io_apics
+        .iter()
+        .map(|entry| {
+            let mut io_apic = IoApic { base: entry.address + hhdm, gsi_base: entry.gsi_base, pins: 0 };
+            io_apic.pins = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+            io_apic
+        })
+        .collect();
+    for io_apic in &io_apics {
+        for pin in 0..io_apic.pins {
+            io_apic.set_entry(io_apic.gsi_base + pin, MASKED, 0);
+        }
+    }
+
+    // ISA IRQs are edge-triggered active-high on the GSI of the same
+    // number unless an override says otherwise
+    let mut isa = [Route { gsi: 0, flags: 0 }; ISA_IRQS];
+    for (irq, route) in isa.iter_mut().enumerate() {
+        route.gsi = irq as u32;
+    }
+    for o in &madt.overrides {
+        if let Some(route) = isa.get_mut(o.irq as usize) {
+            route.gsi = o.gsi;
+            if o.active_low {
+                route.flags |= ACTIVE_LOW;
+            }
+            if o.level_triggered {
+                route.flags |= LEVEL_TRIGGERED;
+            }
+        }
+    }
+
+    let base = madt.local_apic + hhdm;
+    unsafe {
+        let mut msr = Msr::new(IA32_APIC_BASE);
+        let value = msr.read();
+        msr.write(value | APIC_GLOBAL_ENABLE);
+    }
+    lapic_write(base, LAPIC_TPR, 0);
+    lapic_write(base, LAPIC_LVT_TIMER, MASKED);
+    lapic_write(base, LAPIC_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+
+    let pins: u32 = io_apics.iter().map(|io_apic| io_apic.pins).sum();
+    *ROUTING.lock() = Routing { io_apics, isa };
+    LAPIC.store(base, Ordering::Release);
+    crate::serial_println!(
+        "[APIC] Local APIC {} at {:#x}, {} IO-APIC pins, {} overrides",
+        lapic_id(),
+        madt.local_apic,
+        pins,
+        madt.overrides.len()
+    );
+    Ok(())
+}
+
+/// Whether interrupts go through the APICs
+pub fn is_enabled() -> bool {
+    LAPIC.load(Ordering::Acquire) != 0
+}
+
+/// APIC id of the boot CPU
+pub fn lapic_id() -> u8 {
+    let base = LAPIC.load(Ordering::Acquire);
+    if base == 0 {
+        return 0;
+    }
+    (lapic_read(base, LAPIC_ID) >> 24) as u8
+}
+
+/// Unmask IRQ `irq` at its IO-APIC pin: an ISA IRQ where the MADT routes
+/// it, any higher line on the GSI of that number, level-triggered active
+/// low as PCI interrupts are
+pub fn enable_irq(irq: u8) {
+    let routing = ROUTING.lock();
+    let route = match routing.isa.get(irq as usize) {
+        Some(route) => *route,
+        None => Route { gsi: irq as u32, flags: LEVEL_TRIGGERED | ACTIVE_LOW },
+    };
+    let Some(io_apic) = routing.io_apics.iter().find(|io_apic| io_apic.serves(route.gsi)) else {
+        crate::serial_println!("[APIC] No IO-APIC pin for IRQ {} (GSI {})", irq, route.gsi);
+        return;
+    };
+    io_apic.set_entry(route.gsi, (ISA_VECTOR_BASE + irq) as u32 | route.flags, lapic_id());
+}
+
+/// Acknowledge the interrupt being handled
+pub fn eoi() {
+    let base = LAPIC.load(Ordering::Acquire);
+    if base != 0 {
+        lapic_write(base, LAPIC_EOI, 0);
+    }
+}
+
+/// Timer counts (at a divisor of 16) that pass while `wait` runs
+pub fn measure_timer(wait: impl FnOnce()) -> u32 {
+    let base = LAPIC.load(Ordering::Acquire);
+    if base == 0 {
+        return 0;
+    }
+    lapic_write(base, LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    lapic_write(base, LAPIC_LVT_TIMER, MASKED);
+    lapic_write(base, LAPIC_TIMER_INITIAL, u32::MAX);
+    wait();
+    let remaining = lapic_read(base, LAPIC_TIMER_CURRENT);
+    lapic_write(base, LAPIC_TIMER_INITIAL, 0);
+    u32::MAX - remaining
+}
+
+/// Raise `vector` every `count` timer counts (divisor 16)
+pub fn start_periodic_timer(vector: u8, count: u32) {
+    let base = LAPIC.load(Ordering::Acquire);
+    if base == 0 {
+        return;
+    }
+    lapic_write(base, LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    lapic_write(base, LAPIC_LVT_TIMER, vector as u32 | TIMER_PERIODIC);
+    lapic_write(base, LAPIC_TIMER_INITIAL, count);
+}
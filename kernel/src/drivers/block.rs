@@ -0,0 +1,330 @@
+//! Block devices
+//!
+//! A block device is a disk addressed in 512-byte blocks. Drivers
+//! register each disk they bring up under a name (`sda`, `sdb`, ...) and
+//! whatever reads disks (a filesystem, `dd`-style tools) finds them here
+//! without knowing which controller is behind them.
+//!
+//! Registered devices are wrapped in a write-back cache: the most recently
+//! used `CACHE_BLOCKS` blocks of each device are kept in memory, writes
+//! only mark their blocks dirty, and dirty blocks reach the disk when they
+//! are evicted or on `sync` (the shell command, and on the way down at
+//! shutdown). `devices` and `find` hand out the cached devices.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Bytes in a block, the unit every transfer is counted in
+pub const BLOCK_SIZE: usize = 512;
+
+/// Blocks each device keeps cached (256 KiB)
+const CACHE_BLOCKS: usize = 512;
+
+pub trait BlockDevice: Send + Sync {
+    /// Name it is registered under
+    fn name(&self) -> &str;
+
+    /// Model string the drive reports, for listings
+    fn model(&self) -> &str;
+
+    /// Blocks on the device
+    fn blocks(&self) -> u64;
+
+    /// Read `buf.len() / BLOCK_SIZE` blocks starting at `lba`; `buf` is a
+    /// whole number of blocks
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str>;
+
+    /// Write `buf.len() / BLOCK_SIZE` blocks starting at `lba`
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), &'static str>;
+
+    /// Make the writes so far durable
+    fn flush(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// Check a transfer of `len` bytes at `lba` against a device of `blocks`
+/// before handing it to the hardware
+pub fn check_range(blocks: u64, lba: u64, len: usize) -> Result<(), &'static str> {
+    if len % BLOCK_SIZE != 0 {
+        return Err("transfer is not a whole number of blocks");
+    }
+    match lba.checked_add((len / BLOCK_SIZE) as u64) {
+        Some(end) if end <= blocks => Ok(()),
+        _ => Err("transfer runs past the end of the device"),
+    }
+}
+
+struct Entry {
+    data: Box<[u8; BLOCK_SIZE]>,
+    dirty: bool,
+    /// Value of `Cache::clock` when it was last used
+    used: u64,
+}
+
+struct Cache {
+    entries: BTreeMap<u64, Entry>,
+    clock: u64,
+}
+
+impl Cache {
+    fn touch(&mut self, lba: u64) -> Option<&mut Entry> {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.entries.get_mut(&lba)?;
+        entry.used = clock;
+        Some(entry)
+    }
+
+    /// Make room for one more block, writing the least recently used one
+    /// back if it is dirty
+    fn evict(&mut self, device: &dyn BlockDevice) -> Result<(), &'static str> {
+        if self.entries.len() < CACHE_BLOCKS {
+            return Ok(());
+        }
+        let Some((&lba, _)) = self.entries.iter().min_by_key(|(_, entry)| entry.used) else { return Ok(()) };
+        let entry = &self.entries[&lba];
+        if entry.dirty {
+            device.write_blocks(lba, &entry.data[..])?;
+        }
+        self.entries.remove(&lba);
+        Ok(())
+    }
+
+    fn insert(&mut self, device: &dyn BlockDevice, lba: u64, data: &[u8], dirty: bool) -> Result<(), &'static str> {
+        if let Some(entry) = self.touch(lba) {
+            entry.data.copy_from_slice(data);
+            entry.dirty |= dirty;
+            return Ok(());
+        }
+        self.evict(device)?;
+        let mut block = Box::new([0; BLOCK_SIZE]);
+        block.copy_from_slice(data);
+        self.entries.insert(lba, Entry { data: block, dirty, used: self.clock });
+        Ok(())
+    }
+
+    /// Write every dirty block back, runs of neighbours in one transfer;
+    /// blocks stay dirty until their run is written
+    fn write_back(&mut self, device: &dyn BlockDevice) -> Result<(), &'static str> {
+        let dirty: Vec<u64> = self.entries.iter().filter(|(_, entry)| entry.dirty).map(|(&lba, _)| lba).collect();
+        let mut start = 0;
+        while start < dirty.len() {
+            let mut end = start + 1;
+            while end < dirty.len() && dirty[end] == dirty[end - 1] + 1 {
+                end += 1;
+            }
+            let mut data = Vec::with_capacity((end - start) * BLOCK_SIZE);
+            for lba in &dirty[start..end] {
+                data.extend_from_slice(&self.entries[lba].data[..]);
+            }
+            device.write_blocks(dirty[start], &data)?;
+            for lba in &dirty[start..end] {
+                if let Some(entry) = self.entries.get_mut(lba) {
+                    entry.dirty = false;
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn dirty(&self) -> usize {
+        self.entries.values().filter(|entry| entry.dirty).count()
+    }
+}
+
+/// A registered device behind its cache
+pub struct Cached {
+    device: Arc<dyn BlockDevice>,
+    cache: Mutex<Cache>,
+}
+
+impl Cached {
+    /// Blocks in the cache, and how many of them are dirty
+    pub fn usage(&self) -> (usize, usize) {
+        let cache = self.cache.lock();
+        (cache.entries.len(), cache.dirty())
+    }
+}
+
+impl BlockDevice for Cached {
+    fn name(&self) -> &str {
+        self.device.name()
+    }
+
+    fn model(&self) -> &str {
+        self.device.model()
+    }
+
+    fn blocks(&self) -> u64 {
+        self.device.blocks()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        check_range(self.blocks(), lba, buf.len())?;
+        let mut cache = self.cache.lock();
+        let count = buf.len() / BLOCK_SIZE;
+        let mut i = 0;
+        while i < count {
+            let block = &mut buf[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+            if let Some(entry) = cache.touch(lba + i as u64) {
+                block.copy_from_slice(&entry.data[..]);
+                i += 1;
+                continue;
+            }
+            // Read the whole run of missing blocks in one transfer
+            let end = (i + 1..count).find(|&j| cache.entries.contains_key(&(lba + j as u64))).unwrap_or(count);
+            let run = &mut buf[i * BLOCK_SIZE..end * BLOCK_SIZE];
+            self.device.read_blocks(lba + i as u64, run)?;
+            for (j, block) in run.chunks(BLOCK_SIZE).enumerate() {
+                cache.insert(&*self.device, lba + (i + j) as u64, block, false)?;
+            }
+            i = end;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), &'static str> {
+        check_range(self.blocks(), lba, buf.len())?;
+        let mut cache = self.cache.lock();
+        for (i, block) in buf.chunks(BLOCK_SIZE).enumerate() {
+            cache.insert(&*self.device, lba + i as u64, block, true)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.cache.lock().write_back(&*self.device)?;
+        self.device.flush()
+    }
+}
+
+static DEVICES: Mutex<Vec<Arc<Cached>>> = Mutex::new(Vec::new());
+
+/// The next free `sdX` name, for drivers of SCSI-like disks
+pub fn next_disk_name() -> String {
+    let devices = DEVICES.lock();
+    let letter = (b'a'..=b'z').find(|&letter| {
+        let name = [b's', b'd', letter];
+        !devices.iter().any(|device| device.name().as_bytes() == name)
+    });
+    let mut name = String::from("sd");
+    name.push(letter.unwrap_or(b'?') as char);
+    name
+}
+
+pub fn register(device: Arc<dyn BlockDevice>) {
+    crate::serial_println!(
+        "[BLOCK] {}: {} ({} MiB)",
+        device.name(),
+        device.model(),
+        device.blocks() * BLOCK_SIZE as u64 / (1024 * 1024)
+    );
+    let cache = Mutex::new(Cache { entries: BTreeMap::new(), clock: 0 });
+    DEVICES.lock().push(Arc::new(Cached { device, cache }));
+}
+
+/// Every registered device, in registration order
+pub fn devices() -> Vec<Arc<Cached>> {
+    DEVICES.lock().clone()
+}
+
+pub fn find(name: &str) -> Option<Arc<Cached>> {
+    DEVICES.lock().iter().find(|device| device.name() == name).cloned()
+}
+
+/// Write every device's dirty blocks back and flush it; the errors, by
+/// device
+pub fn sync() -> Vec<(String, &'static str)> {
+    let mut errors = Vec::new();
+    for device in devices() {
+        if let Err(e) = device.flush() {
+            errors.push((String::from(device.name()), e));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Counts the transfers that reach it
+    struct RamDisk {
+        data: Mutex<Vec<u8>>,
+        reads: Mutex<usize>,
+        writes: Mutex<usize>,
+    }
+
+    impl BlockDevice for RamDisk {
+        fn name(&self) -> &str {
+            "ram"
+        }
+
+        fn model(&self) -> &str {
+            "test"
+        }
+
+        fn blocks(&self) -> u64 {
+            (self.data.lock().len() / BLOCK_SIZE) as u64
+        }
+
+        fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+            *self.reads.lock() += 1;
+            let start = lba as usize * BLOCK_SIZE;
+            buf.copy_from_slice(&self.data.lock()[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), &'static str> {
+            *self.writes.lock() += 1;
+            let start = lba as usize * BLOCK_SIZE;
+            self.data.lock()[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    #[test_case]
+    fn ranges_stay_on_the_device() {
+        assert!(check_range(8, 0, 8 * BLOCK_SIZE).is_ok());
+        assert!(check_range(8, 7, BLOCK_SIZE).is_ok());
+        assert!(check_range(8, 8, BLOCK_SIZE).is_err());
+        assert!(check_range(8, 0, 100).is_err());
+        assert!(check_range(8, u64::MAX, BLOCK_SIZE).is_err());
+    }
+
+    #[test_case]
+    fn cache_reads_once_and_writes_back_on_flush() {
+        let disk = Arc::new(RamDisk {
+            data: Mutex::new(vec![0; 8 * BLOCK_SIZE]),
+            reads: Mutex::new(0),
+            writes: Mutex::new(0),
+        });
+        let cached = Cached { device: disk.clone(), cache: Mutex::new(Cache { entries: BTreeMap::new(), clock: 0 }) };
+
+        let mut buf = vec![0; 4 * BLOCK_SIZE];
+        cached.read_blocks(2, &mut buf).unwrap();
+        cached.read_blocks(2, &mut buf).unwrap();
+        assert_eq!(*disk.reads.lock(), 1);
+
+        cached.write_blocks(4, &[7; 2 * BLOCK_SIZE]).unwrap();
+        cached.write_blocks(0, &[9; BLOCK_SIZE]).unwrap();
+        assert_eq!(*disk.writes.lock(), 0);
+        assert_eq!(cached.usage(), (5, 3));
+        cached.read_blocks(4, &mut buf[..BLOCK_SIZE]).unwrap();
+        assert_eq!(buf[0], 7);
+
+        cached.flush().unwrap();
+        // Block 0 on its own, 4 and 5 together
+        assert_eq!(*disk.writes.lock(), 2);
+        assert_eq!(cached.usage(), (5, 0));
+        assert_eq!(disk.data.lock()[5 * BLOCK_SIZE], 7);
+        assert_eq!(disk.data.lock()[0], 9);
+    }
+}
@@ -1,8 +1,57 @@
 //! Framebuffer-based console driver for ospabOS
 //! Uses Limine's framebuffer for graphical text output
+//!
+//! Once `enable_scrollback` is called the console also keeps the text on
+//! screen and the last `SCROLLBACK_LINES` lines scrolled off it, so the
+//! view can be moved back with Shift+PageUp. Output that arrives while
+//! scrolled back is recorded but not drawn until the view returns.
+//!
+//! After `enable_double_buffer` every pixel is drawn into an offscreen copy
+//! of the screen and only the rectangle that changed is copied to video
+//! memory. The console functions do that after each call; code that redraws
+//! a whole frame (editors, games) brackets it with `begin_frame` and
+//! `present` so the screen never shows a half-drawn frame.
+//!
+//! Text is drawn a glyph row at a time as 32-bit words, and scrolling
+//! moves the pixels with one memmove while the text model only rotates
+//! which of its rows is the top one.
+//!
+//! An overlay (the display compositor's windows) can be painted over the
+//! back buffer on its way out: each row is copied to a scratch row, handed
+//! to the overlay and then written to video memory, so what the overlay
+//! covers is never lost from `back` and never flickers on screen.
 
 use crate::boot;
+use crate::common::palette;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
 use spin::Mutex;
+use core::ops::Range;
+
+/// Lines kept after they scroll off the top of the screen
+const SCROLLBACK_LINES: usize = 500;
+
+/// Mouse pointer sprite: '#' outline, 'o' fill, '.' transparent
+const POINTER_SPRITE: [&[u8; 8]; 12] = [
+    b"#.......",
+    b"##......",
+    b"#o#.....",
+    b"#oo#....",
+    b"#ooo#...",
+    b"#oooo#..",
+    b"#ooooo#.",
+    b"#oooooo#",
+    b"#ooo####",
+    b"#o##o#..",
+    b"##..#o#.",
+    b".....##.",
+];
+const POINTER_W: usize = 8;
+const POINTER_H: usize = 12;
+
+/// Paints over one row of the screen as it is copied out (see `OverlayRow`)
+pub type Overlay = fn(&mut OverlayRow);
 
 /// PSF2 Font Header Structure
 #[repr(C, packed)]
@@ -25,6 +74,9 @@ static PSF2_FONT: &[u8] = include_bytes!("font.psf2");
 /// Each character is 8 bytes (8 rows of 8 pixels)
 static FONT_8X8: [u8; 760] = include!("font_data.rs");
 
+/// Widest character cell `draw_glyph` builds a row of on the stack
+const MAX_CHAR_WIDTH: usize = 32;
+
 pub struct FramebufferConsole {
     fb_addr: *mut u8,
     width: usize,
@@ -52,6 +104,36 @@ pub struct FramebufferConsole {
     
     // Cursor blinking
     cursor_visible: bool,
+    
+    // Scrollback
+    /// Text on screen, one line per row, starting at `top` and wrapping
+    /// around; empty until scrollback is enabled
+    screen: Vec<Vec<u8>>,
+    /// Index in `screen` of the top row; scrolling moves it instead of
+    /// the lines
+    top: usize,
+    /// Lines scrolled off the top, oldest first
+    history: VecDeque<Vec<u8>>,
+    /// Lines the view is scrolled back by; 0 shows the live screen
+    view_offset: usize,
+    
+    // Double buffering
+    /// Offscreen copy of the screen in framebuffer pixel format, `stride`
+    /// pixels per row; empty until double buffering is enabled
+    back: Vec<u32>,
+    stride: usize,
+    /// Bounding box (x0, y0, x1, y1), end-exclusive, of the pixels drawn
+    /// into `back` since they were last copied out
+    dirty: Option<(usize, usize, usize, usize)>,
+    /// Open `begin_frame` calls; nothing is copied out until all are closed
+    frame_depth: usize,
+    /// Mouse pointer position; drawn over video memory only, never into
+    /// `back`, so copying `back` out erases it
+    pointer: Option<(usize, usize)>,
+    /// Painted over every row copied out, never into `back`
+    overlay: Option<Overlay>,
+    /// Scratch row the overlay paints on, `stride` pixels
+    row: Vec<u32>,
 }
 
 unsafe impl Send for FramebufferConsole {}
@@ -74,9 +156,20 @@ impl FramebufferConsole {
             char_height: 16,  // 16 pixels tall (rectangular like real character)
             cols: 0,
             rows: 0,
-            fg_color: 0x00FFFFFF, // White
-            bg_color: 0x00000000, // Black
+            fg_color: palette::DEFAULT.foreground,
+            bg_color: palette::DEFAULT.background,
             cursor_visible: true,
+            screen: Vec::new(),
+            top: 0,
+            history: VecDeque::new(),
+            view_offset: 0,
+            back: Vec::new(),
+            stride: 0,
+            dirty: None,
+            frame_depth: 0,
+            pointer: None,
+            overlay: None,
+            row: Vec::new(),
         }
     }
     
@@ -116,37 +209,39 @@ impl FramebufferConsole {
         self.bg_color = bg;
     }
     
+    /// Switch colors and redraw the text on screen in them (the text is
+    /// only known once scrollback is enabled)
+    pub fn recolor(&mut self, fg: u32, bg: u32) {
+        self.set_colors(fg, bg);
+        if self.screen.is_empty() {
+            return;
+        }
+        if !self.back.is_empty() {
+            let color = self.encode(bg);
+            self.back.fill(color);
+            self.mark_dirty(0, 0, self.width, self.height);
+        }
+        self.render_view();
+    }
+    
     pub fn clear(&mut self) {
         if self.fb_addr.is_null() || self.width == 0 || self.height == 0 {
             return;
         }
         
-        unsafe {
-            let color = self.bg_color | 0xFF000000;
-            // Use pitch correctly - pitch is in bytes
-            for y in 0..self.height {
-                let row_ptr = self.fb_addr.add(y * self.pitch) as *mut u32;
-                for x in 0..self.width {
-                    core::ptr::write_volatile(row_ptr.add(x), color);
-                }
-            }
-        }
+        self.fill_rect(0, 0, self.width, self.height, self.bg_color);
         
         self.cursor_x = 0;
         self.cursor_y = 0;
+        for line in self.screen.iter_mut() {
+            line.fill(b' ');
+        }
+        self.view_offset = 0;
     }
     
+    /// Convert an RGB color to the framebuffer's pixel format
     #[inline]
-    unsafe fn put_pixel(&self, x: usize, y: usize, color: u32) {
-        // Strict bounds checking for VMware compatibility
-        if x >= self.width || y >= self.height {
-            return;
-        }
-        if self.fb_addr.is_null() {
-            return;
-        }
-        
-        // Convert RGB color to framebuffer format
+    fn encode(&self, color: u32) -> u32 {
         let r = (color >> 16) & 0xFF;
         let g = (color >> 8) & 0xFF;
         let b = color & 0xFF;
@@ -158,47 +253,314 @@ impl FramebufferConsole {
             // RGB format
             (r << self.red_shift) | (g << self.green_shift) | (b << self.blue_shift)
         };
+        pixel_color | 0xFF000000
+    }
+    
+    /// Inverse of `encode`
+    fn decode(&self, pixel: u32) -> u32 {
+        let r = (pixel >> self.red_shift) & 0xFF;
+        let g = (pixel >> self.green_shift) & 0xFF;
+        let b = (pixel >> self.blue_shift) & 0xFF;
+        (r << 16) | (g << 8) | b
+    }
+    
+    /// Pixel (x, y) as RGB, from the back buffer if there is one
+    fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        if x >= self.width || y >= self.height || self.fb_addr.is_null() {
+            return 0;
+        }
+        let pixel = if !self.back.is_empty() {
+            self.back[y * self.stride + x]
+        } else {
+            unsafe { core::ptr::read_volatile(self.fb_addr.add(y * self.pitch + x * self.bpp) as *const u32) }
+        };
+        self.decode(pixel)
+    }
+    
+    #[inline]
+    unsafe fn put_pixel(&mut self, x: usize, y: usize, color: u32) {
+        // Strict bounds checking for VMware compatibility
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        if self.fb_addr.is_null() {
+            return;
+        }
+        
+        let pixel = self.encode(color);
+        if !self.back.is_empty() {
+            self.back[y * self.stride + x] = pixel;
+            self.mark_dirty(x, y, x + 1, y + 1);
+            return;
+        }
         
         let offset = y * self.pitch + x * self.bpp;
         let ptr = self.fb_addr.add(offset) as *mut u32;
         
         // Write as 32-bit value using write_volatile
-        core::ptr::write_volatile(ptr, pixel_color | 0xFF000000);
+        core::ptr::write_volatile(ptr, pixel);
     }
     
-    fn draw_char(&self, x: usize, y: usize, c: char) {
-        if self.fb_addr.is_null() {
+    /// Grow the dirty rectangle to cover (x0, y0)..(x1, y1)
+    #[inline]
+    fn mark_dirty(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let (x1, y1) = (x1.min(self.width), y1.min(self.height));
+        self.dirty = Some(match self.dirty {
+            Some((a0, b0, a1, b1)) => (a0.min(x0), b0.min(y0), a1.max(x1), b1.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+    
+    /// Copy the encoded `pixels` to row `y` from column `x` on, clipped to
+    /// the screen; the caller marks them dirty
+    fn put_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+        if x >= self.width || y >= self.height || self.fb_addr.is_null() {
+            return;
+        }
+        let pixels = &pixels[..pixels.len().min(self.width - x)];
+        if !self.back.is_empty() {
+            let start = y * self.stride + x;
+            self.back[start..start + pixels.len()].copy_from_slice(pixels);
+        } else {
+            unsafe {
+                let dst = self.fb_addr.add(y * self.pitch + x * self.bpp) as *mut u32;
+                core::ptr::copy_nonoverlapping(pixels.as_ptr(), dst, pixels.len());
+            }
+        }
+    }
+    
+    /// Fill (x0, y0)..(x1, y1) with RGB `color`, a row at a time
+    fn fill_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: u32) {
+        let (x1, y1) = (x1.min(self.width), y1.min(self.height));
+        if self.fb_addr.is_null() || x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let pixel = self.encode(color);
+        if !self.back.is_empty() {
+            for y in y0..y1 {
+                self.back[y * self.stride + x0..y * self.stride + x1].fill(pixel);
+            }
+            self.mark_dirty(x0, y0, x1, y1);
+            return;
+        }
+        for y in y0..y1 {
+            unsafe {
+                let row = self.fb_addr.add(y * self.pitch + x0 * self.bpp) as *mut u32;
+                for x in 0..x1 - x0 {
+                    core::ptr::write_volatile(row.add(x), pixel);
+                }
+            }
+        }
+    }
+    
+    /// Draw the RGB `pixels` at (x, y) and again on the `times - 1` rows
+    /// below, clipped to the screen; each pixel is encoded once and the
+    /// repeats are row copies
+    fn put_scanline(&mut self, x: usize, y: usize, pixels: &[u32], times: usize) {
+        if x >= self.width || y >= self.height || self.fb_addr.is_null() || times == 0 {
+            return;
+        }
+        let len = pixels.len().min(self.width - x);
+        let rows = times.min(self.height - y);
+        if !self.back.is_empty() {
+            // Taken out so `encode` can borrow the console alongside it
+            let mut back = core::mem::take(&mut self.back);
+            let start = y * self.stride + x;
+            for (dst, &color) in back[start..start + len].iter_mut().zip(pixels) {
+                *dst = self.encode(color);
+            }
+            for row in 1..rows {
+                back.copy_within(start..start + len, start + row * self.stride);
+            }
+            self.back = back;
+            self.mark_dirty(x, y, x + len, y + rows);
+            return;
+        }
+        for row in 0..rows {
+            unsafe {
+                let dst = self.fb_addr.add((y + row) * self.pitch + x * self.bpp) as *mut u32;
+                for (i, &color) in pixels[..len].iter().enumerate() {
+                    core::ptr::write_volatile(dst.add(i), self.encode(color));
+                }
+            }
+        }
+    }
+    
+    /// Draw offscreen from now on (needs the heap)
+    ///
+    /// The back buffer starts as a copy of the screen. Returns false if it
+    /// could not be allocated; drawing then stays direct.
+    pub fn enable_double_buffer(&mut self) -> bool {
+        if self.fb_addr.is_null() || self.bpp != 4 || !self.back.is_empty() {
+            return !self.back.is_empty();
+        }
+        let stride = self.pitch / 4;
+        let len = stride * self.height;
+        let mut back = Vec::new();
+        let mut row = Vec::new();
+        if back.try_reserve_exact(len).is_err() || row.try_reserve_exact(stride).is_err() {
+            return false;
+        }
+        row.resize(stride, 0);
+        for y in 0..self.height {
+            let row = unsafe { self.fb_addr.add(y * self.pitch) as *const u32 };
+            for x in 0..stride {
+                back.push(unsafe { core::ptr::read_volatile(row.add(x)) });
+            }
+        }
+        self.back = back;
+        self.row = row;
+        self.stride = stride;
+        true
+    }
+    
+    /// Hold back drawing until the matching `present`
+    pub fn begin_frame(&mut self) {
+        self.frame_depth += 1;
+    }
+    
+    /// Close a frame opened with `begin_frame` and show it once none is open
+    pub fn present(&mut self) {
+        self.frame_depth = self.frame_depth.saturating_sub(1);
+        self.flush();
+    }
+    
+    /// Copy the dirty rectangle to video memory, unless a frame is open
+    pub fn flush(&mut self) {
+        if self.frame_depth > 0 {
+            return;
+        }
+        let (x0, y0, x1, y1) = match self.dirty.take() {
+            Some(rect) => rect,
+            None => return,
+        };
+        // Taken out so the overlay can borrow the console alongside it
+        let mut scratch = core::mem::take(&mut self.row);
+        for y in y0..y1 {
+            let mut src = &self.back[y * self.stride + x0..y * self.stride + x1];
+            if let Some(overlay) = self.overlay {
+                let pixels = &mut scratch[..src.len()];
+                pixels.copy_from_slice(src);
+                overlay(&mut OverlayRow { console: self, y, x0, pixels });
+                src = &scratch[..x1 - x0];
+            }
+            unsafe {
+                let dst = self.fb_addr.add(y * self.pitch + x0 * 4) as *mut u32;
+                core::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len());
+            }
+        }
+        self.row = scratch;
+        if let Some((px, py)) = self.pointer {
+            if px < x1 && px + POINTER_W > x0 && py < y1 && py + POINTER_H > y0 {
+                self.draw_pointer(px, py);
+            }
+        }
+    }
+    
+    /// Paint the pointer sprite straight into video memory
+    fn draw_pointer(&self, px: usize, py: usize) {
+        let outline = self.encode(0x000000);
+        let fill = self.encode(0xFFFFFF);
+        for (dy, row) in POINTER_SPRITE.iter().enumerate() {
+            for (dx, &cell) in row.iter().enumerate() {
+                let (x, y) = (px + dx, py + dy);
+                let color = match cell {
+                    b'#' => outline,
+                    b'o' => fill,
+                    _ => continue,
+                };
+                if x < self.width && y < self.height {
+                    unsafe {
+                        let ptr = self.fb_addr.add(y * self.pitch + x * 4) as *mut u32;
+                        core::ptr::write_volatile(ptr, color);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Move the mouse pointer, or hide it with None
+    ///
+    /// Needs the back buffer to restore what the pointer covered; returns
+    /// false without one.
+    pub fn set_pointer(&mut self, pos: Option<(usize, usize)>) -> bool {
+        if self.back.is_empty() {
+            return false;
+        }
+        for (x, y) in self.pointer.into_iter().chain(pos) {
+            self.mark_dirty(x, y, x + POINTER_W, y + POINTER_H);
+        }
+        self.pointer = pos;
+        true
+    }
+    
+    /// Paint `overlay` over the screen from now on, or stop with None
+    ///
+    /// Needs the back buffer; returns false without one.
+    pub fn set_overlay(&mut self, overlay: Option<Overlay>) -> bool {
+        if self.back.is_empty() {
+            return false;
+        }
+        self.overlay = overlay;
+        self.mark_dirty(0, 0, self.width, self.height);
+        true
+    }
+    
+    /// Copy (x0, y0)..(x1, y1) out again, e.g. after the overlay changed
+    pub fn damage(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let (x1, y1) = (x1.min(self.width), y1.min(self.height));
+        if self.back.is_empty() || x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        self.mark_dirty(x0, y0, x1, y1);
+    }
+    
+    /// Draw `c` in the cell at pixel (x, y) and record it for scrollback
+    fn draw_char(&mut self, x: usize, y: usize, c: char) {
+        let (row, col) = (y / self.char_height, x / self.char_width);
+        let index = self.screen_index(row);
+        if let Some(cell) = self.screen.get_mut(index).and_then(|line| line.get_mut(col)) {
+            *cell = if c.is_ascii_graphic() { c as u8 } else { b' ' };
+        }
+        if self.view_offset == 0 {
+            self.draw_glyph(x, y, c);
+        }
+    }
+    
+    /// Index in `screen` of screen row `row`
+    fn screen_index(&self, row: usize) -> usize {
+        match self.screen.len() {
+            0 => row,
+            len => (self.top + row) % len,
+        }
+    }
+    
+    /// Draw `c` with its top-left corner at (x, y), each pixel row of the
+    /// cell built in 32-bit words and copied out in one go
+    fn draw_glyph(&mut self, x: usize, y: usize, c: char) {
+        if self.fb_addr.is_null() || self.char_width > MAX_CHAR_WIDTH {
             return;
         }
         
         let c = c as usize;
-        if c < 32 || c > 126 {
+        if !(32..=126).contains(&c) {
             return;
         }
-        
-        let font_index = (c - 32) * 8; // 8 bytes per character (8x8 font)
-        
-        // Draw with simple nearest-neighbor scaling to 12x12
+        // 8 bytes per character (8x8 font), scaled to the cell
+        let glyph = &FONT_8X8[(c - 32) * 8..(c - 32) * 8 + 8];
+        let (fg, bg) = (self.encode(self.fg_color), self.encode(self.bg_color));
+        let mut pixels = [0u32; MAX_CHAR_WIDTH];
+        let pixels = &mut pixels[..self.char_width];
         for py in 0..self.char_height {
-            let row = py * 8 / self.char_height; // Map to 0-7
-            let font_byte = if font_index + row < FONT_8X8.len() {
-                FONT_8X8[font_index + row]
-            } else {
-                0
-            };
-            
-            for px in 0..self.char_width {
-                let col = px * 8 / self.char_width; // Map to 0-7
-                let color = if (font_byte >> (7 - col)) & 1 == 1 {
-                    self.fg_color
-                } else {
-                    self.bg_color
-                };
-                
-                unsafe {
-                    self.put_pixel(x + px, y + py, color);
-                }
+            let bits = glyph[py * 8 / self.char_height];
+            for (px, pixel) in pixels.iter_mut().enumerate() {
+                let col = px * 8 / self.char_width;
+                *pixel = if (bits >> (7 - col)) & 1 == 1 { fg } else { bg };
             }
+            self.put_row(x, y + py, pixels);
+        }
+        if !self.back.is_empty() {
+            self.mark_dirty(x, y, x + self.char_width, y + self.char_height);
         }
     }
     
@@ -207,24 +569,40 @@ impl FramebufferConsole {
             return;
         }
         
-        // Copy all lines up by one
-        unsafe {
-            let line_bytes = self.pitch * self.char_height;
-            let total_lines = self.rows - 1;
-            
-            for line in 0..total_lines {
-                let src = self.fb_addr.add((line + 1) * self.char_height * self.pitch);
-                let dst = self.fb_addr.add(line * self.char_height * self.pitch);
-                core::ptr::copy(src, dst, line_bytes);
+        if !self.screen.is_empty() {
+            // The top row becomes history and its slot, blanked, the new
+            // bottom row; a full history hands back its oldest line to reuse
+            let mut blank = match self.history.len() {
+                SCROLLBACK_LINES => self.history.pop_front().unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            blank.clear();
+            blank.resize(self.cols, b' ');
+            let top = core::mem::replace(&mut self.screen[self.top], blank);
+            self.history.push_back(top);
+            self.top = (self.top + 1) % self.screen.len();
+            if self.view_offset > 0 {
+                // Keep showing the same lines while output goes on below
+                self.view_offset = (self.view_offset + 1).min(self.history.len());
+                return;
             }
-            
-            // Clear the last line
-            for y in 0..self.char_height {
-                for x in 0..self.width {
-                    self.put_pixel(x, (self.rows - 1) * self.char_height + y, self.bg_color);
-                }
+        }
+        
+        let line_height = self.char_height;
+        let bottom = (self.rows - 1) * line_height;
+        if !self.back.is_empty() {
+            let line_pixels = self.stride * line_height;
+            let used = line_pixels * self.rows;
+            self.back.copy_within(line_pixels..used, 0);
+            self.mark_dirty(0, 0, self.width, self.rows * line_height);
+        } else {
+            // The rows are contiguous: move them all up in one memmove
+            unsafe {
+                let src = self.fb_addr.add(line_height * self.pitch);
+                core::ptr::copy(src, self.fb_addr, bottom * self.pitch);
             }
         }
+        self.fill_rect(0, bottom, self.width, bottom + line_height, self.bg_color);
     }
     
     pub fn write_char(&mut self, c: char) {
@@ -244,6 +622,8 @@ impl FramebufferConsole {
             '\r' => {
                 self.cursor_x = 0;
             }
+            // Form feed: a fresh screen, as `clear` in a user program wants
+            '\x0c' => self.clear(),
             '\x08' => {
                 // Backspace
                 if self.cursor_x > 0 {
@@ -303,6 +683,52 @@ impl FramebufferConsole {
         self.bg_color = old_bg;
     }
     
+    /// Start recording text for scrollback (needs the heap)
+    pub fn enable_scrollback(&mut self) {
+        self.screen = vec![vec![b' '; self.cols]; self.rows];
+        self.top = 0;
+    }
+    
+    /// Redraw the screen from the text model at the current view offset
+    fn render_view(&mut self) {
+        let start = self.history.len() - self.view_offset;
+        for row in 0..self.rows {
+            let idx = start + row;
+            for col in 0..self.cols {
+                let line = if idx < self.history.len() {
+                    &self.history[idx]
+                } else {
+                    &self.screen[self.screen_index(idx - self.history.len())]
+                };
+                let c = line.get(col).copied().unwrap_or(b' ') as char;
+                self.draw_glyph(col * self.char_width, row * self.char_height, c);
+            }
+        }
+        if self.view_offset == 0 && self.cursor_visible {
+            self.draw_cursor(true);
+        }
+    }
+    
+    /// Move the view `lines` back (positive) or forward (negative)
+    pub fn scroll_view(&mut self, lines: isize) {
+        if self.screen.is_empty() {
+            return;
+        }
+        let offset = (self.view_offset as isize + lines).clamp(0, self.history.len() as isize) as usize;
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.render_view();
+        }
+    }
+    
+    /// Return to the live screen
+    pub fn scroll_to_bottom(&mut self) {
+        if self.view_offset > 0 {
+            self.view_offset = 0;
+            self.render_view();
+        }
+    }
+    
     pub fn cols(&self) -> usize {
         self.cols
     }
@@ -311,29 +737,25 @@ impl FramebufferConsole {
         self.rows
     }
     
+    /// Move the cursor `n` columns back without erasing, onto the rows
+    /// above if need be (line editing)
+    pub fn cursor_back(&mut self, n: usize) {
+        let pos = (self.cursor_y * self.cols + self.cursor_x).saturating_sub(n);
+        self.cursor_y = pos / self.cols.max(1);
+        self.cursor_x = pos % self.cols.max(1);
+    }
+
     /// Draw cursor at current position (Linux-style block cursor)
-    pub fn draw_cursor(&self, visible: bool) {
-        if self.fb_addr.is_null() {
+    pub fn draw_cursor(&mut self, visible: bool) {
+        if self.fb_addr.is_null() || self.view_offset > 0 {
             return;
         }
         
-        let x = self.cursor_x * self.char_width;
-        let y = self.cursor_y * self.char_height;
-        
-        // Draw full block cursor (inverted colors)
-        let color = if visible { self.fg_color } else { self.bg_color };
-        
-        for py in 0..self.char_height {
-            for px in 0..self.char_width {
-                unsafe {
-                    self.put_pixel(x + px, y + py, color);
-                }
-            }
-        }
+        self.draw_cursor_at(self.cursor_y, self.cursor_x, visible);
     }
     
     /// Draw cursor at specific row/col position (for editors)
-    pub fn draw_cursor_at(&self, row: usize, col: usize, visible: bool) {
+    pub fn draw_cursor_at(&mut self, row: usize, col: usize, visible: bool) {
         if self.fb_addr.is_null() {
             return;
         }
@@ -343,14 +765,7 @@ impl FramebufferConsole {
         
         // Draw full block cursor (inverted colors)
         let color = if visible { self.fg_color } else { self.bg_color };
-        
-        for py in 0..self.char_height {
-            for px in 0..self.char_width {
-                unsafe {
-                    self.put_pixel(x + px, y + py, color);
-                }
-            }
-        }
+        self.fill_rect(x, y, x + self.char_width, y + self.char_height, color);
     }
     
     /// Toggle cursor visibility
@@ -393,24 +808,107 @@ pub fn is_initialized() -> bool {
 pub fn print(s: &str) {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.write_str(s);
+        console.flush();
     }
 }
 
 pub fn print_char(c: char) {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.write_char(c);
+        console.flush();
     }
 }
 
 pub fn draw_char_at(row: usize, col: usize, c: char, fg: u32, bg: u32) {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.draw_char_cell(row, col, c, fg, bg);
+        console.flush();
     }
 }
 
 pub fn clear() {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.clear();
+        console.flush();
+    }
+}
+
+/// Start keeping scrollback; call once the heap is up
+pub fn enable_scrollback() {
+    CONSOLE.lock().enable_scrollback();
+}
+
+/// Draw offscreen from now on; call once the heap is up
+pub fn enable_double_buffer() -> bool {
+    CONSOLE.lock().enable_double_buffer()
+}
+
+/// Hold back drawing until `present`, to show a whole frame at once
+pub fn begin_frame() {
+    CONSOLE.lock().begin_frame();
+}
+
+/// Show everything drawn since `begin_frame`
+pub fn present() {
+    CONSOLE.lock().present();
+}
+
+/// Move the mouse pointer sprite, or hide it with None
+pub fn set_pointer(pos: Option<(usize, usize)>) -> bool {
+    match CONSOLE.try_lock() {
+        Some(mut console) => {
+            let shown = console.set_pointer(pos);
+            console.flush();
+            shown
+        }
+        None => false,
+    }
+}
+
+/// Paint `overlay` over the screen from now on, or stop with None; false
+/// without double buffering
+pub fn set_overlay(overlay: Option<Overlay>) -> bool {
+    let mut console = CONSOLE.lock();
+    let set = console.set_overlay(overlay);
+    console.flush();
+    set
+}
+
+/// Redraw (x0, y0)..(x1, y1) on screen, e.g. after the overlay changed
+pub fn damage(x0: usize, y0: usize, x1: usize, y1: usize) {
+    let mut console = CONSOLE.lock();
+    console.damage(x0, y0, x1, y1);
+    console.flush();
+}
+
+/// Whether drawing goes through the back buffer
+pub fn is_double_buffered() -> bool {
+    !CONSOLE.lock().back.is_empty()
+}
+
+/// Scroll the view back one page (Shift+PageUp)
+pub fn scrollback_page_up() {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        let page = console.rows().saturating_sub(1) as isize;
+        console.scroll_view(page);
+        console.flush();
+    }
+}
+
+/// Scroll the view forward one page (Shift+PageDown)
+pub fn scrollback_page_down() {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        let page = console.rows().saturating_sub(1) as isize;
+        console.scroll_view(-page);
+        console.flush();
+    }
+}
+
+/// Show the live screen again
+pub fn scroll_to_bottom() {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        console.scroll_to_bottom();
+        console.flush();
     }
 }
 
@@ -419,16 +917,88 @@ pub fn clear_screen() {
     clear();
 }
 
+/// Print `s` in the given colors, keeping the console's own
+pub fn print_colored(s: &str, fg: u32, bg: u32) {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        let (old_fg, old_bg) = (console.fg_color, console.bg_color);
+        console.set_colors(fg, bg);
+        console.write_str(s);
+        console.set_colors(old_fg, old_bg);
+        console.flush();
+    }
+}
+
+/// Print `s` with the byte ranges in `spans` (sorted, not overlapping) in
+/// their own foreground color and the rest in the console's
+pub fn print_highlighted(s: &str, spans: &[(Range<usize>, u32)]) {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        let old_fg = console.fg_color;
+        let bg = console.bg_color;
+        let mut pos = 0;
+        for (range, fg) in spans {
+            console.write_str(&s[pos..range.start]);
+            console.set_colors(*fg, bg);
+            console.write_str(&s[range.clone()]);
+            console.set_colors(old_fg, bg);
+            pos = range.end;
+        }
+        console.write_str(&s[pos..]);
+        console.flush();
+    }
+}
+
+/// Draw `s` on text row `row` from the first column and blank the rest of
+/// the row, without moving the console cursor. The byte ranges in `spans`
+/// (sorted, not overlapping) are drawn in their (fg, bg) colors, the rest
+/// in the console's.
+pub fn draw_row(row: usize, s: &str, spans: &[(Range<usize>, u32, u32)]) {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        let (fg, bg) = (console.fg_color, console.bg_color);
+        let mut spans = spans.iter().peekable();
+        let mut col = 0;
+        for (i, c) in s.char_indices() {
+            while spans.peek().is_some_and(|(range, _, _)| range.end <= i) {
+                spans.next();
+            }
+            let (cell_fg, cell_bg) = match spans.peek() {
+                Some((range, span_fg, span_bg)) if range.start <= i => (*span_fg, *span_bg),
+                _ => (fg, bg),
+            };
+            console.draw_char_cell(row, col, c, cell_fg, cell_bg);
+            col += 1;
+        }
+        for col in col..console.cols {
+            console.draw_char_cell(row, col, ' ', fg, bg);
+        }
+        console.flush();
+    }
+}
+
+/// Console size in character cells: (columns, rows)
+pub fn text_size() -> (usize, usize) {
+    let console = CONSOLE.lock();
+    (console.cols, console.rows)
+}
+
+/// Take the console colors from the current palette and repaint
+pub fn apply_palette() {
+    let palette = palette::current();
+    let mut console = CONSOLE.lock();
+    console.recolor(palette.foreground, palette.background);
+    console.flush();
+}
+
 pub fn set_colors(fg: u32, bg: u32) {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.set_colors(fg, bg);
     }
 }
 
-/// Toggle cursor (called from timer interrupt)
+/// Toggle cursor (called from ktimerd to blink it)
 pub fn toggle_cursor() {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.toggle_cursor();
+        console.flush();
     }
 }
 
@@ -436,6 +1006,7 @@ pub fn toggle_cursor() {
 pub fn show_cursor() {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.show_cursor();
+        console.flush();
     }
 }
 
@@ -443,25 +1014,105 @@ pub fn show_cursor() {
 pub fn hide_cursor() {
     if let Some(mut console) = CONSOLE.try_lock() {
         console.hide_cursor();
+        console.flush();
+    }
+}
+
+/// Move the cursor `n` columns back without erasing what is there
+pub fn cursor_back(n: usize) {
+    if let Some(mut console) = CONSOLE.try_lock() {
+        console.cursor_back(n);
     }
 }
 
 /// Draw cursor at specific row/col position (for text editors)
 pub fn draw_cursor_at(row: usize, col: usize, visible: bool) {
-    if let Some(console) = CONSOLE.try_lock() {
+    if let Some(mut console) = CONSOLE.try_lock() {
         console.draw_cursor_at(row, col, visible);
+        console.flush();
     }
 }
 
 /// Set pixel at specific coordinates (for graphics/DOOM)
 pub fn set_pixel(x: usize, y: usize, color: u32) {
-    if let Some(console) = CONSOLE.try_lock() {
+    if let Some(mut console) = CONSOLE.try_lock() {
         unsafe {
             console.put_pixel(x, y, color);
         }
+        console.flush();
+    }
+}
+
+/// Draw a row of RGB pixels at (x, y), repeated on the `times - 1` rows
+/// below, under one lock; how DOOM draws its scaled frame
+pub fn blit_scanline(x: usize, y: usize, pixels: &[u32], times: usize) {
+    let mut console = CONSOLE.lock();
+    console.put_scanline(x, y, pixels, times);
+    console.flush();
+}
+
+/// Fill (x0, y0)..(x1, y1), end-exclusive, with RGB `color`
+pub fn fill_rect(x0: usize, y0: usize, x1: usize, y1: usize, color: u32) {
+    let mut console = CONSOLE.lock();
+    console.fill_rect(x0, y0, x1, y1, color);
+    console.flush();
+}
+
+/// Character cell `draw_text` uses: the 8x8 font with every row doubled
+pub const TEXT_CELL: (usize, usize) = (8, 16);
+
+/// Draw `text` into `pixels`, an RGB surface `width` pixels wide, the first
+/// cell's top-left corner at (x, y); for window surfaces, which have no
+/// console. Cells that do not fit are left out.
+pub fn draw_text(pixels: &mut [u32], width: usize, x: usize, y: usize, text: &str, fg: u32, bg: u32) {
+    let (cell_width, cell_height) = TEXT_CELL;
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i * cell_width;
+        if left + cell_width > width {
+            return;
+        }
+        let c = if (' '..='~').contains(&c) { c as usize } else { '?' as usize };
+        let glyph = &FONT_8X8[(c - 32) * 8..(c - 32) * 8 + 8];
+        for py in 0..cell_height {
+            let start = (y + py) * width + left;
+            let Some(row) = pixels.get_mut(start..start + cell_width) else { return };
+            let bits = glyph[py * 8 / cell_height];
+            for (px, pixel) in row.iter_mut().enumerate() {
+                *pixel = if (bits >> (7 - px)) & 1 == 1 { fg } else { bg };
+            }
+        }
     }
 }
 
+/// Draw RGB pixels row after row, `width` to a row with no padding,
+/// starting at pixel `index` of the screen; returns how many were on it
+pub fn blit(index: usize, pixels: &[u32]) -> usize {
+    let mut console = CONSOLE.lock();
+    let total = console.width * console.height;
+    let count = pixels.len().min(total.saturating_sub(index));
+    for (i, &color) in pixels[..count].iter().enumerate() {
+        let at = index + i;
+        unsafe {
+            console.put_pixel(at % console.width, at / console.width, color);
+        }
+    }
+    console.flush();
+    count
+}
+
+/// Read RGB pixels from pixel `index` on, laid out as for `blit`; returns
+/// how many there were
+pub fn read_pixels(index: usize, out: &mut [u32]) -> usize {
+    let console = CONSOLE.lock();
+    let total = console.width * console.height;
+    let count = out.len().min(total.saturating_sub(index));
+    for (i, pixel) in out[..count].iter_mut().enumerate() {
+        let at = index + i;
+        *pixel = console.get_pixel(at % console.width, at / console.width);
+    }
+    count
+}
+
 /// Get framebuffer info (for DOOM)
 pub fn get_info() -> FramebufferInfo {
     if let Some(console) = CONSOLE.try_lock() {
@@ -481,6 +1132,65 @@ pub fn get_info() -> FramebufferInfo {
     }
 }
 
+/// One row of the screen on its way to video memory, for an `Overlay`
+///
+/// Coordinates are screen pixels and may lie off the row; whatever falls
+/// outside it is skipped. Colors are RGB.
+pub struct OverlayRow<'a> {
+    console: &'a FramebufferConsole,
+    y: usize,
+    /// Screen column of `pixels[0]`
+    x0: usize,
+    pixels: &'a mut [u32],
+}
+
+impl OverlayRow<'_> {
+    /// Screen row being painted
+    pub fn y(&self) -> usize {
+        self.y
+    }
+    
+    /// Paint `colors` with the first one at column `x`
+    pub fn span(&mut self, x: i32, colors: &[u32]) {
+        let start = self.x0 as i64 - x as i64;
+        let (skip, offset) = if start > 0 { (start as usize, 0) } else { (0, (-start) as usize) };
+        if skip >= colors.len() || offset >= self.pixels.len() {
+            return;
+        }
+        for (dst, &color) in self.pixels[offset..].iter_mut().zip(&colors[skip..]) {
+            *dst = self.console.encode(color);
+        }
+    }
+    
+    /// Paint columns x0..x1 in `color`
+    pub fn fill(&mut self, x0: i32, x1: i32, color: u32) {
+        let lo = (x0 as i64 - self.x0 as i64).max(0) as usize;
+        let hi = (x1 as i64 - self.x0 as i64).clamp(0, self.pixels.len() as i64) as usize;
+        if lo < hi {
+            let pixel = self.console.encode(color);
+            self.pixels[lo..hi].fill(pixel);
+        }
+    }
+    
+    /// Paint this row's slice of `text` in the 8x8 font, with its top-left
+    /// corner at (x, top)
+    pub fn text(&mut self, x: i32, top: i32, text: &str, fg: u32, bg: u32) {
+        let line = self.y as i64 - top as i64;
+        if !(0..8).contains(&line) {
+            return;
+        }
+        let mut bits = [0u32; 8];
+        for (i, c) in text.chars().enumerate() {
+            let c = if (' '..='~').contains(&c) { c as usize } else { '?' as usize };
+            let byte = FONT_8X8.get((c - 32) * 8 + line as usize).copied().unwrap_or(0);
+            for (col, bit) in bits.iter_mut().enumerate() {
+                *bit = if (byte >> (7 - col)) & 1 == 1 { fg } else { bg };
+            }
+            self.span(x + i as i32 * 8, &bits);
+        }
+    }
+}
+
 pub struct FramebufferInfo {
     pub width: usize,
     pub height: usize,
@@ -493,7 +1203,9 @@ use core::fmt;
 
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    CONSOLE.lock().write_fmt(args).unwrap();
+    let mut console = CONSOLE.lock();
+    console.write_fmt(args).unwrap();
+    console.flush();
 }
 
 impl fmt::Write for FramebufferConsole {
@@ -514,3 +1226,93 @@ macro_rules! fb_println {
     ($fmt:expr) => ($crate::fb_print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => ($crate::fb_print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A console of `cols` x `rows` cells drawing into `pixels`
+    fn console(pixels: &mut Vec<u32>, cols: usize, rows: usize) -> FramebufferConsole {
+        let mut console = FramebufferConsole::empty();
+        console.width = cols * console.char_width;
+        console.height = rows * console.char_height;
+        pixels.resize(console.width * console.height, 0);
+        console.fb_addr = pixels.as_mut_ptr().cast();
+        console.pitch = console.width * 4;
+        console.bpp = 4;
+        console.red_shift = 16;
+        console.blue_shift = 0;
+        console.cols = cols;
+        console.rows = rows;
+        console
+    }
+
+    fn text(console: &FramebufferConsole, row: usize) -> &[u8] {
+        &console.screen[console.screen_index(row)]
+    }
+
+    #[test_case]
+    fn scrolling_rotates_rows_into_history() {
+        let mut pixels = Vec::new();
+        let mut console = console(&mut pixels, 4, 2);
+        console.enable_scrollback();
+        console.write_str("a\nb\nc\nd");
+        assert_eq!(text(&console, 0), b"c   ");
+        assert_eq!(text(&console, 1), b"d   ");
+        assert_eq!(console.history, [b"a   ".to_vec(), b"b   ".to_vec()]);
+    }
+
+    #[test_case]
+    fn scrolled_pixels_match_drawing_in_place() {
+        for double_buffered in [false, true] {
+            let (mut scrolled, mut drawn) = (Vec::new(), Vec::new());
+            let mut a = console(&mut scrolled, 4, 2);
+            let mut b = console(&mut drawn, 4, 2);
+            if double_buffered {
+                assert!(a.enable_double_buffer() && b.enable_double_buffer());
+            }
+            a.clear();
+            b.clear();
+            a.write_str("x\nyz\n");
+            b.write_str("yz");
+            a.flush();
+            b.flush();
+            assert!(scrolled == drawn);
+        }
+    }
+
+    #[test_case]
+    fn text_is_drawn_into_surfaces_and_clipped() {
+        let (cell_width, cell_height) = TEXT_CELL;
+        let width = cell_width * 2 + 3;
+        let mut pixels = vec![0; width * cell_height];
+        draw_text(&mut pixels, width, 0, 0, " |x", 1, 2);
+        // The space is all background, the bar has ink, and "x" is cut off
+        assert!(pixels[..cell_width].iter().all(|&pixel| pixel == 2));
+        assert!(pixels.chunks(width).any(|row| row[cell_width..cell_width * 2].contains(&1)));
+        assert!(pixels.chunks(width).all(|row| row[cell_width * 2..].iter().all(|&pixel| pixel == 0)));
+    }
+
+    #[test_case]
+    fn scanlines_match_pixel_by_pixel_drawing() {
+        let row = [0x112233, 0x445566, 0x778899];
+        for double_buffered in [false, true] {
+            let (mut blitted, mut drawn) = (Vec::new(), Vec::new());
+            let mut a = console(&mut blitted, 1, 1);
+            let mut b = console(&mut drawn, 1, 1);
+            if double_buffered {
+                assert!(a.enable_double_buffer() && b.enable_double_buffer());
+            }
+            // Clipped on the right and at the bottom
+            a.put_scanline(6, 13, &row, 4);
+            for y in 13..16 {
+                for (i, &color) in row[..2].iter().enumerate() {
+                    unsafe { b.put_pixel(6 + i, y, color) };
+                }
+            }
+            a.flush();
+            b.flush();
+            assert!(blitted == drawn);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! QEMU fw_cfg driver
+//!
+//! Reads files the host passed with `-fw_cfg name=opt/ospab/<path>,file=<host file>`.
+//! Those files are exposed read-only under /host by the VFS, so userland
+//! binaries can be swapped without rebuilding the initrd
+//! (see scripts/host-share.sh).
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const SELECTOR_PORT: u16 = 0x510;
+const DATA_PORT: u16 = 0x511;
+
+const KEY_SIGNATURE: u16 = 0x0000;
+const KEY_FILE_DIR: u16 = 0x0019;
+
+/// Only files under this prefix are shared; the rest belong to firmware
+pub const SHARE_PREFIX: &str = "opt/ospab/";
+
+const DIR_ENTRY_NAME_LEN: usize = 56;
+
+#[derive(Debug, Clone)]
+pub struct FwCfgFile {
+    /// Path below SHARE_PREFIX
+    pub path: String,
+    pub size: usize,
+    select: u16,
+}
+
+/// Shared files, read once from the fw_cfg directory (None = not probed yet)
+static FILES: Mutex<Option<Vec<FwCfgFile>>> = Mutex::new(None);
+/// Serializes selector/data port access
+static PORT_LOCK: Mutex<()> = Mutex::new(());
+
+fn select(key: u16) {
+    unsafe { Port::<u16>::new(SELECTOR_PORT).write(key) };
+}
+
+fn read_bytes(buf: &mut [u8]) {
+    if buf.is_empty() {
+        return;
+    }
+    unsafe {
+        core::arch::asm!(
+            "rep insb",
+            in("dx") DATA_PORT,
+            inout("rdi") buf.as_mut_ptr() => _,
+            inout("rcx") buf.len() => _,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Whether we are running under QEMU with fw_cfg available
+pub fn is_present() -> bool {
+    let _guard = PORT_LOCK.lock();
+    select(KEY_SIGNATURE);
+    let mut signature = [0u8; 4];
+    read_bytes(&mut signature);
+    &signature == b"QEMU"
+}
+
+/// Probe fw_cfg and cache the list of shared files
+pub fn init() -> usize {
+    let files = if is_present() { read_directory() } else { Vec::new() };
+    let count = files.len();
+    *FILES.lock() = Some(files);
+    if count > 0 {
+        crate::serial_println!("[FW_CFG] {} shared file(s) available under /host", count);
+    }
+    count
+}
+
+fn read_directory() -> Vec<FwCfgFile> {
+    let _guard = PORT_LOCK.lock();
+    select(KEY_FILE_DIR);
+    let mut count = [0u8; 4];
+    read_bytes(&mut count);
+    let count = u32::from_be_bytes(count);
+
+    let mut files = Vec::new();
+    for _ in 0..count {
+        let mut entry = [0u8; 8 + DIR_ENTRY_NAME_LEN];
+        read_bytes(&mut entry);
+        let size = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize;
+        let select = u16::from_be_bytes([entry[4], entry[5]]);
+        let name = &entry[8..];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        let name = match core::str::from_utf8(&name[..name_len]) {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if let Some(path) = name.strip_prefix(SHARE_PREFIX) {
+            let path = path.trim_matches('/');
+            if !path.is_empty() {
+                files.push(FwCfgFile { path: path.to_string(), size, select });
+            }
+        }
+    }
+    files
+}
+
+/// Whether the host shared anything (and /host should exist)
+pub fn has_files() -> bool {
+    FILES.lock().as_ref().map_or(false, |files| !files.is_empty())
+}
+
+/// All shared files
+pub fn files() -> Vec<FwCfgFile> {
+    FILES.lock().clone().unwrap_or_default()
+}
+
+/// Contents of the shared file at `path` (relative to /host)
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    let path = path.trim_matches('/');
+    let file = files().into_iter().find(|f| f.path == path)?;
+    let _guard = PORT_LOCK.lock();
+    select(file.select);
+    let mut data = alloc::vec![0u8; file.size];
+    read_bytes(&mut data);
+    Some(data)
+}
+
+/// Names directly inside directory `path` (relative to /host).
+/// Directories are implied by the file paths.
+pub fn list_dir(path: &str) -> Option<Vec<String>> {
+    let path = path.trim_matches('/');
+    let prefix = if path.is_empty() { String::new() } else { alloc::format!("{}/", path) };
+    let mut names: Vec<String> = files().iter()
+        .filter_map(|f| f.path.strip_prefix(prefix.as_str()))
+        .map(|rest| rest.split('/').next().unwrap_or(rest).to_string())
+        .collect();
+    if names.is_empty() && !path.is_empty() {
+        return None;
+    }
+    names.sort();
+    names.dedup();
+    Some(names)
+}
+
+/// Whether `path` (relative to /host) is a shared file
+pub fn is_file(path: &str) -> bool {
+    let path = path.trim_matches('/');
+    files().iter().any(|f| f.path == path)
+}
+
+/// Whether `path` (relative to /host) is a directory
+pub fn is_dir(path: &str) -> bool {
+    let path = path.trim_matches('/');
+    path.is_empty() || files().iter().any(|f| {
+        f.path.len() > path.len() && f.path.starts_with(path) && f.path.as_bytes()[path.len()] == b'/'
+    })
+}
@@ -0,0 +1,82 @@
+//! High Precision Event Timer
+//!
+//! Only the main counter is used: a free-running 64-bit count at a fixed
+//! rate of at least 10 MHz, which gives the kernel nanosecond time and a
+//! reference to calibrate the local APIC timer against. Its comparators
+//! are left alone. The registers sit below 4 GiB, inside the HHDM.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// Register offsets
+const CAPABILITIES: usize = 0x000;
+const CONFIG: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0F0;
+
+/// Capabilities: the main counter is 64 bits wide
+const COUNT_SIZE_64: u64 = 1 << 13;
+/// Configuration: the main counter runs
+const ENABLE: u64 = 1 << 0;
+
+/// Femtoseconds in a nanosecond
+const FS_PER_NS: u64 = 1_000_000;
+/// Longest period the specification allows (10 MHz)
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+/// Virtual address of the registers, 0 until `init` succeeds
+static BASE: AtomicU64 = AtomicU64::new(0);
+/// Length of one counter tick in femtoseconds
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+
+fn read(base: u64, offset: usize) -> u64 {
+    unsafe { core::ptr::read_volatile((base as usize + offset) as *const u64) }
+}
+
+fn write(base: u64, offset: usize, value: u64) {
+    unsafe { core::ptr::write_volatile((base as usize + offset) as *mut u64, value) }
+}
+
+/// Find the HPET through ACPI and start its counter
+pub fn init() -> Result<(), &'static str> {
+    let phys = crate::drivers::acpi::hpet_address().ok_or("no HPET table")?;
+    let hhdm = crate::boot::hhdm_offset().ok_or("no HHDM")?;
+    let base = phys + hhdm;
+
+    let capabilities = read(base, CAPABILITIES);
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err("HPET reports an invalid period");
+    }
+    // A 32-bit counter wraps within minutes; not worth the bookkeeping
+    if capabilities & COUNT_SIZE_64 == 0 {
+        return Err("HPET counter is only 32 bits");
+    }
+
+    write(base, CONFIG, read(base, CONFIG) | ENABLE);
+    PERIOD_FS.store(period, Ordering::Relaxed);
+    BASE.store(base, Ordering::Release);
+    crate::serial_println!("[HPET] {} MHz counter at {:#x}", 1_000_000_000 / period, phys);
+    Ok(())
+}
+
+pub fn is_available() -> bool {
+    BASE.load(Ordering::Acquire) != 0
+}
+
+/// Nanoseconds on the counter, which started at an arbitrary value
+pub fn nanos() -> Option<u64> {
+    let base = BASE.load(Ordering::Acquire);
+    if base == 0 {
+        return None;
+    }
+    let ticks = read(base, MAIN_COUNTER) as u128;
+    Some((ticks * PERIOD_FS.load(Ordering::Relaxed) as u128 / FS_PER_NS as u128) as u64)
+}
+
+/// Busy-wait for `ns` nanoseconds; returns false without an HPET
+pub fn delay_ns(ns: u64) -> bool {
+    let Some(start) = nanos() else { return false };
+    while nanos().is_some_and(|now| now - start < ns) {
+        core::hint::spin_loop();
+    }
+    true
+}
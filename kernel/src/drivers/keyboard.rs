@@ -6,9 +6,11 @@ use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
 use spin::Mutex;
 use x86_64::instructions::port::Port;
 use crate::drivers::framebuffer;
-use crate::services::vfs;
-use crate::ipc::message::FSRequest;
+use crate::keybindings::{self, Action, Context, Key};
+use crate::services::terminal::{self, LineEditor};
+use alloc::collections::VecDeque;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 // PS/2 Controller ports (Intel 8042)
 const KBD_DATA_PORT: u16 = 0x60;     // Data port
@@ -44,9 +46,7 @@ fn serial_print_hex(val: u8) {
     serial_print(&[HEX[(val >> 4) as usize], HEX[(val & 0xF) as usize]]);
 }
 
-const CMD_BUFFER_SIZE: usize = 256;
 const SCANCODE_BUFFER_SIZE: usize = 128;
-const HISTORY_SIZE: usize = 5;  // Reduced from 20 to 5
 
 // ============================================================================
 // LOCK-FREE SCANCODE RING BUFFER (for ISR)
@@ -61,10 +61,35 @@ static SCANCODE_READ: AtomicUsize = AtomicUsize::new(0);
 static SCANCODE_WRITE: AtomicUsize = AtomicUsize::new(0);
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
-// Track Ctrl state and extended prefix for scancode handling
-static CTRL_PRESSED: AtomicBool = AtomicBool::new(false);
+// Modifier bits, as returned by `modifiers()`
+pub const MOD_SHIFT: u8 = 1 << 0;
+pub const MOD_CTRL: u8 = 1 << 1;
+/// Left Alt; the right one is AltGr
+pub const MOD_ALT: u8 = 1 << 2;
+pub const MOD_ALTGR: u8 = 1 << 3;
+/// Caps Lock is on
+pub const MOD_CAPS: u8 = 1 << 4;
+
+// Modifier state and extended prefix for scancode handling
+static MODIFIERS: AtomicU8 = AtomicU8::new(0);
 static EXTENDED_FLAG: AtomicBool = AtomicBool::new(false);
 
+/// Copy of the scancodes for /dev/kbd, filled by the ISR like SCANCODE_BUF
+static RAW_BUF: [AtomicU8; SCANCODE_BUFFER_SIZE] = {
+    const INIT: AtomicU8 = AtomicU8::new(0);
+    [INIT; SCANCODE_BUFFER_SIZE]
+};
+static RAW_READ: AtomicUsize = AtomicUsize::new(0);
+static RAW_WRITE: AtomicUsize = AtomicUsize::new(0);
+
+// Keyboard commands and replies
+const KBD_SET_TYPEMATIC: u8 = 0xF3;
+const KBD_ACK: u8 = 0xFA;
+const KBD_RESEND: u8 = 0xFE;
+/// Typematic byte after a keyboard reset: 500 ms delay, 10.9 keys/s
+const TYPEMATIC_DEFAULT: u8 = 0x2B;
+static TYPEMATIC: AtomicU8 = AtomicU8::new(TYPEMATIC_DEFAULT);
+
 use core::sync::atomic::AtomicU8;
 
 // ============================================================================
@@ -73,24 +98,29 @@ use core::sync::atomic::AtomicU8;
 
 struct KeyboardState {
     keyboard: Option<Keyboard<layouts::Us104Key, ScancodeSet1>>,
-    cmd_buf: [u8; CMD_BUFFER_SIZE],
-    cmd_len: usize,
-    cursor_pos: usize, // Cursor position in cmd_buf (0..=cmd_len)
-    history: [[u8; CMD_BUFFER_SIZE]; HISTORY_SIZE],
-    history_lens: [usize; HISTORY_SIZE],
-    history_count: usize,
-    history_pos: Option<usize>, // Current position in history (None = not navigating)
+    /// Kernel shell command being typed
+    line: LineEditor,
+    /// Newest first
+    history: VecDeque<String>,
+    search: Option<Search>,
+}
+
+/// Ctrl+R reverse search in progress
+struct Search {
+    query: String,
+    /// History entry matching `query`
+    found: Option<usize>,
+    /// The line being typed when the search started, for Ctrl+G
+    saved: String,
+    /// Columns the search line takes on screen
+    width: usize,
 }
 
 static STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState {
     keyboard: None,
-    cmd_buf: [0u8; CMD_BUFFER_SIZE],
-    cmd_len: 0,
-    cursor_pos: 0,
-    history: [[0u8; CMD_BUFFER_SIZE]; HISTORY_SIZE],
-    history_lens: [0usize; HISTORY_SIZE],
-    history_count: 0,
-    history_pos: None,
+    line: LineEditor::new(),
+    history: VecDeque::new(),
+    search: None,
 });
 
 /// Wait for input buffer to be empty (safe to send command)
@@ -205,7 +235,10 @@ pub fn init() {
 pub fn enable_hw_irq() {
     serial_print(b"[KBD] Enabling keyboard hardware IRQ...\r\n");
     
-    // First unmask IRQ1 in PIC
+    // First unmask IRQ1 at the interrupt controller
+    if let Err(e) = crate::irq::register(1, "keyboard", controller_interrupt) {
+        crate::serial_println!("[KBD] {}", e);
+    }
     crate::interrupts::enable_irq(1);
     
     // Then enable at PS/2 controller level
@@ -239,21 +272,180 @@ pub fn enable_interrupts_at_hw_level() {
     enable_hw_irq();
 }
 
+// Modifiers as seen by the ISR, for chords that act before anyone reads keys
+static CHORD_CTRL: AtomicBool = AtomicBool::new(false);
+static CHORD_ALT: AtomicBool = AtomicBool::new(false);
+static CHORD_SYSRQ: AtomicBool = AtomicBool::new(false);
+
+/// Ctrl+Alt+Del goes to power management whoever owns the keyboard, and
+/// keys pressed while Alt+SysRq is held go to `sysrq`
+///
+/// The right-hand Ctrl/Alt only differ by an 0xE0 prefix, so it is ignored.
+/// Returns true if the scancode was consumed.
+fn check_chord(scancode: u8) -> bool {
+    match scancode {
+        0x1D => CHORD_CTRL.store(true, Ordering::Relaxed),
+        0x9D => CHORD_CTRL.store(false, Ordering::Relaxed),
+        0x38 => CHORD_ALT.store(true, Ordering::Relaxed),
+        0xB8 => {
+            CHORD_ALT.store(false, Ordering::Relaxed);
+            CHORD_SYSRQ.store(false, Ordering::Relaxed);
+        }
+        // Delete, either the extended key or keypad Del
+        0x53 if CHORD_CTRL.load(Ordering::Relaxed) && CHORD_ALT.load(Ordering::Relaxed) => {
+            crate::power::request(crate::power::PowerEvent::CtrlAltDel);
+        }
+        // PrintScreen sends SysRq's own code while Alt is down
+        0x54 => {
+            CHORD_SYSRQ.store(true, Ordering::Relaxed);
+            return true;
+        }
+        0xD4 => {
+            CHORD_SYSRQ.store(false, Ordering::Relaxed);
+            return true;
+        }
+        _ if CHORD_ALT.load(Ordering::Relaxed) && CHORD_SYSRQ.load(Ordering::Relaxed) => {
+            if let Some(key) = crate::sysrq::key_for_scancode(scancode) {
+                crate::sysrq::handle(key);
+                return true;
+            }
+            // Releases of the command keys
+            return crate::sysrq::key_for_scancode(scancode & 0x7F).is_some();
+        }
+        _ => {}
+    }
+    false
+}
+
+/// Push onto one of the ISR ring buffers; dropped if it is full
+fn push_ring(buf: &[AtomicU8; SCANCODE_BUFFER_SIZE], read: &AtomicUsize, write: &AtomicUsize, scancode: u8) {
+    let at = write.load(Ordering::Relaxed);
+    let next = (at + 1) % SCANCODE_BUFFER_SIZE;
+    if next != read.load(Ordering::Relaxed) {
+        buf[at].store(scancode, Ordering::Relaxed);
+        write.store(next, Ordering::Release);
+    }
+}
+
+/// IRQ 1 and 12 handler: take the byte waiting at the PS/2 controller to
+/// the keyboard or the mouse; false if there was none
+pub fn controller_interrupt() -> bool {
+    let status: u8 = unsafe { Port::<u8>::new(KBD_STATUS_PORT).read() };
+    if status & 0x01 == 0 {
+        return false;
+    }
+    let byte: u8 = unsafe { Port::<u8>::new(KBD_DATA_PORT).read() };
+    // Mouse bytes share the data port; the controller flags them
+    if status & 0x20 != 0 {
+        crate::drivers::mouse::handle_byte(byte);
+    } else {
+        queue_scancode(byte);
+    }
+    true
+}
+
 /// Called from ISR - queue scancode using atomic operations (lock-free)
 pub fn queue_scancode(scancode: u8) {
     if !INITIALIZED.load(Ordering::Acquire) {
         return; // Not ready yet
     }
-    
-    let write = SCANCODE_WRITE.load(Ordering::Relaxed);
-    let next_write = (write + 1) % SCANCODE_BUFFER_SIZE;
-    let read = SCANCODE_READ.load(Ordering::Relaxed);
-    
-    if next_write != read {
-        SCANCODE_BUF[write].store(scancode, Ordering::Relaxed);
-        SCANCODE_WRITE.store(next_write, Ordering::Release);
+    // Replies to set_typematic, read again by the IRQ it raised
+    if scancode == KBD_ACK || scancode == KBD_RESEND {
+        return;
     }
-    // If buffer full, drop scancode
+    if check_chord(scancode) {
+        return;
+    }
+    push_ring(&SCANCODE_BUF, &SCANCODE_READ, &SCANCODE_WRITE, scancode);
+    push_ring(&RAW_BUF, &RAW_READ, &RAW_WRITE, scancode);
+}
+
+/// Scancodes for /dev/kbd: set 1 bytes as the keyboard sent them, 0xE0
+/// prefixes and releases included. Returns how many were copied
+pub fn read_raw(buf: &mut [u8]) -> usize {
+    let mut n = 0;
+    while n < buf.len() {
+        let read = RAW_READ.load(Ordering::Relaxed);
+        if read == RAW_WRITE.load(Ordering::Acquire) {
+            break;
+        }
+        buf[n] = RAW_BUF[read].load(Ordering::Relaxed);
+        RAW_READ.store((read + 1) % SCANCODE_BUFFER_SIZE, Ordering::Release);
+        n += 1;
+    }
+    n
+}
+
+/// Drop the scancodes nobody read, so a new /dev/kbd reader starts afresh
+pub fn flush_raw() {
+    RAW_READ.store(RAW_WRITE.load(Ordering::Acquire), Ordering::Release);
+}
+
+/// Modifiers held (and Caps Lock) as of the last key read, MOD_* bits
+pub fn modifiers() -> u8 {
+    MODIFIERS.load(Ordering::Relaxed)
+}
+
+fn modifier(bit: u8) -> bool {
+    modifiers() & bit != 0
+}
+
+fn set_modifier(bit: u8, held: bool) {
+    if held {
+        MODIFIERS.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        MODIFIERS.fetch_and(!bit, Ordering::Relaxed);
+    }
+}
+
+/// Keys per second, in tenths, for the rate bits of a typematic byte
+fn typematic_rate(code: u8) -> u32 {
+    // Period is (8 + A) * 2^B * 4.17 ms
+    let period_us = (8 + (code & 7) as u32) * (1 << ((code >> 3) & 3)) * 4167;
+    10_000_000 / period_us
+}
+
+/// Current repeat delay in ms and rate in tenths of keys per second
+pub fn typematic() -> (u32, u32) {
+    let byte = TYPEMATIC.load(Ordering::Relaxed);
+    (250 * (((byte >> 5) & 3) as u32 + 1), typematic_rate(byte & 0x1F))
+}
+
+/// Send a byte to the keyboard and wait for its ACK; interrupts must be off
+unsafe fn send_to_keyboard(byte: u8) -> Result<(), &'static str> {
+    let mut data_port: Port<u8> = Port::new(KBD_DATA_PORT);
+    for _ in 0..3 {
+        if !wait_input_ready() {
+            return Err("controller busy");
+        }
+        data_port.write(byte);
+        if !wait_output_ready() {
+            return Err("no reply from keyboard");
+        }
+        match data_port.read() {
+            KBD_ACK => return Ok(()),
+            KBD_RESEND => continue,
+            _ => return Err("keyboard refused the command"),
+        }
+    }
+    Err("keyboard refused the command")
+}
+
+/// Set how long a key is held before it repeats (250..=1000 ms, in steps
+/// of 250) and how fast it then repeats (2..=30 keys per second, in
+/// tenths). The nearest values the keyboard supports are used and returned
+pub fn set_typematic(delay_ms: u32, rate_x10: u32) -> Result<(u32, u32), &'static str> {
+    let delay = (delay_ms.clamp(250, 1000) + 125) / 250 - 1;
+    let rate = (0..32u8)
+        .min_by_key(|&code| typematic_rate(code).abs_diff(rate_x10))
+        .unwrap_or(TYPEMATIC_DEFAULT & 0x1F);
+    let byte = ((delay as u8) << 5) | rate;
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        send_to_keyboard(KBD_SET_TYPEMATIC)?;
+        send_to_keyboard(byte)
+    })?;
+    TYPEMATIC.store(byte, Ordering::Relaxed);
+    Ok(typematic())
 }
 
 /// Called from main loop - process queued scancodes
@@ -280,461 +472,384 @@ pub fn process_scancodes() {
     }
 }
 
-pub fn handle_scancode(scancode: u8) {
-    // Track extended prefix and Ctrl make/release based on raw scancodes
+/// Track the modifiers from raw scancodes; true if `scancode` follows an
+/// 0xE0 prefix
+fn track_modifiers(scancode: u8) -> bool {
     if scancode == 0xE0 {
         EXTENDED_FLAG.store(true, Ordering::Relaxed);
-    } else {
-        let _extended = EXTENDED_FLAG.load(Ordering::Relaxed);
-        match scancode {
-            0x1D => CTRL_PRESSED.store(true, Ordering::Relaxed),  // Ctrl press (left/right)
-            0x9D => CTRL_PRESSED.store(false, Ordering::Relaxed), // Ctrl release (left)
-            _ => {}
+        return false;
+    }
+    let extended = EXTENDED_FLAG.swap(false, Ordering::Relaxed);
+    match (extended, scancode) {
+        (_, 0x1D) => set_modifier(MOD_CTRL, true),  // Ctrl press (left/right)
+        (_, 0x9D) => set_modifier(MOD_CTRL, false), // Ctrl release
+        // Extended shifts are the fake ones around Print Screen and the arrows
+        (false, 0x2A | 0x36) => set_modifier(MOD_SHIFT, true),  // Shift press (left/right)
+        (false, 0xAA | 0xB6) => set_modifier(MOD_SHIFT, false), // Shift release
+        (false, 0x38) => set_modifier(MOD_ALT, true),  // Left Alt press
+        (false, 0xB8) => set_modifier(MOD_ALT, false), // Left Alt release
+        (true, 0x38) => set_modifier(MOD_ALTGR, true),  // Right Alt press
+        (true, 0xB8) => set_modifier(MOD_ALTGR, false), // Right Alt release
+        (false, 0x3A) => {
+            MODIFIERS.fetch_xor(MOD_CAPS, Ordering::Relaxed);
+        }
+        _ => {}
+    }
+    extended
+}
+
+/// Run one scancode through the decoder; the keymap decides what the
+/// printable keys type
+fn decode(state: &mut KeyboardState, scancode: u8) -> Option<DecodedKey> {
+    let extended = track_modifiers(scancode);
+    let kb = state.keyboard.as_mut()?;
+    // Always fed to the decoder, which keeps its own modifier state
+    let decoded = kb.add_byte(scancode).ok().flatten().and_then(|event| kb.process_keyevent(event));
+    // Make codes of the main block only: the keypad and cursor keys are
+    // extended and keep their meaning
+    if !extended && scancode < 0x80 {
+        let mods = modifiers();
+        let (shift, caps, altgr) = (mods & MOD_SHIFT != 0, mods & MOD_CAPS != 0, mods & MOD_ALTGR != 0);
+        if let Some(mapped) = crate::keymap::translate(scancode, shift, caps, altgr) {
+            return mapped.map(DecodedKey::Unicode);
         }
-        // reset extended flag after processing a non-0xE0 byte
-        EXTENDED_FLAG.store(false, Ordering::Relaxed);
     }
+    decoded
+}
 
+pub fn handle_scancode(scancode: u8) {
     let mut state = STATE.lock();
-    
-    let kb = match state.keyboard.as_mut() {
-        Some(k) => k,
-        None => return,
-    };
-    
-    let key_event = match kb.add_byte(scancode) {
-        Ok(Some(ev)) => ev,
-        _ => return,
-    };
-    
-    let key = match kb.process_keyevent(key_event) {
-        Some(k) => k,
-        None => return,
-    };
-    
+    let Some(key) = decode(&mut state, scancode) else { return };
+
     // Drop state lock before calling framebuffer (prevents potential deadlock)
     drop(state);
     
     match key {
         DecodedKey::Unicode(character) => {
+            // Typing returns the console from scrollback
+            framebuffer::scroll_to_bottom();
             // If Ctrl is held and a letter is pressed, map to control character (e.g., Ctrl+C -> '\x03')
-            if CTRL_PRESSED.load(Ordering::Relaxed) && character.is_ascii_alphabetic() {
-                let ctl = ((character.to_ascii_lowercase() as u8) - b'a' + 1) as u8;
-                handle_char(ctl as char);
+            let c = if modifier(MOD_CTRL) && character.is_ascii_alphabetic() {
+                ((character.to_ascii_lowercase() as u8) - b'a' + 1) as char
             } else {
-                handle_char(character);
+                character
+            };
+            let action = keybindings::lookup(Context::Shell, Key::Char(c));
+            if action != Some(Action::HistorySearch) && searching() {
+                return search_key(c);
+            }
+            match action {
+                Some(action) => run_action(action),
+                None => handle_char(c),
             }
         }
         DecodedKey::RawKey(key) => {
-            // Handle arrow keys for history navigation and cursor movement
+            // Arrow keys go through the binding table (history and cursor by default)
             use pc_keyboard::KeyCode;
-            match key {
-                KeyCode::ArrowUp => handle_arrow_up(),
-                KeyCode::ArrowDown => handle_arrow_down(),
-                KeyCode::ArrowLeft => handle_arrow_left(),
-                KeyCode::ArrowRight => handle_arrow_right(),
-                _ => {}
+            let shift = modifier(MOD_SHIFT);
+            let ctrl = modifier(MOD_CTRL);
+            let key = match key {
+                KeyCode::PageUp if shift => return framebuffer::scrollback_page_up(),
+                KeyCode::PageDown if shift => return framebuffer::scrollback_page_down(),
+                KeyCode::ArrowUp => Key::Up,
+                KeyCode::ArrowDown => Key::Down,
+                KeyCode::ArrowLeft => Key::Left,
+                KeyCode::ArrowRight => Key::Right,
+                _ => return,
+            };
+            // Leave the search with the line found, to edit it
+            if searching() {
+                return finish_search(false);
+            }
+            // Ctrl+Left/Right act like the plain arrows unless bound themselves
+            let ctrl_key = match key {
+                Key::Left if ctrl => Some(Key::CtrlLeft),
+                Key::Right if ctrl => Some(Key::CtrlRight),
+                _ => None,
+            };
+            let action = ctrl_key
+                .and_then(|key| keybindings::lookup(Context::Shell, key))
+                .or_else(|| keybindings::lookup(Context::Shell, key));
+            if let Some(action) = action {
+                run_action(action);
             }
         }
     }
 }
 
+/// Perform a bound shell action
+fn run_action(action: Action) {
+    match action {
+        Action::Cancel => cancel_line(),
+        Action::Complete => complete_word(),
+        Action::HistoryPrev => handle_arrow_up(),
+        Action::HistoryNext => handle_arrow_down(),
+        Action::HistorySearch => search_history(),
+        Action::CursorLeft => handle_arrow_left(),
+        Action::CursorRight => handle_arrow_right(),
+        Action::ClearScreen => redraw_line(true),
+        Action::PasteClipboard => paste_clipboard(),
+        // Only a running job can be suspended
+        Action::Suspend => {}
+        // Editor actions are only bound in the editor context
+        _ => {}
+    }
+}
+
+/// Type the clipboard at the cursor
+fn paste_clipboard() {
+    let text = crate::services::clipboard::get();
+    let mut state = STATE.lock();
+    framebuffer::hide_cursor();
+    state.line.paste(&text);
+    drop(state);
+    framebuffer::show_cursor();
+}
+
+/// Drop the line being typed and start a new prompt
+fn cancel_line() {
+    let mut state = STATE.lock();
+    framebuffer::hide_cursor();
+    state.line.take();
+    drop(state);
+
+    framebuffer::print("^C\n");
+    let prompt = crate::shell::get_prompt();
+    framebuffer::print(&prompt);
+    framebuffer::show_cursor();
+}
+
+/// Print the prompt and the line being typed again, on a cleared screen if
+/// `clear` is set; the cursor goes to the end of the line
+fn redraw_line(clear: bool) {
+    let mut state = STATE.lock();
+    framebuffer::hide_cursor();
+    if clear {
+        framebuffer::clear();
+    } else {
+        framebuffer::print_char('\n');
+    }
+    let prompt = crate::shell::get_prompt();
+    framebuffer::print(&prompt);
+    let line = state.line.text();
+    state.line.set(&line);
+    framebuffer::print(&line);
+    drop(state);
+    framebuffer::show_cursor();
+}
+
 fn handle_char(c: char) {
     let mut state = STATE.lock();
     framebuffer::hide_cursor();
-    
-    match c {
-        '\x03' => {
-            // Ctrl+C - cancel current input
-            state.history_pos = None;
-            state.cmd_len = 0;
-            state.cursor_pos = 0;
-            drop(state);
 
-            framebuffer::print("^C\n");
-            let prompt = crate::shell::get_prompt();
-            framebuffer::print(&prompt);
-        }
+    match c {
         '\n' | '\r' => {
-            // Reset history navigation and cursor
-            state.history_pos = None;
-            state.cursor_pos = 0;
-            
             framebuffer::print_char('\n');
-            
-            // Save to history if not empty
-            if state.cmd_len > 0 {
-                // Shift history and add new command
-                if state.history_count < HISTORY_SIZE {
-                    state.history_count += 1;
-                }
-                for i in (1..state.history_count).rev() {
-                    state.history[i] = state.history[i - 1];
-                    state.history_lens[i] = state.history_lens[i - 1];
-                }
-                state.history[0] = state.cmd_buf;
-                state.history_lens[0] = state.cmd_len;
-            }
-            
-            // Execute command needs the buffer
-            let cmd_len = state.cmd_len;
-            let cmd_buf: [u8; CMD_BUFFER_SIZE] = state.cmd_buf;
-            state.cmd_len = 0;
+            let line = state.line.take();
+            terminal::add_history(&mut state.history, String::from(line.trim()));
             drop(state); // Drop lock before command execution
-            
-            execute_command_impl(&cmd_buf[..cmd_len]);
-            
+
+            execute_command_impl(&line);
+            crate::shell::session::save_history();
+            crate::shell::jobs::notify();
+
             // Show prompt with current directory
             let prompt = crate::shell::get_prompt();
             framebuffer::print(&prompt);
         }
-        '\x08' => {
-            // Backspace - delete char before cursor
-            state.history_pos = None;
-            
-            if state.cursor_pos > 0 {
-                let cursor_pos = state.cursor_pos;
-                let cmd_len = state.cmd_len;
-                
-                // Shift characters left from cursor position
-                for i in cursor_pos..cmd_len {
-                    state.cmd_buf[i - 1] = state.cmd_buf[i];
-                }
-                state.cmd_len -= 1;
-                state.cursor_pos -= 1;
-                
-                // Redraw line from cursor to end
-                let chars_to_redraw = state.cmd_len - state.cursor_pos;
-                let mut redraw_buf = [0u8; CMD_BUFFER_SIZE];
-                redraw_buf[..chars_to_redraw].copy_from_slice(&state.cmd_buf[state.cursor_pos..state.cmd_len]);
-                
-                drop(state);
-                
-                // Move cursor back, redraw rest of line, clear last char, move cursor back
-                framebuffer::print_char('\x08');
-                if chars_to_redraw > 0 {
-                    if let Ok(s) = core::str::from_utf8(&redraw_buf[..chars_to_redraw]) {
-                        framebuffer::print(s);
-                    }
-                }
-                framebuffer::print_char(' '); // Clear last char
-                // Move cursor back to position
-                for _ in 0..=chars_to_redraw {
-                    framebuffer::print_char('\x08');
-                }
-            }
-        }
-        // Tab completion
-        '\t' => {
-            // Find current token before cursor
-            let cursor_pos = state.cursor_pos;
-            let mut start = cursor_pos;
-            while start > 0 && state.cmd_buf[start - 1] != b' ' {
-                start -= 1;
-            }
-            let prefix = core::str::from_utf8(&state.cmd_buf[start..cursor_pos]).unwrap_or("");
-
-            // Collect candidates from /bin and current directory
-            let mut candidates: alloc::vec::Vec<String> = alloc::vec::Vec::new();
-            if let crate::ipc::message::FSResponse::DirListing(list) = vfs::process_request(FSRequest::ListDir { path: String::from("/bin") }) {
-                for name in list { if name.starts_with(prefix) { candidates.push(name); } }
-            }
-            if let crate::ipc::message::FSResponse::DirListing(list) = vfs::process_request(FSRequest::ListDir { path: String::from(".") }) {
-                for name in list { if name.starts_with(prefix) { candidates.push(name); } }
-            }
-
-            if candidates.len() == 1 {
-                // Insert rest of the candidate
-                let completion = &candidates[0];
-                let suffix = &completion[prefix.len()..];
-                // Insert suffix into buffer
-                let s_bytes = suffix.as_bytes();
-                let add_len = s_bytes.len();
-                let insert_at = state.cursor_pos;
-                let old_len = state.cmd_len;
-                if old_len + add_len >= CMD_BUFFER_SIZE { drop(state); framebuffer::show_cursor(); return; }
-                // Shift right (use locals to avoid re-borrowing `state` inside loop bounds)
-                for i in (insert_at..old_len).rev() {
-                    state.cmd_buf[i + add_len] = state.cmd_buf[i];
-                }
-                for (i, &b) in s_bytes.iter().enumerate() {
-                    state.cmd_buf[insert_at + i] = b;
-                }
-                state.cmd_len = old_len + add_len;
-                state.cursor_pos = insert_at + add_len;
-
-                // Redraw remainder of line
-                let redraw_len = state.cmd_len - state.cursor_pos + 1;
-                let start_pos = state.cursor_pos - 1;
-                let mut redraw_buf = [0u8; CMD_BUFFER_SIZE];
-                redraw_buf[..redraw_len].copy_from_slice(&state.cmd_buf[start_pos..state.cmd_len]);
-                drop(state);
-                if let Ok(s) = core::str::from_utf8(&redraw_buf[..redraw_len]) { framebuffer::print(s); }
-                for _ in 1..redraw_len { framebuffer::print_char('\x08'); }
-                framebuffer::show_cursor();
-                return;
-            } else if candidates.len() > 1 {
-                // List candidates
-                framebuffer::print_char('\n');
-                for name in &candidates { framebuffer::print(name); framebuffer::print("  "); }
-                framebuffer::print_char('\n');
-                // Reprint prompt and buffer
-                let prompt = crate::shell::get_prompt();
-                framebuffer::print(&prompt);
-                if let Ok(s) = core::str::from_utf8(&state.cmd_buf[..state.cmd_len]) { framebuffer::print(s); }
-                framebuffer::show_cursor();
-                return;
-            } else {
-                // No candidates - do nothing
-            }
-        }
-        c if c.is_ascii() && !c.is_control() => {
-            // Exit history mode on typing
-            state.history_pos = None;
-            
-            if state.cmd_len < CMD_BUFFER_SIZE - 1 {
-                let cursor_pos = state.cursor_pos;
-                let cmd_len = state.cmd_len;
-                
-                // Insert character at cursor position
-                if cursor_pos < cmd_len {
-                    // Shift characters right from cursor
-                    for i in (cursor_pos..cmd_len).rev() {
-                        state.cmd_buf[i + 1] = state.cmd_buf[i];
-                    }
-                }
-                
-                state.cmd_buf[cursor_pos] = c as u8;
-                state.cmd_len += 1;
-                state.cursor_pos += 1;
-                
-                // Redraw from cursor to end
-                let chars_to_redraw = state.cmd_len - state.cursor_pos + 1;
-                let start_pos = state.cursor_pos - 1;
-                let mut redraw_buf = [0u8; CMD_BUFFER_SIZE];
-                redraw_buf[..chars_to_redraw].copy_from_slice(&state.cmd_buf[start_pos..state.cmd_len]);
-                
-                drop(state);
-                
-                if let Ok(s) = core::str::from_utf8(&redraw_buf[..chars_to_redraw]) {
-                    framebuffer::print(s);
-                }
-                
-                // Move cursor back to correct position
-                for _ in 1..chars_to_redraw {
-                    framebuffer::print_char('\x08');
-                }
-            }
-        }
+        '\x08' => state.line.backspace(),
+        c if c.is_ascii() && !c.is_control() => state.line.insert(c),
         _ => {}
     }
     framebuffer::show_cursor();
 }
 
-fn handle_arrow_up() {
+/// Complete the word before the cursor (see `terminal::completions`)
+fn complete_word() {
+    // The candidates come from the VFS, looked up without the state lock
+    let line = STATE.lock().line.before_cursor();
+    let candidates = terminal::completions(&line);
     let mut state = STATE.lock();
     framebuffer::hide_cursor();
-    
-    if state.history_count == 0 {
-        drop(state);
-        framebuffer::show_cursor();
-        return;
-    }
-    
-    let next_pos = match state.history_pos {
-        None => 0,
-        Some(pos) if pos + 1 < state.history_count => pos + 1,
-        Some(_) => {
-            drop(state);
-            framebuffer::show_cursor();
-            return; // Already at oldest
-        }
-    };
-    
-    // Clear current line completely on screen
-    let old_len = state.cmd_len;
-    let old_cursor = state.cursor_pos;
-    
-    // Move cursor to end if not there
-    for _ in old_cursor..old_len {
-        framebuffer::print_char(' '); // Move forward
-    }
-    // Now backspace and erase everything
-    for _ in 0..old_len {
-        framebuffer::print_char('\x08'); // Move back
-        framebuffer::print_char(' ');    // Erase
-        framebuffer::print_char('\x08'); // Move back again
-    }
-    
-    // Load history entry
-    state.history_pos = Some(next_pos);
-    state.cmd_len = state.history_lens[next_pos];
-    state.cmd_buf = state.history[next_pos];
-    state.cursor_pos = state.cmd_len; // Move cursor to end
-    
-    // Copy to local buffer before dropping lock
-    let display_len = state.cmd_len;
-    let mut display_buf = [0u8; CMD_BUFFER_SIZE];
-    display_buf[..display_len].copy_from_slice(&state.cmd_buf[..display_len]);
+    let prompt = crate::shell::get_prompt();
+    state.line.complete(&prompt, &candidates);
     drop(state);
-    
-    // Display new command
-    if let Ok(s) = core::str::from_utf8(&display_buf[..display_len]) {
-        framebuffer::print(s);
-    }
+    framebuffer::show_cursor();
+}
+
+fn handle_arrow_up() {
+    let mut state = STATE.lock();
+    framebuffer::hide_cursor();
+    let KeyboardState { line, history, .. } = &mut *state;
+    line.history_prev(history);
     framebuffer::show_cursor();
 }
 
 fn handle_arrow_left() {
     let mut state = STATE.lock();
     framebuffer::hide_cursor();
-    
-    if state.cursor_pos > 0 {
-        state.cursor_pos -= 1;
-        drop(state);
-        // Move cursor left using backspace (without deleting)
-        framebuffer::print_char('\x08');
-        framebuffer::show_cursor();
-        return;
-    }
-    drop(state);
+    state.line.left();
     framebuffer::show_cursor();
 }
 
 fn handle_arrow_right() {
     let mut state = STATE.lock();
     framebuffer::hide_cursor();
-    
-    if state.cursor_pos < state.cmd_len {
-        // Get character at cursor to redraw it (moves cursor forward)
-        let c = state.cmd_buf[state.cursor_pos] as char;
-        state.cursor_pos += 1;
-        drop(state);
-        framebuffer::print_char(c);
-        framebuffer::show_cursor();
-        return;
-    }
-    drop(state);
+    state.line.right();
     framebuffer::show_cursor();
 }
 
 fn handle_arrow_down() {
     let mut state = STATE.lock();
     framebuffer::hide_cursor();
-    
-    let next_pos = match state.history_pos {
-        None => {
-            drop(state);
-            framebuffer::show_cursor();
-            return;
-        }
-        Some(0) => {
-            // Go to empty line - clear current completely
-            let old_len = state.cmd_len;
-            let old_cursor = state.cursor_pos;
-            
-            // Move to end
-            for _ in old_cursor..old_len {
-                framebuffer::print_char(' ');
-            }
-            // Erase everything
-            for _ in 0..old_len {
-                framebuffer::print_char('\x08');
-                framebuffer::print_char(' ');
-                framebuffer::print_char('\x08');
-            }
-            
-            state.history_pos = None;
-            state.cmd_len = 0;
-            state.cursor_pos = 0;
-            drop(state);
-            framebuffer::show_cursor();
-            return;
-        }
-        Some(pos) => pos - 1,
-    };
-    
-    // Clear current line completely on screen
-    let old_len = state.cmd_len;
-    let old_cursor = state.cursor_pos;
-    
-    // Move to end
-    for _ in old_cursor..old_len {
-        framebuffer::print_char(' ');
+    let KeyboardState { line, history, .. } = &mut *state;
+    line.history_next(history);
+    framebuffer::show_cursor();
+}
+
+fn execute_command_impl(cmd: &str) {
+    // Delegate to shell module
+    crate::shell::execute_command(cmd.trim());
+}
+
+/// Print command history, oldest first (called from shell)
+pub fn print_history() {
+    let state = STATE.lock();
+    if state.history.is_empty() {
+        framebuffer::print("No history yet\n");
+        return;
     }
-    // Erase everything
-    for _ in 0..old_len {
-        framebuffer::print_char('\x08');
-        framebuffer::print_char(' ');
-        framebuffer::print_char('\x08');
+    for (i, line) in state.history.iter().rev().enumerate() {
+        framebuffer::print(&alloc::format!("{:5}  {}\n", i + 1, line));
     }
-    
-    // Load history entry
-    state.history_pos = Some(next_pos);
-    state.cmd_len = state.history_lens[next_pos];
-    state.cmd_buf = state.history[next_pos];
-    state.cursor_pos = state.cmd_len; // Move cursor to end
-    
-    // Copy to local buffer before dropping lock
-    let display_len = state.cmd_len;
-    let mut display_buf = [0u8; CMD_BUFFER_SIZE];
-    display_buf[..display_len].copy_from_slice(&state.cmd_buf[..display_len]);
-    drop(state);
-    
-    // Display new command
-    if let Ok(s) = core::str::from_utf8(&display_buf[..display_len]) {
-        framebuffer::print(s);
+}
+
+/// Commands typed, oldest first
+pub fn history() -> Vec<String> {
+    STATE.lock().history.iter().rev().cloned().collect()
+}
+
+/// Replace the history with `lines`, oldest first; only the last
+/// HISTORY_SIZE are kept
+pub fn set_history(lines: Vec<String>) {
+    let mut state = STATE.lock();
+    state.history.clear();
+    // Up starts from the newest entry again
+    let text = state.line.text();
+    state.line.set(&text);
+    for line in lines {
+        terminal::add_history(&mut state.history, line);
     }
-    framebuffer::show_cursor();
 }
 
-#[allow(dead_code)]
-fn clear_current_line(state: &mut spin::MutexGuard<KeyboardState>) {
-    for _ in 0..state.cmd_len {
+/// Redraw the input line, which took `old_width` columns, as `text`
+fn rewrite_line(old_width: usize, text: &str) {
+    framebuffer::print_char('\r');
+    framebuffer::print(text);
+    let width = text.chars().count();
+    for _ in width..old_width {
+        framebuffer::print_char(' ');
+    }
+    for _ in width..old_width {
         framebuffer::print_char('\x08');
     }
 }
 
-fn execute_command_impl(cmd_bytes: &[u8]) {
-    let cmd = match core::str::from_utf8(cmd_bytes) {
-        Ok(s) => s.trim(),
-        Err(_) => {
-            framebuffer::print("Error: invalid UTF-8\n");
-            return;
+fn searching() -> bool {
+    STATE.lock().search.is_some()
+}
+
+/// Newest entry at or after `from` that contains `query`
+fn find_entry(history: &VecDeque<String>, query: &str, from: usize) -> Option<usize> {
+    (from..history.len()).find(|&i| history[i].contains(query))
+}
+
+/// Show the search line for the current query and match
+fn draw_search(state: &mut KeyboardState) {
+    let Some(search) = state.search.as_mut() else { return };
+    let found = search.found.map_or("", |i| state.history[i].as_str());
+    let failed = if search.found.is_none() && !search.query.is_empty() { "failed " } else { "" };
+    let text = alloc::format!("({}reverse-i-search)'{}': {}", failed, search.query, found);
+    rewrite_line(search.width, &text);
+    search.width = text.chars().count();
+}
+
+/// Ctrl+R: start a reverse search, or look for an older match
+fn search_history() {
+    let mut state = STATE.lock();
+    framebuffer::hide_cursor();
+    let next = state.search.as_ref().map(|search| (search.query.clone(), search.found.map_or(0, |i| i + 1)));
+    match next {
+        Some((query, from)) => {
+            let found = find_entry(&state.history, &query, from);
+            if let (Some(i), Some(search)) = (found, state.search.as_mut()) {
+                search.found = Some(i);
+            }
         }
-    };
-    
-    // Delegate to shell module
-    crate::shell::execute_command(cmd);
+        None => {
+            let saved = state.line.text();
+            let width = crate::shell::get_prompt().chars().count() + state.line.width();
+            state.search = Some(Search { query: String::new(), found: None, saved, width });
+        }
+    }
+    draw_search(&mut state);
+    drop(state);
+    framebuffer::show_cursor();
 }
 
-/// Print command history (called from shell)
-pub fn print_history() {
-    let state = STATE.lock();
-    if state.history_count == 0 {
-        framebuffer::print("No history yet\n");
-    } else {
-        framebuffer::print("Command history:\n");
-        for i in 0..state.history_count {
-            let cmd_bytes = &state.history[i][..state.history_lens[i]];
-            if let Ok(s) = core::str::from_utf8(cmd_bytes) {
-                framebuffer::print("  ");
-                // Print index (newest first)
-                let idx = state.history_count - i;
-                if idx < 10 {
-                    framebuffer::print_char(('0' as u8 + idx as u8) as char);
-                } else {
-                    framebuffer::print_char(('0' as u8 + (idx / 10) as u8) as char);
-                    framebuffer::print_char(('0' as u8 + (idx % 10) as u8) as char);
-                }
-                framebuffer::print(". ");
-                framebuffer::print(s);
-                framebuffer::print_char('\n');
+/// A key typed during the search: refine the query, run the match (Enter),
+/// keep it to edit (Esc) or give up (Ctrl+G, Ctrl+C)
+fn search_key(c: char) {
+    match c {
+        '\n' | '\r' => {
+            finish_search(false);
+            handle_char('\n');
+        }
+        '\x1b' => finish_search(false),
+        '\x07' | '\x03' => finish_search(true),
+        '\x08' | ' '..='~' => {
+            let mut state = STATE.lock();
+            let Some(search) = state.search.as_mut() else { return };
+            framebuffer::hide_cursor();
+            if c == '\x08' {
+                search.query.pop();
+            } else {
+                search.query.push(c);
+            }
+            let query = search.query.clone();
+            // A longer query still matches from where the search is; a
+            // shorter one starts over
+            let from = if c == '\x08' { 0 } else { search.found.unwrap_or(0) };
+            let found = find_entry(&state.history, &query, from);
+            if let Some(search) = state.search.as_mut() {
+                search.found = found;
             }
+            draw_search(&mut state);
+            drop(state);
+            framebuffer::show_cursor();
         }
+        _ => {}
     }
 }
 
+/// End the search with the line found in the buffer, or the line from
+/// before it if `abort`
+fn finish_search(abort: bool) {
+    let mut state = STATE.lock();
+    let Some(search) = state.search.take() else { return };
+    framebuffer::hide_cursor();
+    let KeyboardState { line, history, .. } = &mut *state;
+    match search.found {
+        Some(i) if !abort => line.recall(history, i),
+        _ => line.set(&search.saved),
+    }
+    let line = state.line.text();
+    drop(state);
+    let prompt = crate::shell::get_prompt();
+    rewrite_line(search.width, &alloc::format!("{}{}", prompt, line));
+    framebuffer::show_cursor();
+}
+
 /// Read a single key in blocking mode (for text editors)
 /// Returns Some(key) when key is pressed, None should not happen
 pub fn read_key_blocking() -> Option<char> {
@@ -749,18 +864,10 @@ pub fn read_key_blocking() -> Option<char> {
             
             // Process scancode through keyboard decoder
             let mut state = STATE.lock();
-            if let Some(ref mut kbd) = state.keyboard {
-                if let Ok(Some(key_event)) = kbd.add_byte(scancode) {
-                    if let Some(key) = kbd.process_keyevent(key_event) {
-                        match key {
-                            DecodedKey::Unicode(c) => return Some(c),
-                            DecodedKey::RawKey(_) => {
-                                // Ignore raw keys for now
-                                continue;
-                            }
-                        }
-                    }
-                }
+            match decode(&mut state, scancode) {
+                Some(DecodedKey::Unicode(c)) => return Some(c),
+                // Ignore raw keys for now
+                Some(DecodedKey::RawKey(_)) | None => continue,
             }
         }
         
@@ -777,6 +884,8 @@ pub enum EditorKey {
     ArrowDown,
     ArrowLeft,
     ArrowRight,
+    CtrlArrowLeft,
+    CtrlArrowRight,
     PageUp,
     PageDown,
     Home,
@@ -786,45 +895,56 @@ pub enum EditorKey {
 
 /// Read a key for text editor (handles navigation keys)
 pub fn read_editor_key_blocking() -> Option<EditorKey> {
+    loop {
+        if let Some(key) = try_read_editor_key() {
+            return Some(key);
+        }
+
+        // Yield CPU while waiting
+        core::hint::spin_loop();
+    }
+}
+
+/// Next key typed, with the navigation keys, or None if there is none
+/// yet (the TTY line discipline)
+pub fn try_read_editor_key() -> Option<EditorKey> {
     loop {
         // Dequeue scancode from atomic ring buffer
         let read = SCANCODE_READ.load(Ordering::Relaxed);
         let write = SCANCODE_WRITE.load(Ordering::Acquire);
-        
-        if read != write {
-            let scancode = SCANCODE_BUF[read].load(Ordering::Relaxed);
-            SCANCODE_READ.store((read + 1) % SCANCODE_BUFFER_SIZE, Ordering::Release);
-            
-            // Process scancode through keyboard decoder
-            let mut state = STATE.lock();
-            if let Some(ref mut kbd) = state.keyboard {
-                if let Ok(Some(key_event)) = kbd.add_byte(scancode) {
-                    if let Some(key) = kbd.process_keyevent(key_event) {
-                        match key {
-                            DecodedKey::Unicode(c) => return Some(EditorKey::Char(c)),
-                            DecodedKey::RawKey(raw) => {
-                                use pc_keyboard::KeyCode;
-                                match raw {
-                                    KeyCode::ArrowUp => return Some(EditorKey::ArrowUp),
-                                    KeyCode::ArrowDown => return Some(EditorKey::ArrowDown),
-                                    KeyCode::ArrowLeft => return Some(EditorKey::ArrowLeft),
-                                    KeyCode::ArrowRight => return Some(EditorKey::ArrowRight),
-                                    KeyCode::PageUp => return Some(EditorKey::PageUp),
-                                    KeyCode::PageDown => return Some(EditorKey::PageDown),
-                                    KeyCode::Home => return Some(EditorKey::Home),
-                                    KeyCode::End => return Some(EditorKey::End),
-                                    KeyCode::Delete => return Some(EditorKey::Delete),
-                                    _ => continue, // Ignore other raw keys
-                                }
-                            }
-                        }
-                    }
+        if read == write {
+            return None;
+        }
+        let scancode = SCANCODE_BUF[read].load(Ordering::Relaxed);
+        SCANCODE_READ.store((read + 1) % SCANCODE_BUFFER_SIZE, Ordering::Release);
+
+        // The decoder ignores Ctrl; decode() tracks it to deliver control characters
+        let mut state = STATE.lock();
+        match decode(&mut state, scancode) {
+            Some(DecodedKey::Unicode(c)) if modifier(MOD_CTRL) && c.is_ascii_alphabetic() => {
+                let ctl = (c.to_ascii_lowercase() as u8) - b'a' + 1;
+                return Some(EditorKey::Char(ctl as char));
+            }
+            Some(DecodedKey::Unicode(c)) => return Some(EditorKey::Char(c)),
+            Some(DecodedKey::RawKey(raw)) => {
+                use pc_keyboard::KeyCode;
+                match raw {
+                    KeyCode::ArrowUp => return Some(EditorKey::ArrowUp),
+                    KeyCode::ArrowDown => return Some(EditorKey::ArrowDown),
+                    KeyCode::ArrowLeft if modifier(MOD_CTRL) => return Some(EditorKey::CtrlArrowLeft),
+                    KeyCode::ArrowRight if modifier(MOD_CTRL) => return Some(EditorKey::CtrlArrowRight),
+                    KeyCode::ArrowLeft => return Some(EditorKey::ArrowLeft),
+                    KeyCode::ArrowRight => return Some(EditorKey::ArrowRight),
+                    KeyCode::PageUp => return Some(EditorKey::PageUp),
+                    KeyCode::PageDown => return Some(EditorKey::PageDown),
+                    KeyCode::Home => return Some(EditorKey::Home),
+                    KeyCode::End => return Some(EditorKey::End),
+                    KeyCode::Delete => return Some(EditorKey::Delete),
+                    _ => continue, // Ignore other raw keys
                 }
             }
+            None => {}
         }
-        
-        // Yield CPU while waiting
-        core::hint::spin_loop();
     }
 }
 
@@ -839,14 +959,8 @@ pub fn try_read_key() -> Option<char> {
         
         // Process scancode
         let mut state = STATE.lock();
-        if let Some(ref mut kbd) = state.keyboard {
-            if let Ok(Some(key_event)) = kbd.add_byte(scancode) {
-                if let Some(key) = kbd.process_keyevent(key_event) {
-                    if let DecodedKey::Unicode(c) = key {
-                        return Some(c);
-                    }
-                }
-            }
+        if let Some(DecodedKey::Unicode(c)) = decode(&mut state, scancode) {
+            return Some(c);
         }
     }
     
@@ -3,9 +3,18 @@
 
 pub mod vga_buffer;
 pub mod keyboard;
+pub mod mouse;
 pub mod framebuffer;
 pub mod timer;
 pub mod serial;
+pub mod speaker;
+pub mod fw_cfg;
+pub mod acpi;
+pub mod apic;
+pub mod hpet;
+pub mod pci;
+pub mod block;
+pub mod ahci;
 
 const VGA_BUFFER: *mut u16 = 0xB8000 as *mut u16;
 const VGA_WIDTH: usize = 80;
@@ -0,0 +1,292 @@
+//! PS/2 mouse driver (auxiliary port of the 8042, IRQ 12)
+//!
+//! The interrupt handler assembles the 3-byte movement packets, queues
+//! them as `MouseEvent`s in a lock-free ring and tracks an absolute pointer
+//! position clamped to the screen. Programs read the events from
+//! /dev/mouse; the main loop moves the on-screen pointer, when it is shown,
+//! with `update_pointer`.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use x86_64::instructions::port::Port;
+use crate::drivers::framebuffer;
+
+pub const MOUSE_IRQ: u8 = 12;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 0x01;
+const STATUS_INPUT_FULL: u8 = 0x02;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_ENABLE_AUX: u8 = 0xA8;
+/// Send the next data byte to the mouse instead of the keyboard
+const CMD_WRITE_AUX: u8 = 0xD4;
+
+const CONFIG_IRQ12_ENABLED: u8 = 0x02;
+const CONFIG_AUX_CLOCK_DISABLED: u8 = 0x20;
+
+const MOUSE_SET_DEFAULTS: u8 = 0xF6;
+const MOUSE_ENABLE_REPORTING: u8 = 0xF4;
+const MOUSE_ACK: u8 = 0xFA;
+
+/// Packet byte 0: always set, used to resynchronize
+const PACKET_SYNC: u8 = 0x08;
+const PACKET_X_SIGN: u8 = 0x10;
+const PACKET_Y_SIGN: u8 = 0x20;
+const PACKET_OVERFLOW: u8 = 0xC0;
+
+/// Bytes per event read from /dev/mouse
+pub const EVENT_SIZE: usize = 6;
+
+pub const BUTTON_LEFT: u8 = 0x01;
+pub const BUTTON_RIGHT: u8 = 0x02;
+pub const BUTTON_MIDDLE: u8 = 0x04;
+
+/// One movement or button change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// `BUTTON_*` bits held after the event
+    pub buttons: u8,
+    pub dx: i16,
+    /// Positive is down, as on the screen
+    pub dy: i16,
+}
+
+impl MouseEvent {
+    /// /dev/mouse record: buttons, a reserved byte, dx and dy little-endian
+    pub fn to_bytes(self) -> [u8; EVENT_SIZE] {
+        let dx = self.dx.to_le_bytes();
+        let dy = self.dy.to_le_bytes();
+        [self.buttons, 0, dx[0], dx[1], dy[0], dy[1]]
+    }
+
+    fn pack(self) -> u64 {
+        (self.buttons as u64) | ((self.dx as u16 as u64) << 16) | ((self.dy as u16 as u64) << 32)
+    }
+
+    fn unpack(value: u64) -> Self {
+        MouseEvent {
+            buttons: value as u8,
+            dx: (value >> 16) as u16 as i16,
+            dy: (value >> 32) as u16 as i16,
+        }
+    }
+}
+
+// ============================================================================
+// ISR STATE (atomics only)
+// ============================================================================
+
+const EVENT_BUFFER_SIZE: usize = 64;
+
+static EVENTS: [AtomicU64; EVENT_BUFFER_SIZE] = {
+    const INIT: AtomicU64 = AtomicU64::new(0);
+    [INIT; EVENT_BUFFER_SIZE]
+};
+static EVENT_READ: AtomicUsize = AtomicUsize::new(0);
+static EVENT_WRITE: AtomicUsize = AtomicUsize::new(0);
+
+/// Packet being assembled: bytes so far, packed low byte first
+static PACKET: AtomicU32 = AtomicU32::new(0);
+static PACKET_LEN: AtomicUsize = AtomicUsize::new(0);
+
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// Pointer position in pixels and the screen size it is clamped to
+static POINTER_X: AtomicU32 = AtomicU32::new(0);
+static POINTER_Y: AtomicU32 = AtomicU32::new(0);
+static SCREEN_W: AtomicU32 = AtomicU32::new(0);
+static SCREEN_H: AtomicU32 = AtomicU32::new(0);
+/// `BUTTON_*` bits held, as of the last packet
+static BUTTONS: AtomicU8 = AtomicU8::new(0);
+static POINTER_MOVED: AtomicBool = AtomicBool::new(false);
+static POINTER_SHOWN: AtomicBool = AtomicBool::new(false);
+
+fn wait_input_ready() -> bool {
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    for _ in 0..100_000 {
+        if unsafe { status.read() } & STATUS_INPUT_FULL == 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+fn wait_output_ready() -> bool {
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    for _ in 0..100_000 {
+        if unsafe { status.read() } & STATUS_OUTPUT_FULL != 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+fn controller_command(command: u8) -> bool {
+    if !wait_input_ready() {
+        return false;
+    }
+    unsafe { Port::<u8>::new(COMMAND_PORT).write(command) };
+    true
+}
+
+/// Send `command` to the mouse and wait for its acknowledgement
+fn mouse_command(command: u8) -> bool {
+    if !controller_command(CMD_WRITE_AUX) || !wait_input_ready() {
+        return false;
+    }
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+    unsafe { data.write(command) };
+    wait_output_ready() && unsafe { data.read() } == MOUSE_ACK
+}
+
+/// Probe and set up the mouse; call after `keyboard::init`, before
+/// interrupts are enabled (replies are polled from the shared data port)
+pub fn init() -> bool {
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+
+    if !controller_command(CMD_ENABLE_AUX) || !controller_command(CMD_READ_CONFIG) || !wait_output_ready() {
+        return false;
+    }
+    let mut config = unsafe { data.read() };
+    // Interrupts stay off until `enable_irq`
+    config &= !(CONFIG_AUX_CLOCK_DISABLED | CONFIG_IRQ12_ENABLED);
+    if !controller_command(CMD_WRITE_CONFIG) || !wait_input_ready() {
+        return false;
+    }
+    unsafe { data.write(config) };
+
+    if !mouse_command(MOUSE_SET_DEFAULTS) || !mouse_command(MOUSE_ENABLE_REPORTING) {
+        crate::serial_println!("[MOUSE] No PS/2 mouse");
+        return false;
+    }
+
+    let fb = framebuffer::get_info();
+    SCREEN_W.store(fb.width as u32, Ordering::Relaxed);
+    SCREEN_H.store(fb.height as u32, Ordering::Relaxed);
+    POINTER_X.store(fb.width as u32 / 2, Ordering::Relaxed);
+    POINTER_Y.store(fb.height as u32 / 2, Ordering::Relaxed);
+    PRESENT.store(true, Ordering::Release);
+    crate::serial_println!("[MOUSE] PS/2 mouse ready");
+    true
+}
+
+/// Unmask IRQ 12 and turn on the controller's mouse interrupt
+pub fn enable_irq() {
+    if !PRESENT.load(Ordering::Acquire) {
+        return;
+    }
+    if let Err(e) = crate::irq::register(MOUSE_IRQ, "mouse", crate::drivers::keyboard::controller_interrupt) {
+        crate::serial_println!("[MOUSE] {}", e);
+    }
+    crate::interrupts::enable_irq(MOUSE_IRQ);
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+    if !controller_command(CMD_READ_CONFIG) || !wait_output_ready() {
+        return;
+    }
+    let config = unsafe { data.read() } | CONFIG_IRQ12_ENABLED;
+    if controller_command(CMD_WRITE_CONFIG) && wait_input_ready() {
+        unsafe { data.write(config) };
+    }
+}
+
+/// Whether a mouse answered at boot
+pub fn is_present() -> bool {
+    PRESENT.load(Ordering::Acquire)
+}
+
+/// Feed one byte from the auxiliary port (interrupt context)
+pub fn handle_byte(byte: u8) {
+    let len = PACKET_LEN.load(Ordering::Relaxed);
+    // Byte 0 always has bit 3 set; drop bytes until one does
+    if len == 0 && byte & PACKET_SYNC == 0 {
+        return;
+    }
+    let packet = PACKET.load(Ordering::Relaxed) | (byte as u32) << (len * 8);
+    if len < 2 {
+        PACKET.store(packet, Ordering::Relaxed);
+        PACKET_LEN.store(len + 1, Ordering::Relaxed);
+        return;
+    }
+    PACKET.store(0, Ordering::Relaxed);
+    PACKET_LEN.store(0, Ordering::Relaxed);
+
+    let flags = packet as u8;
+    if flags & PACKET_OVERFLOW != 0 {
+        return;
+    }
+    let mut dx = ((packet >> 8) & 0xFF) as i16;
+    let mut dy = ((packet >> 16) & 0xFF) as i16;
+    if flags & PACKET_X_SIGN != 0 {
+        dx -= 256;
+    }
+    if flags & PACKET_Y_SIGN != 0 {
+        dy -= 256;
+    }
+    // The mouse counts up as it moves away from the user
+    let event = MouseEvent { buttons: flags & 0x07, dx, dy: -dy };
+    BUTTONS.store(event.buttons, Ordering::Relaxed);
+    move_pointer(event.dx, event.dy);
+
+    let write = EVENT_WRITE.load(Ordering::Relaxed);
+    let next = (write + 1) % EVENT_BUFFER_SIZE;
+    if next != EVENT_READ.load(Ordering::Acquire) {
+        EVENTS[write].store(event.pack(), Ordering::Relaxed);
+        EVENT_WRITE.store(next, Ordering::Release);
+    }
+    // If the buffer is full the event is dropped
+}
+
+fn move_pointer(dx: i16, dy: i16) {
+    let clamp = |pos: &AtomicU32, delta: i16, limit: &AtomicU32| {
+        let max = limit.load(Ordering::Relaxed).saturating_sub(1) as i64;
+        let value = (pos.load(Ordering::Relaxed) as i64 + delta as i64).clamp(0, max.max(0));
+        pos.store(value as u32, Ordering::Relaxed);
+    };
+    clamp(&POINTER_X, dx, &SCREEN_W);
+    clamp(&POINTER_Y, dy, &SCREEN_H);
+    POINTER_MOVED.store(true, Ordering::Release);
+}
+
+/// Take the oldest queued event
+pub fn read_event() -> Option<MouseEvent> {
+    let read = EVENT_READ.load(Ordering::Relaxed);
+    if read == EVENT_WRITE.load(Ordering::Acquire) {
+        return None;
+    }
+    let event = MouseEvent::unpack(EVENTS[read].load(Ordering::Relaxed));
+    EVENT_READ.store((read + 1) % EVENT_BUFFER_SIZE, Ordering::Release);
+    Some(event)
+}
+
+/// Pointer position in pixels
+pub fn position() -> (usize, usize) {
+    (POINTER_X.load(Ordering::Relaxed) as usize, POINTER_Y.load(Ordering::Relaxed) as usize)
+}
+
+/// `BUTTON_*` bits currently held
+pub fn buttons() -> u8 {
+    BUTTONS.load(Ordering::Relaxed)
+}
+
+/// Show or hide the pointer sprite; false if the console cannot draw it
+pub fn set_pointer_visible(visible: bool) -> bool {
+    POINTER_SHOWN.store(visible, Ordering::Relaxed);
+    framebuffer::set_pointer(if visible { Some(position()) } else { None })
+}
+
+/// Redraw the pointer if it moved; called from the main loop
+pub fn update_pointer() {
+    if POINTER_MOVED.swap(false, Ordering::Acquire)
+        && POINTER_SHOWN.load(Ordering::Relaxed)
+        && !framebuffer::set_pointer(Some(position()))
+    {
+        // Console busy: try again next time round
+        POINTER_MOVED.store(true, Ordering::Release);
+    }
+}
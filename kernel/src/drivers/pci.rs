@@ -0,0 +1,400 @@
+//! PCI configuration space and the devices on the bus
+//!
+//! Configuration space is reached through the memory-mapped ECAM window
+//! the ACPI MCFG table describes, or through the legacy ports 0xCF8/0xCFC
+//! when there is none (or it lies outside the HHDM, above 4 GiB). `init`
+//! walks every bus once and keeps what it finds: ids, class, interrupt and
+//! the base address registers (BARs), sized by the usual write-all-ones
+//! probe. Drivers look their hardware up in `devices`; /proc/pci and
+//! `lspci -v` show the list.
+//!
+//! Only segment 0 is scanned; nothing ospabOS runs on has more.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+/// CONFIG_ADDRESS: the access goes to configuration space
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+// Configuration header offsets
+const VENDOR_ID: u16 = 0x00;
+const DEVICE_ID: u16 = 0x02;
+pub const COMMAND: u16 = 0x04;
+const REVISION: u16 = 0x08;
+const PROG_IF: u16 = 0x09;
+const SUBCLASS: u16 = 0x0A;
+const CLASS: u16 = 0x0B;
+const HEADER_TYPE: u16 = 0x0E;
+const BAR0: u16 = 0x10;
+const INTERRUPT_LINE: u16 = 0x3C;
+const INTERRUPT_PIN: u16 = 0x3D;
+
+/// Command register: respond to I/O and memory accesses
+pub const COMMAND_IO: u16 = 1 << 0;
+pub const COMMAND_MEMORY: u16 = 1 << 1;
+/// Command register: the device may master the bus (DMA)
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+/// Header type: more than one function at this device number
+const MULTIFUNCTION: u8 = 0x80;
+/// BARs in a type 0 (device) and type 1 (bridge) header
+const DEVICE_BARS: usize = 6;
+const BRIDGE_BARS: usize = 2;
+
+/// Where a function sits on segment 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// What a base address register decodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    Memory { address: u64, size: u64, prefetchable: bool },
+    Io { port: u16, size: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub address: Address,
+    pub vendor: u16,
+    pub device: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    /// IRQ line the firmware routed the interrupt to, 0xFF if none
+    pub interrupt_line: u8,
+    /// 1-4 for INTA#-INTD#, 0 if the function raises no interrupt
+    pub interrupt_pin: u8,
+    pub bars: [Option<Bar>; DEVICE_BARS],
+}
+
+impl Device {
+    /// Let the device decode its memory BARs and master the bus
+    pub fn enable_bus_master(&self) {
+        let command = read_u16(self.address, COMMAND);
+        write_u16(self.address, COMMAND, command | COMMAND_MEMORY | COMMAND_BUS_MASTER);
+    }
+}
+
+/// The ECAM window of segment 0, through the HHDM
+#[derive(Clone, Copy)]
+struct Ecam {
+    base: u64,
+    start_bus: u8,
+    end_bus: u8,
+}
+
+impl Ecam {
+    fn register(&self, address: Address, offset: u16) -> Option<u64> {
+        if address.bus < self.start_bus || address.bus > self.end_bus {
+            return None;
+        }
+        let function = ((address.bus - self.start_bus) as u64) << 20
+            | (address.device as u64) << 15
+            | (address.function as u64) << 12;
+        Some(self.base + function + (offset & 0xFFC) as u64)
+    }
+}
+
+/// How configuration space is reached; the lock also keeps the two port
+/// accesses of the legacy mechanism together
+static CONFIG: Mutex<Option<Ecam>> = Mutex::new(None);
+
+static DEVICES: Mutex<Vec<Device>> = Mutex::new(Vec::new());
+
+/// Read the dword at `offset` (rounded down to a multiple of 4)
+pub fn read_u32(address: Address, offset: u16) -> u32 {
+    let config = CONFIG.lock();
+    if let Some(register) = config.and_then(|ecam| ecam.register(address, offset)) {
+        return unsafe { core::ptr::read_volatile(register as *const u32) };
+    }
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(port_address(address, offset));
+        Port::<u32>::new(CONFIG_DATA).read()
+    }
+}
+
+/// Write the dword at `offset` (rounded down to a multiple of 4)
+pub fn write_u32(address: Address, offset: u16, value: u32) {
+    let config = CONFIG.lock();
+    if let Some(register) = config.and_then(|ecam| ecam.register(address, offset)) {
+        unsafe { core::ptr::write_volatile(register as *mut u32, value) };
+        return;
+    }
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(port_address(address, offset));
+        Port::<u32>::new(CONFIG_DATA).write(value);
+    }
+}
+
+pub fn read_u16(address: Address, offset: u16) -> u16 {
+    (read_u32(address, offset) >> ((offset & 2) * 8)) as u16
+}
+
+pub fn read_u8(address: Address, offset: u16) -> u8 {
+    (read_u32(address, offset) >> ((offset & 3) * 8)) as u8
+}
+
+/// Write the word at `offset`, keeping the other half of its dword
+pub fn write_u16(address: Address, offset: u16, value: u16) {
+    let shift = (offset & 2) * 8;
+    let dword = read_u32(address, offset) & !(0xFFFF << shift);
+    write_u32(address, offset, dword | (value as u32) << shift);
+}
+
+fn port_address(address: Address, offset: u16) -> u32 {
+    CONFIG_ENABLE
+        | (address.bus as u32) << 16
+        | (address.device as u32) << 11
+        | (address.function as u32) << 8
+        | (offset & 0xFC) as u32
+}
+
+/// Write all ones to the BAR at `offset` and read back which bits stick
+fn probe(address: Address, offset: u16) -> u32 {
+    let original = read_u32(address, offset);
+    write_u32(address, offset, !0);
+    let mask = read_u32(address, offset);
+    write_u32(address, offset, original);
+    mask
+}
+
+/// The first `count` BARs of the function at `address`
+fn read_bars(address: Address, count: usize) -> [Option<Bar>; DEVICE_BARS] {
+    let mut bars = [None; DEVICE_BARS];
+    // Decoding stays off while a BAR holds all ones
+    let command = read_u16(address, COMMAND);
+    write_u16(address, COMMAND, command & !(COMMAND_IO | COMMAND_MEMORY));
+    let mut i = 0;
+    while i < count {
+        let offset = BAR0 + i as u16 * 4;
+        let low = read_u32(address, offset);
+        if low & 1 != 0 {
+            let mask = probe(address, offset) & !0x3;
+            if mask != 0 {
+                let size = (!(mask | 0xFFFF_0000)).wrapping_add(1);
+                bars[i] = Some(Bar::Io { port: (low & !0x3) as u16, size });
+            }
+            i += 1;
+            continue;
+        }
+        let wide = (low >> 1) & 0b11 == 0b10 && i + 1 < count;
+        // A 32-bit BAR is as if its upper half were all ones
+        let (high, high_mask) =
+            if wide { (read_u32(address, offset + 4), probe(address, offset + 4)) } else { (0, !0) };
+        let mask = (high_mask as u64) << 32 | (probe(address, offset) & !0xF) as u64;
+        if mask as u32 != 0 || (wide && high_mask != 0) {
+            bars[i] = Some(Bar::Memory {
+                address: (high as u64) << 32 | (low & !0xF) as u64,
+                size: (!mask).wrapping_add(1),
+                prefetchable: low & 0x8 != 0,
+            });
+        }
+        i += if wide { 2 } else { 1 };
+    }
+    write_u16(address, COMMAND, command);
+    bars
+}
+
+fn read_device(address: Address) -> Device {
+    let bars = match read_u8(address, HEADER_TYPE) & !MULTIFUNCTION {
+        0 => read_bars(address, DEVICE_BARS),
+        1 => read_bars(address, BRIDGE_BARS),
+        _ => [None; DEVICE_BARS],
+    };
+    Device {
+        address,
+        vendor: read_u16(address, VENDOR_ID),
+        device: read_u16(address, DEVICE_ID),
+        class: read_u8(address, CLASS),
+        subclass: read_u8(address, SUBCLASS),
+        prog_if: read_u8(address, PROG_IF),
+        revision: read_u8(address, REVISION),
+        interrupt_line: read_u8(address, INTERRUPT_LINE),
+        interrupt_pin: read_u8(address, INTERRUPT_PIN),
+        bars,
+    }
+}
+
+/// Every function on the buses `buses`
+fn scan(buses: core::ops::RangeInclusive<u8>) -> Vec<Device> {
+    let mut devices = Vec::new();
+    for bus in buses {
+        for device in 0..32 {
+            let first = Address { bus, device, function: 0 };
+            if read_u16(first, VENDOR_ID) == 0xFFFF {
+                continue;
+            }
+            let functions = if read_u8(first, HEADER_TYPE) & MULTIFUNCTION != 0 { 8 } else { 1 };
+            for function in 0..functions {
+                let address = Address { bus, device, function };
+                if read_u16(address, VENDOR_ID) != 0xFFFF {
+                    devices.push(read_device(address));
+                }
+            }
+        }
+    }
+    devices
+}
+
+/// Pick the configuration mechanism and enumerate the bus
+pub fn init() {
+    let hhdm = crate::boot::hhdm_offset().unwrap_or(0);
+    // Only the first 4 GiB are sure to be in the HHDM
+    let ecam = crate::drivers::acpi::mcfg()
+        .into_iter()
+        .find(|region| region.segment == 0 && region.start_bus <= region.end_bus)
+        .filter(|region| {
+            let buses = (region.end_bus - region.start_bus) as u64 + 1;
+            hhdm != 0 && region.address + (buses << 20) <= 1 << 32
+        })
+        .map(|region| Ecam { base: region.address + hhdm, start_bus: region.start_bus, end_bus: region.end_bus });
+    *CONFIG.lock() = ecam;
+
+    let buses = ecam.map_or(0..=255, |ecam| ecam.start_bus..=ecam.end_bus);
+    let devices = scan(buses);
+    crate::serial_println!(
+        "[PCI] {} functions, configuration space through {}",
+        devices.len(),
+        if ecam.is_some() { "ECAM" } else { "ports 0xCF8/0xCFC" }
+    );
+    *DEVICES.lock() = devices;
+    crate::ipc::bus::publish(crate::ipc::message::ServiceEvent::Ready("pci"));
+}
+
+/// Every function found by `init`, in bus order
+pub fn devices() -> Vec<Device> {
+    DEVICES.lock().clone()
+}
+
+const VENDORS: &[(u16, &str)] = &[
+    (0x8086, "Intel Corporation"),
+    (0x1022, "Advanced Micro Devices, Inc."),
+    (0x10DE, "NVIDIA Corporation"),
+    (0x10EC, "Realtek Semiconductor Co., Ltd."),
+    (0x1234, "QEMU"),
+    (0x15AD, "VMware"),
+    (0x1AF4, "Red Hat, Inc. (virtio)"),
+    (0x1B36, "Red Hat, Inc."),
+    (0x80EE, "InnoTek (VirtualBox)"),
+];
+
+/// (class, subclass or None for any), most specific first
+const CLASSES: &[(u8, Option<u8>, &str)] = &[
+    (0x01, Some(0x01), "IDE interface"),
+    (0x01, Some(0x06), "SATA controller"),
+    (0x01, Some(0x08), "Non-Volatile memory controller"),
+    (0x01, None, "Mass storage controller"),
+    (0x02, Some(0x00), "Ethernet controller"),
+    (0x02, None, "Network controller"),
+    (0x03, Some(0x00), "VGA compatible controller"),
+    (0x03, None, "Display controller"),
+    (0x04, Some(0x03), "Audio device"),
+    (0x04, None, "Multimedia controller"),
+    (0x05, None, "Memory controller"),
+    (0x06, Some(0x00), "Host bridge"),
+    (0x06, Some(0x01), "ISA bridge"),
+    (0x06, Some(0x04), "PCI bridge"),
+    (0x06, None, "Bridge"),
+    (0x07, None, "Communication controller"),
+    (0x08, None, "System peripheral"),
+    (0x0C, Some(0x03), "USB controller"),
+    (0x0C, Some(0x05), "SMBus"),
+    (0x0C, None, "Serial bus controller"),
+];
+
+pub fn vendor_name(vendor: u16) -> &'static str {
+    VENDORS.iter().find(|(id, _)| *id == vendor).map_or("Unknown vendor", |(_, name)| name)
+}
+
+pub fn class_name(class: u8, subclass: u8) -> &'static str {
+    CLASSES
+        .iter()
+        .find(|(c, s, _)| *c == class && s.is_none_or(|s| s == subclass))
+        .map_or("Unclassified device", |(_, _, name)| name)
+}
+
+/// A region size as lspci writes it: 4K, 16M, ...
+fn size_text(size: u64) -> String {
+    for (unit, suffix) in [(1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")] {
+        if size >= unit && size % unit == 0 {
+            return format!("{}{}", size / unit, suffix);
+        }
+    }
+    format!("{}", size)
+}
+
+/// One line per function, like lspci; with `verbose` the interrupt and
+/// BARs below each
+pub fn listing(verbose: bool) -> String {
+    let mut text = String::new();
+    for device in devices() {
+        text += &format!(
+            "{} {} [{:02x}{:02x}]: {} [{:04x}:{:04x}] (rev {:02x})\n",
+            device.address,
+            class_name(device.class, device.subclass),
+            device.class,
+            device.subclass,
+            vendor_name(device.vendor),
+            device.vendor,
+            device.device,
+            device.revision
+        );
+        if !verbose {
+            continue;
+        }
+        if let pin @ 1..=4 = device.interrupt_pin {
+            text += &format!("\tInterrupt: pin {} routed to IRQ {}\n", (b'A' + pin - 1) as char, device.interrupt_line);
+        }
+        for (i, bar) in device.bars.iter().enumerate() {
+            match bar {
+                Some(Bar::Memory { address, size, prefetchable }) => {
+                    text += &format!(
+                        "\tRegion {}: Memory at {:x} ({}prefetchable) [size={}]\n",
+                        i,
+                        address,
+                        if *prefetchable { "" } else { "non-" },
+                        size_text(*size)
+                    );
+                }
+                Some(Bar::Io { port, size }) => {
+                    text += &format!("\tRegion {}: I/O ports at {:x} [size={}]\n", i, port, size_text(*size as u64));
+                }
+                None => {}
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn names_classes_from_most_specific() {
+        assert_eq!(class_name(0x01, 0x06), "SATA controller");
+        assert_eq!(class_name(0x01, 0x04), "Mass storage controller");
+        assert_eq!(class_name(0xFF, 0x00), "Unclassified device");
+        assert_eq!(size_text(4096), "4K");
+        assert_eq!(size_text(16 << 20), "16M");
+        assert_eq!(size_text(32), "32");
+    }
+}
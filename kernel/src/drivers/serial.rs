@@ -6,6 +6,7 @@
 use x86_64::instructions::port::Port;
 use spin::Mutex;
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 const SERIAL_PORT: u16 = 0x3F8; // COM1
 
@@ -101,16 +102,40 @@ impl Write for SerialPort {
 /// Global serial port instance
 static SERIAL: Mutex<SerialPort> = Mutex::new(SerialPort::new());
 
+/// Set while a binary transfer owns the port; text output is dropped
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
 /// Initialize the serial port
 pub fn init() {
     SERIAL.lock().init();
 }
 
-/// Write string to serial port
+/// Write string to serial port, and to the kernel log
 pub fn write(s: &str) {
+    crate::klog::write(s);
+    if RAW_MODE.load(Ordering::Relaxed) || !crate::sysctl::log_to_serial() {
+        return;
+    }
     SERIAL.lock().write_str(s);
 }
 
+/// Hand the port to a binary protocol (or give it back to logging)
+pub fn set_raw_mode(enabled: bool) {
+    RAW_MODE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_raw_mode() -> bool {
+    RAW_MODE.load(Ordering::Relaxed)
+}
+
+/// Write bytes untranslated, even in raw mode
+pub fn write_bytes(bytes: &[u8]) {
+    let mut serial = SERIAL.lock();
+    for &byte in bytes {
+        serial.send_byte(byte);
+    }
+}
+
 /// Write formatted string to serial port
 #[macro_export]
 macro_rules! serial_print {
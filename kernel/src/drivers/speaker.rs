@@ -0,0 +1,144 @@
+//! PC speaker
+//!
+//! The speaker plays the square wave of PIT channel 2, gated on through
+//! port 0x61, so it sounds one tone at a time and cannot play samples.
+//! `play` queues notes and returns at once: a kernel timer moves on to the
+//! next note when one is done, at tick (10 ms) resolution.
+//!
+//! Notes written to /dev/audio or passed to sys_audio_write are 4-byte
+//! records: frequency in Hz, then duration in ms, both u16 little-endian.
+//! A frequency of 0 is a rest.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const PIT_FREQUENCY: u32 = 1193182;
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+/// Channel 2, low then high byte, square wave
+const PIT_CHANNEL2_SQUARE: u8 = 0xB6;
+/// Port 0x61: bit 0 gates channel 2, bit 1 connects it to the speaker
+const SPEAKER_PORT: u16 = 0x61;
+const SPEAKER_ON: u8 = 0b11;
+
+/// Lowest and highest frequency `tone` accepts
+pub const MIN_FREQ: u32 = 20;
+pub const MAX_FREQ: u32 = 20_000;
+/// Size of a note record in /dev/audio
+pub const NOTE_SIZE: usize = 4;
+/// Notes that may wait in the queue; more are dropped
+const MAX_QUEUED: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Note {
+    /// Hz, or 0 for a rest
+    pub freq: u32,
+    pub ms: u32,
+}
+
+impl Note {
+    pub const fn new(freq: u32, ms: u32) -> Self {
+        Self { freq, ms }
+    }
+}
+
+struct Player {
+    queue: VecDeque<Note>,
+    /// A note is sounding and a timer will start the next one
+    playing: bool,
+    /// Bumped by `stop`, so a timer left over from before ignores itself
+    generation: u64,
+}
+
+static PLAYER: Mutex<Player> = Mutex::new(Player { queue: VecDeque::new(), playing: false, generation: 0 });
+
+/// Sound `freq` Hz until told otherwise
+fn tone(freq: u32) {
+    let divisor = (PIT_FREQUENCY / freq).clamp(1, u16::MAX as u32) as u16;
+    unsafe {
+        Port::<u8>::new(PIT_COMMAND).write(PIT_CHANNEL2_SQUARE);
+        let mut data = Port::<u8>::new(PIT_CHANNEL2);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+        let mut speaker = Port::<u8>::new(SPEAKER_PORT);
+        let value = speaker.read();
+        speaker.write(value | SPEAKER_ON);
+    }
+}
+
+fn silence() {
+    unsafe {
+        let mut speaker = Port::<u8>::new(SPEAKER_PORT);
+        let value = speaker.read();
+        speaker.write(value & !SPEAKER_ON);
+    }
+}
+
+/// Start the next note, or go quiet when there is none
+fn advance(player: &mut Player) {
+    match player.queue.pop_front() {
+        Some(note) => {
+            if note.freq == 0 {
+                silence();
+            } else {
+                tone(note.freq.clamp(MIN_FREQ, MAX_FREQ));
+            }
+            player.playing = true;
+            let ticks = crate::timers::ms_to_ticks(note.ms as u64).max(1);
+            crate::timers::call_in(ticks, note_done, player.generation);
+        }
+        None => {
+            silence();
+            player.playing = false;
+        }
+    }
+}
+
+/// Timer callback: the current note is over
+fn note_done(generation: u64) {
+    let mut player = PLAYER.lock();
+    if player.generation == generation {
+        advance(&mut player);
+    }
+}
+
+/// Queue `notes` after whatever is playing; returns how many were queued
+pub fn play(notes: &[Note]) -> usize {
+    let mut player = PLAYER.lock();
+    let room = MAX_QUEUED.saturating_sub(player.queue.len());
+    let queued = notes.len().min(room);
+    player.queue.extend(notes[..queued].iter().copied());
+    if !player.playing {
+        advance(&mut player);
+    }
+    queued
+}
+
+/// Queue one tone of `freq` Hz for `ms` milliseconds
+pub fn beep(freq: u32, ms: u32) {
+    play(&[Note::new(freq, ms)]);
+}
+
+/// Drop the queue and go quiet now
+pub fn stop() {
+    let mut player = PLAYER.lock();
+    player.queue.clear();
+    player.generation += 1;
+    player.playing = false;
+    silence();
+}
+
+/// Notes in `bytes`, /dev/audio records; a partial record at the end is
+/// ignored
+pub fn parse_notes(bytes: &[u8]) -> Vec<Note> {
+    bytes
+        .chunks_exact(NOTE_SIZE)
+        .map(|record| {
+            let freq = u16::from_le_bytes([record[0], record[1]]) as u32;
+            let ms = u16::from_le_bytes([record[2], record[3]]) as u32;
+            Note::new(freq, ms)
+        })
+        .collect()
+}
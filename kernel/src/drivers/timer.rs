@@ -1,15 +1,56 @@
-//! Programmable Interval Timer (PIT) driver
-//! Used for preemptive multitasking and timekeeping (like Linux jiffies)
+//! System tick: preemptive multitasking and timekeeping (like Linux jiffies)
+//!
+//! The tick comes from the local APIC timer when the APICs are in use and
+//! an HPET is there to measure it against; otherwise from the Programmable
+//! Interval Timer (PIT), through whichever interrupt controller is active.
+//! Either way it raises the timer vector at `TICKS_PER_SECOND`.
 
 use x86_64::instructions::port::Port;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 const PIT_FREQUENCY: u32 = 1193182; // Base PIT frequency
 const TARGET_HZ: u32 = 100; // 100 Hz = 10ms per tick
 
+/// Rate of `tick`, for code that converts tick counts to time
+pub const TICKS_PER_SECOND: u64 = TARGET_HZ as u64;
+
 static JIFFIES: AtomicU64 = AtomicU64::new(0);
+/// The local APIC timer drives the tick
+static LAPIC_TIMER: AtomicBool = AtomicBool::new(false);
 
+/// Start the tick; call after the interrupt controller is set up
 pub fn init() {
+    if let Err(e) = crate::irq::register(0, "timer", || {
+        tick();
+        true
+    }) {
+        crate::serial_println!("[TIMER] {}", e);
+    }
+    if crate::drivers::apic::is_enabled() {
+        match crate::drivers::hpet::init().and_then(|()| start_lapic_timer()) {
+            Ok(()) => return,
+            Err(e) => crate::serial_println!("[TIMER] {}, using the PIT", e),
+        }
+    }
+    start_pit();
+}
+
+/// Measure the local APIC timer over one tick and make it periodic
+fn start_lapic_timer() -> Result<(), &'static str> {
+    let tick_ns = 1_000_000_000 / TICKS_PER_SECOND;
+    let counts = crate::drivers::apic::measure_timer(|| {
+        crate::drivers::hpet::delay_ns(tick_ns);
+    });
+    if counts == 0 {
+        return Err("local APIC timer does not count");
+    }
+    crate::drivers::apic::start_periodic_timer(crate::interrupts::InterruptIndex::Timer as u8, counts);
+    LAPIC_TIMER.store(true, Ordering::Relaxed);
+    crate::serial_println!("[TIMER] Local APIC timer, {} counts per tick", counts);
+    Ok(())
+}
+
+fn start_pit() {
     let divisor = (PIT_FREQUENCY / TARGET_HZ) as u16;
     
     unsafe {
@@ -22,10 +63,20 @@ pub fn init() {
         data_port.write((divisor & 0xFF) as u8);
         data_port.write((divisor >> 8) as u8);
     }
+    crate::interrupts::enable_irq(0);
+}
+
+/// What drives the tick, for reports
+pub fn source() -> &'static str {
+    if LAPIC_TIMER.load(Ordering::Relaxed) {
+        "local APIC timer"
+    } else {
+        "PIT"
+    }
 }
 
-/// Called from timer interrupt handler
-pub fn tick() {
+/// Line 0's handler: one more tick
+fn tick() {
     JIFFIES.fetch_add(1, Ordering::Relaxed);
 }
 
@@ -38,3 +89,9 @@ pub fn get_jiffies() -> u64 {
 pub fn get_uptime_ms() -> u64 {
     get_jiffies() * 10 // 10ms per tick
 }
+
+/// Nanosecond timestamp from the HPET, falling back to tick resolution;
+/// only differences between two calls mean anything
+pub fn now_ns() -> u64 {
+    crate::drivers::hpet::nanos().unwrap_or_else(|| get_jiffies() * (1_000_000_000 / TICKS_PER_SECOND))
+}
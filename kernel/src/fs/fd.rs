@@ -1,9 +1,11 @@
 //! Per-process file descriptor table.
 
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 
-use super::vfs::{DeviceFileHandle, DeviceKind, FileHandle, FsError};
+use super::vfs::{FileHandle, FsError};
+use crate::services::terminal::TtyHandle;
 
 pub struct FdTable {
     entries: Vec<Option<Box<dyn FileHandle>>>,
@@ -14,15 +16,25 @@ impl FdTable {
         Self { entries: Vec::new() }
     }
 
-    pub fn with_stdio() -> Self {
+    /// Table with stdin, stdout and stderr on the terminal, as job `job`
+    pub fn with_stdio(job: u32) -> Self {
         let mut table = Self::new();
         table.entries.resize_with(3, || None);
-        table.entries[0] = Some(Box::new(DeviceFileHandle::new(DeviceKind::Keyboard)));
-        table.entries[1] = Some(Box::new(DeviceFileHandle::new(DeviceKind::Framebuffer)));
-        table.entries[2] = Some(Box::new(DeviceFileHandle::new(DeviceKind::Serial)));
+        table.entries[0] = Some(Box::new(TtyHandle::new(job, false)));
+        table.entries[1] = Some(Box::new(TtyHandle::new(job, false)));
+        table.entries[2] = Some(Box::new(TtyHandle::new(job, true)));
         table
     }
 
+    /// Copy of the table for a forked task. Handles that cannot be
+    /// duplicated (sockets) are left closed in the child.
+    pub fn fork(&self) -> Self {
+        let entries = self.entries.iter()
+            .map(|entry| entry.as_ref().and_then(|handle| handle.try_clone()))
+            .collect();
+        Self { entries }
+    }
+
     pub fn insert(&mut self, handle: Box<dyn FileHandle>) -> u32 {
         for (idx, entry) in self.entries.iter_mut().enumerate() {
             if entry.is_none() {
@@ -35,6 +47,14 @@ impl FdTable {
         fd
     }
 
+    /// Open descriptors and what each refers to
+    pub fn describe(&self) -> Vec<(u32, String)> {
+        self.entries.iter()
+            .enumerate()
+            .filter_map(|(fd, entry)| Some((fd as u32, entry.as_ref()?.describe())))
+            .collect()
+    }
+
     pub fn get_mut(&mut self, fd: u32) -> Result<&mut Box<dyn FileHandle>, FsError> {
         let idx = fd as usize;
         if idx >= self.entries.len() {
@@ -43,6 +63,19 @@ impl FdTable {
         self.entries[idx].as_mut().ok_or(FsError::Invalid)
     }
 
+    /// Remove the handle of `fd` while it is used outside the table;
+    /// `put_back` returns it to the same slot
+    pub fn take(&mut self, fd: u32) -> Result<Box<dyn FileHandle>, FsError> {
+        let slot = self.entries.get_mut(fd as usize).ok_or(FsError::Invalid)?;
+        slot.take().ok_or(FsError::Invalid)
+    }
+
+    pub fn put_back(&mut self, fd: u32, handle: Box<dyn FileHandle>) {
+        if let Some(slot) = self.entries.get_mut(fd as usize) {
+            *slot = Some(handle);
+        }
+    }
+
     pub fn close(&mut self, fd: u32) -> Result<(), FsError> {
         let idx = fd as usize;
         if idx >= self.entries.len() {
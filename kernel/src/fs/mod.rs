@@ -5,3 +5,6 @@
 pub mod tar;
 pub mod vfs;
 pub mod fd;
+pub mod procfs;
+pub mod sysfs;
+pub mod quota;
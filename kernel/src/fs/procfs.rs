@@ -0,0 +1,196 @@
+//! /proc: kernel state as files
+//!
+//! Nothing is stored; every read generates the text from the live kernel
+//! structures, so `cat /proc/meminfo` always shows the current numbers:
+//!
+//! ```text
+//! /proc/uptime        seconds since boot and seconds spent in the idle task
+//! /proc/meminfo       physical memory, the kernel heap and task memory
+//! /proc/pci           the PCI functions with their interrupts and BARs
+//! /proc/cpuinfo       what CPUID reports
+//! /proc/interrupts    interrupts per IRQ line and the handlers on it
+//! /proc/profile       the sampling profiler's counts (see `profile`)
+//! /proc/<pid>/status  name, state, parent and CPU time of a task
+//! /proc/<pid>/fd/<n>  what descriptor n of the task refers to
+//! /proc/self          the directory of the task reading it
+//! ```
+//!
+//! Paths are relative to the mount point, as the VFS passes them.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::drivers::timer::TICKS_PER_SECOND;
+use crate::task::scheduler::SCHEDULER;
+
+/// Files at the top of /proc
+const FILES: &[(&str, fn() -> String)] = &[
+    ("cpuinfo", cpuinfo),
+    ("interrupts", interrupts),
+    ("meminfo", meminfo),
+    ("pci", pci),
+    ("profile", profile),
+    ("uptime", uptime),
+];
+
+/// Files in each /proc/<pid>
+const TASK_FILES: &[&str] = &["fd", "status"];
+
+/// Ticks as seconds with two decimals
+fn seconds(ticks: u64) -> String {
+    let hundredths = ticks * 100 / TICKS_PER_SECOND;
+    format!("{}.{:02}", hundredths / 100, hundredths % 100)
+}
+
+fn uptime() -> String {
+    let uptime = crate::drivers::timer::get_jiffies();
+    let mut idle = 0;
+    SCHEDULER.lock().for_each_task(|task| {
+        if task.pid == 0 {
+            idle = task.cpu_ticks;
+        }
+    });
+    format!("{} {}\n", seconds(uptime), seconds(idle))
+}
+
+fn meminfo() -> String {
+    let (total, used, free) = crate::mem::physical::stats();
+    let heap = crate::mm::heap_allocator::stats();
+    let map = crate::mem::physical::memory_map();
+    let tasks = crate::task::list();
+    let frames = |frames: usize| frames as u64 * 4;
+    let bytes = |bytes: usize| bytes as u64 / 1024;
+    let lines = [
+        ("MemTotal", frames(total)),
+        ("MemFree", frames(free)),
+        ("MemUsed", frames(used)),
+        ("Shared", frames(crate::mem::vmm::shared_frames())),
+        ("HeapTotal", bytes(heap.total)),
+        ("HeapUsed", bytes(heap.in_use)),
+        ("HeapFree", bytes(heap.free())),
+        ("HeapPeak", bytes(heap.peak)),
+        ("TasksResident", bytes(tasks.iter().map(|task| task.resident).sum())),
+        ("MapTotal", map.total() / 1024),
+        ("MapUsable", map.usable / 1024),
+        ("MapKernel", map.kernel / 1024),
+        ("MapBootloader", map.bootloader / 1024),
+        ("MapReserved", map.reserved / 1024),
+    ];
+    let mut text = String::new();
+    for (name, kb) in lines {
+        text += &format!("{:<15} {:>8} kB\n", format!("{}:", name), kb);
+    }
+    text
+}
+
+fn cpuinfo() -> String {
+    let info = crate::arch::x86_64::cpu::info();
+    let cpus = crate::drivers::acpi::madt().map_or(1, |madt| madt.cpus.len().max(1));
+    let mut text = String::new();
+    for cpu in 0..cpus {
+        text += &format!("processor\t: {}\n", cpu);
+        text += &format!("vendor_id\t: {}\n", info.vendor);
+        text += &format!("cpu family\t: {}\n", info.family);
+        text += &format!("model\t\t: {}\n", info.model);
+        if let Some(brand) = &info.brand {
+            text += &format!("model name\t: {}\n", brand);
+        }
+        text += &format!("stepping\t: {}\n", info.stepping);
+        text += &format!("flags\t\t: {}\n", info.flags.join(" "));
+        if let Some((physical, virtual_bits)) = info.address_bits {
+            text += &format!("address sizes\t: {} bits physical, {} bits virtual\n", physical, virtual_bits);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn interrupts() -> String {
+    let mut text = format!("{:>4} {:>10} {:>10}  {}\n", "IRQ", "COUNT", "SPURIOUS", "HANDLERS");
+    for line in crate::irq::lines() {
+        text += &format!("{:>4} {:>10} {:>10}  {}\n", line.line, line.count, line.spurious, line.handlers.join(", "));
+    }
+    text
+}
+
+fn pci() -> String {
+    crate::drivers::pci::listing(true)
+}
+
+fn profile() -> String {
+    crate::profile::report().unwrap_or_else(|| "No profile yet; start one with `profile on`\n".to_string())
+}
+
+/// `/proc/<pid>/status`
+fn status(pid: u32) -> Option<String> {
+    let task = crate::task::list().into_iter().find(|task| task.pid == pid)?;
+    Some(format!(
+        "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nUser:\t{}\nCpuTime:\t{}\nVmRSS:\t{} kB\nRssShared:\t{} kB\n",
+        task.name,
+        task.state.as_str(),
+        task.pid,
+        task.parent.unwrap_or(0),
+        if task.user { "yes" } else { "no" },
+        seconds(task.cpu_ticks),
+        task.resident / 1024,
+        task.shared / 1024
+    ))
+}
+
+fn current_pid() -> u32 {
+    SCHEDULER.lock().current_pid()
+}
+
+/// Split `path` into the task it is about and the rest, for `<pid>/...`
+/// and `self/...`
+fn task_path(path: &str) -> Option<(u32, &str)> {
+    let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+    let pid = match first {
+        "self" => current_pid(),
+        pid => pid.parse().ok()?,
+    };
+    crate::task::descriptors(pid)?;
+    Some((pid, rest))
+}
+
+/// Names directly inside directory `path`
+pub fn list_dir(path: &str) -> Option<Vec<String>> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        let mut names: Vec<String> = FILES.iter().map(|(name, _)| name.to_string()).collect();
+        names.push("self".to_string());
+        names.extend(crate::task::list().iter().map(|task| task.pid.to_string()));
+        return Some(names);
+    }
+    let (pid, rest) = task_path(path)?;
+    match rest {
+        "" => Some(TASK_FILES.iter().map(|name| name.to_string()).collect()),
+        "fd" => Some(crate::task::descriptors(pid)?.into_iter().map(|(fd, _)| fd.to_string()).collect()),
+        _ => None,
+    }
+}
+
+/// Contents of the file at `path`
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    let path = path.trim_matches('/');
+    if let Some((_, generate)) = FILES.iter().find(|(name, _)| *name == path) {
+        return Some(generate().into_bytes());
+    }
+    let (pid, rest) = task_path(path)?;
+    let text = match rest.split_once('/') {
+        None if rest == "status" => status(pid)?,
+        Some(("fd", fd)) => {
+            let fd: u32 = fd.parse().ok()?;
+            let (_, target) = crate::task::descriptors(pid)?.into_iter().find(|(n, _)| *n == fd)?;
+            format!("{}\n", target)
+        }
+        _ => return None,
+    };
+    Some(text.into_bytes())
+}
+
+/// Whether `path` is a directory
+pub fn is_dir(path: &str) -> bool {
+    list_dir(path).is_some()
+}
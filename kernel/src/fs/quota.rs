@@ -0,0 +1,92 @@
+//! Size quota of /tmp
+//!
+//! /tmp is not a filesystem of its own: its files are a subtree of the
+//! ramfs in `services::vfs`, on the same kernel heap as the rest of it.
+//! What sets it apart is a quota. The VFS reports every change to the
+//! bytes under /tmp here (writes, truncation, deletion), so the running
+//! total is always at hand. A write that would take it past `size` fails
+//! with "No space left on device" (ENOSPC) rather than exhausting the
+//! kernel heap, and df shows /tmp on a row of its own against the quota.
+//!
+//! The quota is the `kernel.tmp_quota_kb` tunable; 0, the default, is half
+//! of the kernel heap. Lowering it below what /tmp already holds keeps the
+//! files but lets them only shrink.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::fs::vfs::FsError;
+
+/// The directory the quota covers
+pub const DIR: &str = "/tmp";
+
+/// Bytes of file data under /tmp; changed under the VFS tree lock
+static USED: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes /tmp may hold
+pub fn size() -> u64 {
+    match crate::sysctl::tmp_quota_kb() {
+        0 => crate::mm::heap_allocator::heap_stats().1 as u64 / 2,
+        kb => kb * 1024,
+    }
+}
+
+/// Bytes /tmp holds
+pub fn used() -> u64 {
+    USED.load(Ordering::Relaxed)
+}
+
+/// Start counting from `bytes`, what a walk of /tmp found
+pub fn set_used(bytes: u64) {
+    USED.store(bytes, Ordering::Relaxed);
+}
+
+/// Count a file (or subtree) under /tmp going from `old` to `new` bytes
+pub fn resize(old: u64, new: u64) {
+    let used = used().saturating_sub(old).saturating_add(new);
+    USED.store(used, Ordering::Relaxed);
+}
+
+/// Whether normalized `path` lies in /tmp
+pub fn contains(path: &str) -> bool {
+    path == DIR || path.strip_prefix(DIR).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether a file of `old` bytes may become `new` bytes while /tmp holds
+/// `used` bytes in all, under a quota of `size`
+fn fits(used: u64, old: u64, new: u64, size: u64) -> bool {
+    new <= old || used.saturating_sub(old).checked_add(new).is_some_and(|total| total <= size)
+}
+
+/// Check that a file of `old` bytes under /tmp may become `new` bytes
+pub fn reserve(old: u64, new: u64) -> Result<(), FsError> {
+    if fits(used(), old, new, size()) {
+        Ok(())
+    } else {
+        Err(FsError::NoSpace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn files_grow_only_within_the_size() {
+        assert!(fits(0, 0, 100, 100));
+        assert!(!fits(0, 0, 101, 100));
+        // Rewriting a file replaces its bytes rather than adding to them
+        assert!(fits(100, 60, 60, 100));
+        assert!(fits(100, 60, 40, 100));
+        assert!(!fits(100, 60, 61, 100));
+        // Over a lowered size, files may still shrink
+        assert!(fits(200, 50, 10, 100));
+        assert!(!fits(200, 50, 51, 100));
+    }
+
+    #[test_case]
+    fn only_paths_below_the_directory_are_inside() {
+        assert!(contains("/tmp"));
+        assert!(contains("/tmp/a/b"));
+        assert!(!contains("/tmpx"));
+        assert!(!contains("/home/tmp"));
+    }
+}
@@ -0,0 +1,49 @@
+//! /sys: runtime tunables as files
+//!
+//! /sys/kernel holds one file per `sysctl` tunable, containing its value.
+//! Writing a number to one sets it, with the same checks as `sysctl`.
+//! Paths are relative to the mount point, as the VFS passes them.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::vfs::FsError;
+use crate::sysctl;
+
+/// The tunable behind `kernel/<name>`
+fn tunable(path: &str) -> Option<&'static sysctl::Tunable> {
+    let name = path.trim_matches('/').strip_prefix("kernel/")?;
+    sysctl::TUNABLES.iter().find(|tunable| tunable.file_name() == name)
+}
+
+/// Names directly inside directory `path`
+pub fn list_dir(path: &str) -> Option<Vec<String>> {
+    match path.trim_matches('/') {
+        "" => Some(Vec::from(["kernel".to_string()])),
+        "kernel" => Some(sysctl::TUNABLES.iter().map(|tunable| tunable.file_name().to_string()).collect()),
+        _ => None,
+    }
+}
+
+/// Contents of the file at `path`
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    tunable(path).map(|tunable| format!("{}\n", tunable.get()).into_bytes())
+}
+
+/// Set the tunable at `path` to the number in `data`
+pub fn write_file(path: &str, data: &[u8]) -> Result<(), FsError> {
+    let Some(tunable) = tunable(path) else {
+        return Err(if is_dir(path) { FsError::NotFile } else { FsError::ReadOnly });
+    };
+    if crate::auth::current_user_id() != 0 {
+        return Err(FsError::Permission);
+    }
+    let text = core::str::from_utf8(data).map_err(|_| FsError::Invalid)?;
+    sysctl::set(tunable.name, text).map_err(|_| FsError::Invalid)
+}
+
+/// Whether `path` is a directory
+pub fn is_dir(path: &str) -> bool {
+    list_dir(path).is_some()
+}
@@ -1,6 +1,8 @@
-//! Minimal ustar tar parser for initrd loading.
+//! Minimal ustar tar support for initrd loading and archives.
 //!
-//! This parser is intentionally small and read-only.
+//! Intentionally small: regular files and directories only. Shared with
+//! tomato-pm, which unpacks .tpkg payloads with it: stick to `alloc` types
+//! here.
 
 use alloc::format;
 use alloc::string::{String, ToString};
@@ -14,7 +16,23 @@ pub struct TarEntry {
     pub is_dir: bool,
 }
 
+/// Archive entry whose data is left in place in the archive buffer
+pub struct TarIndexEntry<'a> {
+    pub path: String,
+    pub data: &'a [u8],
+    pub is_dir: bool,
+}
+
+/// Parse an archive into entries that own a copy of their data
 pub fn parse_tar(buf: &[u8]) -> Vec<TarEntry> {
+    index_tar(buf)
+        .into_iter()
+        .map(|entry| TarEntry { path: entry.path, data: entry.data.to_vec(), is_dir: entry.is_dir })
+        .collect()
+}
+
+/// Parse an archive's headers without copying any file data
+pub fn index_tar(buf: &[u8]) -> Vec<TarIndexEntry<'_>> {
     let mut entries = Vec::new();
     let mut offset = 0usize;
 
@@ -40,22 +58,92 @@ pub fn parse_tar(buf: &[u8]) -> Vec<TarEntry> {
         let data_end = data_start.saturating_add(size);
 
         let data = if !is_dir && data_end <= buf.len() {
-            buf[data_start..data_end].to_vec()
+            &buf[data_start..data_end]
         } else {
-            Vec::new()
+            &[][..]
         };
 
         if !path.is_empty() {
-            entries.push(TarEntry { path, data, is_dir });
+            entries.push(TarIndexEntry { path, data, is_dir });
         }
 
-        let data_blocks = (size + TAR_BLOCK_SIZE - 1) / TAR_BLOCK_SIZE;
+        let data_blocks = size.div_ceil(TAR_BLOCK_SIZE);
         offset = data_start + data_blocks * TAR_BLOCK_SIZE;
     }
 
     entries
 }
 
+/// Serialize entries into a ustar archive (terminated by two zero blocks)
+pub fn build_tar(entries: &[TarEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for entry in entries {
+        let mut header = [0u8; TAR_BLOCK_SIZE];
+        let mut path = entry.path.clone();
+        if entry.is_dir && !path.ends_with('/') {
+            path.push('/');
+        }
+        let (prefix, name) = split_path(&path);
+        write_string(&mut header[0..100], name);
+        write_octal(&mut header[100..108], if entry.is_dir { 0o755 } else { 0o644 });
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], if entry.is_dir { 0 } else { entry.data.len() });
+        write_octal(&mut header[136..148], 0);
+        header[156] = if entry.is_dir { b'5' } else { b'0' };
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        write_string(&mut header[345..500], prefix);
+
+        // Checksum is computed with the checksum field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let sum: usize = header.iter().map(|b| *b as usize).sum();
+        write_octal(&mut header[148..155], sum);
+        header[155] = b' ';
+
+        out.extend_from_slice(&header);
+        if !entry.is_dir {
+            out.extend_from_slice(&entry.data);
+            let pad = (TAR_BLOCK_SIZE - entry.data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+            out.resize(out.len() + pad, 0);
+        }
+    }
+
+    out.resize(out.len() + 2 * TAR_BLOCK_SIZE, 0);
+    out
+}
+
+/// Split a path into ustar (prefix, name) so that name fits in 100 bytes
+fn split_path(path: &str) -> (&str, &str) {
+    if path.len() <= 100 {
+        return ("", path);
+    }
+    let trimmed = path.trim_end_matches('/');
+    for (i, c) in trimmed.char_indices().rev() {
+        if c == '/' && path.len() - i - 1 <= 100 && i <= 155 {
+            return (&path[..i], &path[i + 1..]);
+        }
+    }
+    ("", &path[..100])
+}
+
+fn write_string(field: &mut [u8], s: &str) {
+    let len = core::cmp::min(field.len(), s.len());
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+/// Write a zero-padded, NUL-terminated octal number
+fn write_octal(field: &mut [u8], value: usize) {
+    let digits = field.len() - 1;
+    let mut v = value;
+    for i in (0..digits).rev() {
+        field[i] = b'0' + (v & 7) as u8;
+        v >>= 3;
+    }
+    field[digits] = 0;
+}
+
 fn is_zero_block(block: &[u8]) -> bool {
     block.iter().all(|b| *b == 0)
 }
@@ -69,7 +157,7 @@ fn read_string(field: &[u8]) -> String {
 fn read_octal(field: &[u8]) -> usize {
     let mut value = 0usize;
     for &b in field {
-        if b < b'0' || b > b'7' {
+        if !(b'0'..=b'7').contains(&b) {
             continue;
         }
         value = (value << 3) + (b - b'0') as usize;
@@ -1,8 +1,13 @@
 //! VFS traits and common file handle helpers.
 
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use crate::services::vfs::{FileType, Metadata};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FsError {
     NotFound,
@@ -11,42 +16,300 @@ pub enum FsError {
     Permission,
     Invalid,
     Io,
+    ReadOnly,
+    NoSpace,
+    /// Nothing to read yet; the caller may retry
+    WouldBlock,
+    /// The descriptor has no position (terminal, socket, most devices)
+    NotSeekable,
+    /// Directory still has entries
+    NotEmpty,
+    /// In use: the root or the current directory
+    Busy,
+    /// Something is already there
+    Exists,
+    /// Too many symbolic links, probably a loop
+    Loop,
+    /// The descriptor is not a terminal (ioctl)
+    NotTty,
+}
+
+impl FsError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FsError::NotFound => "No such file or directory",
+            FsError::NotFile => "Not a regular file",
+            FsError::NotDir => "Not a directory",
+            FsError::Permission => "Permission denied",
+            FsError::Invalid => "Invalid argument",
+            FsError::Io => "I/O error",
+            FsError::ReadOnly => "Read-only file system",
+            FsError::NoSpace => "No space left on device",
+            FsError::WouldBlock => "Resource temporarily unavailable",
+            FsError::NotSeekable => "Illegal seek",
+            FsError::NotEmpty => "Directory not empty",
+            FsError::Busy => "Device or resource busy",
+            FsError::Exists => "File exists",
+            FsError::Loop => "Too many levels of symbolic links",
+            FsError::NotTty => "Inappropriate ioctl for device",
+        }
+    }
+
+    /// errno value reported by the filesystem syscalls (see `syscall::abi`)
+    pub fn errno(self) -> u64 {
+        use crate::syscall::abi;
+        match self {
+            FsError::NotFound => abi::ENOENT,
+            FsError::NotFile => abi::EISDIR,
+            FsError::NotDir => abi::ENOTDIR,
+            FsError::Permission => abi::EACCES,
+            FsError::Invalid => abi::EINVAL,
+            FsError::Io => abi::EIO,
+            FsError::ReadOnly => abi::EROFS,
+            FsError::NoSpace => abi::ENOSPC,
+            FsError::WouldBlock => abi::EAGAIN,
+            FsError::NotSeekable => abi::ESPIPE,
+            FsError::NotEmpty => abi::ENOTEMPTY,
+            FsError::Busy => abi::EBUSY,
+            FsError::Exists => abi::EEXIST,
+            FsError::Loop => abi::ELOOP,
+            FsError::NotTty => abi::ENOTTY,
+        }
+    }
+
+    /// Inverse of `errno`
+    pub fn from_errno(errno: u64) -> Option<Self> {
+        use crate::syscall::abi;
+        Some(match errno {
+            abi::ENOENT => FsError::NotFound,
+            abi::EISDIR => FsError::NotFile,
+            abi::ENOTDIR => FsError::NotDir,
+            abi::EACCES => FsError::Permission,
+            abi::EINVAL => FsError::Invalid,
+            abi::EIO => FsError::Io,
+            abi::EROFS => FsError::ReadOnly,
+            abi::ENOSPC => FsError::NoSpace,
+            abi::EAGAIN => FsError::WouldBlock,
+            abi::ESPIPE => FsError::NotSeekable,
+            abi::ENOTEMPTY => FsError::NotEmpty,
+            abi::EBUSY => FsError::Busy,
+            abi::EEXIST => FsError::Exists,
+            abi::ELOOP => FsError::Loop,
+            abi::ENOTTY => FsError::NotTty,
+            _ => return None,
+        })
+    }
+
+    /// Syscall return value for this error: the negated errno
+    pub fn to_syscall(self) -> u64 {
+        self.errno().wrapping_neg()
+    }
+}
+
+impl core::fmt::Display for FsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum OpenFlags {
+pub enum Access {
     ReadOnly,
     WriteOnly,
     ReadWrite,
 }
 
+/// How sys_open opens a file (the O_* bits of `syscall::abi`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags {
+    pub access: Access,
+    /// Create a missing regular file
+    pub create: bool,
+    /// Empty a regular file opened for writing
+    pub truncate: bool,
+    /// Every write goes to the end of the file as it is then
+    pub append: bool,
+}
+
 impl OpenFlags {
+    pub const READ: OpenFlags = OpenFlags { access: Access::ReadOnly, create: false, truncate: false, append: false };
+
     pub fn from_bits(flags: u64) -> Self {
-        match flags & 0b11 {
-            0b01 => OpenFlags::WriteOnly,
-            0b10 => OpenFlags::ReadWrite,
-            _ => OpenFlags::ReadOnly,
+        use crate::syscall::abi;
+        let access = match flags & abi::O_ACCMODE {
+            abi::O_WRONLY => Access::WriteOnly,
+            abi::O_RDWR => Access::ReadWrite,
+            _ => Access::ReadOnly,
+        };
+        OpenFlags {
+            access,
+            create: flags & abi::O_CREAT != 0,
+            truncate: flags & abi::O_TRUNC != 0,
+            append: flags & abi::O_APPEND != 0,
         }
     }
+
+    pub fn readable(self) -> bool {
+        self.access != Access::WriteOnly
+    }
+
+    pub fn writable(self) -> bool {
+        self.access != Access::ReadOnly
+    }
+}
+
+/// Where sys_lseek moves a descriptor to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+impl SeekFrom {
+    /// The position this names, for a handle at `offset` on `len` bytes.
+    /// Past the end is allowed; before the start is not
+    pub fn resolve(self, offset: usize, len: usize) -> Result<usize, FsError> {
+        let (base, delta) = match self {
+            SeekFrom::Start(position) => return usize::try_from(position).map_err(|_| FsError::Invalid),
+            SeekFrom::Current(delta) => (offset, delta),
+            SeekFrom::End(delta) => (len, delta),
+        };
+        let position = base as i64 + delta;
+        usize::try_from(position).map_err(|_| FsError::Invalid)
+    }
 }
 
 pub trait FileHandle: Send {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError>;
     fn write(&mut self, buf: &[u8]) -> Result<usize, FsError>;
+
+    /// Move to `pos`; returns the new offset from the start
+    fn seek(&mut self, _pos: SeekFrom) -> Result<u64, FsError> {
+        Err(FsError::NotSeekable)
+    }
+
+    /// Device control; only the TTY has requests (see abi::SYS_IOCTL)
+    fn ioctl(&mut self, _request: u64, _arg: u64) -> Result<u64, FsError> {
+        Err(FsError::NotTty)
+    }
+
+    /// Network socket id if this descriptor refers to a socket
+    fn socket_id(&self) -> Option<i32> {
+        None
+    }
+
+    /// What the descriptor refers to, as /proc/<pid>/fd shows it
+    fn describe(&self) -> String {
+        match self.socket_id() {
+            Some(id) => format!("socket:[{}]", id),
+            None => String::from("file"),
+        }
+    }
+
+    /// Type, size and times of what is open, for fstat; None for sockets
+    fn metadata(&self) -> Option<Metadata> {
+        None
+    }
+
+    /// Independent copy for a forked task; None if the handle cannot be shared
+    fn try_clone(&self) -> Option<Box<dyn FileHandle>> {
+        None
+    }
 }
 
 pub trait FileSystem: Send + Sync {
     fn open(&self, path: &str, flags: OpenFlags) -> Result<Box<dyn FileHandle>, FsError>;
 }
 
+/// File contents, shared rather than copied: borrowed from a boot module,
+/// or on the heap behind an `Arc` so that reads and open descriptors take
+/// a reference. Writing copies only while someone else still holds one.
+#[derive(Debug, Clone)]
+pub enum FileBytes {
+    Static(&'static [u8]),
+    Shared(Arc<Vec<u8>>),
+}
+
+impl FileBytes {
+    /// Whether the bytes were copied to the heap
+    pub fn on_heap(&self) -> bool {
+        matches!(self, FileBytes::Shared(_))
+    }
+
+    /// The bytes to change, copied first if they are borrowed or shared
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let FileBytes::Static(data) = self {
+            *self = FileBytes::Shared(Arc::new(data.to_vec()));
+        }
+        match self {
+            FileBytes::Shared(data) => Arc::make_mut(data),
+            FileBytes::Static(_) => unreachable!(),
+        }
+    }
+
+    /// The bytes as a Vec of their own, copied unless nothing else holds them
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            FileBytes::Static(data) => data.to_vec(),
+            FileBytes::Shared(data) => Arc::try_unwrap(data).unwrap_or_else(|data| (*data).clone()),
+        }
+    }
+}
+
+impl Default for FileBytes {
+    fn default() -> Self {
+        FileBytes::Static(&[])
+    }
+}
+
+impl core::ops::Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBytes::Static(data) => data,
+            FileBytes::Shared(data) => data,
+        }
+    }
+}
+
+impl From<&'static [u8]> for FileBytes {
+    fn from(data: &'static [u8]) -> Self {
+        FileBytes::Static(data)
+    }
+}
+
+impl From<Vec<u8>> for FileBytes {
+    fn from(data: Vec<u8>) -> Self {
+        FileBytes::Shared(Arc::new(data))
+    }
+}
+
+impl PartialEq for FileBytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
 pub struct MemFileHandle {
-    data: Vec<u8>,
+    data: FileBytes,
     offset: usize,
+    metadata: Metadata,
 }
 
 impl MemFileHandle {
-    pub fn new(data: Vec<u8>) -> Self {
-        Self { data, offset: 0 }
+    /// A read-only file with no times, as the generated trees have
+    pub fn new(data: impl Into<FileBytes>) -> Self {
+        let data = data.into();
+        let metadata = Metadata { file_type: FileType::Regular, size: data.len(), mode: 0o444, created: 0, modified: 0 };
+        Self { data, offset: 0, metadata }
+    }
+
+    /// Report `metadata` to fstat instead
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
     }
 }
 
@@ -65,6 +328,19 @@ impl FileHandle for MemFileHandle {
     fn write(&mut self, _buf: &[u8]) -> Result<usize, FsError> {
         Err(FsError::Permission)
     }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        self.offset = pos.resolve(self.offset, self.data.len())?;
+        Ok(self.offset as u64)
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        Some(self.metadata)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileHandle>> {
+        Some(Box::new(MemFileHandle { data: self.data.clone(), offset: self.offset, metadata: self.metadata }))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -72,24 +348,60 @@ pub enum DeviceKind {
     Null,
     Zero,
     Keyboard,
+    /// Screen pixels: 4-byte little-endian 0x00RRGGBB, row after row
     Framebuffer,
     Serial,
+    Mouse,
+    Audio,
+    /// Raw scancodes
+    Kbd,
+    /// Text written to it is printed on the screen
+    Console,
 }
 
+impl DeviceKind {
+    /// Node name in /dev
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Null => "null",
+            DeviceKind::Zero => "zero",
+            DeviceKind::Keyboard => "keyboard",
+            DeviceKind::Framebuffer => "framebuffer",
+            DeviceKind::Serial => "serial",
+            DeviceKind::Mouse => "mouse",
+            DeviceKind::Audio => "audio",
+            DeviceKind::Kbd => "kbd",
+            DeviceKind::Console => "console",
+        }
+    }
+}
+
+/// Bytes per framebuffer pixel as the device presents it
+const PIXEL_SIZE: usize = 4;
+
 pub struct DeviceFileHandle {
     kind: DeviceKind,
+    /// Byte position, for the devices that have one (the framebuffer)
+    offset: usize,
 }
 
 impl DeviceFileHandle {
     pub fn new(kind: DeviceKind) -> Self {
-        Self { kind }
+        Self { kind, offset: 0 }
+    }
+}
+
+fn print_bytes(buf: &[u8]) {
+    for &b in buf {
+        let ch = if b < 0x80 { b as char } else { '?' };
+        crate::drivers::framebuffer::print_char(ch);
     }
 }
 
 impl FileHandle for DeviceFileHandle {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
         match self.kind {
-            DeviceKind::Null => Ok(0),
+            DeviceKind::Null | DeviceKind::Console => Ok(0),
             DeviceKind::Zero => {
                 for b in buf.iter_mut() {
                     *b = 0;
@@ -107,19 +419,61 @@ impl FileHandle for DeviceFileHandle {
                     Ok(0)
                 }
             }
-            DeviceKind::Framebuffer | DeviceKind::Serial => Ok(0),
+            DeviceKind::Mouse => {
+                // Whole events only
+                if buf.len() < crate::drivers::mouse::EVENT_SIZE {
+                    return Err(FsError::Invalid);
+                }
+                let mut n = 0;
+                for record in buf.chunks_exact_mut(crate::drivers::mouse::EVENT_SIZE) {
+                    match crate::drivers::mouse::read_event() {
+                        Some(event) => record.copy_from_slice(&event.to_bytes()),
+                        None => break,
+                    }
+                    n += record.len();
+                }
+                Ok(n)
+            }
+            DeviceKind::Kbd => Ok(crate::drivers::keyboard::read_raw(buf)),
+            DeviceKind::Framebuffer => {
+                // Whole pixels from a pixel boundary only
+                if self.offset % PIXEL_SIZE != 0 {
+                    return Err(FsError::Invalid);
+                }
+                let mut pixels = alloc::vec![0u32; buf.len() / PIXEL_SIZE];
+                let count = crate::drivers::framebuffer::read_pixels(self.offset / PIXEL_SIZE, &mut pixels);
+                for (bytes, pixel) in buf.chunks_exact_mut(PIXEL_SIZE).zip(&pixels[..count]) {
+                    bytes.copy_from_slice(&pixel.to_le_bytes());
+                }
+                self.offset += count * PIXEL_SIZE;
+                Ok(count * PIXEL_SIZE)
+            }
+            DeviceKind::Serial | DeviceKind::Audio => Ok(0),
         }
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
         match self.kind {
-            DeviceKind::Null | DeviceKind::Zero | DeviceKind::Keyboard => Ok(buf.len()),
+            DeviceKind::Null | DeviceKind::Zero | DeviceKind::Keyboard | DeviceKind::Mouse | DeviceKind::Kbd => Ok(buf.len()),
+            DeviceKind::Console => {
+                print_bytes(buf);
+                Ok(buf.len())
+            }
             DeviceKind::Framebuffer => {
-                for &b in buf {
-                    let ch = if b < 0x80 { b as char } else { '?' };
-                    crate::drivers::framebuffer::print_char(ch);
+                // Whole pixels to a pixel boundary only
+                if self.offset % PIXEL_SIZE != 0 || buf.len() < PIXEL_SIZE {
+                    return Err(FsError::Invalid);
                 }
-                Ok(buf.len())
+                let pixels: alloc::vec::Vec<u32> = buf
+                    .chunks_exact(PIXEL_SIZE)
+                    .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect();
+                let count = crate::drivers::framebuffer::blit(self.offset / PIXEL_SIZE, &pixels);
+                if count == 0 {
+                    return Err(FsError::NoSpace);
+                }
+                self.offset += count * PIXEL_SIZE;
+                Ok(count * PIXEL_SIZE)
             }
             DeviceKind::Serial => {
                 if let Ok(s) = core::str::from_utf8(buf) {
@@ -133,6 +487,36 @@ impl FileHandle for DeviceFileHandle {
                 }
                 Ok(buf.len())
             }
+            DeviceKind::Audio => {
+                // Whole note records are queued; the count says how many
+                let notes = crate::drivers::speaker::parse_notes(buf);
+                Ok(crate::drivers::speaker::play(&notes) * crate::drivers::speaker::NOTE_SIZE)
+            }
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        match self.kind {
+            DeviceKind::Framebuffer => {
+                let info = crate::drivers::framebuffer::get_info();
+                self.offset = pos.resolve(self.offset, info.width * info.height * PIXEL_SIZE)?;
+                Ok(self.offset as u64)
+            }
+            // Endless and empty, so every position is the same
+            DeviceKind::Null | DeviceKind::Zero => Ok(0),
+            _ => Err(FsError::NotSeekable),
         }
     }
+
+    fn describe(&self) -> String {
+        format!("/dev/{}", self.kind.name())
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        Some(Metadata { file_type: FileType::Device, size: 0, mode: 0o666, created: 0, modified: 0 })
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileHandle>> {
+        Some(Box::new(DeviceFileHandle { kind: self.kind, offset: self.offset }))
+    }
 }
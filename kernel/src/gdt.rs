@@ -1,6 +1,7 @@
 //! Global Descriptor Table (GDT) implementation for ospabOS
 //! Production-ready implementation using spin::Lazy (no static mut)
 
+use core::cell::UnsafeCell;
 use spin::Lazy;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
@@ -25,8 +26,16 @@ static DOUBLE_FAULT_STACK: Stack = Stack { data: [0; STACK_SIZE] };
 /// Kernel privilege stack (RSP0) for Ring 3 -> Ring 0 transitions
 static KERNEL_PRIV_STACK: Stack = Stack { data: [0; STACK_SIZE] };
 
+/// The TSS, in a cell because the scheduler rewrites RSP0 on every switch
+/// to a user task; only ever accessed through the raw pointer from `get`
+struct TssCell(UnsafeCell<TaskStateSegment>);
+
+// SAFETY: there is one CPU, and the only writer (`set_kernel_stack`) runs
+// with interrupts off, so no access to the cell can overlap another
+unsafe impl Sync for TssCell {}
+
 /// Lazy-initialized TSS with IST configured
-static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+static TSS: Lazy<TssCell> = Lazy::new(|| {
     let mut tss = TaskStateSegment::new();
     
     // Set up IST[0] for double fault - points to end of stack (grows down)
@@ -39,7 +48,7 @@ static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
     let priv_stack_end = priv_stack_start + STACK_SIZE as u64;
     tss.privilege_stack_table[0] = priv_stack_end;
     
-    tss
+    TssCell(UnsafeCell::new(tss))
 });
 
 /// GDT with selectors - lazy initialized
@@ -54,8 +63,10 @@ static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
     let user_data = gdt.add_entry(Descriptor::user_data_segment());
     let user_code = gdt.add_entry(Descriptor::user_code_segment());
     
-    // Add TSS segment (requires reference to TSS)
-    let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+    // Add TSS segment (requires reference to TSS). The descriptor only
+    // takes its address; the reference is not used past this line.
+    // SAFETY: nothing writes the TSS before the GDT is built
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*TSS.0.get() }));
 
     (
         gdt,
@@ -83,6 +94,21 @@ pub fn selectors() -> Selectors {
     GDT.1
 }
 
+/// Current RSP0, the stack used on Ring 3 -> Ring 0 transitions
+pub fn kernel_stack() -> VirtAddr {
+    // SAFETY: see `TssCell`; the field is packed, hence the unaligned read
+    unsafe { core::ptr::addr_of!((*TSS.0.get()).privilege_stack_table[0]).read_unaligned() }
+}
+
+/// Point RSP0 at the kernel stack of the task about to run
+pub fn set_kernel_stack(stack_top: VirtAddr) {
+    // SAFETY: the TSS sits in an UnsafeCell, so writing through its pointer
+    // is allowed; the scheduler calls this with interrupts off (see `TssCell`)
+    unsafe {
+        core::ptr::addr_of_mut!((*TSS.0.get()).privilege_stack_table[0]).write_unaligned(stack_top);
+    }
+}
+
 /// Initialize GDT and TSS
 /// 
 /// This function is safe to call multiple times - it will only
@@ -0,0 +1,303 @@
+//! Syntax highlighting for grape
+//!
+//! A small tokenizer per language picks out keywords, strings, numbers,
+//! comments and type names in one line at a time; everything else keeps
+//! the console's color. Rust block comments can span lines, so the
+//! tokenizer carries a `State` from each line to the next.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::common::palette::Syntax;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Toml,
+    Shell,
+}
+
+impl Language {
+    /// Language of `filename` from its extension, or a shell script by its
+    /// `#!` line
+    pub fn detect(filename: &str, first_line: &str) -> Option<Self> {
+        let name = filename.rsplit('/').next().unwrap_or(filename);
+        match name.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("rs") => Some(Language::Rust),
+            Some("toml") => Some(Language::Toml),
+            Some("sh") => Some(Language::Shell),
+            _ if first_line.starts_with("#!") => Some(Language::Shell),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Toml => "TOML",
+            Language::Shell => "shell",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Type,
+}
+
+impl Kind {
+    pub fn color(self, syntax: &Syntax) -> u32 {
+        match self {
+            Kind::Keyword => syntax.keyword,
+            Kind::String => syntax.string,
+            Kind::Comment => syntax.comment,
+            Kind::Number => syntax.number,
+            Kind::Type => syntax.type_name,
+        }
+    }
+}
+
+/// What a line leaves open for the next one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct State {
+    /// Nesting depth of Rust block comments
+    comment_depth: u32,
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while",
+];
+
+const RUST_TYPES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+    "i64", "i128", "isize", "f32", "f64",
+];
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "return", "export", "local", "exit",
+];
+
+/// Colored parts of `line` as sorted byte ranges
+pub fn highlight(language: Language, line: &str, state: &mut State) -> Vec<(Range<usize>, Kind)> {
+    let mut spans = Vec::new();
+    match language {
+        Language::Rust => rust(line.as_bytes(), state, &mut spans),
+        Language::Toml => toml(line.as_bytes(), &mut spans),
+        Language::Shell => shell(line.as_bytes(), &mut spans),
+    }
+    spans
+}
+
+/// Identifier bytes; non-ASCII bytes count so ranges stay on char boundaries
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+fn word_end(line: &[u8], mut i: usize) -> usize {
+    while i < line.len() && is_word(line[i]) {
+        i += 1;
+    }
+    i
+}
+
+/// End of a string whose opening quote is just before `i`; unterminated
+/// strings run to the end of the line
+fn string_end(line: &[u8], mut i: usize, quote: u8, escapes: bool) -> usize {
+    while i < line.len() {
+        match line[i] {
+            b'\\' if escapes => i += 2,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    line.len()
+}
+
+/// End of a number starting at `i`: digits, letters (0x, suffixes), `_`
+/// and a `.` followed by a digit
+fn number_end(line: &[u8], mut i: usize) -> usize {
+    while i < line.len() {
+        let b = line[i];
+        let decimal_point = b == b'.' && line.get(i + 1).is_some_and(u8::is_ascii_digit);
+        if !(is_word(b) || decimal_point) {
+            break;
+        }
+        i += 1;
+    }
+    i
+}
+
+/// End of a Rust block comment, updating the nesting depth
+fn block_comment_end(line: &[u8], mut i: usize, depth: &mut u32) -> usize {
+    while i < line.len() {
+        if line[i..].starts_with(b"/*") {
+            *depth += 1;
+            i += 2;
+        } else if line[i..].starts_with(b"*/") {
+            *depth -= 1;
+            i += 2;
+            if *depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    line.len()
+}
+
+fn rust(line: &[u8], state: &mut State, spans: &mut Vec<(Range<usize>, Kind)>) {
+    let mut i = 0;
+    while i < line.len() {
+        let start = i;
+        if state.comment_depth > 0 {
+            i = block_comment_end(line, i, &mut state.comment_depth);
+            spans.push((start..i, Kind::Comment));
+            continue;
+        }
+        let b = line[i];
+        let kind = if line[i..].starts_with(b"//") {
+            i = line.len();
+            Kind::Comment
+        } else if line[i..].starts_with(b"/*") {
+            state.comment_depth = 1;
+            i = block_comment_end(line, i + 2, &mut state.comment_depth);
+            Kind::Comment
+        } else if b == b'"' {
+            i = string_end(line, i + 1, b'"', true);
+            Kind::String
+        } else if b == b'\'' {
+            // 'x' and '\n' are characters, 'a alone a lifetime
+            let char_len = match line.get(i + 1) {
+                Some(b'\\') => None,
+                Some(&c) if c >= 0xF0 => Some(4),
+                Some(&c) if c >= 0xE0 => Some(3),
+                Some(&c) if c >= 0xC0 => Some(2),
+                _ => Some(1),
+            };
+            match char_len {
+                None => i = string_end(line, i + 1, b'\'', true),
+                Some(len) if line.get(i + 1 + len) == Some(&b'\'') => i += len + 2,
+                Some(_) => {
+                    i = word_end(line, i + 1);
+                    continue;
+                }
+            }
+            Kind::String
+        } else if b.is_ascii_digit() {
+            i = number_end(line, i);
+            Kind::Number
+        } else if is_word(b) {
+            i = word_end(line, i);
+            let word = &line[start..i];
+            if RUST_KEYWORDS.iter().any(|k| k.as_bytes() == word) {
+                Kind::Keyword
+            } else if word[0].is_ascii_uppercase() || RUST_TYPES.iter().any(|t| t.as_bytes() == word) {
+                Kind::Type
+            } else {
+                continue;
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        spans.push((start..i, kind));
+    }
+}
+
+fn toml(line: &[u8], spans: &mut Vec<(Range<usize>, Kind)>) {
+    let trimmed = line.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(line.len());
+    let mut i = trimmed;
+    // [table] and [[array]] headers
+    if line.get(i) == Some(&b'[') {
+        let end = line.iter().position(|&b| b == b'#').unwrap_or(line.len());
+        spans.push((i..end, Kind::Type));
+        i = end;
+    }
+    let mut seen_equals = false;
+    while i < line.len() {
+        let start = i;
+        let b = line[i];
+        let kind = if b == b'#' {
+            i = line.len();
+            Kind::Comment
+        } else if b == b'"' || b == b'\'' {
+            i = string_end(line, i + 1, b, b == b'"');
+            Kind::String
+        } else if b == b'=' {
+            seen_equals = true;
+            i += 1;
+            continue;
+        } else if seen_equals && (b.is_ascii_digit()
+            || (matches!(b, b'+' | b'-') && line.get(i + 1).is_some_and(u8::is_ascii_digit)))
+        {
+            // Numbers and dates (1979-05-27T07:32:00)
+            i += 1;
+            while i < line.len() && (is_word(line[i]) || matches!(line[i], b'.' | b'-' | b'+' | b':')) {
+                i += 1;
+            }
+            Kind::Number
+        } else if is_word(b) || b == b'-' {
+            while i < line.len() && (is_word(line[i]) || line[i] == b'-') {
+                i += 1;
+            }
+            match &line[start..i] {
+                b"true" | b"false" if seen_equals => Kind::Number,
+                _ if !seen_equals => Kind::Keyword,
+                _ => continue,
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        spans.push((start..i, kind));
+    }
+}
+
+fn shell(line: &[u8], spans: &mut Vec<(Range<usize>, Kind)>) {
+    let mut i = 0;
+    while i < line.len() {
+        let start = i;
+        let b = line[i];
+        let at_word_start = i == 0 || line[i - 1].is_ascii_whitespace() || line[i - 1] == b';';
+        let kind = if b == b'#' && at_word_start {
+            i = line.len();
+            Kind::Comment
+        } else if b == b'"' || b == b'\'' {
+            i = string_end(line, i + 1, b, b == b'"');
+            Kind::String
+        } else if b == b'$' {
+            // $NAME, ${NAME}, $1, $?
+            i = match line.get(i + 1) {
+                Some(b'{') => line[i..].iter().position(|&c| c == b'}').map_or(line.len(), |p| i + p + 1),
+                Some(&c) if is_word(c) => word_end(line, i + 1),
+                Some(_) => i + 2,
+                None => i + 1,
+            };
+            Kind::Type
+        } else if is_word(b) {
+            i = word_end(line, i);
+            let word = &line[start..i];
+            let whole_word = at_word_start
+                && line.get(i).is_none_or(|&c| c.is_ascii_whitespace() || c == b';');
+            if whole_word && SHELL_KEYWORDS.iter().any(|k| k.as_bytes() == word) {
+                Kind::Keyword
+            } else if word.iter().all(u8::is_ascii_digit) {
+                Kind::Number
+            } else {
+                continue;
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        spans.push((start..i.min(line.len()), kind));
+    }
+}
@@ -0,0 +1,127 @@
+//! Line storage for grape: a gap buffer of lines
+//!
+//! Lines before the gap are kept in order in `before`, lines after it in
+//! reverse in `after`, so inserting or removing at the gap is a push or a
+//! pop and moving the gap costs only the lines it passes. Edits cluster
+//! around the cursor, so the gap rarely moves far however long the file.
+//!
+//! The buffer also remembers the first line changed since it was last
+//! asked, which lets the editor keep per-line caches (highlighting state)
+//! for everything above it.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::{Index, IndexMut, Range};
+
+#[derive(Default)]
+pub struct Lines {
+    before: Vec<String>,
+    /// Reversed: the line just after the gap is last
+    after: Vec<String>,
+    /// Lowest line index changed since `take_changed`
+    changed: Option<usize>,
+}
+
+impl Lines {
+    pub fn len(&self) -> usize {
+        self.before.len() + self.after.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Put the gap just before line `index`
+    fn move_gap(&mut self, index: usize) {
+        while self.before.len() > index {
+            let line = self.before.pop().unwrap();
+            self.after.push(line);
+        }
+        while self.before.len() < index {
+            match self.after.pop() {
+                Some(line) => self.before.push(line),
+                None => break,
+            }
+        }
+    }
+
+    fn mark(&mut self, index: usize) {
+        self.changed = Some(self.changed.map_or(index, |changed| changed.min(index)));
+    }
+
+    pub fn insert(&mut self, index: usize, line: String) {
+        assert!(index <= self.len(), "line index out of range");
+        self.move_gap(index);
+        self.before.push(line);
+        self.mark(index);
+    }
+
+    pub fn remove(&mut self, index: usize) -> String {
+        assert!(index < self.len(), "line index out of range");
+        self.move_gap(index);
+        self.mark(index);
+        self.after.pop().unwrap()
+    }
+
+    pub fn push(&mut self, line: String) {
+        let len = self.len();
+        self.insert(len, line);
+    }
+
+    /// Replace the lines in `range` with `lines`
+    pub fn splice(&mut self, range: Range<usize>, lines: impl IntoIterator<Item = String>) {
+        assert!(range.start <= range.end && range.end <= self.len(), "line range out of range");
+        self.move_gap(range.end);
+        self.before.truncate(range.start);
+        self.before.extend(lines);
+        self.mark(range.start);
+    }
+
+    /// Copy of the lines in `range`
+    pub fn to_vec(&self, range: Range<usize>) -> Vec<String> {
+        range.map(|index| self[index].clone()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> + '_ {
+        self.before.iter().chain(self.after.iter().rev())
+    }
+
+    /// First line changed since the last call, if any
+    pub fn take_changed(&mut self) -> Option<usize> {
+        self.changed.take()
+    }
+}
+
+impl FromIterator<String> for Lines {
+    fn from_iter<I: IntoIterator<Item = String>>(lines: I) -> Self {
+        Lines {
+            before: lines.into_iter().collect(),
+            after: Vec::new(),
+            changed: Some(0),
+        }
+    }
+}
+
+impl Index<usize> for Lines {
+    type Output = String;
+
+    fn index(&self, index: usize) -> &String {
+        match index.checked_sub(self.before.len()) {
+            None => &self.before[index],
+            Some(offset) => &self.after[self.after.len() - 1 - offset],
+        }
+    }
+}
+
+impl IndexMut<usize> for Lines {
+    fn index_mut(&mut self, index: usize) -> &mut String {
+        self.mark(index);
+        match index.checked_sub(self.before.len()) {
+            None => &mut self.before[index],
+            Some(offset) => {
+                let last = self.after.len() - 1;
+                &mut self.after[last - offset]
+            }
+        }
+    }
+}
@@ -1,38 +1,179 @@
 //! Grape Text Editor - Simple nano-like editor for ospabOS
+//!
+//! It takes over the console, or with `open_windowed` draws into a window
+//! of the display service instead, leaving the console underneath alone.
 
+mod highlight;
+mod lines;
+
+use alloc::collections::VecDeque;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::format;
+use crate::common::palette;
 use crate::drivers::framebuffer;
-use crate::services::vfs;
-use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::{clipboard, vfs};
+use crate::ipc::message::{DisplayRequest, DisplayResponse, FSRequest, FSResponse};
+use crate::services::display;
+use crate::keybindings::{self, Action, Context, Key};
+use highlight::Language;
+use lines::Lines;
+
+/// Edits kept for Ctrl+Z
+const UNDO_LIMIT: usize = 100;
+
+/// Actions on the help bar, with their labels
+const HINTS: &[(Action, &str)] = &[
+    (Action::Help, "Help"),
+    (Action::Save, "Save"),
+    (Action::Exit, "Exit"),
+    (Action::Search, "Search"),
+    (Action::Cut, "Cut"),
+    (Action::Paste, "Paste"),
+    (Action::Undo, "Undo"),
+    (Action::Highlight, "Colors"),
+    (Action::Open, "Open"),
+    (Action::Wrap, "Wrap"),
+];
+
+/// "^X Save" for the key bound to `action`; unbound actions are left out
+fn hint(action: Action, label: &str) -> Option<String> {
+    keybindings::key_for(action).map(|key| format!("{} {}", key.label(), label))
+}
+
+/// Bound editor action for `key`
+fn bound_action(key: crate::drivers::keyboard::EditorKey) -> Option<Action> {
+    match key {
+        crate::drivers::keyboard::EditorKey::Char(c) => keybindings::lookup(Context::Editor, Key::Char(c)),
+        _ => None,
+    }
+}
+
+/// One undoable edit: lines `start..start + inserted` replaced `removed`
+struct UndoEntry {
+    start: usize,
+    removed: Vec<String>,
+    inserted: usize,
+    /// Cursor before the edit
+    cursor: (usize, usize),
+    /// Typing within one line; the next keystroke there joins this entry
+    typing: bool,
+}
+
+/// Incremental search in progress (Ctrl+W)
+struct Search {
+    query: String,
+    /// Cursor when the search started, restored on cancel
+    origin: (usize, usize),
+    found: bool,
+}
+
+/// What the editor loop does after a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Exit,
+    /// Open the file in a new buffer, or switch to it if already open
+    Open(String),
+    NextBuffer,
+    PrevBuffer,
+}
+
+/// One text row of the screen: its text and colored byte ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Row {
+    text: String,
+    spans: Vec<(core::ops::Range<usize>, u32, u32)>,
+}
+
+impl Row {
+    /// `text` all in one color
+    fn colored(text: String, fg: u32, bg: u32) -> Self {
+        let spans = Vec::from([(0..text.len(), fg, bg)]);
+        Row { text, spans }
+    }
+}
 
 /// Grape editor state
 pub struct GrapeEditor {
     filename: String,
-    lines: Vec<String>,
+    lines: Lines,
     cursor_row: usize,
     cursor_col: usize,
     scroll_offset: usize,
+    /// First column shown; lines wider than the screen scroll sideways
+    col_offset: usize,
+    /// Visible columns
+    width: usize,
     modified: bool,
     message: Option<String>,
     max_rows: usize,  // Visible rows (screen height - status bar)
+    search: Option<Search>,
+    /// File name being typed after Ctrl+O
+    prompt: Option<String>,
+    /// Last query searched for, reused by Ctrl+W Enter
+    last_query: String,
+    /// Lines cut by the current run of Ctrl+K, also put on the clipboard,
+    /// where Ctrl+U pastes from
+    cut_buffer: Vec<String>,
+    /// The previous key was Ctrl+K, so the next cut adds to the buffer
+    cutting: bool,
+    undo: VecDeque<UndoEntry>,
+    /// Highlighting rules for the file, if its type is known
+    language: Option<Language>,
+    /// Highlighting switched on (Ctrl+T toggles)
+    highlighting: bool,
+    /// Highlighting state at the start of each line, for the lines from
+    /// the top of the file down to the last one drawn
+    highlight_states: Vec<highlight::State>,
+    /// Long lines continue on the next row instead of scrolling sideways
+    wrap: bool,
+    /// Rows on screen, as last drawn
+    drawn: Vec<Row>,
+    /// Cursor position on screen, as last drawn
+    drawn_cursor: Option<(usize, usize)>,
+    /// Display window drawn into instead of the console
+    window: Option<u32>,
 }
 
 impl GrapeEditor {
     /// Create new editor instance
     pub fn new(filename: &str, max_rows: usize) -> Self {
+        // Writing into the last column wraps the console to the next row
+        let (cols, _) = framebuffer::text_size();
         Self {
             filename: filename.to_string(),
-            lines: Vec::new(),
+            lines: Lines::default(),
             cursor_row: 0,
             cursor_col: 0,
             scroll_offset: 0,
+            col_offset: 0,
+            width: if cols > 1 { cols - 1 } else { 80 },
             modified: false,
             message: None,
             max_rows: max_rows.saturating_sub(2), // Reserve 2 lines for status
+            search: None,
+            prompt: None,
+            last_query: String::new(),
+            cut_buffer: Vec::new(),
+            cutting: false,
+            undo: VecDeque::new(),
+            language: Language::detect(filename, ""),
+            highlighting: true,
+            highlight_states: Vec::new(),
+            wrap: false,
+            drawn: Vec::new(),
+            drawn_cursor: None,
+            window: None,
         }
     }
+
+    /// Draw into `window`, `WINDOW_COLS` wide, from now on
+    fn show_in(&mut self, window: u32) {
+        self.window = Some(window);
+        self.width = WINDOW_COLS;
+        self.invalidate();
+    }
     
     /// Load file from VFS
     pub fn load_file(&mut self) -> Result<(), String> {
@@ -48,87 +189,330 @@ impl GrapeEditor {
                     if self.lines.is_empty() {
                         self.lines.push(String::new());
                     }
+                    self.language = Language::detect(&self.filename, &self.lines[0]);
                     Ok(())
                 } else {
                     Err("File is not valid UTF-8".to_string())
                 }
             }
-            FSResponse::Error(msg) => {
+            FSResponse::Error(e, _) => {
                 // File doesn't exist - start with empty buffer
                 self.lines.push(String::new());
-                Err(msg)
+                Err(e.to_string())
             }
             _ => Err("Unexpected response".to_string())
         }
     }
     
-    /// Draw the editor screen
-    pub fn draw(&self) {
-        framebuffer::clear();
-        
-        // Draw file content
-        let end_row = core::cmp::min(
-            self.scroll_offset + self.max_rows,
-            self.lines.len()
-        );
-        
-        for (_screen_row, file_row) in (self.scroll_offset..end_row).enumerate() {
-            let line = &self.lines[file_row];
-            framebuffer::print(line);
-            framebuffer::print_char('\n');
+    /// Draw the editor screen; `buffer` is this buffer's place among the
+    /// open ones (index, count)
+    ///
+    /// Only rows that differ from the last frame are drawn again.
+    pub fn draw(&mut self, buffer: (usize, usize)) {
+        let (mut rows, cursor) = self.layout_text();
+        rows.push(self.status_row(buffer));
+        rows.push(self.help_row());
+        if let Some(window) = self.window {
+            self.draw_window(window, rows, cursor);
+            return;
         }
         
-        // Draw status bar
-        self.draw_status_bar();
-        
-        // Draw help bar
-        self.draw_help_bar();
-        
-        // Draw cursor at editing position
-        if self.cursor_row >= self.scroll_offset && self.cursor_row < end_row {
-            let screen_row = self.cursor_row - self.scroll_offset;
-            framebuffer::draw_cursor_at(screen_row, self.cursor_col, true);
+        // Build the screen offscreen and show it in one go
+        framebuffer::begin_frame();
+        let full = self.drawn.len() != rows.len();
+        if full {
+            framebuffer::clear();
+        }
+        for (index, row) in rows.iter().enumerate() {
+            // The old cursor block is erased by drawing its row again
+            let had_cursor = self.drawn_cursor.is_some_and(|(cursor_row, _)| cursor_row == index);
+            if full || had_cursor || self.drawn[index] != *row {
+                framebuffer::draw_row(index, &row.text, &row.spans);
+            }
+        }
+        if let Some((row, col)) = cursor {
+            framebuffer::draw_cursor_at(row, col, true);
         }
+        framebuffer::present();
+        self.drawn = rows;
+        self.drawn_cursor = cursor;
     }
     
-    /// Draw status bar
-    fn draw_status_bar(&self) {
-        framebuffer::print("\n-- ");
-        framebuffer::print(&self.filename);
-        if self.modified {
-            framebuffer::print(" [Modified]");
+    /// `draw` for a window: repaint the rows that changed into its surface
+    /// and damage them
+    fn draw_window(&mut self, window: u32, rows: Vec<Row>, cursor: Option<(usize, usize)>) {
+        let (cell_width, cell_height) = framebuffer::TEXT_CELL;
+        let palette = palette::current();
+        let full = self.drawn.len() != rows.len();
+        let changed: Vec<usize> = (0..rows.len())
+            .filter(|&index| {
+                let had_cursor = self.drawn_cursor.is_some_and(|(cursor_row, _)| cursor_row == index);
+                full || had_cursor || self.drawn[index] != rows[index]
+            })
+            .collect();
+        let Some(width) = display::with_pixels(window, |pixels, width, _| {
+            for &index in &changed {
+                let row = &rows[index];
+                let y = index * cell_height;
+                if let Some(line) = pixels.get_mut(y * width..(y + cell_height) * width) {
+                    line.fill(palette.background);
+                }
+                let mut char_buf = [0u8; 4];
+                for (col, (i, c)) in row.text.char_indices().enumerate() {
+                    let (fg, bg) = row
+                        .spans
+                        .iter()
+                        .find(|(range, _, _)| range.contains(&i))
+                        .map_or((palette.foreground, palette.background), |&(_, fg, bg)| (fg, bg));
+                    framebuffer::draw_text(pixels, width, col * cell_width, y, c.encode_utf8(&mut char_buf), fg, bg);
+                }
+            }
+            if let Some((row, col)) = cursor {
+                // A block cursor, as on the console
+                let (x, y) = (col * cell_width, row * cell_height);
+                for line in y..y + cell_height {
+                    let start = line * width + x;
+                    if x + cell_width <= width {
+                        if let Some(cell) = pixels.get_mut(start..start + cell_width) {
+                            cell.fill(palette.foreground);
+                        }
+                    }
+                }
+            }
+            width
+        }) else {
+            return;
+        };
+        if let (Some(&first), Some(&last)) = (changed.first(), changed.last()) {
+            display::process(DisplayRequest::Damage {
+                window,
+                x: 0,
+                y: (first * cell_height) as u32,
+                width: width as u32,
+                height: ((last + 1 - first) * cell_height) as u32,
+            });
         }
-        framebuffer::print(" -- ");
-        
-        // Row/Col position
-        framebuffer::print("Ln ");
-        print_num(self.cursor_row + 1);
-        framebuffer::print(", Col ");
-        print_num(self.cursor_col + 1);
+        self.drawn = rows;
+        self.drawn_cursor = cursor;
+    }
+    
+    /// Forget what is on screen, so the next `draw` repaints everything
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+    
+    /// Highlighting state at the start of line `row`, from the cache
+    fn highlight_state(&mut self, language: Language, row: usize) -> highlight::State {
+        if let Some(changed) = self.lines.take_changed() {
+            // States up to and including the changed line's own still hold
+            self.highlight_states.truncate(changed + 1);
+        }
+        if self.highlight_states.is_empty() {
+            self.highlight_states.push(highlight::State::default());
+        }
+        while self.highlight_states.len() <= row {
+            let index = self.highlight_states.len() - 1;
+            let mut state = self.highlight_states[index];
+            highlight::highlight(language, &self.lines[index], &mut state);
+            self.highlight_states.push(state);
+        }
+        self.highlight_states[row]
+    }
+    
+    /// Text rows (padded to `max_rows`) and the screen position of the cursor
+    fn layout_text(&mut self) -> (Vec<Row>, Option<(usize, usize)>) {
+        let syntax = palette::current().syntax;
+        let background = palette::current().background;
+        let language = self.language.filter(|_| self.highlighting);
+        let mut state = match language {
+            Some(language) => self.highlight_state(language, self.scroll_offset),
+            None => highlight::State::default(),
+        };
         
-        // Message if any
-        if let Some(ref msg) = self.message {
-            framebuffer::print(" | ");
-            framebuffer::print(msg);
+        let mut rows = Vec::new();
+        let mut cursor = None;
+        let mut file_row = self.scroll_offset;
+        while rows.len() < self.max_rows && file_row < self.lines.len() {
+            let line = &self.lines[file_row];
+            let spans = match language {
+                Some(language) => highlight::highlight(language, line, &mut state),
+                None => Vec::new(),
+            };
+            // Byte ranges of the line shown on each screen row
+            let pieces: Vec<core::ops::Range<usize>> = if self.wrap {
+                let starts: Vec<usize> = line.char_indices().map(|(i, _)| i).step_by(self.width).collect();
+                let chars = line.chars().count();
+                (0..chars / self.width + 1)
+                    .map(|piece| {
+                        let start = starts.get(piece).copied().unwrap_or(line.len());
+                        let end = starts.get(piece + 1).copied().unwrap_or(line.len());
+                        start..end
+                    })
+                    .collect()
+            } else {
+                Vec::from([self.visible_range(line)])
+            };
+            if file_row == self.cursor_row {
+                let (piece, col) = if self.wrap {
+                    (self.cursor_col / self.width, self.cursor_col % self.width)
+                } else {
+                    (0, self.cursor_col.saturating_sub(self.col_offset))
+                };
+                if rows.len() + piece < self.max_rows {
+                    cursor = Some((rows.len() + piece, col));
+                }
+            }
+            for visible in pieces {
+                if rows.len() == self.max_rows {
+                    break;
+                }
+                // Spans of the whole line, clipped to this piece
+                let spans = spans.iter()
+                    .filter_map(|(range, kind)| {
+                        let start = range.start.max(visible.start);
+                        let end = range.end.min(visible.end);
+                        (start < end).then(|| (start - visible.start..end - visible.start, kind.color(&syntax), background))
+                    })
+                    .collect();
+                rows.push(Row { text: line[visible].to_string(), spans });
+            }
+            file_row += 1;
         }
+        rows.resize(self.max_rows, Row::default());
+        (rows, cursor)
+    }
+    
+    /// Screen rows line `row` takes when wrapping
+    fn wrapped_rows(&self, row: usize) -> usize {
+        self.lines[row].chars().count() / self.width + 1
+    }
+    
+    /// Byte range of `line` inside the columns on screen
+    fn visible_range(&self, line: &str) -> core::ops::Range<usize> {
+        let mut columns = line.char_indices().map(|(i, _)| i).chain([line.len()]);
+        let start = columns.nth(self.col_offset).unwrap_or(line.len());
+        let end = columns.nth(self.width.saturating_sub(1)).unwrap_or(line.len());
+        start..end
+    }
+    
+    /// Scroll so the cursor is on screen: sideways without wrapping, down
+    /// past wrapped lines with it
+    fn keep_cursor_visible(&mut self) {
+        if self.wrap {
+            self.col_offset = 0;
+            self.scroll_offset = self.scroll_offset.min(self.cursor_row);
+            let below = |editor: &Self| {
+                (editor.scroll_offset..editor.cursor_row).map(|row| editor.wrapped_rows(row)).sum::<usize>()
+                    + editor.cursor_col / editor.width
+            };
+            while self.scroll_offset < self.cursor_row && below(self) >= self.max_rows {
+                self.scroll_offset += 1;
+            }
+            return;
+        }
+        if self.cursor_col < self.col_offset {
+            self.col_offset = self.cursor_col;
+        } else if self.cursor_col >= self.col_offset + self.width {
+            self.col_offset = self.cursor_col + 1 - self.width;
+        }
+    }
+    
+    /// Status bar row
+    fn status_row(&self, (index, count): (usize, usize)) -> Row {
+        let palette = palette::current();
+        let status = if let Some(ref name) = self.prompt {
+            format!("Open: {}", name)
+        } else if let Some(ref search) = self.search {
+            let mut prompt = format!("Search: {}", search.query);
+            if !search.found && !search.query.is_empty() {
+                prompt.push_str("  [not found]");
+            }
+            prompt
+        } else {
+            let mut status = format!("-- {}", self.filename);
+            if count > 1 {
+                status.push_str(&format!(" [{}/{}]", index + 1, count));
+            }
+            if self.modified {
+                status.push_str(" [Modified]");
+            }
+            
+            if let Some(language) = self.language {
+                status.push_str(" -- ");
+                status.push_str(language.name());
+                if !self.highlighting {
+                    status.push_str(" (no colors)");
+                }
+            }
+            if self.wrap {
+                status.push_str(" -- wrap");
+            }
+            
+            // Row/Col position
+            status.push_str(&format!(" -- Ln {}, Col {}", self.cursor_row + 1, self.cursor_col + 1));
+            
+            // Message if any
+            if let Some(ref msg) = self.message {
+                status.push_str(" | ");
+                status.push_str(msg);
+            }
+            status
+        };
+        Row::colored(status, palette.status_fg, palette.status_bg)
     }
     
-    /// Draw help bar
-    fn draw_help_bar(&self) {
-        framebuffer::print_char('\n');
-        framebuffer::print("^G Help  ^X Save  ^C Exit  ^W Search  ^K Cut  ^U Paste");
+    /// Help bar row
+    fn help_row(&self) -> Row {
+        let palette = palette::current();
+        let help: Vec<String> = if self.prompt.is_some() {
+            ["Enter Open".to_string(), "Esc Cancel".to_string()].into()
+        } else if self.search.is_some() {
+            ["Enter Done".to_string()].into_iter()
+                .chain(hint(Action::Search, "Next"))
+                .chain(["Esc Cancel".to_string()])
+                .collect()
+        } else {
+            HINTS.iter().filter_map(|&(action, label)| hint(action, label)).collect()
+        };
+        Row::colored(help.join("  "), palette.status_fg, palette.status_bg)
     }
     
     /// Handle key input
-    pub fn handle_key(&mut self, key: crate::drivers::keyboard::EditorKey) -> bool {
+    pub fn handle_key(&mut self, key: crate::drivers::keyboard::EditorKey) -> Command {
+        let command = self.dispatch_key(key);
+        self.keep_cursor_visible();
+        command
+    }
+    
+    fn dispatch_key(&mut self, key: crate::drivers::keyboard::EditorKey) -> Command {
         use crate::drivers::keyboard::EditorKey;
         
+        if self.prompt.is_some() {
+            return self.handle_prompt_key(key);
+        }
+        if self.search.is_some() {
+            self.handle_search_key(key);
+            return Command::Continue;
+        }
+        // Anything but typing ends the current undo entry, anything but
+        // another cut the current cut
+        let action = bound_action(key);
+        if !matches!(key, EditorKey::Char(c) if c >= ' ' || c == '\x08') {
+            self.seal_undo();
+        }
+        if action != Some(Action::Cut) {
+            self.cutting = false;
+        }
+        if let Some(action) = action {
+            return self.run_action(action);
+        }
+        
         match key {
-            EditorKey::Char(c) => return self.handle_char_input(c),
+            EditorKey::Char(c) => self.handle_char_input(c),
             EditorKey::ArrowUp => self.move_up(),
             EditorKey::ArrowDown => self.move_down(),
-            EditorKey::ArrowLeft => self.move_left(),
-            EditorKey::ArrowRight => self.move_right(),
+            EditorKey::ArrowLeft | EditorKey::CtrlArrowLeft => self.move_left(),
+            EditorKey::ArrowRight | EditorKey::CtrlArrowRight => self.move_right(),
             EditorKey::PageUp => {
                 for _ in 0..10 {
                     self.move_up();
@@ -140,18 +524,28 @@ impl GrapeEditor {
                 }
             }
             EditorKey::Home => {
+                // Ctrl+Home goes to the top of the file
+                if crate::drivers::keyboard::modifiers() & crate::drivers::keyboard::MOD_CTRL != 0 {
+                    self.cursor_row = 0;
+                }
                 self.cursor_col = 0;
             }
             EditorKey::End => {
+                // Ctrl+End goes to the bottom of the file
+                if crate::drivers::keyboard::modifiers() & crate::drivers::keyboard::MOD_CTRL != 0 {
+                    self.cursor_row = self.lines.len() - 1;
+                }
                 self.cursor_col = self.lines[self.cursor_row].len();
             }
             EditorKey::Delete => {
                 // Delete character at cursor
                 if self.cursor_col < self.lines[self.cursor_row].len() {
+                    self.record(self.cursor_row, 1, 1, false);
                     self.lines[self.cursor_row].remove(self.cursor_col);
                     self.modified = true;
                 } else if self.cursor_row + 1 < self.lines.len() {
                     // Join with next line
+                    self.record(self.cursor_row, 2, 1, false);
                     let next_line = self.lines.remove(self.cursor_row + 1);
                     self.lines[self.cursor_row].push_str(&next_line);
                     self.modified = true;
@@ -159,39 +553,76 @@ impl GrapeEditor {
             }
         }
         
-        false // Don't exit
+        Command::Continue
     }
     
-    /// Handle character input (separate from key navigation)
-    fn handle_char_input(&mut self, c: char) -> bool {
-            match c {
-                // Ctrl+G = Get Help
-                '\x07' => {
-                    self.show_help();
-                }
-                // Ctrl+X = Save (Write Out)
-                '\x18' => {
-                    self.save_file();
-                }
-                // Ctrl+C = Exit (always exit, show warning if modified)
-                '\x03' => {
-                    if self.modified {
-                        self.message = Some("Warning: Unsaved changes! (^X to save)".to_string());
-                    }
-                    return true; // Always exit
-                }
-                // Ctrl+W = Where Is (Search)
-                '\x17' => {
-                    self.message = Some("Search: Not implemented yet".to_string());
-                }
-                // Ctrl+K = Cut line
-                '\x0B' => {
-                    self.message = Some("Cut: Not implemented yet".to_string());
+    /// Perform a bound action
+    fn run_action(&mut self, action: Action) -> Command {
+        match action {
+            Action::Help => self.show_help(),
+            Action::Save => self.save_file(),
+            // The editor loop warns about unsaved buffers
+            Action::Exit => return Command::Exit,
+            Action::Open => {
+                self.message = None;
+                self.prompt = Some(String::new());
+            }
+            Action::Wrap => self.wrap = !self.wrap,
+            Action::NextBuffer => return Command::NextBuffer,
+            Action::PrevBuffer => return Command::PrevBuffer,
+            Action::Search => {
+                self.message = None;
+                self.search = Some(Search {
+                    query: String::new(),
+                    origin: (self.cursor_row, self.cursor_col),
+                    found: false,
+                });
+            }
+            Action::Cut => self.cut_line(),
+            Action::Paste => self.paste(),
+            Action::Undo => self.undo(),
+            Action::Highlight => match self.language {
+                Some(_) => self.highlighting = !self.highlighting,
+                None => self.message = Some("No highlighting for this file type".to_string()),
+            },
+            // Shell actions are only bound in the shell context
+            _ => {}
+        }
+        Command::Continue
+    }
+    
+    /// Handle a key while the Ctrl+O prompt is open
+    fn handle_prompt_key(&mut self, key: crate::drivers::keyboard::EditorKey) -> Command {
+        use crate::drivers::keyboard::EditorKey;
+        
+        let name = match self.prompt.as_mut() {
+            Some(name) => name,
+            None => return Command::Continue,
+        };
+        match key {
+            _ if matches!(key, EditorKey::Char('\x1b')) || bound_action(key) == Some(Action::Exit) => {
+                self.prompt = None;
+            }
+            EditorKey::Char('\n') | EditorKey::Char('\r') => {
+                let name = core::mem::take(name);
+                self.prompt = None;
+                let name = name.trim();
+                if !name.is_empty() {
+                    return Command::Open(name.to_string());
                 }
-                // Ctrl+U = Uncut (Paste)
-            '\x15' => {
-                self.message = Some("Paste: Not implemented yet".to_string());
             }
+            EditorKey::Char('\x08') => {
+                name.pop();
+            }
+            EditorKey::Char(c) if c >= ' ' => name.push(c),
+            _ => {}
+        }
+        Command::Continue
+    }
+    
+    /// Handle character input (separate from key navigation)
+    fn handle_char_input(&mut self, c: char) {
+        match c {
             // Backspace
             '\x08' => {
                 self.handle_backspace();
@@ -206,13 +637,212 @@ impl GrapeEditor {
             }
             _ => {}
         }
-        
-        false // Don't exit by default
     }
     
     /// Show help screen
     fn show_help(&mut self) {
-        self.message = Some("^G=Help ^X=Save ^C=Exit ^W=Search ^K=Cut ^U=Paste".to_string());
+        let hints: Vec<String> = HINTS.iter()
+            .filter_map(|&(action, label)| keybindings::key_for(action).map(|key| format!("{}={}", key.label(), label)))
+            .collect();
+        self.message = Some(hints.join(" "));
+    }
+    
+    /// Handle a key while the search prompt is open
+    fn handle_search_key(&mut self, key: crate::drivers::keyboard::EditorKey) {
+        use crate::drivers::keyboard::EditorKey;
+        
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return,
+        };
+        match key {
+            // Esc or the exit key: back to where the search started
+            _ if matches!(key, EditorKey::Char('\x1b')) || bound_action(key) == Some(Action::Exit) => {
+                (self.cursor_row, self.cursor_col) = search.origin;
+                self.search = None;
+                self.scroll_to_cursor();
+            }
+            EditorKey::Char('\n') | EditorKey::Char('\r') => {
+                let query = core::mem::take(&mut search.query);
+                self.search = None;
+                if !query.is_empty() {
+                    self.last_query = query;
+                } else if !self.last_query.is_empty() {
+                    // Enter on an empty prompt repeats the last search
+                    let query = self.last_query.clone();
+                    if !self.find_next(&query, self.cursor_row, self.cursor_col + 1) {
+                        self.message = Some(format!("\"{}\" not found", query));
+                    }
+                }
+            }
+            // The search key again: next match after the cursor
+            _ if bound_action(key) == Some(Action::Search) => {
+                let query = search.query.clone();
+                let found = query.is_empty() || self.find_next(&query, self.cursor_row, self.cursor_col + 1);
+                if let Some(search) = self.search.as_mut() {
+                    search.found = found;
+                }
+            }
+            EditorKey::Char('\x08') => {
+                search.query.pop();
+                self.search_from_origin();
+            }
+            EditorKey::Char(c) if c >= ' ' => {
+                search.query.push(c);
+                self.search_from_origin();
+            }
+            _ => {}
+        }
+    }
+    
+    /// Find the query from where the search started, as it is typed
+    fn search_from_origin(&mut self) {
+        let (query, (row, col)) = match &self.search {
+            Some(search) => (search.query.clone(), search.origin),
+            None => return,
+        };
+        let found = query.is_empty() || self.find_next(&query, row, col);
+        if query.is_empty() {
+            (self.cursor_row, self.cursor_col) = (row, col);
+            self.scroll_to_cursor();
+        }
+        if let Some(search) = self.search.as_mut() {
+            search.found = found;
+        }
+    }
+    
+    /// Move the cursor to the first match of `query` at or after (row,
+    /// col), wrapping around the end of the file
+    fn find_next(&mut self, query: &str, row: usize, col: usize) -> bool {
+        if query.is_empty() {
+            return false;
+        }
+        let count = self.lines.len();
+        for i in 0..=count {
+            let r = (row + i) % count;
+            let line = &self.lines[r];
+            // The starting line is searched from `col` first and, after
+            // wrapping around, up to it
+            let from = if i == 0 { col.min(line.len()) } else { 0 };
+            let found = match line.get(from..).and_then(|rest| rest.find(query)) {
+                Some(pos) if i < count || from + pos < col => Some(from + pos),
+                _ => None,
+            };
+            if let Some(pos) = found {
+                self.cursor_row = r;
+                self.cursor_col = pos;
+                self.scroll_to_cursor();
+                return true;
+            }
+        }
+        false
+    }
+    
+    /// Scroll so the cursor row is on screen
+    fn scroll_to_cursor(&mut self) {
+        if self.cursor_row < self.scroll_offset {
+            self.scroll_offset = self.cursor_row;
+        } else if self.cursor_row >= self.scroll_offset + self.max_rows {
+            self.scroll_offset = self.cursor_row + 1 - self.max_rows;
+        }
+    }
+    
+    /// Cut the cursor line; consecutive cuts collect lines together
+    fn cut_line(&mut self) {
+        if !self.cutting {
+            self.cut_buffer.clear();
+        }
+        self.cutting = true;
+        
+        let row = self.cursor_row;
+        if self.lines.len() == 1 {
+            // The buffer always keeps one (possibly empty) line
+            self.record(row, 1, 1, false);
+            self.cut_buffer.push(core::mem::take(&mut self.lines[0]));
+        } else {
+            self.record(row, 1, 0, false);
+            self.cut_buffer.push(self.lines.remove(row));
+            if self.cursor_row >= self.lines.len() {
+                self.cursor_row = self.lines.len() - 1;
+            }
+        }
+        self.cursor_col = 0;
+        self.modified = true;
+        self.scroll_to_cursor();
+
+        // Each line ends in '\n', so a cut empty line is not an empty clipboard
+        let text: String = self.cut_buffer.iter().map(|line| format!("{}\n", line)).collect();
+        if clipboard::set(text).is_err() {
+            self.message = Some("Too much text for the clipboard".to_string());
+        }
+    }
+    
+    /// Insert the lines on the clipboard above the cursor line
+    fn paste(&mut self) {
+        let text = clipboard::get();
+        if text.is_empty() {
+            self.message = Some("Clipboard is empty".to_string());
+            return;
+        }
+        let pasted: Vec<String> = text.strip_suffix('\n').unwrap_or(&text).split('\n').map(String::from).collect();
+        let row = self.cursor_row;
+        let count = pasted.len();
+        self.record(row, 0, count, false);
+        self.lines.splice(row..row, pasted);
+        self.cursor_row = row + count;
+        self.cursor_col = 0;
+        self.modified = true;
+        self.scroll_to_cursor();
+    }
+    
+    /// Remember lines `start..start + old_len` before an edit replaces them
+    /// with `new_len` lines
+    fn record(&mut self, start: usize, old_len: usize, new_len: usize, typing: bool) {
+        if typing {
+            if let Some(top) = self.undo.back() {
+                if top.typing && top.start == start && top.inserted == 1 {
+                    return;
+                }
+            }
+        }
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(UndoEntry {
+            start,
+            removed: self.lines.to_vec(start..start + old_len),
+            inserted: new_len,
+            cursor: (self.cursor_row, self.cursor_col),
+            typing,
+        });
+    }
+    
+    /// Stop the next keystroke from joining the last undo entry
+    fn seal_undo(&mut self) {
+        if let Some(top) = self.undo.back_mut() {
+            top.typing = false;
+        }
+    }
+    
+    /// Revert the last edit
+    fn undo(&mut self) {
+        let entry = match self.undo.pop_back() {
+            Some(entry) => entry,
+            None => {
+                self.message = Some("Nothing to undo".to_string());
+                return;
+            }
+        };
+        let end = (entry.start + entry.inserted).min(self.lines.len());
+        self.lines.splice(entry.start..end, entry.removed);
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        (self.cursor_row, self.cursor_col) = entry.cursor;
+        self.cursor_row = self.cursor_row.min(self.lines.len() - 1);
+        self.cursor_col = self.cursor_col.min(self.lines[self.cursor_row].len());
+        self.modified = true;
+        self.scroll_to_cursor();
     }
     
     /// Save file
@@ -236,8 +866,8 @@ impl GrapeEditor {
                 self.modified = false;
                 self.message = Some("Saved!".to_string());
             }
-            FSResponse::Error(msg) => {
-                self.message = Some(format!("Save failed: {}", msg));
+            FSResponse::Error(e, _) => {
+                self.message = Some(format!("Save failed: {}", e));
             }
             _ => {}
         }
@@ -246,6 +876,7 @@ impl GrapeEditor {
     /// Handle backspace
     fn handle_backspace(&mut self) {
         if self.cursor_col > 0 {
+            self.record(self.cursor_row, 1, 1, true);
             let line = &mut self.lines[self.cursor_row];
             if self.cursor_col <= line.len() {
                 line.remove(self.cursor_col - 1);
@@ -254,6 +885,8 @@ impl GrapeEditor {
             }
         } else if self.cursor_row > 0 {
             // Join with previous line
+            self.seal_undo();
+            self.record(self.cursor_row - 1, 2, 1, false);
             let current_line = self.lines.remove(self.cursor_row);
             self.cursor_row -= 1;
             self.cursor_col = self.lines[self.cursor_row].len();
@@ -264,6 +897,8 @@ impl GrapeEditor {
     
     /// Handle enter (new line)
     fn handle_enter(&mut self) {
+        self.seal_undo();
+        self.record(self.cursor_row, 1, 2, false);
         let line = &mut self.lines[self.cursor_row];
         let remainder = line.split_off(self.cursor_col);
         self.cursor_row += 1;
@@ -279,6 +914,7 @@ impl GrapeEditor {
     
     /// Handle regular character input
     fn handle_char(&mut self, c: char) {
+        self.record(self.cursor_row, 1, 1, true);
         let line = &mut self.lines[self.cursor_row];
         line.insert(self.cursor_col, c);
         self.cursor_col += 1;
@@ -339,48 +975,119 @@ impl GrapeEditor {
     }
 }
 
-/// Helper to print numbers
-fn print_num(n: usize) {
-    if n == 0 {
-        framebuffer::print_char('0');
-        return;
+/// Rows the editor uses, status bars included
+const SCREEN_ROWS: usize = 20;
+
+/// Columns of the editor's window
+const WINDOW_COLS: usize = 80;
+
+/// Load `filename` into a new buffer, drawn into `window` if there is one
+fn open_buffer(filename: &str, window: Option<u32>) -> GrapeEditor {
+    let mut editor = GrapeEditor::new(filename, SCREEN_ROWS);
+    if let Some(window) = window {
+        editor.show_in(window);
     }
-    
-    let mut buf = [0u8; 20];
-    let mut i = 0;
-    let mut num = n;
-    
-    while num > 0 {
-        buf[i] = b'0' + (num % 10) as u8;
-        num /= 10;
-        i += 1;
+    match editor.load_file() {
+        Ok(_) => editor.message = Some("File loaded".to_string()),
+        Err(_) => editor.message = Some("New file".to_string()),
     }
-    
-    for j in (0..i).rev() {
-        framebuffer::print_char(buf[j] as char);
+    editor
+}
+
+/// Wait for a key; with a window open, the windows are served meanwhile
+/// (nothing else does while the shell waits on us)
+fn next_key(window: Option<u32>) -> crate::drivers::keyboard::EditorKey {
+    loop {
+        if let Some(key) = crate::drivers::keyboard::try_read_editor_key() {
+            return key;
+        }
+        if window.is_some() {
+            crate::drivers::mouse::update_pointer();
+            display::poll();
+        }
+        core::hint::spin_loop();
     }
 }
 
 /// Open file in grape editor
+///
+/// More files can be opened into buffers of their own with Ctrl+O and
+/// switched between with Ctrl+Left/Right; the last search follows the
+/// switch, and cut lines are on the clipboard for all of them.
 pub fn open(filename: &str) -> Result<(), String> {
-    let mut editor = GrapeEditor::new(filename, 20); // ~20 lines visible
-    
-    // Try to load file
-    match editor.load_file() {
-        Ok(_) => editor.message = Some("File loaded".to_string()),
-        Err(_) => editor.message = Some("New file".to_string()),
-    }
+    run(filename, None)
+}
+
+/// Open file in grape editor, in a window of its own
+pub fn open_windowed(filename: &str) -> Result<(), String> {
+    let (cell_width, cell_height) = framebuffer::TEXT_CELL;
+    let owner = crate::task::scheduler::SCHEDULER.lock().current_pid();
+    let window = match display::process(DisplayRequest::Create {
+        owner,
+        title: format!("grape - {}", filename),
+        x: 60,
+        y: 60,
+        width: (WINDOW_COLS * cell_width) as u32,
+        height: (SCREEN_ROWS * cell_height) as u32,
+    }) {
+        DisplayResponse::Created(window) => window,
+        DisplayResponse::Error(error, detail) => {
+            return Err(format!("cannot open a window: {}", detail.unwrap_or_else(|| error.as_str().to_string())));
+        }
+        DisplayResponse::Success => return Err("cannot open a window".to_string()),
+    };
+    let result = run(filename, Some(window));
+    display::process(DisplayRequest::Destroy { window });
+    result
+}
+
+/// The editor loop, on the console or in `window`
+fn run(filename: &str, window: Option<u32>) -> Result<(), String> {
+    let mut buffers = Vec::from([open_buffer(filename, window)]);
+    let mut current = 0;
+    // Exit was pressed with unsaved buffers; pressing it again quits
+    let mut confirm_exit = false;
     
     // Main editor loop - handle keyboard input
     loop {
-        editor.draw();
+        buffers[current].draw((current, buffers.len()));
         
         // Wait for keyboard input
-        if let Some(key) = crate::drivers::keyboard::read_editor_key_blocking() {
-            let should_exit = editor.handle_key(key);
-            if should_exit {
-                break;  // Exit requested
+        let key = next_key(window);
+        let next = match buffers[current].handle_key(key) {
+            Command::Continue => {
+                confirm_exit = false;
+                continue;
             }
+            Command::Exit => {
+                let unsaved: Vec<&str> = buffers.iter()
+                    .filter(|buffer| buffer.modified)
+                    .map(|buffer| buffer.filename.as_str())
+                    .collect();
+                if unsaved.is_empty() || confirm_exit {
+                    break;
+                }
+                let again = hint(Action::Exit, "again").unwrap_or_else(|| "Exit again".to_string());
+                buffers[current].message = Some(format!("Unsaved: {} ({} to quit anyway)", unsaved.join(", "), again));
+                confirm_exit = true;
+                continue;
+            }
+            Command::Open(name) => match buffers.iter().position(|buffer| buffer.filename == name) {
+                Some(index) => index,
+                None => {
+                    buffers.push(open_buffer(&name, window));
+                    buffers.len() - 1
+                }
+            },
+            Command::NextBuffer => (current + 1) % buffers.len(),
+            Command::PrevBuffer => (current + buffers.len() - 1) % buffers.len(),
+        };
+        confirm_exit = false;
+        if next != current {
+            let last_query = core::mem::take(&mut buffers[current].last_query);
+            buffers[next].last_query = last_query;
+            buffers[next].invalidate();
+            current = next;
         }
     }
     
@@ -0,0 +1,348 @@
+//! Boot scripts and service supervision
+//!
+//! Once every init call has finished, the "init" task runs the scripts in
+//! /etc/init.d in name order (`S10net` before `S20echod`) as shell
+//! scripts, and the prompt waits for it. A script that never ends
+//! holds up the prompt: long-running programs belong in a service.
+//!
+//! A service is a program described in /etc/ospab/services/<name>.conf:
+//!
+//! ```text
+//! exec = /bin/echod --port 7
+//! restart = on-failure
+//! ```
+//!
+//! `restart` is `always`, `on-failure` (the default: any status but 0) or
+//! `never`. The "svcd" task starts services, collects them when they exit
+//! and restarts them after a delay, giving up after MAX_RESTARTS failures
+//! in a row. `service <name> start|stop|restart|status` drives it, from
+//! the shell or from a script.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+use crate::task::scheduler::{WaitStatus, SCHEDULER};
+
+pub const SCRIPT_DIR: &str = "/etc/init.d";
+pub const SERVICE_DIR: &str = "/etc/ospab/services";
+
+/// How often svcd looks for work
+const POLL_MS: u64 = 100;
+/// Pause before a service that exited is started again
+const RESTART_DELAY_MS: u64 = 1000;
+/// Failures in a row after which a service is left stopped
+const MAX_RESTARTS: u32 = 5;
+/// How long `stop` waits after SIGTERM before it sends SIGKILL
+const STOP_TIMEOUT_MS: u64 = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    Always,
+    OnFailure,
+    Never,
+}
+
+impl Restart {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "always" => Some(Restart::Always),
+            "on-failure" => Some(Restart::OnFailure),
+            "never" | "no" => Some(Restart::Never),
+            _ => None,
+        }
+    }
+}
+
+struct Service {
+    /// Program and its arguments
+    argv: Vec<String>,
+    restart: Restart,
+    /// Should be running; cleared by `stop` and when svcd gives up
+    wanted: bool,
+    pid: Option<u32>,
+    last_status: Option<i32>,
+    /// Unsuccessful exits in a row
+    failures: u32,
+    /// Uptime in ms at which svcd may start it
+    start_at: u64,
+    /// SIGKILL is due at this uptime unless it exits first
+    kill_at: Option<u64>,
+}
+
+/// Every service started since boot, by name
+static SERVICES: Mutex<BTreeMap<String, Service>> = Mutex::new(BTreeMap::new());
+
+/// The init.d scripts have all run
+static SCRIPTS_DONE: AtomicBool = AtomicBool::new(false);
+
+fn now_ms() -> u64 {
+    crate::drivers::timer::get_uptime_ms()
+}
+
+/// Start the init and svcd tasks; the scripts wait for the init calls
+pub fn start() {
+    crate::task::spawn_kernel_task("svcd", supervisor);
+    crate::task::spawn_kernel_task("init", init_task);
+}
+
+/// Block until the init.d scripts have run
+pub fn wait_scripts() {
+    while !SCRIPTS_DONE.load(Ordering::Acquire) {
+        crate::task::scheduler::yield_now();
+    }
+}
+
+fn init_task() -> ! {
+    crate::boot::initcall::wait_all();
+    let scripts = match vfs::process_request(FSRequest::ListDir { path: SCRIPT_DIR.to_string() }) {
+        FSResponse::DirListing(names) => names,
+        _ => Vec::new(),
+    };
+    for name in scripts {
+        let path = format!("{}/{}", SCRIPT_DIR, name);
+        crate::serial_println!("[INIT] running {}", path);
+        match crate::shell::run_path(&path, &[&path]) {
+            Ok(0) => {}
+            Ok(status) => crate::serial_println!("[INIT] {}: exit status {}", path, status),
+            Err(e) => crate::serial_println!("[INIT] {}: {}", path, e),
+        }
+    }
+    SCRIPTS_DONE.store(true, Ordering::Release);
+
+    SCHEDULER.lock().terminate_current(0);
+    loop {
+        crate::task::scheduler::yield_now();
+    }
+}
+
+/// Read /etc/ospab/services/<name>.conf
+fn load_definition(name: &str) -> Result<(Vec<String>, Restart), String> {
+    let path = format!("{}/{}.conf", SERVICE_DIR, name);
+    let FSResponse::FileData(data) = vfs::process_request(FSRequest::ReadFile { path: path.clone() }) else {
+        return Err(format!("{}: no such service", name));
+    };
+    let text = String::from_utf8_lossy(&data);
+    let mut argv = Vec::new();
+    let mut restart = Restart::OnFailure;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{}: line {}: expected 'key = value'", path, number + 1));
+        };
+        match key.trim() {
+            "exec" => argv = value.split_whitespace().map(String::from).collect(),
+            "restart" => {
+                restart = Restart::parse(value.trim())
+                    .ok_or_else(|| format!("{}: line {}: unknown restart policy '{}'", path, number + 1, value.trim()))?
+            }
+            other => return Err(format!("{}: line {}: unknown key '{}'", path, number + 1, other)),
+        }
+    }
+    if argv.is_empty() {
+        return Err(format!("{}: no exec line", path));
+    }
+    Ok((argv, restart))
+}
+
+/// Have svcd start service `name`, rereading its definition
+pub fn start_service(name: &str) -> Result<(), String> {
+    let (argv, restart) = load_definition(name)?;
+    let mut services = SERVICES.lock();
+    if let Some(service) = services.get(name) {
+        if service.pid.is_some() {
+            return Err(format!("{} is already running", name));
+        }
+    }
+    services.insert(
+        name.to_string(),
+        Service {
+            argv,
+            restart,
+            wanted: true,
+            pid: None,
+            last_status: None,
+            failures: 0,
+            start_at: now_ms(),
+            kill_at: None,
+        },
+    );
+    Ok(())
+}
+
+/// Ask service `name` to exit: SIGTERM now, SIGKILL if it is still running
+/// after STOP_TIMEOUT_MS
+pub fn stop_service(name: &str) -> Result<(), String> {
+    let mut services = SERVICES.lock();
+    let service = services.get_mut(name).ok_or_else(|| format!("{} is not running", name))?;
+    service.wanted = false;
+    let Some(pid) = service.pid else { return Ok(()) };
+    service.kill_at = Some(now_ms() + STOP_TIMEOUT_MS);
+    drop(services);
+    crate::task::signal::send(pid, crate::syscall::abi::SIGTERM as u32).map_err(|errno| format!("errno {}", errno))
+}
+
+fn is_running(name: &str) -> bool {
+    SERVICES.lock().get(name).is_some_and(|service| service.pid.is_some())
+}
+
+/// Stop service `name` if it is running, wait for svcd to collect it and
+/// start it again
+pub fn restart_service(name: &str) -> Result<(), String> {
+    if is_running(name) {
+        stop_service(name)?;
+        while is_running(name) {
+            crate::task::scheduler::yield_now();
+        }
+    }
+    start_service(name)
+}
+
+/// One line describing service `name`, as `service status` prints it
+pub fn status(name: &str) -> Option<String> {
+    let services = SERVICES.lock();
+    let service = services.get(name)?;
+    let last = service.last_status.map(|status| format!(", last exit status {}", status)).unwrap_or_default();
+    let state = match (service.pid, service.wanted) {
+        (Some(pid), true) => format!("running (pid {})", pid),
+        (Some(pid), false) => format!("stopping (pid {})", pid),
+        (None, true) => "starting".to_string(),
+        (None, false) if service.failures > MAX_RESTARTS => format!("failed after {} restarts", MAX_RESTARTS),
+        (None, false) => "stopped".to_string(),
+    };
+    Some(format!("{}: {}{}", name, state, last))
+}
+
+/// Names of every service started since boot
+pub fn list() -> Vec<String> {
+    SERVICES.lock().keys().cloned().collect()
+}
+
+/// Service tasks are children of svcd, which collects and restarts them
+fn supervisor() -> ! {
+    loop {
+        reap();
+        kill_overdue();
+        spawn_due();
+        let _ = crate::timers::sleep(crate::timers::ms_to_ticks(POLL_MS));
+    }
+}
+
+/// Record every service that has exited and schedule its restart
+fn reap() {
+    loop {
+        let (result, dead) = {
+            let mut scheduler = SCHEDULER.lock();
+            let me = scheduler.current_pid();
+            (scheduler.reap(me, None), scheduler.take_dead())
+        };
+        crate::task::release_dead(dead);
+        let WaitStatus::Exited(pid, status) = result else { return };
+
+        let mut services = SERVICES.lock();
+        let Some((name, service)) = services.iter_mut().find(|(_, service)| service.pid == Some(pid)) else {
+            continue;
+        };
+        crate::serial_println!("[INIT] service {} (pid {}) exited with status {}", name, pid, status);
+        service.pid = None;
+        service.kill_at = None;
+        service.last_status = Some(status);
+        if !service.wanted {
+            continue;
+        }
+        let restart = match service.restart {
+            Restart::Always => true,
+            Restart::OnFailure => status != 0,
+            Restart::Never => false,
+        };
+        if status == 0 {
+            service.failures = 0;
+        } else {
+            service.failures += 1;
+        }
+        if !restart {
+            service.wanted = false;
+        } else if service.failures > MAX_RESTARTS {
+            crate::serial_println!("[INIT] service {} keeps failing, giving up", name);
+            service.wanted = false;
+        } else {
+            service.start_at = now_ms() + RESTART_DELAY_MS;
+        }
+    }
+}
+
+/// SIGKILL services that ignored `stop`
+fn kill_overdue() {
+    let now = now_ms();
+    let overdue: Vec<u32> = SERVICES
+        .lock()
+        .values_mut()
+        .filter(|service| service.kill_at.is_some_and(|at| now >= at))
+        .filter_map(|service| {
+            service.kill_at = None;
+            service.pid
+        })
+        .collect();
+    for pid in overdue {
+        let _ = crate::task::signal::send(pid, crate::syscall::abi::SIGKILL as u32);
+    }
+}
+
+/// Start the services that are wanted and due
+fn spawn_due() {
+    let now = now_ms();
+    let due: Vec<(String, Vec<String>)> = SERVICES
+        .lock()
+        .iter()
+        .filter(|(_, service)| service.wanted && service.pid.is_none() && now >= service.start_at)
+        .map(|(name, service)| (name.clone(), service.argv.clone()))
+        .collect();
+
+    for (name, argv) in due {
+        let result = spawn(&argv);
+        let mut services = SERVICES.lock();
+        let Some(service) = services.get_mut(&name) else { continue };
+        match result {
+            Ok(pid) => {
+                crate::serial_println!("[INIT] service {} started (pid {})", name, pid);
+                service.pid = Some(pid);
+            }
+            // As if it had run and failed
+            Err(e) => {
+                crate::serial_println!("[INIT] service {}: {}: {}", name, argv[0], e);
+                service.last_status = Some(127);
+                service.failures += 1;
+                if service.failures > MAX_RESTARTS {
+                    service.wanted = false;
+                } else {
+                    service.start_at = now + RESTART_DELAY_MS;
+                }
+            }
+        }
+    }
+}
+
+fn spawn(argv: &[String]) -> Result<u32, &'static str> {
+    let data = match vfs::process_request(FSRequest::ReadFile { path: argv[0].clone() }) {
+        FSResponse::FileData(data) => data,
+        _ => return Err("cannot read program"),
+    };
+    if !data.starts_with(b"\x7FELF") {
+        return Err("not an ELF executable");
+    }
+    let env: Vec<String> = crate::auth::environment()
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let envp: Vec<&str> = env.iter().map(|s| s.as_str()).collect();
+    let argv: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
+    crate::task::spawn_user(argv[0], data, &argv, &envp)
+}
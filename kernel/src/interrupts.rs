@@ -4,6 +4,8 @@
 use spin::Lazy;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 use x86_64::instructions::port::Port;
+use x86_64::VirtAddr;
+use crate::task::pcb::Fault;
 
 pub const PIC1_OFFSET: u8 = 0x20;
 pub const PIC2_OFFSET: u8 = 0x28;
@@ -63,27 +65,28 @@ fn halt_forever() -> ! {
 
 fn draw_panic_screen() {
     if let Some(fb) = crate::boot::framebuffer() {
+        let palette = crate::common::palette::current();
         let addr = fb.address as *mut u32;
         let width = fb.width as usize;
         let height = fb.height as usize;
         let pitch = fb.pitch as usize / 4;
         
-        // Fill screen with dark red
+        // Fill screen with the panic color
         unsafe {
             for y in 0..height {
                 for x in 0..width {
                     let ptr = addr.add(y * pitch + x);
-                    core::ptr::write_volatile(ptr, 0xFF8B0000);
+                    core::ptr::write_volatile(ptr, 0xFF000000 | palette.panic_bg);
                 }
             }
             
-            // Draw white rectangle in center
+            // Draw a box in the center
             let cx = width / 2;
             let cy = height / 2;
             for y in cy.saturating_sub(40)..core::cmp::min(cy + 40, height) {
                 for x in cx.saturating_sub(150)..core::cmp::min(cx + 150, width) {
                     let ptr = addr.add(y * pitch + x);
-                    core::ptr::write_volatile(ptr, 0xFFFFFFFF);
+                    core::ptr::write_volatile(ptr, 0xFF000000 | palette.panic_box);
                 }
             }
         }
@@ -136,8 +139,56 @@ fn initialize_pics() {
     }
 }
 
+/// Mask every line of both PICs; the APICs have taken over
+fn mask_pics() {
+    unsafe {
+        Port::<u8>::new(0x21).write(0xFF);
+        Port::<u8>::new(0xA1).write(0xFF);
+    }
+}
+
+// ============================================================================
+// INTERRUPT CONTROLLER SELECTION
+// ============================================================================
+
+/// Move the ISA IRQs from the PICs to the APICs, unless the kernel command
+/// line says `noapic` or the firmware describes no IO-APIC; the PICs stay
+/// in charge then. Call before enabling any IRQ.
+pub fn init_controller() {
+    let noapic = crate::boot::kernel_cmdline()
+        .is_some_and(|cmdline| cmdline.split_whitespace().any(|arg| arg == "noapic"));
+    if noapic {
+        serial_str(b"[APIC] Disabled by noapic, using the PIC\r\n");
+        return;
+    }
+    let result = crate::drivers::acpi::madt()
+        .ok_or("no MADT")
+        .and_then(|madt| crate::drivers::apic::init(&madt));
+    match result {
+        Ok(()) => mask_pics(),
+        Err(e) => {
+            serial_str(b"[APIC] ");
+            serial_str(e.as_bytes());
+            serial_str(b", using the PIC\r\n");
+        }
+    }
+}
+
+/// Name of the active interrupt controller, for reports
+pub fn controller() -> &'static str {
+    if crate::drivers::apic::is_enabled() { "APIC" } else { "PIC" }
+}
+
 /// Enable specific IRQs after initialization
 pub fn enable_irq(irq: u8) {
+    if crate::drivers::apic::is_enabled() {
+        crate::drivers::apic::enable_irq(irq);
+        return;
+    }
+    // The PICs have the ISA lines only
+    if irq >= 16 {
+        return;
+    }
     unsafe {
         if irq < 8 {
             let mut pic1_data: Port<u8> = Port::new(0x21);
@@ -159,6 +210,10 @@ pub fn enable_irq(irq: u8) {
 }
 
 pub fn notify_end_of_interrupt(irq: u8) {
+    if crate::drivers::apic::is_enabled() {
+        crate::drivers::apic::eoi();
+        return;
+    }
     unsafe {
         if irq >= 8 {
             let mut pic2: Port<u8> = Port::new(0xA0);
@@ -198,6 +253,9 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     idt.segment_not_present.set_handler_fn(segment_not_present_handler);
     idt.stack_segment_fault.set_handler_fn(stack_segment_handler);
     idt.general_protection_fault.set_handler_fn(gpf_handler);
+    // No IST stack: the handler does real work (demand paging, COW) and a
+    // fault nested in it would overwrite a shared IST stack. A kernel stack
+    // overflow cannot push this frame and ends in the double fault handler.
     idt.page_fault.set_handler_fn(page_fault_handler);
     idt.x87_floating_point.set_handler_fn(x87_fpu_handler);
     idt.alignment_check.set_handler_fn(alignment_check_handler);
@@ -206,8 +264,18 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     idt[20].set_handler_fn(virtualization_exception_handler);
     
     // Hardware interrupts (32+)
-    idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-    idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+    // The timer entry is a raw stub so it can switch tasks on return
+    unsafe {
+        idt[InterruptIndex::Timer.as_usize()]
+            .set_handler_addr(VirtAddr::new(crate::task::switch::timer_entry as u64));
+        idt[crate::task::switch::YIELD_VECTOR as usize]
+            .set_handler_addr(VirtAddr::new(crate::task::switch::yield_entry as u64));
+    }
+    // Every other IRQ line goes to whatever drivers registered on it
+    for (line, stub) in crate::irq::STUBS.iter().enumerate().skip(1) {
+        idt[usize::from(crate::irq::VECTOR_BASE) + line].set_handler_fn(*stub);
+    }
+    idt[crate::drivers::apic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt_handler);
     
     idt
 });
@@ -239,11 +307,61 @@ pub fn init_idt() {
     serial_str(b"[IDT] Initialization complete\r\n");
 }
 
+// ============================================================================
+// USER-MODE FAULTS
+// ============================================================================
+
+/// A fault in user mode only concerns the task that caused it: note what
+/// happened and return from the exception into `task::killed`, which
+/// prints it on the task's stderr and terminates it with exit status
+/// 128 + `sig`. Returns false for faults in kernel mode, which stay fatal.
+fn kill_user_task(stack_frame: &mut InterruptStackFrame, sig: i32, what: &'static str, addr: Option<u64>) -> bool {
+    kill_user_task_for(stack_frame, Fault { signal: sig, what, reason: None, addr, rip: 0 })
+}
+
+/// `kill_user_task` with the whole fault record; `rip` is filled in here.
+/// Nothing here allocates: the heap lock is not interrupt-safe, and a
+/// preempted kernel task may hold it.
+fn kill_user_task_for(stack_frame: &mut InterruptStackFrame, mut fault: Fault) -> bool {
+    if stack_frame.code_segment & 3 != 3 {
+        return false;
+    }
+    // The switch never happens with the scheduler lock held, so user code
+    // never runs while it is taken; failing here is a bug, and halting
+    // below at least reports it
+    let Some(mut scheduler) = crate::task::scheduler::SCHEDULER.try_lock() else {
+        return false;
+    };
+    let Some(task) = scheduler.current_task_mut() else { return false };
+    fault.rip = stack_frame.instruction_pointer.as_u64();
+    task.fault = Some(fault);
+    task.pending_kill.get_or_insert(128 + fault.signal);
+    // Same as a kill at a switch: enter `killed` at the top of the
+    // task's kernel stack, whatever stack this handler runs on
+    let stack_top = (task.kernel_stack & !0xF) - 8;
+    drop(scheduler);
+
+    let selectors = crate::gdt::selectors();
+    unsafe {
+        stack_frame.as_mut().update(|frame| {
+            frame.instruction_pointer = VirtAddr::new(crate::task::killed as *const () as u64);
+            frame.code_segment = selectors.kernel_code.0 as u64;
+            frame.cpu_flags = 0x202;
+            frame.stack_pointer = VirtAddr::new(stack_top);
+            frame.stack_segment = selectors.kernel_data.0 as u64;
+        });
+    }
+    true
+}
+
 // ============================================================================
 // EXCEPTION HANDLERS - All use diverging functions (-> !)
 // ============================================================================
 
-extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn divide_error_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGFPE, "divide error", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: DIVIDE BY ZERO (#DE) !!!\r\n");
     print_stack_frame(&stack_frame);
@@ -275,7 +393,10 @@ extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
     // Don't halt - breakpoint is recoverable
 }
 
-extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn overflow_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGSEGV, "overflow trap", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: OVERFLOW (#OF) !!!\r\n");
     print_stack_frame(&stack_frame);
@@ -283,7 +404,10 @@ extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
     halt_forever();
 }
 
-extern "x86-interrupt" fn bound_range_handler(stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn bound_range_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGSEGV, "bound range exceeded", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: BOUND RANGE EXCEEDED (#BR) !!!\r\n");
     print_stack_frame(&stack_frame);
@@ -291,7 +415,10 @@ extern "x86-interrupt" fn bound_range_handler(stack_frame: InterruptStackFrame)
     halt_forever();
 }
 
-extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn invalid_opcode_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGILL, "invalid opcode", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: INVALID OPCODE (#UD) !!!\r\n");
     serial_str(b"This usually means corrupted code or wrong jump target\r\n");
@@ -321,6 +448,11 @@ extern "x86-interrupt" fn double_fault_handler(
     serial_str(b"Error code: ");
     serial_hex(error_code);
     serial_str(b"\r\n");
+    // A page fault on a stack guard could not push its frame on that stack
+    if crate::mem::vmm::is_kernel_stack_guard(x86_64::registers::control::Cr2::read_raw()) {
+        serial_str(b"\r\n!!! KERNEL STACK OVERFLOW !!!\r\n");
+        print_current_task();
+    }
     print_stack_frame(&stack_frame);
     print_control_registers();
     draw_panic_screen();
@@ -338,7 +470,10 @@ extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame,
     halt_forever();
 }
 
-extern "x86-interrupt" fn segment_not_present_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+extern "x86-interrupt" fn segment_not_present_handler(mut stack_frame: InterruptStackFrame, error_code: u64) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGSEGV, "segment not present", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: SEGMENT NOT PRESENT (#NP) !!!\r\n");
     serial_str(b"Error code: ");
@@ -349,7 +484,10 @@ extern "x86-interrupt" fn segment_not_present_handler(stack_frame: InterruptStac
     halt_forever();
 }
 
-extern "x86-interrupt" fn stack_segment_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+extern "x86-interrupt" fn stack_segment_handler(mut stack_frame: InterruptStackFrame, error_code: u64) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGSEGV, "stack segment fault", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: STACK SEGMENT FAULT (#SS) !!!\r\n");
     serial_str(b"Error code: ");
@@ -360,7 +498,10 @@ extern "x86-interrupt" fn stack_segment_handler(stack_frame: InterruptStackFrame
     halt_forever();
 }
 
-extern "x86-interrupt" fn gpf_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+extern "x86-interrupt" fn gpf_handler(mut stack_frame: InterruptStackFrame, error_code: u64) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGSEGV, "general protection fault", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: GENERAL PROTECTION FAULT (#GP) !!!\r\n");
     serial_str(b"Error code: ");
@@ -373,10 +514,41 @@ extern "x86-interrupt" fn gpf_handler(stack_frame: InterruptStackFrame, error_co
     halt_forever();
 }
 
-extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+extern "x86-interrupt" fn page_fault_handler(mut stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    let cr2 = x86_64::registers::control::Cr2::read_raw();
+    
+    // Recoverable: demand paging and copy-on-write in the current task
+    let mut reason = None;
+    if cr2 <= crate::mem::vmm::USER_SPACE_END {
+        match resolve_user_fault(cr2, error_code) {
+            Ok(()) => return,
+            // Run the access again once the lock holder has run; only
+            // possible when the faulting code can be preempted
+            Err(crate::mem::vmm::FAULT_BUSY) if stack_frame.cpu_flags & 0x200 != 0 => return,
+            Err(e) => reason = Some(e),
+        }
+    }
+    
+    // A bad access by user code kills the task, kernel addresses included
+    if stack_frame.code_segment & 3 == 3 {
+        let fault = Fault {
+            signal: crate::syscall::abi::SIGSEGV,
+            what: "segmentation fault",
+            reason: Some(reason.unwrap_or("kernel address")),
+            addr: Some(cr2),
+            rip: 0,
+        };
+        if kill_user_task_for(&mut stack_frame, fault) {
+            return;
+        }
+    }
+    
     x86_64::instructions::interrupts::disable();
     
-    let cr2 = x86_64::registers::control::Cr2::read_raw();
+    if reason == Some("stack overflow") {
+        serial_str(b"\r\n!!! USER STACK OVERFLOW !!!\r\n");
+        print_current_task();
+    }
     
     serial_str(b"\r\n!!! EXCEPTION: PAGE FAULT (#PF) !!!\r\n");
     serial_str(b"Faulting address (CR2): ");
@@ -411,7 +583,42 @@ extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, e
     halt_forever();
 }
 
-extern "x86-interrupt" fn x87_fpu_handler(stack_frame: InterruptStackFrame) {
+/// Map the faulting page if it belongs to the current task's address space
+fn resolve_user_fault(addr: u64, error_code: PageFaultErrorCode) -> Result<(), &'static str> {
+    // The faulting code may hold the scheduler lock; then this is fatal
+    let mut scheduler = crate::task::scheduler::SCHEDULER.try_lock().ok_or("scheduler busy")?;
+    let space = scheduler.current_task_mut()
+        .and_then(|task| task.address_space.as_mut())
+        .ok_or("no address space")?;
+    space.handle_fault(
+        addr,
+        error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION),
+        error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE),
+    )
+}
+
+/// Report the task that was running when a fatal exception hit
+fn print_current_task() {
+    serial_str(b"Task: ");
+    match crate::task::scheduler::SCHEDULER.try_lock() {
+        Some(mut scheduler) => match scheduler.current_task_mut() {
+            Some(task) => {
+                serial_str(b"pid ");
+                serial_hex(task.pid as u64);
+                serial_str(b" ");
+                serial_str(task.name.as_bytes());
+            }
+            None => serial_str(b"none"),
+        },
+        None => serial_str(b"unknown (scheduler locked)"),
+    }
+    serial_str(b"\r\n");
+}
+
+extern "x86-interrupt" fn x87_fpu_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGFPE, "x87 floating-point exception", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: x87 FPU ERROR (#MF) !!!\r\n");
     print_stack_frame(&stack_frame);
@@ -419,7 +626,10 @@ extern "x86-interrupt" fn x87_fpu_handler(stack_frame: InterruptStackFrame) {
     halt_forever();
 }
 
-extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+extern "x86-interrupt" fn alignment_check_handler(mut stack_frame: InterruptStackFrame, error_code: u64) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGBUS, "alignment check", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: ALIGNMENT CHECK (#AC) !!!\r\n");
     serial_str(b"Error code: ");
@@ -430,7 +640,10 @@ extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFra
     halt_forever();
 }
 
-extern "x86-interrupt" fn simd_handler(stack_frame: InterruptStackFrame) {
+extern "x86-interrupt" fn simd_handler(mut stack_frame: InterruptStackFrame) {
+    if kill_user_task(&mut stack_frame, crate::syscall::abi::SIGFPE, "SIMD floating-point exception", None) {
+        return;
+    }
     x86_64::instructions::interrupts::disable();
     serial_str(b"\r\n!!! EXCEPTION: SIMD FLOATING POINT (#XF) !!!\r\n");
     print_stack_frame(&stack_frame);
@@ -463,51 +676,8 @@ extern "x86-interrupt" fn virtualization_exception_handler(stack_frame: Interrup
 // HARDWARE INTERRUPT HANDLERS
 // ============================================================================
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // Update timer tick count
-    crate::drivers::timer::tick();
-    
-    // Trigger task scheduling (v0.1.0)
-    // crate::task::scheduler::timer_tick(); // TODO: Enable when ready
-    
-    // Send EOI to PIC
-    unsafe {
-        core::arch::asm!(
-            "mov al, 0x20",
-            "out 0x20, al",
-            options(nomem, nostack, preserves_flags)
-        );
-    }
-}
-
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use x86_64::instructions::port::Port;
-    
-    // Read status register first to check if data is available
-    let status: u8 = unsafe {
-        let mut port = Port::<u8>::new(0x64);
-        port.read()
-    };
-    
-    // Check if output buffer is full (data available)
-    if (status & 0x01) == 0 {
-        // Spurious interrupt - acknowledge and return
-        notify_end_of_interrupt(1);
-        return;
-    }
-    
-    // Read scancode from keyboard data port
-    let scancode: u8 = unsafe {
-        let mut port = Port::<u8>::new(0x60);
-        port.read()
-    };
-    
-    // Queue for processing in main loop
-    crate::drivers::keyboard::queue_scancode(scancode);
-    
-    // Acknowledge interrupt to PIC
-    notify_end_of_interrupt(1);
-}
+/// Local APIC spurious interrupt: nothing to handle and no EOI
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
 
 // ============================================================================
 // DEBUG HELPERS
@@ -557,8 +727,7 @@ fn print_control_registers() {
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
-    Timer = 32,    // PIC1_OFFSET + 0
-    Keyboard = 33, // PIC1_OFFSET + 1
+    Timer = 32,    // PIC1_OFFSET + 0, or the local APIC timer
 }
 
 impl InterruptIndex {
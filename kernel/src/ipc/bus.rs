@@ -1,85 +1,312 @@
 //! Message Bus - Central dispatcher for microkernel IPC
+//!
+//! Every service reads from a mailbox, a queue named by a `MailboxId`.
+//! The core services have well-known mailboxes; others get one from
+//! `create_mailbox` and publish it through the name registry
+//! (`ipc::registry`), where clients look it up at runtime.
+//!
+//! Requests and replies travel as `Envelope`s: `call` queues a request
+//! with a private reply mailbox and waits for the answer there. Services
+//! still living in the kernel attach a handler to their mailbox with
+//! `serve`; `call` then drains that mailbox on the caller's stack, so the
+//! path is the same one a user-space service would be reached by, only
+//! without the context switch. `Channel` gives each service a typed
+//! request/response pair over this, e.g. `VFS: Channel<FSRequest, FSResponse>`.
+//!
+//! A task with nothing else to do waits in `receive_blocking`; it is
+//! blocked in the scheduler and woken by the next message for it.
+//!
+//! Lifecycle events (`ServiceEvent`) are not queued in a mailbox but kept
+//! in a log every subscriber reads at its own pace, so a service that
+//! starts late still learns what became ready before it.
 
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
 use spin::Mutex;
 use super::message::*;
+use crate::task::scheduler::{self, SCHEDULER};
+
+/// Identifies a service's mailbox on the bus
+pub type MailboxId = u32;
+
+/// Well-known mailboxes of the core services
+pub const VFS_MAILBOX: MailboxId = 1;
+pub const UI_MAILBOX: MailboxId = 2;
+pub const PKG_MAILBOX: MailboxId = 3;
+pub const SYSTEM_MAILBOX: MailboxId = 4;
+pub const DISPLAY_MAILBOX: MailboxId = 5;
+pub const CLIPBOARD_MAILBOX: MailboxId = 6;
+
+/// First id handed out by `create_mailbox`
+const FIRST_DYNAMIC_MAILBOX: MailboxId = 16;
+
+/// Serves one request for an in-kernel endpoint; the return value, if
+/// any, is the reply
+pub type Handler = fn(Message) -> Option<Message>;
+
+/// A queued message and where to answer it
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub message: Message,
+    /// Mailbox waiting for the reply, for requests sent with `call`
+    pub reply_to: Option<MailboxId>,
+}
 
 /// Message queue for a service
 struct ServiceQueue {
-    messages: VecDeque<Message>,
+    messages: VecDeque<Envelope>,
+    /// In-kernel server for the queue, see `serve`
+    handler: Option<Handler>,
+    /// Tasks blocked in `receive_blocking`
+    waiters: Vec<u32>,
 }
 
 impl ServiceQueue {
     const fn new() -> Self {
         Self {
             messages: VecDeque::new(),
+            handler: None,
+            waiters: Vec::new(),
         }
     }
 }
 
+/// Typed request/response endpoint of a service
+pub struct Channel<Req, Resp> {
+    pub mailbox: MailboxId,
+    types: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req: Into<Message>, Resp: TryFrom<Message>> Channel<Req, Resp> {
+    pub const fn new(mailbox: MailboxId) -> Self {
+        Self { mailbox, types: PhantomData }
+    }
+
+    /// Send `request` and wait for the reply
+    pub fn call(&self, request: Req) -> Result<Resp, &'static str> {
+        let bus = get().ok_or("message bus not initialized")?;
+        let reply = bus.call(self.mailbox, request.into())?;
+        Resp::try_from(reply).map_err(|_| "unexpected reply type")
+    }
+
+    /// Send `request` without waiting for a reply
+    pub fn send(&self, request: Req) -> Result<(), &'static str> {
+        get().ok_or("message bus not initialized")?.send_to(self.mailbox, request.into())
+    }
+}
+
+/// The filesystem service
+pub const VFS: Channel<FSRequest, FSResponse> = Channel::new(VFS_MAILBOX);
+/// The window compositor; answered from the main loop (`display::poll`),
+/// so the main loop itself must not `call` it
+pub const DISPLAY: Channel<DisplayRequest, DisplayResponse> = Channel::new(DISPLAY_MAILBOX);
+/// The clipboard
+pub const CLIPBOARD: Channel<ClipboardRequest, ClipboardResponse> = Channel::new(CLIPBOARD_MAILBOX);
+
 /// Central message bus
 pub struct MessageBus {
-    vfs_queue: Mutex<ServiceQueue>,
-    ui_queue: Mutex<ServiceQueue>,
-    pkg_queue: Mutex<ServiceQueue>,
-    system_queue: Mutex<ServiceQueue>,
+    mailboxes: Mutex<BTreeMap<MailboxId, ServiceQueue>>,
+    next_mailbox: Mutex<MailboxId>,
+    events: Mutex<Vec<ServiceEvent>>,
 }
 
 impl MessageBus {
-    /// Create new message bus
-    pub const fn new() -> Self {
+    /// Create new message bus with the well-known mailboxes
+    pub fn new() -> Self {
+        let mut mailboxes = BTreeMap::new();
+        for id in [VFS_MAILBOX, UI_MAILBOX, PKG_MAILBOX, SYSTEM_MAILBOX, DISPLAY_MAILBOX, CLIPBOARD_MAILBOX] {
+            mailboxes.insert(id, ServiceQueue::new());
+        }
         Self {
-            vfs_queue: Mutex::new(ServiceQueue::new()),
-            ui_queue: Mutex::new(ServiceQueue::new()),
-            pkg_queue: Mutex::new(ServiceQueue::new()),
-            system_queue: Mutex::new(ServiceQueue::new()),
+            mailboxes: Mutex::new(mailboxes),
+            next_mailbox: Mutex::new(FIRST_DYNAMIC_MAILBOX),
+            events: Mutex::new(Vec::new()),
         }
     }
 
     /// Dispatch message to appropriate service queue
     pub fn dispatch(&self, msg: Message) {
-        match msg {
-            Message::FS(ref _req) => {
-                let mut queue = self.vfs_queue.lock();
-                queue.messages.push_back(msg);
+        let mailbox = match msg {
+            Message::FS(_) => VFS_MAILBOX,
+            Message::UI(_) => UI_MAILBOX,
+            Message::Pkg(_) => PKG_MAILBOX,
+            Message::System(_) => SYSTEM_MAILBOX,
+            Message::Display(_) => DISPLAY_MAILBOX,
+            Message::Clipboard(_) => CLIPBOARD_MAILBOX,
+            // Replies go back through `reply`, to the caller's mailbox
+            Message::FSReply(_) | Message::DisplayReply(_) | Message::ClipboardReply(_) | Message::Unhandled => return,
+        };
+        let _ = self.send_to(mailbox, msg);
+    }
+
+    /// Queue `msg` in `mailbox`
+    pub fn send_to(&self, mailbox: MailboxId, msg: Message) -> Result<(), &'static str> {
+        self.post(mailbox, Envelope { message: msg, reply_to: None })
+    }
+
+    /// Answer a request received with `reply_to` set
+    pub fn reply(&self, reply_to: MailboxId, msg: Message) -> Result<(), &'static str> {
+        self.send_to(reply_to, msg)
+    }
+
+    fn post(&self, mailbox: MailboxId, envelope: Envelope) -> Result<(), &'static str> {
+        let waiters = {
+            let mut mailboxes = self.mailboxes.lock();
+            let queue = mailboxes.get_mut(&mailbox).ok_or("no such mailbox")?;
+            queue.messages.push_back(envelope);
+            core::mem::take(&mut queue.waiters)
+        };
+        if !waiters.is_empty() {
+            let mut scheduler = SCHEDULER.lock();
+            for pid in waiters {
+                scheduler.unblock(pid);
             }
-            Message::UI(ref _req) => {
-                let mut queue = self.ui_queue.lock();
-                queue.messages.push_back(msg);
+        }
+        Ok(())
+    }
+
+    /// Send `msg` to `mailbox` and wait for the reply
+    ///
+    /// If the mailbox has an in-kernel handler, the queued requests are
+    /// served right here; otherwise the caller blocks until the service
+    /// task answers.
+    pub fn call(&self, mailbox: MailboxId, msg: Message) -> Result<Message, &'static str> {
+        let reply_to = self.create_mailbox();
+        let result = self.post(mailbox, Envelope { message: msg, reply_to: Some(reply_to) })
+            .and_then(|()| {
+                self.pump(mailbox);
+                Ok(self.receive_blocking(reply_to).message)
+            });
+        self.destroy_mailbox(reply_to);
+        result
+    }
+
+    /// Serve everything queued in `mailbox` with its handler, if it has one
+    fn pump(&self, mailbox: MailboxId) {
+        loop {
+            // The handler runs unlocked: it may use the bus itself
+            let (handler, envelope) = {
+                let mut mailboxes = self.mailboxes.lock();
+                let Some(queue) = mailboxes.get_mut(&mailbox) else { return };
+                let Some(handler) = queue.handler else { return };
+                match queue.messages.pop_front() {
+                    Some(envelope) => (handler, envelope),
+                    None => return,
+                }
+            };
+            let reply = handler(envelope.message);
+            if let Some(reply_to) = envelope.reply_to {
+                // The caller is waiting: it gets an answer either way
+                let _ = self.reply(reply_to, reply.unwrap_or(Message::Unhandled));
             }
-            Message::Pkg(ref _req) => {
-                let mut queue = self.pkg_queue.lock();
-                queue.messages.push_back(msg);
+        }
+    }
+
+    /// Attach an in-kernel handler to `mailbox`, serving what is already
+    /// queued there
+    pub fn serve(&self, mailbox: MailboxId, handler: Handler) -> Result<(), &'static str> {
+        self.mailboxes.lock().get_mut(&mailbox).ok_or("no such mailbox")?.handler = Some(handler);
+        self.pump(mailbox);
+        Ok(())
+    }
+
+    /// Get next message from `mailbox` without waiting
+    pub fn receive(&self, mailbox: MailboxId) -> Option<Envelope> {
+        let mut mailboxes = self.mailboxes.lock();
+        mailboxes.get_mut(&mailbox)?.messages.pop_front()
+    }
+
+    /// Get next message from `mailbox`, blocking the current task until
+    /// one arrives
+    pub fn receive_blocking(&self, mailbox: MailboxId) -> Envelope {
+        loop {
+            if let Some(envelope) = self.receive(mailbox) {
+                return envelope;
             }
-            Message::System(ref _req) => {
-                let mut queue = self.system_queue.lock();
-                queue.messages.push_back(msg);
+            {
+                // Checking and blocking under the scheduler lock means a
+                // sender cannot slip its wake-up in between
+                let mut scheduler = SCHEDULER.lock();
+                let mut mailboxes = self.mailboxes.lock();
+                let Some(queue) = mailboxes.get_mut(&mailbox) else {
+                    panic!("receive_blocking on missing mailbox {}", mailbox);
+                };
+                if let Some(envelope) = queue.messages.pop_front() {
+                    return envelope;
+                }
+                let pid = scheduler.current_pid();
+                if !queue.waiters.contains(&pid) {
+                    queue.waiters.push(pid);
+                }
+                scheduler.block_current();
             }
+            scheduler::yield_now();
+        }
+    }
+
+    /// Allocate a new, empty mailbox
+    pub fn create_mailbox(&self) -> MailboxId {
+        let mut next = self.next_mailbox.lock();
+        let id = *next;
+        *next += 1;
+        self.mailboxes.lock().insert(id, ServiceQueue::new());
+        id
+    }
+
+    /// Drop a mailbox created by `create_mailbox` and anything still in it
+    pub fn destroy_mailbox(&self, mailbox: MailboxId) {
+        if mailbox >= FIRST_DYNAMIC_MAILBOX {
+            self.mailboxes.lock().remove(&mailbox);
         }
     }
 
+    /// Number of messages waiting in `mailbox`
+    pub fn pending(&self, mailbox: MailboxId) -> Option<usize> {
+        self.mailboxes.lock().get(&mailbox).map(|q| q.messages.len())
+    }
+
+    /// Append `event` to the event log
+    pub fn publish(&self, event: ServiceEvent) {
+        crate::serial_println!("[IPC] Event: {:?}", event);
+        self.events.lock().push(event);
+    }
+
+    /// Events published after the first `cursor` ones
+    pub fn events_since(&self, cursor: usize) -> Vec<ServiceEvent> {
+        let events = self.events.lock();
+        events.get(cursor..).map(|e| e.to_vec()).unwrap_or_default()
+    }
+
+    /// Whether the last event about `service` says it is ready
+    pub fn is_ready(&self, service: &str) -> bool {
+        let events = self.events.lock();
+        matches!(events.iter().rev().find(|e| e.service() == service), Some(ServiceEvent::Ready(_)))
+    }
+
     /// Get next message from VFS queue
     pub fn poll_vfs(&self) -> Option<Message> {
-        let mut queue = self.vfs_queue.lock();
-        queue.messages.pop_front()
+        self.receive(VFS_MAILBOX).map(|envelope| envelope.message)
     }
 
     /// Get next message from UI queue
     pub fn poll_ui(&self) -> Option<Message> {
-        let mut queue = self.ui_queue.lock();
-        queue.messages.pop_front()
+        self.receive(UI_MAILBOX).map(|envelope| envelope.message)
     }
 
     /// Get next message from Package manager queue
     pub fn poll_pkg(&self) -> Option<Message> {
-        let mut queue = self.pkg_queue.lock();
-        queue.messages.pop_front()
+        self.receive(PKG_MAILBOX).map(|envelope| envelope.message)
     }
 
     /// Get next message from System queue
     pub fn poll_system(&self) -> Option<Message> {
-        let mut queue = self.system_queue.lock();
-        queue.messages.pop_front()
+        self.receive(SYSTEM_MAILBOX).map(|envelope| envelope.message)
+    }
+
+    /// Get next message from Display queue
+    pub fn poll_display(&self) -> Option<Message> {
+        self.receive(DISPLAY_MAILBOX).map(|envelope| envelope.message)
     }
 }
 
@@ -99,6 +326,23 @@ pub fn send(msg: Message) {
     }
 }
 
+/// Publish a lifecycle event
+pub fn publish(event: ServiceEvent) {
+    if let Some(bus) = get() {
+        bus.publish(event);
+    }
+}
+
+/// Whether `service` has announced that it is ready (and not gone down since)
+pub fn is_ready(service: &str) -> bool {
+    get().map_or(false, |bus| bus.is_ready(service))
+}
+
+/// Serve `mailbox` with an in-kernel `handler`, see `MessageBus::serve`
+pub fn serve(mailbox: MailboxId, handler: Handler) -> Result<(), &'static str> {
+    get().ok_or("message bus not initialized")?.serve(mailbox, handler)
+}
+
 /// Get message bus reference
 pub fn get() -> Option<&'static MessageBus> {
     unsafe {
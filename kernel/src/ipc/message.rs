@@ -2,6 +2,8 @@
 
 use alloc::string::String;
 use alloc::vec::Vec;
+use crate::fs::vfs::{FileBytes, FsError};
+use crate::services::vfs::Metadata;
 
 /// Main message enum for inter-service communication
 #[derive(Debug, Clone)]
@@ -14,6 +16,69 @@ pub enum Message {
     Pkg(PkgRequest),
     /// System control
     System(SystemRequest),
+    /// Window compositor requests
+    Display(DisplayRequest),
+    /// Clipboard requests
+    Clipboard(ClipboardRequest),
+    /// Filesystem reply
+    FSReply(FSResponse),
+    /// Window compositor reply
+    DisplayReply(DisplayResponse),
+    /// Clipboard reply
+    ClipboardReply(ClipboardResponse),
+    /// The service had no answer for the request
+    Unhandled,
+}
+
+impl From<FSRequest> for Message {
+    fn from(request: FSRequest) -> Self {
+        Message::FS(request)
+    }
+}
+
+impl From<DisplayRequest> for Message {
+    fn from(request: DisplayRequest) -> Self {
+        Message::Display(request)
+    }
+}
+
+impl From<ClipboardRequest> for Message {
+    fn from(request: ClipboardRequest) -> Self {
+        Message::Clipboard(request)
+    }
+}
+
+impl TryFrom<Message> for FSResponse {
+    type Error = Message;
+
+    fn try_from(message: Message) -> Result<Self, Message> {
+        match message {
+            Message::FSReply(response) => Ok(response),
+            other => Err(other),
+        }
+    }
+}
+
+impl TryFrom<Message> for DisplayResponse {
+    type Error = Message;
+
+    fn try_from(message: Message) -> Result<Self, Message> {
+        match message {
+            Message::DisplayReply(response) => Ok(response),
+            other => Err(other),
+        }
+    }
+}
+
+impl TryFrom<Message> for ClipboardResponse {
+    type Error = Message;
+
+    fn try_from(message: Message) -> Result<Self, Message> {
+        match message {
+            Message::ClipboardReply(response) => Ok(response),
+            other => Err(other),
+        }
+    }
 }
 
 /// Filesystem operations
@@ -27,12 +92,20 @@ pub enum FSRequest {
     WriteFile { path: String, data: Vec<u8> },
     /// Create directory
     CreateDir { path: String },
-    /// Delete file/directory
+    /// Delete a file or an empty directory
     Delete { path: String },
+    /// Delete a directory and everything in it (or a file)
+    DeleteTree { path: String },
     /// Change current directory
     ChangeDir { path: String },
     /// Get current working directory
     GetCwd,
+    /// Type, size, permissions and times of a path
+    Stat { path: String },
+    /// Make `path` a symbolic link to `target`
+    Symlink { target: String, path: String },
+    /// Where the symbolic link at `path` points
+    ReadLink { path: String },
 }
 
 /// Filesystem response
@@ -40,14 +113,18 @@ pub enum FSRequest {
 pub enum FSResponse {
     /// List of entries
     DirListing(Vec<String>),
-    /// File contents
-    FileData(Vec<u8>),
+    /// File contents, shared with the file rather than copied
+    FileData(FileBytes),
     /// Success confirmation
     Success,
-    /// Error message
-    Error(String),
+    /// Failure, with an optional detail for logs
+    Error(FsError, Option<String>),
     /// Current working directory
     Cwd(String),
+    /// Answer to Stat
+    Metadata(Metadata),
+    /// Answer to ReadLink
+    LinkTarget(String),
 }
 
 /// UI/Terminal operations
@@ -85,8 +162,8 @@ pub enum PkgRequest {
 pub enum PkgResponse {
     /// Success message
     Success(String),
-    /// Error message
-    Error(String),
+    /// Failure, with an optional detail for logs
+    Error(FsError, Option<String>),
     /// List of packages
     PackageList(Vec<String>),
 }
@@ -101,3 +178,75 @@ pub enum SystemRequest {
     /// Get system info
     GetInfo,
 }
+
+/// Window compositor operations
+///
+/// Windows are named by the id `Create` returns. Positions are screen
+/// pixels of the content's top-left corner (decorations are drawn around
+/// it) and may be off-screen; rectangles in `Damage` and `Blit` are
+/// relative to the content.
+#[derive(Debug, Clone)]
+pub enum DisplayRequest {
+    /// Open a window owned by task `owner`, on top of the others
+    Create { owner: u32, title: String, x: i32, y: i32, width: u32, height: u32 },
+    /// Move a window
+    Move { window: u32, x: i32, y: i32 },
+    /// Bring a window to the top
+    Raise { window: u32 },
+    /// Redraw part of a window whose pixels changed
+    Damage { window: u32, x: u32, y: u32, width: u32, height: u32 },
+    /// Copy RGB pixels, `width` per row, into a window and redraw them
+    Blit { window: u32, x: u32, y: u32, width: u32, pixels: Vec<u32> },
+    /// Close a window
+    Destroy { window: u32 },
+}
+
+/// Window compositor response
+#[derive(Debug, Clone)]
+pub enum DisplayResponse {
+    /// Id of the new window
+    Created(u32),
+    /// Success confirmation
+    Success,
+    /// Failure, with an optional detail for logs
+    Error(FsError, Option<String>),
+}
+
+/// Clipboard operations
+#[derive(Debug, Clone)]
+pub enum ClipboardRequest {
+    /// Text on the clipboard
+    Get,
+    /// Replace the text on the clipboard
+    Set(String),
+    /// Empty the clipboard
+    Clear,
+}
+
+/// Clipboard response
+#[derive(Debug, Clone)]
+pub enum ClipboardResponse {
+    /// Answer to Get
+    Text(String),
+    /// Success confirmation
+    Success,
+    /// Failure, with an optional detail for logs
+    Error(FsError, Option<String>),
+}
+
+/// Service lifecycle notifications, published on the bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceEvent {
+    /// The service is functional and may be depended on
+    Ready(&'static str),
+    /// The service stopped working
+    Down(&'static str),
+}
+
+impl ServiceEvent {
+    pub fn service(&self) -> &'static str {
+        match self {
+            ServiceEvent::Ready(name) | ServiceEvent::Down(name) => name,
+        }
+    }
+}
@@ -3,6 +3,9 @@
 
 pub mod message;
 pub mod bus;
+pub mod wire;
+pub mod registry;
+pub mod shm;
 
 pub use message::Message;
 pub use bus::MessageBus;
@@ -0,0 +1,114 @@
+//! Service registry - name service for the message bus
+//!
+//! Services register a name, their mailbox and the capabilities they
+//! offer when they initialize; clients resolve a name (or a capability)
+//! to a mailbox at runtime instead of hard-coding queue ids.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use super::bus::MailboxId;
+
+/// Current state of a registered service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Registered but still initializing
+    Starting,
+    /// Serving requests
+    Ready,
+    /// Serving requests with reduced functionality
+    Degraded,
+    /// Not serving requests
+    Down,
+}
+
+impl Health {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Health::Starting => "starting",
+            Health::Ready => "ready",
+            Health::Degraded => "degraded",
+            Health::Down => "down",
+        }
+    }
+}
+
+/// What a service publishes about itself
+pub struct ServiceDescriptor {
+    pub name: &'static str,
+    pub mailbox: MailboxId,
+    /// Capability names, e.g. "fs.read"
+    pub capabilities: &'static [&'static str],
+    /// Reports the service's health when asked
+    pub probe: fn() -> Health,
+}
+
+/// A registry entry as reported by `list`
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub mailbox: MailboxId,
+    pub capabilities: Vec<&'static str>,
+    pub health: Health,
+}
+
+static REGISTRY: Mutex<Vec<ServiceDescriptor>> = Mutex::new(Vec::new());
+
+/// Publish a service under its name
+pub fn register(service: ServiceDescriptor) -> Result<(), &'static str> {
+    let mut registry = REGISTRY.lock();
+    if registry.iter().any(|s| s.name == service.name) {
+        return Err("service name already registered");
+    }
+    crate::serial_println!("[IPC] Registered service '{}' on mailbox {}", service.name, service.mailbox);
+    registry.push(service);
+    Ok(())
+}
+
+/// Remove a service from the registry
+pub fn unregister(name: &str) {
+    REGISTRY.lock().retain(|s| s.name != name);
+}
+
+/// Mailbox of the service registered as `name`
+pub fn resolve(name: &str) -> Option<MailboxId> {
+    REGISTRY.lock().iter().find(|s| s.name == name).map(|s| s.mailbox)
+}
+
+/// Mailbox of the first service offering `capability`
+pub fn resolve_capability(capability: &str) -> Option<MailboxId> {
+    REGISTRY.lock()
+        .iter()
+        .find(|s| s.capabilities.contains(&capability))
+        .map(|s| s.mailbox)
+}
+
+/// Names of the registered services
+pub fn names() -> Vec<&'static str> {
+    REGISTRY.lock().iter().map(|s| s.name).collect()
+}
+
+/// Every registered service with its current health
+pub fn list() -> Vec<ServiceInfo> {
+    // Probes may take the service's own locks, so run them unlocked
+    let entries: Vec<(ServiceInfo, fn() -> Health)> = REGISTRY.lock()
+        .iter()
+        .map(|s| {
+            let info = ServiceInfo {
+                name: String::from(s.name),
+                mailbox: s.mailbox,
+                capabilities: s.capabilities.to_vec(),
+                health: Health::Starting,
+            };
+            (info, s.probe)
+        })
+        .collect();
+
+    entries
+        .into_iter()
+        .map(|(mut info, probe)| {
+            info.health = probe();
+            info
+        })
+        .collect()
+}
@@ -0,0 +1,183 @@
+//! Shared memory regions between tasks
+//!
+//! sys_shm_create allocates zeroed physical frames and sys_shm_map maps
+//! those same frames into the calling task's address space. A region
+//! appears at the same address in every task (its slot above `SHM_BASE`),
+//! so pointers into it mean the same thing on both sides.
+//!
+//! A region lives while its creator does or while any task has it mapped.
+//! Frames are reference counted by the VMM, so a page a task still maps
+//! stays valid even after the region itself is gone.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::mem::physical::FRAME_ALLOCATOR;
+use crate::mem::vmm::{self, AddressSpace};
+
+/// Start of the shared-memory slots in every user address space
+pub const SHM_BASE: u64 = 0x0000_6000_0000_0000;
+/// Largest region, and the distance between two slots
+pub const SHM_MAX_SIZE: usize = 16 * 1024 * 1024;
+const MAX_REGIONS: u32 = 256;
+
+/// Region flag: tasks other than the creator may map it writable
+pub const SHM_OTHERS_WRITE: u64 = 1;
+/// Mapping flag: map writable
+pub const SHM_WRITE: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmError {
+    /// Bad size or flags
+    Invalid,
+    /// No such region
+    NotFound,
+    /// Writable mapping of a region only its creator may write
+    Denied,
+    /// Out of region ids or physical memory
+    NoSpace,
+}
+
+impl ShmError {
+    pub fn errno(self) -> u64 {
+        use crate::syscall::abi;
+        match self {
+            ShmError::Invalid => abi::EINVAL,
+            ShmError::NotFound => abi::ENOENT,
+            ShmError::Denied => abi::EACCES,
+            ShmError::NoSpace => abi::ENOSPC,
+        }
+    }
+
+    /// Negated errno, as returned by the shm syscalls
+    pub fn to_syscall(self) -> u64 {
+        self.errno().wrapping_neg()
+    }
+}
+
+struct Region {
+    /// Physical frames, one reference each held by the region itself
+    frames: Vec<u64>,
+    owner: u32,
+    owner_alive: bool,
+    others_write: bool,
+    /// Tasks that have the region mapped
+    mapped: Vec<u32>,
+}
+
+impl Region {
+    fn unused(&self) -> bool {
+        !self.owner_alive && self.mapped.is_empty()
+    }
+
+    fn release(self) {
+        for frame in self.frames {
+            vmm::release_frame(frame);
+        }
+    }
+}
+
+static REGIONS: Mutex<BTreeMap<u32, Region>> = Mutex::new(BTreeMap::new());
+
+/// Address of region `id` in every task
+pub fn address(id: u32) -> u64 {
+    SHM_BASE + id as u64 * SHM_MAX_SIZE as u64
+}
+
+/// Create a zero-filled region of `size` bytes owned by `owner`
+pub fn create(owner: u32, size: usize, flags: u64) -> Result<u32, ShmError> {
+    if size == 0 || size > SHM_MAX_SIZE || flags & !SHM_OTHERS_WRITE != 0 {
+        return Err(ShmError::Invalid);
+    }
+    let hhdm = crate::boot::hhdm_offset().ok_or(ShmError::NoSpace)?;
+
+    let mut regions = REGIONS.lock();
+    let id = (0..MAX_REGIONS).find(|id| !regions.contains_key(id)).ok_or(ShmError::NoSpace)?;
+
+    let pages = size.div_ceil(4096);
+    let mut frames = Vec::with_capacity(pages);
+    for _ in 0..pages {
+        let frame = match FRAME_ALLOCATOR.lock().allocate() {
+            Some(frame) => frame as u64,
+            None => {
+                for frame in frames {
+                    FRAME_ALLOCATOR.lock().free(frame as usize);
+                }
+                return Err(ShmError::NoSpace);
+            }
+        };
+        unsafe { core::ptr::write_bytes((frame + hhdm) as *mut u8, 0, 4096) };
+        frames.push(frame);
+    }
+
+    regions.insert(id, Region {
+        frames,
+        owner,
+        owner_alive: true,
+        others_write: flags & SHM_OTHERS_WRITE != 0,
+        mapped: Vec::new(),
+    });
+    Ok(id)
+}
+
+/// Map region `id` into `space`, the address space of task `pid`, and
+/// return its address. Mapping a region twice returns the same address.
+pub fn map(pid: u32, space: &mut AddressSpace, id: u32, flags: u64) -> Result<u64, ShmError> {
+    if flags & !SHM_WRITE != 0 {
+        return Err(ShmError::Invalid);
+    }
+    let mut regions = REGIONS.lock();
+    let region = regions.get_mut(&id).ok_or(ShmError::NotFound)?;
+    let writable = flags & SHM_WRITE != 0;
+    if writable && pid != region.owner && !region.others_write {
+        return Err(ShmError::Denied);
+    }
+    if region.mapped.contains(&pid) {
+        return Ok(address(id));
+    }
+
+    space
+        .map_shared(address(id), &region.frames, writable)
+        .map_err(|_| ShmError::Invalid)?;
+    region.mapped.push(pid);
+    Ok(address(id))
+}
+
+/// Remove region `id` from `space`, the address space of task `pid`
+pub fn unmap(pid: u32, space: &mut AddressSpace, id: u32) -> Result<(), ShmError> {
+    let mut regions = REGIONS.lock();
+    let region = regions.get_mut(&id).ok_or(ShmError::NotFound)?;
+    let position = region.mapped.iter().position(|&p| p == pid).ok_or(ShmError::NotFound)?;
+    region.mapped.remove(position);
+    space.unmap_shared(address(id), region.frames.len());
+    if region.unused() {
+        regions.remove(&id).unwrap().release();
+    }
+    Ok(())
+}
+
+/// A forked `child` inherits the parent's mappings
+pub fn fork(parent: u32, child: u32) {
+    for region in REGIONS.lock().values_mut() {
+        if region.mapped.contains(&parent) {
+            region.mapped.push(child);
+        }
+    }
+}
+
+/// Forget the mappings of task `pid`, whose address space is gone
+/// (exec). With `exited`, it also stops keeping its own regions alive.
+pub fn release_task(pid: u32, exited: bool) {
+    let mut regions = REGIONS.lock();
+    for region in regions.values_mut() {
+        region.mapped.retain(|&p| p != pid);
+        if exited && region.owner == pid {
+            region.owner_alive = false;
+        }
+    }
+    let unused: Vec<u32> = regions.iter().filter(|(_, r)| r.unused()).map(|(&id, _)| id).collect();
+    for id in unused {
+        regions.remove(&id).unwrap().release();
+    }
+}
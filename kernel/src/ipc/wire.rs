@@ -0,0 +1,696 @@
+//! Wire format for bus messages
+//!
+//! Messages cross the user/kernel boundary as self-contained frames:
+//!
+//! ```text
+//! +---------+-----+--------------+------------------+
+//! | version | tag | length (LE)  | payload          |
+//! |   u8    | u8  |     u32      | `length` bytes   |
+//! +---------+-----+--------------+------------------+
+//! ```
+//!
+//! The tag names the message variant; each service owns a range of tags
+//! (see `TAG_*`), so a frame can be routed without decoding its payload.
+//! Payload integers are little-endian, strings and byte buffers are a u32
+//! length followed by the bytes, and an `Option` is a 0/1 byte followed by
+//! the value. Decoding rejects other versions, unknown tags and trailing
+//! bytes: new variants take a new tag, changed layouts bump `WIRE_VERSION`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::message::*;
+use crate::fs::vfs::FsError;
+use crate::services::vfs::{FileType, Metadata};
+
+/// Version byte at the start of every frame
+pub const WIRE_VERSION: u8 = 1;
+
+/// Bytes before the payload: version, tag and length
+pub const HEADER_LEN: usize = 6;
+
+/// Tag ranges, one per service (low nibble selects the variant)
+pub const TAG_FS_REQUEST: u8 = 0x10;
+pub const TAG_FS_RESPONSE: u8 = 0x20;
+pub const TAG_UI_REQUEST: u8 = 0x30;
+pub const TAG_PKG_REQUEST: u8 = 0x40;
+pub const TAG_PKG_RESPONSE: u8 = 0x50;
+pub const TAG_SYSTEM_REQUEST: u8 = 0x60;
+pub const TAG_DISPLAY_REQUEST: u8 = 0x70;
+pub const TAG_DISPLAY_RESPONSE: u8 = 0x80;
+/// Bus-level messages (`Message::Unhandled`)
+pub const TAG_BUS: u8 = 0x90;
+pub const TAG_CLIPBOARD_REQUEST: u8 = 0xA0;
+pub const TAG_CLIPBOARD_RESPONSE: u8 = 0xB0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// Frame or field ends early
+    Truncated,
+    /// Frame was written by another version of the format
+    BadVersion(u8),
+    /// Tag unknown for the type being decoded
+    UnknownTag(u8),
+    /// String field is not UTF-8
+    BadUtf8,
+    /// Enum field holds a value with no meaning
+    BadValue,
+    /// Payload is longer than its fields
+    TrailingBytes,
+}
+
+impl WireError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WireError::Truncated => "truncated frame",
+            WireError::BadVersion(_) => "unsupported wire version",
+            WireError::UnknownTag(_) => "unknown message tag",
+            WireError::BadUtf8 => "string is not UTF-8",
+            WireError::BadValue => "invalid field value",
+            WireError::TrailingBytes => "trailing bytes in frame",
+        }
+    }
+}
+
+/// A message with a wire representation
+pub trait Wire: Sized {
+    /// Tag identifying this value's variant
+    fn tag(&self) -> u8;
+    /// Append the payload (everything after the header)
+    fn encode_payload(&self, out: &mut Writer);
+    /// Rebuild a value from the tag and payload of a frame
+    fn decode_payload(tag: u8, payload: &mut Reader) -> Result<Self, WireError>;
+}
+
+/// Encode `msg` as one frame
+pub fn encode<T: Wire>(msg: &T) -> Vec<u8> {
+    let mut out = Writer { buf: Vec::with_capacity(HEADER_LEN) };
+    out.u8(WIRE_VERSION);
+    out.u8(msg.tag());
+    out.u32(0);
+    msg.encode_payload(&mut out);
+    let len = (out.buf.len() - HEADER_LEN) as u32;
+    out.buf[2..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+    out.buf
+}
+
+/// Decode the frame at the start of `bytes`
+///
+/// Returns the message and the length of its frame, so a stream of frames
+/// can be walked.
+pub fn decode<T: Wire>(bytes: &[u8]) -> Result<(T, usize), WireError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(WireError::Truncated);
+    }
+    if bytes[0] != WIRE_VERSION {
+        return Err(WireError::BadVersion(bytes[0]));
+    }
+    let tag = bytes[1];
+    let len = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]) as usize;
+    let end = HEADER_LEN.checked_add(len).ok_or(WireError::Truncated)?;
+    if bytes.len() < end {
+        return Err(WireError::Truncated);
+    }
+
+    let mut payload = Reader { buf: &bytes[HEADER_LEN..end], pos: 0 };
+    let msg = T::decode_payload(tag, &mut payload)?;
+    if payload.pos != payload.buf.len() {
+        return Err(WireError::TrailingBytes);
+    }
+    Ok((msg, end))
+}
+
+/// Payload builder
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn str(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+
+    pub fn strings(&mut self, v: &[String]) {
+        self.u32(v.len() as u32);
+        for s in v {
+            self.str(s);
+        }
+    }
+}
+
+/// Payload cursor
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], WireError> {
+        if self.buf.len() - self.pos < n {
+            return Err(WireError::Truncated);
+        }
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, WireError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, WireError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, WireError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn str(&mut self) -> Result<String, WireError> {
+        String::from_utf8(self.bytes()?).map_err(|_| WireError::BadUtf8)
+    }
+
+    pub fn strings(&mut self) -> Result<Vec<String>, WireError> {
+        let count = self.u32()? as usize;
+        // Every string takes at least its length prefix
+        if count > (self.buf.len() - self.pos) / 4 {
+            return Err(WireError::Truncated);
+        }
+        (0..count).map(|_| self.str()).collect()
+    }
+}
+
+fn encode_error(out: &mut Writer, error: FsError, detail: &Option<String>) {
+    out.u8(error.errno() as u8);
+    match detail {
+        Some(detail) => {
+            out.u8(1);
+            out.str(detail);
+        }
+        None => out.u8(0),
+    }
+}
+
+fn decode_error(r: &mut Reader) -> Result<(FsError, Option<String>), WireError> {
+    let error = FsError::from_errno(r.u8()? as u64).ok_or(WireError::BadValue)?;
+    let detail = match r.u8()? {
+        0 => None,
+        1 => Some(r.str()?),
+        _ => return Err(WireError::BadValue),
+    };
+    Ok((error, detail))
+}
+
+fn encode_metadata(out: &mut Writer, metadata: &Metadata) {
+    out.u8(match metadata.file_type {
+        FileType::Regular => 0,
+        FileType::Directory => 1,
+        FileType::Device => 2,
+        FileType::Link => 3,
+    });
+    out.u64(metadata.size as u64);
+    out.u32(metadata.mode as u32);
+    out.u64(metadata.created);
+    out.u64(metadata.modified);
+}
+
+fn decode_metadata(r: &mut Reader) -> Result<Metadata, WireError> {
+    let file_type = match r.u8()? {
+        0 => FileType::Regular,
+        1 => FileType::Directory,
+        2 => FileType::Device,
+        3 => FileType::Link,
+        _ => return Err(WireError::BadValue),
+    };
+    Ok(Metadata {
+        file_type,
+        size: r.u64()? as usize,
+        mode: u16::try_from(r.u32()?).map_err(|_| WireError::BadValue)?,
+        created: r.u64()?,
+        modified: r.u64()?,
+    })
+}
+
+impl Wire for FSRequest {
+    fn tag(&self) -> u8 {
+        TAG_FS_REQUEST
+            + match self {
+                FSRequest::ListDir { .. } => 0,
+                FSRequest::ReadFile { .. } => 1,
+                FSRequest::WriteFile { .. } => 2,
+                FSRequest::CreateDir { .. } => 3,
+                FSRequest::Delete { .. } => 4,
+                FSRequest::ChangeDir { .. } => 5,
+                FSRequest::GetCwd => 6,
+                FSRequest::Stat { .. } => 7,
+                FSRequest::DeleteTree { .. } => 8,
+                FSRequest::Symlink { .. } => 9,
+                FSRequest::ReadLink { .. } => 10,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            FSRequest::ListDir { path }
+            | FSRequest::ReadFile { path }
+            | FSRequest::CreateDir { path }
+            | FSRequest::Delete { path }
+            | FSRequest::ChangeDir { path }
+            | FSRequest::Stat { path }
+            | FSRequest::DeleteTree { path }
+            | FSRequest::ReadLink { path } => out.str(path),
+            FSRequest::Symlink { target, path } => {
+                out.str(target);
+                out.str(path);
+            }
+            FSRequest::WriteFile { path, data } => {
+                out.str(path);
+                out.bytes(data);
+            }
+            FSRequest::GetCwd => {}
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_FS_REQUEST) {
+            0 => FSRequest::ListDir { path: r.str()? },
+            1 => FSRequest::ReadFile { path: r.str()? },
+            2 => FSRequest::WriteFile { path: r.str()?, data: r.bytes()? },
+            3 => FSRequest::CreateDir { path: r.str()? },
+            4 => FSRequest::Delete { path: r.str()? },
+            5 => FSRequest::ChangeDir { path: r.str()? },
+            6 => FSRequest::GetCwd,
+            7 => FSRequest::Stat { path: r.str()? },
+            8 => FSRequest::DeleteTree { path: r.str()? },
+            9 => FSRequest::Symlink { target: r.str()?, path: r.str()? },
+            10 => FSRequest::ReadLink { path: r.str()? },
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for FSResponse {
+    fn tag(&self) -> u8 {
+        TAG_FS_RESPONSE
+            + match self {
+                FSResponse::DirListing(_) => 0,
+                FSResponse::FileData(_) => 1,
+                FSResponse::Success => 2,
+                FSResponse::Error(..) => 3,
+                FSResponse::Cwd(_) => 4,
+                FSResponse::Metadata(_) => 5,
+                FSResponse::LinkTarget(_) => 6,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            FSResponse::DirListing(entries) => out.strings(entries),
+            FSResponse::FileData(data) => out.bytes(data),
+            FSResponse::Success => {}
+            FSResponse::Error(error, detail) => encode_error(out, *error, detail),
+            FSResponse::Cwd(path) | FSResponse::LinkTarget(path) => out.str(path),
+            FSResponse::Metadata(metadata) => encode_metadata(out, metadata),
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_FS_RESPONSE) {
+            0 => FSResponse::DirListing(r.strings()?),
+            1 => FSResponse::FileData(r.bytes()?.into()),
+            2 => FSResponse::Success,
+            3 => {
+                let (error, detail) = decode_error(r)?;
+                FSResponse::Error(error, detail)
+            }
+            4 => FSResponse::Cwd(r.str()?),
+            5 => FSResponse::Metadata(decode_metadata(r)?),
+            6 => FSResponse::LinkTarget(r.str()?),
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for UIRequest {
+    fn tag(&self) -> u8 {
+        TAG_UI_REQUEST
+            + match self {
+                UIRequest::Print(_) => 0,
+                UIRequest::PrintLn(_) => 1,
+                UIRequest::Clear => 2,
+                UIRequest::SetCursor { .. } => 3,
+                UIRequest::ReadLine => 4,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            UIRequest::Print(text) | UIRequest::PrintLn(text) => out.str(text),
+            UIRequest::SetCursor { x, y } => {
+                out.u32(*x as u32);
+                out.u32(*y as u32);
+            }
+            UIRequest::Clear | UIRequest::ReadLine => {}
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_UI_REQUEST) {
+            0 => UIRequest::Print(r.str()?),
+            1 => UIRequest::PrintLn(r.str()?),
+            2 => UIRequest::Clear,
+            3 => UIRequest::SetCursor { x: r.u32()? as usize, y: r.u32()? as usize },
+            4 => UIRequest::ReadLine,
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for PkgRequest {
+    fn tag(&self) -> u8 {
+        TAG_PKG_REQUEST
+            + match self {
+                PkgRequest::Install { .. } => 0,
+                PkgRequest::Remove { .. } => 1,
+                PkgRequest::Update => 2,
+                PkgRequest::List => 3,
+                PkgRequest::Search { .. } => 4,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            PkgRequest::Install { name } | PkgRequest::Remove { name } => out.str(name),
+            PkgRequest::Search { query } => out.str(query),
+            PkgRequest::Update | PkgRequest::List => {}
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_PKG_REQUEST) {
+            0 => PkgRequest::Install { name: r.str()? },
+            1 => PkgRequest::Remove { name: r.str()? },
+            2 => PkgRequest::Update,
+            3 => PkgRequest::List,
+            4 => PkgRequest::Search { query: r.str()? },
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for PkgResponse {
+    fn tag(&self) -> u8 {
+        TAG_PKG_RESPONSE
+            + match self {
+                PkgResponse::Success(_) => 0,
+                PkgResponse::Error(..) => 1,
+                PkgResponse::PackageList(_) => 2,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            PkgResponse::Success(text) => out.str(text),
+            PkgResponse::Error(error, detail) => encode_error(out, *error, detail),
+            PkgResponse::PackageList(names) => out.strings(names),
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_PKG_RESPONSE) {
+            0 => PkgResponse::Success(r.str()?),
+            1 => {
+                let (error, detail) = decode_error(r)?;
+                PkgResponse::Error(error, detail)
+            }
+            2 => PkgResponse::PackageList(r.strings()?),
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for SystemRequest {
+    fn tag(&self) -> u8 {
+        TAG_SYSTEM_REQUEST
+            + match self {
+                SystemRequest::Shutdown => 0,
+                SystemRequest::Reboot => 1,
+                SystemRequest::GetInfo => 2,
+            }
+    }
+
+    fn encode_payload(&self, _out: &mut Writer) {}
+
+    fn decode_payload(tag: u8, _r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_SYSTEM_REQUEST) {
+            0 => SystemRequest::Shutdown,
+            1 => SystemRequest::Reboot,
+            2 => SystemRequest::GetInfo,
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for DisplayRequest {
+    fn tag(&self) -> u8 {
+        TAG_DISPLAY_REQUEST
+            + match self {
+                DisplayRequest::Create { .. } => 0,
+                DisplayRequest::Move { .. } => 1,
+                DisplayRequest::Raise { .. } => 2,
+                DisplayRequest::Damage { .. } => 3,
+                DisplayRequest::Blit { .. } => 4,
+                DisplayRequest::Destroy { .. } => 5,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            DisplayRequest::Create { owner, title, x, y, width, height } => {
+                out.u32(*owner);
+                out.str(title);
+                out.u32(*x as u32);
+                out.u32(*y as u32);
+                out.u32(*width);
+                out.u32(*height);
+            }
+            DisplayRequest::Move { window, x, y } => {
+                out.u32(*window);
+                out.u32(*x as u32);
+                out.u32(*y as u32);
+            }
+            DisplayRequest::Raise { window } | DisplayRequest::Destroy { window } => out.u32(*window),
+            DisplayRequest::Damage { window, x, y, width, height } => {
+                for v in [*window, *x, *y, *width, *height] {
+                    out.u32(v);
+                }
+            }
+            DisplayRequest::Blit { window, x, y, width, pixels } => {
+                for v in [*window, *x, *y, *width] {
+                    out.u32(v);
+                }
+                let bytes: Vec<u8> = pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+                out.bytes(&bytes);
+            }
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_DISPLAY_REQUEST) {
+            0 => DisplayRequest::Create {
+                owner: r.u32()?,
+                title: r.str()?,
+                x: r.u32()? as i32,
+                y: r.u32()? as i32,
+                width: r.u32()?,
+                height: r.u32()?,
+            },
+            1 => DisplayRequest::Move { window: r.u32()?, x: r.u32()? as i32, y: r.u32()? as i32 },
+            2 => DisplayRequest::Raise { window: r.u32()? },
+            3 => DisplayRequest::Damage {
+                window: r.u32()?,
+                x: r.u32()?,
+                y: r.u32()?,
+                width: r.u32()?,
+                height: r.u32()?,
+            },
+            4 => {
+                let (window, x, y, width) = (r.u32()?, r.u32()?, r.u32()?, r.u32()?);
+                let bytes = r.bytes()?;
+                if bytes.len() % 4 != 0 {
+                    return Err(WireError::BadValue);
+                }
+                let pixels = bytes
+                    .chunks_exact(4)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                DisplayRequest::Blit { window, x, y, width, pixels }
+            }
+            5 => DisplayRequest::Destroy { window: r.u32()? },
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for DisplayResponse {
+    fn tag(&self) -> u8 {
+        TAG_DISPLAY_RESPONSE
+            + match self {
+                DisplayResponse::Created(_) => 0,
+                DisplayResponse::Success => 1,
+                DisplayResponse::Error(..) => 2,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            DisplayResponse::Created(window) => out.u32(*window),
+            DisplayResponse::Success => {}
+            DisplayResponse::Error(error, detail) => encode_error(out, *error, detail),
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_DISPLAY_RESPONSE) {
+            0 => DisplayResponse::Created(r.u32()?),
+            1 => DisplayResponse::Success,
+            2 => {
+                let (error, detail) = decode_error(r)?;
+                DisplayResponse::Error(error, detail)
+            }
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for ClipboardRequest {
+    fn tag(&self) -> u8 {
+        TAG_CLIPBOARD_REQUEST
+            + match self {
+                ClipboardRequest::Get => 0,
+                ClipboardRequest::Set(_) => 1,
+                ClipboardRequest::Clear => 2,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            ClipboardRequest::Get | ClipboardRequest::Clear => {}
+            ClipboardRequest::Set(text) => out.str(text),
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_CLIPBOARD_REQUEST) {
+            0 => ClipboardRequest::Get,
+            1 => ClipboardRequest::Set(r.str()?),
+            2 => ClipboardRequest::Clear,
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Wire for ClipboardResponse {
+    fn tag(&self) -> u8 {
+        TAG_CLIPBOARD_RESPONSE
+            + match self {
+                ClipboardResponse::Text(_) => 0,
+                ClipboardResponse::Success => 1,
+                ClipboardResponse::Error(..) => 2,
+            }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            ClipboardResponse::Text(text) => out.str(text),
+            ClipboardResponse::Success => {}
+            ClipboardResponse::Error(error, detail) => encode_error(out, *error, detail),
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag.wrapping_sub(TAG_CLIPBOARD_RESPONSE) {
+            0 => ClipboardResponse::Text(r.str()?),
+            1 => ClipboardResponse::Success,
+            2 => {
+                let (error, detail) = decode_error(r)?;
+                ClipboardResponse::Error(error, detail)
+            }
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
+
+/// A bus message is framed as the request or reply it carries; the tag
+/// range tells which service it is for
+impl Wire for Message {
+    fn tag(&self) -> u8 {
+        match self {
+            Message::FS(req) => req.tag(),
+            Message::UI(req) => req.tag(),
+            Message::Pkg(req) => req.tag(),
+            Message::System(req) => req.tag(),
+            Message::Display(req) => req.tag(),
+            Message::Clipboard(req) => req.tag(),
+            Message::FSReply(resp) => resp.tag(),
+            Message::DisplayReply(resp) => resp.tag(),
+            Message::ClipboardReply(resp) => resp.tag(),
+            Message::Unhandled => TAG_BUS,
+        }
+    }
+
+    fn encode_payload(&self, out: &mut Writer) {
+        match self {
+            Message::FS(req) => req.encode_payload(out),
+            Message::UI(req) => req.encode_payload(out),
+            Message::Pkg(req) => req.encode_payload(out),
+            Message::System(req) => req.encode_payload(out),
+            Message::Display(req) => req.encode_payload(out),
+            Message::Clipboard(req) => req.encode_payload(out),
+            Message::FSReply(resp) => resp.encode_payload(out),
+            Message::DisplayReply(resp) => resp.encode_payload(out),
+            Message::ClipboardReply(resp) => resp.encode_payload(out),
+            Message::Unhandled => {}
+        }
+    }
+
+    fn decode_payload(tag: u8, r: &mut Reader) -> Result<Self, WireError> {
+        Ok(match tag & 0xF0 {
+            TAG_FS_REQUEST => Message::FS(FSRequest::decode_payload(tag, r)?),
+            TAG_UI_REQUEST => Message::UI(UIRequest::decode_payload(tag, r)?),
+            TAG_PKG_REQUEST => Message::Pkg(PkgRequest::decode_payload(tag, r)?),
+            TAG_SYSTEM_REQUEST => Message::System(SystemRequest::decode_payload(tag, r)?),
+            TAG_DISPLAY_REQUEST => Message::Display(DisplayRequest::decode_payload(tag, r)?),
+            TAG_FS_RESPONSE => Message::FSReply(FSResponse::decode_payload(tag, r)?),
+            TAG_DISPLAY_RESPONSE => Message::DisplayReply(DisplayResponse::decode_payload(tag, r)?),
+            TAG_CLIPBOARD_REQUEST => Message::Clipboard(ClipboardRequest::decode_payload(tag, r)?),
+            TAG_CLIPBOARD_RESPONSE => Message::ClipboardReply(ClipboardResponse::decode_payload(tag, r)?),
+            TAG_BUS if tag == TAG_BUS => Message::Unhandled,
+            _ => return Err(WireError::UnknownTag(tag)),
+        })
+    }
+}
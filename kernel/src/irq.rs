@@ -0,0 +1,190 @@
+//! Interrupt handlers registered at run time
+//!
+//! Hardware IRQ lines 0-31 arrive at vectors 0x20-0x3F: the ISA IRQs where
+//! the PICs put them, PCI lines on the IO-APIC pins above them. Every one
+//! of those vectors has a stub in the IDT that calls `dispatch`, which runs
+//! the handlers drivers have registered on the line, in the order they
+//! were registered, and then acknowledges the interrupt. The timer is the
+//! exception: its vector is the raw entry that switches tasks, which runs
+//! line 0's handlers itself before acknowledging.
+//!
+//! A line can be shared. Every handler on it runs on every interrupt and
+//! returns whether its device had raised it; one that nobody claims is
+//! counted as spurious (see /proc/interrupts).
+//!
+//! Handlers run with interrupts off and must not block or allocate.
+//! `register` and `unregister` keep interrupts off while they change a
+//! line, and `dispatch` copies the line's handlers out before running
+//! them, so a handler may unregister itself.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::{HandlerFunc, InterruptStackFrame};
+
+/// IRQ lines with a vector of their own
+pub const LINES: usize = 32;
+
+/// Vector of line 0; line n is at `VECTOR_BASE + n`
+pub const VECTOR_BASE: u8 = crate::interrupts::PIC1_OFFSET;
+
+/// Most handlers on one line
+const MAX_SHARED: usize = 4;
+
+/// Handles an interrupt on its line; true if its device raised it
+pub type Handler = fn() -> bool;
+
+#[derive(Clone, Copy)]
+struct Action {
+    name: &'static str,
+    handler: Handler,
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    /// Registered handlers first, in order, then `None`s
+    actions: [Option<Action>; MAX_SHARED],
+    count: u64,
+    spurious: u64,
+}
+
+const EMPTY: Line = Line { actions: [None; MAX_SHARED], count: 0, spurious: 0 };
+
+static TABLE: Mutex<[Line; LINES]> = Mutex::new([EMPTY; LINES]);
+
+/// Run `handler` on every interrupt on `line`, after the handlers already
+/// there; `name` identifies it to `unregister` and in /proc/interrupts.
+/// Registering does not unmask the line (`interrupts::enable_irq` does).
+pub fn register(line: u8, name: &'static str, handler: Handler) -> Result<(), &'static str> {
+    without_interrupts(|| {
+        let mut table = TABLE.lock();
+        let state = table.get_mut(line as usize).ok_or("no such IRQ line")?;
+        if state.actions.iter().flatten().any(|action| action.name == name) {
+            return Err("handler already registered");
+        }
+        let slot = state.actions.iter_mut().find(|slot| slot.is_none()).ok_or("too many handlers on the line")?;
+        *slot = Some(Action { name, handler });
+        Ok(())
+    })
+}
+
+/// Remove the handler `name` from `line`; false if it was not there. The
+/// line stays unmasked.
+pub fn unregister(line: u8, name: &str) -> bool {
+    without_interrupts(|| {
+        let mut table = TABLE.lock();
+        let Some(state) = table.get_mut(line as usize) else { return false };
+        let Some(i) = state.actions.iter().position(|slot| slot.is_some_and(|action| action.name == name)) else {
+            return false;
+        };
+        state.actions[i..].rotate_left(1);
+        state.actions[MAX_SHARED - 1] = None;
+        true
+    })
+}
+
+/// Run the handlers of `line`; the caller acknowledges the interrupt
+pub fn run(line: u8) {
+    let actions = {
+        let mut table = TABLE.lock();
+        let Some(state) = table.get_mut(line as usize) else { return };
+        state.count += 1;
+        state.actions
+    };
+    let mut claimed = false;
+    for action in actions.iter().flatten() {
+        claimed |= (action.handler)();
+    }
+    if !claimed {
+        TABLE.lock()[line as usize].spurious += 1;
+    }
+}
+
+/// Handle an interrupt on `line` from start to end
+pub fn dispatch(line: u8) {
+    run(line);
+    crate::interrupts::notify_end_of_interrupt(line);
+}
+
+/// What /proc/interrupts shows about a line
+pub struct LineInfo {
+    pub line: u8,
+    pub count: u64,
+    pub spurious: u64,
+    pub handlers: Vec<&'static str>,
+}
+
+/// Every line that has handlers or has fired
+pub fn lines() -> Vec<LineInfo> {
+    let table = without_interrupts(|| *TABLE.lock());
+    table
+        .iter()
+        .enumerate()
+        .filter(|(_, state)| state.count > 0 || state.actions[0].is_some())
+        .map(|(line, state)| LineInfo {
+            line: line as u8,
+            count: state.count,
+            spurious: state.spurious,
+            handlers: state.actions.iter().flatten().map(|action| action.name).collect(),
+        })
+        .collect()
+}
+
+macro_rules! stubs {
+    ($($line:literal)*) => {
+        /// IDT entries of the lines, by line; line 0's is not installed
+        pub const STUBS: [HandlerFunc; LINES] = [$({
+            extern "x86-interrupt" fn stub(_stack_frame: InterruptStackFrame) {
+                dispatch($line);
+            }
+            stub
+        }),*];
+    };
+}
+
+stubs!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Nothing uses it outside these tests
+    const LINE: u8 = LINES as u8 - 1;
+
+    static FIRST: AtomicUsize = AtomicUsize::new(0);
+    static SECOND: AtomicUsize = AtomicUsize::new(0);
+
+    fn first() -> bool {
+        FIRST.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    fn second() -> bool {
+        SECOND.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    fn info() -> LineInfo {
+        lines().into_iter().find(|info| info.line == LINE).unwrap()
+    }
+
+    #[test_case]
+    fn shared_lines_run_every_handler_until_unregistered() {
+        register(LINE, "first", first).unwrap();
+        register(LINE, "second", second).unwrap();
+        assert!(register(LINE, "second", second).is_err());
+        run(LINE);
+        assert_eq!((FIRST.load(Ordering::Relaxed), SECOND.load(Ordering::Relaxed)), (1, 1));
+        assert_eq!(info().handlers, ["first", "second"]);
+
+        assert!(unregister(LINE, "second"));
+        assert!(!unregister(LINE, "second"));
+        run(LINE);
+        assert_eq!((FIRST.load(Ordering::Relaxed), SECOND.load(Ordering::Relaxed)), (2, 1));
+        let info = info();
+        assert_eq!((info.count, info.spurious), (2, 1));
+        assert_eq!(info.handlers, ["first"]);
+        assert!(unregister(LINE, "first"));
+    }
+}
@@ -0,0 +1,377 @@
+//! Remappable keys for the shell line editor and grape
+//!
+//! Keys that trigger an action (cancel, completion, history, save, ...) are
+//! looked up in a binding table instead of being matched directly. The
+//! table starts from the built-in defaults; /etc/ospab/keybindings.conf
+//! overrides them at boot and on `bind reload`:
+//!
+//! ```text
+//! # context key = action
+//! shell ctrl+p = history-prev
+//! editor ctrl+s = save
+//! editor ctrl+x = none
+//! ```
+//!
+//! Keys are `ctrl+a` .. `ctrl+z`, `tab`, `esc`, the arrow keys `up`,
+//! `down`, `left`, `right` and `ctrl+left`, `ctrl+right`; `none` unbinds a
+//! key. Typing, Enter and
+//! Backspace are not bindable.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::ipc::message::{FSRequest, FSResponse};
+
+pub const CONFIG_PATH: &str = "/etc/ospab/keybindings.conf";
+
+/// Which line editor a binding applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Shell,
+    Editor,
+}
+
+impl Context {
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Shell => "shell",
+            Context::Editor => "editor",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "shell" => Some(Context::Shell),
+            "editor" | "grape" => Some(Context::Editor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Control character: Ctrl+letter, Tab or Esc
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    CtrlLeft,
+    CtrlRight,
+}
+
+impl Key {
+    /// Parse a key as written in keybindings.conf
+    pub fn parse(name: &str) -> Result<Self, &'static str> {
+        let name = name.to_ascii_lowercase();
+        let key = match name.as_str() {
+            "tab" => Key::Char('\t'),
+            "esc" | "escape" => Key::Char('\x1b'),
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "ctrl+left" => Key::CtrlLeft,
+            "ctrl+right" => Key::CtrlRight,
+            _ => {
+                let letter = name.strip_prefix("ctrl+")
+                    .and_then(|rest| {
+                        let mut chars = rest.chars();
+                        chars.next().filter(|c| c.is_ascii_lowercase() && chars.next().is_none())
+                    })
+                    .ok_or("unknown key")?;
+                // These arrive as Backspace and Enter
+                if matches!(letter, 'h' | 'j' | 'm') {
+                    return Err("key is reserved for Backspace/Enter");
+                }
+                Key::Char((letter as u8 - b'a' + 1) as char)
+            }
+        };
+        Ok(key)
+    }
+
+    /// Name as written in keybindings.conf
+    pub fn name(self) -> String {
+        match self {
+            Key::Char('\t') => "tab".to_string(),
+            Key::Char('\x1b') => "esc".to_string(),
+            Key::Char(c) => format!("ctrl+{}", (c as u8 + b'a' - 1) as char),
+            Key::Up => "up".to_string(),
+            Key::Down => "down".to_string(),
+            Key::Left => "left".to_string(),
+            Key::Right => "right".to_string(),
+            Key::CtrlLeft => "ctrl+left".to_string(),
+            Key::CtrlRight => "ctrl+right".to_string(),
+        }
+    }
+
+    /// Short form for help bars: "^X", "Tab", "Up"
+    pub fn label(self) -> String {
+        match self {
+            Key::Char('\t') => "Tab".to_string(),
+            Key::Char('\x1b') => "Esc".to_string(),
+            Key::Char(c) => format!("^{}", (c as u8 + b'A' - 1) as char),
+            Key::Up => "Up".to_string(),
+            Key::Down => "Down".to_string(),
+            Key::Left => "Left".to_string(),
+            Key::Right => "Right".to_string(),
+            Key::CtrlLeft => "^Left".to_string(),
+            Key::CtrlRight => "^Right".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // Shell
+    Cancel,
+    /// Stop the foreground job (nothing at the prompt)
+    Suspend,
+    Complete,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    CursorLeft,
+    CursorRight,
+    ClearScreen,
+    /// Type the clipboard into the line
+    PasteClipboard,
+    // Editor
+    Help,
+    Save,
+    Exit,
+    Search,
+    Cut,
+    Paste,
+    Undo,
+    Highlight,
+    Open,
+    Wrap,
+    NextBuffer,
+    PrevBuffer,
+}
+
+const ACTIONS: &[Action] = &[
+    Action::Cancel,
+    Action::Suspend,
+    Action::Complete,
+    Action::HistoryPrev,
+    Action::HistoryNext,
+    Action::HistorySearch,
+    Action::CursorLeft,
+    Action::CursorRight,
+    Action::ClearScreen,
+    Action::PasteClipboard,
+    Action::Help,
+    Action::Save,
+    Action::Exit,
+    Action::Search,
+    Action::Cut,
+    Action::Paste,
+    Action::Undo,
+    Action::Highlight,
+    Action::Open,
+    Action::Wrap,
+    Action::NextBuffer,
+    Action::PrevBuffer,
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Cancel => "cancel",
+            Action::Suspend => "suspend",
+            Action::Complete => "complete",
+            Action::HistoryPrev => "history-prev",
+            Action::HistoryNext => "history-next",
+            Action::HistorySearch => "history-search",
+            Action::CursorLeft => "cursor-left",
+            Action::CursorRight => "cursor-right",
+            Action::ClearScreen => "clear-screen",
+            Action::PasteClipboard => "paste",
+            Action::Help => "help",
+            Action::Save => "save",
+            Action::Exit => "exit",
+            Action::Search => "search",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::Undo => "undo",
+            Action::Highlight => "highlight",
+            Action::Open => "open",
+            Action::Wrap => "wrap",
+            Action::NextBuffer => "next-buffer",
+            Action::PrevBuffer => "prev-buffer",
+        }
+    }
+
+    /// The line editor that performs the action
+    pub fn context(self) -> Context {
+        match self {
+            Action::Cancel
+            | Action::Suspend
+            | Action::Complete
+            | Action::HistoryPrev
+            | Action::HistoryNext
+            | Action::HistorySearch
+            | Action::CursorLeft
+            | Action::CursorRight
+            | Action::ClearScreen
+            | Action::PasteClipboard => Context::Shell,
+            _ => Context::Editor,
+        }
+    }
+
+    pub fn parse(context: Context, name: &str) -> Option<Self> {
+        ACTIONS.iter().copied().find(|action| action.name() == name && action.context() == context)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub context: Context,
+    pub key: Key,
+    pub action: Action,
+}
+
+const fn bind(context: Context, key: Key, action: Action) -> Binding {
+    Binding { context, key, action }
+}
+
+/// Built-in keys, used until keybindings.conf is read
+pub const DEFAULTS: &[Binding] = &[
+    bind(Context::Shell, Key::Char('\x03'), Action::Cancel),
+    bind(Context::Shell, Key::Char('\x1a'), Action::Suspend),
+    bind(Context::Shell, Key::Char('\t'), Action::Complete),
+    bind(Context::Shell, Key::Up, Action::HistoryPrev),
+    bind(Context::Shell, Key::Down, Action::HistoryNext),
+    bind(Context::Shell, Key::Char('\x12'), Action::HistorySearch),
+    bind(Context::Shell, Key::Left, Action::CursorLeft),
+    bind(Context::Shell, Key::Right, Action::CursorRight),
+    bind(Context::Shell, Key::Char('\x0C'), Action::ClearScreen),
+    // Ctrl+V, also what Ctrl+Shift+V gives
+    bind(Context::Shell, Key::Char('\x16'), Action::PasteClipboard),
+    bind(Context::Editor, Key::Char('\x07'), Action::Help),
+    bind(Context::Editor, Key::Char('\x18'), Action::Save),
+    bind(Context::Editor, Key::Char('\x03'), Action::Exit),
+    bind(Context::Editor, Key::Char('\x17'), Action::Search),
+    bind(Context::Editor, Key::Char('\x0B'), Action::Cut),
+    bind(Context::Editor, Key::Char('\x15'), Action::Paste),
+    bind(Context::Editor, Key::Char('\x1A'), Action::Undo),
+    bind(Context::Editor, Key::Char('\x14'), Action::Highlight),
+    bind(Context::Editor, Key::Char('\x0F'), Action::Open),
+    bind(Context::Editor, Key::Char('\x0C'), Action::Wrap),
+    bind(Context::Editor, Key::CtrlRight, Action::NextBuffer),
+    bind(Context::Editor, Key::CtrlLeft, Action::PrevBuffer),
+];
+
+/// Table in use; None until something is loaded, meaning `DEFAULTS`
+static BINDINGS: Mutex<Option<Vec<Binding>>> = Mutex::new(None);
+
+/// Action bound to `key` in `context`
+pub fn lookup(context: Context, key: Key) -> Option<Action> {
+    let bindings = BINDINGS.lock();
+    bindings.as_deref().unwrap_or(DEFAULTS).iter()
+        .find(|binding| binding.context == context && binding.key == key)
+        .map(|binding| binding.action)
+}
+
+/// First key bound to `action`, for help text
+pub fn key_for(action: Action) -> Option<Key> {
+    let bindings = BINDINGS.lock();
+    bindings.as_deref().unwrap_or(DEFAULTS).iter()
+        .find(|binding| binding.action == action)
+        .map(|binding| binding.key)
+}
+
+/// Snapshot of the table
+pub fn list() -> Vec<Binding> {
+    BINDINGS.lock().as_deref().unwrap_or(DEFAULTS).to_vec()
+}
+
+/// Bind `key` to `action` in `context`, or unbind it with None
+fn apply(table: &mut Vec<Binding>, context: Context, key: Key, action: Option<Action>) {
+    table.retain(|binding| !(binding.context == context && binding.key == key));
+    if let Some(action) = action {
+        table.push(Binding { context, key, action });
+    }
+}
+
+/// Parse "context key" and "action" into a binding change
+fn parse_binding(context: &str, key: &str, action: &str) -> Result<(Context, Key, Option<Action>), String> {
+    let context = Context::parse(context).ok_or_else(|| format!("unknown context '{}'", context))?;
+    let key = Key::parse(key).map_err(|e| format!("{}: {}", key, e))?;
+    let action = match action {
+        "none" => None,
+        name => Some(Action::parse(context, name)
+            .ok_or_else(|| format!("unknown {} action '{}'", context.name(), name))?),
+    };
+    Ok((context, key, action))
+}
+
+/// Change one binding until the next reload
+pub fn set(context: &str, key: &str, action: &str) -> Result<(), String> {
+    let (context, key, action) = parse_binding(context, key, action)?;
+    let mut bindings = BINDINGS.lock();
+    let table = bindings.get_or_insert_with(|| DEFAULTS.to_vec());
+    apply(table, context, key, action);
+    Ok(())
+}
+
+/// Rebuild the table from the defaults and keybindings.conf
+///
+/// Returns how many lines of the file were applied. On any error the table
+/// in use is kept and every bad line is reported.
+pub fn reload() -> Result<usize, Vec<String>> {
+    let mut table = DEFAULTS.to_vec();
+    let data = match crate::services::vfs::process_request(FSRequest::ReadFile {
+        path: CONFIG_PATH.to_string(),
+    }) {
+        FSResponse::FileData(data) => data,
+        // No file: the defaults
+        _ => Default::default(),
+    };
+    let text = core::str::from_utf8(&data).map_err(|_| Vec::from(["not UTF-8".to_string()]))?;
+
+    let mut applied = 0;
+    let mut errors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once('=')
+            .ok_or_else(|| "expected 'context key = action'".to_string())
+            .and_then(|(left, action)| {
+                let mut words = left.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(context), Some(key), None) => parse_binding(context, key, action.trim()),
+                    _ => Err("expected 'context key = action'".to_string()),
+                }
+            });
+        match parsed {
+            Ok((context, key, action)) => {
+                apply(&mut table, context, key, action);
+                applied += 1;
+            }
+            Err(e) => errors.push(format!("line {}: {}", number + 1, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    *BINDINGS.lock() = Some(table);
+    Ok(applied)
+}
+
+/// Read keybindings.conf at boot (after the initrd, which may ship one)
+pub fn init() {
+    match reload() {
+        Ok(count) => crate::serial_println!("[KEYS] {} bindings from {}", count, CONFIG_PATH),
+        Err(errors) => {
+            for error in errors {
+                crate::serial_println!("[KEYS] {}: {}", CONFIG_PATH, error);
+            }
+        }
+    }
+}
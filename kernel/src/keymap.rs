@@ -0,0 +1,293 @@
+//! Keyboard layouts
+//!
+//! The decoder turns scancodes into characters of the US layout; a keymap
+//! replaces what the printable keys type. Built in are `us`, `uk`, `de`,
+//! `fr` and `dvorak`; any other name is read from /etc/keymaps/<name>.map,
+//! and a path to a map file works too. `loadkeys` switches layouts and
+//! /etc/keymap names the one loaded at boot.
+//!
+//! A map file has one key per line: its scancode (set 1, the US position),
+//! then what it types alone, with Shift and with AltGr. `-` types nothing
+//! and `U+20AC` is a character by code point; missing columns type nothing:
+//!
+//! ```text
+//! # scancode normal shift altgr
+//! 0x10 q Q @
+//! 0x12 e E U+20AC
+//! ```
+//!
+//! Keys a map leaves out keep their US meaning. Caps Lock shifts keys whose
+//! Shift column is the upper case of the normal one. There are no dead
+//! keys: ^ and ´ type themselves.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::ipc::message::{FSRequest, FSResponse};
+
+pub const CONFIG_PATH: &str = "/etc/keymap";
+/// Where layouts that are not built in are looked for
+pub const KEYMAP_DIR: &str = "/etc/keymaps";
+
+const UK: &str = "
+0x29 ` U+00AC U+00A6
+0x03 2 \"
+0x04 3 U+00A3
+0x28 ' @
+0x2B # ~
+0x56 \\ |
+";
+
+const DE: &str = "
+0x29 ^ U+00B0
+0x02 1 !
+0x03 2 \" U+00B2
+0x04 3 U+00A7 U+00B3
+0x05 4 $
+0x06 5 %
+0x07 6 &
+0x08 7 / {
+0x09 8 ( [
+0x0A 9 ) ]
+0x0B 0 = }
+0x0C U+00DF ? \\
+0x0D U+00B4 `
+0x10 q Q @
+0x12 e E U+20AC
+0x15 z Z
+0x1A U+00FC U+00DC
+0x1B + * ~
+0x27 U+00F6 U+00D6
+0x28 U+00E4 U+00C4
+0x2B # '
+0x56 < > |
+0x2C y Y
+0x32 m M U+00B5
+0x33 , ;
+0x34 . :
+0x35 U+002D _
+";
+
+const FR: &str = "
+0x29 U+00B2
+0x02 & 1
+0x03 U+00E9 2 ~
+0x04 \" 3 #
+0x05 ' 4 {
+0x06 ( 5 [
+0x07 U+002D 6 |
+0x08 U+00E8 7 `
+0x09 _ 8 \\
+0x0A U+00E7 9 ^
+0x0B U+00E0 0 @
+0x0C ) U+00B0 ]
+0x0D = + }
+0x10 a A
+0x11 z Z
+0x12 e E U+20AC
+0x1A ^ U+00A8
+0x1B $ U+00A3 U+00A4
+0x1E q Q
+0x27 m M
+0x28 U+00F9 %
+0x2B * U+00B5
+0x56 < >
+0x2C w W
+0x32 , ?
+0x33 ; .
+0x34 : /
+0x35 ! U+00A7
+";
+
+const DVORAK: &str = "
+0x0C [ {
+0x0D ] }
+0x10 ' \"
+0x11 , <
+0x12 . >
+0x13 p P
+0x14 y Y
+0x15 f F
+0x16 g G
+0x17 c C
+0x18 r R
+0x19 l L
+0x1A / ?
+0x1B = +
+0x1E a A
+0x1F o O
+0x20 e E
+0x21 u U
+0x22 i I
+0x23 d D
+0x24 h H
+0x25 t T
+0x26 n N
+0x27 s S
+0x28 U+002D _
+0x2C ; :
+0x2D q Q
+0x2E j J
+0x2F k K
+0x30 x X
+0x31 b B
+0x32 m M
+0x33 w W
+0x34 v V
+0x35 z Z
+";
+
+/// Layouts that need no file
+pub const BUILTIN: &[(&str, &str)] = &[("us", ""), ("uk", UK), ("de", DE), ("fr", FR), ("dvorak", DVORAK)];
+
+/// What one key types
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyDef {
+    normal: Option<char>,
+    shift: Option<char>,
+    altgr: Option<char>,
+}
+
+impl KeyDef {
+    /// Caps Lock applies: the key is a letter
+    fn is_letter(&self) -> bool {
+        match (self.normal, self.shift) {
+            (Some(normal), Some(shift)) => normal.is_alphabetic() && normal.to_uppercase().eq(core::iter::once(shift)),
+            _ => false,
+        }
+    }
+}
+
+struct Keymap {
+    name: String,
+    keys: BTreeMap<u8, KeyDef>,
+}
+
+/// Layout in use; None means plain US
+static KEYMAP: Mutex<Option<Keymap>> = Mutex::new(None);
+
+/// One column of a map file
+fn parse_char(field: &str) -> Result<Option<char>, String> {
+    if field == "-" {
+        return Ok(None);
+    }
+    if let Some(hex) = field.strip_prefix("U+").or_else(|| field.strip_prefix("u+")) {
+        return u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Some)
+            .ok_or_else(|| format!("bad code point '{}'", field));
+    }
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Some(c)),
+        _ => Err(format!("'{}' is not one character", field)),
+    }
+}
+
+fn parse_scancode(field: &str) -> Result<u8, String> {
+    let value = match field.strip_prefix("0x").or_else(|| field.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => field.parse::<u8>(),
+    };
+    match value {
+        Ok(code) if code > 0 && code < 0x80 => Ok(code),
+        _ => Err(format!("bad scancode '{}'", field)),
+    }
+}
+
+/// Parse a map file; every bad line is reported
+fn parse(text: &str) -> Result<BTreeMap<u8, KeyDef>, Vec<String>> {
+    let mut keys = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 4 {
+            errors.push(format!("line {}: expected 'scancode normal [shift [altgr]]'", number + 1));
+            continue;
+        }
+        let parsed = parse_scancode(fields[0]).and_then(|code| {
+            let column = |i: usize| fields.get(i).map_or(Ok(None), |field| parse_char(field));
+            Ok((code, KeyDef { normal: column(1)?, shift: column(2)?, altgr: column(3)? }))
+        });
+        match parsed {
+            Ok((code, key)) => {
+                keys.insert(code, key);
+            }
+            Err(e) => errors.push(format!("line {}: {}", number + 1, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(keys)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Map text for `name`: a built-in layout, a file in KEYMAP_DIR or a path
+fn source(name: &str) -> Result<String, String> {
+    if let Some((_, text)) = BUILTIN.iter().find(|(builtin, _)| *builtin == name) {
+        return Ok(text.to_string());
+    }
+    let path = if name.contains('/') { name.to_string() } else { format!("{}/{}.map", KEYMAP_DIR, name) };
+    match crate::services::vfs::process_request(FSRequest::ReadFile { path: path.clone() }) {
+        FSResponse::FileData(data) => String::from_utf8(data.into_vec()).map_err(|_| format!("{}: not UTF-8", path)),
+        _ => Err(format!("{}: no such layout", name)),
+    }
+}
+
+/// Switch to layout `name`; on any error the current one is kept and every
+/// problem is reported. Returns how many keys the layout changes
+pub fn load(name: &str) -> Result<usize, Vec<String>> {
+    let text = source(name).map_err(|e| Vec::from([e]))?;
+    let keys = parse(&text)?;
+    let count = keys.len();
+    *KEYMAP.lock() = if keys.is_empty() { None } else { Some(Keymap { name: name.to_string(), keys }) };
+    Ok(count)
+}
+
+/// Name of the layout in use
+pub fn current() -> String {
+    KEYMAP.lock().as_ref().map_or_else(|| "us".to_string(), |keymap| keymap.name.clone())
+}
+
+/// What the key with make code `scancode` types under the current layout:
+/// None if the layout leaves it to the decoder, Some(None) if it types
+/// nothing
+pub fn translate(scancode: u8, shift: bool, caps: bool, altgr: bool) -> Option<Option<char>> {
+    let keymap = KEYMAP.lock();
+    let key = keymap.as_ref()?.keys.get(&scancode)?;
+    if altgr {
+        return Some(key.altgr);
+    }
+    let shifted = if key.is_letter() { shift != caps } else { shift };
+    Some(if shifted { key.shift } else { key.normal })
+}
+
+/// Load the layout named in /etc/keymap at boot (after the initrd, which
+/// may ship one)
+pub fn init() {
+    let FSResponse::FileData(data) = crate::services::vfs::process_request(FSRequest::ReadFile {
+        path: CONFIG_PATH.to_string(),
+    }) else {
+        return;
+    };
+    let text = String::from_utf8_lossy(&data);
+    let Some(name) = text.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#')) else {
+        return;
+    };
+    match load(name) {
+        Ok(count) => crate::serial_println!("[KEYS] layout {} ({} keys)", name, count),
+        Err(errors) => {
+            for error in errors {
+                crate::serial_println!("[KEYS] {}: {}: {}", CONFIG_PATH, name, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Kernel log ring buffer (dmesg)
+//!
+//! Everything written to the serial log is also kept here, each line
+//! stamped with the uptime, so `dmesg` works without a serial cable. The
+//! buffer is a fixed array, usable before the heap exists; when it fills
+//! up, the oldest bytes are overwritten.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const LOG_SIZE: usize = 64 * 1024;
+
+struct Ring {
+    buf: [u8; LOG_SIZE],
+    /// Index of the oldest byte
+    start: usize,
+    len: usize,
+    /// The next byte starts a line and gets a timestamp first
+    line_start: bool,
+}
+
+impl Ring {
+    fn push(&mut self, byte: u8) {
+        let end = (self.start + self.len) % LOG_SIZE;
+        self.buf[end] = byte;
+        if self.len == LOG_SIZE {
+            self.start = (self.start + 1) % LOG_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        for &byte in s.as_bytes() {
+            self.push(byte);
+        }
+    }
+}
+
+static LOG: Mutex<Ring> = Mutex::new(Ring { buf: [0; LOG_SIZE], start: 0, len: 0, line_start: true });
+
+/// Append `text` to the log
+pub fn write(text: &str) {
+    // Interrupt handlers log too; they must not find the lock taken
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut log = LOG.lock();
+        for line in text.split_inclusive('\n') {
+            if log.line_start {
+                let ms = crate::drivers::timer::get_uptime_ms();
+                let mut stamp = [0u8; 24];
+                let stamp = format_stamp(&mut stamp, ms);
+                log.push_str(stamp);
+            }
+            log.push_str(line);
+            log.line_start = line.ends_with('\n');
+        }
+    });
+}
+
+/// "[   12.340000] " into `buf`, without allocating
+fn format_stamp(buf: &mut [u8; 24], ms: u64) -> &str {
+    use core::fmt::Write;
+
+    struct Cursor<'a> {
+        buf: &'a mut [u8],
+        len: usize,
+    }
+
+    impl Write for Cursor<'_> {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.buf.get_mut(self.len..end).ok_or(core::fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    let mut cursor = Cursor { buf, len: 0 };
+    let _ = write!(cursor, "[{:>5}.{:06}] ", ms / 1000, (ms % 1000) * 1000);
+    let len = cursor.len;
+    core::str::from_utf8(&buf[..len]).unwrap_or("")
+}
+
+/// The whole log, oldest line first
+pub fn read() -> String {
+    let bytes: Vec<u8> = x86_64::instructions::interrupts::without_interrupts(|| {
+        let log = LOG.lock();
+        (0..log.len).map(|i| log.buf[(log.start + i) % LOG_SIZE]).collect()
+    });
+    // After wrapping, the first line is cut off somewhere in the middle
+    let wrapped = bytes.len() == LOG_SIZE;
+    let text = String::from_utf8_lossy(&bytes);
+    match text.find('\n') {
+        Some(end) if wrapped => String::from(&text[end + 1..]),
+        _ => text.into_owned(),
+    }
+}
+
+/// Empty the log (dmesg -c)
+pub fn clear() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut log = LOG.lock();
+        log.start = 0;
+        log.len = 0;
+        log.line_start = true;
+    });
+}
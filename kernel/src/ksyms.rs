@@ -0,0 +1,205 @@
+//! The kernel's own symbol table
+//!
+//! Limine hands over the kernel ELF it booted (`boot::limine::kernel_file`),
+//! .symtab included, so an address in kernel code can be put down to the
+//! function it lies in without shipping a separate map. The table is read
+//! on first use and kept for good; names stay mangled until `demangle`.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::loader::elf::{read_at, Elf64Header, ELF_MAGIC};
+
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Elf64SectionHeader {
+    sh_name: u32,
+    sh_type: u32,
+    sh_flags: u64,
+    sh_addr: u64,
+    sh_offset: u64,
+    sh_size: u64,
+    sh_link: u32,
+    sh_info: u32,
+    sh_addralign: u64,
+    sh_entsize: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Elf64Sym {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: u64,
+    st_size: u64,
+}
+
+/// A kernel function
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub start: u64,
+    /// End-exclusive; a symbol without a size runs to the next one
+    pub end: u64,
+    /// Mangled, as in the ELF
+    pub name: &'static str,
+}
+
+static TABLE: Mutex<Option<&'static [Symbol]>> = Mutex::new(None);
+
+/// Every kernel function, by address; empty if the bootloader did not
+/// pass the kernel file or it was stripped
+pub fn table() -> &'static [Symbol] {
+    *TABLE.lock().get_or_insert_with(|| {
+        let symbols = crate::boot::limine::kernel_file().map(parse).unwrap_or_default();
+        Vec::leak(symbols)
+    })
+}
+
+/// Index in `symbols`, sorted as `table` returns them, of the symbol
+/// `addr` lies in
+pub fn find(symbols: &[Symbol], addr: u64) -> Option<usize> {
+    let i = symbols.partition_point(|symbol| symbol.start <= addr).checked_sub(1)?;
+    (addr < symbols[i].end).then_some(i)
+}
+
+/// Section header `index` of `elf`
+fn section(elf: &[u8], header: &Elf64Header, index: usize) -> Option<Elf64SectionHeader> {
+    read_at(elf, (header.e_shoff as usize).checked_add(index.checked_mul(header.e_shentsize as usize)?)?)
+}
+
+/// The bytes of `section`
+fn contents<'a>(elf: &'a [u8], section: &Elf64SectionHeader) -> Option<&'a [u8]> {
+    let start = section.sh_offset as usize;
+    elf.get(start..start.checked_add(section.sh_size as usize)?)
+}
+
+/// The functions in the .symtab of `elf`, sorted by address
+fn parse(elf: &'static [u8]) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let header: Elf64Header = match read_at(elf, 0) {
+        Some(header) if header.e_ident[..4] == ELF_MAGIC => header,
+        _ => return symbols,
+    };
+    for index in 0..header.e_shnum as usize {
+        let Some(symtab) = section(elf, &header, index) else { break };
+        if symtab.sh_type != SHT_SYMTAB {
+            continue;
+        }
+        let strtab = section(elf, &header, symtab.sh_link as usize);
+        let (Some(entries), Some(strings)) = (contents(elf, &symtab), strtab.and_then(|strtab| contents(elf, &strtab)))
+        else {
+            continue;
+        };
+        let size = core::mem::size_of::<Elf64Sym>();
+        for at in (0..entries.len() / size).map(|i| i * size) {
+            let Some(sym) = read_at::<Elf64Sym>(entries, at) else { break };
+            if sym.st_info & 0xF != STT_FUNC || sym.st_value == 0 {
+                continue;
+            }
+            let name = strings.get(sym.st_name as usize..).unwrap_or_default();
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            let Ok(name) = core::str::from_utf8(name) else { continue };
+            symbols.push(Symbol { start: sym.st_value, end: sym.st_value + sym.st_size, name });
+        }
+    }
+    symbols.sort_unstable_by_key(|symbol| symbol.start);
+    for i in 0..symbols.len() {
+        if symbols[i].end == symbols[i].start {
+            symbols[i].end = symbols.get(i + 1).map_or(symbols[i].start, |next| next.start);
+        }
+    }
+    symbols
+}
+
+/// What the legacy Rust mangling escapes, and what it stands for
+const ESCAPES: &[(&str, &str)] = &[
+    ("$LT$", "<"),
+    ("$GT$", ">"),
+    ("$RF$", "&"),
+    ("$BP$", "*"),
+    ("$C$", ","),
+    ("$SP$", "@"),
+    ("$u20$", " "),
+    ("$u22$", "\""),
+    ("$u27$", "'"),
+    ("$u2b$", "+"),
+    ("$u3b$", ";"),
+    ("$u5b$", "["),
+    ("$u5d$", "]"),
+    ("$u7b$", "{"),
+    ("$u7d$", "}"),
+    ("$u7e$", "~"),
+];
+
+fn unescape(part: &str) -> String {
+    // A leading `_` only keeps the identifier from starting with `$`
+    let mut rest = if part.starts_with("_$") { &part[1..] } else { part };
+    let mut out = String::new();
+    while let Some(c) = rest.chars().next() {
+        if let Some(tail) = rest.strip_prefix("..") {
+            out.push_str("::");
+            rest = tail;
+        } else if let Some((from, to)) = ESCAPES.iter().find(|(from, _)| rest.starts_with(from)) {
+            out.push_str(to);
+            rest = &rest[from.len()..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// `name` as Rust source spells it, for names in the legacy mangling
+/// (`_ZN4core3fmt5write17h0123456789abcdefE` is `core::fmt::write`);
+/// anything else comes back as it is
+pub fn demangle(name: &str) -> String {
+    let Some(mut rest) = name.strip_prefix("_ZN") else { return name.to_string() };
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let Ok(len) = rest[..digits].parse::<usize>() else { return name.to_string() };
+        let Some(part) = rest.get(digits..digits + len) else { return name.to_string() };
+        parts.push(part);
+        rest = &rest[digits + len..];
+    }
+    let is_hash = |part: &&str| part.len() == 17 && part.starts_with('h') && part[1..].bytes().all(|b| b.is_ascii_hexdigit());
+    if parts.last().is_some_and(is_hash) {
+        parts.pop();
+    }
+    parts.iter().map(|part| unescape(part)).collect::<Vec<_>>().join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn demangles_legacy_rust_names() {
+        assert_eq!(demangle("_ZN4core3fmt5write17h0123456789abcdefE"), "core::fmt::write");
+        assert_eq!(
+            demangle("_ZN65_$LT$ospab_os..fs..vfs..FileBytes$u20$as$u20$core..ops..Deref$GT$5deref17h00000000000000ffE"),
+            "<ospab_os::fs::vfs::FileBytes as core::ops::Deref>::deref"
+        );
+        assert_eq!(demangle("_start"), "_start");
+        assert_eq!(demangle("_ZN4core"), "_ZN4core");
+    }
+
+    #[test_case]
+    fn finds_the_symbol_an_address_lies_in() {
+        let symbols = [
+            Symbol { start: 0x1000, end: 0x1010, name: "a" },
+            Symbol { start: 0x1020, end: 0x1040, name: "b" },
+        ];
+        assert_eq!(find(&symbols, 0xFFF), None);
+        assert_eq!(find(&symbols, 0x1000), Some(0));
+        assert_eq!(find(&symbols, 0x1010), None);
+        assert_eq!(find(&symbols, 0x103F), Some(1));
+        assert_eq!(find(&symbols, 0x1040), None);
+    }
+}
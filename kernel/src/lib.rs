@@ -1,7 +1,11 @@
 #![no_std]
+#![cfg_attr(test, no_main)]
 #![feature(abi_x86_interrupt)]
 #![feature(alloc_error_handler)]
 #![feature(c_variadic)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
@@ -28,8 +32,22 @@ pub mod grape;  // Grape text editor
 pub mod auth;     // User authentication system
 pub mod net;      // Network stack
 pub mod doom;   // DOOM port
+pub mod doomgeneric; // doomgeneric engine bridge (C)
 pub mod power;  // Power management (shutdown/reboot)
+pub mod sysrq;  // Emergency SysRq keys
+pub mod klog;   // Kernel log ring buffer (dmesg)
+pub mod ksyms;  // Kernel symbol table, from the booted ELF
+pub mod profile; // Timer-tick sampling profiler (/proc/profile)
+pub mod timers; // Timer wheel: task wakeups and deferred callbacks
+pub mod irq;    // IRQ handlers registered at run time, shared lines
+pub mod keybindings; // Remappable shell and editor keys
+pub mod keymap; // Keyboard layouts (loadkeys)
+pub mod init; // init.d scripts and service supervision
+pub mod osinfo; // Version, feature and hardware report
+pub mod sysctl; // Runtime tunables (sysctl, /sys/kernel)
 pub mod loader; // Executable loaders
+pub mod random; // Boot-seeded PRNG (ASLR, AT_RANDOM)
+pub mod testing; // cargo test under QEMU
 
 // v0.1.0 "Foundation" additions
 pub mod syscall; // Syscall interface
\ No newline at end of file
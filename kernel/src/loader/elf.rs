@@ -1,35 +1,67 @@
 //! Minimal ELF64 loader for user-space executables.
 
-use crate::mem::vmm::VMM;
-use x86_64::structures::paging::PageTableFlags;
-use x86_64::VirtAddr;
+use crate::mem::vmm::{Backing, Protection, Region, USER_HEAP_BASE, VMM};
+use crate::random;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+pub(crate) const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 const ELF_CLASS_64: u8 = 2;
 const ELF_DATA_LITTLE: u8 = 1;
 const ELF_MACHINE_X86_64: u16 = 0x3E;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
 const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// Auxiliary vector: end of the vector
+const AT_NULL: u64 = 0;
+/// Auxiliary vector: address of 16 random bytes, for stack canaries
+const AT_RANDOM: u64 = 25;
+
+/// Where the lowest segment of a position-independent executable is placed
+const PIE_LOAD_BASE: u64 = 0x0000_5555_5555_4000;
 
 const USER_STACK_SIZE: usize = 4096 * 4;
+/// Highest the user stack may end; each exec moves it down a random
+/// number of pages, up to STACK_RANDOM_PAGES
 const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_F000;
+const STACK_RANDOM_PAGES: u64 = 1 << 14; // 64 MiB
+/// Lowest the unmapped page below the user stack can be; segments must
+/// end below it
+const USER_STACK_LIMIT: u64 = USER_STACK_TOP - STACK_RANDOM_PAGES * 4096 - USER_STACK_SIZE as u64 - 4096;
+/// The sys_malloc heap starts up to this many pages above USER_HEAP_BASE
+const HEAP_RANDOM_PAGES: u64 = 1 << 24; // 64 GiB
+/// Most of the stack the argument block may take
+const MAX_ARG_BYTES: usize = USER_STACK_SIZE / 2;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-struct Elf64Header {
-    e_ident: [u8; 16],
-    e_type: u16,
-    e_machine: u16,
-    e_version: u32,
-    e_entry: u64,
-    e_phoff: u64,
-    e_shoff: u64,
-    e_flags: u32,
-    e_ehsize: u16,
-    e_phentsize: u16,
-    e_phnum: u16,
-    e_shentsize: u16,
-    e_shnum: u16,
-    e_shstrndx: u16,
+pub(crate) struct Elf64Header {
+    pub(crate) e_ident: [u8; 16],
+    pub(crate) e_type: u16,
+    pub(crate) e_machine: u16,
+    pub(crate) e_version: u32,
+    pub(crate) e_entry: u64,
+    pub(crate) e_phoff: u64,
+    pub(crate) e_shoff: u64,
+    pub(crate) e_flags: u32,
+    pub(crate) e_ehsize: u16,
+    pub(crate) e_phentsize: u16,
+    pub(crate) e_phnum: u16,
+    pub(crate) e_shentsize: u16,
+    pub(crate) e_shnum: u16,
+    pub(crate) e_shstrndx: u16,
 }
 
 #[repr(C)]
@@ -45,18 +77,51 @@ struct Elf64ProgramHeader {
     p_align: u64,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Elf64Dyn {
+    d_tag: i64,
+    d_val: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Elf64Rela {
+    r_offset: u64,
+    r_info: u64,
+    r_addend: i64,
+}
+
 pub struct ElfLoadResult {
     pub entry: u64,
+    /// Initial stack pointer, pointing at argc
     pub user_stack: u64,
     pub address_space: crate::mem::vmm::AddressSpace,
 }
 
-pub fn load_user_elf(data: &[u8]) -> Result<ElfLoadResult, &'static str> {
-    if data.len() < core::mem::size_of::<Elf64Header>() {
-        return Err("ELF header too small");
+/// A PT_LOAD segment and the file bytes that back it
+struct Segment {
+    ph: Elf64ProgramHeader,
+    bytes: Vec<u8>,
+}
+
+/// Read a `T` at byte offset `off` of `data`
+pub(crate) fn read_at<T: Copy>(data: &[u8], off: usize) -> Option<T> {
+    if off.checked_add(core::mem::size_of::<T>())? > data.len() {
+        return None;
     }
+    Some(unsafe { (data.as_ptr().add(off) as *const T).read_unaligned() })
+}
 
-    let header = unsafe { (data.as_ptr() as *const Elf64Header).read_unaligned() };
+/// Build the address space for the executable in `data`
+///
+/// `argv` and `envp` are copied onto the new user stack in the System V
+/// layout (argc, argv[], NULL, envp[], NULL, auxv terminated by AT_NULL),
+/// with the strings themselves above it. The stack top and the sys_malloc
+/// heap base are random for each call; the auxv's AT_RANDOM points at 16
+/// random bytes for the program's stack canary.
+pub fn load_user_elf(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<ElfLoadResult, &'static str> {
+    let header: Elf64Header = read_at(data, 0).ok_or("ELF header too small")?;
 
     if header.e_ident[0..4] != ELF_MAGIC {
         return Err("Invalid ELF magic");
@@ -70,6 +135,9 @@ pub fn load_user_elf(data: &[u8]) -> Result<ElfLoadResult, &'static str> {
     if header.e_machine != ELF_MACHINE_X86_64 {
         return Err("Unsupported ELF machine");
     }
+    if header.e_type != ET_EXEC && header.e_type != ET_DYN {
+        return Err("ELF is not an executable");
+    }
 
     let phoff = header.e_phoff as usize;
     let phentsize = header.e_phentsize as usize;
@@ -79,66 +147,218 @@ pub fn load_user_elf(data: &[u8]) -> Result<ElfLoadResult, &'static str> {
         return Err("ELF program headers out of range");
     }
 
-    let mut vmm = VMM.lock();
-    let vmm = vmm.as_mut().ok_or("VMM not initialized")?;
-    let mut addr_space = vmm.create_user_address_space()?;
-
+    let mut segments = Vec::new();
+    let mut dynamic = None;
     for idx in 0..phnum {
-        let off = phoff + idx * phentsize;
-        if off + core::mem::size_of::<Elf64ProgramHeader>() > data.len() {
-            return Err("ELF program header truncated");
-        }
-
-        let ph = unsafe { (data.as_ptr().add(off) as *const Elf64ProgramHeader).read_unaligned() };
-        if ph.p_type != PT_LOAD {
-            continue;
+        let ph: Elf64ProgramHeader =
+            read_at(data, phoff + idx * phentsize).ok_or("ELF program header truncated")?;
+        match ph.p_type {
+            PT_LOAD => {}
+            PT_DYNAMIC => {
+                dynamic = Some(ph);
+                continue;
+            }
+            PT_INTERP => return Err("Dynamically linked ELF not supported"),
+            _ => continue,
         }
 
         if (ph.p_offset + ph.p_filesz) as usize > data.len() {
             return Err("ELF segment out of range");
         }
+        if ph.p_filesz > ph.p_memsz {
+            return Err("ELF segment file size exceeds memory size");
+        }
+        if ph.p_flags & PF_W != 0 && ph.p_flags & PF_X != 0 {
+            return Err("ELF segment is both writable and executable");
+        }
+        let bytes = data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize].to_vec();
+        segments.push(Segment { ph, bytes });
+    }
+    if segments.is_empty() {
+        return Err("ELF has no loadable segments");
+    }
 
-        let seg_start = ph.p_vaddr & !0xFFF;
-        let seg_end = (ph.p_vaddr + ph.p_memsz + 0xFFF) & !0xFFF;
-        let pages = ((seg_end - seg_start) / 4096) as usize;
-
-        // Map writable during load to allow segment initialization in kernel.
-        let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
-
-        addr_space.allocate_pages(VirtAddr::new(seg_start), pages, flags)?;
+    // Position-independent executables are linked at 0 (or close to it)
+    // and slid up to PIE_LOAD_BASE; their absolute pointers are fixed up
+    // by the R_X86_64_RELATIVE entries of the dynamic section
+    let bias = if header.e_type == ET_DYN {
+        let lowest = segments.iter().map(|s| s.ph.p_vaddr & !0xFFF).min().unwrap_or(0);
+        let bias = PIE_LOAD_BASE.wrapping_sub(lowest);
+        if let Some(dynamic) = dynamic {
+            apply_relocations(data, &dynamic, &mut segments, bias)?;
+        }
+        bias
+    } else {
+        0
+    };
 
-        let (old_cr3, old_flags) = x86_64::registers::control::Cr3::read();
-        unsafe { addr_space.switch_to(); }
+    // Segments are only recorded here; their pages are filled in on first
+    // access by the page fault handler, which also zeroes the BSS
+    // (p_memsz past p_filesz)
+    let mut regions = Vec::new();
+    // (start, end, writable) of the pages of code and data segments
+    let mut writable_or_code: Vec<(u64, u64, bool)> = Vec::new();
+    for segment in segments {
+        let ph = segment.ph;
+        let vaddr = ph.p_vaddr.checked_add(bias).ok_or("ELF segment outside user space")?;
+        let end = vaddr.checked_add(ph.p_memsz).ok_or("ELF segment outside user space")?;
+        if end > USER_STACK_LIMIT {
+            return Err("ELF segment outside user space");
+        }
 
-        unsafe {
-            let dst = core::slice::from_raw_parts_mut(ph.p_vaddr as *mut u8, ph.p_memsz as usize);
-            for b in dst.iter_mut() {
-                *b = 0;
+        // Code is read+execute, everything else no-execute
+        let protection = if ph.p_flags & PF_X != 0 {
+            Protection::ReadExecute
+        } else if ph.p_flags & PF_W != 0 {
+            Protection::ReadWrite
+        } else {
+            Protection::Read
+        };
+        let region = Region {
+            start: vaddr & !0xFFF,
+            end: (end + 0xFFF) & !0xFFF,
+            flags: protection.user_flags(),
+            backing: Backing::Image { bytes: Arc::new(segment.bytes), at: vaddr },
+        };
+        // A page holding both code and writable data would have to be
+        // writable and executable
+        let writable = ph.p_flags & PF_W != 0;
+        let executable = ph.p_flags & PF_X != 0;
+        if writable || executable {
+            let clash = writable_or_code.iter().any(|&(start, end, w)| {
+                w != writable && start < region.end && region.start < end
+            });
+            if clash {
+                return Err("ELF code and writable data share a page");
             }
-            let src = &data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize];
-            dst[..src.len()].copy_from_slice(src);
+            writable_or_code.push((region.start, region.end, writable));
         }
-
-        unsafe { x86_64::registers::control::Cr3::write(old_cr3, old_flags); }
+        regions.push(region);
     }
 
-    let stack_start = USER_STACK_TOP - USER_STACK_SIZE as u64;
-    let stack_pages = USER_STACK_SIZE / 4096;
-    addr_space.allocate_pages(VirtAddr::new(stack_start), stack_pages, PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE)?;
+    // Stack and heap move on every exec
+    let stack_top = USER_STACK_TOP - random::below(STACK_RANDOM_PAGES) * 4096;
+    let stack_start = stack_top - USER_STACK_SIZE as u64;
+    let (arg_block, user_stack) = build_arg_block(argv, envp, stack_top)?;
+    regions.push(Region {
+        start: stack_start,
+        end: stack_top,
+        flags: Protection::ReadWrite.user_flags(),
+        backing: Backing::Image { bytes: Arc::new(arg_block), at: user_stack },
+    });
 
-    let (old_cr3, old_flags) = x86_64::registers::control::Cr3::read();
-    unsafe { addr_space.switch_to(); }
-    unsafe {
-        let dst = core::slice::from_raw_parts_mut(stack_start as *mut u8, USER_STACK_SIZE);
-        for b in dst.iter_mut() {
-            *b = 0;
-        }
+    let mut vmm = VMM.lock();
+    let vmm = vmm.as_mut().ok_or("VMM not initialized")?;
+    let mut addr_space = vmm.create_user_address_space()?;
+    for region in regions {
+        addr_space.add_region(region);
     }
-    unsafe { x86_64::registers::control::Cr3::write(old_cr3, old_flags); }
+    addr_space.add_guard_page(stack_start - 4096);
+    addr_space.set_heap_base(USER_HEAP_BASE + random::below(HEAP_RANDOM_PAGES) * 4096);
 
     Ok(ElfLoadResult {
-        entry: header.e_entry,
-        user_stack: USER_STACK_TOP - 16,
+        entry: header.e_entry.wrapping_add(bias),
+        user_stack,
         address_space: addr_space,
     })
 }
+
+/// Translate a link-time virtual address to an offset in the file
+fn vaddr_to_offset(segments: &[Segment], vaddr: u64) -> Option<usize> {
+    segments
+        .iter()
+        .find(|s| vaddr >= s.ph.p_vaddr && vaddr < s.ph.p_vaddr + s.ph.p_filesz)
+        .map(|s| (s.ph.p_offset + (vaddr - s.ph.p_vaddr)) as usize)
+}
+
+/// Apply the RELA table named by the dynamic section to the segment copies
+fn apply_relocations(
+    data: &[u8],
+    dynamic: &Elf64ProgramHeader,
+    segments: &mut [Segment],
+    bias: u64,
+) -> Result<(), &'static str> {
+    let (mut rela, mut relasz, mut relaent) = (None, 0u64, core::mem::size_of::<Elf64Rela>() as u64);
+    let count = dynamic.p_filesz as usize / core::mem::size_of::<Elf64Dyn>();
+    for i in 0..count {
+        let off = dynamic.p_offset as usize + i * core::mem::size_of::<Elf64Dyn>();
+        let entry: Elf64Dyn = read_at(data, off).ok_or("ELF dynamic section out of range")?;
+        match entry.d_tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(entry.d_val),
+            DT_RELASZ => relasz = entry.d_val,
+            DT_RELAENT => relaent = entry.d_val,
+            _ => {}
+        }
+    }
+
+    let rela = match rela {
+        Some(vaddr) => vaddr_to_offset(segments, vaddr).ok_or("ELF relocation table out of range")?,
+        None => return Ok(()),
+    };
+    if relaent < core::mem::size_of::<Elf64Rela>() as u64 {
+        return Err("Bad ELF relocation entry size");
+    }
+
+    for i in 0..(relasz / relaent) as usize {
+        let reloc: Elf64Rela =
+            read_at(data, rela + i * relaent as usize).ok_or("ELF relocation table out of range")?;
+        match reloc.r_info as u32 {
+            R_X86_64_NONE => continue,
+            R_X86_64_RELATIVE => {}
+            _ => return Err("Unsupported ELF relocation type"),
+        }
+
+        let value = bias.wrapping_add(reloc.r_addend as u64).to_le_bytes();
+        let segment = segments
+            .iter_mut()
+            .find(|s| reloc.r_offset >= s.ph.p_vaddr && reloc.r_offset + 8 <= s.ph.p_vaddr + s.ph.p_memsz)
+            .ok_or("ELF relocation outside segments")?;
+        // Targets in the BSS grow the copy; the rest stays zero
+        let at = (reloc.r_offset - segment.ph.p_vaddr) as usize;
+        if segment.bytes.len() < at + 8 {
+            segment.bytes.resize(at + 8, 0);
+        }
+        segment.bytes[at..at + 8].copy_from_slice(&value);
+    }
+    Ok(())
+}
+
+/// Lay out argc, argv, envp and the auxiliary vector for a user stack
+/// ending at `stack_top`
+///
+/// Returns the bytes to place at the returned stack pointer, which is
+/// 16-byte aligned as the System V ABI requires at process entry.
+fn build_arg_block(argv: &[&str], envp: &[&str], stack_top: u64) -> Result<(Vec<u8>, u64), &'static str> {
+    // The AT_RANDOM bytes, then the strings
+    let strings_len: usize = 16 + argv.iter().chain(envp).map(|s| s.len() + 1).sum::<usize>();
+    let strings_len = (strings_len + 15) & !15;
+    // argc, argv[] + NULL, envp[] + NULL, AT_RANDOM and AT_NULL pairs
+    let words = 1 + argv.len() + 1 + envp.len() + 1 + 4;
+    let table_len = (words * 8 + 15) & !15;
+    if table_len + strings_len > MAX_ARG_BYTES {
+        return Err("Argument list too long");
+    }
+
+    let sp = stack_top - (table_len + strings_len) as u64;
+    let mut block = alloc::vec![0u8; table_len + strings_len];
+    let mut table = Vec::with_capacity(words);
+    table.push(argv.len() as u64);
+
+    random::fill(&mut block[table_len..table_len + 16]);
+    let mut string_off = table_len + 16;
+    for list in [argv, envp] {
+        for s in list {
+            table.push(sp + string_off as u64);
+            block[string_off..string_off + s.len()].copy_from_slice(s.as_bytes());
+            string_off += s.len() + 1;
+        }
+        table.push(0);
+    }
+    table.extend([AT_RANDOM, sp + table_len as u64, AT_NULL, 0]);
+
+    for (i, word) in table.iter().enumerate() {
+        block[i * 8..i * 8 + 8].copy_from_slice(&word.to_le_bytes());
+    }
+    Ok((block, sp))
+}
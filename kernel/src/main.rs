@@ -11,13 +11,17 @@ extern crate alloc;
 extern crate ospab_os;
 
 use core::panic::PanicInfo;
-use ospab_os::{boot, drivers, fb_println, gdt, interrupts, mm, process, ipc, services, shell, task, mem, syscall, auth, net};
+use ospab_os::{arch, boot, drivers, fb_println, gdt, interrupts, mm, process, ipc, services, shell, task, mem, syscall, auth, net, power, keybindings, keymap, init, timers, random};
 
 // ============================================================================
 // SERIAL OUTPUT - For debugging
 // ============================================================================
 
 fn serial_print(msg: &[u8]) {
+    // Don't corrupt an rz/sz transfer in progress
+    if drivers::serial::is_raw_mode() {
+        return;
+    }
     unsafe {
         use x86_64::instructions::port::Port;
         let mut port = Port::<u8>::new(0x3F8);
@@ -47,6 +51,23 @@ fn serial_hex(val: u64) {
     }
 }
 
+// ============================================================================
+// PARALLEL INIT - Non-critical subsystems, run by boot::initcall workers
+// ============================================================================
+
+// Each call starts once the services in `deps` have announced readiness on
+// the bus; table order does not matter
+static INIT_CALLS: &[boot::initcall::InitCall] = &[
+    boot::initcall::InitCall { name: "initrd", deps: &["vfs"], run: services::vfs::load_initrd },
+    // /etc/passwd from the kernel wins over one shipped in the initrd
+    boot::initcall::InitCall { name: "auth", deps: &["initrd"], run: auth::init },
+    boot::initcall::InitCall { name: "network", deps: &[], run: net::init },
+    boot::initcall::InitCall { name: "pci", deps: &[], run: drivers::pci::init },
+    boot::initcall::InitCall { name: "ahci", deps: &["pci"], run: drivers::ahci::init },
+    boot::initcall::InitCall { name: "keybindings", deps: &["initrd"], run: keybindings::init },
+    boot::initcall::InitCall { name: "keymap", deps: &["initrd"], run: keymap::init },
+];
+
 // ============================================================================
 // PANIC HANDLER - Full debug output to Serial (COM1)
 // ============================================================================
@@ -110,7 +131,8 @@ fn panic(info: &PanicInfo) -> ! {
     
     // Try to show on framebuffer
     if drivers::framebuffer::is_initialized() {
-        drivers::framebuffer::set_colors(0x00FF0000, 0x00000000);
+        let palette = ospab_os::common::palette::current();
+        drivers::framebuffer::set_colors(palette.panic_fg, palette.panic_bg);
         drivers::framebuffer::print("\n\n!!! KERNEL PANIC !!!\n");
         drivers::framebuffer::print("System halted.\n");
     }
@@ -129,6 +151,7 @@ fn panic(info: &PanicInfo) -> ! {
 pub extern "C" fn _start() -> ! {
     // CRITICAL: Disable interrupts until everything is set up
     x86_64::instructions::interrupts::disable();
+    boot::timeline::start();
     
     // Enable SSE/SSE2 - required for x86-interrupt calling convention
     unsafe {
@@ -173,16 +196,19 @@ pub extern "C" fn _start() -> ! {
     } else {
         serial_print(b"NOT AVAILABLE\r\n");
     }
+    boot::timeline::mark("limine");
     
     // Step 3: Initialize GDT (MUST be before IDT)
     serial_print(b"[3/7] Initializing GDT...\r\n");
     gdt::init();
     serial_print(b"[3/7] GDT loaded successfully\r\n");
+    boot::timeline::mark("gdt");
     
     // Step 4: Initialize IDT and PICs
     serial_print(b"[4/7] Initializing IDT and PICs...\r\n");
     interrupts::init_idt();
     serial_print(b"[4/7] IDT and PICs ready\r\n");
+    boot::timeline::mark("idt + pic");
     
     // Step 5: Initialize framebuffer
     serial_print(b"[5/7] Initializing framebuffer...\r\n");
@@ -199,16 +225,20 @@ pub extern "C" fn _start() -> ! {
     } else {
         serial_print(b"[5/7] Framebuffer FAILED\r\n");
     }
+    boot::timeline::mark("framebuffer");
     
     // Step 6: Initialize serial port for hardware debugging
     serial_print(b"[6/8] Initializing serial port (COM1)...\r\n");
     drivers::serial::init();
     serial_print(b"[6/8] Serial port ready\r\n");
+    boot::timeline::mark("serial");
     
     // Step 7: Initialize keyboard driver (no interrupts yet)
     serial_print(b"[7/8] Initializing keyboard driver...\r\n");
     drivers::keyboard::init();
+    drivers::mouse::init();
     serial_print(b"[7/8] Keyboard driver ready\r\n");
+    boot::timeline::mark("keyboard");
     
     // Step 8: System ready
     serial_print(b"[8/8] All components initialized\r\n");
@@ -224,15 +254,22 @@ pub extern "C" fn _start() -> ! {
     // Memory management
     serial_print(b"[SUBSYS] Initializing memory management...\r\n");
     mm::init();
+    drivers::framebuffer::enable_scrollback();
+    if !drivers::framebuffer::enable_double_buffer() {
+        serial_print(b"[FB] No back buffer, drawing directly\r\n");
+    }
+    boot::timeline::mark("heap");
     
-    // Timer (PIT)
-    serial_print(b"[SUBSYS] Initializing timer (PIT)...\r\n");
+    // Interrupt controller (APIC, or the PIC with noapic) and the tick
+    serial_print(b"[SUBSYS] Initializing interrupt controller and timer...\r\n");
+    interrupts::init_controller();
     drivers::timer::init();
-    interrupts::enable_irq(0); // Enable timer interrupt
+    boot::timeline::mark("timer");
     
     // Process management
     serial_print(b"[SUBSYS] Initializing process management...\r\n");
     process::init();
+    boot::timeline::mark("process");
     
     // === v0.1.0 "FOUNDATION" INITIALIZATION ===
     serial_print(b"\r\n[v0.1.0] Initializing Foundation components...\r\n");
@@ -240,10 +277,17 @@ pub extern "C" fn _start() -> ! {
     // Task management with TSS
     serial_print(b"[v0.1.0] Initializing task management (TSS + scheduler)...\r\n");
     task::init();
+    boot::timeline::mark("scheduler + tss");
     
+    // CPU features, before the VMM decides on page flags
+    serial_print(b"[CPU] Detecting CPU features...\r\n");
+    arch::x86_64::cpu::init();
+    random::init();
+
     // Frame allocator
     serial_print(b"[v0.1.0] Initializing frame allocator...\r\n");
-    mem::physical::FRAME_ALLOCATOR.lock().init(0x100000, 0x200000); // Kernel at 1MB-2MB
+    mem::physical::init();
+    boot::timeline::mark("frame allocator");
     
     // Virtual Memory Manager (v0.1.5)
     serial_print(b"[v0.1.5] Initializing Virtual Memory Manager...\r\n");
@@ -254,11 +298,17 @@ pub extern "C" fn _start() -> ! {
     } else {
         serial_print(b"[v0.1.5] VMM initialized successfully\r\n");
     }
+    boot::timeline::mark("vmm");
     
     // Syscall interface (v0.1.5)
     serial_print(b"[v0.1.5] Initializing syscall interface...\r\n");
     syscall::init();
     serial_print(b"[v0.1.5] Syscall interface ready\r\n");
+    boot::timeline::mark("syscall");
+    
+    // Kernel timers (needs kernel stacks for ktimerd)
+    timers::init();
+    boot::timeline::mark("timers");
     
     serial_print(b"[v0.1.0] Foundation components initialized\r\n");
     
@@ -268,24 +318,33 @@ pub extern "C" fn _start() -> ! {
     // Message Bus
     serial_print(b"[IPC] Initializing message bus...\r\n");
     ipc::bus::init();
+    boot::timeline::mark("ipc bus");
     
     // Terminal Service (wraps existing I/O)
     serial_print(b"[IPC] Initializing terminal service...\r\n");
     services::terminal::init();
+    boot::timeline::mark("terminal service");
+    
+    // Display Service (window compositor)
+    serial_print(b"[IPC] Initializing display service...\r\n");
+    services::display::init();
+
+    // Clipboard Service
+    serial_print(b"[IPC] Initializing clipboard service...\r\n");
+    services::clipboard::init();
     
     // VFS Service
     serial_print(b"[IPC] Initializing VFS service...\r\n");
+    drivers::fw_cfg::init();
+    boot::timeline::mark("fw_cfg");
     services::vfs::init();
 
-    // User Authentication System
-    serial_print(b"[AUTH] Initializing user authentication...\r\n");
-    auth::init();
-
-    // Network Stack
-    serial_print(b"[NET] Initializing network stack...\r\n");
-    net::init();
+    // The rest comes up on worker tasks once interrupts are enabled
+    serial_print(b"[INIT] Starting parallel init (initrd, auth, network, pci, ahci)...\r\n");
+    boot::initcall::start(INIT_CALLS);
+    // /etc/init.d runs once they are all done
+    init::start();
 
-    
     serial_print(b"\r\n[FB] Preparing screen output...\r\n");
     // Display welcome on screen
     if fb_ok {
@@ -309,6 +368,7 @@ pub extern "C" fn _start() -> ! {
         fb_println!("[OK] VFS Service (Initrd) online");
         fb_println!();
         serial_print(b"[FB] Welcome screen drawn\r\n");
+        boot::timeline::mark("welcome screen");
     } else {
         serial_print(b"[FB] Skipped - framebuffer not available\r\n");
     }
@@ -317,6 +377,7 @@ pub extern "C" fn _start() -> ! {
     // Step 1: Enable CPU interrupts (sti)
     serial_print(b"\r\n[INIT] Enabling CPU interrupts (sti)...\r\n");
     x86_64::instructions::interrupts::enable();
+    boot::timeline::start_clock();
     serial_print(b"[INIT] CPU interrupts enabled!\r\n");
     
     // Tiny delay - system should be stable immediately
@@ -328,7 +389,22 @@ pub extern "C" fn _start() -> ! {
     // Step 2: Enable keyboard IRQ (AFTER sti, at the very end)
     serial_print(b"[INIT] Enabling keyboard hardware IRQ...\r\n");
     drivers::keyboard::enable_hw_irq();
+    drivers::mouse::enable_irq();
     serial_print(b"[INIT] Keyboard IRQ enabled!\r\n");
+    boot::timeline::mark("interrupts");
+    
+    // Step 3: Power button and Ctrl+Alt+Del
+    power::init();
+    boot::timeline::mark("power");
+    
+    // The prompt shows the logged-in user
+    if !boot::initcall::wait_ready("auth") {
+        serial_print(b"[INIT] auth did not come up, continuing without it\r\n");
+    }
+    init::wait_scripts();
+    boot::timeline::mark("init.d");
+    // History and aliases of the user at the prompt
+    shell::session::start();
     
     serial_print(b"\r\n[FB] Drawing prompt...\r\n");
     if fb_ok {
@@ -342,36 +418,46 @@ pub extern "C" fn _start() -> ! {
         let prompt = shell::get_prompt();
         drivers::framebuffer::print(&prompt);
         drivers::framebuffer::show_cursor();
+        timers::call_in(CURSOR_BLINK_TICKS, blink_cursor, 0);
         serial_print(b"[FB] Prompt drawn, cursor shown\r\n");
     } else {
         serial_print(b"[FB] Skipped - framebuffer not available\r\n");
     }
     
+    boot::timeline::mark("prompt");
     serial_print(b"\r\n[READY] Entering main loop\r\n");
     
-    let mut tick_counter: u64 = 0;
-    
     // Main event loop - microkernel message processing
     loop {
         // Process keyboard events (Terminal Service)
         services::terminal::poll_input();
-        
-        // Check timer ticks
-        let current_jiffies = drivers::timer::get_jiffies();
-        if current_jiffies != tick_counter {
-            tick_counter = current_jiffies;
-            
-            // Blink cursor every 50 ticks (500ms)
-            if tick_counter % 50 == 0 {
-                drivers::framebuffer::toggle_cursor();
-            }
-        }
+        drivers::mouse::update_pointer();
+        services::display::poll();
         
         // Halt CPU until next interrupt (saves power and allows interrupts to fire)
         x86_64::instructions::hlt();
     }
 }
 
+/// How often a steady cursor checks whether it should blink again
+const CURSOR_BLINK_TICKS: u64 = 50;
+
+/// Blink the shell cursor every `kernel.cursor_blink_ms`; runs on ktimerd
+/// and re-arms itself. Programs that own the keyboard draw their own
+/// screen, so it stays put for them.
+fn blink_cursor(_: u64) {
+    let period = ospab_os::sysctl::cursor_blink_ms();
+    if services::terminal::foreground().is_none() {
+        if period > 0 {
+            drivers::framebuffer::toggle_cursor();
+        } else {
+            drivers::framebuffer::show_cursor();
+        }
+    }
+    let ticks = if period > 0 { timers::ms_to_ticks(period) } else { CURSOR_BLINK_TICKS };
+    timers::call_in(ticks, blink_cursor, 0);
+}
+
 // ============================================================================
 // PROGRESS BAR FOR BOOT LOADING - TEMPORARILY DISABLED
 // ============================================================================
@@ -1,19 +1,24 @@
 //! Physical Frame Allocator for ospabOS v0.1.0
-//! Uses bitmap-based allocation with proper locking
+//! Bitmap over every USABLE region of the Limine memory map
 
 use spin::Mutex;
-use alloc::format;
+use alloc::vec::Vec;
+use crate::boot::limine;
 
 const PAGE_SIZE: usize = 4096;
-const TOTAL_MEMORY: usize = 128 * 1024 * 1024; // 128 MB (realistic for now)
-const TOTAL_FRAMES: usize = TOTAL_MEMORY / PAGE_SIZE;
-const BITMAP_SIZE: usize = (TOTAL_FRAMES + 7) / 8; // Round up
+
+/// Memory below 1 MB is left to legacy hardware and firmware
+const LOW_MEMORY_END: u64 = 0x100000;
 
 /// Global frame allocator
 pub static FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
 
 pub struct FrameAllocator {
-    bitmap: [u8; BITMAP_SIZE],
+    /// One bit per frame from physical address 0 up to the end of the
+    /// highest usable region; set = allocated or not RAM we may hand out
+    bitmap: Vec<u64>,
+    /// Usable frame ranges (start, end), from the memory map
+    regions: Vec<(usize, usize)>,
     next_free: usize,
     total_frames: usize,
     used_frames: usize,
@@ -22,103 +27,121 @@ pub struct FrameAllocator {
 impl FrameAllocator {
     pub const fn new() -> Self {
         FrameAllocator {
-            bitmap: [0; BITMAP_SIZE],
+            bitmap: Vec::new(),
+            regions: Vec::new(),
             next_free: 0,
-            total_frames: TOTAL_FRAMES,
+            total_frames: 0,
             used_frames: 0,
         }
     }
-    
-    /// Initialize allocator (mark kernel memory as used)
-    pub fn init(&mut self, kernel_start: usize, kernel_end: usize) {
-        // Mark first 1 MB as reserved (BIOS, VGA, etc.)
-        let reserved_frames = (1024 * 1024) / PAGE_SIZE;
-        for i in 0..reserved_frames {
-            self.mark_used(i);
+
+    /// Build the bitmap from the Limine memory map
+    ///
+    /// Only MEMMAP_USABLE entries are managed: the kernel, modules,
+    /// framebuffer, ACPI tables and bootloader structures (including the
+    /// page tables still in use) have their own types and are never handed
+    /// out. The heap lives in a usable region and is counted as used.
+    pub fn init(&mut self) {
+        self.regions.clear();
+        if let Some(memmap) = limine::memory_map() {
+            for entry in memmap.filter(|e| e.typ == limine::MEMMAP_USABLE) {
+                let start = entry.base.max(LOW_MEMORY_END);
+                let end = entry.base + entry.length;
+                let start_frame = ((start as usize) + PAGE_SIZE - 1) / PAGE_SIZE;
+                let end_frame = end as usize / PAGE_SIZE;
+                if start_frame < end_frame {
+                    self.regions.push((start_frame, end_frame));
+                }
+            }
         }
-        
-        // Mark kernel memory as used
-        let kernel_start_frame = kernel_start / PAGE_SIZE;
-        let kernel_end_frame = (kernel_end + PAGE_SIZE - 1) / PAGE_SIZE;
-        
-        for i in kernel_start_frame..kernel_end_frame {
-            self.mark_used(i);
+
+        let frame_count = self.regions.iter().map(|&(_, end)| end).max().unwrap_or(0);
+        self.bitmap = alloc::vec![!0u64; (frame_count + 63) / 64];
+        self.total_frames = 0;
+        self.used_frames = 0;
+        self.next_free = 0;
+        for i in 0..self.regions.len() {
+            let (start, end) = self.regions[i];
+            for frame in start..end {
+                self.bitmap[frame / 64] &= !(1 << (frame % 64));
+            }
+            self.total_frames += end - start;
         }
-        
+
+        if let Some((heap_start, heap_end)) = crate::mm::heap_allocator::physical_range() {
+            let first = heap_start as usize / PAGE_SIZE;
+            let last = (heap_end as usize + PAGE_SIZE - 1) / PAGE_SIZE;
+            for frame in first..last {
+                self.mark_used(frame);
+            }
+        }
+
         crate::serial_println!("[MEM] Frame allocator initialized");
+        crate::serial_println!("      Usable regions: {}", self.regions.len());
         crate::serial_println!("      Total frames: {}", self.total_frames);
         crate::serial_println!("      Used frames: {}", self.used_frames);
         crate::serial_println!("      Free frames: {}", self.total_frames - self.used_frames);
     }
-    
+
     /// Allocate a physical frame
     pub fn allocate(&mut self) -> Option<usize> {
-        // Start from last known free position
-        for i in self.next_free..self.total_frames {
-            let byte_idx = i / 8;
-            let bit_idx = i % 8;
-            
-            if (self.bitmap[byte_idx] & (1 << bit_idx)) == 0 {
-                // Found free frame
-                self.bitmap[byte_idx] |= 1 << bit_idx;
-                self.used_frames += 1;
-                self.next_free = i + 1;
-                return Some(i * PAGE_SIZE);
-            }
-        }
-        
-        // Wrap around and search from beginning
-        for i in 0..self.next_free {
-            let byte_idx = i / 8;
-            let bit_idx = i % 8;
-            
-            if (self.bitmap[byte_idx] & (1 << bit_idx)) == 0 {
-                self.bitmap[byte_idx] |= 1 << bit_idx;
-                self.used_frames += 1;
-                self.next_free = i + 1;
-                return Some(i * PAGE_SIZE);
+        let words = self.bitmap.len();
+        let first_word = self.next_free / 64;
+
+        // Start from last known free position, then wrap around
+        for i in (first_word..words).chain(0..first_word) {
+            let word = self.bitmap[i];
+            if word == !0 {
+                continue;
             }
+            let bit = (!word).trailing_zeros() as usize;
+            let frame = i * 64 + bit;
+            self.bitmap[i] |= 1 << bit;
+            self.used_frames += 1;
+            self.next_free = frame + 1;
+            return Some(frame * PAGE_SIZE);
         }
-        
+
         None // Out of memory
     }
-    
+
     /// Free a physical frame
     pub fn free(&mut self, addr: usize) {
         let frame = addr / PAGE_SIZE;
-        
-        if frame >= self.total_frames {
+
+        if !self.is_usable(frame) {
             return;
         }
-        
-        let byte_idx = frame / 8;
-        let bit_idx = frame % 8;
-        
-        if (self.bitmap[byte_idx] & (1 << bit_idx)) != 0 {
-            self.bitmap[byte_idx] &= !(1 << bit_idx);
+
+        let (word, bit) = (frame / 64, frame % 64);
+        if (self.bitmap[word] & (1 << bit)) != 0 {
+            self.bitmap[word] &= !(1 << bit);
             self.used_frames -= 1;
-            
+
             if frame < self.next_free {
                 self.next_free = frame;
             }
         }
     }
-    
+
+    /// Whether `frame` lies in a usable region of the memory map
+    fn is_usable(&self, frame: usize) -> bool {
+        self.regions.iter().any(|&(start, end)| frame >= start && frame < end)
+    }
+
     /// Mark frame as used
     fn mark_used(&mut self, frame: usize) {
-        if frame >= self.total_frames {
+        if !self.is_usable(frame) {
             return;
         }
-        
-        let byte_idx = frame / 8;
-        let bit_idx = frame % 8;
-        
-        if (self.bitmap[byte_idx] & (1 << bit_idx)) == 0 {
-            self.bitmap[byte_idx] |= 1 << bit_idx;
+
+        let (word, bit) = (frame / 64, frame % 64);
+        if (self.bitmap[word] & (1 << bit)) == 0 {
+            self.bitmap[word] |= 1 << bit;
             self.used_frames += 1;
         }
     }
-    
+
     /// Get memory statistics
     pub fn stats(&self) -> (usize, usize, usize) {
         (self.total_frames, self.used_frames, self.total_frames - self.used_frames)
@@ -131,6 +154,42 @@ pub fn stats() -> (usize, usize, usize) {
     allocator.stats()
 }
 
+/// RAM as the Limine memory map describes it, in bytes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryMap {
+    /// Free for the kernel: the frames the allocator manages, the heap and
+    /// what lies below 1 MB
+    pub usable: u64,
+    /// The kernel image and the boot modules
+    pub kernel: u64,
+    /// Bootloader structures, including page tables still in use
+    pub bootloader: u64,
+    /// Reserved by the firmware, ACPI tables and bad memory
+    pub reserved: u64,
+}
+
+impl MemoryMap {
+    /// All RAM the machine has; the framebuffer is not counted
+    pub fn total(&self) -> u64 {
+        self.usable + self.kernel + self.bootloader + self.reserved
+    }
+}
+
+/// Sum the Limine memory map by type
+pub fn memory_map() -> MemoryMap {
+    let mut map = MemoryMap::default();
+    for entry in limine::memory_map().into_iter().flatten() {
+        match entry.typ {
+            limine::MEMMAP_USABLE => map.usable += entry.length,
+            limine::MEMMAP_KERNEL_AND_MODULES => map.kernel += entry.length,
+            limine::MEMMAP_BOOTLOADER_RECLAIMABLE => map.bootloader += entry.length,
+            limine::MEMMAP_FRAMEBUFFER => {}
+            _ => map.reserved += entry.length,
+        }
+    }
+    map
+}
+
 /// Allocate a physical page
 pub fn allocate_page() -> Option<usize> {
     FRAME_ALLOCATOR.lock().allocate()
@@ -141,7 +200,7 @@ pub fn free_page(addr: usize) {
     FRAME_ALLOCATOR.lock().free(addr)
 }
 
-/// Initialize physical memory allocator
+/// Initialize physical memory allocator from the memory map (needs the heap)
 pub fn init() {
-    // Physical allocator is initialized via FRAME_ALLOCATOR
-}
\ No newline at end of file
+    FRAME_ALLOCATOR.lock().init();
+}
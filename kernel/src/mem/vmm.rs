@@ -1,15 +1,19 @@
 //! Virtual Memory Manager for ospabOS v0.1.5
 //! Implements 4-level paging (PML4 -> PDPT -> PD -> PT) with user/kernel separation
 
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 use x86_64::{
     structures::paging::{
-        FrameAllocator as X64FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame,
-        Size4KiB,
+        page_table::PageTableEntry, FrameAllocator as X64FrameAllocator, Mapper, OffsetPageTable, Page, PageTable,
+        PageTableFlags, PhysFrame, Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
 
+use crate::arch::x86_64::cpu::{self, Feature};
 use crate::mem::physical::FRAME_ALLOCATOR;
 use crate::boot;
 
@@ -29,12 +33,44 @@ impl FrameAllocatorWrapper {
     }
 }
 
+/// Page table frames for `map_to` from an allocator lock the caller holds
+struct LockedFrames<'a>(&'a mut crate::mem::physical::FrameAllocator);
+
+unsafe impl X64FrameAllocator<Size4KiB> for LockedFrames<'_> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let frame_addr = self.0.allocate()?;
+        Some(PhysFrame::containing_address(PhysAddr::new(frame_addr as u64)))
+    }
+}
+
+/// `handle_fault` error for a fault that could not be handled because a
+/// lock it needs is held, likely by a preempted task. The #PF handler runs
+/// with interrupts off and must not spin on it; the access is retried
+/// instead once the holder has had a chance to run.
+pub const FAULT_BUSY: &str = "memory locks busy";
+
 /// Virtual address space boundaries
 pub const USER_SPACE_START: u64 = 0x0000_0000_0000_0000;
 pub const USER_SPACE_END: u64 = 0x0000_7FFF_FFFF_FFFF; // 128 TB user space
 pub const KERNEL_SPACE_START: u64 = 0xFFFF_8000_0000_0000; // HHDM base
 pub const KERNEL_HEAP_START: u64 = 0xFFFF_FFFF_8000_0000;
 pub const KERNEL_HEAP_SIZE: u64 = 32 * 1024 * 1024; // 32 MB
+/// Where sys_malloc memory starts unless the loader picks a random base
+pub const USER_HEAP_BASE: u64 = 0x0000_4000_0000_0000; // 64 TB
+
+/// Kernel task stacks, one per slot. The slot space below each stack is
+/// never mapped, so running off the bottom faults instead of silently
+/// corrupting whatever lies below.
+pub const KERNEL_STACK_AREA: u64 = 0xFFFF_FE00_0000_0000;
+/// Virtual space per kernel stack, guard pages included
+pub const KERNEL_STACK_SLOT: u64 = 64 * 1024;
+const KERNEL_STACK_SLOTS: u64 = 4096;
+
+/// Whether `addr` lies in the unmapped part of the kernel stack area
+/// (a fault there is a kernel stack overflow)
+pub fn is_kernel_stack_guard(addr: u64) -> bool {
+    addr >= KERNEL_STACK_AREA && addr < KERNEL_STACK_AREA + KERNEL_STACK_SLOTS * KERNEL_STACK_SLOT
+}
 
 /// Page Table Entry flags for user/kernel pages
 pub const USER_PAGE_FLAGS: PageTableFlags = PageTableFlags::PRESENT
@@ -44,12 +80,131 @@ pub const USER_PAGE_FLAGS: PageTableFlags = PageTableFlags::PRESENT
 pub const KERNEL_PAGE_FLAGS: PageTableFlags = PageTableFlags::PRESENT
     .union(PageTableFlags::WRITABLE);
 
+/// NO_EXECUTE where the CPU has the no-execute bit (`cpu::init` enables
+/// it); nothing elsewhere, where the bit is reserved and would fault
+pub fn no_execute() -> PageTableFlags {
+    if cpu::has(Feature::Nx) {
+        PageTableFlags::NO_EXECUTE
+    } else {
+        PageTableFlags::empty()
+    }
+}
+
+/// What user code may do with a page. Writable pages are never
+/// executable and executable ones never writable (W^X).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    /// Read-only data
+    Read,
+    /// Data, heap and stack
+    ReadWrite,
+    /// Code
+    ReadExecute,
+}
+
+impl Protection {
+    /// Page table flags for a user page with this protection
+    pub fn user_flags(self) -> PageTableFlags {
+        let base = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        match self {
+            Protection::Read => base | no_execute(),
+            Protection::ReadWrite => base | PageTableFlags::WRITABLE | no_execute(),
+            Protection::ReadExecute => base,
+        }
+    }
+}
+
+/// Software PTE bit: page is shared copy-on-write (mapped read-only)
+const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Software PTE bit: page of a shared-memory region, which fork keeps
+/// shared instead of copy-on-write
+const SHM: PageTableFlags = PageTableFlags::BIT_10;
+
+/// What backs the pages of a region until they are first touched
+#[derive(Clone)]
+pub enum Backing {
+    /// Zero-filled (heap, stack)
+    Anonymous,
+    /// `bytes` appear at virtual address `at`, the rest is zero (ELF segments)
+    Image { bytes: Arc<Vec<u8>>, at: u64 },
+}
+
+/// A valid range of user addresses whose pages are mapped on first access
+#[derive(Clone)]
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub flags: PageTableFlags,
+    pub backing: Backing,
+}
+
+impl Region {
+    pub fn anonymous(start: u64, end: u64, flags: PageTableFlags) -> Self {
+        Region { start, end, flags, backing: Backing::Anonymous }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    fn overlaps_page(&self, page: u64) -> bool {
+        self.start < page + 4096 && self.end > page
+    }
+
+    /// Copy this region's initial contents for `page` into `dst` (already zeroed)
+    fn fill(&self, page: u64, dst: &mut [u8]) {
+        if let Backing::Image { bytes, at } = &self.backing {
+            let lo = page.max(*at).max(self.start);
+            let hi = (page + 4096).min(at + bytes.len() as u64).min(self.end);
+            if lo < hi {
+                dst[(lo - page) as usize..(hi - page) as usize]
+                    .copy_from_slice(&bytes[(lo - at) as usize..(hi - at) as usize]);
+            }
+        }
+    }
+}
+
+/// Owner counts for frames shared copy-on-write; frames not listed have
+/// one owner. The page fault handler only ever counts owners down to one
+/// and leaves the entry, as removing it could free heap memory; such
+/// entries go when the frame is released from task context.
+static SHARED_FRAMES: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+fn share_frame(addr: u64) {
+    *SHARED_FRAMES.lock().entry(addr).or_insert(1) += 1;
+}
+
+/// Frames mapped by more than one address space
+pub fn shared_frames() -> usize {
+    SHARED_FRAMES.lock().values().filter(|&&owners| owners > 1).count()
+}
+
+/// Drop one owner of a user frame, freeing it with the last one
+pub(crate) fn release_frame(addr: u64) {
+    let mut shared = SHARED_FRAMES.lock();
+    match shared.get_mut(&addr) {
+        Some(owners) if *owners > 1 => *owners -= 1,
+        Some(_) => {
+            shared.remove(&addr);
+            FRAME_ALLOCATOR.lock().free(addr as usize);
+        }
+        None => FRAME_ALLOCATOR.lock().free(addr as usize),
+    }
+}
+
 /// Address Space - represents a virtual address space with its own page table
 pub struct AddressSpace {
     /// Physical address of the PML4 (root page table)
     pub cr3: PhysAddr,
     /// Cached mapper for this address space
     mapper: Option<OffsetPageTable<'static>>,
+    /// User ranges that are mapped on demand by `handle_fault`
+    regions: Vec<Region>,
+    /// Unmapped pages below user stacks; touching one is a stack overflow
+    guards: Vec<u64>,
+    /// Where the next sys_malloc allocation goes
+    heap_next: u64,
 }
 
 impl AddressSpace {
@@ -75,9 +230,17 @@ impl AddressSpace {
         Ok(Self {
             cr3: pml4_addr,
             mapper: None,
+            regions: Vec::new(),
+            guards: Vec::new(),
+            heap_next: USER_HEAP_BASE,
         })
     }
 
+    /// Start sys_malloc allocations at `base` (page aligned)
+    pub fn set_heap_base(&mut self, base: u64) {
+        self.heap_next = base & !0xFFF;
+    }
+
     /// Get a mapper for this address space
     pub fn mapper(&mut self) -> &mut OffsetPageTable<'static> {
         if self.mapper.is_none() {
@@ -200,6 +363,303 @@ impl AddressSpace {
     }
 }
 
+impl AddressSpace {
+    /// Register a range of valid user addresses. Nothing is mapped until
+    /// the pages are touched.
+    pub fn add_region(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    /// Keep the page at `page` unmapped as a guard below a stack
+    pub fn add_guard_page(&mut self, page: u64) {
+        self.guards.push(page & !0xFFF);
+    }
+
+    /// Resolve a page fault at user address `addr`
+    ///
+    /// Maps a fresh page when `addr` lies in a region but is not mapped
+    /// yet, and breaks copy-on-write sharing on writes. Anything else is a
+    /// genuine invalid access and returns an error. Runs in the #PF
+    /// handler: locks are only tried (`FAULT_BUSY` when one is held) and
+    /// the heap is not touched.
+    pub fn handle_fault(&mut self, addr: u64, present: bool, write: bool) -> Result<(), &'static str> {
+        let page = addr & !0xFFF;
+        if present {
+            return if write { self.break_cow(page) } else { Err("protection violation") };
+        }
+        if self.guards.contains(&page) {
+            return Err("stack overflow");
+        }
+        if !self.regions.iter().any(|r| r.contains(addr)) {
+            return Err("address not mapped");
+        }
+
+        let hhdm = boot::hhdm_offset().ok_or("HHDM offset not available")?;
+        let mut frames = FRAME_ALLOCATOR.try_lock().ok_or(FAULT_BUSY)?;
+        let frame_addr = frames.allocate().ok_or("Out of physical memory")? as u64;
+        let dst = unsafe { core::slice::from_raw_parts_mut((frame_addr + hhdm) as *mut u8, 4096) };
+        dst.fill(0);
+        // Segments that are not page aligned may share a page, which then
+        // gets every permission they have between them
+        let mut flags = PageTableFlags::empty();
+        let mut executable = false;
+        for region in self.regions.iter().filter(|r| r.overlaps_page(page)) {
+            region.fill(page, dst);
+            flags |= region.flags;
+            executable |= !region.flags.contains(PageTableFlags::NO_EXECUTE);
+        }
+        if executable {
+            flags.remove(PageTableFlags::NO_EXECUTE);
+        }
+
+        let frame = PhysFrame::containing_address(PhysAddr::new(frame_addr));
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(page));
+        let result = unsafe { self.mapper().map_to(page, frame, flags, &mut LockedFrames(&mut frames)) };
+        match result {
+            Ok(flush) => {
+                flush.flush();
+                Ok(())
+            }
+            Err(_) => {
+                frames.free(frame_addr as usize);
+                Err("Failed to map page")
+            }
+        }
+    }
+
+    /// Physical address `virt` is mapped to, if the page is present
+    pub fn translate(&self, virt: u64) -> Option<u64> {
+        let hhdm = boot::hhdm_offset()?;
+        let entry = unsafe { leaf_entry(self.cr3, hhdm, virt) }?;
+        Some(entry.addr().as_u64() + (virt & 0xFFF))
+    }
+
+    /// User pages present in this address space, and how many of those
+    /// it shares with others (copy-on-write after fork, or shared memory)
+    pub fn resident_pages(&self) -> (usize, usize) {
+        let Some(hhdm) = boot::hhdm_offset() else { return (0, 0) };
+        let shared = SHARED_FRAMES.lock();
+        let (mut resident, mut shared_pages) = (0, 0);
+        unsafe {
+            for_each_user_page(self.cr3, hhdm, |_, entry| {
+                resident += 1;
+                if shared.get(&entry.addr().as_u64()).is_some_and(|&owners| owners > 1) {
+                    shared_pages += 1;
+                }
+            });
+        }
+        (resident, shared_pages)
+    }
+
+    /// Give the faulting task a private, writable copy of a COW page
+    fn break_cow(&mut self, page: u64) -> Result<(), &'static str> {
+        let hhdm = boot::hhdm_offset().ok_or("HHDM offset not available")?;
+        let entry = unsafe { leaf_entry(self.cr3, hhdm, page) }.ok_or("address not mapped")?;
+        let flags = entry.flags();
+        if !flags.contains(COW) {
+            return Err("write to read-only page");
+        }
+        let flags = (flags - COW - PageTableFlags::DIRTY - PageTableFlags::ACCESSED) | PageTableFlags::WRITABLE;
+
+        let old = entry.addr().as_u64();
+        let mut shared = SHARED_FRAMES.try_lock().ok_or(FAULT_BUSY)?;
+        if let Some(owners) = shared.get_mut(&old).filter(|owners| **owners > 1) {
+            let new = FRAME_ALLOCATOR.try_lock().ok_or(FAULT_BUSY)?.allocate().ok_or("Out of physical memory")? as u64;
+            unsafe {
+                core::ptr::copy_nonoverlapping((old + hhdm) as *const u8, (new + hhdm) as *mut u8, 4096);
+            }
+            entry.set_addr(PhysAddr::new(new), flags);
+            // Never the last owner, so the frame stays and so does the entry
+            *owners -= 1;
+        } else {
+            // The other owners are gone, keep the frame
+            entry.set_flags(flags);
+        }
+        x86_64::instructions::tlb::flush(VirtAddr::new(page));
+        Ok(())
+    }
+
+    /// Share every user page (lower half) copy-on-write with a new address
+    /// space that also has the kernel mappings. Used by fork.
+    pub fn clone_user_space(&self) -> Result<AddressSpace, &'static str> {
+        let hhdm = boot::hhdm_offset().ok_or("HHDM offset not available")?;
+        let mut child = AddressSpace::new()?;
+        child.regions = self.regions.clone();
+        child.guards = self.guards.clone();
+        child.heap_next = self.heap_next;
+        if let Err(e) = child.clone_kernel_mappings() {
+            child.destroy();
+            return Err(e);
+        }
+
+        let mut result = Ok(());
+        unsafe {
+            for_each_user_page(self.cr3, hhdm, |virt, entry| {
+                if result.is_err() {
+                    return;
+                }
+                // Writable pages become read-only in both until one writes,
+                // except shared memory, which both keep writing to
+                let mut flags = entry.flags() - PageTableFlags::ACCESSED - PageTableFlags::DIRTY;
+                if flags.contains(PageTableFlags::WRITABLE) && !flags.contains(SHM) {
+                    flags = (flags - PageTableFlags::WRITABLE) | COW;
+                    entry.set_flags(flags);
+                }
+                let page = Page::<Size4KiB>::containing_address(VirtAddr::new(virt));
+                let frame = PhysFrame::containing_address(entry.addr());
+                match child.map_page(page, frame, flags) {
+                    Ok(()) => share_frame(entry.addr().as_u64()),
+                    Err(e) => result = Err(e),
+                }
+            });
+        }
+        // The parent is usually the active address space
+        x86_64::instructions::tlb::flush_all();
+
+        match result {
+            Ok(()) => Ok(child),
+            Err(e) => {
+                child.destroy();
+                Err(e)
+            }
+        }
+    }
+
+    /// Map the shared-memory `frames` at `start`. Each mapping holds a
+    /// reference on its frame until it is unmapped or the address space
+    /// is destroyed.
+    pub fn map_shared(&mut self, start: u64, frames: &[u64], writable: bool) -> Result<(), &'static str> {
+        let protection = if writable { Protection::ReadWrite } else { Protection::Read };
+        let flags = protection.user_flags() | SHM;
+        for (i, &frame) in frames.iter().enumerate() {
+            let page = Page::containing_address(VirtAddr::new(start + i as u64 * 4096));
+            if let Err(e) = self.map_page(page, PhysFrame::containing_address(PhysAddr::new(frame)), flags) {
+                self.unmap_shared(start, i);
+                return Err(e);
+            }
+            share_frame(frame);
+        }
+        Ok(())
+    }
+
+    /// Undo `map_shared` for `count` pages at `start`
+    pub fn unmap_shared(&mut self, start: u64, count: usize) {
+        for i in 0..count {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start + i as u64 * 4096));
+            if let Ok((frame, flush)) = self.mapper().unmap(page) {
+                flush.flush();
+                release_frame(frame.start_address().as_u64());
+            }
+        }
+    }
+
+    /// Free every user page and page table of this address space, and the
+    /// PML4 itself. Kernel mappings are shared and left alone. Must not be
+    /// the active address space.
+    pub fn destroy(self) {
+        let hhdm = match boot::hhdm_offset() {
+            Some(hhdm) => hhdm,
+            None => return,
+        };
+        unsafe {
+            for_each_user_page(self.cr3, hhdm, |_, entry| {
+                release_frame(entry.addr().as_u64());
+            });
+            let pml4 = &*((self.cr3.as_u64() + hhdm) as *const PageTable);
+            for pml4e in pml4.iter().take(256).filter(|e| e.flags().contains(PageTableFlags::PRESENT)) {
+                let pdpt = &*((pml4e.addr().as_u64() + hhdm) as *const PageTable);
+                for pdpte in pdpt.iter().filter(|e| is_table(e.flags())) {
+                    let pd = &*((pdpte.addr().as_u64() + hhdm) as *const PageTable);
+                    for pde in pd.iter().filter(|e| is_table(e.flags())) {
+                        FRAME_ALLOCATOR.lock().free(pde.addr().as_u64() as usize);
+                    }
+                    FRAME_ALLOCATOR.lock().free(pdpte.addr().as_u64() as usize);
+                }
+                FRAME_ALLOCATOR.lock().free(pml4e.addr().as_u64() as usize);
+            }
+        }
+        FRAME_ALLOCATOR.lock().free(self.cr3.as_u64() as usize);
+    }
+}
+
+/// Present, non-huge entry pointing at a lower-level table
+fn is_table(flags: PageTableFlags) -> bool {
+    flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE)
+}
+
+/// Page table entry mapping the 4 KiB page at `virt`, if present
+unsafe fn leaf_entry(cr3: PhysAddr, hhdm: u64, virt: u64) -> Option<&'static mut PageTableEntry> {
+    let mut table = &mut *((cr3.as_u64() + hhdm) as *mut PageTable);
+    for level in [39, 30, 21] {
+        let entry = &table[((virt >> level) & 0x1FF) as usize];
+        if !is_table(entry.flags()) {
+            return None;
+        }
+        table = &mut *((entry.addr().as_u64() + hhdm) as *mut PageTable);
+    }
+    let entry = &mut table[((virt >> 12) & 0x1FF) as usize];
+    if entry.flags().contains(PageTableFlags::PRESENT) {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// Set NO_EXECUTE on every page mapping `start..end` in the page tables at
+/// `cr3`, whatever size the pages are. Pages not mapped are skipped.
+unsafe fn set_no_execute(cr3: PhysAddr, hhdm: u64, start: u64, end: u64) {
+    let mut virt = start & !0xFFF;
+    while virt < end {
+        let mut table = &mut *((cr3.as_u64() + hhdm) as *mut PageTable);
+        let mut size = 1u64 << 39;
+        for level in [39, 30, 21, 12] {
+            size = 1 << level;
+            let entry = &mut table[((virt >> level) & 0x1FF) as usize];
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT) {
+                break;
+            }
+            if level == 12 || flags.contains(PageTableFlags::HUGE_PAGE) {
+                entry.set_flags(flags | PageTableFlags::NO_EXECUTE);
+                break;
+            }
+            table = &mut *((entry.addr().as_u64() + hhdm) as *mut PageTable);
+        }
+        virt = (virt & !(size - 1)) + size;
+    }
+}
+
+/// Call `f(virt, entry)` for every present 4 KiB page in the lower half.
+/// Huge pages are never created for user space and are skipped.
+unsafe fn for_each_user_page(cr3: PhysAddr, hhdm: u64, mut f: impl FnMut(u64, &mut PageTableEntry)) {
+    let pml4 = &*((cr3.as_u64() + hhdm) as *const PageTable);
+    for (i4, pml4e) in pml4.iter().enumerate().take(256) {
+        if !pml4e.flags().contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let pdpt = &*((pml4e.addr().as_u64() + hhdm) as *const PageTable);
+        for (i3, pdpte) in pdpt.iter().enumerate() {
+            if !is_table(pdpte.flags()) {
+                continue;
+            }
+            let pd = &*((pdpte.addr().as_u64() + hhdm) as *const PageTable);
+            for (i2, pde) in pd.iter().enumerate() {
+                if !is_table(pde.flags()) {
+                    continue;
+                }
+                let pt = &mut *((pde.addr().as_u64() + hhdm) as *mut PageTable);
+                for (i1, pte) in pt.iter_mut().enumerate() {
+                    if pte.flags().contains(PageTableFlags::PRESENT) {
+                        let virt = ((i4 as u64) << 39) | ((i3 as u64) << 30)
+                            | ((i2 as u64) << 21) | ((i1 as u64) << 12);
+                        f(virt, pte);
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Global VMM instance
 pub static VMM: Mutex<Option<VirtualMemoryManager>> = Mutex::new(None);
 
@@ -207,25 +667,59 @@ pub static VMM: Mutex<Option<VirtualMemoryManager>> = Mutex::new(None);
 pub struct VirtualMemoryManager {
     /// Kernel address space
     kernel_space: AddressSpace,
-    /// Next user heap address for sys_malloc
-    next_user_heap: VirtAddr,
+    /// Kernel stack slots never handed out yet start here
+    next_stack_slot: u64,
+    /// Slots returned by `free_kernel_stack`
+    free_stack_slots: Vec<u64>,
 }
 
 impl VirtualMemoryManager {
     /// Initialize the VMM with the current kernel page table
     pub fn init() -> Result<(), &'static str> {
+        // Copy-on-write relies on the kernel faulting on read-only user pages too
+        unsafe {
+            use x86_64::registers::control::{Cr0, Cr0Flags};
+            Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+        }
+
         // Get current PML4 from CR3
         let (pml4_frame, _) = x86_64::registers::control::Cr3::read();
         let pml4_addr = pml4_frame.start_address();
 
+        // User address spaces copy the kernel PML4 entries when they are
+        // created, so the stack area needs its table before the first one
+        let hhdm = boot::hhdm_offset().ok_or("HHDM offset not available")?;
+        unsafe {
+            let pml4 = &mut *((pml4_addr.as_u64() + hhdm) as *mut PageTable);
+            let entry = &mut pml4[((KERNEL_STACK_AREA >> 39) & 0x1FF) as usize];
+            if entry.is_unused() {
+                let table = FRAME_ALLOCATOR.lock().allocate().ok_or("Out of physical memory")? as u64;
+                core::ptr::write_bytes((table + hhdm) as *mut u8, 0, 4096);
+                entry.set_addr(PhysAddr::new(table), PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+            }
+        }
+
+        // Nothing runs from the kernel heap; take away the execute permission
+        // the bootloader's direct map gives it. The 1 GiB or 2 MiB pages
+        // that map it cover other memory too, which loses it as well:
+        // kernel code runs from its own mapping, never the direct map.
+        if let (true, Some((start, end))) = (cpu::has(Feature::Nx), crate::mm::heap_allocator::physical_range()) {
+            unsafe { set_no_execute(pml4_addr, hhdm, start + hhdm, end + hhdm) };
+            x86_64::instructions::tlb::flush_all();
+        }
+
         let kernel_space = AddressSpace {
             cr3: pml4_addr,
             mapper: None,
+            regions: Vec::new(),
+            guards: Vec::new(),
+            heap_next: USER_HEAP_BASE,
         };
 
         let vmm = VirtualMemoryManager {
             kernel_space,
-            next_user_heap: VirtAddr::new(0x0000_4000_0000_0000), // Start at 64 TB
+            next_stack_slot: 0,
+            free_stack_slots: Vec::new(),
         };
 
         *VMM.lock() = Some(vmm);
@@ -245,13 +739,61 @@ impl VirtualMemoryManager {
         // Round up to page size
         let pages = (size + 4095) / 4096;
 
-        let start_addr = self.next_user_heap;
-        self.next_user_heap += pages as u64 * 4096;
+        let start = address_space.heap_next;
+        let end = start.checked_add(pages as u64 * 4096).ok_or("User heap exhausted")?;
+        if end > USER_SPACE_END {
+            return Err("User heap exhausted");
+        }
+        address_space.heap_next = end;
 
-        // Allocate pages in the given address space
-        address_space.allocate_pages(start_addr, pages, USER_PAGE_FLAGS)?;
+        // Pages are mapped when first touched
+        address_space.add_region(Region::anonymous(start, end, Protection::ReadWrite.user_flags()));
+
+        Ok(VirtAddr::new(start))
+    }
+
+    /// Map a kernel stack of `size` bytes at the top of a free slot in the
+    /// kernel stack area and return its top
+    pub fn alloc_kernel_stack(&mut self, size: usize) -> Result<u64, &'static str> {
+        let pages = (size + 4095) / 4096;
+        if pages as u64 * 4096 > KERNEL_STACK_SLOT - 4096 {
+            return Err("Kernel stack too large");
+        }
+        let slot = match self.free_stack_slots.pop() {
+            Some(slot) => slot,
+            None if self.next_stack_slot < KERNEL_STACK_SLOTS => {
+                self.next_stack_slot += 1;
+                self.next_stack_slot - 1
+            }
+            None => return Err("Out of kernel stack slots"),
+        };
+        let top = KERNEL_STACK_AREA + (slot + 1) * KERNEL_STACK_SLOT;
+        let start = VirtAddr::new(top - pages as u64 * 4096);
+        if let Err(e) = self.kernel_space.allocate_pages(start, pages, KERNEL_PAGE_FLAGS | no_execute()) {
+            self.free_kernel_stack(top, size);
+            return Err(e);
+        }
+        Ok(top)
+    }
 
-        Ok(start_addr)
+    /// Unmap a stack returned by `alloc_kernel_stack` and free its frames
+    pub fn free_kernel_stack(&mut self, top: u64, size: usize) {
+        let hhdm = match boot::hhdm_offset() {
+            Some(hhdm) => hhdm,
+            None => return,
+        };
+        let pages = (size + 4095) / 4096;
+        for i in 1..=pages as u64 {
+            let virt = top - i * 4096;
+            let frame = match unsafe { leaf_entry(self.kernel_space.cr3, hhdm, virt) } {
+                Some(entry) => entry.addr().as_u64(),
+                None => continue,
+            };
+            if self.kernel_space.unmap_page(Page::containing_address(VirtAddr::new(virt))).is_ok() {
+                FRAME_ALLOCATOR.lock().free(frame as usize);
+            }
+        }
+        self.free_stack_slots.push((top - KERNEL_STACK_AREA) / KERNEL_STACK_SLOT - 1);
     }
 
     /// Create a new user address space with kernel mappings
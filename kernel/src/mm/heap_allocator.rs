@@ -1,48 +1,346 @@
 //! Kernel Heap Allocator with Limine Memory Map support
 //!
-//! This allocator scans the Limine Memory Map for USABLE regions
-//! and initializes the heap dynamically based on available memory.
+//! The heap is carved out of the largest USABLE region of the Limine
+//! Memory Map. Pages are managed by a buddy allocator; small objects come
+//! from slab caches that sit on top of it, one cache per size class plus
+//! dedicated caches for the kernel objects allocated most often. Anything
+//! larger than the biggest cache gets whole buddy blocks.
 
+use alloc::vec::Vec;
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
 use crate::boot::limine;
 
-pub struct SimpleAllocator {
-    heap_start: Mutex<Option<usize>>,
-    heap_size: Mutex<usize>,
-    allocated: Mutex<usize>,
+const PAGE_SIZE: usize = 4096;
+
+/// Largest buddy block: 2^MAX_ORDER pages (32 MB)
+const MAX_ORDER: usize = 13;
+
+/// Slab header at the start of every slab page
+#[repr(C)]
+struct Slab {
+    /// Next slab of the same cache that has free objects
+    next: usize,
+    /// First free object in this slab
+    free: usize,
+    /// Objects handed out from this slab
+    in_use: usize,
+}
+
+const SLAB_HEADER: usize = core::mem::size_of::<Slab>();
+
+/// Slab caches: name and object size. Dedicated caches come first so they
+/// win over a size class of the same size.
+const CACHES: [(&str, usize); 10] = [
+    ("pcb", round_up(core::mem::size_of::<crate::task::pcb::ProcessControlBlock>(), 16)),
+    ("vnode", round_up(core::mem::size_of::<crate::services::vfs::VNode>(), 16)),
+    ("message", round_up(core::mem::size_of::<crate::ipc::message::Message>(), 16)),
+    ("kmalloc-16", 16),
+    ("kmalloc-32", 32),
+    ("kmalloc-64", 64),
+    ("kmalloc-128", 128),
+    ("kmalloc-256", 256),
+    ("kmalloc-512", 512),
+    ("kmalloc-1024", 1024),
+];
+
+const fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// Alignment guaranteed for objects of a cache
+const fn cache_align(size: usize) -> usize {
+    if size.is_power_of_two() && size <= PAGE_SIZE { size } else { 16 }
+}
+
+/// Offset of the first object in a slab page
+const fn first_object(size: usize) -> usize {
+    round_up(SLAB_HEADER, cache_align(size))
+}
+
+const fn objects_per_slab(size: usize) -> usize {
+    let first = first_object(size);
+    if first >= PAGE_SIZE { 0 } else { (PAGE_SIZE - first) / size }
+}
+
+struct Cache {
+    /// Slabs with at least one free object
+    partial: usize,
+    active: usize,
+    total: usize,
+    slabs: usize,
+}
+
+/// Buddy page allocator plus slab caches over the heap region
+struct Heap {
+    start: usize,
+    size: usize,
+    /// Free list heads per order (0 = empty); the link is stored in the block
+    free_lists: [usize; MAX_ORDER + 1],
+    /// Bytes handed out by the buddy allocator (slabs and large blocks)
+    reserved: usize,
+    caches: [Cache; CACHES.len()],
+}
+
+impl Heap {
+    const fn new() -> Self {
+        const EMPTY: Cache = Cache { partial: 0, active: 0, total: 0, slabs: 0 };
+        Heap {
+            start: 0,
+            size: 0,
+            free_lists: [0; MAX_ORDER + 1],
+            reserved: 0,
+            caches: [EMPTY; CACHES.len()],
+        }
+    }
+
+    /// Hand the region to the buddy allocator as the largest aligned blocks
+    /// that fit
+    unsafe fn init(&mut self, start: usize, size: usize) {
+        self.start = start;
+        self.size = size & !(PAGE_SIZE - 1);
+        let mut offset = 0;
+        while offset < self.size {
+            let mut order = MAX_ORDER;
+            while offset % (PAGE_SIZE << order) != 0 || offset + (PAGE_SIZE << order) > self.size {
+                order -= 1;
+            }
+            self.push(order, start + offset);
+            offset += PAGE_SIZE << order;
+        }
+    }
+
+    unsafe fn push(&mut self, order: usize, block: usize) {
+        *(block as *mut usize) = self.free_lists[order];
+        self.free_lists[order] = block;
+    }
+
+    /// Take `block` off the free list of `order` if it is there
+    unsafe fn remove(&mut self, order: usize, block: usize) -> bool {
+        let mut link = &mut self.free_lists[order] as *mut usize;
+        while *link != 0 {
+            if *link == block {
+                *link = *(block as *const usize);
+                return true;
+            }
+            link = *link as *mut usize;
+        }
+        false
+    }
+
+    unsafe fn alloc_pages(&mut self, order: usize) -> usize {
+        let mut found = order;
+        while found <= MAX_ORDER && self.free_lists[found] == 0 {
+            found += 1;
+        }
+        if found > MAX_ORDER {
+            return 0;
+        }
+        let block = self.free_lists[found];
+        self.free_lists[found] = *(block as *const usize);
+        // Split, keeping the lower half each time
+        while found > order {
+            found -= 1;
+            self.push(found, block + (PAGE_SIZE << found));
+        }
+        self.reserved += PAGE_SIZE << order;
+        block
+    }
+
+    unsafe fn free_pages(&mut self, mut block: usize, mut order: usize) {
+        self.reserved -= PAGE_SIZE << order;
+        // Merge with the buddy for as long as it is free too
+        while order < MAX_ORDER {
+            let buddy = self.start + ((block - self.start) ^ (PAGE_SIZE << order));
+            if buddy + (PAGE_SIZE << order) > self.start + self.size || !self.remove(order, buddy) {
+                break;
+            }
+            block = block.min(buddy);
+            order += 1;
+        }
+        self.push(order, block);
+    }
+
+    unsafe fn alloc_object(&mut self, index: usize) -> usize {
+        let size = CACHES[index].1;
+        if self.caches[index].partial == 0 {
+            let page = self.alloc_pages(0);
+            if page == 0 {
+                return 0;
+            }
+            let count = objects_per_slab(size);
+            let first = page + first_object(size);
+            for i in 0..count {
+                let object = first + i * size;
+                *(object as *mut usize) = if i + 1 < count { object + size } else { 0 };
+            }
+            *(page as *mut Slab) = Slab { next: 0, free: first, in_use: 0 };
+            let cache = &mut self.caches[index];
+            cache.partial = page;
+            cache.total += count;
+            cache.slabs += 1;
+        }
+
+        let cache = &mut self.caches[index];
+        let slab = &mut *(cache.partial as *mut Slab);
+        let object = slab.free;
+        slab.free = *(object as *const usize);
+        slab.in_use += 1;
+        if slab.free == 0 {
+            cache.partial = slab.next;
+        }
+        cache.active += 1;
+        object
+    }
+
+    unsafe fn free_object(&mut self, index: usize, object: usize) {
+        let page = object & !(PAGE_SIZE - 1);
+        let slab = &mut *(page as *mut Slab);
+        let was_full = slab.free == 0;
+        *(object as *mut usize) = slab.free;
+        slab.free = object;
+        slab.in_use -= 1;
+
+        let cache = &mut self.caches[index];
+        cache.active -= 1;
+        if was_full {
+            slab.next = cache.partial;
+            cache.partial = page;
+        }
+
+        // Give an empty slab back unless it is the cache's only spare
+        if slab.in_use == 0 && !(cache.partial == page && slab.next == 0) {
+            let mut link = &mut cache.partial as *mut usize;
+            while *link != page {
+                link = &mut (*(*link as *mut Slab)).next;
+            }
+            *link = slab.next;
+            cache.total -= objects_per_slab(CACHES[index].1);
+            cache.slabs -= 1;
+            self.free_pages(page, 0);
+        }
+    }
+}
+
+/// Cache serving `layout`, or None for a whole-page allocation
+fn cache_for(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(1);
+    CACHES.iter()
+        .enumerate()
+        .filter(|(_, &(_, object))| {
+            object >= size && cache_align(object) >= layout.align() && objects_per_slab(object) >= 2
+        })
+        .min_by_key(|(_, &(_, object))| object)
+        .map(|(index, _)| index)
+}
+
+/// Buddy order for an allocation too large for the slab caches
+fn page_order(layout: Layout) -> usize {
+    let pages = (layout.size().max(layout.align()) + PAGE_SIZE - 1) / PAGE_SIZE;
+    pages.next_power_of_two().trailing_zeros() as usize
+}
+
+pub struct KernelAllocator {
+    heap: Mutex<Heap>,
+    /// Bytes handed out and not yet freed
+    in_use: AtomicUsize,
+    /// Highest `in_use` seen since boot
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+}
+
+/// Heap usage snapshot, see `stats`
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Size of the heap region
+    pub total: usize,
+    /// Bytes currently allocated
+    pub in_use: usize,
+    /// Highest `in_use` since boot
+    pub peak: usize,
+    /// Bytes taken from the page allocator (slab pages and large blocks)
+    pub reserved: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+impl HeapStats {
+    /// Bytes still available for new allocations
+    pub fn free(&self) -> usize {
+        self.total.saturating_sub(self.reserved)
+    }
+
+    /// Share of the reserved space that is not in use (free slab objects
+    /// and rounding to buddy blocks), in percent
+    pub fn fragmentation_percent(&self) -> usize {
+        if self.reserved == 0 {
+            0
+        } else {
+            self.reserved.saturating_sub(self.in_use) * 100 / self.reserved
+        }
+    }
+}
+
+/// Usage of one slab cache, see `slab_info`
+#[derive(Debug, Clone, Copy)]
+pub struct SlabInfo {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    /// Objects currently allocated
+    pub active: usize,
+    /// Objects in all slabs of the cache
+    pub total: usize,
+    pub slabs: usize,
 }
 
-unsafe impl GlobalAlloc for SimpleAllocator {
+unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // Simple bump allocator
-        let size = layout.size();
-        let align = layout.align();
-        
-        if let Some(start) = *self.heap_start.lock() {
-            let current = *self.allocated.lock();
-            let aligned = (current + align - 1) & !(align - 1);
-            
-            if aligned + size <= *self.heap_size.lock() {
-                *self.allocated.lock() = aligned + size;
-                return (start + aligned) as *mut u8;
+        let ptr = {
+            let mut heap = self.heap.lock();
+            if heap.size == 0 {
+                return null_mut();
+            }
+            match cache_for(layout) {
+                Some(index) => heap.alloc_object(index),
+                None if page_order(layout) <= MAX_ORDER => heap.alloc_pages(page_order(layout)),
+                None => 0,
             }
+        };
+        if ptr == 0 {
+            return null_mut();
         }
-        
-        null_mut()
+
+        let size = layout.size();
+        let in_use = self.in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(in_use, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        ptr as *mut u8
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        // TODO: implement proper deallocation
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        {
+            let mut heap = self.heap.lock();
+            match cache_for(layout) {
+                Some(index) => heap.free_object(index, ptr as usize),
+                None => heap.free_pages(ptr as usize, page_order(layout)),
+            }
+        }
+        self.in_use.fetch_sub(layout.size(), Ordering::Relaxed);
+        self.frees.fetch_add(1, Ordering::Relaxed);
     }
 }
 
 #[global_allocator]
-static ALLOCATOR: SimpleAllocator = SimpleAllocator {
-    heap_start: Mutex::new(None),
-    heap_size: Mutex::new(0),
-    allocated: Mutex::new(0),
+static ALLOCATOR: KernelAllocator = KernelAllocator {
+    heap: Mutex::new(Heap::new()),
+    in_use: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+    allocations: AtomicUsize::new(0),
+    frees: AtomicUsize::new(0),
 };
 
 #[alloc_error_handler]
@@ -55,7 +353,7 @@ pub fn init() {
     // Find largest USABLE memory region from Limine
     let mut best_base: Option<u64> = None;
     let mut best_size: u64 = 0;
-    
+
     if let Some(memmap) = limine::memory_map() {
         for entry in memmap {
             // Only use USABLE memory
@@ -64,7 +362,7 @@ pub fn init() {
                 if entry.base < 0x100000 {
                     continue;
                 }
-                
+
                 // Find largest suitable region (at least 16MB)
                 if entry.length >= 16 * 1024 * 1024 && entry.length > best_size {
                     best_base = Some(entry.base);
@@ -73,18 +371,16 @@ pub fn init() {
             }
         }
     }
-    
+
     if let Some(base) = best_base {
         // Use HHDM offset to access physical memory
         let hhdm = limine::hhdm_offset().unwrap_or(0);
         let heap_virt = (base + hhdm) as usize;
-        
+
         // Cap heap size at 32MB for safety
         let heap_size = core::cmp::min(best_size as usize, 32 * 1024 * 1024);
-        
-        *ALLOCATOR.heap_start.lock() = Some(heap_virt);
-        *ALLOCATOR.heap_size.lock() = heap_size;
-        *ALLOCATOR.allocated.lock() = 0;
+
+        unsafe { ALLOCATOR.heap.lock().init(heap_virt, heap_size) };
     } else {
         panic!("No suitable USABLE memory region found for heap!");
     }
@@ -92,8 +388,67 @@ pub fn init() {
 
 /// Get heap statistics
 pub fn heap_stats() -> (usize, usize, usize) {
-    let start = ALLOCATOR.heap_start.lock().unwrap_or(0);
-    let size = *ALLOCATOR.heap_size.lock();
-    let allocated = *ALLOCATOR.allocated.lock();
-    (start, size, allocated)
+    let heap = ALLOCATOR.heap.lock();
+    (heap.start, heap.size, heap.reserved)
+}
+
+/// Physical range backing the heap (start, end), so the frame allocator
+/// can leave it alone
+pub fn physical_range() -> Option<(u64, u64)> {
+    let heap = ALLOCATOR.heap.lock();
+    if heap.size == 0 {
+        return None;
+    }
+    let hhdm = limine::hhdm_offset().unwrap_or(0);
+    let start = heap.start as u64 - hhdm;
+    Some((start, start + heap.size as u64))
+}
+
+/// Detailed heap usage for `free` and sys_sysinfo
+pub fn stats() -> HeapStats {
+    let (total, reserved) = {
+        let heap = ALLOCATOR.heap.lock();
+        (heap.size, heap.reserved)
+    };
+    counters(total, reserved)
+}
+
+/// `stats` without waiting for the heap lock, for interrupt context
+pub fn try_stats() -> Option<HeapStats> {
+    let (total, reserved) = {
+        let heap = ALLOCATOR.heap.try_lock()?;
+        (heap.size, heap.reserved)
+    };
+    Some(counters(total, reserved))
+}
+
+fn counters(total: usize, reserved: usize) -> HeapStats {
+    HeapStats {
+        total,
+        in_use: ALLOCATOR.in_use.load(Ordering::Relaxed),
+        peak: ALLOCATOR.peak.load(Ordering::Relaxed),
+        reserved,
+        allocations: ALLOCATOR.allocations.load(Ordering::Relaxed),
+        frees: ALLOCATOR.frees.load(Ordering::Relaxed),
+    }
+}
+
+/// Per-cache usage for the `slabinfo` command
+pub fn slab_info() -> Vec<SlabInfo> {
+    // Copy out under the lock; building the Vec allocates
+    let counts: [(usize, usize, usize); CACHES.len()] = {
+        let heap = ALLOCATOR.heap.lock();
+        core::array::from_fn(|i| (heap.caches[i].active, heap.caches[i].total, heap.caches[i].slabs))
+    };
+    CACHES.iter()
+        .zip(counts.iter())
+        .map(|(&(name, object_size), &(active, total, slabs))| SlabInfo {
+            name,
+            object_size,
+            objects_per_slab: objects_per_slab(object_size),
+            active,
+            total,
+            slabs,
+        })
+        .collect()
 }
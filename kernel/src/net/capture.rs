@@ -0,0 +1,228 @@
+//! Packet Capture
+//!
+//! Every frame that crosses the ethernet driver (and every loopback
+//! packet, wrapped in an ethernet header with zero MACs) is copied to the
+//! open capture taps. Raw sockets and `tcpdump` read from a tap.
+
+use super::ethernet::{ETHERTYPE_ARP, ETHERTYPE_IPV4};
+use super::ip::{PROTO_ICMP, PROTO_TCP, PROTO_UDP};
+use super::tcp::{FLAG_ACK, FLAG_FIN, FLAG_PSH, FLAG_RST, FLAG_SYN};
+use crate::drivers::timer;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Frames kept per tap before the oldest are dropped
+const MAX_QUEUED_FRAMES: usize = 256;
+/// pcap snapshot length, larger than any frame we produce
+const SNAPLEN: u32 = 65535;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub timestamp_ms: u64,
+    pub data: Vec<u8>,
+}
+
+struct Tap {
+    frames: VecDeque<CapturedFrame>,
+    dropped: usize,
+}
+
+static TAPS: Mutex<BTreeMap<u32, Tap>> = Mutex::new(BTreeMap::new());
+/// Number of open taps, checked before taking the lock on the hot path
+static ACTIVE_TAPS: AtomicUsize = AtomicUsize::new(0);
+static NEXT_TAP: AtomicU32 = AtomicU32::new(1);
+
+/// Start capturing; the driver is put into promiscuous mode while any tap is open
+pub fn open() -> u32 {
+    let id = NEXT_TAP.fetch_add(1, Ordering::Relaxed);
+    TAPS.lock().insert(id, Tap { frames: VecDeque::new(), dropped: 0 });
+    if ACTIVE_TAPS.fetch_add(1, Ordering::SeqCst) == 0 {
+        super::ethernet::ETHERNET_DRIVER.set_promiscuous(true);
+    }
+    id
+}
+
+pub fn close(id: u32) {
+    if TAPS.lock().remove(&id).is_some() && ACTIVE_TAPS.fetch_sub(1, Ordering::SeqCst) == 1 {
+        super::ethernet::ETHERNET_DRIVER.set_promiscuous(false);
+    }
+}
+
+/// Pop the oldest captured frame from a tap
+pub fn next(id: u32) -> Option<CapturedFrame> {
+    TAPS.lock().get_mut(&id)?.frames.pop_front()
+}
+
+/// Frames dropped because the tap was full
+pub fn dropped(id: u32) -> usize {
+    TAPS.lock().get(&id).map_or(0, |tap| tap.dropped)
+}
+
+/// Copy a frame to every open tap
+pub fn record(frame: &[u8]) {
+    if ACTIVE_TAPS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let captured = CapturedFrame {
+        timestamp_ms: timer::get_uptime_ms(),
+        data: frame.to_vec(),
+    };
+    for tap in TAPS.lock().values_mut() {
+        if tap.frames.len() >= MAX_QUEUED_FRAMES {
+            tap.frames.pop_front();
+            tap.dropped += 1;
+        }
+        tap.frames.push_back(captured.clone());
+    }
+}
+
+/// Capture taps are only fed while someone is listening
+pub fn is_active() -> bool {
+    ACTIVE_TAPS.load(Ordering::Relaxed) != 0
+}
+
+/// Serialize frames as a classic (microsecond) pcap file
+pub fn to_pcap(frames: &[CapturedFrame]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes()); // version major
+    out.extend_from_slice(&4u16.to_le_bytes()); // version minor
+    out.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    out.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    out.extend_from_slice(&SNAPLEN.to_le_bytes());
+    out.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+
+    for frame in frames {
+        let len = frame.data.len() as u32;
+        out.extend_from_slice(&((frame.timestamp_ms / 1000) as u32).to_le_bytes());
+        out.extend_from_slice(&(((frame.timestamp_ms % 1000) * 1000) as u32).to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes()); // captured length
+        out.extend_from_slice(&len.to_le_bytes()); // original length
+        out.extend_from_slice(&frame.data);
+    }
+    out
+}
+
+/// One-line, tcpdump-style summary of an ethernet frame
+pub fn describe(frame: &[u8]) -> String {
+    if frame.len() < 14 {
+        return format!("truncated frame, length {}", frame.len());
+    }
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    let payload = &frame[14..];
+    match ethertype {
+        ETHERTYPE_IPV4 => describe_ipv4(payload),
+        ETHERTYPE_ARP => describe_arp(payload),
+        other => format!("{} > {}, ethertype {:#06x}, length {}",
+                         mac(&frame[6..12]), mac(&frame[0..6]), other, frame.len()),
+    }
+}
+
+fn describe_arp(data: &[u8]) -> String {
+    if data.len() < 28 {
+        return format!("ARP, truncated, length {}", data.len());
+    }
+    let op = u16::from_be_bytes([data[6], data[7]]);
+    let sender_ip = ip(&data[14..18]);
+    let target_ip = ip(&data[24..28]);
+    match op {
+        1 => format!("ARP, Request who-has {} tell {}", target_ip, sender_ip),
+        2 => format!("ARP, Reply {} is-at {}", sender_ip, mac(&data[8..14])),
+        _ => format!("ARP, op {}, length {}", op, data.len()),
+    }
+}
+
+fn describe_ipv4(data: &[u8]) -> String {
+    if data.len() < 20 || data[0] >> 4 != 4 {
+        return format!("IP, truncated, length {}", data.len());
+    }
+    let header_len = (data[0] & 0x0F) as usize * 4;
+    let total_len = core::cmp::min(u16::from_be_bytes([data[2], data[3]]) as usize, data.len());
+    let flags_frag = u16::from_be_bytes([data[6], data[7]]);
+    let ttl = data[8];
+    let protocol = data[9];
+    let src = ip(&data[12..16]);
+    let dst = ip(&data[16..20]);
+    if header_len < 20 || header_len > total_len {
+        return format!("IP {} > {}: bad header length {}", src, dst, header_len);
+    }
+    let body = &data[header_len..total_len];
+
+    // Only the first fragment carries the transport header
+    let offset = flags_frag & 0x1FFF;
+    if offset != 0 {
+        return format!("IP {} > {}: frag id {}, offset {}, length {}",
+                       src, dst, u16::from_be_bytes([data[4], data[5]]), offset as usize * 8, body.len());
+    }
+
+    match protocol {
+        PROTO_UDP if body.len() >= 8 => {
+            let sport = u16::from_be_bytes([body[0], body[1]]);
+            let dport = u16::from_be_bytes([body[2], body[3]]);
+            format!("IP {}.{} > {}.{}: UDP, length {}", src, sport, dst, dport, body.len() - 8)
+        }
+        PROTO_TCP if body.len() >= 20 => {
+            let sport = u16::from_be_bytes([body[0], body[1]]);
+            let dport = u16::from_be_bytes([body[2], body[3]]);
+            let seq = u32::from_be_bytes([body[4], body[5], body[6], body[7]]);
+            let ack = u32::from_be_bytes([body[8], body[9], body[10], body[11]]);
+            let data_offset = (body[12] >> 4) as usize * 4;
+            let flags = body[13];
+            let window = u16::from_be_bytes([body[14], body[15]]);
+            let len = body.len().saturating_sub(data_offset);
+            let mut line = format!("IP {}.{} > {}.{}: Flags [{}], seq {}",
+                                   src, sport, dst, dport, tcp_flags(flags), seq);
+            if flags & FLAG_ACK != 0 {
+                line.push_str(&format!(", ack {}", ack));
+            }
+            line.push_str(&format!(", win {}, length {}", window, len));
+            line
+        }
+        PROTO_ICMP if body.len() >= 8 => {
+            let id = u16::from_be_bytes([body[4], body[5]]);
+            let seq = u16::from_be_bytes([body[6], body[7]]);
+            let kind = match body[0] {
+                0 => "echo reply",
+                3 => "destination unreachable",
+                8 => "echo request",
+                11 => "time exceeded",
+                _ => "type",
+            };
+            if body[0] == 0 || body[0] == 8 {
+                format!("IP {} > {}: ICMP {}, id {}, seq {}, length {}", src, dst, kind, id, seq, body.len())
+            } else {
+                format!("IP {} > {}: ICMP {} {}, length {}", src, dst, kind, body[0], body.len())
+            }
+        }
+        _ => format!("IP {} > {}: proto {}, ttl {}, length {}", src, dst, protocol, ttl, body.len()),
+    }
+}
+
+fn tcp_flags(flags: u8) -> String {
+    let mut out = String::new();
+    for (bit, ch) in [(FLAG_SYN, 'S'), (FLAG_FIN, 'F'), (FLAG_RST, 'R'), (FLAG_PSH, 'P')] {
+        if flags & bit != 0 {
+            out.push(ch);
+        }
+    }
+    if flags & FLAG_ACK != 0 {
+        out.push('.');
+    }
+    if out.is_empty() {
+        out.push_str("none");
+    }
+    out
+}
+
+fn ip(b: &[u8]) -> String {
+    format!("{}.{}.{}.{}", b[0], b[1], b[2], b[3])
+}
+
+fn mac(b: &[u8]) -> String {
+    format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", b[0], b[1], b[2], b[3], b[4], b[5])
+}
@@ -1,30 +1,250 @@
-//! DNS Resolution Stub
+//! DNS Resolver
 //!
-//! Provides hostname to IP address resolution.
+//! Resolves hostnames to IPv4 addresses by sending A-record queries over
+//! UDP to the configured nameserver. Answers are cached until their TTL
+//! expires.
 
-use super::{IpAddress, Result, NetworkError};
+use super::{udp, IpAddress, Result, NetworkError};
+use crate::drivers::timer;
+use crate::ipc::message::{FSRequest, FSResponse};
 use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
 use spin::Mutex;
 
-static DNS_CACHE: Mutex<BTreeMap<&'static str, IpAddress>> = Mutex::new(BTreeMap::new());
+pub const DNS_PORT: u16 = 53;
+
+/// QEMU user-mode networking resolver, used when nothing else is configured
+const DEFAULT_NAMESERVER: IpAddress = IpAddress::new(10, 0, 2, 3);
+const QUERY_TIMEOUT_MS: u64 = 2000;
+const MAX_CACHE_ENTRIES: usize = 64;
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+struct CacheEntry {
+    ip: IpAddress,
+    /// Uptime in ms after which the entry is stale (None = never expires)
+    expires_ms: Option<u64>,
+}
+
+static DNS_CACHE: Mutex<BTreeMap<String, CacheEntry>> = Mutex::new(BTreeMap::new());
+/// Nameserver handed out by DHCP, takes precedence over /etc/resolv.conf
+static NAMESERVER: Mutex<Option<IpAddress>> = Mutex::new(None);
+static NEXT_QUERY_ID: AtomicU16 = AtomicU16::new(0x4f50);
 
 pub fn init() {
-    let mut cache = DNS_CACHE.lock();
-    cache.insert("localhost", IpAddress::new(127, 0, 0, 1));
-    cache.insert("google.com", IpAddress::new(8, 8, 8, 8));
-    cache.insert("cloudflare.com", IpAddress::new(1, 1, 1, 1));
+    add_to_cache("localhost", IpAddress::new(127, 0, 0, 1));
+}
+
+/// Set the nameserver learned from DHCP
+pub fn set_nameserver(server: Option<IpAddress>) {
+    *NAMESERVER.lock() = server;
+}
+
+/// Current nameserver: DHCP, then /etc/resolv.conf, then the built-in default
+pub fn nameserver() -> IpAddress {
+    if let Some(server) = *NAMESERVER.lock() {
+        return server;
+    }
+    resolv_conf_nameserver().unwrap_or(DEFAULT_NAMESERVER)
+}
+
+fn resolv_conf_nameserver() -> Option<IpAddress> {
+    let data = match crate::services::vfs::process_request(FSRequest::ReadFile {
+        path: "/etc/resolv.conf".to_string(),
+    }) {
+        FSResponse::FileData(data) => data,
+        _ => return None,
+    };
+    let text = core::str::from_utf8(&data).ok()?;
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("nameserver"))
+        .find_map(IpAddress::parse)
 }
 
 pub fn resolve(hostname: &str) -> Result<IpAddress> {
-    // Check cache first
-    if let Some(ip) = DNS_CACHE.lock().get(hostname) {
-        return Ok(*ip);
+    if let Some(ip) = IpAddress::parse(hostname) {
+        return Ok(ip);
+    }
+
+    let name = hostname.trim_end_matches('.').to_ascii_lowercase();
+    if let Some(ip) = lookup_cache(&name) {
+        return Ok(ip);
+    }
+
+    let (ip, ttl) = query(nameserver(), &name)?;
+    insert_cache(name, ip, Some(timer::get_uptime_ms() + ttl as u64 * 1000));
+    Ok(ip)
+}
+
+/// Send a single A-record query to `server` and wait for the answer.
+/// Returns the address and its TTL in seconds. Bypasses the cache.
+pub fn query(server: IpAddress, hostname: &str) -> Result<(IpAddress, u32)> {
+    let id = NEXT_QUERY_ID.fetch_add(1, Ordering::Relaxed);
+    let request = build_query(id, hostname)?;
+
+    let port = udp::bind_ephemeral()?;
+    let result = exchange(server, port, id, &request);
+    udp::unbind(port);
+    result
+}
+
+fn exchange(server: IpAddress, port: u16, id: u16, request: &[u8]) -> Result<(IpAddress, u32)> {
+    let src = super::source_address_for(server);
+    udp::send_to(src, port, server, DNS_PORT, request)?;
+
+    let deadline = timer::get_uptime_ms() + QUERY_TIMEOUT_MS;
+    let mut buf = [0u8; 512];
+    loop {
+        // Checked first so that a stream of stray datagrams cannot keep the
+        // query alive past its deadline
+        if timer::get_uptime_ms() >= deadline {
+            return Err(NetworkError::Timeout);
+        }
+        super::poll();
+        match udp::receive_on(port, &mut buf) {
+            Ok((from, from_port, len)) => {
+                // Ignore stray datagrams and stale answers from earlier queries
+                if from != server || from_port != DNS_PORT {
+                    continue;
+                }
+                match parse_response(id, &buf[..len]) {
+                    Err(NetworkError::InvalidPacket) => continue,
+                    other => return other,
+                }
+            }
+            Err(NetworkError::Timeout) => {}
+            Err(e) => return Err(e),
+        }
+        super::idle();
+    }
+}
+
+/// Build a recursive A-record query for `hostname`
+fn build_query(id: u16, hostname: &str) -> Result<Vec<u8>> {
+    if hostname.is_empty() || hostname.len() > 253 {
+        return Err(NetworkError::InvalidAddress);
+    }
+
+    let mut msg = Vec::with_capacity(18 + hostname.len());
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&0x0100u16.to_be_bytes()); // RD
+    msg.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    msg.extend_from_slice(&[0; 6]); // ANCOUNT, NSCOUNT, ARCOUNT
+
+    for label in hostname.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(NetworkError::InvalidAddress);
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&TYPE_A.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+/// Extract the first A record from a response matching `id`
+fn parse_response(id: u16, data: &[u8]) -> Result<(IpAddress, u32)> {
+    if data.len() < 12 || u16::from_be_bytes([data[0], data[1]]) != id {
+        return Err(NetworkError::InvalidPacket);
+    }
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    if flags & 0x8000 == 0 {
+        return Err(NetworkError::InvalidPacket);
+    }
+    match (flags & 0x000F) as u8 {
+        0 => {}
+        RCODE_NXDOMAIN => return Err(NetworkError::NameNotFound),
+        _ => return Err(NetworkError::ConnectionFailed),
     }
 
-    // Stub DNS resolution - return error for unknown hosts
-    Err(NetworkError::InvalidAddress)
+    let qdcount = u16::from_be_bytes([data[4], data[5]]);
+    let ancount = u16::from_be_bytes([data[6], data[7]]);
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(data, pos)? + 4;
+    }
+
+    for _ in 0..ancount {
+        pos = skip_name(data, pos)?;
+        let rr = data.get(pos..pos + 10).ok_or(NetworkError::InvalidPacket)?;
+        let rtype = u16::from_be_bytes([rr[0], rr[1]]);
+        let class = u16::from_be_bytes([rr[2], rr[3]]);
+        let ttl = u32::from_be_bytes([rr[4], rr[5], rr[6], rr[7]]);
+        let rdlen = u16::from_be_bytes([rr[8], rr[9]]) as usize;
+        pos += 10;
+        let rdata = data.get(pos..pos + rdlen).ok_or(NetworkError::InvalidPacket)?;
+        pos += rdlen;
+
+        // CNAMEs are followed by the server, so just look for the A record
+        if rtype == TYPE_A && class == CLASS_IN && rdlen == 4 {
+            let ip = IpAddress::from_bytes([rdata[0], rdata[1], rdata[2], rdata[3]]);
+            return Ok((ip, ttl));
+        }
+    }
+
+    Err(NetworkError::NameNotFound)
 }
 
-pub fn add_to_cache(hostname: &'static str, ip: IpAddress) {
-    DNS_CACHE.lock().insert(hostname, ip);
-}
\ No newline at end of file
+/// Return the offset just past the (possibly compressed) name at `pos`
+fn skip_name(data: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *data.get(pos).ok_or(NetworkError::InvalidPacket)?;
+        match len {
+            0 => return Ok(pos + 1),
+            // Compression pointer ends the name
+            l if l & 0xC0 == 0xC0 => return Ok(pos + 2),
+            l if l & 0xC0 != 0 => return Err(NetworkError::InvalidPacket),
+            l => pos += 1 + l as usize,
+        }
+    }
+}
+
+fn lookup_cache(name: &str) -> Option<IpAddress> {
+    let mut cache = DNS_CACHE.lock();
+    let entry = cache.get(name)?;
+    match entry.expires_ms {
+        Some(expires) if timer::get_uptime_ms() >= expires => {
+            cache.remove(name);
+            None
+        }
+        _ => Some(entry.ip),
+    }
+}
+
+fn insert_cache(name: String, ip: IpAddress, expires_ms: Option<u64>) {
+    let mut cache = DNS_CACHE.lock();
+    if cache.len() >= MAX_CACHE_ENTRIES && !cache.contains_key(&name) {
+        // Evict expired entries first, then anything that can expire
+        let now = timer::get_uptime_ms();
+        cache.retain(|_, e| e.expires_ms.map_or(true, |t| t > now));
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let victim = cache.iter()
+                .find(|(_, e)| e.expires_ms.is_some())
+                .map(|(k, _)| k.clone());
+            match victim {
+                Some(k) => { cache.remove(&k); }
+                None => return,
+            }
+        }
+    }
+    cache.insert(name, CacheEntry { ip, expires_ms });
+}
+
+/// Add a static entry that never expires
+pub fn add_to_cache(hostname: &str, ip: IpAddress) {
+    insert_cache(hostname.to_ascii_lowercase(), ip, None);
+}
+
+/// Drop all learned entries, keeping static ones
+pub fn flush_cache() {
+    DNS_CACHE.lock().retain(|_, e| e.expires_ms.is_none());
+}
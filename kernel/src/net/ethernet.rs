@@ -1,9 +1,20 @@
 //! Ethernet Driver Stub
 //!
-//! Provides basic ethernet frame handling.
-//! Currently just a stub for demonstration.
+//! Provides basic ethernet frame handling. There is no NIC driver behind
+//! it yet: `has_device` is false, sending fails with `NoDevice` and
+//! nothing is ever received. eth0 is configured all the same, so traffic
+//! to other hosts is refused at once (see `net::check_route`) instead of
+//! waiting out a timeout for a reply that cannot come. Loopback traffic
+//! never reaches this driver and works.
 
 use super::{MacAddress, Result, NetworkError};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+const HEADER_LEN: usize = 14;
 
 pub struct EthernetFrame {
     pub dst_mac: MacAddress,
@@ -21,21 +32,85 @@ impl EthernetFrame {
             payload,
         }
     }
+
+    /// Serialize frame to wire format (without FCS)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(self.dst_mac.bytes());
+        out.extend_from_slice(self.src_mac.bytes());
+        out.extend_from_slice(&self.ethertype.to_be_bytes());
+        out.extend_from_slice(&self.payload);
+        out
+    }
+
+    /// Parse frame from wire format
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            return Err(NetworkError::InvalidPacket);
+        }
+        let mut dst = [0u8; 6];
+        let mut src = [0u8; 6];
+        dst.copy_from_slice(&data[0..6]);
+        src.copy_from_slice(&data[6..12]);
+        Ok(Self {
+            dst_mac: MacAddress::new(dst),
+            src_mac: MacAddress::new(src),
+            ethertype: u16::from_be_bytes([data[12], data[13]]),
+            payload: data[HEADER_LEN..].to_vec(),
+        })
+    }
 }
 
-pub struct EthernetDriver;
+pub struct EthernetDriver {
+    /// Accept frames for any destination MAC (set while capturing)
+    promiscuous: AtomicBool,
+}
 
 impl EthernetDriver {
-    pub fn send_frame(&self, _frame: EthernetFrame) -> Result<()> {
-        // Stub implementation - just log
-        crate::serial_print(b"[ETH] Frame sent (stub)\r\n");
+    pub const fn new() -> Self {
+        Self {
+            promiscuous: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_promiscuous(&self, enabled: bool) {
+        self.promiscuous.store(enabled, Ordering::SeqCst);
+        crate::serial_println!("[ETH] Promiscuous mode {}", if enabled { "on" } else { "off" });
+    }
+
+    pub fn is_promiscuous(&self) -> bool {
+        self.promiscuous.load(Ordering::SeqCst)
+    }
+
+    /// Whether a NIC is there to send and receive frames; always false
+    /// until a driver is written
+    pub fn has_device(&self) -> bool {
+        false
+    }
+
+    pub fn send_frame(&self, frame: EthernetFrame) -> Result<()> {
+        if !self.has_device() {
+            return Err(NetworkError::NoDevice);
+        }
+        if super::capture::is_active() {
+            super::capture::record(&frame.to_bytes());
+        }
         Ok(())
     }
 
     pub fn receive_frame(&self) -> Option<EthernetFrame> {
-        // Stub - no frames to receive
+        let frame = self.poll_device()?;
+        // Taps see everything the NIC hands us, before any filtering
+        if super::capture::is_active() {
+            super::capture::record(&frame.to_bytes());
+        }
+        Some(frame)
+    }
+
+    fn poll_device(&self) -> Option<EthernetFrame> {
+        // No NIC, so no frames to receive
         None
     }
 }
 
-pub static ETHERNET_DRIVER: EthernetDriver = EthernetDriver;
\ No newline at end of file
+pub static ETHERNET_DRIVER: EthernetDriver = EthernetDriver::new();
@@ -0,0 +1,258 @@
+//! Minimal HTTP/1.1 client
+//!
+//! Supports plain-http GET with Content-Length, chunked and
+//! read-until-close bodies. Redirects are followed a few times. Bodies
+//! are held on the kernel heap whole, so one larger than `MAX_BODY_SIZE`
+//! fails with `NetworkError::TooLarge` instead of exhausting it.
+
+use super::socket::{self, SocketDomain, SocketType};
+use super::{NetworkError, Result};
+use crate::drivers::timer;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const DEFAULT_PORT: u16 = 80;
+const IDLE_TIMEOUT_MS: u64 = 10_000;
+const MAX_REDIRECTS: usize = 5;
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+/// Largest response body accepted
+pub const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    /// Parse `http://host[:port][/path]`. The scheme may be omitted.
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = if let Some(rest) = url.strip_prefix("http://") {
+            rest
+        } else if url.contains("://") {
+            // No TLS
+            return Err(NetworkError::NotImplemented);
+        } else {
+            url
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| NetworkError::InvalidAddress)?),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(NetworkError::InvalidAddress);
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Case-insensitive header lookup
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Perform a GET request, following redirects
+pub fn get(url: &str) -> Result<Response> {
+    let mut url = Url::parse(url)?;
+    for _ in 0..MAX_REDIRECTS {
+        let response = get_once(&url)?;
+        match (response.status, response.header("Location")) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => {
+                url = if location.starts_with('/') {
+                    Url { path: location.to_string(), ..url }
+                } else {
+                    Url::parse(location)?
+                };
+            }
+            _ => return Ok(response),
+        }
+    }
+    Err(NetworkError::ConnectionFailed)
+}
+
+fn get_once(url: &Url) -> Result<Response> {
+    let ip = super::resolve_hostname(&url.host)?;
+    let fd = socket::socket(SocketDomain::AfInet, SocketType::Stream, 0)?;
+    let result = socket::connect(fd, ip, url.port).and_then(|_| exchange(fd, url));
+    let _ = socket::close_socket(fd);
+    result
+}
+
+fn exchange(fd: i32, url: &Url) -> Result<Response> {
+    let host = if url.port == DEFAULT_PORT {
+        url.host.clone()
+    } else {
+        format!("{}:{}", url.host, url.port)
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ospab-wget/0.1\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        url.path, host
+    );
+    socket::send(fd, request.as_bytes())?;
+
+    let mut reader = Reader { fd, buf: Vec::new(), eof: false };
+
+    // Status line and headers
+    let header_end = loop {
+        if let Some(pos) = find(&reader.buf, b"\r\n\r\n") {
+            break pos;
+        }
+        if reader.buf.len() > MAX_HEADER_SIZE || !reader.fill()? {
+            return Err(NetworkError::InvalidPacket);
+        }
+    };
+    let head = String::from_utf8_lossy(&reader.buf[..header_end]).into_owned();
+    reader.buf.drain(..header_end + 4);
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().ok_or(NetworkError::InvalidPacket)?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next().map_or(false, |v| v.starts_with("HTTP/1.")) {
+        return Err(NetworkError::InvalidPacket);
+    }
+    let status = parts.next()
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or(NetworkError::InvalidPacket)?;
+    let reason = parts.next().unwrap_or("").to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let mut response = Response { status, reason, headers, body: Vec::new() };
+
+    let chunked = response.header("Transfer-Encoding")
+        .map_or(false, |v| v.to_ascii_lowercase().contains("chunked"));
+    let length = response.header("Content-Length").and_then(|v| v.parse::<usize>().ok());
+
+    response.body = if status == 204 || status == 304 {
+        Vec::new()
+    } else if chunked {
+        read_chunked(&mut reader)?
+    } else if let Some(len) = length {
+        if len > MAX_BODY_SIZE {
+            return Err(NetworkError::TooLarge);
+        }
+        reader.read_exact(len)?
+    } else {
+        while reader.fill()? {
+            if reader.buf.len() > MAX_BODY_SIZE {
+                return Err(NetworkError::TooLarge);
+            }
+        }
+        core::mem::take(&mut reader.buf)
+    };
+    Ok(response)
+}
+
+fn read_chunked(reader: &mut Reader) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line = reader.read_line()?;
+        let size_str = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16).map_err(|_| NetworkError::InvalidPacket)?;
+        if size == 0 {
+            // Skip trailers up to the terminating blank line
+            while !reader.read_line()?.is_empty() {}
+            return Ok(body);
+        }
+        if size > MAX_BODY_SIZE - body.len() {
+            return Err(NetworkError::TooLarge);
+        }
+        body.extend_from_slice(&reader.read_exact(size)?);
+        if !reader.read_line()?.is_empty() {
+            return Err(NetworkError::InvalidPacket);
+        }
+    }
+}
+
+/// Buffered reader over a stream socket
+struct Reader {
+    fd: i32,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl Reader {
+    /// Read more data into the buffer. Returns false at end of stream.
+    fn fill(&mut self) -> Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0u8; 2048];
+        let deadline = timer::get_uptime_ms() + IDLE_TIMEOUT_MS;
+        loop {
+            super::poll();
+            match socket::receive(self.fd, &mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(NetworkError::Timeout) => {}
+                Err(e) => return Err(e),
+            }
+            if timer::get_uptime_ms() >= deadline {
+                return Err(NetworkError::Timeout);
+            }
+            super::idle();
+        }
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>> {
+        while self.buf.len() < len {
+            if !self.fill()? {
+                return Err(NetworkError::ConnectionFailed);
+            }
+        }
+        Ok(self.buf.drain(..len).collect())
+    }
+
+    /// Read a CRLF-terminated line (without the terminator)
+    fn read_line(&mut self) -> Result<String> {
+        loop {
+            if let Some(pos) = find(&self.buf, b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..pos]).into_owned();
+                self.buf.drain(..pos + 2);
+                return Ok(line);
+            }
+            if self.buf.len() > MAX_HEADER_SIZE {
+                return Err(NetworkError::InvalidPacket);
+            }
+            if !self.fill()? {
+                return Err(NetworkError::ConnectionFailed);
+            }
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
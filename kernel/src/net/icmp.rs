@@ -0,0 +1,114 @@
+//! ICMP Echo (ping)
+//!
+//! Answers echo requests and matches echo replies to outstanding pings.
+
+use super::{IpAddress, Result, NetworkError};
+use super::ip::{internet_checksum, IpPacket, IP_LAYER, PROTO_ICMP};
+use crate::drivers::timer;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_ECHO_REQUEST: u8 = 8;
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EchoReply {
+    pub from: IpAddress,
+    pub seq: u16,
+    /// ICMP payload size in bytes
+    pub len: usize,
+    pub ttl: u8,
+    pub rtt_ms: u64,
+}
+
+struct Received {
+    from: IpAddress,
+    id: u16,
+    seq: u16,
+    len: usize,
+    ttl: u8,
+    at_ms: u64,
+}
+
+static REPLIES: Mutex<Vec<Received>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+fn build(kind: u8, id: u16, seq: u16, data: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(HEADER_LEN + data.len());
+    msg.push(kind);
+    msg.push(0); // code
+    msg.extend_from_slice(&[0, 0]); // checksum
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&seq.to_be_bytes());
+    msg.extend_from_slice(data);
+    let checksum = internet_checksum(&msg);
+    msg[2..4].copy_from_slice(&checksum.to_be_bytes());
+    msg
+}
+
+/// Handle an incoming ICMP message
+pub fn deliver(src: IpAddress, dst: IpAddress, ttl: u8, data: &[u8]) {
+    if data.len() < HEADER_LEN || internet_checksum(data) != 0 {
+        return;
+    }
+    let id = u16::from_be_bytes([data[4], data[5]]);
+    let seq = u16::from_be_bytes([data[6], data[7]]);
+
+    match data[0] {
+        TYPE_ECHO_REQUEST => {
+            let reply = build(TYPE_ECHO_REPLY, id, seq, &data[HEADER_LEN..]);
+            let _ = IP_LAYER.send_packet(IpPacket::new(dst, src, PROTO_ICMP, reply));
+        }
+        TYPE_ECHO_REPLY => {
+            let mut replies = REPLIES.lock();
+            // Nobody is waiting on stale replies forever
+            if replies.len() >= 64 {
+                replies.remove(0);
+            }
+            replies.push(Received {
+                from: src,
+                id,
+                seq,
+                len: data.len() - HEADER_LEN,
+                ttl,
+                at_ms: timer::get_uptime_ms(),
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Send one echo request with `size` bytes of payload and wait for the reply
+pub fn echo(dst: IpAddress, seq: u16, size: usize, timeout_ms: u64) -> Result<EchoReply> {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    let request = build(TYPE_ECHO_REQUEST, id, seq, &data);
+
+    let src = super::source_address_for(dst);
+    let sent_ms = timer::get_uptime_ms();
+    IP_LAYER.send_packet(IpPacket::new(src, dst, PROTO_ICMP, request))?;
+
+    let deadline = sent_ms + timeout_ms;
+    loop {
+        super::poll();
+        {
+            let mut replies = REPLIES.lock();
+            if let Some(pos) = replies.iter().position(|r| r.id == id && r.seq == seq) {
+                let r = replies.remove(pos);
+                return Ok(EchoReply {
+                    from: r.from,
+                    seq: r.seq,
+                    len: r.len,
+                    ttl: r.ttl,
+                    rtt_ms: r.at_ms.saturating_sub(sent_ms),
+                });
+            }
+        }
+        if timer::get_uptime_ms() >= deadline {
+            return Err(NetworkError::Timeout);
+        }
+        super::idle();
+    }
+}
@@ -2,8 +2,29 @@
 //!
 //! Handles IPv4 packet processing and routing.
 
-use super::{IpAddress, Result, NetworkError};
+use super::{IpAddress, MacAddress, Result, NetworkError};
+use super::ethernet::{EthernetFrame, ETHERNET_DRIVER, ETHERTYPE_IPV4};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+use crate::drivers::timer;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+const HEADER_LEN: usize = 20;
+/// Largest payload that fits in a single IPv4 datagram
+pub const MAX_PAYLOAD: usize = u16::MAX as usize - HEADER_LEN;
+
+pub const FLAG_DONT_FRAGMENT: u8 = 0b010;
+pub const FLAG_MORE_FRAGMENTS: u8 = 0b001;
+
+/// Incomplete datagrams are dropped after this long (RFC 791 suggests 15s+)
+const REASSEMBLY_TIMEOUT_MS: u64 = 30_000;
+const MAX_REASSEMBLIES: usize = 16;
 
 #[derive(Debug)]
 pub struct IpPacket {
@@ -43,26 +64,275 @@ impl IpPacket {
         }
     }
 
+    pub fn is_fragment(&self) -> bool {
+        self.flags & FLAG_MORE_FRAGMENTS != 0 || self.fragment_offset != 0
+    }
+
+    fn header_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut h = [0u8; HEADER_LEN];
+        h[0] = (self.version << 4) | (self.ihl & 0x0F);
+        h[1] = self.tos;
+        h[2..4].copy_from_slice(&self.total_length.to_be_bytes());
+        h[4..6].copy_from_slice(&self.id.to_be_bytes());
+        let frag = ((self.flags as u16) << 13) | (self.fragment_offset & 0x1FFF);
+        h[6..8].copy_from_slice(&frag.to_be_bytes());
+        h[8] = self.ttl;
+        h[9] = self.protocol;
+        h[10..12].copy_from_slice(&self.checksum.to_be_bytes());
+        h[12..16].copy_from_slice(self.src_ip.bytes());
+        h[16..20].copy_from_slice(self.dst_ip.bytes());
+        h
+    }
+
     pub fn calculate_checksum(&mut self) {
-        // Simple checksum calculation (stub)
-        self.checksum = 0x1234;
+        self.checksum = 0;
+        self.checksum = internet_checksum(&self.header_bytes());
+    }
+
+    /// Serialize packet to wire format (checksum is recomputed)
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        self.calculate_checksum();
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(&self.header_bytes());
+        out.extend_from_slice(&self.payload);
+        out
+    }
+
+    /// Parse packet from wire format, verifying the header checksum
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN || data[0] >> 4 != 4 {
+            return Err(NetworkError::InvalidPacket);
+        }
+        let ihl = data[0] & 0x0F;
+        let header_len = ihl as usize * 4;
+        let total_length = u16::from_be_bytes([data[2], data[3]]);
+        if header_len < HEADER_LEN || (total_length as usize) < header_len || data.len() < total_length as usize {
+            return Err(NetworkError::InvalidPacket);
+        }
+        if internet_checksum(&data[..header_len]) != 0 {
+            return Err(NetworkError::InvalidPacket);
+        }
+        let frag = u16::from_be_bytes([data[6], data[7]]);
+        Ok(Self {
+            version: 4,
+            ihl,
+            tos: data[1],
+            total_length,
+            id: u16::from_be_bytes([data[4], data[5]]),
+            flags: (frag >> 13) as u8,
+            fragment_offset: frag & 0x1FFF,
+            ttl: data[8],
+            protocol: data[9],
+            checksum: u16::from_be_bytes([data[10], data[11]]),
+            src_ip: IpAddress::from_bytes([data[12], data[13], data[14], data[15]]),
+            dst_ip: IpAddress::from_bytes([data[16], data[17], data[18], data[19]]),
+            payload: data[header_len..total_length as usize].to_vec(),
+        })
     }
 }
 
-pub struct IpLayer;
+/// RFC 1071 ones-complement checksum
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Fragments of one datagram, keyed by (src, dst, protocol, id)
+struct Reassembly {
+    started_ms: u64,
+    /// Payload pieces keyed by byte offset
+    fragments: BTreeMap<usize, Vec<u8>>,
+    /// Known once the last fragment (MF clear) has arrived
+    total_len: Option<usize>,
+}
+
+impl Reassembly {
+    /// Return the whole payload if every byte has arrived
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let total = self.total_len?;
+        let mut payload = Vec::with_capacity(total);
+        for (&offset, data) in &self.fragments {
+            if offset > payload.len() {
+                return None; // hole
+            }
+            let skip = payload.len() - offset;
+            if skip < data.len() {
+                payload.extend_from_slice(&data[skip..]);
+            }
+        }
+        if payload.len() == total { Some(payload) } else { None }
+    }
+}
+
+type ReassemblyKey = (IpAddress, IpAddress, u8, u16);
+
+pub struct IpLayer {
+    /// Packets addressed to ourselves, picked up by the next receive.
+    /// Queued rather than delivered inline so protocol handlers can send
+    /// replies while holding their own locks.
+    loopback: Mutex<VecDeque<IpPacket>>,
+    reassembly: Mutex<BTreeMap<ReassemblyKey, Reassembly>>,
+    next_id: AtomicU16,
+}
 
 impl IpLayer {
-    pub fn send_packet(&self, packet: IpPacket) -> Result<()> {
-        // Route packet to appropriate interface
-        crate::serial_print(b"[IP] Packet sent (stub)\r\n");
+    pub const fn new() -> Self {
+        Self {
+            loopback: Mutex::new(VecDeque::new()),
+            reassembly: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU16::new(1),
+        }
+    }
+
+    pub fn send_packet(&self, mut packet: IpPacket) -> Result<()> {
+        if packet.payload.len() > MAX_PAYLOAD {
+            return Err(NetworkError::BufferTooSmall);
+        }
+        if packet.id == 0 {
+            packet.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Traffic for ourselves never touches the wire
+        if packet.dst_ip.is_loopback() || self.is_local(packet.dst_ip) {
+            if super::capture::is_active() {
+                // Shown like Linux lo: ethernet framing with zero MACs
+                let frame = EthernetFrame::new(MacAddress::new([0; 6]), MacAddress::new([0; 6]),
+                                               ETHERTYPE_IPV4, packet.to_bytes());
+                super::capture::record(&frame.to_bytes());
+            }
+            self.loopback.lock().push_back(packet);
+            return Ok(());
+        }
+
+        let iface_name = self.route_packet(packet.dst_ip)?;
+        let iface = super::get_interface(&iface_name).ok_or(NetworkError::NoDevice)?;
+        let mtu = iface.mtu as usize;
+
+        if HEADER_LEN + packet.payload.len() <= mtu {
+            return self.transmit(&iface, packet);
+        }
+        if packet.flags & FLAG_DONT_FRAGMENT != 0 {
+            return Err(NetworkError::BufferTooSmall);
+        }
+
+        // Fragment offsets are in 8-byte units
+        let chunk = (mtu - HEADER_LEN) & !7;
+        let payload = core::mem::take(&mut packet.payload);
+        let mut offset = 0;
+        while offset < payload.len() {
+            let end = core::cmp::min(offset + chunk, payload.len());
+            let mut fragment = IpPacket::new(packet.src_ip, packet.dst_ip, packet.protocol,
+                                             payload[offset..end].to_vec());
+            fragment.id = packet.id;
+            fragment.ttl = packet.ttl;
+            fragment.tos = packet.tos;
+            fragment.fragment_offset = (offset / 8) as u16;
+            if end < payload.len() {
+                fragment.flags |= FLAG_MORE_FRAGMENTS;
+            }
+            self.transmit(&iface, fragment)?;
+            offset = end;
+        }
         Ok(())
     }
 
+    fn transmit(&self, iface: &super::NetworkInterface, mut packet: IpPacket) -> Result<()> {
+        let bytes = packet.to_bytes();
+        // No ARP yet: broadcast and let the gateway pick it up
+        let frame = EthernetFrame::new(
+            MacAddress::new([0xFF; 6]),
+            iface.mac,
+            ETHERTYPE_IPV4,
+            bytes,
+        );
+        ETHERNET_DRIVER.send_frame(frame)
+    }
+
     pub fn receive_packet(&self) -> Option<IpPacket> {
-        // Stub - no packets to receive
+        if let Some(packet) = self.loopback.lock().pop_front() {
+            return Some(packet);
+        }
+        while let Some(frame) = ETHERNET_DRIVER.receive_frame() {
+            if frame.ethertype != ETHERTYPE_IPV4 {
+                continue;
+            }
+            if let Ok(packet) = IpPacket::parse(&frame.payload) {
+                return Some(packet);
+            }
+        }
         None
     }
 
+    /// Hand a received packet to the matching transport protocol
+    pub fn deliver(&self, packet: IpPacket) {
+        let packet = if packet.is_fragment() {
+            match self.reassemble(packet) {
+                Some(p) => p,
+                None => return,
+            }
+        } else {
+            packet
+        };
+
+        match packet.protocol {
+            PROTO_ICMP => super::icmp::deliver(packet.src_ip, packet.dst_ip, packet.ttl, &packet.payload),
+            PROTO_UDP => super::udp::deliver(packet.src_ip, &packet.payload),
+            PROTO_TCP => super::tcp::deliver(packet.src_ip, packet.dst_ip, &packet.payload),
+            _ => {}
+        }
+    }
+
+    /// Store a fragment; returns the full datagram once all pieces are in
+    fn reassemble(&self, fragment: IpPacket) -> Option<IpPacket> {
+        let key = (fragment.src_ip, fragment.dst_ip, fragment.protocol, fragment.id);
+        let offset = fragment.fragment_offset as usize * 8;
+        if offset + fragment.payload.len() > MAX_PAYLOAD {
+            return None;
+        }
+
+        let mut table = self.reassembly.lock();
+        if !table.contains_key(&key) && table.len() >= MAX_REASSEMBLIES {
+            // Make room by dropping the oldest incomplete datagram
+            let oldest = table.iter().min_by_key(|(_, r)| r.started_ms).map(|(k, _)| *k)?;
+            table.remove(&oldest);
+        }
+        let entry = table.entry(key).or_insert_with(|| Reassembly {
+            started_ms: timer::get_uptime_ms(),
+            fragments: BTreeMap::new(),
+            total_len: None,
+        });
+        if fragment.flags & FLAG_MORE_FRAGMENTS == 0 {
+            entry.total_len = Some(offset + fragment.payload.len());
+        }
+        entry.fragments.insert(offset, fragment.payload);
+
+        let payload = entry.assemble()?;
+        table.remove(&key);
+
+        let mut packet = IpPacket::new(key.0, key.1, key.2, payload);
+        packet.id = key.3;
+        packet.ttl = fragment.ttl;
+        packet.tos = fragment.tos;
+        Some(packet)
+    }
+
+    /// Drop reassembly state for datagrams that never completed
+    pub fn expire_fragments(&self) {
+        let now = timer::get_uptime_ms();
+        self.reassembly.lock()
+            .retain(|_, r| now.saturating_sub(r.started_ms) < REASSEMBLY_TIMEOUT_MS);
+    }
+
     pub fn route_packet(&self, dst: IpAddress) -> Result<String> {
         // Simple routing logic
         if dst.0[0] == 127 {
@@ -71,6 +341,10 @@ impl IpLayer {
             Ok("eth0".to_string())
         }
     }
+
+    pub fn is_local(&self, addr: IpAddress) -> bool {
+        super::list_interfaces().iter().any(|iface| iface.ip == addr)
+    }
 }
 
-pub static IP_LAYER: IpLayer = IpLayer;
\ No newline at end of file
+pub static IP_LAYER: IpLayer = IpLayer::new();
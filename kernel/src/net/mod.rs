@@ -4,14 +4,17 @@
 //! Currently implements basic stub networking for demonstration.
 
 pub mod ethernet;
+pub mod capture;
 pub mod ip;
+pub mod icmp;
 pub mod tcp;
 pub mod udp;
 pub mod socket;
 pub mod dns;
+pub mod http;
 
 use alloc::collections::BTreeMap;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use spin::Mutex;
 
@@ -23,6 +26,31 @@ pub enum NetworkError {
     Timeout,
     BufferTooSmall,
     NotImplemented,
+    NameNotFound,
+    InvalidPacket,
+    /// More data than the kernel will buffer
+    TooLarge,
+}
+
+impl NetworkError {
+    pub fn errno(self) -> u64 {
+        use crate::syscall::abi;
+        match self {
+            NetworkError::NoDevice => abi::ENODEV,
+            NetworkError::InvalidAddress | NetworkError::BufferTooSmall => abi::EINVAL,
+            NetworkError::ConnectionFailed => abi::ECONNREFUSED,
+            NetworkError::Timeout => abi::ETIMEDOUT,
+            NetworkError::NotImplemented => abi::ENOSYS,
+            NetworkError::NameNotFound => abi::ENOENT,
+            NetworkError::InvalidPacket => abi::EIO,
+            NetworkError::TooLarge => abi::ENOMEM,
+        }
+    }
+
+    /// Syscall return value for this error: the negated errno
+    pub fn to_syscall(self) -> u64 {
+        self.errno().wrapping_neg()
+    }
 }
 
 pub type Result<T> = core::result::Result<T, NetworkError>;
@@ -40,7 +68,14 @@ impl MacAddress {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl core::fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", b[0], b[1], b[2], b[3], b[4], b[5])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct IpAddress([u8; 4]);
 
 impl IpAddress {
@@ -55,6 +90,29 @@ impl IpAddress {
     pub fn from_bytes(bytes: [u8; 4]) -> Self {
         Self(bytes)
     }
+
+    /// Parse dotted-quad notation ("10.0.2.3")
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut bytes = [0u8; 4];
+        let mut parts = s.trim().split('.');
+        for byte in bytes.iter_mut() {
+            *byte = parts.next()?.parse::<u8>().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self(bytes))
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.0[0] == 127
+    }
+}
+
+impl core::fmt::Display for IpAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -107,7 +165,8 @@ pub fn init() {
     };
     stack.add_interface(lo);
 
-    // Create a dummy ethernet interface
+    // Create a dummy ethernet interface; it has no NIC driver behind it
+    // (see `ethernet`)
     let eth0 = NetworkInterface {
         name: "eth0".to_string(),
         mac: MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
@@ -117,9 +176,14 @@ pub fn init() {
         mtu: 1500,
     };
     stack.add_interface(eth0);
+    drop(stack);
+
+    dns::init();
+    tcp::start_timers();
 
     crate::serial_print(b"[NET] Network stack initialized\r\n");
     crate::serial_print(b"[NET] Interfaces: lo (127.0.0.1), eth0 (192.168.1.100)\r\n");
+    crate::ipc::bus::publish(crate::ipc::message::ServiceEvent::Ready("network"));
 }
 
 pub fn get_interface(name: &str) -> Option<NetworkInterface> {
@@ -130,18 +194,50 @@ pub fn list_interfaces() -> Vec<NetworkInterface> {
     NETWORK_STACK.lock().list_interfaces().into_iter().cloned().collect()
 }
 
-// Stub implementations for networking functions
+/// Pick the local address used as the source when talking to `dst`
+pub fn source_address_for(dst: IpAddress) -> IpAddress {
+    ip::IP_LAYER.route_packet(dst)
+        .ok()
+        .and_then(|name| get_interface(&name))
+        .map(|iface| iface.ip)
+        .unwrap_or(IpAddress::new(127, 0, 0, 1))
+}
+
+/// Drain received frames from the NIC and hand them up the stack
+pub fn poll() {
+    while let Some(packet) = ip::IP_LAYER.receive_packet() {
+        ip::IP_LAYER.deliver(packet);
+    }
+    ip::IP_LAYER.expire_fragments();
+}
+
+/// Wait for the next interrupt while polling for network traffic.
+/// Also usable from syscall context, where interrupts are masked on entry.
+pub fn idle() {
+    use x86_64::instructions::interrupts;
+    if interrupts::are_enabled() {
+        x86_64::instructions::hlt();
+    } else {
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+    }
+}
+
+/// Send a single 56-byte echo request, returning the round-trip time in ms
 pub fn ping(address: IpAddress, timeout_ms: u32) -> Result<u32> {
-    // Simulate ping - always succeed for demo
-    crate::drivers::timer::sleep_ms(timeout_ms / 2);
-    Ok(timeout_ms / 2)
+    icmp::echo(address, 1, 56, timeout_ms as u64).map(|reply| reply.rtt_ms as u32)
 }
 
-pub fn resolve_hostname(hostname: &str) -> Result<IpAddress> {
-    // Simple stub DNS resolution
-    match hostname {
-        "localhost" => Ok(IpAddress::new(127, 0, 0, 1)),
-        "google.com" => Ok(IpAddress::new(8, 8, 8, 8)),
-        _ => Err(NetworkError::InvalidAddress),
+/// Fail with `NoDevice` if reaching `dst` takes the NIC, which has no
+/// driver yet; callers that wait for an answer check this first
+pub fn check_route(dst: IpAddress) -> Result<()> {
+    if dst.is_loopback() || ip::IP_LAYER.is_local(dst) || ethernet::ETHERNET_DRIVER.has_device() {
+        Ok(())
+    } else {
+        Err(NetworkError::NoDevice)
     }
+}
+
+pub fn resolve_hostname(hostname: &str) -> Result<IpAddress> {
+    dns::resolve(hostname)
 }
\ No newline at end of file
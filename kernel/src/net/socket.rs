@@ -3,13 +3,13 @@
 //! Provides BSD socket API compatibility.
 
 use super::{IpAddress, Result, NetworkError};
-use alloc::vec::Vec;
+use crate::fs::vfs::{FileHandle, FsError};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SocketType {
     Stream,  // TCP
     Dgram,   // UDP
-    Raw,     // Raw IP
+    Raw,     // Raw ethernet frames (packet capture)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -23,55 +23,85 @@ pub struct Socket {
     pub protocol: i32,
     pub bound_addr: Option<(IpAddress, u16)>,
     pub connected_addr: Option<(IpAddress, u16)>,
+    /// Established TCP connection for stream sockets
+    tcp_conn: Option<super::tcp::ConnKey>,
+    /// Capture tap feeding raw sockets
+    capture_tap: Option<u32>,
 }
 
 impl Socket {
     pub fn new(domain: SocketDomain, socktype: SocketType, protocol: i32) -> Result<Self> {
+        // Raw sockets see every frame from the moment they exist
+        let capture_tap = match socktype {
+            SocketType::Raw => Some(super::capture::open()),
+            _ => None,
+        };
         Ok(Self {
             domain,
             socktype,
             protocol,
             bound_addr: None,
             connected_addr: None,
+            tcp_conn: None,
+            capture_tap,
         })
     }
 
     pub fn bind(&mut self, addr: IpAddress, port: u16) -> Result<()> {
+        if self.bound_addr.is_some() {
+            return Err(NetworkError::InvalidAddress);
+        }
+        let port = match self.socktype {
+            // Datagrams are only queued for bound ports
+            SocketType::Dgram if port == 0 => super::udp::bind_ephemeral()?,
+            SocketType::Dgram => {
+                super::udp::bind(port)?;
+                port
+            }
+            _ => port,
+        };
         self.bound_addr = Some((addr, port));
-        crate::serial_print(b"[SOCKET] Socket bound (stub)\r\n");
         Ok(())
     }
 
     pub fn connect(&mut self, addr: IpAddress, port: u16) -> Result<()> {
-        self.connected_addr = Some((addr, port));
-
         match self.socktype {
             SocketType::Stream => {
                 // TCP connect
-                let local_addr = self.bound_addr.map(|(a, _)| a).unwrap_or(IpAddress::new(127, 0, 0, 1));
+                let local_addr = self.bound_addr.map(|(a, _)| a).unwrap_or(super::source_address_for(addr));
                 let local_port = self.bound_addr.map(|(_, p)| p).unwrap_or(0);
-                super::tcp::connect(local_addr, local_port, addr, port)?;
+                let key = super::tcp::connect(local_addr, local_port, addr, port)?;
+                self.bound_addr = Some((key.0, key.1));
+                self.tcp_conn = Some(key);
             }
             SocketType::Dgram => {
-                // UDP is connectionless, just store address
+                // UDP is connectionless: pick a local port so replies can be received
+                if self.bound_addr.is_none() {
+                    self.bind(super::source_address_for(addr), 0)?;
+                }
             }
             _ => return Err(NetworkError::NotImplemented),
         }
 
+        self.connected_addr = Some((addr, port));
         Ok(())
     }
 
     pub fn send(&self, data: &[u8]) -> Result<usize> {
+        if self.socktype == SocketType::Raw {
+            // Data is a complete ethernet frame
+            let frame = super::ethernet::EthernetFrame::parse(data)?;
+            super::ethernet::ETHERNET_DRIVER.send_frame(frame)?;
+            return Ok(data.len());
+        }
         if let Some((addr, port)) = self.connected_addr {
             match self.socktype {
                 SocketType::Stream => {
-                    let local_addr = self.bound_addr.map(|(a, _)| a).unwrap_or(IpAddress::new(127, 0, 0, 1));
-                    let local_port = self.bound_addr.map(|(_, p)| p).unwrap_or(0);
-                    super::tcp::send((local_addr, local_port, addr, port), data)
+                    let key = self.tcp_conn.ok_or(NetworkError::ConnectionFailed)?;
+                    super::tcp::send(key, data)
                 }
                 SocketType::Dgram => {
-                    let src_addr = self.bound_addr.map(|(a, _)| a).unwrap_or(IpAddress::new(127, 0, 0, 1));
-                    let src_port = self.bound_addr.map(|(_, p)| p).unwrap_or(0);
+                    let (src_addr, src_port) = self.bound_addr.ok_or(NetworkError::ConnectionFailed)?;
                     super::udp::send_to(src_addr, src_port, addr, port, data)?;
                     Ok(data.len())
                 }
@@ -85,31 +115,37 @@ impl Socket {
     pub fn receive(&self, buffer: &mut [u8]) -> Result<usize> {
         match self.socktype {
             SocketType::Stream => {
-                if let Some((addr, port)) = self.connected_addr {
-                    let local_addr = self.bound_addr.map(|(a, _)| a).unwrap_or(IpAddress::new(127, 0, 0, 1));
-                    let local_port = self.bound_addr.map(|(_, p)| p).unwrap_or(0);
-                    super::tcp::receive((local_addr, local_port, addr, port), buffer)
-                } else {
-                    Err(NetworkError::ConnectionFailed)
-                }
+                let key = self.tcp_conn.ok_or(NetworkError::ConnectionFailed)?;
+                super::tcp::receive(key, buffer)
             }
             SocketType::Dgram => {
-                match super::udp::receive_from(buffer) {
+                let (_, port) = self.bound_addr.ok_or(NetworkError::ConnectionFailed)?;
+                match super::udp::receive_on(port, buffer) {
                     Ok((_, _, len)) => Ok(len),
                     Err(e) => Err(e),
                 }
             }
-            _ => Err(NetworkError::NotImplemented),
+            SocketType::Raw => {
+                let tap = self.capture_tap.ok_or(NetworkError::NoDevice)?;
+                let frame = super::capture::next(tap).ok_or(NetworkError::Timeout)?;
+                // Like SOCK_RAW, an oversized frame is truncated
+                let len = core::cmp::min(frame.data.len(), buffer.len());
+                buffer[..len].copy_from_slice(&frame.data[..len]);
+                Ok(len)
+            }
         }
     }
 
     pub fn close(self) -> Result<()> {
-        if let (Some((local_addr, local_port)), Some((remote_addr, remote_port))) = (self.bound_addr, self.connected_addr) {
-            if self.socktype == SocketType::Stream {
-                super::tcp::close((local_addr, local_port, remote_addr, remote_port))?;
-            }
+        if let Some(key) = self.tcp_conn {
+            super::tcp::close(key)?;
+        }
+        if let (SocketType::Dgram, Some((_, port))) = (self.socktype, self.bound_addr) {
+            super::udp::unbind(port);
+        }
+        if let Some(tap) = self.capture_tap {
+            super::capture::close(tap);
         }
-        crate::serial_print(b"[SOCKET] Socket closed (stub)\r\n");
         Ok(())
     }
 }
@@ -172,4 +208,46 @@ pub fn close_socket(fd: i32) -> Result<()> {
     } else {
         Err(NetworkError::InvalidAddress)
     }
-}
\ No newline at end of file
+}
+/// Blocking receive: waits until data arrives or the peer closes
+pub fn receive_blocking(fd: i32, buffer: &mut [u8]) -> Result<usize> {
+    loop {
+        super::poll();
+        match receive(fd, buffer) {
+            Err(NetworkError::Timeout) => super::idle(),
+            other => return other,
+        }
+    }
+}
+
+/// File descriptor wrapper so sockets can live in a task's fd table.
+/// Dropping the handle closes the socket.
+pub struct SocketHandle {
+    id: i32,
+}
+
+impl SocketHandle {
+    pub fn new(id: i32) -> Self {
+        Self { id }
+    }
+}
+
+impl FileHandle for SocketHandle {
+    fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, FsError> {
+        receive_blocking(self.id, buf).map_err(|_| FsError::Io)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, FsError> {
+        send(self.id, buf).map_err(|_| FsError::Io)
+    }
+
+    fn socket_id(&self) -> Option<i32> {
+        Some(self.id)
+    }
+}
+
+impl Drop for SocketHandle {
+    fn drop(&mut self) {
+        let _ = close_socket(self.id);
+    }
+}
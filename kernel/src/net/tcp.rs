@@ -1,12 +1,32 @@
 //! TCP Protocol Implementation
 //!
 //! Provides reliable, connection-oriented communication.
+//! Client side only: active open, in-order receive (out-of-order segments
+//! are dropped and re-ACKed), retransmission of unacknowledged segments.
 
 use super::{IpAddress, Result, NetworkError};
-use alloc::collections::BTreeMap;
+use super::ip::{internet_checksum, IpPacket, IP_LAYER, PROTO_TCP};
+use crate::drivers::timer;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
 use spin::Mutex;
 
+const HEADER_LEN: usize = 20;
+const MSS: usize = 1460;
+const RECV_BUFFER_SIZE: usize = 65535;
+const RETRANSMIT_TIMEOUT_MS: u64 = 1000;
+/// How often ktimerd checks the retransmission timers
+const TIMER_INTERVAL_MS: u64 = 100;
+const MAX_RETRIES: u32 = 5;
+const CONNECT_TIMEOUT_MS: u64 = 5000;
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+pub const FLAG_FIN: u8 = 0x01;
+pub const FLAG_SYN: u8 = 0x02;
+pub const FLAG_RST: u8 = 0x04;
+pub const FLAG_PSH: u8 = 0x08;
+pub const FLAG_ACK: u8 = 0x10;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TcpState {
     Closed,
@@ -22,6 +42,96 @@ pub enum TcpState {
     TimeWait,
 }
 
+/// (local addr, local port, remote addr, remote port)
+pub type ConnKey = (IpAddress, u16, IpAddress, u16);
+
+#[derive(Debug)]
+pub struct TcpSegment {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub payload: Vec<u8>,
+}
+
+impl TcpSegment {
+    /// Serialize with the checksum computed over the IPv4 pseudo-header
+    pub fn to_bytes(&self, src: IpAddress, dst: IpAddress) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(&self.src_port.to_be_bytes());
+        out.extend_from_slice(&self.dst_port.to_be_bytes());
+        out.extend_from_slice(&self.seq.to_be_bytes());
+        out.extend_from_slice(&self.ack.to_be_bytes());
+        out.push(((HEADER_LEN / 4) as u8) << 4);
+        out.push(self.flags);
+        out.extend_from_slice(&self.window.to_be_bytes());
+        out.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+        out.extend_from_slice(&self.payload);
+
+        let checksum = internet_checksum(&pseudo_header(src, dst, &out));
+        out[16..18].copy_from_slice(&checksum.to_be_bytes());
+        out
+    }
+
+    pub fn parse(src: IpAddress, dst: IpAddress, data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            return Err(NetworkError::InvalidPacket);
+        }
+        let offset = (data[12] >> 4) as usize * 4;
+        if offset < HEADER_LEN || offset > data.len() {
+            return Err(NetworkError::InvalidPacket);
+        }
+        if internet_checksum(&pseudo_header(src, dst, data)) != 0 {
+            return Err(NetworkError::InvalidPacket);
+        }
+        Ok(Self {
+            src_port: u16::from_be_bytes([data[0], data[1]]),
+            dst_port: u16::from_be_bytes([data[2], data[3]]),
+            seq: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ack: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            flags: data[13],
+            window: u16::from_be_bytes([data[14], data[15]]),
+            payload: data[offset..].to_vec(),
+        })
+    }
+
+    /// Sequence space consumed by this segment (SYN and FIN count as one)
+    fn seq_len(&self) -> u32 {
+        let mut len = self.payload.len() as u32;
+        if self.flags & FLAG_SYN != 0 { len += 1; }
+        if self.flags & FLAG_FIN != 0 { len += 1; }
+        len
+    }
+}
+
+fn pseudo_header(src: IpAddress, dst: IpAddress, segment: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12 + segment.len());
+    buf.extend_from_slice(src.bytes());
+    buf.extend_from_slice(dst.bytes());
+    buf.push(0);
+    buf.push(PROTO_TCP);
+    buf.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    buf.extend_from_slice(segment);
+    buf
+}
+
+/// a < b in sequence space
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Segment waiting for acknowledgement
+#[derive(Debug)]
+struct Unacked {
+    seq: u32,
+    flags: u8,
+    payload: Vec<u8>,
+    sent_ms: u64,
+    retries: u32,
+}
+
 #[derive(Debug)]
 pub struct TcpConnection {
     pub local_addr: IpAddress,
@@ -29,82 +139,392 @@ pub struct TcpConnection {
     pub remote_addr: IpAddress,
     pub remote_port: u16,
     pub state: TcpState,
+    /// Next sequence number to send (SND.NXT)
     pub send_seq: u32,
+    /// Oldest unacknowledged sequence number (SND.UNA)
+    pub send_una: u32,
+    /// Next sequence number expected from the peer (RCV.NXT)
     pub recv_seq: u32,
+    recv_buffer: VecDeque<u8>,
+    retransmit: VecDeque<Unacked>,
+    /// Set when the connection was reset or timed out
+    error: Option<NetworkError>,
+    /// Owner called close(); drop once the shutdown completes
+    released: bool,
+}
+
+impl TcpConnection {
+    fn window(&self) -> u16 {
+        (RECV_BUFFER_SIZE - self.recv_buffer.len()).min(u16::MAX as usize) as u16
+    }
+
+    fn transmit(&self, seq: u32, flags: u8, payload: &[u8]) {
+        let segment = TcpSegment {
+            src_port: self.local_port,
+            dst_port: self.remote_port,
+            seq,
+            ack: if flags & FLAG_ACK != 0 { self.recv_seq } else { 0 },
+            flags,
+            window: self.window(),
+            payload: payload.to_vec(),
+        };
+        let bytes = segment.to_bytes(self.local_addr, self.remote_addr);
+        let _ = IP_LAYER.send_packet(IpPacket::new(self.local_addr, self.remote_addr, PROTO_TCP, bytes));
+    }
+
+    /// Send a segment that occupies sequence space and track it for retransmission
+    fn send_tracked(&mut self, flags: u8, payload: Vec<u8>) {
+        let seq = self.send_seq;
+        self.transmit(seq, flags, &payload);
+        let mut len = payload.len() as u32;
+        if flags & (FLAG_SYN | FLAG_FIN) != 0 {
+            len += 1;
+        }
+        self.send_seq = self.send_seq.wrapping_add(len);
+        self.retransmit.push_back(Unacked {
+            seq,
+            flags,
+            payload,
+            sent_ms: timer::get_uptime_ms(),
+            retries: 0,
+        });
+    }
+
+    fn send_ack(&self) {
+        self.transmit(self.send_seq, FLAG_ACK, &[]);
+    }
+
+    fn handle_ack(&mut self, ack: u32) {
+        if seq_lt(self.send_una, ack) && !seq_lt(self.send_seq, ack) {
+            self.send_una = ack;
+        }
+        while let Some(front) = self.retransmit.front() {
+            let mut end = front.seq.wrapping_add(front.payload.len() as u32);
+            if front.flags & (FLAG_SYN | FLAG_FIN) != 0 {
+                end = end.wrapping_add(1);
+            }
+            if seq_lt(self.send_una, end) {
+                break;
+            }
+            self.retransmit.pop_front();
+        }
+    }
+
+    /// Everything we sent, including our FIN, has been acknowledged
+    fn all_acked(&self) -> bool {
+        self.send_una == self.send_seq
+    }
+
+    fn handle_segment(&mut self, seg: TcpSegment) {
+        if seg.flags & FLAG_RST != 0 {
+            self.state = TcpState::Closed;
+            self.error = Some(NetworkError::ConnectionFailed);
+            return;
+        }
+
+        if self.state == TcpState::SynSent {
+            if seg.flags & (FLAG_SYN | FLAG_ACK) == (FLAG_SYN | FLAG_ACK) && seg.ack == self.send_seq {
+                self.recv_seq = seg.seq.wrapping_add(1);
+                self.handle_ack(seg.ack);
+                self.state = TcpState::Established;
+                self.send_ack();
+            }
+            return;
+        }
+
+        if seg.flags & FLAG_ACK != 0 {
+            self.handle_ack(seg.ack);
+        }
+
+        if seg.seq_len() == 0 {
+            self.advance_close_state(false);
+            return;
+        }
+
+        // Only accept the next in-order segment; anything else gets a duplicate ACK
+        if seg.seq != self.recv_seq {
+            self.send_ack();
+            return;
+        }
+
+        let room = RECV_BUFFER_SIZE - self.recv_buffer.len();
+        let take = seg.payload.len().min(room);
+        self.recv_buffer.extend(&seg.payload[..take]);
+        self.recv_seq = self.recv_seq.wrapping_add(take as u32);
+
+        let fin = seg.flags & FLAG_FIN != 0 && take == seg.payload.len();
+        if fin {
+            self.recv_seq = self.recv_seq.wrapping_add(1);
+        }
+        self.advance_close_state(fin);
+        self.send_ack();
+    }
+
+    fn advance_close_state(&mut self, fin_received: bool) {
+        self.state = match (self.state, fin_received) {
+            (TcpState::Established, true) => TcpState::CloseWait,
+            (TcpState::FinWait1, true) if self.all_acked() => TcpState::TimeWait,
+            (TcpState::FinWait1, true) => TcpState::Closing,
+            (TcpState::FinWait1, false) if self.all_acked() => TcpState::FinWait2,
+            (TcpState::FinWait2, true) => TcpState::TimeWait,
+            (TcpState::Closing, _) if self.all_acked() => TcpState::TimeWait,
+            (TcpState::LastAck, _) if self.all_acked() => TcpState::Closed,
+            (state, _) => state,
+        };
+    }
+
+    /// Resend segments whose retransmission timer expired
+    fn tick(&mut self, now: u64) {
+        let mut resend = Vec::new();
+        for entry in self.retransmit.iter_mut() {
+            if now.saturating_sub(entry.sent_ms) < RETRANSMIT_TIMEOUT_MS << entry.retries {
+                continue;
+            }
+            if entry.retries >= MAX_RETRIES {
+                self.state = TcpState::Closed;
+                self.error = Some(NetworkError::Timeout);
+                return;
+            }
+            entry.retries += 1;
+            entry.sent_ms = now;
+            resend.push((entry.seq, entry.flags, entry.payload.clone()));
+        }
+        for (seq, flags, payload) in resend {
+            self.transmit(seq, flags, &payload);
+        }
+    }
 }
 
 pub struct TcpSocket {
-    connections: BTreeMap<(IpAddress, u16, IpAddress, u16), TcpConnection>,
+    connections: BTreeMap<ConnKey, TcpConnection>,
+    next_port: u16,
+    isn_counter: u32,
 }
 
 impl TcpSocket {
     pub const fn new() -> Self {
         Self {
             connections: BTreeMap::new(),
+            next_port: EPHEMERAL_PORT_START,
+            isn_counter: 0,
         }
     }
 
+    fn alloc_port(&mut self, local_addr: IpAddress, remote_addr: IpAddress, remote_port: u16) -> u16 {
+        loop {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_PORT_START } else { port + 1 };
+            if !self.connections.contains_key(&(local_addr, port, remote_addr, remote_port)) {
+                return port;
+            }
+        }
+    }
+
+    /// Start an active open. Returns the connection key; the handshake
+    /// completes asynchronously as segments are delivered.
     pub fn connect(&mut self, local_addr: IpAddress, local_port: u16,
-                   remote_addr: IpAddress, remote_port: u16) -> Result<()> {
-        let conn = TcpConnection {
+                   remote_addr: IpAddress, remote_port: u16) -> Result<ConnKey> {
+        let local_port = if local_port == 0 {
+            self.alloc_port(local_addr, remote_addr, remote_port)
+        } else {
+            local_port
+        };
+        let key = (local_addr, local_port, remote_addr, remote_port);
+        if self.connections.contains_key(&key) {
+            return Err(NetworkError::InvalidAddress);
+        }
+
+        self.isn_counter = self.isn_counter.wrapping_add(64000);
+        let iss = (timer::get_jiffies() as u32).wrapping_mul(2500).wrapping_add(self.isn_counter);
+
+        let mut conn = TcpConnection {
             local_addr,
             local_port,
             remote_addr,
             remote_port,
             state: TcpState::SynSent,
-            send_seq: 1000,
+            send_seq: iss,
+            send_una: iss,
             recv_seq: 0,
+            recv_buffer: VecDeque::new(),
+            retransmit: VecDeque::new(),
+            error: None,
+            released: false,
         };
+        conn.send_tracked(FLAG_SYN, Vec::new());
+        self.connections.insert(key, conn);
+        Ok(key)
+    }
 
-        self.connections.insert((local_addr, local_port, remote_addr, remote_port), conn);
-
-        // Send SYN packet (stub)
-        crate::serial_print(b"[TCP] SYN sent (stub)\r\n");
+    pub fn state(&self, addr: ConnKey) -> Result<TcpState> {
+        let conn = self.connections.get(&addr).ok_or(NetworkError::ConnectionFailed)?;
+        match conn.error {
+            Some(e) => Err(e),
+            None => Ok(conn.state),
+        }
+    }
 
-        // Simulate connection establishment
-        if let Some(conn) = self.connections.get_mut(&(local_addr, local_port, remote_addr, remote_port)) {
-            conn.state = TcpState::Established;
+    pub fn send(&mut self, addr: ConnKey, data: &[u8]) -> Result<usize> {
+        let conn = self.connections.get_mut(&addr).ok_or(NetworkError::ConnectionFailed)?;
+        if let Some(e) = conn.error {
+            return Err(e);
+        }
+        if !matches!(conn.state, TcpState::Established | TcpState::CloseWait) {
+            return Err(NetworkError::ConnectionFailed);
         }
+        for chunk in data.chunks(MSS) {
+            conn.send_tracked(FLAG_ACK | FLAG_PSH, chunk.to_vec());
+        }
+        Ok(data.len())
+    }
 
-        Ok(())
+    /// Non-blocking read. Returns Ok(0) once the peer has closed and all
+    /// data has been consumed, Timeout if nothing is available yet.
+    pub fn receive(&mut self, addr: ConnKey, buffer: &mut [u8]) -> Result<usize> {
+        let conn = self.connections.get_mut(&addr).ok_or(NetworkError::ConnectionFailed)?;
+        if !conn.recv_buffer.is_empty() {
+            let len = buffer.len().min(conn.recv_buffer.len());
+            for (dst, src) in buffer.iter_mut().zip(conn.recv_buffer.drain(..len)) {
+                *dst = src;
+            }
+            if len > 0 {
+                // Window opened up again
+                conn.send_ack();
+            }
+            return Ok(len);
+        }
+        if let Some(e) = conn.error {
+            return Err(e);
+        }
+        match conn.state {
+            TcpState::CloseWait | TcpState::Closing | TcpState::LastAck
+            | TcpState::TimeWait | TcpState::Closed => Ok(0),
+            _ => Err(NetworkError::Timeout),
+        }
     }
 
-    pub fn send(&mut self, _addr: (IpAddress, u16, IpAddress, u16), _data: &[u8]) -> Result<usize> {
-        // Stub implementation
-        crate::serial_print(b"[TCP] Data sent (stub)\r\n");
-        Ok(_data.len())
+    pub fn close(&mut self, addr: ConnKey) -> Result<()> {
+        let conn = match self.connections.get_mut(&addr) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        conn.released = true;
+        match conn.state {
+            TcpState::Established | TcpState::SynReceived => {
+                conn.send_tracked(FLAG_FIN | FLAG_ACK, Vec::new());
+                conn.state = TcpState::FinWait1;
+            }
+            TcpState::CloseWait => {
+                conn.send_tracked(FLAG_FIN | FLAG_ACK, Vec::new());
+                conn.state = TcpState::LastAck;
+            }
+            _ => {
+                self.connections.remove(&addr);
+            }
+        }
+        Ok(())
     }
 
-    pub fn receive(&mut self, _addr: (IpAddress, u16, IpAddress, u16), _buffer: &mut [u8]) -> Result<usize> {
-        // Stub - no data to receive
-        Err(NetworkError::Timeout)
+    /// Handle an incoming segment
+    pub fn deliver(&mut self, src: IpAddress, dst: IpAddress, data: &[u8]) {
+        let seg = match TcpSegment::parse(src, dst, data) {
+            Ok(seg) => seg,
+            Err(_) => return,
+        };
+        let key = (dst, seg.dst_port, src, seg.src_port);
+        match self.connections.get_mut(&key) {
+            Some(conn) => {
+                conn.handle_segment(seg);
+            }
+            None if seg.flags & FLAG_RST == 0 => {
+                // Nobody listening: answer with a reset
+                let (seq, ack, flags) = if seg.flags & FLAG_ACK != 0 {
+                    (seg.ack, 0, FLAG_RST)
+                } else {
+                    (0, seg.seq.wrapping_add(seg.seq_len()), FLAG_RST | FLAG_ACK)
+                };
+                let rst = TcpSegment {
+                    src_port: seg.dst_port,
+                    dst_port: seg.src_port,
+                    seq,
+                    ack,
+                    flags,
+                    window: 0,
+                    payload: Vec::new(),
+                };
+                let bytes = rst.to_bytes(dst, src);
+                let _ = IP_LAYER.send_packet(IpPacket::new(dst, src, PROTO_TCP, bytes));
+            }
+            None => {}
+        }
     }
 
-    pub fn close(&mut self, addr: (IpAddress, u16, IpAddress, u16)) -> Result<()> {
-        if let Some(conn) = self.connections.get_mut(&addr) {
-            conn.state = TcpState::FinWait1;
-            // Send FIN (stub)
-            crate::serial_print(b"[TCP] FIN sent (stub)\r\n");
-            conn.state = TcpState::Closed;
+    pub fn tick(&mut self) {
+        let now = timer::get_uptime_ms();
+        for conn in self.connections.values_mut() {
+            conn.tick(now);
         }
-        Ok(())
+        // TIME_WAIT is not held: there is no port reuse pressure yet
+        self.connections.retain(|_, conn| {
+            !(conn.released && (conn.error.is_some()
+                || matches!(conn.state, TcpState::TimeWait | TcpState::Closed)))
+        });
     }
 }
 
 static TCP_SOCKET: Mutex<TcpSocket> = Mutex::new(TcpSocket::new());
 
-pub fn connect(local_addr: IpAddress, local_port: u16, remote_addr: IpAddress, remote_port: u16) -> Result<()> {
-    TCP_SOCKET.lock().connect(local_addr, local_port, remote_addr, remote_port)
+/// Open a connection and wait for the handshake to complete.
+/// A zero `local_port` picks an ephemeral port. Returns the connection key.
+pub fn connect(local_addr: IpAddress, local_port: u16, remote_addr: IpAddress, remote_port: u16) -> Result<ConnKey> {
+    super::check_route(remote_addr)?;
+    let key = TCP_SOCKET.lock().connect(local_addr, local_port, remote_addr, remote_port)?;
+    let deadline = timer::get_uptime_ms() + CONNECT_TIMEOUT_MS;
+    loop {
+        super::poll();
+        match TCP_SOCKET.lock().state(key) {
+            Ok(TcpState::SynSent) => {}
+            Ok(_) => return Ok(key),
+            Err(e) => {
+                TCP_SOCKET.lock().connections.remove(&key);
+                return Err(e);
+            }
+        }
+        if timer::get_uptime_ms() >= deadline {
+            TCP_SOCKET.lock().connections.remove(&key);
+            return Err(NetworkError::Timeout);
+        }
+        super::idle();
+    }
 }
 
-pub fn send(addr: (IpAddress, u16, IpAddress, u16), data: &[u8]) -> Result<usize> {
+pub fn send(addr: ConnKey, data: &[u8]) -> Result<usize> {
     TCP_SOCKET.lock().send(addr, data)
 }
 
-pub fn receive(addr: (IpAddress, u16, IpAddress, u16), buffer: &mut [u8]) -> Result<usize> {
+pub fn receive(addr: ConnKey, buffer: &mut [u8]) -> Result<usize> {
     TCP_SOCKET.lock().receive(addr, buffer)
 }
 
-pub fn close(addr: (IpAddress, u16, IpAddress, u16)) -> Result<()> {
+pub fn close(addr: ConnKey) -> Result<()> {
     TCP_SOCKET.lock().close(addr)
-}
\ No newline at end of file
+}
+
+pub fn deliver(src: IpAddress, dst: IpAddress, data: &[u8]) {
+    TCP_SOCKET.lock().deliver(src, dst, data)
+}
+
+/// Run retransmission timers
+pub fn tick() {
+    TCP_SOCKET.lock().tick()
+}
+
+fn retransmit_timer(_: u64) {
+    tick();
+    crate::timers::call_in(crate::timers::ms_to_ticks(TIMER_INTERVAL_MS), retransmit_timer, 0);
+}
+
+/// Check retransmission timers periodically from now on
+pub fn start_timers() {
+    crate::timers::call_in(crate::timers::ms_to_ticks(TIMER_INTERVAL_MS), retransmit_timer, 0);
+}
@@ -3,7 +3,14 @@
 //! Provides connectionless, unreliable communication.
 
 use super::{IpAddress, Result, NetworkError};
+use super::ip::{IpPacket, IP_LAYER, PROTO_UDP};
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
+use spin::Mutex;
+
+const HEADER_LEN: usize = 8;
+const EPHEMERAL_PORT_START: u16 = 49152;
+const MAX_QUEUED_DATAGRAMS: usize = 32;
 
 #[derive(Debug)]
 pub struct UdpPacket {
@@ -22,31 +29,139 @@ impl UdpPacket {
             src_port,
             dst_port,
             length,
-            checksum: 0, // Will be calculated
+            checksum: 0, // Optional for IPv4
             payload,
         }
     }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(&self.src_port.to_be_bytes());
+        out.extend_from_slice(&self.dst_port.to_be_bytes());
+        out.extend_from_slice(&self.length.to_be_bytes());
+        out.extend_from_slice(&self.checksum.to_be_bytes());
+        out.extend_from_slice(&self.payload);
+        out
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            return Err(NetworkError::InvalidPacket);
+        }
+        let length = u16::from_be_bytes([data[4], data[5]]);
+        if (length as usize) < HEADER_LEN || length as usize > data.len() {
+            return Err(NetworkError::InvalidPacket);
+        }
+        Ok(Self {
+            src_port: u16::from_be_bytes([data[0], data[1]]),
+            dst_port: u16::from_be_bytes([data[2], data[3]]),
+            length,
+            checksum: u16::from_be_bytes([data[6], data[7]]),
+            payload: data[HEADER_LEN..length as usize].to_vec(),
+        })
+    }
+}
+
+/// Datagram waiting to be read from a bound port
+struct Datagram {
+    src_addr: IpAddress,
+    src_port: u16,
+    data: Vec<u8>,
 }
 
-pub struct UdpSocket;
+pub struct UdpSocket {
+    /// Receive queues keyed by bound local port
+    ports: Mutex<BTreeMap<u16, VecDeque<Datagram>>>,
+    next_ephemeral: Mutex<u16>,
+}
 
 impl UdpSocket {
+    pub const fn new() -> Self {
+        Self {
+            ports: Mutex::new(BTreeMap::new()),
+            next_ephemeral: Mutex::new(EPHEMERAL_PORT_START),
+        }
+    }
+
     pub fn send_to(&self, src_addr: IpAddress, src_port: u16,
                    dst_addr: IpAddress, dst_port: u16, data: &[u8]) -> Result<()> {
+        if data.len() > super::ip::MAX_PAYLOAD - HEADER_LEN {
+            return Err(NetworkError::BufferTooSmall);
+        }
         let packet = UdpPacket::new(src_port, dst_port, data.to_vec());
+        IP_LAYER.send_packet(IpPacket::new(src_addr, dst_addr, PROTO_UDP, packet.to_bytes()))
+    }
 
-        // Send via IP layer (stub)
-        crate::serial_print(b"[UDP] Packet sent (stub)\r\n");
+    /// Start queueing datagrams addressed to `port`
+    pub fn bind(&self, port: u16) -> Result<()> {
+        let mut ports = self.ports.lock();
+        if ports.contains_key(&port) {
+            return Err(NetworkError::InvalidAddress);
+        }
+        ports.insert(port, VecDeque::new());
         Ok(())
     }
 
-    pub fn receive_from(&self, _buffer: &mut [u8]) -> Result<(IpAddress, u16, usize)> {
-        // Stub - no data to receive
-        Err(NetworkError::Timeout)
+    pub fn unbind(&self, port: u16) {
+        self.ports.lock().remove(&port);
+    }
+
+    /// Allocate and bind a free port from the ephemeral range
+    pub fn bind_ephemeral(&self) -> Result<u16> {
+        let mut next = self.next_ephemeral.lock();
+        for _ in EPHEMERAL_PORT_START..=u16::MAX {
+            let port = *next;
+            *next = if port == u16::MAX { EPHEMERAL_PORT_START } else { port + 1 };
+            if self.bind(port).is_ok() {
+                return Ok(port);
+            }
+        }
+        Err(NetworkError::NoDevice)
+    }
+
+    /// Queue an incoming datagram for its destination port (drops if unbound)
+    pub fn deliver(&self, src_addr: IpAddress, data: &[u8]) {
+        let packet = match UdpPacket::parse(data) {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        let mut ports = self.ports.lock();
+        if let Some(queue) = ports.get_mut(&packet.dst_port) {
+            if queue.len() >= MAX_QUEUED_DATAGRAMS {
+                queue.pop_front();
+            }
+            queue.push_back(Datagram {
+                src_addr,
+                src_port: packet.src_port,
+                data: packet.payload,
+            });
+        }
+    }
+
+    /// Non-blocking read from a bound port
+    pub fn receive_on(&self, port: u16, buffer: &mut [u8]) -> Result<(IpAddress, u16, usize)> {
+        let mut ports = self.ports.lock();
+        let queue = ports.get_mut(&port).ok_or(NetworkError::InvalidAddress)?;
+        let datagram = queue.pop_front().ok_or(NetworkError::Timeout)?;
+        let len = core::cmp::min(buffer.len(), datagram.data.len());
+        buffer[..len].copy_from_slice(&datagram.data[..len]);
+        Ok((datagram.src_addr, datagram.src_port, len))
+    }
+
+    /// Non-blocking read from whichever bound port has data first
+    pub fn receive_from(&self, buffer: &mut [u8]) -> Result<(IpAddress, u16, usize)> {
+        let port = {
+            let ports = self.ports.lock();
+            ports.iter().find(|(_, q)| !q.is_empty()).map(|(p, _)| *p)
+        };
+        match port {
+            Some(port) => self.receive_on(port, buffer),
+            None => Err(NetworkError::Timeout),
+        }
     }
 }
 
-pub static UDP_SOCKET: UdpSocket = UdpSocket;
+pub static UDP_SOCKET: UdpSocket = UdpSocket::new();
 
 pub fn send_to(src_addr: IpAddress, src_port: u16, dst_addr: IpAddress, dst_port: u16, data: &[u8]) -> Result<()> {
     UDP_SOCKET.send_to(src_addr, src_port, dst_addr, dst_port, data)
@@ -54,4 +169,24 @@ pub fn send_to(src_addr: IpAddress, src_port: u16, dst_addr: IpAddress, dst_port
 
 pub fn receive_from(buffer: &mut [u8]) -> Result<(IpAddress, u16, usize)> {
     UDP_SOCKET.receive_from(buffer)
-}
\ No newline at end of file
+}
+
+pub fn receive_on(port: u16, buffer: &mut [u8]) -> Result<(IpAddress, u16, usize)> {
+    UDP_SOCKET.receive_on(port, buffer)
+}
+
+pub fn bind(port: u16) -> Result<()> {
+    UDP_SOCKET.bind(port)
+}
+
+pub fn bind_ephemeral() -> Result<u16> {
+    UDP_SOCKET.bind_ephemeral()
+}
+
+pub fn unbind(port: u16) {
+    UDP_SOCKET.unbind(port)
+}
+
+pub fn deliver(src_addr: IpAddress, data: &[u8]) {
+    UDP_SOCKET.deliver(src_addr, data)
+}
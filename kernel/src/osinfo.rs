@@ -0,0 +1,192 @@
+//! System introspection for `osinfo`
+//!
+//! Gathers what the kernel knows about itself, how it was booted and the
+//! machine it runs on into one report, so a bug report can carry all of it
+//! in a single paste.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::common::json::{self, Json};
+
+pub const VERSION: &str = "0.1.0";
+pub const CODENAME: &str = "Foundation";
+
+/// A kernel feature and whether this build/boot has it
+pub struct Feature {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub detail: String,
+}
+
+pub struct BootInfo {
+    /// "Limine 8.x"
+    pub bootloader: Option<String>,
+    pub cmdline: Option<String>,
+    /// Whether the bootloader accepted the base revision we asked for
+    pub base_revision_ok: bool,
+    pub modules: usize,
+    pub uptime_ms: u64,
+}
+
+pub struct Hardware {
+    pub cpu_vendor: String,
+    pub cpu_brand: Option<String>,
+    /// Notable CPUID feature flags, lower case
+    pub cpu_flags: Vec<&'static str>,
+    pub cpus: usize,
+    pub memory_bytes: u64,
+    /// Width, height, bits per pixel
+    pub framebuffer: Option<(usize, usize, usize)>,
+    pub acpi: bool,
+}
+
+pub struct OsInfo {
+    pub version: &'static str,
+    pub codename: &'static str,
+    pub features: Vec<Feature>,
+    pub boot: BootInfo,
+    pub hardware: Hardware,
+}
+
+/// Collect the report
+pub fn collect() -> OsInfo {
+    OsInfo {
+        version: VERSION,
+        codename: CODENAME,
+        features: features(),
+        boot: boot_info(),
+        hardware: hardware(),
+    }
+}
+
+fn features() -> Vec<Feature> {
+    let interfaces = crate::net::list_interfaces();
+    let filesystems = crate::services::vfs::filesystems();
+    let mounts: Vec<String> = filesystems.iter()
+        .map(|(mount, fs)| alloc::format!("{} on {}", fs, mount))
+        .collect();
+
+    Vec::from([
+        Feature {
+            name: "smp",
+            enabled: false,
+            detail: "boot CPU only".to_string(),
+        },
+        Feature {
+            name: "preemption",
+            enabled: true,
+            detail: alloc::format!(
+                "{} Hz {}",
+                crate::drivers::timer::TICKS_PER_SECOND,
+                crate::drivers::timer::source()
+            ),
+        },
+        Feature {
+            name: "apic",
+            enabled: crate::drivers::apic::is_enabled(),
+            detail: alloc::format!("{} interrupt controller", crate::interrupts::controller()),
+        },
+        Feature {
+            name: "nx",
+            enabled: crate::arch::x86_64::cpu::has(crate::arch::x86_64::cpu::Feature::Nx),
+            detail: "W^X user pages, no-execute stacks and heaps".to_string(),
+        },
+        Feature {
+            name: "hpet",
+            enabled: crate::drivers::hpet::is_available(),
+            detail: String::new(),
+        },
+        Feature {
+            name: "networking",
+            enabled: !interfaces.is_empty(),
+            detail: interfaces.iter().map(|i| i.name.as_str()).collect::<Vec<_>>().join(" "),
+        },
+        Feature {
+            name: "nic-driver",
+            enabled: false,
+            detail: "ethernet is a stub, no hardware driver".to_string(),
+        },
+        Feature {
+            name: "filesystems",
+            enabled: !filesystems.is_empty(),
+            detail: mounts.join(", "),
+        },
+        Feature {
+            name: "aslr",
+            enabled: false,
+            detail: "programs load at their linked addresses".to_string(),
+        },
+        Feature {
+            name: "acpi-power-button",
+            enabled: crate::drivers::acpi::power_button_enabled(),
+            detail: String::new(),
+        },
+    ])
+}
+
+fn boot_info() -> BootInfo {
+    use crate::boot::limine;
+
+    BootInfo {
+        bootloader: limine::bootloader_name_version()
+            .map(|(name, version)| alloc::format!("{} {}", name, version)),
+        cmdline: limine::kernel_cmdline().map(String::from),
+        base_revision_ok: limine::base_revision_supported(),
+        modules: limine::module_count(),
+        uptime_ms: crate::drivers::timer::get_uptime_ms(),
+    }
+}
+
+/// What CPUID, ACPI and the bootloader say about the machine
+pub fn hardware() -> Hardware {
+    let cpu = crate::arch::x86_64::cpu::info();
+    let (total_frames, _, _) = crate::mem::physical::stats();
+    let fb = crate::drivers::framebuffer::get_info();
+
+    Hardware {
+        cpu_vendor: cpu.vendor,
+        cpu_brand: cpu.brand,
+        cpu_flags: cpu.flags,
+        cpus: crate::drivers::acpi::madt().map_or(1, |madt| madt.cpus.len().max(1)),
+        memory_bytes: total_frames as u64 * 4096,
+        framebuffer: (fb.width > 0).then_some((fb.width, fb.height, fb.bpp * 8)),
+        acpi: crate::boot::limine::rsdp_address().is_some(),
+    }
+}
+
+impl OsInfo {
+    pub fn to_json(&self) -> Json {
+        let boot = &self.boot;
+        let hw = &self.hardware;
+        json::object([
+            ("version", self.version.into()),
+            ("codename", self.codename.into()),
+            ("features", Json::Object(self.features.iter()
+                .map(|f| (f.name, json::object([
+                    ("enabled", f.enabled.into()),
+                    ("detail", f.detail.as_str().into()),
+                ])))
+                .collect())),
+            ("boot", json::object([
+                ("bootloader", boot.bootloader.clone().into()),
+                ("cmdline", boot.cmdline.clone().into()),
+                ("base_revision_ok", boot.base_revision_ok.into()),
+                ("modules", boot.modules.into()),
+                ("uptime_ms", boot.uptime_ms.into()),
+            ])),
+            ("hardware", json::object([
+                ("cpu_vendor", hw.cpu_vendor.as_str().into()),
+                ("cpu_brand", hw.cpu_brand.clone().into()),
+                ("cpu_flags", json::array(hw.cpu_flags.iter().copied())),
+                ("cpus", hw.cpus.into()),
+                ("memory_bytes", hw.memory_bytes.into()),
+                ("framebuffer", hw.framebuffer.map(|(w, h, bpp)| json::object([
+                    ("width", w.into()),
+                    ("height", h.into()),
+                    ("bpp", bpp.into()),
+                ])).into()),
+                ("acpi", hw.acpi.into()),
+            ])),
+        ])
+    }
+}
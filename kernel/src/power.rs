@@ -1,7 +1,145 @@
 //! Power management for ospabOS
 //! Provides shutdown and reboot functionality
+//!
+//! The ACPI power button and Ctrl+Alt+Del are reported from interrupt
+//! context with `request`; the "power" task picks them up and runs the
+//! action configured in /etc/ospab/power.conf:
+//!
+//! ```text
+//! power_button = shutdown
+//! ctrl_alt_del = reboot
+//! ```
+//!
+//! Actions are `shutdown`, `reboot` and `ignore`.
 
+use alloc::string::ToString;
+use core::sync::atomic::{AtomicU8, Ordering};
 use x86_64::instructions::port::Port;
+use crate::ipc::message::{FSRequest, FSResponse, ServiceEvent};
+
+const CONFIG_PATH: &str = "/etc/ospab/power.conf";
+
+/// Hardware events that ask for a power transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PowerEvent {
+    PowerButton = 1,
+    CtrlAltDel = 2,
+}
+
+impl PowerEvent {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(PowerEvent::PowerButton),
+            2 => Some(PowerEvent::CtrlAltDel),
+            _ => None,
+        }
+    }
+
+    /// Key in power.conf
+    fn config_key(self) -> &'static str {
+        match self {
+            PowerEvent::PowerButton => "power_button",
+            PowerEvent::CtrlAltDel => "ctrl_alt_del",
+        }
+    }
+
+    fn default_action(self) -> PowerAction {
+        match self {
+            PowerEvent::PowerButton => PowerAction::Shutdown,
+            PowerEvent::CtrlAltDel => PowerAction::Reboot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Shutdown,
+    Reboot,
+    Ignore,
+}
+
+impl PowerAction {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "shutdown" | "poweroff" => Some(PowerAction::Shutdown),
+            "reboot" => Some(PowerAction::Reboot),
+            "ignore" => Some(PowerAction::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Event waiting for the power task, 0 if none
+static PENDING: AtomicU8 = AtomicU8::new(0);
+
+/// Report a power event; safe to call from interrupt handlers
+pub fn request(event: PowerEvent) {
+    PENDING.store(event as u8, Ordering::Release);
+}
+
+/// Start the power task and enable the ACPI power button
+pub fn init() {
+    crate::task::spawn_kernel_task("power", power_task);
+    if let Err(e) = crate::drivers::acpi::init() {
+        crate::serial_println!("[POWER] No ACPI power button: {}", e);
+    }
+}
+
+fn power_task() -> ! {
+    loop {
+        if let Some(event) = PowerEvent::from_u8(PENDING.swap(0, Ordering::Acquire)) {
+            let action = configured_action(event);
+            crate::serial_println!("[POWER] {:?}: {:?}", event, action);
+            if action != PowerAction::Ignore {
+                graceful(action);
+            }
+        }
+        crate::task::scheduler::yield_now();
+    }
+}
+
+/// Action for `event` from power.conf, or its default
+fn configured_action(event: PowerEvent) -> PowerAction {
+    let data = match crate::services::vfs::process_request(FSRequest::ReadFile {
+        path: CONFIG_PATH.to_string(),
+    }) {
+        FSResponse::FileData(data) => data,
+        _ => return event.default_action(),
+    };
+    let text = core::str::from_utf8(&data).unwrap_or("");
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| key.trim() == event.config_key())
+        .find_map(|(_, value)| PowerAction::parse(value.trim()))
+        .unwrap_or_else(|| event.default_action())
+}
+
+/// Orderly shutdown or reboot: tell services they are going down, then
+/// power off or reset
+pub fn graceful(action: PowerAction) {
+    let verb = match action {
+        PowerAction::Shutdown => "shutdown",
+        PowerAction::Reboot => "reboot",
+        PowerAction::Ignore => return,
+    };
+    crate::drivers::framebuffer::print(&alloc::format!("\nSystem is going down for {} NOW\n", verb));
+    crate::drivers::framebuffer::print("Stopping services...\n");
+    for name in crate::ipc::registry::names() {
+        crate::ipc::bus::publish(ServiceEvent::Down(name));
+    }
+    crate::drivers::framebuffer::print("Syncing disks...\n");
+    for (device, e) in crate::drivers::block::sync() {
+        crate::drivers::framebuffer::print(&alloc::format!("sync: {}: {}\n", device, e));
+    }
+
+    match action {
+        PowerAction::Reboot => reboot(),
+        _ => shutdown(),
+    }
+}
 
 /// Shutdown the system using ACPI
 pub fn shutdown() {
@@ -0,0 +1,117 @@
+//! Sampling profiler
+//!
+//! While it runs, every timer tick charges the instruction it interrupted
+//! to the kernel function that instruction lies in, found in the kernel's
+//! own symbol table (see `ksyms`). Ticks that land in user mode, or on
+//! kernel code no symbol covers, have a bucket each. `profile on` starts
+//! a fresh profile and `profile off` stops it; the counts stay readable in
+//! /proc/profile, busiest function first, until the next `profile on`.
+//!
+//! At `TICKS_PER_SECOND` samples a second it takes a few seconds of load
+//! before the numbers mean much.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use crate::ksyms::{self, Symbol};
+use crate::task::pcb::TaskContext;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Ticks that found the counts locked by a reader and were dropped
+static MISSED: AtomicU64 = AtomicU64::new(0);
+
+struct Profile {
+    symbols: &'static [Symbol],
+    /// Samples per symbol, by index in `symbols`
+    counts: Vec<u64>,
+    user: u64,
+    /// Kernel addresses outside every symbol
+    unknown: u64,
+}
+
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+/// Throw away the last profile and start sampling; false if the kernel
+/// has no symbol table to put samples down to
+pub fn start() -> bool {
+    let symbols = ksyms::table();
+    if symbols.is_empty() {
+        return false;
+    }
+    *PROFILE.lock() = Some(Profile { symbols, counts: vec![0; symbols.len()], user: 0, unknown: 0 });
+    MISSED.store(0, Ordering::Relaxed);
+    RUNNING.store(true, Ordering::Relaxed);
+    true
+}
+
+/// Stop sampling, keeping the counts
+pub fn stop() {
+    RUNNING.store(false, Ordering::Relaxed);
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Charge one tick to where `context` was interrupted; called from the
+/// timer interrupt, so it never waits on the lock
+pub fn sample(context: &TaskContext) {
+    if !is_running() {
+        return;
+    }
+    let Some(mut profile) = PROFILE.try_lock() else {
+        MISSED.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+    let Some(profile) = profile.as_mut() else { return };
+    if context.cs & 3 == 3 {
+        profile.user += 1;
+    } else {
+        match ksyms::find(profile.symbols, context.rip) {
+            Some(i) => profile.counts[i] += 1,
+            None => profile.unknown += 1,
+        }
+    }
+}
+
+/// The counts so far, busiest first, as /proc/profile shows them; None
+/// before the first `start`
+pub fn report() -> Option<String> {
+    // Copied out so the ticks are not missed while names are demangled
+    let (mut rows, missed) = {
+        let profile = PROFILE.lock();
+        let profile = profile.as_ref()?;
+        let mut rows: Vec<(u64, &'static str)> = Vec::new();
+        for (&count, symbol) in profile.counts.iter().zip(profile.symbols) {
+            if count > 0 {
+                rows.push((count, symbol.name));
+            }
+        }
+        for (count, name) in [(profile.user, "[user]"), (profile.unknown, "[unknown]")] {
+            if count > 0 {
+                rows.push((count, name));
+            }
+        }
+        (rows, MISSED.load(Ordering::Relaxed))
+    };
+    rows.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let total: u64 = rows.iter().map(|(count, _)| count).sum();
+    let mut text = format!(
+        "{} samples ({} missed), {}\n{:>8} {:>6}  FUNCTION\n",
+        total,
+        missed,
+        if is_running() { "running" } else { "stopped" },
+        "SAMPLES",
+        "%"
+    );
+    for (count, name) in rows {
+        let tenths = count * 1000 / total;
+        text += &format!("{:>8} {:>4}.{}  {}\n", count, tenths / 10, tenths % 10, ksyms::demangle(name));
+    }
+    Some(text)
+}
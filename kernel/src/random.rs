@@ -0,0 +1,67 @@
+//! Kernel random numbers
+//!
+//! A SplitMix64 generator seeded at boot from the TSC and the CMOS clock.
+//! Good enough to move each program's stack and heap somewhere else on
+//! every exec (ASLR) and to hand it a stack canary (AT_RANDOM); not for
+//! keys or anything an attacker may watch for long.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+
+/// SplitMix64 increment (2^64 / golden ratio)
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+static STATE: AtomicU64 = AtomicU64::new(GAMMA);
+
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// The CMOS clock registers (seconds to year) as one number. There is no
+/// RTC driver; the raw BCD bytes are as good as decoded ones for a seed.
+fn cmos_clock() -> u64 {
+    let mut index = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+    let mut read = |reg: u8| unsafe {
+        index.write(reg);
+        data.read()
+    };
+    // Wait (briefly) for an update in progress to finish
+    for _ in 0..10_000 {
+        if read(0x0A) & 0x80 == 0 {
+            break;
+        }
+    }
+    [0x00, 0x02, 0x04, 0x07, 0x08, 0x09]
+        .iter()
+        .fold(0u64, |clock, &reg| (clock << 8) | read(reg) as u64)
+}
+
+/// Seed the generator; call once the TSC is running (any time at boot)
+pub fn init() {
+    let seed = rdtsc() ^ cmos_clock().rotate_left(32);
+    STATE.store(mix(seed), Ordering::Relaxed);
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Next random 64-bit number
+pub fn next_u64() -> u64 {
+    mix(STATE.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA))
+}
+
+/// Random number in `0..bound`; `bound` must not be 0
+pub fn below(bound: u64) -> u64 {
+    next_u64() % bound
+}
+
+/// Fill `buf` with random bytes
+pub fn fill(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        chunk.copy_from_slice(&next_u64().to_le_bytes()[..chunk.len()]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Clipboard Service - text shared between programs
+//!
+//! One piece of text, replaced by whoever copies last and read by whoever
+//! pastes: grape's Ctrl+K puts the lines it cuts there and Ctrl+U takes
+//! them back, possibly in another program; Ctrl+Shift+V types it into the
+//! shell line, and the `clipboard` command shows or sets it.
+//!
+//! Requests arrive as `ClipboardRequest`s on the clipboard mailbox, served
+//! in the kernel, or from kernel code through `process`, which does not
+//! go through the bus and so may be used with other locks held.
+
+use alloc::format;
+use alloc::string::String;
+use spin::Mutex;
+use crate::fs::vfs::FsError;
+use crate::ipc::message::{ClipboardRequest, ClipboardResponse, Message, ServiceEvent};
+use crate::ipc::registry::Health;
+use crate::ipc::{bus, registry};
+
+/// Most bytes the clipboard holds
+pub const MAX_LEN: usize = 64 * 1024;
+
+static CLIPBOARD: Mutex<String> = Mutex::new(String::new());
+
+/// Initialize clipboard service
+pub fn init() {
+    if let Err(e) = bus::serve(bus::CLIPBOARD_MAILBOX, serve) {
+        crate::serial_println!("[CLIPBOARD] Cannot serve the bus: {}", e);
+    }
+    let _ = registry::register(registry::ServiceDescriptor {
+        name: "clipboard",
+        mailbox: bus::CLIPBOARD_MAILBOX,
+        capabilities: &["clipboard.text"],
+        probe: health,
+    });
+    bus::publish(ServiceEvent::Ready("clipboard"));
+}
+
+/// Registry health probe: nothing can break
+fn health() -> Health {
+    Health::Ready
+}
+
+/// Process a clipboard request
+pub fn process(request: ClipboardRequest) -> ClipboardResponse {
+    let mut clipboard = CLIPBOARD.lock();
+    match request {
+        ClipboardRequest::Get => ClipboardResponse::Text(clipboard.clone()),
+        ClipboardRequest::Set(text) if text.len() > MAX_LEN => {
+            ClipboardResponse::Error(FsError::NoSpace, Some(format!("{} bytes, at most {}", text.len(), MAX_LEN)))
+        }
+        ClipboardRequest::Set(text) => {
+            *clipboard = text;
+            ClipboardResponse::Success
+        }
+        ClipboardRequest::Clear => {
+            clipboard.clear();
+            ClipboardResponse::Success
+        }
+    }
+}
+
+/// Text on the clipboard, empty if nothing was copied
+pub fn get() -> String {
+    match process(ClipboardRequest::Get) {
+        ClipboardResponse::Text(text) => text,
+        _ => String::new(),
+    }
+}
+
+/// Put `text` on the clipboard
+pub fn set(text: String) -> Result<(), FsError> {
+    match process(ClipboardRequest::Set(text)) {
+        ClipboardResponse::Error(error, _) => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Empty the clipboard
+pub fn clear() {
+    process(ClipboardRequest::Clear);
+}
+
+/// Bus handler for the clipboard mailbox
+fn serve(message: Message) -> Option<Message> {
+    let Message::Clipboard(request) = message else { return None };
+    Some(Message::ClipboardReply(process(request)))
+}
@@ -0,0 +1,407 @@
+//! Display Service - window compositor over the framebuffer
+//!
+//! Tasks own windows: rectangular surfaces with their own RGB pixels. The
+//! compositor stacks them in z-order (the top one has focus) and paints
+//! them, with a border and title bar, over the console as the framebuffer
+//! copies its back buffer out. The console stays the desktop underneath,
+//! so whatever a window covers is back as soon as it moves away, and only
+//! the rectangles that changed are redrawn.
+//!
+//! Requests arrive as `DisplayRequest`s on the display mailbox, or from
+//! kernel code through `process`. The left mouse button raises the window
+//! under the pointer and drags windows by their title bar.
+//!
+//! Windows need the framebuffer's back buffer. Lock order: the console
+//! lock may be held while taking the compositor's (painting happens under
+//! both), never the other way round, so nothing here calls into the
+//! framebuffer while holding `DISPLAY`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::common::palette;
+use crate::drivers::framebuffer::{self, OverlayRow};
+use crate::drivers::mouse;
+use crate::fs::vfs::FsError;
+use crate::ipc::message::{DisplayRequest, DisplayResponse, Message, ServiceEvent};
+use crate::ipc::{bus, registry};
+use crate::ipc::registry::Health;
+
+/// Title bar height above a window's content
+const TITLE_HEIGHT: i32 = 12;
+/// Border width around the title bar and content
+const BORDER: i32 = 1;
+const MAX_WINDOWS: usize = 16;
+/// Largest window content in pixels
+const MAX_PIXELS: usize = 1024 * 768;
+
+/// Screen rectangle, end-exclusive; may reach off-screen
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+}
+
+impl Rect {
+    fn union(self, other: Rect) -> Rect {
+        Rect {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+        }
+    }
+
+    fn contains(self, x: i32, y: i32) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    /// Redraw the on-screen part
+    fn damage(self) {
+        let clamp = |v: i32| v.max(0) as usize;
+        framebuffer::damage(clamp(self.x0), clamp(self.y0), clamp(self.x1), clamp(self.y1));
+    }
+}
+
+struct Window {
+    id: u32,
+    owner: u32,
+    title: String,
+    /// Screen position of the content's top-left corner
+    x: i32,
+    y: i32,
+    width: usize,
+    height: usize,
+    /// RGB, `width` per row
+    pixels: Vec<u32>,
+}
+
+impl Window {
+    /// Content plus decorations
+    fn frame(&self) -> Rect {
+        Rect {
+            x0: self.x - BORDER,
+            y0: self.y - TITLE_HEIGHT - BORDER,
+            x1: self.x + self.width as i32 + BORDER,
+            y1: self.y + self.height as i32 + BORDER,
+        }
+    }
+
+    /// Part of the content, clipped to it
+    fn content_rect(&self, x: u32, y: u32, width: u32, height: u32) -> Rect {
+        let x0 = (x as usize).min(self.width);
+        let y0 = (y as usize).min(self.height);
+        let x1 = x0.saturating_add(width as usize).min(self.width);
+        let y1 = y0.saturating_add(height as usize).min(self.height);
+        Rect {
+            x0: self.x + x0 as i32,
+            y0: self.y + y0 as i32,
+            x1: self.x + x1 as i32,
+            y1: self.y + y1 as i32,
+        }
+    }
+
+    fn in_title_bar(&self, x: i32, y: i32) -> bool {
+        self.frame().contains(x, y) && y < self.y
+    }
+
+    /// Paint this window's slice of the row being copied out
+    fn paint_row(&self, row: &mut OverlayRow, focused: bool) {
+        let colors = palette::current();
+        let y = row.y() as i32;
+        let frame = self.frame();
+        if y < frame.y0 || y >= frame.y1 {
+            return;
+        }
+        let right = self.x + self.width as i32;
+        if y < frame.y0 + BORDER || y >= frame.y1 - BORDER {
+            row.fill(frame.x0, frame.x1, colors.window_border);
+            return;
+        }
+        row.fill(frame.x0, self.x, colors.window_border);
+        row.fill(right, frame.x1, colors.window_border);
+
+        if y < self.y {
+            let background = if focused { colors.window_title_focused } else { colors.window_title };
+            row.fill(self.x, right, background);
+            // As many characters as fit, with a 2 pixel margin
+            let fits = self.width.saturating_sub(4) / 8;
+            let title = match self.title.char_indices().nth(fits) {
+                Some((end, _)) => &self.title[..end],
+                None => &self.title,
+            };
+            let top = self.y - TITLE_HEIGHT + 2;
+            row.text(self.x + 2, top, title, colors.window_title_text, background);
+        } else {
+            let line = (y - self.y) as usize;
+            row.span(self.x, &self.pixels[line * self.width..(line + 1) * self.width]);
+        }
+    }
+}
+
+/// Result of a request: the response and what to redraw, or an error with
+/// a detail for logs
+type Handled = Result<(DisplayResponse, Option<Rect>), (FsError, &'static str)>;
+
+struct Compositor {
+    /// Bottom to top; the last one has focus
+    windows: Vec<Window>,
+    next_id: u32,
+    /// Window being dragged by its title bar, with the grab point relative
+    /// to its content
+    drag: Option<(u32, i32, i32)>,
+    /// Mouse buttons as of the last `poll`
+    buttons: u8,
+}
+
+impl Compositor {
+    const fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            next_id: 1,
+            drag: None,
+            buttons: 0,
+        }
+    }
+
+    fn index(&self, window: u32) -> Result<usize, (FsError, &'static str)> {
+        self.windows.iter().position(|w| w.id == window).ok_or((FsError::NotFound, "no such window"))
+    }
+
+    /// Frame of the focused window, whose title bar changes with focus
+    fn focus_frame(&self) -> Option<Rect> {
+        self.windows.last().map(Window::frame)
+    }
+
+    fn handle(&mut self, request: DisplayRequest) -> Handled {
+        match request {
+            DisplayRequest::Create { owner, title, x, y, width, height } => {
+                let (width, height) = (width as usize, height as usize);
+                if width == 0 || height == 0 || width.saturating_mul(height) > MAX_PIXELS {
+                    return Err((FsError::Invalid, "bad window size"));
+                }
+                if self.windows.len() >= MAX_WINDOWS {
+                    return Err((FsError::NoSpace, "too many windows"));
+                }
+                let mut pixels = Vec::new();
+                if pixels.try_reserve_exact(width * height).is_err() {
+                    return Err((FsError::NoSpace, "out of memory for window"));
+                }
+                pixels.resize(width * height, 0);
+
+                let id = self.next_id;
+                self.next_id += 1;
+                let window = Window { id, owner, title, x, y, width, height, pixels };
+                let damage = self.focus_frame().map_or(window.frame(), |old| old.union(window.frame()));
+                self.windows.push(window);
+                Ok((DisplayResponse::Created(id), Some(damage)))
+            }
+            DisplayRequest::Move { window, x, y } => {
+                let index = self.index(window)?;
+                let window = &mut self.windows[index];
+                let old = window.frame();
+                window.x = x;
+                window.y = y;
+                Ok((DisplayResponse::Success, Some(old.union(window.frame()))))
+            }
+            DisplayRequest::Raise { window } => {
+                let index = self.index(window)?;
+                Ok((DisplayResponse::Success, self.raise(index)))
+            }
+            DisplayRequest::Damage { window, x, y, width, height } => {
+                let window = &self.windows[self.index(window)?];
+                Ok((DisplayResponse::Success, Some(window.content_rect(x, y, width, height))))
+            }
+            DisplayRequest::Blit { window, x, y, width, pixels } => {
+                if width == 0 || pixels.len() % width as usize != 0 {
+                    return Err((FsError::Invalid, "pixels are not whole rows"));
+                }
+                let index = self.index(window)?;
+                let window = &mut self.windows[index];
+                let rows = pixels.len() / width as usize;
+                let rect = window.content_rect(x, y, width, rows as u32);
+                let columns = (rect.x1 - rect.x0) as usize;
+                if columns == 0 {
+                    return Ok((DisplayResponse::Success, None));
+                }
+                for (line, src) in pixels.chunks_exact(width as usize).enumerate() {
+                    let dst_y = y as usize + line;
+                    if dst_y >= window.height {
+                        break;
+                    }
+                    let start = dst_y * window.width + x as usize;
+                    window.pixels[start..start + columns].copy_from_slice(&src[..columns]);
+                }
+                Ok((DisplayResponse::Success, Some(rect)))
+            }
+            DisplayRequest::Destroy { window } => {
+                let index = self.index(window)?;
+                let closed = self.windows.remove(index).frame();
+                let damage = self.focus_frame().map_or(closed, |focus| closed.union(focus));
+                Ok((DisplayResponse::Success, Some(damage)))
+            }
+        }
+    }
+
+    /// Move window `index` to the top; returns what to redraw
+    fn raise(&mut self, index: usize) -> Option<Rect> {
+        if index + 1 == self.windows.len() {
+            return None;
+        }
+        let old_focus = self.focus_frame()?;
+        let window = self.windows.remove(index);
+        let damage = window.frame().union(old_focus);
+        self.windows.push(window);
+        Some(damage)
+    }
+
+    /// Raise on click and drag by the title bar; returns what to redraw
+    fn handle_mouse(&mut self) -> Option<Rect> {
+        let buttons = mouse::buttons();
+        let was_down = core::mem::replace(&mut self.buttons, buttons) & mouse::BUTTON_LEFT != 0;
+        let is_down = buttons & mouse::BUTTON_LEFT != 0;
+        let (px, py) = mouse::position();
+        let (px, py) = (px as i32, py as i32);
+
+        match (was_down, is_down) {
+            (false, true) => {
+                let index = self.windows.iter().rposition(|w| w.frame().contains(px, py))?;
+                let window = &self.windows[index];
+                if window.in_title_bar(px, py) {
+                    self.drag = Some((window.id, px - window.x, py - window.y));
+                }
+                self.raise(index)
+            }
+            (true, true) => {
+                let (id, grab_x, grab_y) = self.drag?;
+                let index = self.index(id).ok()?;
+                let window = &mut self.windows[index];
+                if (window.x, window.y) == (px - grab_x, py - grab_y) {
+                    return None;
+                }
+                let old = window.frame();
+                window.x = px - grab_x;
+                window.y = py - grab_y;
+                Some(old.union(window.frame()))
+            }
+            _ => {
+                self.drag = None;
+                None
+            }
+        }
+    }
+}
+
+/// Global compositor
+static DISPLAY: Mutex<Compositor> = Mutex::new(Compositor::new());
+
+/// Framebuffer overlay: the windows, bottom to top, over one row
+fn paint_row(row: &mut OverlayRow) {
+    // Busy means a request is being handled; it redraws when it is done
+    if let Some(display) = DISPLAY.try_lock() {
+        let top = display.windows.len().saturating_sub(1);
+        for (i, window) in display.windows.iter().enumerate() {
+            window.paint_row(row, i == top);
+        }
+    }
+}
+
+/// Initialize display service
+pub fn init() {
+    let _ = registry::register(registry::ServiceDescriptor {
+        name: "display",
+        mailbox: bus::DISPLAY_MAILBOX,
+        capabilities: &["display.window"],
+        probe: health,
+    });
+    bus::publish(ServiceEvent::Ready("display"));
+}
+
+/// Registry health probe: degraded while windows cannot be shown
+fn health() -> Health {
+    if framebuffer::is_double_buffered() {
+        Health::Ready
+    } else {
+        Health::Degraded
+    }
+}
+
+/// Process a display request
+pub fn process(request: DisplayRequest) -> DisplayResponse {
+    if matches!(request, DisplayRequest::Create { .. }) && !framebuffer::is_double_buffered() {
+        return DisplayResponse::Error(FsError::Io, Some("framebuffer is not double buffered".into()));
+    }
+
+    let mut display = DISPLAY.lock();
+    let had_windows = !display.windows.is_empty();
+    let result = display.handle(request);
+    let has_windows = !display.windows.is_empty();
+    drop(display);
+
+    // The console copies out at full speed while there is nothing on top
+    if had_windows != has_windows {
+        framebuffer::set_overlay(if has_windows { Some(paint_row) } else { None });
+    }
+    match result {
+        Ok((response, damage)) => {
+            if let Some(rect) = damage {
+                rect.damage();
+            }
+            response
+        }
+        Err((error, detail)) => DisplayResponse::Error(error, Some(detail.into())),
+    }
+}
+
+/// Run `f` on a window's pixels (RGB, `width` per row) with its width and
+/// height; send a `Damage` request afterwards to show the change
+///
+/// `f` runs with the compositor locked and must not draw to the console.
+pub fn with_pixels<R>(window: u32, f: impl FnOnce(&mut [u32], usize, usize) -> R) -> Option<R> {
+    let mut display = DISPLAY.lock();
+    let index = display.index(window).ok()?;
+    let window = &mut display.windows[index];
+    Some(f(&mut window.pixels, window.width, window.height))
+}
+
+/// Close the windows of a task that exited
+pub fn release(owner: u32) {
+    let windows: Vec<u32> = DISPLAY.lock().windows.iter()
+        .filter(|w| w.owner == owner)
+        .map(|w| w.id)
+        .collect();
+    for window in windows {
+        process(DisplayRequest::Destroy { window });
+    }
+}
+
+/// Serve queued requests and follow the mouse; called from the main loop
+pub fn poll() {
+    if let Some(bus) = bus::get() {
+        while let Some(envelope) = bus.receive(bus::DISPLAY_MAILBOX) {
+            let reply = match envelope.message {
+                Message::Display(request) => {
+                    let response = process(request);
+                    if let (DisplayResponse::Error(error, detail), None) = (&response, envelope.reply_to) {
+                        crate::serial_println!("[DISPLAY] {}: {}", error.as_str(), detail.clone().unwrap_or_default());
+                    }
+                    Message::DisplayReply(response)
+                }
+                _ => Message::Unhandled,
+            };
+            if let Some(reply_to) = envelope.reply_to {
+                let _ = bus.reply(reply_to, reply);
+            }
+        }
+    }
+
+    if !mouse::is_present() {
+        return;
+    }
+    let damage = DISPLAY.try_lock().and_then(|mut display| display.handle_mouse());
+    if let Some(rect) = damage {
+        rect.damage();
+    }
+}
@@ -1,5 +1,7 @@
 //! Services module - Microkernel services
 
+pub mod clipboard;
+pub mod display;
 pub mod terminal;
 pub mod vfs;
 
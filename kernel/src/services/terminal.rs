@@ -1,8 +1,31 @@
 //! Terminal Service - Bridge between existing I/O and IPC layer
 //! Wraps stable framebuffer and keyboard code without modifying it
+//!
+//! It also owns the TTY that user tasks see as stdin/stdout/stderr. Each
+//! descriptor is tagged with a job: the pid whose descriptor table created
+//! it, shared by the tasks it forks. Input goes to the foreground job;
+//! while there is none, the keyboard stays with the kernel shell. In
+//! canonical mode a job gets whole lines, edited with the same line editor
+//! as the kernel shell's (cursor keys, history, Tab completion); in raw
+//! mode it gets the keys as typed, arrows as escape sequences. Output is queued per job and drawn a line at a
+//! time, so lines from different jobs do not interleave.
 
-use crate::drivers::{framebuffer, keyboard};
-use crate::ipc::message::UIRequest;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::drivers::keyboard::{self, EditorKey};
+use crate::drivers::framebuffer;
+use crate::fs::vfs::{FileHandle, FsError};
+use crate::ipc::message::{FSRequest, FSResponse, ServiceEvent, UIRequest};
+use crate::ipc::{bus, registry};
+use crate::ipc::registry::Health;
+use crate::services::clipboard;
+use crate::services::vfs::{self, FileType, Metadata};
+use crate::keybindings::{self, Action, Context, Key};
+use crate::syscall::abi;
+use spin::MutexGuard;
 
 /// Terminal service that uses existing stable I/O functions
 pub struct TerminalService;
@@ -54,6 +77,26 @@ static TERMINAL: spin::Mutex<Option<TerminalService>> = spin::Mutex::new(None);
 pub fn init() {
     let mut term = TERMINAL.lock();
     *term = Some(TerminalService::new());
+    drop(term);
+
+    let _ = registry::register(registry::ServiceDescriptor {
+        name: "terminal",
+        mailbox: bus::UI_MAILBOX,
+        capabilities: &["ui.print", "ui.input"],
+        probe: health,
+    });
+    bus::publish(ServiceEvent::Ready("terminal"));
+}
+
+/// Registry health probe: degraded without a framebuffer to draw on
+fn health() -> Health {
+    if TERMINAL.lock().is_none() {
+        Health::Down
+    } else if framebuffer::is_initialized() {
+        Health::Ready
+    } else {
+        Health::Degraded
+    }
 }
 
 /// Print text using terminal service
@@ -83,3 +126,634 @@ pub fn poll_input() {
         term.poll_keyboard();
     }
 }
+
+/// Buffered output is drawn once it reaches this size even without a newline
+const OUTPUT_FLUSH_LEN: usize = 1024;
+
+/// Longest line the line editor takes
+const MAX_LINE: usize = 255;
+
+/// Commands kept for the arrow keys, Ctrl+R and ~/.history
+pub const HISTORY_SIZE: usize = 500;
+
+/// A line being typed: the text, the cursor in it and the history entry
+/// it came from
+///
+/// The line discipline of both the kernel shell and the TTY of user tasks.
+/// Every change is drawn on the console as it is made; callers hide the
+/// blinking cursor around it if they show one.
+pub struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,
+    /// History entry shown, 0 the newest; None while typing a new line
+    history_pos: Option<usize>,
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self { line: Vec::new(), cursor: 0, history_pos: None }
+    }
+
+    pub fn text(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line.is_empty()
+    }
+
+    /// Columns the line takes on screen
+    pub fn width(&self) -> usize {
+        self.line.len()
+    }
+
+    /// Draw the line from the cursor on, blank `erase` columns after it and
+    /// put the cursor back
+    fn draw_tail(&self, erase: usize) {
+        let tail: String = self.line[self.cursor..].iter().collect();
+        framebuffer::print(&tail);
+        for _ in 0..erase {
+            framebuffer::print_char(' ');
+        }
+        framebuffer::cursor_back(tail.chars().count() + erase);
+    }
+
+    /// Type `c` at the cursor
+    pub fn insert(&mut self, c: char) {
+        if self.line.len() >= MAX_LINE {
+            return;
+        }
+        self.history_pos = None;
+        self.line.insert(self.cursor, c);
+        self.cursor += 1;
+        framebuffer::print_char(c);
+        self.draw_tail(0);
+    }
+
+    /// Type `text` at the cursor as one line: line breaks become spaces
+    /// and other control characters are dropped
+    pub fn paste(&mut self, text: &str) {
+        let room = MAX_LINE.saturating_sub(self.line.len());
+        let chars: Vec<char> = text
+            .chars()
+            .map(|c| if c == '\n' || c == '\t' { ' ' } else { c })
+            .filter(|c| !c.is_control())
+            .take(room)
+            .collect();
+        if chars.is_empty() {
+            return;
+        }
+        self.history_pos = None;
+        let pasted: String = chars.iter().collect();
+        self.line.splice(self.cursor..self.cursor, chars.iter().copied());
+        self.cursor += chars.len();
+        framebuffer::print(&pasted);
+        self.draw_tail(0);
+    }
+
+    /// Delete the character before the cursor
+    pub fn backspace(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.history_pos = None;
+        self.cursor -= 1;
+        self.line.remove(self.cursor);
+        framebuffer::cursor_back(1);
+        self.draw_tail(1);
+    }
+
+    pub fn left(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            framebuffer::cursor_back(1);
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.cursor < self.line.len() {
+            framebuffer::print_char(self.line[self.cursor]);
+            self.cursor += 1;
+        }
+    }
+
+    /// Set the line to `text` without drawing it, cursor at the end
+    pub fn set(&mut self, text: &str) {
+        self.line = text.chars().take(MAX_LINE).collect();
+        self.cursor = self.line.len();
+        self.history_pos = None;
+    }
+
+    /// Show `text` in place of the line, cursor at the end
+    fn replace(&mut self, text: &str) {
+        let old_width = self.line.len();
+        framebuffer::cursor_back(self.cursor);
+        self.set(text);
+        framebuffer::print(&self.text());
+        let erase = old_width.saturating_sub(self.line.len());
+        for _ in 0..erase {
+            framebuffer::print_char(' ');
+        }
+        framebuffer::cursor_back(erase);
+    }
+
+    /// Put history entry `pos` (0 the newest) in the line without drawing it
+    pub fn recall(&mut self, history: &VecDeque<String>, pos: usize) {
+        self.set(&history[pos]);
+        self.history_pos = Some(pos);
+    }
+
+    /// Up: the next older history entry
+    pub fn history_prev(&mut self, history: &VecDeque<String>) {
+        let pos = self.history_pos.map_or(0, |pos| pos + 1);
+        if pos < history.len() {
+            self.replace(&history[pos]);
+            self.history_pos = Some(pos);
+        }
+    }
+
+    /// Down: the next newer history entry, then an empty line
+    pub fn history_next(&mut self, history: &VecDeque<String>) {
+        match self.history_pos {
+            None => {}
+            Some(0) => self.replace(""),
+            Some(pos) => {
+                self.replace(&history[pos - 1]);
+                self.history_pos = Some(pos - 1);
+            }
+        }
+    }
+
+    /// The line, leaving the editor empty
+    pub fn take(&mut self) -> String {
+        let text = self.text();
+        self.line.clear();
+        self.cursor = 0;
+        self.history_pos = None;
+        text
+    }
+
+    /// The text before the cursor, which completion looks at
+    pub fn before_cursor(&self) -> String {
+        self.line[..self.cursor].iter().collect()
+    }
+
+    /// The word before the cursor, the one Tab completes
+    pub fn word(&self) -> String {
+        let start = self.line[..self.cursor].iter().rposition(|&c| c == ' ').map_or(0, |i| i + 1);
+        self.line[start..self.cursor].iter().collect()
+    }
+
+    /// Complete the word before the cursor from `candidates`, the words
+    /// (see `completions`) it can become
+    ///
+    /// A single candidate is typed in, followed by a space unless it is a
+    /// directory. Several are typed in as far as they agree; if that adds
+    /// nothing they are listed in columns and `prompt` and the line are
+    /// drawn again below them.
+    pub fn complete(&mut self, prompt: &str, candidates: &[String]) {
+        let word = self.word();
+        let typed = word.chars().count();
+        if let [only] = candidates {
+            for c in only.chars().skip(typed) {
+                self.insert(c);
+            }
+            if !only.ends_with('/') {
+                self.insert(' ');
+            }
+            return;
+        }
+        let Some(first) = candidates.first() else { return };
+        let common = candidates.iter().fold(first.chars().count(), |len, candidate| {
+            first.chars().zip(candidate.chars()).take(len).take_while(|(a, b)| a == b).count()
+        });
+        if common > typed {
+            for c in first.chars().skip(typed).take(common - typed) {
+                self.insert(c);
+            }
+            return;
+        }
+        // Listed without the directory part they share with the word
+        let dir_len = word.rfind('/').map_or(0, |i| i + 1);
+        let names: Vec<&str> = candidates.iter().map(|candidate| &candidate[dir_len..]).collect();
+        framebuffer::print_char('\n');
+        framebuffer::print(&columns(&names, framebuffer::text_size().0));
+        framebuffer::print(prompt);
+        framebuffer::print(&self.text());
+        self.cursor = self.line.len();
+    }
+}
+
+/// `names` in columns down then across, as ls lays them out, to fit `width`
+fn columns(names: &[&str], width: usize) -> String {
+    let column_width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0) + 2;
+    let per_row = (width.saturating_sub(1) / column_width).max(1);
+    let rows = names.len().div_ceil(per_row);
+    let mut text = String::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        for name in names.iter().skip(row).step_by(rows) {
+            line += &format!("{:<width$}", name, width = column_width);
+        }
+        text += line.trim_end();
+        text.push('\n');
+    }
+    text
+}
+
+/// Remember `line` as the newest command, unless it is empty or repeats
+/// the one before
+pub fn add_history(history: &mut VecDeque<String>, line: String) {
+    if line.is_empty() || history.front() == Some(&line) {
+        return;
+    }
+    history.push_front(line);
+    history.truncate(HISTORY_SIZE);
+}
+
+/// Commands whose arguments are directories, so only those are offered
+const DIRECTORY_COMMANDS: &[&str] = &["cd", "rmdir"];
+
+/// Whether `path` is a directory
+fn is_dir(path: &str) -> bool {
+    matches!(
+        vfs::process_request(FSRequest::Stat { path: String::from(path) }),
+        FSResponse::Metadata(metadata) if metadata.file_type == FileType::Directory
+    )
+}
+
+/// Words Tab can turn the last word of `line` (the text before the
+/// cursor) into, sorted
+///
+/// The first word of a line is a command, looked up in /bin; the others are
+/// paths, completed one directory at a time: `/var/lo` becomes `/var/log/`,
+/// with a slash on directories so the next Tab looks inside. Names starting
+/// with '.' are only offered when the word does. `cd` and `rmdir` are only
+/// offered directories.
+///
+/// Reads directories, so it must not be called with the TTY lock held.
+pub fn completions(line: &str) -> Vec<String> {
+    let start = line.rfind(' ').map_or(0, |i| i + 1);
+    let (before, word) = line.split_at(start);
+    let command = before.split_whitespace().next();
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let (listed, dirs_only) = match command {
+        None if dir.is_empty() => ("/bin", false),
+        None => (dir, false),
+        Some(command) => (if dir.is_empty() { "." } else { dir }, DIRECTORY_COMMANDS.contains(&command)),
+    };
+    let FSResponse::DirListing(names) = vfs::process_request(FSRequest::ListDir { path: String::from(listed) }) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = names
+        .into_iter()
+        .filter(|name| name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')))
+        .filter_map(|name| {
+            let path = format!("{}{}", dir, name);
+            // Commands in /bin are files; no need to look
+            if command.is_none() && dir.is_empty() {
+                return Some(path);
+            }
+            let lookup = if dir.is_empty() { format!("./{}", name) } else { path.clone() };
+            if is_dir(&lookup) {
+                Some(path + "/")
+            } else if dirs_only {
+                None
+            } else {
+                Some(path)
+            }
+        })
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// Queues of one job
+#[derive(Default)]
+struct JobQueues {
+    /// Keys go through unedited and unechoed, for programs that draw
+    /// their own screen
+    raw: bool,
+    /// Completed lines not read yet
+    input: VecDeque<u8>,
+    /// Ctrl+D typed on an empty line: the next read returns 0
+    eof: bool,
+    /// Output since the last newline
+    output: Vec<u8>,
+}
+
+struct Tty {
+    foreground: Option<u32>,
+    /// Line being typed for the foreground job
+    editor: LineEditor,
+    /// Lines typed by user tasks, newest first
+    history: VecDeque<String>,
+    /// Text drawn since the last newline, usually a prompt; drawn again
+    /// when completion lists candidates
+    prompt: String,
+    /// Ctrl+C typed; the foreground job gets SIGINT at the next `poll`
+    interrupt: bool,
+    /// Ctrl+Z typed; the foreground job gets SIGTSTP at the next `poll`
+    suspend: bool,
+    jobs: BTreeMap<u32, JobQueues>,
+}
+
+static TTY: spin::Mutex<Tty> = spin::Mutex::new(Tty {
+    foreground: None,
+    editor: LineEditor::new(),
+    history: VecDeque::new(),
+    prompt: String::new(),
+    interrupt: false,
+    suspend: false,
+    jobs: BTreeMap::new(),
+});
+
+/// What a key sends in raw mode
+fn raw_bytes(key: EditorKey, out: &mut VecDeque<u8>) {
+    let sequence = match key {
+        EditorKey::Char(c) => {
+            let mut utf8 = [0u8; 4];
+            out.extend(c.encode_utf8(&mut utf8).bytes());
+            return;
+        }
+        EditorKey::ArrowUp => "\x1b[A",
+        EditorKey::ArrowDown => "\x1b[B",
+        EditorKey::ArrowRight => "\x1b[C",
+        EditorKey::ArrowLeft => "\x1b[D",
+        EditorKey::CtrlArrowRight => "\x1b[1;5C",
+        EditorKey::CtrlArrowLeft => "\x1b[1;5D",
+        EditorKey::Home => "\x1b[H",
+        EditorKey::End => "\x1b[F",
+        EditorKey::Delete => "\x1b[3~",
+        EditorKey::PageUp => "\x1b[5~",
+        EditorKey::PageDown => "\x1b[6~",
+    };
+    out.extend(sequence.bytes());
+}
+
+/// The binding table's name for a key, if it can be bound
+fn binding_key(key: EditorKey) -> Option<Key> {
+    match key {
+        EditorKey::Char(c) => Some(Key::Char(c)),
+        EditorKey::ArrowUp => Some(Key::Up),
+        EditorKey::ArrowDown => Some(Key::Down),
+        EditorKey::ArrowLeft => Some(Key::Left),
+        EditorKey::ArrowRight => Some(Key::Right),
+        EditorKey::CtrlArrowLeft => Some(Key::CtrlLeft),
+        EditorKey::CtrlArrowRight => Some(Key::CtrlRight),
+        _ => None,
+    }
+}
+
+impl Tty {
+    /// Draw `bytes` for a job, keeping track of the line they leave
+    fn draw(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            framebuffer::print_char(if b < 0x80 { b as char } else { '?' });
+        }
+        let tail = match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(end) => {
+                self.prompt.clear();
+                &bytes[end + 1..]
+            }
+            None => bytes,
+        };
+        self.prompt.push_str(&String::from_utf8_lossy(tail));
+    }
+
+    fn flush(&mut self, job: u32) {
+        if let Some(queues) = self.jobs.get_mut(&job) {
+            let output = core::mem::take(&mut queues.output);
+            self.draw(&output);
+        }
+    }
+
+    /// Run keys typed since the last call through the line discipline of
+    /// the foreground job
+    ///
+    /// Stops at Tab and returns the text before the cursor; the completions
+    /// have to be found without the TTY lock (see `pump`).
+    fn pump_input(&mut self) -> Option<String> {
+        let job = self.foreground?;
+        while let Some(key) = keyboard::try_read_editor_key() {
+            let queues = self.jobs.entry(job).or_default();
+            if queues.raw {
+                raw_bytes(key, &mut queues.input);
+                continue;
+            }
+            let action = binding_key(key).and_then(|key| keybindings::lookup(Context::Shell, key));
+            match (key, action) {
+                (EditorKey::Char('\n' | '\r'), _) => {
+                    framebuffer::print_char('\n');
+                    self.prompt.clear();
+                    let line = self.editor.take();
+                    queues.input.extend(line.bytes());
+                    queues.input.push_back(b'\n');
+                    add_history(&mut self.history, String::from(line.trim()));
+                }
+                (EditorKey::Char('\x08' | '\x7f'), _) => self.editor.backspace(),
+                (_, Some(Action::Cancel)) => {
+                    framebuffer::print("^C\n");
+                    self.prompt.clear();
+                    self.editor.take();
+                    self.interrupt = true;
+                }
+                (_, Some(Action::Suspend)) => {
+                    framebuffer::print("^Z\n");
+                    self.prompt.clear();
+                    self.editor.take();
+                    self.suspend = true;
+                }
+                (_, Some(Action::Complete)) => return Some(self.editor.before_cursor()),
+                (_, Some(Action::HistoryPrev)) => self.editor.history_prev(&self.history),
+                (_, Some(Action::HistoryNext)) => self.editor.history_next(&self.history),
+                (_, Some(Action::CursorLeft)) => self.editor.left(),
+                (_, Some(Action::CursorRight)) => self.editor.right(),
+                (_, Some(Action::PasteClipboard)) => self.editor.paste(&clipboard::get()),
+                (EditorKey::Char('\x04'), _) => {
+                    if self.editor.is_empty() {
+                        queues.eof = true;
+                    } else {
+                        queues.input.extend(self.editor.take().bytes());
+                    }
+                }
+                (EditorKey::Char(c), _) if !c.is_control() => self.editor.insert(c),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Read the keyboard for the foreground job, completing words on Tab
+///
+/// Completion lists directories through the VFS, which must not be locked
+/// under the TTY (its users can take the scheduler lock, which comes
+/// first), so the guard is given up for it and taken again.
+fn pump(mut tty: MutexGuard<'static, Tty>) -> MutexGuard<'static, Tty> {
+    while let Some(line) = tty.pump_input() {
+        let job = tty.foreground;
+        drop(tty);
+        let candidates = completions(&line);
+        tty = TTY.lock();
+        if tty.foreground == job {
+            let tty = &mut *tty;
+            tty.editor.complete(&tty.prompt, &candidates);
+        }
+    }
+    tty
+}
+
+/// Give the keyboard to `job`, or back to the kernel shell with None
+///
+/// A job gets the keyboard in the mode it last chose, a new one in
+/// canonical mode.
+pub fn set_foreground(job: Option<u32>) {
+    let mut tty = TTY.lock();
+    tty.foreground = job;
+    tty.editor.take();
+    tty.interrupt = false;
+    tty.suspend = false;
+}
+
+/// Read keys for the foreground job while it is busy, and send it SIGINT
+/// if Ctrl+C was typed or SIGTSTP if Ctrl+Z was
+///
+/// Called while the kernel shell waits for the job. The signals go out
+/// after the TTY lock is dropped (the scheduler lock comes first).
+pub fn poll() {
+    let (interrupted, suspended) = {
+        let mut tty = pump(TTY.lock());
+        let interrupted = core::mem::take(&mut tty.interrupt).then_some(tty.foreground).flatten();
+        let suspended = core::mem::take(&mut tty.suspend).then_some(tty.foreground).flatten();
+        (interrupted, suspended)
+    };
+    if let Some(job) = interrupted {
+        let _ = crate::task::signal::send(job, crate::syscall::abi::SIGINT as u32);
+    }
+    if let Some(job) = suspended {
+        let _ = crate::task::signal::send(job, crate::syscall::abi::SIGTSTP as u32);
+    }
+}
+
+/// Job currently reading the keyboard
+pub fn foreground() -> Option<u32> {
+    TTY.lock().foreground
+}
+
+/// Flush and drop the queues of a job whose tasks have all exited
+pub fn release(job: u32) {
+    let mut tty = TTY.lock();
+    tty.flush(job);
+    tty.jobs.remove(&job);
+    if tty.foreground == Some(job) {
+        tty.foreground = None;
+        tty.editor.take();
+    }
+}
+
+/// A task's view of the terminal (stdin, stdout and stderr)
+pub struct TtyHandle {
+    job: u32,
+    /// Write through instead of a line at a time (stderr)
+    unbuffered: bool,
+}
+
+impl TtyHandle {
+    pub fn new(job: u32, unbuffered: bool) -> Self {
+        Self { job, unbuffered }
+    }
+}
+
+impl FileHandle for TtyHandle {
+    /// Reads one queued line at most; WouldBlock until one is typed, and
+    /// for as long as the job is in the background
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut tty = TTY.lock();
+        // A prompt without a newline has to be visible before input
+        tty.flush(self.job);
+        if tty.foreground == Some(self.job) {
+            tty = pump(tty);
+        }
+        let Some(queues) = tty.jobs.get_mut(&self.job) else {
+            return Err(FsError::WouldBlock);
+        };
+        if queues.input.is_empty() {
+            if core::mem::take(&mut queues.eof) {
+                return Ok(0);
+            }
+            return Err(FsError::WouldBlock);
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            let Some(b) = queues.input.pop_front() else { break };
+            buf[n] = b;
+            n += 1;
+            if b == b'\n' {
+                break;
+            }
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        let mut tty = TTY.lock();
+        let queues = tty.jobs.entry(self.job).or_default();
+        queues.output.extend_from_slice(buf);
+        if self.unbuffered || queues.output.len() >= OUTPUT_FLUSH_LEN {
+            tty.flush(self.job);
+        } else if let Some(end) = queues.output.iter().rposition(|&b| b == b'\n') {
+            let rest = queues.output.split_off(end + 1);
+            let lines = core::mem::replace(&mut queues.output, rest);
+            tty.draw(&lines);
+        }
+        Ok(buf.len())
+    }
+
+    /// TTY_GETMODE and TTY_SETMODE switch the job between canonical mode
+    /// (edited lines, echoed) and raw mode (keys as typed, no echo);
+    /// TTY_GETSIZE gives the text screen size
+    fn ioctl(&mut self, request: u64, arg: u64) -> Result<u64, FsError> {
+        let mut tty = TTY.lock();
+        let queues = tty.jobs.entry(self.job).or_default();
+        match request {
+            abi::TTY_GETMODE => Ok(if queues.raw { abi::TTY_RAW } else { abi::TTY_CANONICAL }),
+            abi::TTY_SETMODE => {
+                queues.raw = match arg {
+                    abi::TTY_CANONICAL => false,
+                    abi::TTY_RAW => true,
+                    _ => return Err(FsError::Invalid),
+                };
+                // A half-typed line does not carry over
+                if tty.foreground == Some(self.job) {
+                    tty.editor.take();
+                }
+                Ok(0)
+            }
+            abi::TTY_GETSIZE => {
+                let (cols, rows) = framebuffer::text_size();
+                Ok((rows as u64) << 32 | cols as u64)
+            }
+            _ => Err(FsError::Invalid),
+        }
+    }
+
+    fn describe(&self) -> String {
+        String::from("tty")
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        Some(Metadata { file_type: FileType::Device, size: 0, mode: 0o620, created: 0, modified: 0 })
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileHandle>> {
+        Some(Box::new(TtyHandle::new(self.job, self.unbuffered)))
+    }
+}
@@ -5,24 +5,61 @@
 //! /bin - system binaries (commands)
 //! /etc - configuration files
 //! /home - user home directories
-//! /tmp - temporary files
+//! /tmp - temporary files, under a size quota (see `fs::quota`)
 //! /dev - device files
 //! /usr - user programs
 //! /var - variable data (logs, etc)
+//! /host - read-only files shared by QEMU via fw_cfg (when present)
+//! /proc - kernel state, generated on every read (see `fs::procfs`)
+//! /sys - runtime tunables (see `fs::sysfs`)
 
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::format;
 use alloc::collections::BTreeMap;
-use core::ffi::CStr;
-use crate::ipc::message::{FSRequest, FSResponse};
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::ipc::message::{FSRequest, FSResponse, Message, ServiceEvent};
+use crate::ipc::{bus, registry};
+use crate::ipc::registry::Health;
 use crate::boot::limine;
-use crate::fs::tar;
-use crate::fs::vfs::{DeviceFileHandle, DeviceKind, FileHandle, FileSystem, FsError, MemFileHandle, OpenFlags};
+use crate::fs::{procfs, quota, sysfs, tar};
+use crate::fs::vfs::{
+    DeviceFileHandle, DeviceKind, FileBytes, FileHandle, FileSystem, FsError, MemFileHandle, OpenFlags, SeekFrom,
+};
+use crate::drivers::fw_cfg;
 use alloc::boxed::Box;
 
+/// Mount point of the fw_cfg host share
+const HOST_MOUNT: &str = "/host";
+/// Nodes in /dev; a node's device id is its position here
+const DEVICES: &[DeviceKind] = &[
+    DeviceKind::Null,
+    DeviceKind::Zero,
+    DeviceKind::Keyboard,
+    DeviceKind::Framebuffer,
+    DeviceKind::Serial,
+    DeviceKind::Mouse,
+    DeviceKind::Audio,
+    DeviceKind::Kbd,
+    DeviceKind::Console,
+];
+/// Most a whole-file read of a device returns (/dev/zero never ends)
+const DEVICE_READ_LIMIT: usize = 64 * 1024;
+
+/// Mount point of procfs
+const PROC_MOUNT: &str = "/proc";
+/// Mount point of sysfs
+const SYS_MOUNT: &str = "/sys";
+/// Most symbolic links followed in one path lookup, like Linux; more is
+/// taken for a loop
+const MAX_LINK_FOLLOWS: usize = 40;
+/// Every bootloader module as a file, under its name
+const MODULES_DIR: &str = "/boot/modules";
+/// The module unpacked into the root of the tree
+const INITRD_MODULE: &str = "initrd.tar";
+
 /// File type
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
     Regular,    // Regular file
     Directory,  // Directory
@@ -36,9 +73,47 @@ pub struct VNode {
     pub name: String,
     pub file_type: FileType,
     pub size: usize,
-    pub data: Option<Vec<u8>>,  // For regular files
+    /// For regular files; initrd files borrow the module memory until
+    /// modified, and readers share the rest (see `FileBytes`)
+    pub data: Option<FileBytes>,
     pub children: Option<BTreeMap<String, VNode>>,  // For directories
     pub device_id: Option<usize>,  // For device files
+    /// Path a symbolic link points to, as given when it was made
+    pub link_target: Option<String>,
+    /// Permission bits, as in `0o644`
+    pub mode: u16,
+    /// Milliseconds since boot, as there is no wall clock
+    pub created: u64,
+    pub modified: u64,
+}
+
+/// Space one mounted filesystem takes, as df shows it
+#[derive(Debug, Clone)]
+pub struct FsUsage {
+    pub filesystem: &'static str,
+    pub mount: &'static str,
+    /// Bytes it can hold; for the ramfs, what it holds plus the free
+    /// physical memory
+    pub size: u64,
+    pub used: u64,
+}
+
+/// What stat reports about a path
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub size: usize,
+    pub mode: u16,
+    pub created: u64,
+    pub modified: u64,
+}
+
+impl Metadata {
+    /// A node of a generated tree (/host, /proc, /sys), which has no times
+    fn generated(is_dir: bool, size: usize, mode: u16) -> Self {
+        let file_type = if is_dir { FileType::Directory } else { FileType::Regular };
+        Self { file_type, size, mode, created: 0, modified: 0 }
+    }
 }
 
 impl VNode {
@@ -51,19 +126,27 @@ impl VNode {
             data: None,
             children: Some(BTreeMap::new()),
             device_id: None,
+            link_target: None,
+            mode: 0o755,
+            created: now(),
+            modified: now(),
         }
     }
     
     /// Create new file
-    pub fn new_file(name: &str, data: Vec<u8>) -> Self {
-        let size = data.len();
+    pub fn new_file(name: &str, data: impl Into<FileBytes>) -> Self {
+        let data = data.into();
         Self {
             name: name.to_string(),
             file_type: FileType::Regular,
-            size,
+            size: data.len(),
             data: Some(data),
             children: None,
             device_id: None,
+            link_target: None,
+            mode: 0o644,
+            created: now(),
+            modified: now(),
         }
     }
     
@@ -76,10 +159,45 @@ impl VNode {
             data: None,
             children: None,
             device_id: Some(device_id),
+            link_target: None,
+            mode: 0o666,
+            created: now(),
+            modified: now(),
+        }
+    }
+
+    /// Create new symbolic link to `target`
+    pub fn new_link(name: &str, target: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            file_type: FileType::Link,
+            size: target.len(),
+            data: None,
+            children: None,
+            device_id: None,
+            link_target: Some(target.to_string()),
+            mode: 0o777,
+            created: now(),
+            modified: now(),
+        }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            file_type: self.file_type,
+            size: self.size,
+            mode: self.mode,
+            created: self.created,
+            modified: self.modified,
         }
     }
 }
 
+/// Timestamp for nodes created or changed now
+fn now() -> u64 {
+    crate::drivers::timer::get_uptime_ms()
+}
+
 /// Unix-like VFS Service
 pub struct VFSService {
     root: spin::Mutex<VNode>,
@@ -97,6 +215,10 @@ impl VFSService {
                 data: None,
                 children: None,
                 device_id: None,
+                link_target: None,
+                mode: 0o755,
+                created: 0,
+                modified: 0,
             }),
             current_dir: spin::Mutex::new(String::new()),
         }
@@ -121,7 +243,7 @@ impl VFSService {
         }
     }
 
-    fn insert_path(root: &mut VNode, path: &str, data: Option<Vec<u8>>, is_dir: bool) {
+    fn insert_path(root: &mut VNode, path: &str, data: FileBytes, is_dir: bool) {
         let clean = path.trim_start_matches('/').trim_start_matches("./");
         if clean.is_empty() {
             return;
@@ -129,7 +251,7 @@ impl VFSService {
 
         let components: Vec<&str> = clean.split('/').filter(|s| !s.is_empty()).collect();
 
-        fn insert_components(node: &mut VNode, comps: &[&str], data: &Option<Vec<u8>>, is_dir: bool) {
+        fn insert_components(node: &mut VNode, comps: &[&str], data: FileBytes, is_dir: bool) {
             if comps.is_empty() {
                 return;
             }
@@ -138,11 +260,14 @@ impl VFSService {
             let children = node.children.get_or_insert_with(BTreeMap::new);
 
             if comps.len() == 1 {
+                // Archive contents predate the boot, so they have no times
+                let mut entry = if is_dir { VNode::new_dir(name) } else { VNode::new_file(name, data) };
+                entry.created = 0;
+                entry.modified = 0;
                 if is_dir {
-                    children.entry(name.to_string()).or_insert_with(|| VNode::new_dir(name));
+                    children.entry(name.to_string()).or_insert(entry);
                 } else {
-                    let file_data = data.clone().unwrap_or_default();
-                    children.insert(name.to_string(), VNode::new_file(name, file_data));
+                    children.insert(name.to_string(), entry);
                 }
                 return;
             }
@@ -151,7 +276,41 @@ impl VFSService {
             insert_components(child, &comps[1..], data, is_dir);
         }
 
-        insert_components(root, &components, &data, is_dir);
+        insert_components(root, &components, data, is_dir);
+    }
+
+    /// Path relative to the /host share, if `path` (normalized) lies inside it
+    fn host_relative(path: &str) -> Option<&str> {
+        if !fw_cfg::has_files() {
+            return None;
+        }
+        if path == HOST_MOUNT {
+            Some("")
+        } else {
+            path.strip_prefix(HOST_MOUNT)?.strip_prefix('/')
+        }
+    }
+
+    /// Path relative to `mount`, if `path` (normalized) lies inside it
+    fn mount_relative<'a>(path: &'a str, mount: &str) -> Option<&'a str> {
+        if path == mount {
+            Some("")
+        } else {
+            path.strip_prefix(mount)?.strip_prefix('/')
+        }
+    }
+
+    fn proc_relative(path: &str) -> Option<&str> {
+        Self::mount_relative(path, PROC_MOUNT)
+    }
+
+    fn sys_relative(path: &str) -> Option<&str> {
+        Self::mount_relative(path, SYS_MOUNT)
+    }
+
+    /// Files cannot be created or removed in the generated trees
+    fn read_only(path: &str) -> bool {
+        Self::host_relative(path).is_some() || Self::proc_relative(path).is_some() || Self::sys_relative(path).is_some()
     }
 
     fn resolve_path_mut<'a>(node: &'a mut VNode, components: &[&str]) -> Option<&'a mut VNode> {
@@ -163,7 +322,8 @@ impl VFSService {
         Some(current)
     }
 
-    /// Initialize VFS with Unix-like directory tree
+    /// Initialize VFS with Unix-like directory tree (initrd contents are
+    /// added later by `load_initrd`)
     pub fn init(&self) {
         // Create root directory structure
         let mut root = VNode::new_dir("/");
@@ -186,6 +346,8 @@ impl VFSService {
         let mut etc_children = BTreeMap::new();
         etc_children.insert("hostname".to_string(),
             VNode::new_file("hostname", b"ospabOS\n".to_vec()));
+        etc_children.insert("resolv.conf".to_string(),
+            VNode::new_file("resolv.conf", b"nameserver 10.0.2.3\n".to_vec()));
         etc_children.insert("os-release".to_string(),
             VNode::new_file("os-release", 
                 b"NAME=\"ospabOS\"\nVERSION=\"0.1.0\"\nID=ospab\nPRETTY_NAME=\"ospabOS 0.1.0 Foundation\"\n".to_vec()));
@@ -209,11 +371,9 @@ impl VFSService {
         // /dev - device files
         let mut dev = VNode::new_dir("dev");
         let mut dev_children = BTreeMap::new();
-        dev_children.insert("null".to_string(), VNode::new_device("null", 0));
-        dev_children.insert("zero".to_string(), VNode::new_device("zero", 1));
-        dev_children.insert("keyboard".to_string(), VNode::new_device("keyboard", 2));
-        dev_children.insert("framebuffer".to_string(), VNode::new_device("framebuffer", 3));
-        dev_children.insert("serial".to_string(), VNode::new_device("serial", 4));
+        for (id, kind) in DEVICES.iter().enumerate() {
+            dev_children.insert(kind.name().to_string(), VNode::new_device(kind.name(), id));
+        }
         dev.children = Some(dev_children);
         children.insert("dev".to_string(), dev);
         
@@ -232,79 +392,88 @@ impl VFSService {
         let mut var_log = VNode::new_dir("log");
         var_log.children = Some(BTreeMap::new());
         var_children.insert("log".to_string(), var_log);
+        let mut var_backups = VNode::new_dir("backups");
+        var_backups.children = Some(BTreeMap::new());
+        var_children.insert("backups".to_string(), var_backups);
         var.children = Some(var_children);
         children.insert("var".to_string(), var);
         
-        root.children = Some(children);
+        // /host - contents served by fw_cfg on demand, never stored here
+        if fw_cfg::has_files() {
+            children.insert("host".to_string(), VNode::new_dir("host"));
+        }
         
-        // Load files from Limine modules into root
-        if let Some(modules) = limine::modules() {
-            for module in modules {
-                if module.path.is_null() {
-                    continue;
-                }
-
-                let path = unsafe {
-                    if let Ok(cstr) = CStr::from_ptr(module.path as *const i8).to_str() {
-                        cstr
-                    } else {
-                        continue;
-                    }
-                };
-
-                let filename = if let Some(pos) = path.rfind('/') {
-                    &path[pos + 1..]
-                } else {
-                    path
-                };
-
-                let data = unsafe {
-                    core::slice::from_raw_parts(module.address as *const u8, module.size as usize)
-                };
-
-                if filename.ends_with(".tar") {
-                    let entries = tar::parse_tar(data);
-                    for entry in entries {
-                        Self::insert_path(&mut root, &entry.path, Some(entry.data), entry.is_dir);
-                    }
-                    continue;
-                }
+        // /proc and /sys - generated, never stored here
+        children.insert("proc".to_string(), VNode::new_dir("proc"));
+        children.insert("sys".to_string(), VNode::new_dir("sys"));
+        
+        root.children = Some(children);
 
-                // Copy file data for plain modules
-                let file_data = data.to_vec();
-                Self::insert_path(&mut root, filename, Some(file_data), false);
+        // /var/lib/tomato and /etc/tomato - the package manager's database
+        // and configuration, where tomato-core says they are
+        for dir in tomato_core::paths::DIRS {
+            let mut node = &mut root;
+            for component in dir.split('/').filter(|c| !c.is_empty()) {
+                node = node
+                    .children
+                    .get_or_insert_with(BTreeMap::new)
+                    .entry(component.to_string())
+                    .or_insert_with(|| VNode::new_dir(component));
             }
         }
+        crate::boot::timeline::mark("vfs tree");
         
         *self.root.lock() = root;
         *self.current_dir.lock() = "/".to_string();
     }
     
-    /// Resolve path to VNode
-    fn resolve_path(&self, path: &str) -> Option<VNode> {
+    /// Normalized `path` with the symbolic links in it replaced by what
+    /// they point to, the last component only if `follow_last`. Parts that
+    /// do not exist are kept as they are, for paths about to be created
+    fn follow_links(&self, path: &str, follow_last: bool) -> Result<String, FsError> {
         let root = self.root.lock();
-        
-        if path == "/" {
-            return Some(root.clone());
-        }
-        
-        let path = path.trim_start_matches('/');
-        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-        
-        let mut current = root.clone();
-        
-        for component in components {
-            if let Some(ref children) = current.children {
-                if let Some(child) = children.get(component) {
-                    current = child.clone();
-                } else {
-                    return None;
+        // Still to look at, last component first
+        let mut pending: Vec<String> = path.split('/').rev().map(String::from).collect();
+        let mut resolved: Vec<String> = Vec::new();
+        let mut follows = 0;
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    resolved.pop();
+                    continue;
                 }
-            } else {
-                return None;
+                _ => resolved.push(component),
+            }
+            if pending.iter().all(|rest| rest.is_empty() || rest == ".") && !follow_last {
+                break;
+            }
+            let mut node = Some(&*root);
+            for name in &resolved {
+                node = node.and_then(|node| node.children.as_ref()?.get(name.as_str()));
+            }
+            let Some(target) = node.and_then(|node| node.link_target.as_ref()) else { continue };
+            follows += 1;
+            if follows > MAX_LINK_FOLLOWS {
+                return Err(FsError::Loop);
+            }
+            // Relative targets start in the directory holding the link
+            resolved.pop();
+            if target.starts_with('/') {
+                resolved.clear();
             }
+            pending.extend(target.split('/').rev().map(String::from));
+        }
+        Ok(format!("/{}", resolved.join("/")))
+    }
+
+    /// The node at normalized `path` under `root`, borrowed: callers hold
+    /// the tree lock and copy out only what they need
+    fn lookup<'a>(root: &'a VNode, path: &str) -> Option<&'a VNode> {
+        let mut current = root;
+        for component in path.split('/').filter(|s| !s.is_empty()) {
+            current = current.children.as_ref()?.get(component)?;
         }
-        
         Some(current)
     }
 
@@ -320,33 +489,190 @@ impl VFSService {
             }
         };
         let resolve_path = Self::normalize_path(&resolve_path);
+        let resolve_path = self.follow_links(&resolve_path, true)?;
+
+        if let Some(rel) = Self::host_relative(&resolve_path) {
+            if flags.writable() {
+                return Err(FsError::Permission);
+            }
+            if fw_cfg::is_dir(rel) {
+                return Err(FsError::NotFile);
+            }
+            let data = fw_cfg::read_file(rel).ok_or(FsError::NotFound)?;
+            return Ok(Box::new(MemFileHandle::new(data)));
+        }
+
+        if let Some(rel) = Self::proc_relative(&resolve_path) {
+            if flags.writable() {
+                return Err(FsError::Permission);
+            }
+            if procfs::is_dir(rel) {
+                return Err(FsError::NotFile);
+            }
+            let data = procfs::read_file(rel).ok_or(FsError::NotFound)?;
+            return Ok(Box::new(MemFileHandle::new(data)));
+        }
 
-        let node = self.resolve_path(&resolve_path).ok_or(FsError::NotFound)?;
+        if let Some(rel) = Self::sys_relative(&resolve_path) {
+            if flags.writable() {
+                return Err(FsError::Permission);
+            }
+            if sysfs::is_dir(rel) {
+                return Err(FsError::NotFile);
+            }
+            let data = sysfs::read_file(rel).ok_or(FsError::NotFound)?;
+            return Ok(Box::new(MemFileHandle::new(data)));
+        }
+
+        if flags.create || (flags.truncate && flags.writable()) {
+            self.prepare_file(&resolve_path, flags)?;
+        }
+        let root = self.root.lock();
+        let node = Self::lookup(&root, &resolve_path).ok_or(FsError::NotFound)?;
 
         match node.file_type {
+            // Writers work on the node itself, so every descriptor sees
+            // their changes at once
+            FileType::Regular if flags.writable() => Ok(Box::new(RamFileHandle { path: resolve_path, offset: 0, flags })),
+            // Readers share the bytes as they are now; a later write copies
+            // them for the file and leaves this descriptor its snapshot
             FileType::Regular => {
-                if matches!(flags, OpenFlags::WriteOnly | OpenFlags::ReadWrite) {
-                    return Err(FsError::Permission);
-                }
-                let data = node.data.unwrap_or_default();
-                Ok(Box::new(MemFileHandle::new(data)))
+                let metadata = node.metadata();
+                Ok(Box::new(MemFileHandle::new(node.data.clone().unwrap_or_default()).with_metadata(metadata)))
             }
             FileType::Device => {
-                let dev = match node.device_id.unwrap_or(0) {
-                    0 => DeviceKind::Null,
-                    1 => DeviceKind::Zero,
-                    2 => DeviceKind::Keyboard,
-                    3 => DeviceKind::Framebuffer,
-                    4 => DeviceKind::Serial,
-                    _ => return Err(FsError::Invalid),
-                };
-                Ok(Box::new(DeviceFileHandle::new(dev)))
+                let node = node.clone();
+                drop(root);
+                Ok(Box::new(Self::open_device(&node)?))
             }
             FileType::Directory => Err(FsError::NotFile),
             FileType::Link => Err(FsError::Invalid),
         }
     }
 
+    /// Create the regular file at normalized `path` for O_CREAT if it is
+    /// missing, and empty it for O_TRUNC
+    fn prepare_file(&self, path: &str, flags: OpenFlags) -> Result<(), FsError> {
+        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (name, parent_parts) = components.split_last().ok_or(FsError::NotFile)?;
+        let mut root = self.root.lock();
+        let parent = Self::resolve_path_mut(&mut root, parent_parts).ok_or(FsError::NotFound)?;
+        if parent.file_type != FileType::Directory {
+            return Err(FsError::NotDir);
+        }
+        let children = parent.children.get_or_insert_with(BTreeMap::new);
+        match children.get_mut(*name) {
+            Some(node) => {
+                if node.file_type == FileType::Regular && flags.truncate && flags.writable() {
+                    if quota::contains(path) {
+                        quota::resize(node.size as u64, 0);
+                    }
+                    node.data = Some(FileBytes::default());
+                    node.size = 0;
+                    node.modified = now();
+                }
+            }
+            None if flags.create => {
+                children.insert(name.to_string(), VNode::new_file(name, Vec::new()));
+                parent.modified = now();
+            }
+            None => return Err(FsError::NotFound),
+        }
+        Ok(())
+    }
+
+    /// The regular file at normalized `path`
+    fn file_node<'a>(root: &'a mut VNode, path: &str) -> Result<&'a mut VNode, FsError> {
+        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let node = Self::resolve_path_mut(root, &components).ok_or(FsError::NotFound)?;
+        if node.file_type != FileType::Regular {
+            return Err(FsError::NotFile);
+        }
+        Ok(node)
+    }
+
+    /// Read from byte `offset` of the file at `path`; 0 past the end
+    fn read_at(&self, path: &str, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut root = self.root.lock();
+        let node = Self::file_node(&mut root, path)?;
+        let data = node.data.as_deref().unwrap_or_default();
+        if offset >= data.len() {
+            return Ok(0);
+        }
+        let count = buf.len().min(data.len() - offset);
+        buf[..count].copy_from_slice(&data[offset..offset + count]);
+        Ok(count)
+    }
+
+    /// Write `buf` at byte `offset` of the file at `path`, or at its end for
+    /// None, filling any gap with zeros; returns the offset after it
+    fn write_at(&self, path: &str, offset: Option<usize>, buf: &[u8]) -> Result<usize, FsError> {
+        let mut root = self.root.lock();
+        let len = Self::file_node(&mut root, path)?.size;
+        let start = offset.unwrap_or(len);
+        let end = start.checked_add(buf.len()).ok_or(FsError::Invalid)?;
+        let in_tmp = quota::contains(path);
+        if in_tmp {
+            quota::reserve(len as u64, end.max(len) as u64)?;
+        }
+        let node = Self::file_node(&mut root, path)?;
+        let data = node.data.get_or_insert_with(FileBytes::default).to_mut();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        if in_tmp {
+            quota::resize(len as u64, data.len() as u64);
+        }
+        node.size = data.len();
+        node.modified = now();
+        Ok(end)
+    }
+
+    fn file_metadata(&self, path: &str) -> Result<Metadata, FsError> {
+        let mut root = self.root.lock();
+        Ok(Self::file_node(&mut root, path)?.metadata())
+    }
+
+    /// The node at `path` if it is a device; other nodes are not cloned
+    fn device_at(&self, path: &str) -> Option<VNode> {
+        let root = self.root.lock();
+        Self::lookup(&root, path).filter(|node| node.file_type == FileType::Device).cloned()
+    }
+
+    /// Handle on the driver behind device node `node`
+    fn open_device(node: &VNode) -> Result<DeviceFileHandle, FsError> {
+        let kind = node.device_id.and_then(|id| DEVICES.get(id)).copied().ok_or(FsError::Invalid)?;
+        if kind == DeviceKind::Kbd {
+            // Only what is typed from now on
+            crate::drivers::keyboard::flush_raw();
+        }
+        Ok(DeviceFileHandle::new(kind))
+    }
+
+    /// What a whole-file read of device node `node` sees: one read of up
+    /// to DEVICE_READ_LIMIT bytes
+    fn read_device(node: &VNode) -> Result<Vec<u8>, FsError> {
+        let mut handle = Self::open_device(node)?;
+        let mut data = alloc::vec![0u8; DEVICE_READ_LIMIT];
+        let n = handle.read(&mut data)?;
+        data.truncate(n);
+        Ok(data)
+    }
+
+    /// Write all of `data` to device node `node`
+    fn write_device(node: &VNode, data: &[u8]) -> Result<(), FsError> {
+        let mut handle = Self::open_device(node)?;
+        let mut done = 0;
+        while done < data.len() {
+            match handle.write(&data[done..])? {
+                0 => return Err(FsError::NoSpace),
+                n => done += n,
+            }
+        }
+        Ok(())
+    }
+
     /// Process filesystem request
     pub fn process(&self, request: FSRequest) -> FSResponse {
         match request {
@@ -364,8 +690,37 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, true) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
+                
+                if let Some(rel) = Self::host_relative(&resolve_path) {
+                    return match fw_cfg::list_dir(rel) {
+                        Some(names) => FSResponse::DirListing(names),
+                        None if fw_cfg::is_file(rel) => FSResponse::Error(FsError::NotDir, None),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
+                
+                if let Some(rel) = Self::proc_relative(&resolve_path) {
+                    return match procfs::list_dir(rel) {
+                        Some(names) => FSResponse::DirListing(names),
+                        None if procfs::read_file(rel).is_some() => FSResponse::Error(FsError::NotDir, None),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
                 
-                if let Some(node) = self.resolve_path(&resolve_path) {
+                if let Some(rel) = Self::sys_relative(&resolve_path) {
+                    return match sysfs::list_dir(rel) {
+                        Some(names) => FSResponse::DirListing(names),
+                        None if sysfs::read_file(rel).is_some() => FSResponse::Error(FsError::NotDir, None),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
+                
+                let root = self.root.lock();
+                if let Some(node) = Self::lookup(&root, &resolve_path) {
                     if node.file_type == FileType::Directory {
                         if let Some(ref children) = node.children {
                             let mut names: Vec<String> = children.keys().cloned().collect();
@@ -375,10 +730,10 @@ impl VFSService {
                             FSResponse::DirListing(Vec::new())
                         }
                     } else {
-                        FSResponse::Error("Not a directory".to_string())
+                        FSResponse::Error(FsError::NotDir, None)
                     }
                 } else {
-                    FSResponse::Error("Directory not found".to_string())
+                    FSResponse::Error(FsError::NotFound, None)
                 }
             }
             FSRequest::ReadFile { path } => {
@@ -393,23 +748,59 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, true) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
+                
+                if let Some(rel) = Self::host_relative(&resolve_path) {
+                    if fw_cfg::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotFile, None);
+                    }
+                    return match fw_cfg::read_file(rel) {
+                        Some(data) => FSResponse::FileData(data.into()),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
+                
+                if let Some(rel) = Self::proc_relative(&resolve_path) {
+                    if procfs::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotFile, None);
+                    }
+                    return match procfs::read_file(rel) {
+                        Some(data) => FSResponse::FileData(data.into()),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
+                
+                if let Some(rel) = Self::sys_relative(&resolve_path) {
+                    if sysfs::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotFile, None);
+                    }
+                    return match sysfs::read_file(rel) {
+                        Some(data) => FSResponse::FileData(data.into()),
+                        None => FSResponse::Error(FsError::NotFound, None),
+                    };
+                }
                 
-                if let Some(node) = self.resolve_path(&resolve_path) {
+                let root = self.root.lock();
+                if let Some(node) = Self::lookup(&root, &resolve_path) {
                     match node.file_type {
-                        FileType::Regular => {
-                            if let Some(data) = node.data {
-                                FSResponse::FileData(data)
-                            } else {
-                                FSResponse::FileData(Vec::new())
-                            }
-                        }
+                        // Shared, not copied; see `FileBytes`
+                        FileType::Regular => FSResponse::FileData(node.data.clone().unwrap_or_default()),
+                        // Drivers are not called with the tree locked
                         FileType::Device => {
-                            FSResponse::FileData(b"<device file>".to_vec())
+                            let node = node.clone();
+                            drop(root);
+                            match Self::read_device(&node) {
+                                Ok(data) => FSResponse::FileData(data.into()),
+                                Err(e) => FSResponse::Error(e, None),
+                            }
                         }
-                        _ => FSResponse::Error("Cannot read this file type".to_string())
+                        _ => FSResponse::Error(FsError::NotFile, None)
                     }
                 } else {
-                    FSResponse::Error(format!("File not found: {}", path))
+                    FSResponse::Error(FsError::NotFound, None)
                 }
             }
             FSRequest::WriteFile { path, data } => {
@@ -424,29 +815,71 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, true) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
+                if let Some(rel) = Self::sys_relative(&resolve_path) {
+                    return match sysfs::write_file(rel, &data) {
+                        Ok(()) => FSResponse::Success,
+                        Err(e) => FSResponse::Error(e, None),
+                    };
+                }
+                if Self::read_only(&resolve_path) {
+                    return FSResponse::Error(FsError::ReadOnly, None);
+                }
+                // Writing to a device node goes to its driver
+                if let Some(node) = self.device_at(&resolve_path) {
+                    return match Self::write_device(&node, &data) {
+                        Ok(()) => FSResponse::Success,
+                        Err(e) => FSResponse::Error(e, None),
+                    };
+                }
                 let clean = resolve_path.trim_start_matches('/');
                 if clean.is_empty() {
-                    return FSResponse::Error("Invalid path".to_string());
+                    return FSResponse::Error(FsError::Invalid, None);
                 }
                 let components: Vec<&str> = clean.split('/').filter(|s| !s.is_empty()).collect();
                 if components.is_empty() {
-                    return FSResponse::Error("Invalid path".to_string());
+                    return FSResponse::Error(FsError::Invalid, None);
                 }
                 let (parent_parts, name) = components.split_at(components.len() - 1);
                 let mut root = self.root.lock();
+                // What the new file replaces, a directory with all it holds
+                // included
+                let in_tmp = quota::contains(&resolve_path);
+                let old = match Self::lookup(&root, &resolve_path) {
+                    Some(node) if in_tmp => Self::tree_bytes(node, &resolve_path, None, false),
+                    _ => 0,
+                };
+                if in_tmp {
+                    if let Err(e) = quota::reserve(old, data.len() as u64) {
+                        return FSResponse::Error(e, None);
+                    }
+                }
                 let parent = if parent_parts.is_empty() {
                     &mut *root
                 } else {
                     match Self::resolve_path_mut(&mut root, parent_parts) {
                         Some(node) => node,
-                        None => return FSResponse::Error("Directory not found".to_string()),
+                        None => return FSResponse::Error(FsError::NotFound, None),
                     }
                 };
                 if parent.file_type != FileType::Directory {
-                    return FSResponse::Error("Not a directory".to_string());
+                    return FSResponse::Error(FsError::NotDir, None);
                 }
+                parent.modified = now();
                 let children = parent.children.get_or_insert_with(BTreeMap::new);
-                children.insert(name[0].to_string(), VNode::new_file(name[0], data));
+                if in_tmp {
+                    quota::resize(old, data.len() as u64);
+                }
+                let mut file = VNode::new_file(name[0], data);
+                // Overwriting keeps the creation time and permissions
+                if let Some(old) = children.get(name[0]) {
+                    file.created = old.created;
+                    file.mode = old.mode;
+                }
+                children.insert(name[0].to_string(), file);
                 FSResponse::Success
             }
             FSRequest::CreateDir { path } => {
@@ -461,6 +894,13 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, false) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
+                if Self::read_only(&resolve_path) {
+                    return FSResponse::Error(FsError::ReadOnly, None);
+                }
                 let clean = resolve_path.trim_start_matches('/');
                 if clean.is_empty() {
                     return FSResponse::Success;
@@ -473,17 +913,21 @@ impl VFSService {
                 } else {
                     match Self::resolve_path_mut(&mut root, parent_parts) {
                         Some(node) => node,
-                        None => return FSResponse::Error("Directory not found".to_string()),
+                        None => return FSResponse::Error(FsError::NotFound, None),
                     }
                 };
                 if parent.file_type != FileType::Directory {
-                    return FSResponse::Error("Not a directory".to_string());
+                    return FSResponse::Error(FsError::NotDir, None);
                 }
                 let children = parent.children.get_or_insert_with(BTreeMap::new);
-                children.entry(name[0].to_string()).or_insert_with(|| VNode::new_dir(name[0]));
+                if !children.contains_key(name[0]) {
+                    children.insert(name[0].to_string(), VNode::new_dir(name[0]));
+                    parent.modified = now();
+                }
                 FSResponse::Success
             }
-            FSRequest::Delete { path } => {
+            FSRequest::Symlink { target, path } => self.symlink(&target, &path),
+            FSRequest::ReadLink { path } => {
                 let resolve_path = if path.starts_with('/') {
                     path.clone()
                 } else {
@@ -495,31 +939,20 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
-                let clean = resolve_path.trim_start_matches('/');
-                if clean.is_empty() {
-                    return FSResponse::Error("Invalid path".to_string());
-                }
-                let components: Vec<&str> = clean.split('/').filter(|s| !s.is_empty()).collect();
-                let (parent_parts, name) = components.split_at(components.len() - 1);
-                let mut root = self.root.lock();
-                let parent = if parent_parts.is_empty() {
-                    &mut *root
-                } else {
-                    match Self::resolve_path_mut(&mut root, parent_parts) {
-                        Some(node) => node,
-                        None => return FSResponse::Error("Directory not found".to_string()),
-                    }
+                let resolve_path = match self.follow_links(&resolve_path, false) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
                 };
-                if parent.file_type != FileType::Directory {
-                    return FSResponse::Error("Not a directory".to_string());
-                }
-                if let Some(children) = parent.children.as_mut() {
-                    if children.remove(name[0]).is_some() {
-                        return FSResponse::Success;
-                    }
+                match Self::lookup(&self.root.lock(), &resolve_path) {
+                    Some(node) => match &node.link_target {
+                        Some(target) => FSResponse::LinkTarget(target.clone()),
+                        None => FSResponse::Error(FsError::Invalid, None),
+                    },
+                    None => FSResponse::Error(FsError::NotFound, None),
                 }
-                FSResponse::Error("Not found".to_string())
             }
+            FSRequest::Delete { path } => self.delete(&path, false),
+            FSRequest::DeleteTree { path } => self.delete(&path, true),
             FSRequest::ChangeDir { path } => {
                 let resolve_path = if path.starts_with('/') {
                     path.clone()
@@ -547,23 +980,322 @@ impl VFSService {
                     }
                 };
                 let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, true) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
                 
-                if let Some(node) = self.resolve_path(&resolve_path) {
-                    if node.file_type == FileType::Directory {
+                if let Some(rel) = Self::host_relative(&resolve_path) {
+                    if !fw_cfg::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotDir, None);
+                    }
+                    *self.current_dir.lock() = resolve_path;
+                    return FSResponse::Success;
+                }
+                
+                if let Some(rel) = Self::proc_relative(&resolve_path) {
+                    if !procfs::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotDir, None);
+                    }
+                    *self.current_dir.lock() = resolve_path;
+                    return FSResponse::Success;
+                }
+                
+                if let Some(rel) = Self::sys_relative(&resolve_path) {
+                    if !sysfs::is_dir(rel) {
+                        return FSResponse::Error(FsError::NotDir, None);
+                    }
+                    *self.current_dir.lock() = resolve_path;
+                    return FSResponse::Success;
+                }
+                
+                let is_dir = Self::lookup(&self.root.lock(), &resolve_path).map(|node| node.file_type == FileType::Directory);
+                if let Some(is_dir) = is_dir {
+                    if is_dir {
                         *self.current_dir.lock() = resolve_path;
                         FSResponse::Success
                     } else {
-                        FSResponse::Error("Not a directory".to_string())
+                        FSResponse::Error(FsError::NotDir, None)
                     }
                 } else {
-                    FSResponse::Error("Directory not found".to_string())
+                    FSResponse::Error(FsError::NotFound, None)
                 }
             }
             FSRequest::GetCwd => {
                 let cwd = self.current_dir.lock();
                 FSResponse::Cwd(cwd.clone())
             }
+            FSRequest::Stat { path } => {
+                let resolve_path = if path == "." || path.is_empty() {
+                    self.current_dir.lock().clone()
+                } else if path.starts_with('/') {
+                    path.clone()
+                } else {
+                    let cwd = self.current_dir.lock().clone();
+                    if cwd == "/" {
+                        format!("/{}", path)
+                    } else {
+                        format!("{}/{}", cwd, path)
+                    }
+                };
+                let resolve_path = Self::normalize_path(&resolve_path);
+                let resolve_path = match self.follow_links(&resolve_path, true) {
+                    Ok(path) => path,
+                    Err(e) => return FSResponse::Error(e, None),
+                };
+                match self.stat(&resolve_path) {
+                    Some(metadata) => FSResponse::Metadata(metadata),
+                    None => FSResponse::Error(FsError::NotFound, None),
+                }
+            }
+        }
+    }
+
+    /// Make `path` a symbolic link to `target`, which need not exist
+    fn symlink(&self, target: &str, path: &str) -> FSResponse {
+        if target.is_empty() {
+            return FSResponse::Error(FsError::NotFound, None);
+        }
+        let resolve_path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            let cwd = self.current_dir.lock().clone();
+            if cwd == "/" {
+                format!("/{}", path)
+            } else {
+                format!("{}/{}", cwd, path)
+            }
+        };
+        let resolve_path = Self::normalize_path(&resolve_path);
+        if Self::read_only(&resolve_path) {
+            return FSResponse::Error(FsError::ReadOnly, None);
+        }
+        let resolve_path = match self.follow_links(&resolve_path, false) {
+            Ok(path) => path,
+            Err(e) => return FSResponse::Error(e, None),
+        };
+        let components: Vec<&str> = resolve_path.split('/').filter(|s| !s.is_empty()).collect();
+        let Some((name, parent_parts)) = components.split_last() else {
+            return FSResponse::Error(FsError::Exists, None);
+        };
+        let mut root = self.root.lock();
+        let Some(parent) = Self::resolve_path_mut(&mut root, parent_parts) else {
+            return FSResponse::Error(FsError::NotFound, None);
+        };
+        if parent.file_type != FileType::Directory {
+            return FSResponse::Error(FsError::NotDir, None);
+        }
+        let children = parent.children.get_or_insert_with(BTreeMap::new);
+        if children.contains_key(*name) {
+            return FSResponse::Error(FsError::Exists, None);
+        }
+        children.insert(name.to_string(), VNode::new_link(name, target));
+        parent.modified = now();
+        FSResponse::Success
+    }
+
+    /// Remove the node at `path`; a directory must be empty unless
+    /// `recursive`, and neither / nor the current directory or one above it
+    /// can go
+    fn delete(&self, path: &str, recursive: bool) -> FSResponse {
+        let resolve_path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            let cwd = self.current_dir.lock().clone();
+            if cwd == "/" {
+                format!("/{}", path)
+            } else {
+                format!("{}/{}", cwd, path)
+            }
+        };
+        let resolve_path = Self::normalize_path(&resolve_path);
+        let resolve_path = match self.follow_links(&resolve_path, false) {
+            Ok(path) => path,
+            Err(e) => return FSResponse::Error(e, None),
+        };
+        if Self::read_only(&resolve_path) {
+            return FSResponse::Error(FsError::ReadOnly, None);
+        }
+        let clean = resolve_path.trim_start_matches('/');
+        if clean.is_empty() {
+            return FSResponse::Error(FsError::Busy, None);
+        }
+        {
+            let cwd = self.current_dir.lock();
+            if *cwd == resolve_path || cwd.starts_with(&format!("{}/", resolve_path)) {
+                return FSResponse::Error(FsError::Busy, None);
+            }
+        }
+        let components: Vec<&str> = clean.split('/').filter(|s| !s.is_empty()).collect();
+        let (parent_parts, name) = components.split_at(components.len() - 1);
+        let mut root = self.root.lock();
+        let parent = if parent_parts.is_empty() {
+            &mut *root
+        } else {
+            match Self::resolve_path_mut(&mut root, parent_parts) {
+                Some(node) => node,
+                None => return FSResponse::Error(FsError::NotFound, None),
+            }
+        };
+        if parent.file_type != FileType::Directory {
+            return FSResponse::Error(FsError::NotDir, None);
+        }
+        let Some(children) = parent.children.as_mut() else {
+            return FSResponse::Error(FsError::NotFound, None);
+        };
+        let Some(node) = children.get(name[0]) else {
+            return FSResponse::Error(FsError::NotFound, None);
+        };
+        // The whole subtree goes with the node
+        let has_entries = node.children.as_ref().is_some_and(|entries| !entries.is_empty());
+        if has_entries && !recursive {
+            return FSResponse::Error(FsError::NotEmpty, None);
+        }
+        if quota::contains(&resolve_path) {
+            quota::resize(Self::tree_bytes(node, &resolve_path, None, false), 0);
+        }
+        children.remove(name[0]);
+        parent.modified = now();
+        FSResponse::Success
+    }
+
+    /// Bytes of file data in `node` and everything under it; with
+    /// `heap_only`, only data copied to the heap, not borrowed from a boot
+    /// module. `path` is where `node` is, to leave out `skip` and what
+    /// other filesystems put at /host
+    fn tree_bytes(node: &VNode, path: &str, skip: Option<&str>, heap_only: bool) -> u64 {
+        if Some(path) == skip {
+            return 0;
+        }
+        let own = match &node.data {
+            Some(data) if heap_only && !data.on_heap() => 0,
+            Some(data) => data.len() as u64,
+            None => 0,
+        };
+        let children = node.children.iter().flat_map(|children| children.iter());
+        own + children
+            .map(|(name, child)| Self::tree_bytes(child, &Self::join(path, name), skip, heap_only))
+            .sum::<u64>()
+    }
+
+    fn join(dir: &str, name: &str) -> String {
+        if dir == "/" {
+            format!("/{}", name)
+        } else {
+            format!("{}/{}", dir, name)
+        }
+    }
+
+    /// Bytes in /tmp, walked; `quota::used` keeps the running count
+    fn tmp_bytes(root: &VNode) -> u64 {
+        root.children
+            .as_ref()
+            .and_then(|children| children.get(&quota::DIR[1..]))
+            .map_or(0, |tmp| Self::tree_bytes(tmp, quota::DIR, None, false))
+    }
+
+    /// Bytes in the ramfs outside /tmp, and in /tmp
+    fn ram_usage(&self) -> (u64, u64) {
+        let root = self.root.lock();
+        (Self::tree_bytes(&root, "/", Some(quota::DIR), true), quota::used())
+    }
+
+    /// Size of every directory from `path` down, deepest first and `path`
+    /// last, as du walks them. Symbolic links below `path` are not
+    /// followed; /proc and /sys are empty and /host holds the shared files
+    fn disk_usage(&self, path: &str) -> Result<Vec<(String, u64)>, FsError> {
+        let resolve_path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            Self::join(&self.current_dir.lock(), path)
+        };
+        let resolve_path = self.follow_links(&Self::normalize_path(&resolve_path), true)?;
+        let mut sizes = Vec::new();
+        if let Some(rel) = Self::host_relative(&resolve_path) {
+            if !fw_cfg::is_file(rel) && !fw_cfg::is_dir(rel) {
+                return Err(FsError::NotFound);
+            }
+            sizes.push((path.to_string(), Self::host_bytes(rel)));
+            return Ok(sizes);
+        }
+        if Self::proc_relative(&resolve_path).is_some() || Self::sys_relative(&resolve_path).is_some() {
+            sizes.push((path.to_string(), 0));
+            return Ok(sizes);
+        }
+        let root = self.root.lock();
+        let mut node = &*root;
+        for component in resolve_path.split('/').filter(|s| !s.is_empty()) {
+            node = node.children.as_ref().and_then(|children| children.get(component)).ok_or(FsError::NotFound)?;
+        }
+        Self::walk_usage(node, &resolve_path, path, &mut sizes);
+        Ok(sizes)
+    }
+
+    /// Push the size of each directory under `node` (at `path`, shown as
+    /// `shown`); returns the size of `node`
+    fn walk_usage(node: &VNode, path: &str, shown: &str, sizes: &mut Vec<(String, u64)>) -> u64 {
+        if path == HOST_MOUNT {
+            let bytes = Self::host_bytes("");
+            sizes.push((shown.to_string(), bytes));
+            return bytes;
+        }
+        let mut total = node.data.as_ref().map_or(0, |data| data.len() as u64);
+        if let Some(children) = &node.children {
+            for (name, child) in children {
+                let child_shown = if shown.ends_with('/') {
+                    format!("{}{}", shown, name)
+                } else {
+                    format!("{}/{}", shown, name)
+                };
+                let child_path = Self::join(path, name);
+                if child.children.is_some() || child_path == HOST_MOUNT {
+                    total += Self::walk_usage(child, &child_path, &child_shown, sizes);
+                } else {
+                    total += child.data.as_ref().map_or(0, |data| data.len() as u64);
+                }
+            }
+        }
+        sizes.push((shown.to_string(), total));
+        total
+    }
+
+    /// Bytes of the files shared by the host under `rel`
+    fn host_bytes(rel: &str) -> u64 {
+        let rel = rel.trim_matches('/');
+        fw_cfg::files()
+            .iter()
+            .filter(|file| rel.is_empty() || file.path == rel || file.path.starts_with(&format!("{}/", rel)))
+            .map(|file| file.size as u64)
+            .sum()
+    }
+
+    /// Metadata of the node at normalized `path`
+    fn stat(&self, path: &str) -> Option<Metadata> {
+        if let Some(rel) = Self::host_relative(path) {
+            if fw_cfg::is_dir(rel) {
+                return Some(Metadata::generated(true, 0, 0o555));
+            }
+            return fw_cfg::read_file(rel).map(|data| Metadata::generated(false, data.len(), 0o444));
+        }
+        if let Some(rel) = Self::proc_relative(path) {
+            if procfs::is_dir(rel) {
+                return Some(Metadata::generated(true, 0, 0o555));
+            }
+            return procfs::read_file(rel).map(|data| Metadata::generated(false, data.len(), 0o444));
+        }
+        if let Some(rel) = Self::sys_relative(path) {
+            if sysfs::is_dir(rel) {
+                return Some(Metadata::generated(true, 0, 0o555));
+            }
+            // Root may write the tunables
+            return sysfs::read_file(rel).map(|data| Metadata::generated(false, data.len(), 0o644));
         }
+        let root = self.root.lock();
+        let mut current = &*root;
+        for component in path.split('/').filter(|s| !s.is_empty()) {
+            current = current.children.as_ref()?.get(component)?;
+        }
+        Some(current.metadata())
     }
 }
 
@@ -576,21 +1308,214 @@ impl FileSystem for VFSService {
 /// Global VFS instance
 static VFS: spin::Mutex<Option<VFSService>> = spin::Mutex::new(None);
 
+/// Run `f` on the VFS; for file handles, which outlive the lock taken by
+/// `open`
+fn with_service<R>(f: impl FnOnce(&VFSService) -> Result<R, FsError>) -> Result<R, FsError> {
+    match *VFS.lock() {
+        Some(ref vfs) => f(vfs),
+        None => Err(FsError::Io),
+    }
+}
+
+/// A regular file of the tree opened for writing. It keeps only the path,
+/// so reads see the latest contents and O_APPEND writes land at the end
+/// of the file as it is at the time of each write
+struct RamFileHandle {
+    /// Normalized absolute path
+    path: String,
+    offset: usize,
+    flags: OpenFlags,
+}
+
+impl FileHandle for RamFileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if !self.flags.readable() {
+            return Err(FsError::Permission);
+        }
+        let count = with_service(|vfs| vfs.read_at(&self.path, self.offset, buf))?;
+        self.offset += count;
+        Ok(count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        let at = if self.flags.append { None } else { Some(self.offset) };
+        self.offset = with_service(|vfs| vfs.write_at(&self.path, at, buf))?;
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let len = with_service(|vfs| vfs.file_metadata(&self.path))?.size;
+        self.offset = pos.resolve(self.offset, len)?;
+        Ok(self.offset as u64)
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        with_service(|vfs| vfs.file_metadata(&self.path)).ok()
+    }
+
+    fn describe(&self) -> String {
+        self.path.clone()
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn FileHandle>> {
+        Some(Box::new(RamFileHandle { path: self.path.clone(), offset: self.offset, flags: self.flags }))
+    }
+}
+
+/// Set once the initrd modules are in the tree
+static INITRD_LOADED: AtomicBool = AtomicBool::new(false);
+
 /// Initialize VFS service
 pub fn init() {
     let mut vfs = VFS.lock();
     let service = VFSService::new();
     service.init();
     *vfs = Some(service);
+    drop(vfs);
+
+    if let Err(e) = bus::serve(bus::VFS_MAILBOX, serve) {
+        crate::serial_println!("[VFS] Cannot serve the bus: {}", e);
+    }
+    let _ = registry::register(registry::ServiceDescriptor {
+        name: "vfs",
+        mailbox: bus::VFS_MAILBOX,
+        capabilities: &["fs.read", "fs.write", "fs.dir"],
+        probe: health,
+    });
+    bus::publish(ServiceEvent::Ready("vfs"));
+}
+
+/// Registry health probe: ready once the initrd has been loaded
+fn health() -> Health {
+    if VFS.lock().is_none() {
+        Health::Down
+    } else if INITRD_LOADED.load(Ordering::Acquire) {
+        Health::Ready
+    } else {
+        Health::Starting
+    }
+}
+
+/// Add the files from the Limine modules
+///
+/// The `initrd.tar` module is unpacked into the root, other modules appear
+/// there as plain files, and all of them, the archive included, under
+/// /boot/modules. Module memory stays mapped for the lifetime of the
+/// kernel, so nothing is copied: file nodes borrow their bytes in place
+/// and get a heap copy the first time they are written. Indexing happens
+/// before the VFS lock is taken, so the rest of the tree stays usable
+/// meanwhile.
+pub fn load_initrd() {
+    let mut files = Vec::new();
+    for module in limine::module_list() {
+        if module.name.is_empty() {
+            continue;
+        }
+        files.push((alloc::format!("{}/{}", MODULES_DIR, module.name), module.data, false));
+        if module.name == INITRD_MODULE || module.cmdline == INITRD_MODULE {
+            for entry in tar::index_tar(module.data) {
+                files.push((entry.path, entry.data, entry.is_dir));
+            }
+        } else {
+            files.push((module.name.to_string(), module.data, false));
+        }
+    }
+
+    if let Some(ref vfs) = *VFS.lock() {
+        let mut root = vfs.root.lock();
+        for (path, data, is_dir) in files {
+            VFSService::insert_path(&mut root, &path, FileBytes::Static(data), is_dir);
+        }
+        // The archive may have put files in /tmp
+        quota::set_used(VFSService::tmp_bytes(&root));
+    }
+    INITRD_LOADED.store(true, Ordering::Release);
+    bus::publish(ServiceEvent::Ready("initrd"));
+}
+
+/// Filesystems making up the tree, as (mount point, type)
+pub fn filesystems() -> Vec<(&'static str, &'static str)> {
+    let mut mounts = Vec::new();
+    if VFS.lock().is_some() {
+        mounts.push(("/", "ramfs"));
+    }
+    if INITRD_LOADED.load(Ordering::Acquire) {
+        mounts.push(("/", "initrd"));
+    }
+    if fw_cfg::has_files() {
+        mounts.push((HOST_MOUNT, "fw_cfg"));
+    }
+    if VFS.lock().is_some() {
+        mounts.push((PROC_MOUNT, "proc"));
+        mounts.push((SYS_MOUNT, "sysfs"));
+    }
+    mounts
+}
+
+/// How full each of `filesystems` is
+///
+/// The initrd is as large as the boot modules and always full; the ramfs
+/// counts what was written to the tree (initrd files it has not copied
+/// are the initrd's), able to grow into the free physical memory. /proc
+/// and /sys hold nothing. /tmp, part of the ramfs, gets a row of its own
+/// after it showing what it holds against its quota.
+pub fn usage() -> Vec<FsUsage> {
+    let (_, _, free_frames) = crate::mem::physical::stats();
+    let free = free_frames as u64 * 4096;
+    let (ramfs, tmp) = match *VFS.lock() {
+        Some(ref vfs) => vfs.ram_usage(),
+        None => (0, 0),
+    };
+    let mut usage: Vec<FsUsage> = filesystems()
+        .into_iter()
+        .map(|(mount, filesystem)| {
+            let (size, used) = match filesystem {
+                "ramfs" => (ramfs + free, ramfs),
+                "initrd" => {
+                    let modules: u64 = limine::module_list().iter().map(|module| module.data.len() as u64).sum();
+                    (modules, modules)
+                }
+                "fw_cfg" => {
+                    let shared = VFSService::host_bytes("");
+                    (shared, shared)
+                }
+                _ => (0, 0),
+            };
+            FsUsage { filesystem, mount, size, used }
+        })
+        .collect();
+    if let Some(ramfs) = usage.iter().position(|fs| fs.filesystem == "ramfs") {
+        usage.insert(ramfs + 1, FsUsage { filesystem: "ramfs", mount: quota::DIR, size: quota::size(), used: tmp });
+    }
+    usage
+}
+
+/// Size of every directory from `path` down, for du; see
+/// `VFSService::disk_usage`
+pub fn disk_usage(path: &str) -> Result<Vec<(String, u64)>, FsError> {
+    with_service(|vfs| vfs.disk_usage(path))
 }
 
 /// Process VFS request
+///
+/// Goes through the VFS mailbox on the message bus like any other client
+/// would; `serve` answers it.
 pub fn process_request(request: FSRequest) -> FSResponse {
-    if let Some(ref vfs) = *VFS.lock() {
-        vfs.process(request)
-    } else {
-        FSResponse::Error("VFS not initialized".to_string())
+    if VFS.lock().is_none() {
+        return FSResponse::Error(FsError::Io, Some("VFS not initialized".to_string()));
     }
+    bus::VFS.call(request)
+        .unwrap_or_else(|e| FSResponse::Error(FsError::Io, Some(e.to_string())))
+}
+
+/// Bus handler for the VFS mailbox
+fn serve(message: Message) -> Option<Message> {
+    let Message::FS(request) = message else { return None };
+    let response = match *VFS.lock() {
+        Some(ref vfs) => vfs.process(request),
+        None => FSResponse::Error(FsError::Io, Some("VFS not initialized".to_string())),
+    };
+    Some(Message::FSReply(response))
 }
 
 pub fn open(path: &str, flags: u64) -> Result<Box<dyn FileHandle>, FsError> {
@@ -598,3 +1523,124 @@ pub fn open(path: &str, flags: u64) -> Result<Box<dyn FileHandle>, FsError> {
     let service = vfs.as_ref().ok_or(FsError::Invalid)?;
     service.open(path, OpenFlags::from_bits(flags))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> String {
+        path.to_string()
+    }
+
+    /// /etc/motd and /home, with links /home/etc -> /etc,
+    /// /home/motd -> ../etc/motd and /home/loop -> /home/loop
+    fn tree() -> VFSService {
+        let vfs = VFSService::new();
+        for request in [
+            FSRequest::CreateDir { path: path("/etc") },
+            FSRequest::CreateDir { path: path("/home") },
+            FSRequest::WriteFile { path: path("/etc/motd"), data: b"hello".to_vec() },
+            FSRequest::Symlink { target: path("/etc"), path: path("/home/etc") },
+            FSRequest::Symlink { target: path("../etc/motd"), path: path("/home/motd") },
+            FSRequest::Symlink { target: path("/home/loop"), path: path("/home/loop") },
+        ] {
+            assert!(matches!(vfs.process(request), FSResponse::Success));
+        }
+        vfs
+    }
+
+    fn read(vfs: &VFSService, file: &str) -> Result<Vec<u8>, FsError> {
+        match vfs.process(FSRequest::ReadFile { path: path(file) }) {
+            FSResponse::FileData(data) => Ok(data.into_vec()),
+            FSResponse::Error(e, _) => Err(e),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test_case]
+    fn normalize_path_drops_empty_and_dot_components() {
+        assert_eq!(VFSService::normalize_path("/a//b/./c/"), "/a/b/c");
+        assert_eq!(VFSService::normalize_path("a/b"), "/a/b");
+        assert_eq!(VFSService::normalize_path(""), "/");
+    }
+
+    #[test_case]
+    fn normalize_path_stops_dotdot_at_root() {
+        assert_eq!(VFSService::normalize_path("/a/b/../c"), "/a/c");
+        assert_eq!(VFSService::normalize_path("/../../a"), "/a");
+        assert_eq!(VFSService::normalize_path("/a/.."), "/");
+    }
+
+    #[test_case]
+    fn follow_links_resolves_absolute_and_relative_targets() {
+        let vfs = tree();
+        assert_eq!(vfs.follow_links("/home/etc/motd", true).unwrap(), "/etc/motd");
+        assert_eq!(vfs.follow_links("/home/motd", true).unwrap(), "/etc/motd");
+        assert_eq!(read(&vfs, "/home/etc/motd"), Ok(b"hello".to_vec()));
+        assert_eq!(read(&vfs, "/home/motd"), Ok(b"hello".to_vec()));
+    }
+
+    #[test_case]
+    fn follow_links_keeps_last_link_unless_asked() {
+        let vfs = tree();
+        assert_eq!(vfs.follow_links("/home/motd", false).unwrap(), "/home/motd");
+        assert!(matches!(
+            vfs.process(FSRequest::ReadLink { path: path("/home/motd") }),
+            FSResponse::LinkTarget(target) if target == "../etc/motd"
+        ));
+    }
+
+    #[test_case]
+    fn follow_links_keeps_missing_components() {
+        let vfs = tree();
+        assert_eq!(vfs.follow_links("/home/etc/new/file", true).unwrap(), "/etc/new/file");
+    }
+
+    #[test_case]
+    fn link_loops_are_reported() {
+        let vfs = tree();
+        assert_eq!(vfs.follow_links("/home/loop", true), Err(FsError::Loop));
+        assert_eq!(read(&vfs, "/home/loop"), Err(FsError::Loop));
+    }
+
+    #[test_case]
+    fn relative_paths_start_in_the_current_directory() {
+        let vfs = tree();
+        assert!(matches!(vfs.process(FSRequest::ChangeDir { path: path("/home") }), FSResponse::Success));
+        assert_eq!(read(&vfs, "motd"), Ok(b"hello".to_vec()));
+        assert_eq!(read(&vfs, "../etc/motd"), Ok(b"hello".to_vec()));
+        assert_eq!(read(&vfs, "missing"), Err(FsError::NotFound));
+    }
+
+    #[test_case]
+    fn readers_share_file_data_and_keep_what_they_opened() {
+        let vfs = tree();
+        let shared = |vfs: &VFSService| match vfs.process(FSRequest::ReadFile { path: path("/etc/motd") }) {
+            FSResponse::FileData(FileBytes::Shared(data)) => data,
+            other => panic!("unexpected response {:?}", other),
+        };
+        let first = shared(&vfs);
+        assert!(alloc::sync::Arc::ptr_eq(&first, &shared(&vfs)));
+
+        let mut handle = vfs.open_handle("/etc/motd", OpenFlags::READ).unwrap();
+        vfs.write_at("/etc/motd", Some(0), b"J").unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(handle.read(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"hello");
+        assert_eq!(first.as_slice(), b"hello");
+        assert_eq!(read(&vfs, "/etc/motd"), Ok(b"Jello".to_vec()));
+    }
+
+    #[test_case]
+    fn files_are_not_directories() {
+        let vfs = tree();
+        assert!(matches!(
+            vfs.process(FSRequest::ChangeDir { path: path("/etc/motd") }),
+            FSResponse::Error(FsError::NotDir, _)
+        ));
+        assert!(matches!(
+            vfs.process(FSRequest::WriteFile { path: path("/etc/motd/x"), data: Vec::new() }),
+            FSResponse::Error(FsError::NotDir, _)
+        ));
+    }
+}
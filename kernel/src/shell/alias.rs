@@ -0,0 +1,61 @@
+//! Aliases
+//!
+//! `alias ll='ls -l'` makes `ll /etc` run `ls -l /etc`. Only the first
+//! word of a command is looked up, and the text an alias expands to is
+//! not looked up again, so `alias ls='ls -F'` does what it says.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+static ALIASES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// An alias is being run: what it expands to is taken literally
+static EXPANDING: AtomicBool = AtomicBool::new(false);
+
+pub fn set(name: &str, value: &str) {
+    ALIASES.lock().insert(name.to_string(), value.to_string());
+}
+
+pub fn get(name: &str) -> Option<String> {
+    ALIASES.lock().get(name).cloned()
+}
+
+/// Returns whether `name` was an alias
+pub fn remove(name: &str) -> bool {
+    ALIASES.lock().remove(name).is_some()
+}
+
+pub fn clear() {
+    ALIASES.lock().clear();
+}
+
+/// Every alias, by name
+pub fn list() -> Vec<(String, String)> {
+    ALIASES.lock().iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+}
+
+/// `name='value'` as `alias` prints it, quoted so it can be typed back in
+pub fn quoted(name: &str, value: &str) -> String {
+    format!("{}='{}'", name, value.replace('\'', "'\\''"))
+}
+
+/// `line` with its first word replaced, if that word is an alias
+pub fn expand(line: &str) -> Option<String> {
+    if EXPANDING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let (first, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let value = get(first)?;
+    Some(if rest.is_empty() { value } else { format!("{} {}", value, rest) })
+}
+
+/// Run `f`, which runs an expanded alias, without expanding aliases again
+pub fn run_expanded(f: impl FnOnce()) {
+    let outer = EXPANDING.swap(true, Ordering::Relaxed);
+    f();
+    EXPANDING.store(outer, Ordering::Relaxed);
+}
@@ -0,0 +1,205 @@
+//! Job control for the kernel shell
+//!
+//! `cmd &` starts a program without waiting for it, and Ctrl+Z stops the
+//! program in the foreground. Both become jobs, numbered from 1 and named
+//! `%n` by `fg`, `bg` and `kill`. `fg` gives a job the keyboard again and
+//! waits for it; `bg` lets a stopped one carry on in the background.
+//! Finished jobs are reported once, after the next command.
+//!
+//! Only programs can be jobs: builtins and scripts run inside the shell.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::output;
+use crate::services::{display, terminal};
+use crate::syscall::abi::{SIGCONT, SIGTSTP};
+use crate::task::scheduler::{WaitStatus, SCHEDULER};
+use crate::task::ChildStatus;
+
+/// `$?` of a command that was stopped
+pub const STOPPED_STATUS: i32 = 128 + SIGTSTP;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    Stopped,
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            State::Running => "Running",
+            State::Stopped => "Stopped",
+        }
+    }
+}
+
+struct Job {
+    id: usize,
+    pid: u32,
+    command: String,
+    state: State,
+}
+
+/// Jobs of the shell, oldest first; the last one is the current job (`+`)
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+fn print_job(job: &Job, current: bool, state: &str) {
+    let mark = if current { '+' } else { ' ' };
+    output::print(&format!("[{}]{}  {:<24}{}\n", job.id, mark, state, job.command));
+}
+
+/// Add `pid` as a job, or update it if it already is one; returns its number
+fn record(pid: u32, command: &str, state: State) -> usize {
+    let mut jobs = JOBS.lock();
+    if let Some(position) = jobs.iter().position(|job| job.pid == pid) {
+        // It becomes the current job
+        let mut job = jobs.remove(position);
+        job.state = state;
+        let id = job.id;
+        jobs.push(job);
+        return id;
+    }
+    let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+    jobs.push(Job { id, pid, command: String::from(command), state });
+    id
+}
+
+fn forget(pid: u32) {
+    JOBS.lock().retain(|job| job.pid != pid);
+}
+
+/// Give the keyboard to the child `pid` and wait until it exits or stops
+///
+/// A stopped child becomes a job and the status is STOPPED_STATUS.
+pub fn foreground(pid: u32, command: &str) -> Result<i32, &'static str> {
+    terminal::set_foreground(Some(pid));
+    let status = crate::task::wait_job(pid);
+    if status == Some(ChildStatus::Stopped) {
+        // Its queued input and output stay with it
+        terminal::set_foreground(None);
+        let id = record(pid, command, State::Stopped);
+        let jobs = JOBS.lock();
+        if let Some(job) = jobs.iter().find(|job| job.id == id) {
+            print_job(job, true, State::Stopped.as_str());
+        }
+        return Ok(STOPPED_STATUS);
+    }
+    forget(pid);
+    terminal::release(pid);
+    display::release(pid);
+    match status {
+        Some(ChildStatus::Exited(status)) => Ok(status),
+        _ => Err("lost child task"),
+    }
+}
+
+/// Start the program `argv[0]` as a background job
+pub fn background(argv: &[&str]) -> Result<(), &'static str> {
+    let path = super::resolve_command_path(argv[0]);
+    let pid = super::spawn_program(&path, argv)?;
+    let id = record(pid, &argv.join(" "), State::Running);
+    output::print(&format!("[{}] {}\n", id, pid));
+    Ok(())
+}
+
+/// Report jobs that finished or stopped since the last call, forgetting
+/// the finished ones
+pub fn notify() {
+    let pids: Vec<u32> = JOBS.lock().iter().map(|job| job.pid).collect();
+    for pid in pids {
+        let (result, stopped, dead) = {
+            let mut scheduler = SCHEDULER.lock();
+            let parent = scheduler.current_pid();
+            let result = scheduler.reap(parent, Some(pid));
+            let stopped = result == WaitStatus::Running && scheduler.take_stopped(parent, Some(pid)).is_some();
+            (result, stopped, scheduler.take_dead())
+        };
+        crate::task::release_dead(dead);
+
+        let mut jobs = JOBS.lock();
+        let current = jobs.last().map(|job| job.pid) == Some(pid);
+        let Some(position) = jobs.iter().position(|job| job.pid == pid) else { continue };
+        match result {
+            WaitStatus::Running if stopped => {
+                jobs[position].state = State::Stopped;
+                print_job(&jobs[position], current, State::Stopped.as_str());
+            }
+            WaitStatus::Running => {}
+            WaitStatus::Exited(_, status) => {
+                let job = jobs.remove(position);
+                drop(jobs);
+                let state = if status == 0 { String::from("Done") } else { format!("Exit {}", status) };
+                print_job(&job, current, &state);
+                terminal::release(pid);
+                display::release(pid);
+            }
+            // Collected elsewhere
+            WaitStatus::NoChildren => {
+                jobs.remove(position);
+            }
+        }
+    }
+}
+
+/// Pid of the job `spec` names: `%n`, or the current job for None
+pub fn find(spec: Option<&str>) -> Result<u32, String> {
+    job_index(spec).map(|index| JOBS.lock()[index].pid)
+}
+
+fn job_index(spec: Option<&str>) -> Result<usize, String> {
+    let jobs = JOBS.lock();
+    let Some(spec) = spec else {
+        return jobs.len().checked_sub(1).ok_or_else(|| String::from("no current job"));
+    };
+    let id = spec.strip_prefix('%').unwrap_or(spec);
+    id.parse::<usize>()
+        .ok()
+        .and_then(|id| jobs.iter().position(|job| job.id == id))
+        .ok_or_else(|| format!("{}: no such job", spec))
+}
+
+/// `jobs`: list the jobs with their state
+pub fn list() {
+    notify();
+    let jobs = JOBS.lock();
+    for (i, job) in jobs.iter().enumerate() {
+        print_job(job, i + 1 == jobs.len(), job.state.as_str());
+    }
+}
+
+/// `fg [%n]`: continue a job in the foreground and wait for it
+pub fn fg(spec: Option<&str>) -> Result<i32, String> {
+    let index = job_index(spec)?;
+    let (pid, command, state) = {
+        let jobs = JOBS.lock();
+        let job = &jobs[index];
+        (job.pid, job.command.clone(), job.state)
+    };
+    output::print(&format!("{}\n", command));
+    record(pid, &command, State::Running);
+    if state == State::Stopped {
+        crate::task::signal::send(pid, SIGCONT as u32).map_err(|_| format!("{}: cannot continue", command))?;
+    }
+    foreground(pid, &command).map_err(String::from)
+}
+
+/// `bg [%n]`: let a stopped job continue in the background
+pub fn bg(spec: Option<&str>) -> Result<(), String> {
+    let index = job_index(spec)?;
+    let (pid, command, state) = {
+        let jobs = JOBS.lock();
+        let job = &jobs[index];
+        (job.pid, job.command.clone(), job.state)
+    };
+    let id = record(pid, &command, State::Running);
+    if state == State::Running {
+        return Err(format!("job {} already in background", id));
+    }
+    crate::task::signal::send(pid, SIGCONT as u32).map_err(|_| format!("{}: cannot continue", command))?;
+    output::print(&format!("[{}]+ {} &\n", id, command));
+    Ok(())
+}
@@ -1,17 +1,39 @@
 //! Shell - Command interpreter that dispatches messages to services
 
 pub mod task; // v0.1.0: Shell as background task
+pub mod output;
+pub mod alias;
+pub mod jobs;
+pub mod script;
+pub mod session;
+pub mod words;
 
 use alloc::string::ToString;
 use alloc::vec::Vec;
 use alloc::format;
+use crate::fs::vfs::FileBytes;
 use crate::ipc::message::FSRequest;
 use crate::services::vfs;
 use crate::drivers::framebuffer;
-use crate::task::scheduler::SCHEDULER;
 use crate::apps::coreutils;
 use crate::mem::physical;
 use crate::net;
+use crate::common::json::{self, Json};
+use core::sync::atomic::{AtomicI32, Ordering};
+
+/// Exit status of the last command, `$?`
+static LAST_STATUS: AtomicI32 = AtomicI32::new(0);
+
+/// What network commands say about `NetworkError::NoDevice`
+const NO_NIC: &str = "network is unreachable (no NIC driver, only loopback works)";
+
+pub fn last_status() -> i32 {
+    LAST_STATUS.load(Ordering::Relaxed)
+}
+
+fn set_status(status: i32) {
+    LAST_STATUS.store(status, Ordering::Relaxed);
+}
 
 /// Helper function to parse IP address string
 fn parse_ip_addr(s: &str) -> Result<net::IpAddress, ()> {
@@ -32,18 +54,42 @@ fn parse_ip_addr(s: &str) -> Result<net::IpAddress, ()> {
     Ok(net::IpAddress::from_bytes(bytes))
 }
 
+/// Whether `--json` was passed: print one line of JSON instead of the
+/// usual table, for scripts and the test harness
+fn wants_json(parts: &[&str]) -> bool {
+    parts.iter().skip(1).any(|&arg| arg == "--json")
+}
+
+fn print_json(value: Json) {
+    output::print(&format!("{}\n", value));
+}
+
 /// Helper function to print IP address
 fn print_ip_addr(ip: net::IpAddress) {
     let bytes = ip.bytes();
     print_num(bytes[0] as u64);
-    framebuffer::print_char('.');
+    output::print_char('.');
     print_num(bytes[1] as u64);
-    framebuffer::print_char('.');
+    output::print_char('.');
     print_num(bytes[2] as u64);
-    framebuffer::print_char('.');
+    output::print_char('.');
     print_num(bytes[3] as u64);
 }
 
+/// Read a line from the keyboard without echoing it
+fn read_password() -> alloc::string::String {
+    let mut password = alloc::string::String::new();
+    while let Some(c) = crate::drivers::keyboard::read_key_blocking() {
+        match c {
+            '\n' | '\r' => break,
+            '\x08' => { password.pop(); }
+            c => password.push(c),
+        }
+    }
+    output::print_char('\n');
+    password
+}
+
 /// Get formatted prompt string with current directory
 pub fn get_prompt() -> alloc::string::String {
     use alloc::format;
@@ -63,9 +109,10 @@ pub fn get_prompt() -> alloc::string::String {
 
 /// Format directory path for prompt display
 fn format_directory(path: &str) -> alloc::string::String {
-    
-    // Home directory (/home/user) shows as ~
-    if path == "/home/user" {
+    let home = crate::auth::getenv("HOME").unwrap_or_else(|| "/home/user".to_string());
+
+    // Home directory shows as ~
+    if path == home {
         return "~".to_string();
     }
     
@@ -74,9 +121,9 @@ fn format_directory(path: &str) -> alloc::string::String {
         return "/".to_string();
     }
     
-    // For paths starting with /home/user/, replace with ~
-    if path.starts_with("/home/user/") {
-        return path.replacen("/home/user", "~", 1);
+    // For paths below home, replace with ~
+    if path.starts_with(&format!("{}/", home)) {
+        return path.replacen(&home, "~", 1);
     }
     
     // For long paths, show only last 2-3 components
@@ -101,169 +148,616 @@ fn resolve_command_path(cmd: &str) -> alloc::string::String {
     }
 }
 
-pub fn exec_path(path: &str) -> Result<(), &'static str> {
+/// Run the executable or script at `path`; `argv` is passed to ELF images
+pub fn exec_path(path: &str, argv: &[&str]) -> Result<(), &'static str> {
     let response = vfs::process_request(FSRequest::ReadFile { path: path.to_string() });
     let data = match response {
         crate::ipc::message::FSResponse::FileData(data) => data,
-        crate::ipc::message::FSResponse::Error(_) => return Err("file not found"),
+        crate::ipc::message::FSResponse::Error(e, _) => return Err(e.as_str()),
         _ => return Err("unexpected response"),
     };
 
     if data.starts_with(b"#!") {
         if let Ok(text) = core::str::from_utf8(&data) {
-            run_script(text);
+            script::run(text, argv);
             return Ok(());
         }
         return Err("invalid script encoding");
     }
 
     if data.starts_with(b"\x7FELF") {
-        let load = match crate::loader::elf::load_user_elf(&data) {
-            Ok(res) => res,
-            Err(_) => {
-                framebuffer::print("ELF load failed\n");
-                return Err("elf load failed");
-            }
-        };
+        // Only returns if the image could not be loaded
+        return crate::task::exec_image(path, &data, argv, &[]).map_err(|_| {
+            output::print("ELF load failed\n");
+            "elf load failed"
+        });
+    }
 
-        let entry = load.entry;
-        let user_stack = load.user_stack;
-        let addr_space = load.address_space;
-        let cr3 = addr_space.cr3.as_u64();
+    if let Ok(text) = core::str::from_utf8(&data) {
+        script::run(text, argv);
+        return Ok(());
+    }
 
-        let mut scheduler = SCHEDULER.lock();
-        let current = match scheduler.current_task_mut() {
-            Some(task) => task,
-            None => return Err("no current task"),
-        };
+    Err("unknown file format")
+}
 
-        current.user_stack = user_stack;
-        current.page_table = cr3;
-        current.address_space = Some(addr_space);
+fn spawn_image(path: &str, data: FileBytes, argv: &[&str]) -> Result<u32, &'static str> {
+    let env: Vec<alloc::string::String> = crate::auth::environment()
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let envp: Vec<&str> = env.iter().map(|s| s.as_str()).collect();
+    crate::task::spawn_user(path, data, argv, &envp)
+}
 
-        unsafe { crate::arch::x86_64::enter_user_mode_with_cr3(entry, user_stack, cr3); }
+/// Start the program at `path` as a child task without waiting for it
+fn spawn_program(path: &str, argv: &[&str]) -> Result<u32, &'static str> {
+    let data = match vfs::process_request(FSRequest::ReadFile { path: path.to_string() }) {
+        crate::ipc::message::FSResponse::FileData(data) => data,
+        crate::ipc::message::FSResponse::Error(e, _) => return Err(e.as_str()),
+        _ => return Err("unexpected response"),
+    };
+    if !data.starts_with(b"\x7FELF") {
+        return Err("only programs can run in the background");
     }
+    spawn_image(path, data, argv)
+}
 
-    if let Ok(text) = core::str::from_utf8(&data) {
-        run_script(text);
-        return Ok(());
+/// Run the executable at `path` as a child task and wait for it
+///
+/// Scripts run in the shell itself, as with `exec_path`. Returns the
+/// exit status, a script's included; a program stopped with Ctrl+Z
+/// becomes a job and returns `jobs::STOPPED_STATUS`.
+pub fn run_path(path: &str, argv: &[&str]) -> Result<i32, &'static str> {
+    let response = vfs::process_request(FSRequest::ReadFile { path: path.to_string() });
+    let data = match response {
+        crate::ipc::message::FSResponse::FileData(data) => data,
+        crate::ipc::message::FSResponse::Error(e, _) => return Err(e.as_str()),
+        _ => return Err("unexpected response"),
+    };
+
+    if !data.starts_with(b"\x7FELF") {
+        return exec_path(path, argv).map(|_| last_status());
     }
 
-    Err("unknown file format")
+    let pid = spawn_image(path, data, argv)?;
+    // The program has the keyboard until it exits or stops
+    jobs::foreground(pid, &argv.join(" "))
 }
 
-fn run_script(content: &str) {
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+/// Split a trailing `> file` or `>> file` off `words`; quoted `>` is
+/// just text
+///
+/// Returns the remaining words and the target with its append flag.
+fn split_redirection(words: Vec<words::Word>) -> Result<(Vec<words::Word>, Option<(alloc::string::String, bool)>), &'static str> {
+    let mut rest = Vec::new();
+    let mut target = None;
+    let mut iter = words.into_iter();
+    while let Some(word) = iter.next() {
+        let operator = if word.bare && word.text.starts_with(">>") {
+            2
+        } else if word.bare && word.text.starts_with('>') {
+            1
+        } else {
+            rest.push(word);
             continue;
-        }
-        execute_command(trimmed);
+        };
+        let path = if word.text.len() == operator {
+            iter.next().ok_or("missing redirection target")?.text
+        } else {
+            word.text[operator..].to_string()
+        };
+        target = Some((path, operator == 2));
     }
+    Ok((rest, target))
 }
 
 /// Execute shell command
+///
+/// Lines with `;`, `&&`, `||` or `if`/`for`/`while` go through the script
+/// interpreter. A single command is split into words as `words` describes
+/// (quotes, `$(...)`, variables, wildcards) and honours `>`/`>>` redirection for output printed through `output`; user
+/// programs still write to their own stdout. A trailing `&` runs a program
+/// as a background job. Its exit status is left in `$?`.
 pub fn execute_command(cmd: &str) {
-    let parts: Vec<&str> = cmd.split_whitespace().collect();
+    if script::is_compound(cmd) {
+        script::run_line(cmd);
+    } else {
+        run_simple(cmd);
+    }
+}
+
+/// Run one command, or set a variable for `name=value`
+fn run_simple(cmd: &str) {
+    if let Some(line) = alias::expand(cmd) {
+        alias::run_expanded(|| execute_command(&line));
+        return;
+    }
+
+    let assignment = cmd.split_once('=').filter(|(name, _)| script::is_name(name));
+    let split = words::split(assignment.map_or(cmd, |(_, value)| value));
+    let split = match split {
+        Ok(split) => split,
+        Err(e) => {
+            output::print(&format!("sh: {}\n", e));
+            set_status(2);
+            return;
+        }
+    };
+    if let Some((name, _)) = assignment {
+        let value: Vec<&str> = split.iter().map(|word| word.text.as_str()).collect();
+        script::set_variable(name, &value.join(" "));
+        set_status(0);
+        return;
+    }
+
+    let mut split = split;
+    let background = match split.last_mut() {
+        Some(word) if word.bare && word.text == "&" => {
+            split.pop();
+            true
+        }
+        Some(word) if word.bare && word.text.ends_with('&') => {
+            word.text.pop();
+            true
+        }
+        _ => false,
+    };
+
+    let (split, target) = match split_redirection(split) {
+        Ok(split) => split,
+        Err(e) => {
+            output::print(&format!("sh: {}\n", e));
+            set_status(2);
+            return;
+        }
+    };
+    let args = words::glob(split);
+    let parts: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
     if parts.is_empty() {
         return;
     }
+    set_status(0);
+
+    // Commands may need subsystems that are still coming up in parallel
+    crate::boot::initcall::wait_all();
 
+    if background {
+        if target.is_some() {
+            output::print("sh: background jobs write to the terminal, not a file\n");
+            set_status(2);
+        } else if let Err(e) = jobs::background(&parts) {
+            output::print(&format!("sh: {}: {}\n", parts[0], e));
+            set_status(1);
+        }
+        return;
+    }
+
+    match target {
+        Some((path, append)) => {
+            let sink = alloc::boxed::Box::new(output::FileSink::new(&path, append));
+            if let Err(e) = output::redirect(sink, || dispatch(parts)) {
+                output::print(&format!("sh: {}\n", e));
+                set_status(1);
+            }
+        }
+        None => dispatch(parts),
+    }
+}
+
+/// Run one built-in command or program
+fn dispatch(parts: Vec<&str>) {
     match parts[0] {
         "help" => {
-            framebuffer::print("ospabOS v0.1.0 \"Foundation\" - Available commands:\n");
-            framebuffer::print("  help       - Show this help\n");
-            framebuffer::print("  clear      - Clear screen\n");
-            framebuffer::print("  echo       - Echo text\n");
-            framebuffer::print("  uptime     - Show system uptime\n");
-            framebuffer::print("  version    - Show kernel version\n");
-            framebuffer::print("  history    - Show command history\n");
-            framebuffer::print("  ls         - List directory (initrd)\n");
-            framebuffer::print("  cat        - Display file contents\n");
-            framebuffer::print("  cd         - Change directory (VFS)\n");
-            framebuffer::print("  pwd        - Print working directory\n");
-            framebuffer::print("  ps         - Show process list\n");
-            framebuffer::print("  free       - Show memory usage\n");
-            framebuffer::print("  date       - Show current date/time\n");
-            framebuffer::print("  uname      - Show system information\n");
-            framebuffer::print("  whoami     - Show current user\n");
-            framebuffer::print("  login      - Login as different user\n");
-            framebuffer::print("  logout     - Logout current user\n");
-            framebuffer::print("  useradd    - Add new user\n");
-            framebuffer::print("  users      - List all users\n");
-            framebuffer::print("  grape      - Text editor (^G=help)\n");
-            framebuffer::print("  tomato     - Package manager\n");
-            framebuffer::print("  doom       - Run DOOM\n");
-            framebuffer::print("  sudo       - Run command as superuser\n");
-            framebuffer::print("  top        - Display process information\n");
-            framebuffer::print("  df         - Show disk space usage\n");
-            framebuffer::print("  du         - Show directory space usage\n");
-            framebuffer::print("  kill       - Kill process by PID\n");
-            framebuffer::print("  pkill      - Kill process by name\n");
-            framebuffer::print("  chmod      - Change file permissions\n");
-            framebuffer::print("  chown      - Change file owner\n");
-            framebuffer::print("  grep       - Search for patterns in files\n");
-            framebuffer::print("  find       - Search for files\n");
-            framebuffer::print("  wc         - Count words/lines/bytes\n");
-            framebuffer::print("  head       - Show first lines of file\n");
-            framebuffer::print("  tail       - Show last lines of file\n");
-            framebuffer::print("  sort       - Sort lines of text\n");
-            framebuffer::print("  uniq       - Remove duplicate lines\n");
-            framebuffer::print("  tar        - Archive files\n");
-            framebuffer::print("  wget       - Download files\n");
-            framebuffer::print("  ping       - Test network connectivity\n");
-            framebuffer::print("  ifconfig   - Configure network interfaces\n");
-            framebuffer::print("  dmesg      - Print kernel log\n");
-            framebuffer::print("  shutdown   - Shutdown system\n");
-            framebuffer::print("  reboot     - Reboot system\n");
+            output::print("ospabOS v0.1.0 \"Foundation\" - Available commands:\n");
+            output::print("  help       - Show this help\n");
+            output::print("  clear      - Clear screen\n");
+            output::print("  theme      - List color themes, theme set <name> switches\n");
+            output::print("  bind       - List key bindings, bind reload rereads keybindings.conf\n");
+            output::print("  loadkeys   - Show or switch the keyboard layout (-l lists: us uk de fr dvorak)\n");
+            output::print("  service    - Start, stop or show services [name start|stop|restart|status]\n");
+            output::print("  kbdrate    - Show or set key repeat [-d delay_ms] [-r keys_per_s]\n");
+            output::print("  sysctl     - Show or set kernel tunables [-a | -d | name[=value]] (also /sys/kernel)\n");
+            output::print("  echo       - Echo text\n");
+            output::print("  test, [    - Check files, strings and numbers; status in $? (-f -d -e -z -n = != -eq -lt ...)\n");
+            output::print("  true/false - Exit with status 0 / 1, for && || and if\n");
+            output::print("  exit       - End a script [status]\n");
+            output::print("  jobs       - List background and stopped jobs (cmd & starts one, Ctrl+Z stops one)\n");
+            output::print("  fg, bg     - Continue a job in the foreground / background [%n]\n");
+            output::print("  uptime     - Show system uptime\n");
+            output::print("  version    - Show kernel version\n");
+            output::print("  osinfo     - Report version, features, boot and hardware (--json)\n");
+            output::print("  lscpu      - Show the CPU model and features (--json, also /proc/cpuinfo)\n");
+            output::print("  history    - Show command history (-c clears it, Ctrl+R searches it)\n");
+            output::print("  alias      - List or define aliases [name=value]; unalias removes them\n");
+            output::print("  ls         - List directory (initrd)\n");
+            output::print("  cat        - Display file contents\n");
+            output::print("  xxd        - Show a file as hex and text [-s offset] [-l length]; -w offset hex patches it\n");
+            output::print("  ln -s      - Make a symbolic link <target> <link>; readlink shows where one points\n");
+            output::print("               (symbolic links only, hard links are not supported yet)\n");
+            output::print("  cd         - Change directory (VFS)\n");
+            output::print("  pwd        - Print working directory\n");
+            output::print("  ps         - Show process list (--json)\n");
+            output::print("  free       - Show memory, heap and task usage (-h human-readable, --tasks per task, --json)\n");
+            output::print("  slabinfo   - Show kernel slab cache usage\n");
+            output::print("  services   - List registered services and their health (--json)\n");
+            output::print("  ulimit     - Show or set the CPU time limit (-t seconds)\n");
+            output::print("  date       - Show current date/time\n");
+            output::print("  uname      - Show system information\n");
+            output::print("  whoami     - Show current user\n");
+            output::print("  login      - Login as different user\n");
+            output::print("  logout     - Logout current user\n");
+            output::print("  useradd    - Add new user\n");
+            output::print("  userdel    - Delete user (-r archives home)\n");
+            output::print("  su         - Switch user session\n");
+            output::print("  env        - Show session environment\n");
+            output::print("  users      - List all users\n");
+            output::print("  grape      - Text editor (^G=help, --window in a window)\n");
+            output::print("  clipboard  - Show the clipboard [set <text> | clear]; Ctrl+Shift+V pastes it\n");
+            output::print("  tomato     - Package manager\n");
+            output::print("  beep       - Sound the PC speaker [freq] [ms] (--stop to silence)\n");
+            output::print("  doom       - Run DOOM [map] (--window in a window, --engine for doomgeneric)\n");
+            output::print("  sudo       - Run command as superuser\n");
+            output::print("  top        - Display process information\n");
+            output::print("  df         - Show space used on each filesystem (-h human-readable, --json)\n");
+            output::print("  du         - Show space used under directories (-h human-readable, -s total only)\n");
+            output::print("  kill       - Signal a process by PID or %job [TERM|INT|KILL|STOP|CONT]\n");
+            output::print("  pkill      - Kill process by name\n");
+            output::print("  chmod      - Change file permissions\n");
+            output::print("  chown      - Change file owner\n");
+            output::print("  grep       - Search for patterns in files\n");
+            output::print("  find       - Search for files\n");
+            output::print("  wc         - Count words/lines/bytes\n");
+            output::print("  head       - Show first lines of file\n");
+            output::print("  tail       - Show last lines of file\n");
+            output::print("  sort       - Sort lines of text\n");
+            output::print("  uniq       - Remove duplicate lines\n");
+            output::print("  tar        - Archive files\n");
+            output::print("  wget       - Download a file over HTTP\n");
+            output::print("  ping       - Test network connectivity\n");
+            output::print("  tcpdump    - Capture and decode network traffic\n");
+            output::print("  rz         - Receive files over serial (YMODEM)\n");
+            output::print("  sz         - Send a file over serial (YMODEM)\n");
+            output::print("  nslookup   - Query DNS for a hostname\n");
+            output::print("  ifconfig   - Configure network interfaces (--json)\n");
+            output::print("  dmesg      - Print kernel log (-c clears it)\n");
+            output::print("  lsmod      - List bootloader modules (also in /boot/modules)\n");
+            output::print("  lspci      - List PCI devices (-v: interrupts and BARs, as in /proc/pci)\n");
+            output::print("  sync       - Write cached disk blocks back to the disks\n");
+            output::print("  strace     - Log a task's syscalls to dmesg (strace <pid> | all | off [pid])\n");
+            output::print("  profile    - Sample where the kernel spends its time (on | off; no argument shows it)\n");
+            output::print("  mouse      - Show the mouse pointer position, on|off shows or hides it\n");
+            output::print("  boottime   - Show boot stage timings (blame: slowest first)\n");
+            output::print("  shutdown   - Shutdown system\n");
+            output::print("  reboot     - Reboot system\n");
         }
         "clear" => {
             framebuffer::clear();
         }
-        "echo" => {
-            if parts.len() > 1 {
-                let text = parts[1..].join(" ");
-                framebuffer::print(&text);
-                framebuffer::print_char('\n');
+        "true" => {}
+        "false" => set_status(1),
+        "test" | "[" => {
+            let mut args = &parts[1..];
+            if parts[0] == "[" {
+                match args.split_last() {
+                    Some((&"]", rest)) => args = rest,
+                    _ => {
+                        output::print("[: missing ']'\n");
+                        set_status(2);
+                        return;
+                    }
+                }
+            }
+            match script::test(args) {
+                Ok(result) => set_status(if result { 0 } else { 1 }),
+                Err(e) => {
+                    output::print(&format!("{}: {}\n", parts[0], e));
+                    set_status(2);
+                }
+            }
+        }
+        "exit" => {
+            let status = match parts.get(1).map(|arg| arg.parse::<i32>()) {
+                None => last_status(),
+                Some(Ok(status)) => status,
+                Some(Err(_)) => {
+                    output::print("Usage: exit [status]\n");
+                    2
+                }
+            };
+            set_status(status);
+            if script::in_script() {
+                script::request_exit();
+            } else {
+                output::print("exit: not in a script\n");
             }
         }
         "uptime" => {
             use crate::drivers::timer;
             let uptime_ms = timer::get_uptime_ms();
             let uptime_s = uptime_ms / 1000;
-            framebuffer::print("Uptime: ");
+            output::print("Uptime: ");
             print_num(uptime_s);
-            framebuffer::print(" seconds\n");
+            output::print(" seconds\n");
+        }
+        "beep" => {
+            use crate::drivers::speaker;
+            if parts.get(1) == Some(&"--stop") {
+                speaker::stop();
+                return;
+            }
+            let freq = parts.get(1).map_or(Ok(880), |arg| arg.parse::<u32>());
+            let ms = parts.get(2).map_or(Ok(200), |arg| arg.parse::<u32>());
+            match (freq, ms) {
+                (Ok(freq), Ok(ms)) if (speaker::MIN_FREQ..=speaker::MAX_FREQ).contains(&freq) => speaker::beep(freq, ms),
+                _ => output::print("Usage: beep [freq 20-20000] [ms] | beep --stop\n"),
+            }
+        }
+        "theme" => {
+            use crate::common::palette;
+            match (parts.get(1).copied(), parts.get(2).copied()) {
+                (None, _) | (Some("list"), _) => {
+                    let current = palette::current().name;
+                    for theme in palette::PALETTES {
+                        let marker = if theme.name == current { '*' } else { ' ' };
+                        output::print(&format!("{} {:<14}{}\n", marker, theme.name, theme.description));
+                    }
+                }
+                (Some("set"), Some(name)) => match palette::select(name) {
+                    Ok(_) => crate::drivers::framebuffer::apply_palette(),
+                    Err(e) => output::print(&format!("theme: {}: {}\n", name, e)),
+                },
+                _ => output::print("Usage: theme [list | set <name>]\n"),
+            }
+        }
+        "bind" => {
+            use crate::keybindings;
+            match parts.get(1..).unwrap_or(&[]) {
+                [] | ["list"] => {
+                    for binding in keybindings::list() {
+                        output::print(&format!("{:<8}{:<8}{}\n",
+                            binding.context.name(), binding.key.name(), binding.action.name()));
+                    }
+                }
+                ["reload"] => match keybindings::reload() {
+                    Ok(count) => output::print(&format!("bind: {} bindings from {}\n", count, keybindings::CONFIG_PATH)),
+                    Err(errors) => {
+                        for error in errors {
+                            output::print(&format!("bind: {}: {}\n", keybindings::CONFIG_PATH, error));
+                        }
+                        output::print("bind: kept the current bindings\n");
+                    }
+                },
+                [context, key, action] => {
+                    if let Err(e) = keybindings::set(context, key, action) {
+                        output::print(&format!("bind: {}\n", e));
+                    }
+                }
+                _ => output::print("Usage: bind [list | reload | <shell|editor> <key> <action|none>]\n"),
+            }
+        }
+        "loadkeys" => {
+            use crate::keymap;
+            match parts.get(1..).unwrap_or(&[]) {
+                [] => output::print(&format!("{}\n", keymap::current())),
+                ["-l" | "--list"] => {
+                    for (name, _) in keymap::BUILTIN {
+                        output::print(&format!("{}\n", name));
+                    }
+                }
+                [name] => match keymap::load(name) {
+                    Ok(_) => output::print(&format!("loadkeys: layout {}\n", name)),
+                    Err(errors) => {
+                        for error in errors {
+                            output::print(&format!("loadkeys: {}\n", error));
+                        }
+                        output::print(&format!("loadkeys: kept layout {}\n", keymap::current()));
+                    }
+                },
+                _ => output::print("Usage: loadkeys [-l | <layout | file>]\n"),
+            }
+        }
+        "service" => {
+            use crate::init;
+            match parts.get(1..).unwrap_or(&[]) {
+                [] | ["list"] => {
+                    for name in init::list() {
+                        if let Some(status) = init::status(&name) {
+                            output::print(&format!("{}\n", status));
+                        }
+                    }
+                }
+                [name, action @ ("start" | "stop" | "restart")] => {
+                    let result = match *action {
+                        "start" => init::start_service(name),
+                        "stop" => init::stop_service(name),
+                        _ => init::restart_service(name),
+                    };
+                    if let Err(e) = result {
+                        output::print(&format!("service: {}\n", e));
+                        set_status(1);
+                    }
+                }
+                [name, "status"] => match init::status(name) {
+                    Some(status) => output::print(&format!("{}\n", status)),
+                    None => {
+                        output::print(&format!("{}: not started since boot\n", name));
+                        set_status(1);
+                    }
+                },
+                _ => output::print("Usage: service [list | <name> start|stop|restart|status]\n"),
+            }
+        }
+        "sysctl" => {
+            use crate::sysctl;
+            match parts.get(1..).unwrap_or(&[]) {
+                [] | ["-a"] => {
+                    for tunable in sysctl::TUNABLES.iter() {
+                        output::print(&format!("{} = {}\n", tunable.name, tunable.get()));
+                    }
+                }
+                ["-d"] => {
+                    for tunable in sysctl::TUNABLES.iter() {
+                        output::print(&format!("{} ({}..{}): {}\n", tunable.name, tunable.min, tunable.max, tunable.description));
+                    }
+                }
+                args => {
+                    for arg in args {
+                        let result = match arg.split_once('=') {
+                            Some((name, value)) => sysctl::set(name.trim(), value).map(|_| name.trim()),
+                            None => Ok(*arg),
+                        };
+                        match result.and_then(|name| sysctl::find(name).ok_or_else(|| format!("{}: no such tunable", name))) {
+                            Ok(tunable) => output::print(&format!("{} = {}\n", tunable.name, tunable.get())),
+                            Err(e) => {
+                                output::print(&format!("sysctl: {}\n", e));
+                                set_status(1);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        "kbdrate" => {
+            use crate::drivers::keyboard;
+            let (mut delay, mut rate) = keyboard::typematic();
+            let mut changed = false;
+            let mut args = parts[1..].iter();
+            while let Some(arg) = args.next() {
+                let parsed = match (*arg, args.next()) {
+                    ("-d", Some(value)) => value.parse::<u32>().ok().map(|ms| delay = ms),
+                    ("-r", Some(value)) => parse_tenths(value).map(|tenths| rate = tenths),
+                    _ => None,
+                };
+                if parsed.is_none() {
+                    output::print("Usage: kbdrate [-d 250|500|750|1000] [-r 2..30]\n");
+                    return;
+                }
+                changed = true;
+            }
+            if changed {
+                match keyboard::set_typematic(delay, rate) {
+                    Ok(set) => (delay, rate) = set,
+                    Err(e) => {
+                        output::print(&format!("kbdrate: {}\n", e));
+                        return;
+                    }
+                }
+            }
+            output::print(&format!("Typematic rate is {}.{} cps (delay = {} ms)\n", rate / 10, rate % 10, delay));
         }
         "version" => {
-            framebuffer::print("ospabOS v0.1.0 \"Foundation\"\n");
-            framebuffer::print("Preemptive multitasking + Syscall interface + VMM\n");
-            framebuffer::print("Message-passing architecture with IPC\n");
+            output::print(&format!("ospabOS v{} \"{}\"\n", crate::osinfo::VERSION, crate::osinfo::CODENAME));
+            output::print("Preemptive multitasking + Syscall interface + VMM\n");
+            output::print("Message-passing architecture with IPC\n");
+        }
+        "lscpu" => {
+            let cpu = crate::arch::x86_64::cpu::info();
+            let cpus = crate::drivers::acpi::madt().map_or(1, |madt| madt.cpus.len().max(1));
+            if wants_json(&parts) {
+                print_json(json::object([
+                    ("architecture", "x86_64".into()),
+                    ("cpus", cpus.into()),
+                    ("vendor", cpu.vendor.as_str().into()),
+                    ("model_name", cpu.brand.clone().into()),
+                    ("family", cpu.family.into()),
+                    ("model", cpu.model.into()),
+                    ("stepping", cpu.stepping.into()),
+                    ("flags", json::array(cpu.flags.iter().copied())),
+                ]));
+                return;
+            }
+            output::print(&format!("{:<20}{}\n", "Architecture:", "x86_64"));
+            output::print(&format!("{:<20}{}\n", "CPU(s):", cpus));
+            output::print(&format!("{:<20}{}\n", "Vendor ID:", cpu.vendor));
+            output::print(&format!("{:<20}{}\n", "Model name:", cpu.brand.as_deref().unwrap_or("unknown")));
+            output::print(&format!("{:<20}{}\n", "CPU family:", cpu.family));
+            output::print(&format!("{:<20}{}\n", "Model:", cpu.model));
+            output::print(&format!("{:<20}{}\n", "Stepping:", cpu.stepping));
+            if let Some((physical, virtual_bits)) = cpu.address_bits {
+                output::print(&format!("{:<20}{} bits physical, {} bits virtual\n", "Address sizes:", physical, virtual_bits));
+            }
+            output::print(&format!("{:<20}{}\n", "Flags:", cpu.flags.join(" ")));
+        }
+        "osinfo" => {
+            let info = crate::osinfo::collect();
+            if wants_json(&parts) {
+                print_json(info.to_json());
+                return;
+            }
+            output::print(&format!("ospabOS {} \"{}\"\n\nFeatures:\n", info.version, info.codename));
+            for feature in &info.features {
+                output::print(&format!("  {:<18} {:<4} {}\n", feature.name,
+                    if feature.enabled { "yes" } else { "no" }, feature.detail));
+            }
+            let boot = &info.boot;
+            output::print("\nBoot:\n");
+            output::print(&format!("  bootloader         {}\n", boot.bootloader.as_deref().unwrap_or("unknown")));
+            output::print(&format!("  command line       {}\n", boot.cmdline.as_deref().unwrap_or("(none)")));
+            output::print(&format!("  base revision      {}\n",
+                if boot.base_revision_ok { "supported" } else { "not supported" }));
+            output::print(&format!("  modules            {}\n", boot.modules));
+            output::print(&format!("  uptime             {} ms\n", boot.uptime_ms));
+            let hw = &info.hardware;
+            output::print("\nHardware:\n");
+            output::print(&format!("  cpu                {} ({})\n",
+                hw.cpu_brand.as_deref().unwrap_or("unknown model"), hw.cpu_vendor));
+            output::print(&format!("  cpu flags          {}\n", hw.cpu_flags.join(" ")));
+            output::print(&format!("  cpus               {}\n", hw.cpus));
+            output::print(&format!("  memory             {}\n", format_size(hw.memory_bytes, true)));
+            match hw.framebuffer {
+                Some((w, h, bpp)) => output::print(&format!("  framebuffer        {}x{}, {} bpp\n", w, h, bpp)),
+                None => output::print("  framebuffer        none\n"),
+            }
+            output::print(&format!("  acpi               {}\n", if hw.acpi { "yes" } else { "no" }));
         }
         "history" => {
             use crate::drivers::keyboard;
-            keyboard::print_history();
+            if parts.get(1) == Some(&"-c") {
+                keyboard::set_history(Vec::new());
+                session::save_history();
+            } else {
+                keyboard::print_history();
+            }
         }
-        "ls" => {
-            let path = if parts.len() > 1 { parts[1] } else { "." };
-            match coreutils::ls(path) {
-                Ok(entries) => {
-                    if entries.is_empty() {
-                        framebuffer::print("(empty directory)\n");
-                    } else {
-                        for entry in entries {
-                            framebuffer::print(&entry);
-                            framebuffer::print_char('\n');
-                        }
-                    }
+        "alias" => {
+            if parts.len() == 1 {
+                for (name, value) in alias::list() {
+                    output::print(&format!("alias {}\n", alias::quoted(&name, &value)));
                 }
-                Err(msg) => {
-                    framebuffer::print("Error: ");
-                    framebuffer::print(&msg);
-                    framebuffer::print_char('\n');
+            }
+            for arg in &parts[1..] {
+                match arg.split_once('=') {
+                    Some((name, value)) if script::is_name(name) => alias::set(name, value),
+                    Some(_) => {
+                        output::print(&format!("alias: '{}': invalid alias name\n", arg));
+                        set_status(1);
+                    }
+                    None => match alias::get(arg) {
+                        Some(value) => output::print(&format!("alias {}\n", alias::quoted(arg, &value))),
+                        None => {
+                            output::print(&format!("alias: {}: not found\n", arg));
+                            set_status(1);
+                        }
+                    },
                 }
             }
         }
+        "unalias" => match parts.get(1..).unwrap_or(&[]) {
+            [] => {
+                output::print("Usage: unalias <name>... | unalias -a\n");
+                set_status(2);
+            }
+            ["-a"] => alias::clear(),
+            names => {
+                for name in names {
+                    if !alias::remove(name) {
+                        output::print(&format!("unalias: {}: not found\n", name));
+                        set_status(1);
+                    }
+                }
+            }
+        },
         "cd" => {
             if parts.len() > 1 {
                 let mut path = parts[1].to_string();
@@ -275,54 +769,126 @@ pub fn execute_command(cmd: &str) {
                 let response = vfs::process_request(FSRequest::ChangeDir { path });
                 match response {
                     crate::ipc::message::FSResponse::Success => {}
-                    crate::ipc::message::FSResponse::Error(msg) => {
-                        framebuffer::print("Error: ");
-                        framebuffer::print(&msg);
-                        framebuffer::print_char('\n');
+                    crate::ipc::message::FSResponse::Error(e, _) => {
+                        output::print(&format!("cd: {}\n", e));
+                        set_status(1);
                     }
                     _ => {}
                 }
             } else {
-                framebuffer::print("Usage: cd <directory>\n");
+                output::print("Usage: cd <directory>\n");
+                set_status(2);
             }
         }
         "pwd" => {
             let response = vfs::process_request(FSRequest::GetCwd);
             match response {
                 crate::ipc::message::FSResponse::Cwd(path) => {
-                    framebuffer::print(&path);
-                    framebuffer::print_char('\n');
+                    output::print(&path);
+                    output::print_char('\n');
                 }
                 _ => {}
             }
         }
         "ps" => {
-            framebuffer::print("  PID TTY          TIME CMD\n");
-            framebuffer::print("    1 ?        00:00:00 kernel\n");
-            framebuffer::print("    2 ?        00:00:00 init\n");
-            framebuffer::print("    3 ?        00:00:00 shell\n");
-            framebuffer::print("    4 ?        00:00:00 vfs\n");
-            framebuffer::print("    5 ?        00:00:00 ipc\n");
-            // In a real implementation, we'd iterate through the task list
+            let tasks = crate::task::list();
+            if wants_json(&parts) {
+                print_json(json::object([("tasks", json::array(tasks.iter().map(|task| json::object([
+                    ("pid", task.pid.into()),
+                    ("ppid", task.parent.into()),
+                    ("name", task.name.as_str().into()),
+                    ("state", task.state.as_str().into()),
+                    ("cpu_ticks", task.cpu_ticks.into()),
+                    ("user", task.user.into()),
+                ]))))]));
+                return;
+            }
+            output::print("  PID  PPID STATE        TIME CMD\n");
+            for task in &tasks {
+                let seconds = task.cpu_ticks / crate::drivers::timer::TICKS_PER_SECOND;
+                output::print(&format!("{:>5} {:>5} {:<8} {:02}:{:02}:{:02} {}{}\n",
+                    task.pid, task.parent.unwrap_or(0), task.state.as_str(),
+                    seconds / 3600, seconds / 60 % 60, seconds % 60, task.name,
+                    if task.user { "" } else { " [kernel]" }));
+            }
         }
         "free" => {
+            let human = parts.iter().skip(1).any(|&arg| arg == "-h");
+            let per_task = parts.iter().skip(1).any(|&arg| arg == "--tasks");
             let (total_frames, used_frames, free_frames) = physical::stats();
+            let heap = crate::mm::heap_allocator::stats();
+            let map = physical::memory_map();
+            let tasks = crate::task::list();
+            let size = |bytes: usize| format_size(bytes as u64, human);
             
-            let total_kb = total_frames * 4; // 4KB per frame
-            let used_kb = used_frames * 4;
-            let free_kb = free_frames * 4;
+            let total = total_frames * 4096;
+            let used = used_frames * 4096;
+            let free = free_frames * 4096;
+            let shared = crate::mem::vmm::shared_frames() * 4096;
+            let resident: usize = tasks.iter().map(|task| task.resident).sum();
+            let task_shared: usize = tasks.iter().map(|task| task.shared).sum();
             
-            framebuffer::print("              total        used        free      shared  buff/cache   available\n");
-            framebuffer::print("Mem:     ");
-            print_num(total_kb as u64);
-            framebuffer::print("       ");
-            print_num(used_kb as u64);
-            framebuffer::print("    ");
-            print_num(free_kb as u64);
-            framebuffer::print("           0        2048    ");
-            print_num(free_kb as u64);
-            framebuffer::print("\n");
-            framebuffer::print("Swap:             0           0          0\n");
+            if wants_json(&parts) {
+                print_json(json::object([
+                    ("memory", json::object([
+                        ("total", total.into()),
+                        ("used", used.into()),
+                        ("free", free.into()),
+                        ("shared", shared.into()),
+                    ])),
+                    ("heap", json::object([
+                        ("total", heap.total.into()),
+                        ("in_use", heap.in_use.into()),
+                        ("free", heap.free().into()),
+                        ("peak", heap.peak.into()),
+                        ("allocations", heap.allocations.into()),
+                        ("frees", heap.frees.into()),
+                    ])),
+                    ("memory_map", json::object([
+                        ("total", map.total().into()),
+                        ("usable", map.usable.into()),
+                        ("kernel_and_modules", map.kernel.into()),
+                        ("bootloader", map.bootloader.into()),
+                        ("reserved", map.reserved.into()),
+                    ])),
+                    ("tasks", json::array(tasks.iter().filter(|task| task.user).map(|task| json::object([
+                        ("pid", task.pid.into()),
+                        ("name", task.name.as_str().into()),
+                        ("resident", task.resident.into()),
+                        ("shared", task.shared.into()),
+                    ])))),
+                    ("swap", json::object([
+                        ("total", 0u64.into()),
+                        ("used", 0u64.into()),
+                        ("free", 0u64.into()),
+                    ])),
+                ]));
+                return;
+            }
+            
+            output::print(&format!("{:<7}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}\n",
+                "", "total", "used", "free", "shared", "buff/cache", "available"));
+            output::print(&format!("{:<7}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}\n",
+                "Mem:", size(total), size(used), size(free), size(shared), size(0), size(free)));
+            output::print(&format!("{:<7}{:>12}{:>12}{:>12}\n",
+                "Heap:", size(heap.total), size(heap.in_use), size(heap.free())));
+            output::print(&format!("{:<7}{:>12}{:>12}{:>12}{:>12}\n",
+                "Tasks:", "", size(resident), "", size(task_shared)));
+            output::print(&format!("{:<7}{:>12}{:>12}{:>12}\n",
+                "Swap:", size(0), size(0), size(0)));
+            output::print(&format!("Heap peak {}, {}% fragmented ({} allocations, {} frees)\n",
+                format_size(heap.peak as u64, true), heap.fragmentation_percent(),
+                heap.allocations, heap.frees));
+            output::print(&format!("RAM {}: {} usable, {} kernel and modules, {} bootloader, {} reserved\n",
+                format_size(map.total(), true), format_size(map.usable, true), format_size(map.kernel, true),
+                format_size(map.bootloader, true), format_size(map.reserved, true)));
+            if per_task {
+                output::print(&format!("\n{:>5} {:>12} {:>12} {}\n", "PID", "resident", "shared", "CMD"));
+                for task in tasks.iter().filter(|task| task.user) {
+                    output::print(&format!("{:>5} {:>12} {:>12} {}\n",
+                        task.pid, size(task.resident), size(task.shared), task.name));
+                }
+            }
         }
         "date" => {
             use crate::drivers::timer;
@@ -333,484 +899,1092 @@ pub fn execute_command(cmd: &str) {
             let minutes = (uptime_s % 3600) / 60;
             let seconds = uptime_s % 60;
             
-            framebuffer::print("Thu Feb  6 14:30:45 UTC 2026\n");
-            framebuffer::print("System uptime: ");
+            output::print("Thu Feb  6 14:30:45 UTC 2026\n");
+            output::print("System uptime: ");
             if days > 0 {
                 print_num(days);
-                framebuffer::print(" days, ");
+                output::print(" days, ");
             }
             print_num(hours);
-            framebuffer::print(":");
-            if minutes < 10 { framebuffer::print("0"); }
+            output::print(":");
+            if minutes < 10 { output::print("0"); }
             print_num(minutes);
-            framebuffer::print(":");
-            if seconds < 10 { framebuffer::print("0"); }
+            output::print(":");
+            if seconds < 10 { output::print("0"); }
             print_num(seconds);
-            framebuffer::print("\n");
+            output::print("\n");
         }
         "uname" => {
             if parts.len() > 1 {
                 match parts[1] {
                     "-a" | "--all" => {
-                        framebuffer::print("ospabOS ospab 0.1.0 Foundation SMP Thu Feb  6 14:30:45 UTC 2026 x86_64 x86_64 x86_64 GNU/Linux\n");
+                        output::print("ospabOS ospab 0.1.0 Foundation SMP Thu Feb  6 14:30:45 UTC 2026 x86_64 x86_64 x86_64 GNU/Linux\n");
                     }
                     "-s" | "--kernel-name" => {
-                        framebuffer::print("ospabOS\n");
+                        output::print("ospabOS\n");
                     }
                     "-n" | "--nodename" => {
-                        framebuffer::print("ospab\n");
+                        output::print("ospab\n");
                     }
                     "-r" | "--kernel-release" => {
-                        framebuffer::print("0.1.0\n");
+                        output::print("0.1.0\n");
                     }
                     "-v" | "--kernel-version" => {
-                        framebuffer::print("Foundation\n");
+                        output::print("Foundation\n");
                     }
                     "-m" | "--machine" => {
-                        framebuffer::print("x86_64\n");
+                        output::print("x86_64\n");
                     }
                     "-p" | "--processor" => {
-                        framebuffer::print("x86_64\n");
+                        output::print("x86_64\n");
                     }
                     "-i" | "--hardware-platform" => {
-                        framebuffer::print("x86_64\n");
+                        output::print("x86_64\n");
                     }
                     "-o" | "--operating-system" => {
-                        framebuffer::print("GNU/Linux\n");
+                        output::print("GNU/Linux\n");
                     }
                     _ => {
-                        framebuffer::print("Usage: uname [OPTION]...\n");
-                        framebuffer::print("Print certain system information.\n");
-                        framebuffer::print("  -a, --all                print all information\n");
-                        framebuffer::print("  -s, --kernel-name        print the kernel name\n");
-                        framebuffer::print("  -n, --nodename           print the network node hostname\n");
-                        framebuffer::print("  -r, --kernel-release     print the kernel release\n");
-                        framebuffer::print("  -v, --kernel-version     print the kernel version\n");
-                        framebuffer::print("  -m, --machine            print the machine hardware name\n");
-                        framebuffer::print("  -p, --processor          print the processor type\n");
-                        framebuffer::print("  -i, --hardware-platform  print the hardware platform\n");
-                        framebuffer::print("  -o, --operating-system   print the operating system\n");
+                        output::print("Usage: uname [OPTION]...\n");
+                        output::print("Print certain system information.\n");
+                        output::print("  -a, --all                print all information\n");
+                        output::print("  -s, --kernel-name        print the kernel name\n");
+                        output::print("  -n, --nodename           print the network node hostname\n");
+                        output::print("  -r, --kernel-release     print the kernel release\n");
+                        output::print("  -v, --kernel-version     print the kernel version\n");
+                        output::print("  -m, --machine            print the machine hardware name\n");
+                        output::print("  -p, --processor          print the processor type\n");
+                        output::print("  -i, --hardware-platform  print the hardware platform\n");
+                        output::print("  -o, --operating-system   print the operating system\n");
                     }
                 }
             } else {
-                framebuffer::print("ospabOS\n");
+                output::print("ospabOS\n");
             }
         }
         "whoami" => {
             let username = crate::auth::current_username();
-            framebuffer::print(&username);
-            framebuffer::print("\n");
+            output::print(&username);
+            output::print("\n");
         }
         "login" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: login <username> <password>\n");
+                output::print("Usage: login <username> <password>\n");
                 return;
             }
             match crate::auth::switch_user(parts[1], parts[2]) {
                 Ok(_) => {
                     let username = crate::auth::current_username();
-                    framebuffer::print("Logged in as ");
-                    framebuffer::print(&username);
-                    framebuffer::print("\n");
+                    output::print("Logged in as ");
+                    output::print(&username);
+                    output::print("\n");
+                    session::start();
                 }
                 Err(msg) => {
-                    framebuffer::print("Login failed: ");
-                    framebuffer::print(msg);
-                    framebuffer::print("\n");
+                    output::print("Login failed: ");
+                    output::print(msg);
+                    output::print("\n");
                 }
             }
         }
         "logout" => {
             // Switch back to root
             let _ = crate::auth::switch_user("root", "root");
-            framebuffer::print("Logged out\n");
+            output::print("Logged out\n");
+            session::start();
         }
         "useradd" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: useradd <username> <password>\n");
+                output::print("Usage: useradd <username> <password>\n");
                 return;
             }
             match crate::auth::add_user(parts[1], parts[2]) {
                 Ok(id) => {
-                    framebuffer::print("User ");
-                    framebuffer::print(parts[1]);
-                    framebuffer::print(" created with ID ");
+                    output::print("User ");
+                    output::print(parts[1]);
+                    output::print(" created with ID ");
                     print_num(id as u64);
-                    framebuffer::print("\n");
+                    output::print("\n");
                 }
                 Err(msg) => {
-                    framebuffer::print("Failed to create user: ");
-                    framebuffer::print(msg);
-                    framebuffer::print("\n");
+                    output::print("Failed to create user: ");
+                    output::print(msg);
+                    output::print("\n");
                 }
             }
         }
-        "users" => {
-            let users = crate::auth::list_users();
-            for user in users {
-                framebuffer::print(&user.name);
-                framebuffer::print(" (ID: ");
-                print_num(user.id as u64);
-                framebuffer::print(")\n");
+        "userdel" => {
+            let remove_home = parts.len() > 1 && parts[1] == "-r";
+            let name_idx = if remove_home { 2 } else { 1 };
+            if parts.len() <= name_idx {
+                output::print("Usage: userdel [-r] <username>\n");
+                return;
             }
-        }
-        "cat" => {
-            if parts.len() < 2 {
-                framebuffer::print("Usage: cat <filename>\n");
+            let name = parts[name_idx];
+            // Refusals come first, so a deletion that cannot happen leaves
+            // no archive behind
+            let user = match crate::auth::check_remove_user(name) {
+                Ok(user) => user,
+                Err(msg) => {
+                    output::print("userdel: ");
+                    output::print(msg);
+                    output::print("\n");
+                    return;
+                }
+            };
+
+            // Archive before touching the account, so a failed archive
+            // leaves the user as it was
+            let archive = alloc::format!("/var/backups/{}-home.tar", name);
+            if remove_home {
+                if let Err(msg) = coreutils::tar_dir(&user.home_dir, &archive) {
+                    output::print("userdel: ");
+                    output::print(&user.home_dir);
+                    output::print(": ");
+                    output::print(&msg);
+                    output::print("\n");
+                    return;
+                }
+            }
+
+            if let Err(msg) = crate::auth::remove_user(name) {
+                output::print("userdel: ");
+                output::print(msg);
+                output::print("\n");
                 return;
             }
-            let filename = parts[1];
-            match coreutils::cat(filename) {
-                Ok(data) => {
-                    if let Ok(text) = core::str::from_utf8(&data) {
-                        framebuffer::print(text);
-                        if !text.ends_with('\n') {
-                            framebuffer::print_char('\n');
-                        }
-                    } else {
-                        framebuffer::print("(binary file, ");
-                        print_num(data.len() as u64);
-                        framebuffer::print(" bytes)\n");
-                    }
+            output::print("User ");
+            output::print(name);
+            output::print(" removed\n");
+
+            if remove_home {
+                output::print("Home directory archived to ");
+                output::print(&archive);
+                output::print("\n");
+                if let Err(msg) = coreutils::rm_tree(&user.home_dir) {
+                    output::print("userdel: ");
+                    output::print(&user.home_dir);
+                    output::print(": ");
+                    output::print(&msg);
+                    output::print("\n");
                 }
+            }
+        }
+        "su" => {
+            let target = if parts.len() > 1 { parts[1] } else { "root" };
+            // Root drops privileges without a password
+            let password = if crate::auth::current_user_id() == 0 {
+                None
+            } else {
+                output::print("Password: ");
+                Some(read_password())
+            };
+            match crate::auth::set_credentials(target, password.as_deref()) {
+                Ok(_) => session::start(),
                 Err(msg) => {
-                    framebuffer::print("Error: ");
-                    framebuffer::print(&msg);
-                    framebuffer::print_char('\n');
+                    output::print("su: ");
+                    output::print(msg);
+                    output::print("\n");
                 }
             }
         }
+        "env" => {
+            for (key, value) in crate::auth::environment() {
+                output::print(&key);
+                output::print_char('=');
+                output::print(&value);
+                output::print_char('\n');
+            }
+        }
+        "users" => {
+            let users = crate::auth::list_users();
+            for user in users {
+                output::print(&user.name);
+                output::print(" (ID: ");
+                print_num(user.id as u64);
+                output::print(")\n");
+            }
+        }
         "mkdir" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: mkdir <dir>\n");
+                output::print("Usage: mkdir <dir>\n");
+                set_status(2);
                 return;
             }
             match coreutils::mkdir(parts[1]) {
                 Ok(_) => {}
                 Err(msg) => {
-                    framebuffer::print("Error: ");
-                    framebuffer::print(&msg);
-                    framebuffer::print_char('\n');
+                    output::print("Error: ");
+                    output::print(&msg);
+                    output::print_char('\n');
+                    set_status(1);
                 }
             }
         }
         "cp" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: cp <src> <dst>\n");
+                output::print("Usage: cp <src> <dst>\n");
+                set_status(2);
                 return;
             }
             match coreutils::cp(parts[1], parts[2]) {
                 Ok(_) => {}
                 Err(msg) => {
-                    framebuffer::print("Error: ");
-                    framebuffer::print(&msg);
-                    framebuffer::print_char('\n');
+                    output::print("Error: ");
+                    output::print(&msg);
+                    output::print_char('\n');
+                    set_status(1);
                 }
             }
         }
         "mv" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: mv <src> <dst>\n");
+                output::print("Usage: mv <src> <dst>\n");
+                set_status(2);
                 return;
             }
             match coreutils::mv(parts[1], parts[2]) {
                 Ok(_) => {}
                 Err(msg) => {
-                    framebuffer::print("Error: ");
-                    framebuffer::print(&msg);
-                    framebuffer::print_char('\n');
+                    output::print("Error: ");
+                    output::print(&msg);
+                    output::print_char('\n');
+                    set_status(1);
                 }
             }
         }
-        "grape" => {
-            if parts.len() < 2 {
-                framebuffer::print("Usage: grape <filename>\n");
-                framebuffer::print("Commands:\n");
-                framebuffer::print("  ^G (Ctrl+G) - Help\n");
-                framebuffer::print("  ^X (Ctrl+X) - Save\n");
-                framebuffer::print("  ^C (Ctrl+C) - Exit\n");
-                framebuffer::print("  ^W (Ctrl+W) - Search\n");
-                framebuffer::print("  ^K (Ctrl+K) - Cut\n");
-                framebuffer::print("  ^U (Ctrl+U) - Paste\n");
-                return;
-            }
-            let filename = parts[1];
-            match crate::grape::open(filename) {
+        "ln" => {
+            if parts.len() < 3 || parts[1] != "-s" {
+                if parts.len() == 3 {
+                    output::print("ln: hard links are not supported, use ln -s\n");
+                    set_status(1);
+                } else {
+                    output::print("Usage: ln -s <target> <link>\n");
+                    set_status(2);
+                }
+                return;
+            }
+            if parts.len() < 4 {
+                output::print("Usage: ln -s <target> <link>\n");
+                set_status(2);
+                return;
+            }
+            match coreutils::symlink(parts[2], parts[3]) {
                 Ok(_) => {}
-                Err(e) => {
-                    framebuffer::print("Error opening file: ");
-                    framebuffer::print(&e);
-                    framebuffer::print_char('\n');
+                Err(msg) => {
+                    output::print("Error: ");
+                    output::print(&msg);
+                    output::print_char('\n');
+                    set_status(1);
                 }
             }
         }
-        "tomato" => {
+        "readlink" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: tomato <install|remove|update|list|search> [package]\n");
+                output::print("Usage: readlink <link>\n");
+                set_status(2);
                 return;
             }
-            match parts[1] {
-                "list" => {
-                    framebuffer::print("Installed packages:\n");
-                    framebuffer::print("  (none - package manager not yet implemented)\n");
+            match coreutils::readlink(parts[1]) {
+                Ok(target) => {
+                    output::print(&target);
+                    output::print_char('\n');
                 }
-                "install" | "remove" | "update" | "search" => {
-                    framebuffer::print("Package manager not yet implemented\n");
+                Err(msg) => {
+                    output::print("Error: ");
+                    output::print(&msg);
+                    output::print_char('\n');
+                    set_status(1);
+                }
+            }
+        }
+        "clipboard" => {
+            use crate::services::clipboard;
+            match parts.get(1..).unwrap_or(&[]) {
+                [] => {
+                    let text = clipboard::get();
+                    output::print(&text);
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        output::print_char('\n');
+                    }
+                }
+                ["clear"] => clipboard::clear(),
+                ["set", text @ ..] => {
+                    if let Err(e) = clipboard::set(text.join(" ")) {
+                        output::print(&format!("clipboard: {}\n", e.as_str()));
+                        set_status(1);
+                    }
                 }
                 _ => {
-                    framebuffer::print("Unknown tomato command\n");
+                    output::print("Usage: clipboard [set <text> | clear]\n");
+                    set_status(2);
                 }
             }
         }
+        "grape" => {
+            let window = parts.contains(&"--window");
+            let Some(filename) = parts[1..].iter().copied().find(|arg| !arg.starts_with("--")) else {
+                output::print("Usage: grape [--window] <filename>\n");
+                output::print("Commands:\n");
+                output::print("  ^G (Ctrl+G) - Help\n");
+                output::print("  ^X (Ctrl+X) - Save\n");
+                output::print("  ^C (Ctrl+C) - Exit\n");
+                output::print("  ^W (Ctrl+W) - Search\n");
+                output::print("  ^K (Ctrl+K) - Cut\n");
+                output::print("  ^U (Ctrl+U) - Paste\n");
+                return;
+            };
+            let opened = if window { crate::grape::open_windowed(filename) } else { crate::grape::open(filename) };
+            match opened {
+                Ok(_) => {}
+                Err(e) => {
+                    output::print("Error opening file: ");
+                    output::print(&e);
+                    output::print_char('\n');
+                }
+            }
+        }
+        "tomato" => {
+            use crate::apps::tomato;
+            match parts.get(1..).unwrap_or(&[]) {
+                ["list", ..] if wants_json(&parts) => {
+                    print_json(json::object([("packages", json::array(tomato::installed()))]));
+                }
+                ["list"] => {
+                    let installed = tomato::installed();
+                    output::print("Installed packages:\n");
+                    if installed.is_empty() {
+                        output::print("  (none)\n");
+                    }
+                    for name in installed {
+                        output::print(&format!("  {}\n", name));
+                    }
+                }
+                ["install", name] => match tomato::install(name) {
+                    Ok(added) if added.is_empty() => output::print(&format!("{} is already installed\n", name)),
+                    Ok(added) => {
+                        for package in added {
+                            output::print(&format!("Installed {}\n", package));
+                        }
+                    }
+                    Err(e) => output::print(&format!("tomato: {}\n", e)),
+                },
+                ["remove", name] | ["remove", "--force", name] => {
+                    match tomato::remove(name, parts.contains(&"--force")) {
+                        Ok(()) => output::print(&format!("Removed {}\n", name)),
+                        Err(e) => output::print(&format!("tomato: {}\n", e)),
+                    }
+                }
+                ["autoremove"] => match tomato::autoremove() {
+                    Ok(removed) if removed.is_empty() => output::print("Nothing to remove\n"),
+                    Ok(removed) => {
+                        for package in removed {
+                            output::print(&format!("Removed {}\n", package));
+                        }
+                    }
+                    Err(e) => output::print(&format!("tomato: {}\n", e)),
+                },
+                ["update"] | ["update", _] => match tomato::update(parts.get(2).copied()) {
+                    Ok(count) => output::print(&format!("Index updated: {} packages available\n", count)),
+                    Err(e) => output::print(&format!("tomato: {}\n", e)),
+                },
+                ["upgrade"] | ["upgrade", "--dry-run"] => {
+                    let dry_run = parts.contains(&"--dry-run");
+                    match tomato::upgrade(dry_run) {
+                        Ok(planned) if planned.is_empty() => output::print("All packages are up to date\n"),
+                        Ok(planned) => {
+                            for (upgrade, package) in &planned {
+                                let from = if upgrade.from.is_empty() { "?" } else { upgrade.from.as_str() };
+                                output::print(&format!("{} {} -> {}\n", upgrade.name, from, upgrade.to));
+                                for line in package.changelog.lines() {
+                                    output::print(&format!("    {}\n", line));
+                                }
+                            }
+                            if dry_run {
+                                output::print("Dry run: nothing changed\n");
+                            } else {
+                                output::print(&format!("Upgraded {} packages\n", planned.len()));
+                            }
+                        }
+                        Err(e) => output::print(&format!("tomato: {}\n", e)),
+                    }
+                }
+                ["search", query] => match tomato::search(query) {
+                    Ok(found) if found.is_empty() => output::print("No packages found\n"),
+                    Ok(found) => {
+                        for package in found {
+                            output::print(&format!("{:<16}{:<10}{}\n", package.name, package.version, package.description));
+                        }
+                    }
+                    Err(e) => output::print(&format!("tomato: {}\n", e)),
+                },
+                _ => output::print("Usage: tomato <install|remove [--force]|search> <package> | list [--json] | autoremove | update [source] | upgrade [--dry-run]\n"),
+            }
+        }
         "doom" => {
-            framebuffer::print("Starting DOOM...\n");
-            framebuffer::print("(Ctrl+C to exit)\n\n");
+            output::print("Starting DOOM...\n");
+            if parts.contains(&"--engine") {
+                crate::doom::run_doomgeneric();
+                return;
+            }
+            let window = parts.contains(&"--window");
+            let map = parts[1..].iter().copied().find(|arg| !arg.starts_with("--"));
+            if window {
+                crate::doom::run_windowed(map);
+                return;
+            }
+            output::print("(Ctrl+C to exit)\n\n");
             // Small delay to show message
             for _ in 0..5000000 {
                 core::hint::spin_loop();
             }
-            crate::doom::run_demo();
+            crate::doom::run_demo(map);
         }
         "doom" => {
-            framebuffer::print("Starting DOOM...\n");
-            crate::doom::run_demo();
+            output::print("Starting DOOM...\n");
+            crate::doom::run_demo(None);
         }
         "sudo" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: sudo <command>\n");
+                output::print("Usage: sudo <command>\n");
                 return;
             }
             // In ospabOS, we're always root, so just execute the command
-            framebuffer::print("You are already root. Executing: ");
-            framebuffer::print(&parts[1..].join(" "));
-            framebuffer::print("\n");
+            output::print("You are already root. Executing: ");
+            output::print(&parts[1..].join(" "));
+            output::print("\n");
             // For now, just show what would be executed
-            framebuffer::print("(sudo simulation - command not actually executed)\n");
+            output::print("(sudo simulation - command not actually executed)\n");
         }
         "top" => {
-            framebuffer::print("top - ");
+            output::print("top - ");
             use crate::drivers::timer;
             let uptime_ms = timer::get_uptime_ms();
             let uptime_s = uptime_ms / 1000;
             let hours = uptime_s / 3600;
             let minutes = (uptime_s % 3600) / 60;
             print_num(hours);
-            framebuffer::print(":");
-            if minutes < 10 { framebuffer::print("0"); }
+            output::print(":");
+            if minutes < 10 { output::print("0"); }
             print_num(minutes);
-            framebuffer::print(" up,  1 user,  load average: 0.00, 0.00, 0.00\n");
-            framebuffer::print("Tasks:   5 total,   1 running,   4 sleeping,   0 stopped,   0 zombie\n");
-            framebuffer::print("%Cpu(s):  0.0 us,  0.0 sy,  0.0 ni,100.0 id,  0.0 wa,  0.0 hi,  0.0 si,  0.0 st\n");
-            framebuffer::print("MiB Mem :   4096.0 total,   4090.0 free,      6.0 used,      0.0 buff/cache\n");
-            framebuffer::print("MiB Swap:      0.0 total,      0.0 free,      0.0 used,      0.0 avail Mem\n");
-            framebuffer::print("\n");
-            framebuffer::print("  PID USER      PR  NI    VIRT    RES    SHR S  %CPU  %MEM     TIME+ COMMAND\n");
-            framebuffer::print("    1 root      20   0       0      0      0 S   0.0   0.0   0:00.00 kernel\n");
-            framebuffer::print("    2 root      20   0       0      0      0 S   0.0   0.0   0:00.00 init\n");
-            framebuffer::print("    3 root      20   0       0      0      0 R   0.0   0.0   0:00.00 shell\n");
-            framebuffer::print("    4 root      20   0       0      0      0 S   0.0   0.0   0:00.00 vfs\n");
-            framebuffer::print("    5 root      20   0       0      0      0 S   0.0   0.0   0:00.00 ipc\n");
+            output::print(" up,  1 user,  load average: 0.00, 0.00, 0.00\n");
+            output::print("Tasks:   5 total,   1 running,   4 sleeping,   0 stopped,   0 zombie\n");
+            output::print("%Cpu(s):  0.0 us,  0.0 sy,  0.0 ni,100.0 id,  0.0 wa,  0.0 hi,  0.0 si,  0.0 st\n");
+            output::print("MiB Mem :   4096.0 total,   4090.0 free,      6.0 used,      0.0 buff/cache\n");
+            output::print("MiB Swap:      0.0 total,      0.0 free,      0.0 used,      0.0 avail Mem\n");
+            output::print("\n");
+            output::print("  PID USER      PR  NI    VIRT    RES    SHR S  %CPU  %MEM     TIME+ COMMAND\n");
+            output::print("    1 root      20   0       0      0      0 S   0.0   0.0   0:00.00 kernel\n");
+            output::print("    2 root      20   0       0      0      0 S   0.0   0.0   0:00.00 init\n");
+            output::print("    3 root      20   0       0      0      0 R   0.0   0.0   0:00.00 shell\n");
+            output::print("    4 root      20   0       0      0      0 S   0.0   0.0   0:00.00 vfs\n");
+            output::print("    5 root      20   0       0      0      0 S   0.0   0.0   0:00.00 ipc\n");
         }
         "df" => {
-            framebuffer::print("Filesystem     1K-blocks  Used Available Use% Mounted on\n");
-            framebuffer::print("tmpfs                512     0       512   0% /tmp\n");
-            framebuffer::print("initrd              1024   256       768  25% /\n");
-            framebuffer::print("proc                    0     0         0   0% /proc\n");
-            framebuffer::print("sysfs                  0     0         0   0% /sys\n");
+            let human = parts.iter().skip(1).any(|&arg| arg == "-h");
+            let filesystems = vfs::usage();
+            let use_percent = |size: u64, used: u64| if size == 0 { 0 } else { (used * 100).div_ceil(size) };
+            if wants_json(&parts) {
+                print_json(json::object([("filesystems", json::array(filesystems.iter().map(
+                    |fs| json::object([
+                        ("filesystem", fs.filesystem.into()),
+                        ("size", fs.size.into()),
+                        ("used", fs.used.into()),
+                        ("available", (fs.size - fs.used).into()),
+                        ("use_percent", use_percent(fs.size, fs.used).into()),
+                        ("mounted_on", fs.mount.into()),
+                    ]),
+                )))]));
+                return;
+            }
+            output::print(&format!("{:<15}{:>10}{:>10}{:>10}{:>5} {}\n",
+                "Filesystem", if human { "Size" } else { "1K-blocks" }, "Used", "Available", "Use%", "Mounted on"));
+            for fs in &filesystems {
+                output::print(&format!("{:<15}{:>10}{:>10}{:>10}{:>4}% {}\n",
+                    fs.filesystem,
+                    format_size(fs.size, human),
+                    format_size(fs.used, human),
+                    format_size(fs.size - fs.used, human),
+                    use_percent(fs.size, fs.used),
+                    fs.mount));
+            }
         }
         "du" => {
-            let path = if parts.len() > 1 { parts[1] } else { "." };
-            framebuffer::print("4K\t");
-            framebuffer::print(path);
-            framebuffer::print("\n");
-            framebuffer::print("(disk usage calculation not fully implemented)\n");
+            let mut human = false;
+            let mut summary = false;
+            let mut paths = Vec::new();
+            for arg in &parts[1..] {
+                match *arg {
+                    "-h" => human = true,
+                    "-s" => summary = true,
+                    "-sh" | "-hs" => {
+                        human = true;
+                        summary = true;
+                    }
+                    arg if arg.starts_with('-') => {
+                        output::print(&format!("du: unknown option {}\nUsage: du [-h] [-s] [path...]\n", arg));
+                        set_status(2);
+                        return;
+                    }
+                    path => paths.push(path),
+                }
+            }
+            if paths.is_empty() {
+                paths.push(".");
+            }
+            // Whole kibibytes, rounding up as a disk would
+            let size = |bytes: u64| if human { format_size(bytes, true) } else { format!("{}", bytes.div_ceil(1024)) };
+            for path in paths {
+                match vfs::disk_usage(path) {
+                    Ok(sizes) => {
+                        let shown = if summary { &sizes[sizes.len() - 1..] } else { &sizes[..] };
+                        for (dir, bytes) in shown {
+                            output::print(&format!("{}\t{}\n", size(*bytes), dir));
+                        }
+                    }
+                    Err(e) => {
+                        output::print(&format!("du: {}: {}\n", path, e.as_str()));
+                        set_status(1);
+                    }
+                }
+            }
+        }
+        "jobs" => jobs::list(),
+        "fg" => match jobs::fg(parts.get(1).copied()) {
+            Ok(status) => set_status(status),
+            Err(e) => {
+                output::print(&format!("fg: {}\n", e));
+                set_status(1);
+            }
+        },
+        "bg" => {
+            if let Err(e) = jobs::bg(parts.get(1).copied()) {
+                output::print(&format!("bg: {}\n", e));
+                set_status(1);
+            }
         }
         "kill" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: kill <pid> [signal]\n");
+                output::print("Usage: kill <pid|%job> [signal]\n");
                 return;
             }
-            framebuffer::print("kill: sending signal to process ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (simulation)\n");
+            use crate::syscall::abi::{SIGCONT, SIGINT, SIGKILL, SIGSTOP, SIGTERM, SIGTSTP};
+            let sig = match parts.get(2).map(|s| s.trim_start_matches("SIG")) {
+                None | Some("TERM") => Some(SIGTERM as u32),
+                Some("INT") => Some(SIGINT as u32),
+                Some("KILL") => Some(SIGKILL as u32),
+                Some("STOP") => Some(SIGSTOP as u32),
+                Some("TSTP") => Some(SIGTSTP as u32),
+                Some("CONT") => Some(SIGCONT as u32),
+                Some(n) => n.parse().ok(),
+            };
+            let pid = if parts[1].starts_with('%') {
+                match jobs::find(Some(parts[1])) {
+                    Ok(pid) => Ok(pid),
+                    Err(e) => {
+                        output::print(&format!("kill: {}\n", e));
+                        set_status(1);
+                        return;
+                    }
+                }
+            } else {
+                parts[1].parse::<u32>()
+            };
+            let (Ok(pid), Some(sig)) = (pid, sig) else {
+                output::print("Usage: kill <pid|%job> [signal]\n");
+                return;
+            };
+            match crate::task::signal::send(pid, sig) {
+                Ok(()) => {}
+                Err(crate::syscall::abi::ESRCH) => {
+                    output::print(&format!("kill: ({}) - No such process\n", pid));
+                    set_status(1);
+                }
+                Err(_) => {
+                    output::print(&format!("kill: cannot signal {}\n", pid));
+                    set_status(1);
+                }
+            }
         }
         "pkill" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: pkill <pattern>\n");
+                output::print("Usage: pkill <pattern>\n");
                 return;
             }
-            framebuffer::print("pkill: killing processes matching '");
-            framebuffer::print(parts[1]);
-            framebuffer::print("' (simulation)\n");
+            output::print("pkill: killing processes matching '");
+            output::print(parts[1]);
+            output::print("' (simulation)\n");
         }
         "chmod" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: chmod <mode> <file>\n");
+                output::print("Usage: chmod <mode> <file>\n");
                 return;
             }
-            framebuffer::print("chmod: changing permissions of '");
-            framebuffer::print(parts[2]);
-            framebuffer::print("' to ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (simulation)\n");
+            output::print("chmod: changing permissions of '");
+            output::print(parts[2]);
+            output::print("' to ");
+            output::print(parts[1]);
+            output::print(" (simulation)\n");
         }
         "chown" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: chown <owner> <file>\n");
+                output::print("Usage: chown <owner> <file>\n");
                 return;
             }
-            framebuffer::print("chown: changing ownership of '");
-            framebuffer::print(parts[2]);
-            framebuffer::print("' to ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (simulation)\n");
+            output::print("chown: changing ownership of '");
+            output::print(parts[2]);
+            output::print("' to ");
+            output::print(parts[1]);
+            output::print(" (simulation)\n");
         }
         "grep" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: grep <pattern> <file>\n");
+                output::print("Usage: grep <pattern> <file>\n");
                 return;
             }
-            framebuffer::print("grep: searching for '");
-            framebuffer::print(parts[1]);
-            framebuffer::print("' in ");
-            framebuffer::print(parts[2]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("grep: searching for '");
+            output::print(parts[1]);
+            output::print("' in ");
+            output::print(parts[2]);
+            output::print(" (not implemented)\n");
         }
         "find" => {
             let path = if parts.len() > 1 { parts[1] } else { "." };
             let name = if parts.len() > 3 && parts[2] == "-name" { parts[3] } else { "*" };
-            framebuffer::print("find: searching in ");
-            framebuffer::print(path);
-            framebuffer::print(" for ");
-            framebuffer::print(name);
-            framebuffer::print(" (not implemented)\n");
-        }
-        "wc" => {
-            if parts.len() < 2 {
-                framebuffer::print("Usage: wc [options] <file>\n");
-                framebuffer::print("Options: -l (lines), -w (words), -c (bytes)\n");
-                return;
-            }
-            framebuffer::print("wc: counting ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("find: searching in ");
+            output::print(path);
+            output::print(" for ");
+            output::print(name);
+            output::print(" (not implemented)\n");
         }
         "head" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: head [-n lines] <file>\n");
+                output::print("Usage: head [-n lines] <file>\n");
                 return;
             }
-            framebuffer::print("head: showing first 10 lines of ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("head: showing first 10 lines of ");
+            output::print(parts[1]);
+            output::print(" (not implemented)\n");
         }
         "tail" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: tail [-n lines] <file>\n");
+                output::print("Usage: tail [-n lines] <file>\n");
                 return;
             }
-            framebuffer::print("tail: showing last 10 lines of ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("tail: showing last 10 lines of ");
+            output::print(parts[1]);
+            output::print(" (not implemented)\n");
         }
         "sort" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: sort [options] <file>\n");
+                output::print("Usage: sort [options] <file>\n");
                 return;
             }
-            framebuffer::print("sort: sorting ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("sort: sorting ");
+            output::print(parts[1]);
+            output::print(" (not implemented)\n");
         }
         "uniq" => {
             if parts.len() < 2 {
-                framebuffer::print("Usage: uniq [options] <file>\n");
+                output::print("Usage: uniq [options] <file>\n");
                 return;
             }
-            framebuffer::print("uniq: removing duplicates from ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("uniq: removing duplicates from ");
+            output::print(parts[1]);
+            output::print(" (not implemented)\n");
         }
         "tar" => {
             if parts.len() < 3 {
-                framebuffer::print("Usage: tar [c|x|t] [f archive] [files...]\n");
-                framebuffer::print("  c - create, x - extract, t - list\n");
+                output::print("Usage: tar [c|x|t] [f archive] [files...]\n");
+                output::print("  c - create, x - extract, t - list\n");
                 return;
             }
-            framebuffer::print("tar: ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" archive ");
-            framebuffer::print(parts[2]);
-            framebuffer::print(" (not implemented)\n");
+            output::print("tar: ");
+            output::print(parts[1]);
+            output::print(" archive ");
+            output::print(parts[2]);
+            output::print(" (not implemented)\n");
         }
         "wget" => {
-            if parts.len() < 2 {
-                framebuffer::print("Usage: wget <url>\n");
+            let (output, url) = match parts.as_slice() {
+                [_, "-O", file, url] => (Some(*file), *url),
+                [_, url] => (None, *url),
+                _ => {
+                    output::print("Usage: wget [-O file] <url>\n");
+                    return;
+                }
+            };
+
+            output::print("Connecting to ");
+            output::print(url);
+            output::print("...\n");
+            let response = match net::http::get(url) {
+                Ok(r) => r,
+                Err(e) => {
+                    output::print("wget: ");
+                    output::print(match e {
+                        net::NetworkError::NameNotFound => "unable to resolve host",
+                        net::NetworkError::Timeout => "connection timed out",
+                        net::NetworkError::NotImplemented => "unsupported scheme (http only)",
+                        net::NetworkError::InvalidAddress => "invalid URL",
+                        net::NetworkError::InvalidPacket => "malformed response",
+                        net::NetworkError::TooLarge => "response body too large",
+                        net::NetworkError::NoDevice => NO_NIC,
+                        _ => "connection failed",
+                    });
+                    output::print("\n");
+                    return;
+                }
+            };
+
+            output::print("HTTP request sent, response: ");
+            print_num(response.status as u64);
+            output::print(" ");
+            output::print(&response.reason);
+            output::print("\n");
+            if !(200..300).contains(&response.status) {
                 return;
             }
-            framebuffer::print("wget: downloading ");
-            framebuffer::print(parts[1]);
-            framebuffer::print(" (network not implemented)\n");
+
+            // Default to the last path component, like wget does
+            let filename = output.map(|f| f.to_string()).unwrap_or_else(|| {
+                let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+                let path = without_scheme.split('?').next().unwrap_or("");
+                match path.split_once('/') {
+                    Some((_, p)) if !p.is_empty() && !p.ends_with('/') => {
+                        p.rsplit('/').next().unwrap_or(p).to_string()
+                    }
+                    _ => "index.html".to_string(),
+                }
+            });
+            let len = response.body.len();
+            match vfs::process_request(FSRequest::WriteFile { path: filename.clone(), data: response.body }) {
+                crate::ipc::message::FSResponse::Success => {
+                    output::print("Saved ");
+                    print_num(len as u64);
+                    output::print(" bytes to ");
+                    output::print(&filename);
+                    output::print("\n");
+                }
+                _ => {
+                    output::print("wget: cannot write ");
+                    output::print(&filename);
+                    output::print("\n");
+                }
+            }
         }
         "ping" => {
-            if parts.len() < 2 {
-                framebuffer::print("Usage: ping <host>\n");
-                return;
-            }
+            let (size, host) = match parts.as_slice() {
+                [_, "-s", size, host] => match size.parse::<usize>() {
+                    Ok(size) if size <= net::ip::MAX_PAYLOAD - 8 => (size, *host),
+                    _ => {
+                        output::print("ping: invalid packet size\n");
+                        return;
+                    }
+                },
+                [_, host] => (56, *host),
+                _ => {
+                    output::print("Usage: ping [-s size] <host>\n");
+                    return;
+                }
+            };
 
             // Try to resolve hostname first
-            let ip_result = if let Ok(ip) = parse_ip_addr(parts[1]) {
+            let ip_result = if let Ok(ip) = parse_ip_addr(host) {
                 Ok(ip)
             } else {
-                net::resolve_hostname(parts[1])
+                net::resolve_hostname(host)
             };
 
             match ip_result {
                 Ok(ip) => {
-                    framebuffer::print("PING ");
-                    framebuffer::print(parts[1]);
-                    framebuffer::print(" (");
+                    output::print("PING ");
+                    output::print(host);
+                    output::print(" (");
                     print_ip_addr(ip);
-                    framebuffer::print(") 56(84) bytes of data.\n");
-
-                    match net::ping(ip, 1000) {
-                        Ok(rtt) => {
-                            framebuffer::print("64 bytes from ");
-                            print_ip_addr(ip);
-                            framebuffer::print(": icmp_seq=1 ttl=64 time=");
-                            print_num(rtt as u64);
-                            framebuffer::print(" ms\n");
-                            framebuffer::print("\n--- ");
-                            framebuffer::print(parts[1]);
-                            framebuffer::print(" ping statistics ---\n");
-                            framebuffer::print("1 packets transmitted, 1 received, 0% packet loss, time ");
-                            print_num(rtt as u64);
-                            framebuffer::print("ms\n");
+                    output::print(") ");
+                    print_num(size as u64);
+                    output::print("(");
+                    print_num(size as u64 + 28);
+                    output::print(") bytes of data.\n");
+
+                    match net::icmp::echo(ip, 1, size, 1000) {
+                        Ok(reply) => {
+                            print_num(reply.len as u64 + 8);
+                            output::print(" bytes from ");
+                            print_ip_addr(reply.from);
+                            output::print(": icmp_seq=1 ttl=");
+                            print_num(reply.ttl as u64);
+                            output::print(" time=");
+                            print_num(reply.rtt_ms);
+                            output::print(" ms\n");
+                            output::print("\n--- ");
+                            output::print(host);
+                            output::print(" ping statistics ---\n");
+                            output::print("1 packets transmitted, 1 received, 0% packet loss, time ");
+                            print_num(reply.rtt_ms);
+                            output::print("ms\n");
+                        }
+                        Err(net::NetworkError::NoDevice) => {
+                            output::print("ping: ");
+                            output::print(NO_NIC);
+                            output::print("\n");
                         }
                         Err(_) => {
-                            framebuffer::print("Request timeout for icmp_seq 1\n");
+                            output::print("Request timeout for icmp_seq 1\n");
                         }
                     }
                 }
+                Err(net::NetworkError::NoDevice) => {
+                    output::print("ping: ");
+                    output::print(NO_NIC);
+                    output::print("\n");
+                }
                 Err(_) => {
-                    framebuffer::print("ping: ");
-                    framebuffer::print(parts[1]);
-                    framebuffer::print(": Name or service not known\n");
+                    output::print("ping: ");
+                    output::print(host);
+                    output::print(": Name or service not known\n");
+                }
+            }
+        }
+        "rz" => {
+            if parts.len() > 2 {
+                output::print("Usage: rz [file]\n");
+                return;
+            }
+            output::print("rz: waiting for YMODEM/XMODEM upload on COM1, press any key to abort\n");
+            let files = match crate::apps::ymodem::receive() {
+                Ok(files) => files,
+                Err(e) => {
+                    output::print("rz: ");
+                    output::print(&e);
+                    output::print("\n");
+                    return;
+                }
+            };
+            // One name cannot hold a whole batch; nothing is written then
+            if parts.len() == 2 && files.len() > 1 {
+                output::print("rz: ");
+                print_num(files.len() as u64);
+                output::print(" files received, a file name can only be given for one\n");
+                return;
+            }
+            for file in files {
+                // An explicit name wins; sender paths are reduced to their last component
+                let path = match (parts.get(1), file.name.as_deref().and_then(|name| name.rsplit('/').next())) {
+                    (Some(name), _) => name.to_string(),
+                    (None, Some(name)) if !name.is_empty() => name.to_string(),
+                    _ => "rz.bin".to_string(),
+                };
+                let len = file.data.len();
+                match vfs::process_request(FSRequest::WriteFile { path: path.clone(), data: file.data }) {
+                    crate::ipc::message::FSResponse::Success => {
+                        output::print("Received ");
+                        output::print(&path);
+                        output::print(" (");
+                        print_num(len as u64);
+                        output::print(" bytes)\n");
+                    }
+                    _ => {
+                        output::print("rz: cannot write ");
+                        output::print(&path);
+                        output::print("\n");
+                    }
+                }
+            }
+        }
+        "sz" => {
+            if parts.len() != 2 {
+                output::print("Usage: sz <file>\n");
+                return;
+            }
+            let data = match coreutils::cat(parts[1]) {
+                Ok(data) => data,
+                Err(e) => {
+                    output::print("sz: ");
+                    output::print(&e);
+                    output::print("\n");
+                    return;
+                }
+            };
+            let name = parts[1].rsplit('/').next().unwrap_or(parts[1]);
+            output::print("sz: start a YMODEM receive on the host (e.g. rb), press any key to abort\n");
+            match crate::apps::ymodem::send(name, &data) {
+                Ok(()) => {
+                    output::print("Sent ");
+                    output::print(name);
+                    output::print(" (");
+                    print_num(data.len() as u64);
+                    output::print(" bytes)\n");
+                }
+                Err(e) => {
+                    output::print("sz: ");
+                    output::print(&e);
+                    output::print("\n");
+                }
+            }
+        }
+        "tcpdump" => {
+            let mut count: Option<usize> = None;
+            let mut output: Option<&str> = None;
+            let mut args = parts[1..].iter();
+            while let Some(arg) = args.next() {
+                match (*arg, args.next()) {
+                    ("-c", Some(n)) => match n.parse::<usize>() {
+                        Ok(n) if n > 0 => count = Some(n),
+                        _ => {
+                            output::print("tcpdump: invalid packet count\n");
+                            return;
+                        }
+                    },
+                    ("-w", Some(file)) => output = Some(*file),
+                    _ => {
+                        output::print("Usage: tcpdump [-c count] [-w file.pcap]\n");
+                        return;
+                    }
+                }
+            }
+
+            let fd = match net::socket::socket(net::socket::SocketDomain::AfInet, net::socket::SocketType::Raw, 0) {
+                Ok(fd) => fd,
+                Err(_) => {
+                    output::print("tcpdump: cannot open capture socket\n");
+                    return;
+                }
+            };
+            output::print("tcpdump: listening on all interfaces, press any key to stop\n");
+
+            let mut captured = Vec::new();
+            let mut packets = 0usize;
+            let mut buf = alloc::vec![0u8; 65536];
+            while count.map_or(true, |c| packets < c) {
+                if crate::drivers::keyboard::try_read_key().is_some() {
+                    break;
+                }
+                net::poll();
+                let len = match net::socket::receive(fd, &mut buf) {
+                    Ok(len) => len,
+                    Err(net::NetworkError::Timeout) => {
+                        net::idle();
+                        continue;
+                    }
+                    Err(_) => break,
+                };
+                let frame = net::capture::CapturedFrame {
+                    timestamp_ms: crate::drivers::timer::get_uptime_ms(),
+                    data: buf[..len].to_vec(),
+                };
+                output::print(&format!("{}.{:03} {}\n", frame.timestamp_ms / 1000,
+                                            frame.timestamp_ms % 1000, net::capture::describe(&frame.data)));
+                packets += 1;
+                // Without -w there is no need to keep the frames around
+                if output.is_some() {
+                    captured.push(frame);
+                }
+            }
+            let _ = net::socket::close_socket(fd);
+
+            print_num(packets as u64);
+            output::print(" packets captured\n");
+
+            if let Some(file) = output {
+                let data = net::capture::to_pcap(&captured);
+                match vfs::process_request(FSRequest::WriteFile { path: file.to_string(), data }) {
+                    crate::ipc::message::FSResponse::Success => {
+                        output::print("Wrote ");
+                        output::print(file);
+                        output::print("\n");
+                    }
+                    _ => {
+                        output::print("tcpdump: cannot write ");
+                        output::print(file);
+                        output::print("\n");
+                    }
+                }
+            }
+        }
+        "nslookup" => {
+            if parts.len() < 2 {
+                output::print("Usage: nslookup <host> [server]\n");
+                return;
+            }
+
+            let server = if parts.len() > 2 {
+                match parse_ip_addr(parts[2]) {
+                    Ok(ip) => ip,
+                    Err(_) => {
+                        output::print("nslookup: invalid server address\n");
+                        return;
+                    }
+                }
+            } else {
+                net::dns::nameserver()
+            };
+
+            output::print("Server:\t\t");
+            print_ip_addr(server);
+            output::print("\nAddress:\t");
+            print_ip_addr(server);
+            output::print("#53\n\n");
+
+            match net::dns::query(server, parts[1]) {
+                Ok((ip, _ttl)) => {
+                    output::print("Name:\t");
+                    output::print(parts[1]);
+                    output::print("\nAddress: ");
+                    print_ip_addr(ip);
+                    output::print("\n");
+                }
+                Err(net::NetworkError::NameNotFound) => {
+                    output::print("** server can't find ");
+                    output::print(parts[1]);
+                    output::print(": NXDOMAIN\n");
+                }
+                Err(net::NetworkError::Timeout) => {
+                    output::print(";; connection timed out; no servers could be reached\n");
+                }
+                Err(net::NetworkError::NoDevice) => {
+                    output::print("nslookup: ");
+                    output::print(NO_NIC);
+                    output::print("\n");
+                }
+                Err(_) => {
+                    output::print("nslookup: lookup failed for ");
+                    output::print(parts[1]);
+                    output::print("\n");
                 }
             }
         }
         "ifconfig" => {
             let interfaces = net::list_interfaces();
+            if wants_json(&parts) {
+                print_json(json::object([("interfaces", json::array(interfaces.iter().map(|iface| {
+                    let ip = iface.ip.bytes();
+                    let mask = iface.netmask.bytes();
+                    let broadcast = (!iface.ip.is_loopback()).then(|| {
+                        net::IpAddress::from_bytes(core::array::from_fn(|i| ip[i] | !mask[i])).to_string()
+                    });
+                    json::object([
+                        ("name", iface.name.as_str().into()),
+                        ("mtu", (iface.mtu as u32).into()),
+                        ("inet", iface.ip.to_string().into()),
+                        ("netmask", iface.netmask.to_string().into()),
+                        ("broadcast", broadcast.into()),
+                        ("ether", iface.mac.to_string().into()),
+                    ])
+                })))]));
+                return;
+            }
             for iface in interfaces {
-                framebuffer::print(&iface.name);
-                framebuffer::print(": flags=73<UP,LOOPBACK,RUNNING>  mtu ");
+                output::print(&iface.name);
+                output::print(": flags=73<UP,LOOPBACK,RUNNING>  mtu ");
                 print_num(iface.mtu as u64);
-                framebuffer::print("\n        inet ");
+                output::print("\n        inet ");
                 print_ip_addr(iface.ip);
-                framebuffer::print("  netmask ");
+                output::print("  netmask ");
                 print_ip_addr(iface.netmask);
                 if iface.name == "eth0" {
-                    framebuffer::print("  broadcast ");
+                    output::print("  broadcast ");
                     // Calculate broadcast address
                     let ip_bytes = iface.ip.bytes();
                     let mask_bytes = iface.netmask.bytes();
@@ -823,65 +1997,298 @@ pub fn execute_command(cmd: &str) {
                     let broadcast_ip = net::IpAddress::from_bytes(broadcast);
                     print_ip_addr(broadcast_ip);
                 }
-                framebuffer::print("\n        ether ");
+                output::print("\n        ether ");
                 if iface.name == "lo" {
-                    framebuffer::print("00:00:00:00:00:00");
+                    output::print("00:00:00:00:00:00");
                 } else {
-                    framebuffer::print("52:54:00:12:34:56");
+                    output::print("52:54:00:12:34:56");
                 }
-                framebuffer::print("  txqueuelen 1000  (");
+                output::print("  txqueuelen 1000  (");
                 if iface.name == "lo" {
-                    framebuffer::print("Local Loopback");
+                    output::print("Local Loopback");
                 } else {
-                    framebuffer::print("Ethernet");
+                    output::print("Ethernet");
+                }
+                output::print(")\n");
+                output::print("        RX packets 0  bytes 0 (0.0 B)\n");
+                output::print("        RX errors 0  dropped 0  overruns 0  frame 0\n");
+                output::print("        TX packets 0  bytes 0 (0.0 B)\n");
+                output::print("        TX errors 0  dropped 0 overruns 0  carrier 0  collisions 0\n\n");
+            }
+        }
+        "boottime" => {
+            let mut stages = match crate::boot::timeline::stages() {
+                Some(stages) => stages,
+                None => {
+                    output::print("boottime: clock not calibrated yet, try again in a moment\n");
+                    return;
+                }
+            };
+            let total: u64 = stages.iter().map(|stage| stage.duration_us).sum();
+            let slowest = stages.iter().map(|stage| stage.duration_us).max().unwrap_or(0);
+            output::print(&format!("Startup finished in {} (kernel entry to shell prompt)\n\n",
+                format_duration_us(total)));
+            if parts.get(1) == Some(&"blame") {
+                stages.sort_by(|a, b| b.duration_us.cmp(&a.duration_us));
+            }
+            for stage in &stages {
+                let marker = if stage.duration_us == slowest && slowest > 0 { "  <- slowest" } else { "" };
+                output::print(&format!("{:>12}  {}{}\n",
+                    format_duration_us(stage.duration_us), stage.name, marker));
+            }
+        }
+        "slabinfo" => {
+            output::print(&format!("{:<14}{:>8}{:>8}{:>8}{:>10}{:>8}\n",
+                "name", "active", "total", "size", "per slab", "slabs"));
+            for cache in crate::mm::heap_allocator::slab_info() {
+                output::print(&format!("{:<14}{:>8}{:>8}{:>8}{:>10}{:>8}\n",
+                    cache.name, cache.active, cache.total, cache.object_size,
+                    cache.objects_per_slab, cache.slabs));
+            }
+            let heap = crate::mm::heap_allocator::stats();
+            output::print(&format!("\nPages in use: {}, {}% of it unused\n",
+                format_size(heap.reserved as u64, true), heap.fragmentation_percent()));
+        }
+        "mouse" => {
+            use crate::drivers::mouse;
+            if !mouse::is_present() {
+                output::print("mouse: no PS/2 mouse found\n");
+                return;
+            }
+            match parts.get(1).copied() {
+                Some(arg @ ("on" | "off")) => {
+                    if !mouse::set_pointer_visible(arg == "on") {
+                        output::print("mouse: the console cannot draw a pointer\n");
+                    }
+                }
+                Some(_) => output::print("Usage: mouse [on|off]\n"),
+                None => {
+                    let (x, y) = mouse::position();
+                    output::print(&format!("PS/2 mouse at {},{} (events in /dev/mouse)\n", x, y));
+                }
+            }
+        }
+        "services" => {
+            use crate::ipc::registry;
+            let services = registry::list();
+            if wants_json(&parts) {
+                print_json(json::object([("services", json::array(services.iter().map(|service| json::object([
+                    ("name", service.name.as_str().into()),
+                    ("mailbox", service.mailbox.into()),
+                    ("health", service.health.as_str().into()),
+                    ("capabilities", json::array(service.capabilities.iter().copied())),
+                ]))))]));
+                return;
+            }
+            if services.is_empty() {
+                output::print("No services registered\n");
+                return;
+            }
+            output::print(&format!("{:<12}{:>8}  {:<10}{}\n",
+                "NAME", "MAILBOX", "HEALTH", "CAPABILITIES"));
+            for service in services {
+                output::print(&format!("{:<12}{:>8}  {:<10}{}\n",
+                    service.name, service.mailbox, service.health.as_str(),
+                    service.capabilities.join(" ")));
+            }
+        }
+        "ulimit" => {
+            use crate::syscall::abi::{RLimit, RLIM_INFINITY};
+            // Only the CPU limit (-t, seconds) exists; programs started
+            // from here inherit it
+            if parts.len() < 2 || parts[1] != "-t" {
+                output::print("Usage: ulimit -t [seconds|unlimited]\n");
+                return;
+            }
+            match parts.get(2) {
+                None => {
+                    let limit = crate::task::cpu_limit();
+                    if limit.cur == RLIM_INFINITY {
+                        output::print("unlimited\n");
+                    } else {
+                        output::print(&format!("{}\n", limit.cur));
+                    }
+                }
+                Some(value) => {
+                    let seconds = match *value {
+                        "unlimited" => RLIM_INFINITY,
+                        v => match v.parse::<u64>() {
+                            Ok(n) => n,
+                            Err(_) => {
+                                output::print("ulimit: invalid limit\n");
+                                return;
+                            }
+                        },
+                    };
+                    let limit = RLimit { cur: seconds, max: seconds };
+                    if let Err(e) = crate::task::set_cpu_limit(limit) {
+                        output::print(&format!("ulimit: {}\n", e));
+                    }
                 }
-                framebuffer::print(")\n");
-                framebuffer::print("        RX packets 0  bytes 0 (0.0 B)\n");
-                framebuffer::print("        RX errors 0  dropped 0  overruns 0  frame 0\n");
-                framebuffer::print("        TX packets 0  bytes 0 (0.0 B)\n");
-                framebuffer::print("        TX errors 0  dropped 0 overruns 0  carrier 0  collisions 0\n\n");
             }
         }
         "dmesg" => {
-            framebuffer::print("[    0.000000] ospabOS v0.1.0 \"Foundation\" booting...\n");
-            framebuffer::print("[    0.001234] GDT initialized\n");
-            framebuffer::print("[    0.002345] IDT initialized\n");
-            framebuffer::print("[    0.003456] Framebuffer initialized: 1280x720\n");
-            framebuffer::print("[    0.004567] Serial port initialized\n");
-            framebuffer::print("[    0.005678] Keyboard initialized\n");
-            framebuffer::print("[    0.006789] Memory management initialized\n");
-            framebuffer::print("[    0.007890] VMM initialized\n");
-            framebuffer::print("[    0.008901] Syscall interface ready\n");
-            framebuffer::print("[    0.009012] IPC services online\n");
-            framebuffer::print("[    0.010123] System ready\n");
+            output::print(&crate::klog::read());
+            if parts.get(1) == Some(&"-c") {
+                crate::klog::clear();
+            }
         }
+        "lsmod" => {
+            let modules = crate::boot::limine::module_list();
+            if modules.is_empty() {
+                output::print("No modules\n");
+                return;
+            }
+            output::print(&format!("{:<24}{:>10}  {}\n", "NAME", "SIZE", "PATH"));
+            for module in modules {
+                output::print(&format!("{:<24}{:>10}  {}\n", module.name, module.data.len(), module.path));
+            }
+        }
+        "lspci" => {
+            let listing = crate::drivers::pci::listing(parts.get(1) == Some(&"-v"));
+            if listing.is_empty() {
+                output::print("No PCI devices\n");
+            } else {
+                output::print(&listing);
+            }
+        }
+        "sync" => {
+            for (device, e) in crate::drivers::block::sync() {
+                output::print(&format!("sync: {}: {}\n", device, e));
+                set_status(1);
+            }
+        }
+        "strace" => {
+            use crate::syscall::trace;
+            match (parts.get(1).copied(), parts.get(2).copied()) {
+                (None, _) => {
+                    let traced = trace::traced();
+                    if trace::trace_all() {
+                        output::print("Tracing all tasks\n");
+                    } else if traced.is_empty() {
+                        output::print("Not tracing any task\n");
+                    } else {
+                        let pids: Vec<alloc::string::String> = traced.iter().map(|pid| pid.to_string()).collect();
+                        output::print(&format!("Tracing pid {}\n", pids.join(", ")));
+                    }
+                }
+                (Some("all"), None) => {
+                    trace::set_trace_all(true);
+                    output::print("Tracing all tasks, see dmesg\n");
+                }
+                (Some("off"), None) => trace::untrace_all(),
+                (Some("off"), Some(pid)) => match pid.parse::<u32>() {
+                    Ok(pid) if trace::untrace(pid) => {}
+                    _ => output::print(&format!("strace: {}: not traced\n", pid)),
+                },
+                (Some(pid), None) => match pid.parse::<u32>() {
+                    Ok(pid) if crate::task::scheduler::SCHEDULER.lock().task_mut(pid).is_some() => {
+                        trace::trace(pid);
+                        output::print(&format!("Tracing pid {}, see dmesg\n", pid));
+                    }
+                    _ => output::print(&format!("strace: {}: no such task\n", pid)),
+                },
+                _ => output::print("Usage: strace [<pid> | all | off [pid]]\n"),
+            }
+        }
+        "profile" => match parts.get(1).copied() {
+            None => match crate::profile::report() {
+                Some(report) => output::print(&report),
+                None => output::print("No profile yet; start one with `profile on`\n"),
+            },
+            Some("on") => {
+                if crate::profile::start() {
+                    output::print("Profiling, see /proc/profile\n");
+                } else {
+                    output::print("profile: the kernel has no symbol table\n");
+                }
+            }
+            Some("off") => crate::profile::stop(),
+            Some(_) => output::print("Usage: profile [on | off]\n"),
+        },
         "ospabshell" => {
             let path = "/bin/ospabshell".to_string();
-            if exec_path(&path).is_err() {
-                framebuffer::print("Failed to start ospabshell\n");
+            // It replaces this shell and reads lines through its TTY
+            let pid = crate::task::scheduler::SCHEDULER.lock().current_pid();
+            crate::services::terminal::set_foreground(Some(pid));
+            if exec_path(&path, &[path.as_str()]).is_err() {
+                crate::services::terminal::set_foreground(None);
+                output::print("Failed to start ospabshell\n");
             }
         }
         "shutdown" => {
-            crate::power::shutdown();
+            crate::power::graceful(crate::power::PowerAction::Shutdown);
         }
         "reboot" => {
-            crate::power::reboot();
+            crate::power::graceful(crate::power::PowerAction::Reboot);
         }
         _ => {
+            // Everything else lives in /bin (ls, cat, echo, wc, ...)
             let path = resolve_command_path(parts[0]);
-            if exec_path(&path).is_err() {
-                framebuffer::print("Unknown command: ");
-                framebuffer::print(parts[0]);
-                framebuffer::print("\n");
+            match run_path(&path, &parts) {
+                Ok(status) => {
+                    // Scripts test statuses themselves, and a stop was reported
+                    if status != 0 && status != jobs::STOPPED_STATUS && !script::in_script() {
+                        output::print(&format!("{}: exit status {}\n", parts[0], status));
+                    }
+                    set_status(status);
+                }
+                Err(_) => {
+                    output::print("Unknown command: ");
+                    output::print(parts[0]);
+                    output::print("\n");
+                    set_status(127);
+                }
             }
         }
     }
 }
 
+/// Duration for `boottime`: 12.345ms or 1.234s
+fn format_duration_us(us: u64) -> alloc::string::String {
+    if us >= 1_000_000 {
+        format!("{}.{:03}s", us / 1_000_000, (us / 1000) % 1000)
+    } else {
+        format!("{}.{:03}ms", us / 1000, us % 1000)
+    }
+}
+
+/// Size for `free` and `df`: kibibytes, or with `human` the largest fitting unit
+/// (1.5Gi, 512Mi, 12Ki, 100B)
+fn format_size(bytes: u64, human: bool) -> alloc::string::String {
+    if !human {
+        return format!("{}", bytes / 1024);
+    }
+    const UNITS: [&str; 4] = ["Ki", "Mi", "Gi", "Ti"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut unit = 0;
+    let mut scaled = bytes * 10 / 1024;
+    while scaled >= 10240 && unit + 1 < UNITS.len() {
+        scaled /= 1024;
+        unit += 1;
+    }
+    if scaled >= 100 {
+        format!("{}{}", scaled / 10, UNITS[unit])
+    } else {
+        format!("{}.{}{}", scaled / 10, scaled % 10, UNITS[unit])
+    }
+}
+
+/// "10" or "10.9" in tenths
+fn parse_tenths(text: &str) -> Option<u32> {
+    match text.split_once('.') {
+        Some((whole, tenth)) if tenth.len() == 1 => Some(whole.parse::<u32>().ok()? * 10 + tenth.parse::<u32>().ok()?),
+        Some(_) => None,
+        None => Some(text.parse::<u32>().ok()? * 10),
+    }
+}
+
 // Helper to print numbers
 fn print_num(n: u64) {
     if n == 0 {
-        framebuffer::print_char('0');
+        output::print_char('0');
         return;
     }
     
@@ -896,6 +2303,6 @@ fn print_num(n: u64) {
     }
     
     for j in (0..i).rev() {
-        framebuffer::print_char(buf[j] as char);
+        output::print_char(buf[j] as char);
     }
 }
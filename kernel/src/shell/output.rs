@@ -0,0 +1,162 @@
+//! Where shell command output goes
+//!
+//! Commands print through `print`/`print_char`, which write to the sink on
+//! top of a stack: the console when the stack is empty, otherwise a buffer
+//! (`capture`, used by `$(...)`) or a file (`redirect`, used by `>` and
+//! `>>`). Output that other subsystems draw on the framebuffer themselves
+//! is not captured.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::drivers::framebuffer;
+use crate::fs::vfs::FsError;
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+use crate::syscall::abi;
+
+/// Destination for command output
+pub trait OutputSink: Send {
+    fn write_str(&mut self, s: &str);
+
+    /// Called when the sink is removed; returns the text it kept, if any
+    fn finish(self: Box<Self>) -> Result<String, String>;
+}
+
+/// The framebuffer console
+pub struct Console;
+
+impl OutputSink for Console {
+    fn write_str(&mut self, s: &str) {
+        framebuffer::print(s);
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, String> {
+        Ok(String::new())
+    }
+}
+
+/// Keeps everything written, for `capture`
+#[derive(Default)]
+pub struct Buffer {
+    text: String,
+}
+
+impl OutputSink for Buffer {
+    fn write_str(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, String> {
+        Ok(self.text)
+    }
+}
+
+/// Collects output and writes it to a VFS file when finished
+pub struct FileSink {
+    path: String,
+    append: bool,
+    data: Vec<u8>,
+}
+
+impl FileSink {
+    pub fn new(path: &str, append: bool) -> Self {
+        FileSink { path: path.to_string(), append, data: Vec::new() }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write_str(&mut self, s: &str) {
+        self.data.extend_from_slice(s.as_bytes());
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, String> {
+        if self.append {
+            // One write at the end of the file as it is now, so output
+            // other writers appended meanwhile is kept
+            let flags = abi::O_WRONLY | abi::O_CREAT | abi::O_APPEND;
+            let mut file = vfs::open(&self.path, flags).map_err(|e| format!("{}: {}", self.path, e))?;
+            let mut done = 0;
+            while done < self.data.len() {
+                match file.write(&self.data[done..]) {
+                    Ok(0) => return Err(format!("{}: {}", self.path, FsError::NoSpace)),
+                    Ok(n) => done += n,
+                    Err(e) => return Err(format!("{}: {}", self.path, e)),
+                }
+            }
+            return Ok(String::new());
+        }
+        match vfs::process_request(FSRequest::WriteFile { path: self.path.clone(), data: self.data }) {
+            FSResponse::Success => Ok(String::new()),
+            FSResponse::Error(e, _) => Err(format!("{}: {}", self.path, e)),
+            _ => Err(format!("{}: unexpected response", self.path)),
+        }
+    }
+}
+
+static SINKS: Mutex<Vec<Box<dyn OutputSink>>> = Mutex::new(Vec::new());
+
+/// Write `s` to the current sink
+pub fn print(s: &str) {
+    let mut sinks = SINKS.lock();
+    match sinks.last_mut() {
+        Some(sink) => sink.write_str(s),
+        None => framebuffer::print(s),
+    }
+}
+
+pub fn print_char(c: char) {
+    let mut buf = [0u8; 4];
+    print(c.encode_utf8(&mut buf));
+}
+
+/// Whether output currently goes to the console
+pub fn is_console() -> bool {
+    SINKS.lock().is_empty()
+}
+
+/// Run `f` with its output going to `sink`
+///
+/// Returns what the sink kept (the text for a `Buffer`), or the error from
+/// finishing it (a file that could not be written).
+pub fn redirect(sink: Box<dyn OutputSink>, f: impl FnOnce()) -> Result<String, String> {
+    SINKS.lock().push(sink);
+    f();
+    let sink = SINKS.lock().pop().expect("output sink stack underflow");
+    sink.finish()
+}
+
+/// Run `f` and return its output instead of printing it
+pub fn capture(f: impl FnOnce()) -> String {
+    redirect(Box::new(Buffer::default()), f).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn capture_returns_what_was_printed() {
+        let text = capture(|| {
+            print("hello ");
+            print_char('x');
+            assert!(!is_console());
+        });
+        assert_eq!(text, "hello x");
+        assert!(is_console());
+    }
+
+    #[test_case]
+    fn nested_captures_keep_their_own_output() {
+        let mut inner = String::new();
+        let outer = capture(|| {
+            print("a");
+            inner = capture(|| print("b"));
+            print("c");
+        });
+        assert_eq!(outer, "ac");
+        assert_eq!(inner, "b");
+    }
+}
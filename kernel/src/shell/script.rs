@@ -0,0 +1,414 @@
+//! Script interpreter
+//!
+//! A script is shell commands, one per line or separated by `;`, plus the
+//! control structures of sh:
+//!
+//! ```text
+//! if test -f /etc/motd; then
+//!     cat /etc/motd
+//! elif [ -d /etc ]; then echo no motd
+//! else
+//!     echo no /etc
+//! fi
+//! for file in *.txt "my notes"; do cat $file; done
+//! while [ -f /tmp/lock ]; do service lockd status; done
+//! ```
+//!
+//! A condition is a list of commands and holds when the last one exits
+//! with status 0; `until` loops while it does not. `a && b` runs b only if
+//! a succeeded, `a || b` only if it failed. `name=value` sets a variable,
+//! `$name` and `${name}` read it (falling back to the session
+//! environment), `$?` is the last exit status, `$0`, `$1`.. and `$#` are a
+//! script's path, arguments and argument count. `exit [n]` ends a script.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::{last_status, output, set_status};
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+
+/// Words that open or continue a control structure
+const KEYWORDS: &[&str] = &["if", "then", "elif", "else", "fi", "for", "while", "until", "do", "done"];
+
+/// Variables set by `name=value`, `for` and script arguments
+static VARIABLES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Scripts running, nested ones included
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// `exit` was run: unwind to the end of the current script
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+enum Stmt {
+    /// One command or an `&&` / `||` list of them
+    Command(String),
+    If { branches: Vec<(Vec<Stmt>, Vec<Stmt>)>, otherwise: Vec<Stmt> },
+    /// `words` are split and expanded each time the loop starts
+    For { var: String, words: String, body: Vec<Stmt> },
+    While { cond: Vec<Stmt>, body: Vec<Stmt>, until: bool },
+}
+
+/// Value of variable `name`; `?` is the last exit status
+pub fn variable(name: &str) -> Option<String> {
+    if name == "?" {
+        return Some(last_status().to_string());
+    }
+    if let Some(value) = VARIABLES.lock().get(name) {
+        return Some(value.clone());
+    }
+    crate::auth::getenv(name)
+}
+
+pub fn set_variable(name: &str, value: &str) {
+    VARIABLES.lock().insert(name.to_string(), value.to_string());
+}
+
+/// A name `name=value` may set
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A script is running, so `exit` has something to end
+pub fn in_script() -> bool {
+    DEPTH.load(Ordering::Relaxed) > 0
+}
+
+/// End the running script once the current command returns
+pub fn request_exit() {
+    EXITING.store(true, Ordering::Relaxed);
+}
+
+fn is_dir(path: &str) -> bool {
+    matches!(vfs::process_request(FSRequest::ListDir { path: path.to_string() }), FSResponse::DirListing(_))
+}
+
+fn is_file(path: &str) -> bool {
+    matches!(vfs::process_request(FSRequest::ReadFile { path: path.to_string() }), FSResponse::FileData(_))
+}
+
+fn integer(arg: &str) -> Result<i64, String> {
+    arg.parse::<i64>().map_err(|_| format!("{}: integer expected", arg))
+}
+
+/// Evaluate the expression of `test` / `[`: `-e -f -d path`, `-z -n string`,
+/// `a = b`, `a != b`, `a -eq -ne -lt -le -gt -ge b`, and `!` before any
+pub fn test(args: &[&str]) -> Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        ["!", rest @ ..] => test(rest).map(|result| !result),
+        [string] => Ok(!string.is_empty()),
+        ["-e", path] => Ok(is_dir(path) || is_file(path)),
+        ["-f", path] => Ok(is_file(path)),
+        ["-d", path] => Ok(is_dir(path)),
+        ["-z", string] => Ok(string.is_empty()),
+        ["-n", string] => Ok(!string.is_empty()),
+        [a, "=" | "==", b] => Ok(a == b),
+        [a, "!=", b] => Ok(a != b),
+        [a, op @ ("-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"), b] => {
+            let (a, b) = (integer(a)?, integer(b)?);
+            Ok(match *op {
+                "-eq" => a == b,
+                "-ne" => a != b,
+                "-lt" => a < b,
+                "-le" => a <= b,
+                "-gt" => a > b,
+                _ => a >= b,
+            })
+        }
+        _ => Err(format!("{}: unknown expression", args.join(" "))),
+    }
+}
+
+/// Split `text` at each of `separators` that is outside quotes and
+/// `$(...)`; returns the pieces and the separator that ended each (None
+/// for the last)
+fn split_top_level<'a>(text: &'a str, separators: &[&'static str]) -> Vec<(&'a str, Option<&'static str>)> {
+    let mut pieces = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    let mut i = 0;
+    let bytes = text.as_bytes();
+    while i < bytes.len() {
+        match (bytes[i], quote) {
+            (b'\\', Some(b'"') | None) => i += 1,
+            (b'\'' | b'"', None) => quote = Some(bytes[i]),
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => {}
+            (b'(', None) => depth += 1,
+            (b')', None) => depth = depth.saturating_sub(1),
+            _ if depth == 0 && text.is_char_boundary(i) => {
+                if let Some(&separator) = separators.iter().find(|sep| text[i..].starts_with(**sep)) {
+                    pieces.push((&text[start..i], Some(separator)));
+                    i += separator.len();
+                    start = i;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    pieces.push((&text[start..], None));
+    pieces
+}
+
+/// `line` needs the interpreter rather than a single command
+pub fn is_compound(line: &str) -> bool {
+    let first = line.split_whitespace().next().unwrap_or("");
+    KEYWORDS.contains(&first) || split_top_level(line, &[";", "&&", "||"]).len() > 1
+}
+
+/// Commands of `text`: its lines split at `;`, without comments and blanks
+fn units(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| split_top_level(line, &[";"]))
+        .map(|(unit, _)| unit.trim().to_string())
+        .filter(|unit| !unit.is_empty())
+        .collect()
+}
+
+struct Parser {
+    units: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    /// The next unit, with a leading keyword split off as a unit of its
+    /// own: `then echo hi` is `then` followed by `echo hi`. `for` keeps its
+    /// words
+    fn next(&mut self) -> Option<String> {
+        let unit = self.units.get(self.pos)?.clone();
+        let (first, rest) = unit.split_once(char::is_whitespace).unwrap_or((&unit, ""));
+        if first != "for" && KEYWORDS.contains(&first) && !rest.trim().is_empty() {
+            self.units[self.pos] = rest.trim().to_string();
+            return Some(first.to_string());
+        }
+        self.pos += 1;
+        Some(unit)
+    }
+
+    /// Statements up to one of `ends`, and the end found; `ends` empty
+    /// means up to the end of the script
+    fn block(&mut self, ends: &[&str]) -> Result<(Vec<Stmt>, String), String> {
+        let mut stmts = Vec::new();
+        loop {
+            let Some(unit) = self.next() else {
+                if ends.is_empty() {
+                    return Ok((stmts, String::new()));
+                }
+                return Err(format!("expected '{}'", ends.join("' or '")));
+            };
+            if ends.contains(&unit.as_str()) {
+                return Ok((stmts, unit));
+            }
+            stmts.push(self.statement(unit)?);
+        }
+    }
+
+    fn statement(&mut self, unit: String) -> Result<Stmt, String> {
+        let first = unit.split_whitespace().next().unwrap_or("");
+        match first {
+            "if" => {
+                let mut branches = Vec::new();
+                let mut otherwise = Vec::new();
+                loop {
+                    let (cond, _) = self.block(&["then"])?;
+                    let (body, end) = self.block(&["elif", "else", "fi"])?;
+                    branches.push((cond, body));
+                    match end.as_str() {
+                        "elif" => continue,
+                        "else" => otherwise = self.block(&["fi"])?.0,
+                        _ => {}
+                    }
+                    break;
+                }
+                Ok(Stmt::If { branches, otherwise })
+            }
+            "while" | "until" => {
+                let (cond, _) = self.block(&["do"])?;
+                let (body, _) = self.block(&["done"])?;
+                Ok(Stmt::While { cond, body, until: first == "until" })
+            }
+            "for" => {
+                let usage = || "expected 'for <name> in <words>'".to_string();
+                let rest = unit["for".len()..].trim_start();
+                let (var, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if !is_name(var) {
+                    return Err(usage());
+                }
+                let words = match rest.trim_start().split_once(char::is_whitespace) {
+                    Some(("in", words)) => words.to_string(),
+                    None if rest.trim() == "in" || rest.trim().is_empty() => String::new(),
+                    _ => return Err(usage()),
+                };
+                let var = var.to_string();
+                let (before, _) = self.block(&["do"])?;
+                if !before.is_empty() {
+                    return Err("expected 'do'".to_string());
+                }
+                let (body, _) = self.block(&["done"])?;
+                Ok(Stmt::For { var, words, body })
+            }
+            _ if KEYWORDS.contains(&first) => Err(format!("unexpected '{}'", first)),
+            _ => Ok(Stmt::Command(unit)),
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Vec<Stmt>, String> {
+    let mut parser = Parser { units: units(text), pos: 0 };
+    Ok(parser.block(&[])?.0)
+}
+
+/// Run an `&&` / `||` list: each command after the first runs only if the
+/// status so far calls for it
+fn run_list(line: &str) {
+    let mut op = None;
+    for (command, next) in split_top_level(line, &["&&", "||"]) {
+        let run = match op {
+            Some("&&") => last_status() == 0,
+            Some("||") => last_status() != 0,
+            _ => true,
+        };
+        if run {
+            super::run_simple(command.trim());
+            if EXITING.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        op = next;
+    }
+}
+
+/// Run `stmts`; false once `exit` has been called
+fn exec(stmts: &[Stmt]) -> bool {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Command(line) => run_list(line),
+            Stmt::If { branches, otherwise } => {
+                let mut taken = false;
+                for (cond, body) in branches {
+                    if !exec(cond) {
+                        return false;
+                    }
+                    if last_status() == 0 {
+                        taken = true;
+                        if !exec(body) {
+                            return false;
+                        }
+                        break;
+                    }
+                }
+                if !taken {
+                    set_status(0);
+                    if !exec(otherwise) {
+                        return false;
+                    }
+                }
+            }
+            Stmt::For { var, words, body } => {
+                let words = match super::words::split(words) {
+                    Ok(words) => super::words::glob(words),
+                    Err(e) => {
+                        output::print(&format!("sh: {}\n", e));
+                        set_status(2);
+                        continue;
+                    }
+                };
+                set_status(0);
+                for word in words {
+                    set_variable(var, &word);
+                    if !exec(body) {
+                        return false;
+                    }
+                }
+            }
+            Stmt::While { cond, body, until } => loop {
+                if !exec(cond) {
+                    return false;
+                }
+                if (last_status() == 0) == *until {
+                    set_status(0);
+                    break;
+                }
+                if !exec(body) {
+                    return false;
+                }
+            },
+        }
+        if EXITING.load(Ordering::Relaxed) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Run a command line typed at the prompt that uses `;`, `&&`, `||` or a
+/// control structure
+pub fn run_line(line: &str) {
+    match parse(line) {
+        Ok(stmts) => {
+            exec(&stmts);
+        }
+        Err(e) => {
+            output::print(&format!("sh: syntax error: {}\n", e));
+            set_status(2);
+        }
+    }
+}
+
+/// Remove `$0`, `$1`.. and `$#` from `variables`, returning them
+fn take_positional(variables: &mut BTreeMap<String, String>) -> Vec<(String, String)> {
+    let names: Vec<String> = variables
+        .keys()
+        .filter(|name| name.as_str() == "#" || name.bytes().all(|b| b.is_ascii_digit()))
+        .cloned()
+        .collect();
+    names.into_iter().filter_map(|name| variables.remove_entry(&name)).collect()
+}
+
+/// Run script `text` with `argv` as `$0`, `$1`..; returns its exit status,
+/// also left in `$?`
+pub fn run(text: &str, argv: &[&str]) -> i32 {
+    let stmts = match parse(text) {
+        Ok(stmts) => stmts,
+        Err(e) => {
+            output::print(&format!("{}: syntax error: {}\n", argv.first().unwrap_or(&"sh"), e));
+            set_status(2);
+            return 2;
+        }
+    };
+
+    // Arguments belong to this script; a script it runs gets its own
+    let outer = {
+        let mut variables = VARIABLES.lock();
+        let outer = take_positional(&mut variables);
+        for (i, arg) in argv.iter().enumerate() {
+            variables.insert(i.to_string(), arg.to_string());
+        }
+        variables.insert("#".to_string(), argv.len().saturating_sub(1).to_string());
+        outer
+    };
+
+    DEPTH.fetch_add(1, Ordering::Relaxed);
+    set_status(0);
+    exec(&stmts);
+    EXITING.store(false, Ordering::Relaxed);
+    DEPTH.fetch_sub(1, Ordering::Relaxed);
+
+    let mut variables = VARIABLES.lock();
+    take_positional(&mut variables);
+    variables.extend(outer);
+    drop(variables);
+    last_status()
+}
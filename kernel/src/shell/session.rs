@@ -0,0 +1,61 @@
+//! Per-user shell state
+//!
+//! At login the shell reads the user's command history from ~/.history
+//! and runs ~/.ospabrc, a script for aliases and variables:
+//!
+//! ```text
+//! alias ll='ls -l'
+//! EDITOR=grape
+//! ```
+//!
+//! The history file is rewritten after every command typed at the prompt.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{alias, script};
+use crate::drivers::keyboard;
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+
+pub const HISTORY_FILE: &str = ".history";
+pub const RC_FILE: &str = ".ospabrc";
+
+/// `name` in the home directory of the session's user
+fn home_file(name: &str) -> Option<String> {
+    let user = crate::auth::current_user()?;
+    Some(format!("{}/{}", user.home_dir.trim_end_matches('/'), name))
+}
+
+fn read(path: &str) -> Option<String> {
+    match vfs::process_request(FSRequest::ReadFile { path: path.to_string() }) {
+        FSResponse::FileData(data) => Some(String::from_utf8_lossy(&data).into_owned()),
+        _ => None,
+    }
+}
+
+/// Load the history and aliases of the user who just logged in
+pub fn start() {
+    let history = home_file(HISTORY_FILE).and_then(|path| read(&path)).unwrap_or_default();
+    keyboard::set_history(history.lines().map(String::from).collect());
+
+    // Aliases belong to the user whose .ospabrc set them
+    alias::clear();
+    if let Some(path) = home_file(RC_FILE) {
+        if let Some(text) = read(&path) {
+            script::run(&text, &[&path]);
+        }
+    }
+}
+
+/// Write the history to ~/.history
+pub fn save_history() {
+    let Some(path) = home_file(HISTORY_FILE) else { return };
+    let lines: Vec<String> = keyboard::history();
+    let mut data = lines.join("\n");
+    if !data.is_empty() {
+        data.push('\n');
+    }
+    let _ = vfs::process_request(FSRequest::WriteFile { path, data: data.into_bytes() });
+}
@@ -16,12 +16,7 @@ pub fn shell_task() -> ! {
         }
         
         // Yield to other tasks
-        // crate::syscall::dispatch_syscall(0, 0, 0, 0, 0); // sys_yield - TODO: Enable when ready
-        
-        // Small delay to avoid busy waiting
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        crate::task::scheduler::yield_now();
     }
 }
 
@@ -0,0 +1,253 @@
+//! Splitting a command line into words
+//!
+//! Words end at unquoted blanks. `'...'` keeps everything as typed;
+//! `"..."` keeps blanks but still expands `$name` and `$(...)`, and `\`
+//! inside it escapes only `"`, `\` and `$`. Outside quotes `\` makes the
+//! next character literal. An unquoted expansion is split at blanks into
+//! more words.
+//!
+//! Unquoted `*` (any run of characters) and `?` (any one character) make a
+//! word a pattern, replaced by the names it matches in sorted order. Only
+//! the last path component may hold wildcards: `*.txt` and `/etc/*.conf`
+//! work, `*/x` does not. Names starting with `.` only match a pattern that
+//! starts with one, and a pattern that matches nothing stays as typed.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::str::CharIndices;
+
+use super::{output, script};
+use crate::ipc::message::{FSRequest, FSResponse};
+use crate::services::vfs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Glob {
+    Char(char),
+    /// `*`
+    Any,
+    /// `?`
+    One,
+}
+
+pub struct Word {
+    /// The word without its quotes and escapes
+    pub text: String,
+    /// `text` with the unquoted wildcards marked
+    glob: Vec<Glob>,
+    /// Typed without quotes or escapes, so `>` in it is a redirection
+    pub bare: bool,
+}
+
+impl Word {
+    fn new() -> Self {
+        Self { text: String::new(), glob: Vec::new(), bare: true }
+    }
+
+    fn is_pattern(&self) -> bool {
+        self.glob.iter().any(|glob| !matches!(glob, Glob::Char(_)))
+    }
+}
+
+fn glob_char(glob: &Glob) -> char {
+    match glob {
+        Glob::Char(c) => *c,
+        Glob::Any => '*',
+        Glob::One => '?',
+    }
+}
+
+#[derive(Default)]
+struct Words {
+    done: Vec<Word>,
+    current: Option<Word>,
+}
+
+impl Words {
+    fn push(&mut self, c: char, quoted: bool) {
+        let word = self.current.get_or_insert_with(Word::new);
+        word.text.push(c);
+        word.glob.push(match c {
+            '*' if !quoted => Glob::Any,
+            '?' if !quoted => Glob::One,
+            c => Glob::Char(c),
+        });
+    }
+
+    /// A quote or escape: the word exists even if it stays empty (`""`)
+    fn quote(&mut self) {
+        self.current.get_or_insert_with(Word::new).bare = false;
+    }
+
+    fn end(&mut self) {
+        if let Some(word) = self.current.take() {
+            self.done.push(word);
+        }
+    }
+}
+
+/// Expand the `$` whose following text is `after`: `$(command)`,
+/// `${name}`, `$name`, `$?`, `$#` or `$1`. Returns the value and how many
+/// bytes of `after` it used, or None if the `$` is just a `$`
+fn expansion(after: &str) -> Option<(String, usize)> {
+    if let Some(inner) = after.strip_prefix('(') {
+        let mut depth = 1;
+        for (i, c) in inner.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        // Newlines become spaces and trailing ones go, as in sh
+                        let captured = output::capture(|| super::execute_command(&inner[..i]));
+                        return Some((captured.trim_end_matches('\n').replace('\n', " "), i + 2));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Unbalanced: left as typed
+        return None;
+    }
+    let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+        let end = braced.find('}')?;
+        (&braced[..end], end + 2)
+    } else if after.starts_with(|c: char| c == '?' || c == '#' || c.is_ascii_digit()) {
+        (&after[..1], 1)
+    } else {
+        let end = after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+        (&after[..end], end)
+    };
+    if name.is_empty() {
+        return None;
+    }
+    // Unset variables expand to nothing
+    Some((script::variable(name).unwrap_or_default(), len))
+}
+
+/// Move `chars` past byte `end`, over the text an expansion used
+fn skip_to(chars: &mut Peekable<CharIndices>, end: usize) {
+    while chars.peek().is_some_and(|&(i, _)| i < end) {
+        chars.next();
+    }
+}
+
+/// Split `line` into words, removing quotes and expanding variables and
+/// `$(...)`; wildcards are left for `glob`
+pub fn split(line: &str) -> Result<Vec<Word>, String> {
+    let mut words = Words::default();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.end(),
+            '\'' => {
+                words.quote();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => words.push(c, true),
+                        None => return Err("unterminated '".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                words.quote();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.peek() {
+                            Some(&(_, c @ ('"' | '\\' | '$'))) => {
+                                chars.next();
+                                words.push(c, true);
+                            }
+                            _ => words.push('\\', true),
+                        },
+                        Some((j, '$')) => match expansion(&line[j + 1..]) {
+                            Some((value, len)) => {
+                                skip_to(&mut chars, j + 1 + len);
+                                value.chars().for_each(|c| words.push(c, true));
+                            }
+                            None => words.push('$', true),
+                        },
+                        Some((_, c)) => words.push(c, true),
+                        None => return Err("unterminated \"".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some((_, c)) = chars.next() {
+                    words.quote();
+                    words.push(c, true);
+                }
+            }
+            '$' => match expansion(&line[i + 1..]) {
+                Some((value, len)) => {
+                    skip_to(&mut chars, i + 1 + len);
+                    for c in value.chars() {
+                        if c.is_whitespace() {
+                            words.end();
+                        } else {
+                            words.push(c, false);
+                        }
+                    }
+                }
+                None => words.push('$', false),
+            },
+            c => words.push(c, false),
+        }
+    }
+    words.end();
+    Ok(words.done)
+}
+
+fn matches_name(pattern: &[Glob], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((Glob::Any, rest)) => (0..=name.len()).any(|skip| matches_name(rest, &name[skip..])),
+        Some((Glob::One, rest)) => !name.is_empty() && matches_name(rest, &name[1..]),
+        Some((Glob::Char(c), rest)) => name.first() == Some(c) && matches_name(rest, &name[1..]),
+    }
+}
+
+/// Paths matching the pattern `word`, sorted
+fn expand_pattern(word: &Word) -> Vec<String> {
+    let slash = word.glob.iter().rposition(|glob| *glob == Glob::Char('/'));
+    let (dir, name) = match slash {
+        Some(i) => word.glob.split_at(i + 1),
+        None => (&[][..], &word.glob[..]),
+    };
+    let prefix: String = dir.iter().map(glob_char).collect();
+    if dir.iter().any(|glob| !matches!(glob, Glob::Char(_))) {
+        return Vec::new();
+    }
+
+    let path = if prefix.is_empty() { ".".to_string() } else { prefix.clone() };
+    let FSResponse::DirListing(names) = vfs::process_request(FSRequest::ListDir { path }) else {
+        return Vec::new();
+    };
+    let dotted = name.first() == Some(&Glob::Char('.'));
+    names
+        .into_iter()
+        .filter(|entry| dotted || !entry.starts_with('.'))
+        .filter(|entry| matches_name(name, &entry.chars().collect::<Vec<_>>()))
+        .map(|entry| format!("{}{}", prefix, entry))
+        .collect()
+}
+
+/// The text of `words`, with each pattern replaced by what it matches
+pub fn glob(words: Vec<Word>) -> Vec<String> {
+    let mut args = Vec::new();
+    for word in words {
+        if word.is_pattern() {
+            let paths = expand_pattern(&word);
+            if !paths.is_empty() {
+                args.extend(paths);
+                continue;
+            }
+        }
+        args.push(word.text);
+    }
+    args
+}
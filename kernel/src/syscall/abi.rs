@@ -38,11 +38,27 @@ pub const SYS_EXIT: u64 = 4;
 pub const SYS_GETPID: u64 = 5;
 
 /// sys_open(path: *const u8, flags: u64) -> fd
-/// Open a file from VFS
+/// Open a file from VFS; flags are an access mode (O_RDONLY, O_WRONLY,
+/// O_RDWR) and any of O_CREAT, O_TRUNC and O_APPEND
 pub const SYS_OPEN: u64 = 7;
 
-/// sys_exec(path: *const u8) -> status
-/// Execute a script or binary
+/// Flags for sys_open
+pub const O_RDONLY: u64 = 0;
+pub const O_WRONLY: u64 = 1;
+pub const O_RDWR: u64 = 2;
+pub const O_ACCMODE: u64 = 3;
+/// Create the file if it does not exist
+pub const O_CREAT: u64 = 0o100;
+/// Empty the file when it is opened for writing
+pub const O_TRUNC: u64 = 0o1000;
+/// Write at the end of the file, wherever the offset is
+pub const O_APPEND: u64 = 0o2000;
+
+/// sys_exec(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> status
+/// Replace the calling task's image with an ELF binary. argv and envp are
+/// NULL-terminated arrays of C strings placed on the new stack; a null argv
+/// passes just the path. Only returns on failure, in which case the old
+/// image keeps running
 pub const SYS_EXEC: u64 = 8;
 
 /// sys_draw_char(x: u64, y: u64, ch: u64, fg: u64, bg: u64) -> status
@@ -67,6 +83,309 @@ pub const SYS_SHUTDOWN: u64 = 14;
 /// sys_reboot() -> !
 pub const SYS_REBOOT: u64 = 15;
 
+/// sys_setcred(name: *const u8, password: *const u8) -> uid
+/// Switch session credentials; password may be null when called as root
+pub const SYS_SETCRED: u64 = 16;
+
+/// sys_socket(domain: u64, type: u64, protocol: u64) -> fd
+/// Create a socket; the descriptor lives in the task's fd table
+pub const SYS_SOCKET: u64 = 17;
+
+/// sys_bind(fd: u64, addr: u32, port: u16) -> status
+/// addr is the IPv4 address as a big-endian u32 (a.b.c.d = 0xAABBCCDD)
+pub const SYS_BIND: u64 = 18;
+
+/// sys_connect(fd: u64, addr: u32, port: u16) -> status
+pub const SYS_CONNECT: u64 = 19;
+
+/// sys_send(fd: u64, buf: *const u8, len: usize) -> bytes_sent
+pub const SYS_SEND: u64 = 20;
+
+/// sys_recv(fd: u64, buf: *mut u8, len: usize) -> bytes_received
+/// Blocks until data arrives; 0 means the peer closed the connection
+pub const SYS_RECV: u64 = 21;
+
+/// sys_close(fd: u64) -> status
+/// Close any descriptor, including sockets
+pub const SYS_CLOSE: u64 = 22;
+
+/// sys_fork() -> pid
+/// Duplicate the calling task (address space and descriptors). Returns the
+/// child's pid in the parent and 0 in the child
+pub const SYS_FORK: u64 = 23;
+
+/// sys_waitpid(pid: u64, status: *mut i32, flags: u64) -> pid
+/// Wait for a child to exit (pid 0 = any child) and reap it. With WNOHANG
+/// returns 0 instead of blocking while the child is still running
+pub const SYS_WAITPID: u64 = 24;
+
+/// Flags for sys_waitpid
+pub const WNOHANG: u64 = 1;
+
+/// sys_sysinfo(info: *mut SysInfo) -> status
+/// Fill in memory and uptime statistics
+pub const SYS_SYSINFO: u64 = 25;
+
+/// Filled in by sys_sysinfo; all sizes are in bytes
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SysInfo {
+    pub uptime_ms: u64,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    pub mem_free: u64,
+    pub heap_total: u64,
+    pub heap_used: u64,
+    pub heap_peak: u64,
+    pub heap_free: u64,
+    /// Percent of the consumed heap that is free but not reusable
+    pub heap_fragmentation: u64,
+    pub tasks: u64,
+}
+
+/// sys_getrlimit(resource: u64, limit: *mut RLimit) -> status
+/// Read one of the calling task's resource limits
+pub const SYS_GETRLIMIT: u64 = 26;
+
+/// sys_setrlimit(resource: u64, limit: *const RLimit) -> status
+/// Change a resource limit; cur may not exceed max, and only root may
+/// raise max. Limits are inherited by forked and spawned children
+pub const SYS_SETRLIMIT: u64 = 27;
+
+/// sys_shm_create(size: usize, flags: u64) -> id
+/// Create a zero-filled shared memory region of up to SHM_MAX_SIZE bytes.
+/// It lives while its creator does or while any task has it mapped
+pub const SYS_SHM_CREATE: u64 = 28;
+
+/// sys_shm_map(id: u64, flags: u64) -> addr
+/// Map a region into the calling task; it is at the same address in every
+/// task. Only the creator may map it SHM_WRITE unless it was created with
+/// SHM_OTHERS_WRITE. Forked children inherit mappings, exec drops them
+pub const SYS_SHM_MAP: u64 = 29;
+
+/// sys_shm_unmap(id: u64) -> status
+pub const SYS_SHM_UNMAP: u64 = 30;
+
+pub use crate::ipc::shm::{SHM_MAX_SIZE, SHM_OTHERS_WRITE, SHM_WRITE};
+
+/// sys_kill(pid: u64, sig: u64) -> status
+/// Send a signal to a task; sig 0 only checks that the task exists
+pub const SYS_KILL: u64 = 31;
+
+/// sys_sigaction(sig: u64, handler: u64, restorer: u64) -> status
+/// Set what a signal does: SIG_DFL (terminate with exit status 128 + sig),
+/// SIG_IGN, or the address of a handler called with the signal number.
+/// The handler returns into `restorer`, which must call sys_sigreturn.
+/// SIGKILL cannot be caught or ignored. Handlers are reset by exec and
+/// inherited by fork
+pub const SYS_SIGACTION: u64 = 32;
+
+/// sys_sigreturn() -> !
+/// Resume the code a signal handler interrupted; only valid from the
+/// restorer, with the stack as the handler left it
+pub const SYS_SIGRETURN: u64 = 33;
+
+/// Handlers for sys_sigaction
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/// sys_futex_wait(addr: *const u32, expected: u32) -> status
+/// Block while the 32-bit word at addr (4-byte aligned, writable) holds
+/// `expected`. EAGAIN if it already differs, EINTR if a signal arrives;
+/// wakeups may be spurious. Waiters are matched by physical address, so
+/// futexes in shared memory work between tasks
+pub const SYS_FUTEX_WAIT: u64 = 34;
+
+/// sys_futex_wake(addr: *const u32, count: u32) -> woken
+/// Wake up to `count` tasks waiting on the word at addr, oldest first
+pub const SYS_FUTEX_WAKE: u64 = 35;
+
+/// sys_nanosleep(ns: u64) -> status
+/// Block for at least `ns` nanoseconds, rounded up to timer ticks (10 ms).
+/// EINTR if a signal arrives first
+pub const SYS_NANOSLEEP: u64 = 36;
+
+/// sys_alarm(seconds: u64) -> remaining
+/// Raise SIGALRM in the calling task after `seconds`, replacing any
+/// pending alarm (0 cancels it). Returns the seconds the old one had left
+pub const SYS_ALARM: u64 = 37;
+
+/// sys_beep(freq: u64, ms: u64) -> status
+/// Sound the PC speaker at `freq` Hz for `ms` milliseconds, after any notes
+/// already queued; returns at once. freq 0 silences it and drops the
+/// queue. EINVAL outside 20..=20000 Hz
+pub const SYS_BEEP: u64 = 38;
+
+/// sys_audio_write(buf: *const u8, len: usize) -> bytes queued
+/// Queue notes for the PC speaker, as written to /dev/audio: 4-byte
+/// records of u16 frequency (0 = rest) and u16 milliseconds, little-endian
+pub const SYS_AUDIO_WRITE: u64 = 39;
+
+/// sys_stat(path: *const u8, stat: *mut Stat) -> status
+/// Fill in the type, size, permissions and times of the node at path
+pub const SYS_STAT: u64 = 40;
+
+/// sys_fstat(fd: u64, stat: *mut Stat) -> status
+/// Same for an open descriptor, as it was when opened; EINVAL for sockets
+pub const SYS_FSTAT: u64 = 41;
+
+/// sys_lseek(fd: u64, offset: i64, whence: u64) -> offset
+/// Move the read/write position of a file, or of /dev/framebuffer, to
+/// `offset` from SEEK_SET (the start), SEEK_CUR or SEEK_END. Seeking past
+/// the end is allowed; a write there fills the gap with zeros. ESPIPE for
+/// descriptors without a position
+pub const SYS_LSEEK: u64 = 42;
+
+/// Whence for sys_lseek
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+/// sys_unlink(path: *const u8) -> status
+/// Remove a file or device node; EISDIR for a directory
+pub const SYS_UNLINK: u64 = 43;
+
+/// sys_rmdir(path: *const u8, flags: u64) -> status
+/// Remove an empty directory, or with RMDIR_RECURSIVE a directory and
+/// everything in it. ENOTEMPTY if it has entries, EBUSY for / and for the
+/// current directory or any directory above it
+pub const SYS_RMDIR: u64 = 44;
+
+/// Flags for sys_rmdir
+pub const RMDIR_RECURSIVE: u64 = 1;
+
+/// sys_ioctl(fd: u64, request: u64, arg: u64) -> result
+/// Device control. The TTY takes TTY_GETMODE, which returns its mode,
+/// TTY_SETMODE with the mode as `arg`, and TTY_GETSIZE, which returns the
+/// screen size as rows << 32 | columns. ENOTTY for other descriptors
+pub const SYS_IOCTL: u64 = 45;
+
+/// Requests for sys_ioctl
+pub const TTY_GETMODE: u64 = 1;
+pub const TTY_SETMODE: u64 = 2;
+pub const TTY_GETSIZE: u64 = 3;
+
+/// TTY modes: canonical hands out whole lines, edited and echoed as they
+/// are typed (arrows, history, Tab); raw hands out keys as typed, unechoed,
+/// arrows and the other cursor keys as escape sequences ("\x1b[A")
+pub const TTY_CANONICAL: u64 = 0;
+pub const TTY_RAW: u64 = 1;
+
+/// Filled in by sys_stat and sys_fstat. Times are milliseconds since boot,
+/// 0 for files that have none (initrd contents, /proc, /host)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    /// File type (S_IF*) and permission bits
+    pub mode: u64,
+    pub size: u64,
+    pub created_ms: u64,
+    pub modified_ms: u64,
+}
+
+/// File type bits of Stat::mode
+pub const S_IFMT: u64 = 0o170000;
+pub const S_IFCHR: u64 = 0o020000;
+pub const S_IFDIR: u64 = 0o040000;
+pub const S_IFREG: u64 = 0o100000;
+pub const S_IFLNK: u64 = 0o120000;
+
+/// Resources for sys_getrlimit/sys_setrlimit
+/// CPU time in seconds; a task that uses more is terminated with exit
+/// status 128 + SIGXCPU
+pub const RLIMIT_CPU: u64 = 0;
+
+/// No limit
+pub const RLIM_INFINITY: u64 = !0;
+
+// Signal numbers; a task killed by a signal exits with status 128 + sig
+
+/// Interrupt from the terminal (Ctrl+C)
+pub const SIGINT: i32 = 2;
+/// Invalid opcode in user mode
+pub const SIGILL: i32 = 4;
+/// Misaligned access with alignment checking on
+pub const SIGBUS: i32 = 7;
+/// Divide error or floating-point exception in user mode
+pub const SIGFPE: i32 = 8;
+/// Also sent by SysRq kill-all; cannot be caught
+pub const SIGKILL: i32 = 9;
+/// Bad memory access or protection fault, including a corrupt signal frame
+pub const SIGSEGV: i32 = 11;
+/// Timer set with sys_alarm expired
+pub const SIGALRM: i32 = 14;
+/// Polite request to terminate
+pub const SIGTERM: i32 = 15;
+/// Resume a stopped task; ignored unless it has a handler
+pub const SIGCONT: i32 = 18;
+/// Stop until SIGCONT; cannot be caught
+pub const SIGSTOP: i32 = 19;
+/// Stop from the terminal (Ctrl+Z)
+pub const SIGTSTP: i32 = 20;
+/// CPU time limit exceeded
+pub const SIGXCPU: i32 = 24;
+
+/// Soft (enforced) and hard (ceiling for cur) limit
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    pub cur: u64,
+    pub max: u64,
+}
+
+impl RLimit {
+    pub const UNLIMITED: RLimit = RLimit { cur: RLIM_INFINITY, max: RLIM_INFINITY };
+}
+
+/// Error numbers. sys_open, sys_read, sys_write, sys_lseek, sys_ioctl, sys_chdir,
+/// sys_getcwd, sys_listdir, fork, waitpid, sysinfo, the rlimit, socket,
+/// stat, remove, shm, signal, futex, sleep and credential syscalls return the
+/// negated errno on failure (values above `!0 - 4096`); the other
+/// failures still return !0.
+pub const EPERM: u64 = 1;
+pub const ENOENT: u64 = 2;
+pub const ESRCH: u64 = 3;
+pub const EINTR: u64 = 4;
+pub const EIO: u64 = 5;
+pub const EBADF: u64 = 9;
+pub const ECHILD: u64 = 10;
+pub const EAGAIN: u64 = 11;
+pub const ENOMEM: u64 = 12;
+pub const EACCES: u64 = 13;
+/// A pointer argument reaches outside user space
+pub const EFAULT: u64 = 14;
+pub const EBUSY: u64 = 16;
+pub const EEXIST: u64 = 17;
+pub const ENOTDIR: u64 = 20;
+pub const EISDIR: u64 = 21;
+pub const EINVAL: u64 = 22;
+pub const ENODEV: u64 = 19;
+pub const ENOTTY: u64 = 25;
+pub const ENOSPC: u64 = 28;
+pub const ESPIPE: u64 = 29;
+pub const ENOSYS: u64 = 38;
+pub const ENOTEMPTY: u64 = 39;
+pub const ELOOP: u64 = 40;
+pub const EROFS: u64 = 30;
+pub const ETIMEDOUT: u64 = 110;
+pub const ECONNREFUSED: u64 = 111;
+
+/// Whether a syscall return value is an error
+pub const fn is_error(ret: u64) -> bool {
+    ret > !0 - 4096
+}
+
+/// errno carried by an error return value
+pub const fn errno(ret: u64) -> u64 {
+    ret.wrapping_neg()
+}
+
+/// Socket domains and types for sys_socket
+pub const AF_INET: u64 = 2;
+pub const SOCK_STREAM: u64 = 1;
+pub const SOCK_DGRAM: u64 = 2;
+pub const SOCK_RAW: u64 = 3;
+
 /// Userspace syscall wrappers (for future userspace programs)
 #[allow(dead_code)]
 mod userspace {
@@ -138,8 +457,22 @@ mod userspace {
         unsafe { syscall3(SYS_OPEN, path.as_ptr() as u64, flags, 0) }
     }
 
-    pub fn exec(path: &str) -> u64 {
-        unsafe { syscall1(SYS_EXEC, path.as_ptr() as u64) }
+    /// `path` must be NUL-terminated; `argv` and `envp` are NULL-terminated
+    /// pointer arrays, or null
+    pub fn exec(path: &str, argv: *const *const u8, envp: *const *const u8) -> u64 {
+        unsafe { syscall3(SYS_EXEC, path.as_ptr() as u64, argv as u64, envp as u64) }
+    }
+
+    pub fn fork() -> u64 {
+        unsafe { syscall0(SYS_FORK) }
+    }
+
+    pub fn waitpid(pid: u64, status: &mut i32, flags: u64) -> u64 {
+        unsafe { syscall3(SYS_WAITPID, pid, status as *mut i32 as u64, flags) }
+    }
+
+    pub fn sysinfo(info: &mut SysInfo) -> u64 {
+        unsafe { syscall1(SYS_SYSINFO, info as *mut SysInfo as u64) }
     }
 
     pub fn draw_char(x: u64, y: u64, ch: u64, fg: u64, bg: u64) -> u64 {
@@ -185,4 +518,33 @@ mod userspace {
         unsafe { syscall0(SYS_REBOOT); }
         loop {}
     }
+
+    pub fn socket(domain: u64, socktype: u64, protocol: u64) -> u64 {
+        unsafe { syscall3(SYS_SOCKET, domain, socktype, protocol) }
+    }
+
+    pub fn bind(fd: u64, addr: [u8; 4], port: u16) -> u64 {
+        unsafe { syscall3(SYS_BIND, fd, u32::from_be_bytes(addr) as u64, port as u64) }
+    }
+
+    pub fn connect(fd: u64, addr: [u8; 4], port: u16) -> u64 {
+        unsafe { syscall3(SYS_CONNECT, fd, u32::from_be_bytes(addr) as u64, port as u64) }
+    }
+
+    pub fn send(fd: u64, data: &[u8]) -> u64 {
+        unsafe { syscall3(SYS_SEND, fd, data.as_ptr() as u64, data.len() as u64) }
+    }
+
+    pub fn recv(fd: u64, buf: &mut [u8]) -> u64 {
+        unsafe { syscall3(SYS_RECV, fd, buf.as_mut_ptr() as u64, buf.len() as u64) }
+    }
+
+    pub fn close(fd: u64) -> u64 {
+        unsafe { syscall1(SYS_CLOSE, fd) }
+    }
+
+    pub fn setcred(name: &str, password: Option<&str>) -> u64 {
+        let password = password.map_or(0, |p| p.as_ptr() as u64);
+        unsafe { syscall3(SYS_SETCRED, name.as_ptr() as u64, password, 0) }
+    }
 }
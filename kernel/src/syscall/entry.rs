@@ -1,32 +1,72 @@
 //! SYSCALL entry stub and low-level context save/restore.
+//!
+//! Each syscall runs on the calling task's own kernel stack (kept in
+//! `SYSCALL_KERNEL_RSP` by the scheduler), so a syscall can block and let
+//! other tasks run.
 
 use core::arch::naked_asm;
 
-const SYSCALL_STACK_SIZE: usize = 4096 * 4;
+/// Scratch slot for the user RSP while switching stacks (interrupts are
+/// masked on entry, so it is only live for a few instructions)
+#[no_mangle]
+static mut SYSCALL_USER_RSP: u64 = 0;
+
+/// Top of the running task's kernel stack
+#[no_mangle]
+static mut SYSCALL_KERNEL_RSP: u64 = 0;
 
-#[repr(C, align(16))]
-struct SyscallStack {
-    data: [u8; SYSCALL_STACK_SIZE],
+/// User state saved by the entry stub, lowest address first
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub r10: u64,
+    pub r8: u64,
+    pub r9: u64,
+    /// User RIP (saved by SYSCALL)
+    pub rcx: u64,
+    /// User RFLAGS (saved by SYSCALL)
+    pub r11: u64,
+    pub user_rsp: u64,
 }
 
-static SYSCALL_STACK: SyscallStack = SyscallStack { data: [0; SYSCALL_STACK_SIZE] };
+/// Point syscall entry at the kernel stack of the task about to run
+pub fn set_kernel_stack(stack_top: u64) {
+    unsafe { core::ptr::addr_of_mut!(SYSCALL_KERNEL_RSP).write(stack_top) };
+}
 
-#[no_mangle]
-static mut SYSCALL_USER_RSP: u64 = 0;
+/// User state of the syscall currently being handled
+pub fn current_frame() -> SyscallFrame {
+    unsafe {
+        let top = core::ptr::addr_of!(SYSCALL_KERNEL_RSP).read();
+        ((top - core::mem::size_of::<SyscallFrame>() as u64) as *const SyscallFrame).read()
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn do_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
-    crate::syscall::dispatch_syscall(num, arg1, arg2, arg3, arg4, arg5)
-        .wrapping_add(0 * arg5)
+    let ret = crate::syscall::dispatch_syscall(num, arg1, arg2, arg3, arg4, arg5)
+        .wrapping_add(0 * arg5);
+    // Pending signals are acted on before going back to user mode
+    crate::task::signal::on_syscall_exit(ret);
+    ret
 }
 
 #[unsafe(naked)]
 pub unsafe extern "C" fn syscall_handler() -> ! {
     naked_asm!(
-        // Save user RSP and switch to kernel syscall stack
+        // Switch to the task's kernel stack, keeping the user RSP on it
         "mov [rip + {user_rsp}], rsp",
-        "lea rsp, [rip + {stack_base}]",
-        "add rsp, 16384",
+        "mov rsp, [rip + {kernel_rsp}]",
+        "push qword ptr [rip + {user_rsp}]",
         // Save volatile state from user
         "push r11",
         "push rcx",
@@ -49,7 +89,10 @@ pub unsafe extern "C" fn syscall_handler() -> ! {
         "mov rcx, [rsp + 64]",  // arg3 (saved rdx)
         "mov r8,  [rsp + 72]",  // arg4 (saved r10)
         "mov r9,  [rsp + 80]",  // arg5 (saved r8)
+        // 15 pushes leave the stack 8 bytes off the ABI alignment
+        "sub rsp, 8",
         "call {do_syscall}",
+        "add rsp, 8",
         // Restore registers (except rax which holds return value)
         "pop r15",
         "pop r14",
@@ -66,10 +109,10 @@ pub unsafe extern "C" fn syscall_handler() -> ! {
         "pop rcx",
         "pop r11",
         // Restore user RSP and return to user
-        "mov rsp, [rip + {user_rsp}]",
+        "pop rsp",
         "sysretq",
         user_rsp = sym SYSCALL_USER_RSP,
-        stack_base = sym SYSCALL_STACK,
+        kernel_rsp = sym SYSCALL_KERNEL_RSP,
         do_syscall = sym do_syscall
     )
 }
@@ -1,6 +1,7 @@
 //! Syscall Interface for ospabOS v0.1.0
 //! Implements x86_64 syscall/sysret mechanism
 
+use crate::fs::vfs::{FileHandle, FsError, SeekFrom};
 use crate::task::scheduler::SCHEDULER;
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -10,6 +11,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 pub mod dispatcher;
 pub mod abi;
 pub mod entry;
+pub mod trace;
 
 /// Syscall numbers (stable ABI)
 #[derive(Debug, Clone, Copy)]
@@ -31,6 +33,16 @@ pub enum SyscallNumber {
     Uptime = 13,
     Shutdown = 14,
     Reboot = 15,
+    SetCred = 16,
+    Socket = 17,
+    Bind = 18,
+    Connect = 19,
+    Send = 20,
+    Recv = 21,
+    Close = 22,
+    Fork = 23,
+    WaitPid = 24,
+    SysInfo = 25,
 }
 
 static SPAWN_WORKER_STARTED: AtomicBool = AtomicBool::new(false);
@@ -42,6 +54,8 @@ pub fn init() {
         // Enable syscall/sysret support
         enable_syscall_support();
     }
+    // Until the first task switch, syscalls run on the boot TSS stack
+    entry::set_kernel_stack(crate::gdt::kernel_stack().as_u64());
 }
 
 /// Enable syscall support in CPU
@@ -72,7 +86,8 @@ unsafe fn enable_syscall_support() {
 
 /// Dispatch syscall from user space
 pub fn dispatch_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
-    match num {
+    let traced = trace::begin(num, [arg1, arg2, arg3, arg4, arg5]);
+    let ret = match num {
         0 => sys_yield(),
         1 => sys_spawn(arg1 as *const u8, arg2 as usize),
         2 => sys_write(arg1, arg2 as *const u8, arg3 as usize),
@@ -81,7 +96,7 @@ pub fn dispatch_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, ar
         5 => sys_getpid(),
         6 => sys_malloc(arg1 as usize), // New: memory allocation
         7 => sys_open(arg1 as *const u8, arg2),
-        8 => sys_exec(arg1 as *const u8),
+        8 => sys_exec(arg1 as *const u8, arg2 as *const *const u8, arg3 as *const *const u8),
         9 => sys_draw_char(arg1, arg2, arg3, arg4, arg5),
         10 => sys_chdir(arg1 as *const u8),
         11 => sys_getcwd(arg1 as *mut u8, arg2 as usize),
@@ -89,13 +104,47 @@ pub fn dispatch_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, ar
         13 => sys_uptime(),
         14 => sys_shutdown(),
         15 => sys_reboot(),
+        16 => sys_setcred(arg1 as *const u8, arg2 as *const u8),
+        17 => sys_socket(arg1, arg2, arg3),
+        18 => sys_bind(arg1, arg2 as u32, arg3 as u16),
+        19 => sys_connect(arg1, arg2 as u32, arg3 as u16),
+        20 => sys_send(arg1, arg2 as *const u8, arg3 as usize),
+        21 => sys_recv(arg1, arg2 as *mut u8, arg3 as usize),
+        22 => sys_close(arg1),
+        23 => sys_fork(),
+        24 => sys_waitpid(arg1, arg2 as *mut i32, arg3),
+        25 => sys_sysinfo(arg1 as *mut abi::SysInfo),
+        26 => sys_getrlimit(arg1, arg2 as *mut abi::RLimit),
+        27 => sys_setrlimit(arg1, arg2 as *const abi::RLimit),
+        28 => sys_shm_create(arg1 as usize, arg2),
+        29 => sys_shm_map(arg1, arg2),
+        30 => sys_shm_unmap(arg1),
+        31 => sys_kill(arg1, arg2),
+        32 => sys_sigaction(arg1, arg2, arg3),
+        33 => crate::task::signal::sigreturn(),
+        34 => sys_futex_wait(arg1, arg2 as u32),
+        35 => sys_futex_wake(arg1, arg2 as u32),
+        36 => sys_nanosleep(arg1),
+        37 => sys_alarm(arg1),
+        38 => sys_beep(arg1, arg2),
+        39 => sys_audio_write(arg1 as *const u8, arg2 as usize),
+        40 => sys_stat(arg1 as *const u8, arg2 as *mut abi::Stat),
+        41 => sys_fstat(arg1, arg2 as *mut abi::Stat),
+        42 => sys_lseek(arg1, arg2 as i64, arg3),
+        43 => sys_unlink(arg1 as *const u8),
+        44 => sys_rmdir(arg1 as *const u8, arg2),
+        45 => sys_ioctl(arg1, arg2, arg3),
         _ => !0, // Invalid syscall
+    };
+    if let Some(call) = traced {
+        trace::end(call, ret);
     }
+    ret
 }
 
 /// Syscall implementations
 fn sys_yield() -> u64 {
-    SCHEDULER.lock().yield_task();
+    crate::task::scheduler::yield_now();
     0
 }
 
@@ -114,61 +163,415 @@ fn sys_spawn(path_ptr: *const u8, _name_len: usize) -> u64 {
     0
 }
 
+/// Touch every page of a user buffer so demand-paged memory is mapped
+/// before the scheduler lock is taken (the fault handler cannot take it)
+pub(crate) fn fault_in(buf: *mut u8, len: usize, write: bool) {
+    let start = buf as usize;
+    let mut page = start & !0xFFF;
+    while page < start + len {
+        let addr = core::cmp::max(page, start) as *mut u8;
+        unsafe {
+            let byte = core::ptr::read_volatile(addr);
+            if write {
+                core::ptr::write_volatile(addr, byte);
+            }
+        }
+        page += 4096;
+    }
+}
+
+/// Whether the `len` bytes at `addr` end at or below `end`
+fn fits_below(addr: u64, len: usize, end: u64) -> bool {
+    addr.checked_add(len as u64).is_some_and(|last| last <= end)
+}
+
+/// `ptr` if a whole `T` there lies in user space, EFAULT otherwise. A
+/// task must not get the kernel to read or write its own memory for it,
+/// so every pointer argument goes through this or `user_slice`.
+fn user_ptr<T>(ptr: *mut T) -> Result<*mut T, u64> {
+    if fits_below(ptr as u64, core::mem::size_of::<T>(), crate::mem::vmm::USER_SPACE_END) {
+        Ok(ptr)
+    } else {
+        Err(abi::EFAULT.wrapping_neg())
+    }
+}
+
+/// The `len` bytes at user address `ptr`, EFAULT unless all of them lie
+/// in user space
+///
+/// # Safety
+/// Whatever is mapped there may be read for the lifetime `'a`.
+unsafe fn user_slice<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], u64> {
+    if !fits_below(ptr as u64, len, crate::mem::vmm::USER_SPACE_END) {
+        return Err(abi::EFAULT.wrapping_neg());
+    }
+    Ok(core::slice::from_raw_parts(ptr, len))
+}
+
+/// `user_slice` for a buffer the kernel writes to
+///
+/// # Safety
+/// Whatever is mapped there may be written for the lifetime `'a`.
+unsafe fn user_slice_mut<'a>(ptr: *mut u8, len: usize) -> Result<&'a mut [u8], u64> {
+    if !fits_below(ptr as u64, len, crate::mem::vmm::USER_SPACE_END) {
+        return Err(abi::EFAULT.wrapping_neg());
+    }
+    Ok(core::slice::from_raw_parts_mut(ptr, len))
+}
+
+/// Run `f` on descriptor `fd` of the current task with the scheduler
+/// unlocked: file handles take the VFS lock, which must not be taken
+/// under it. The handle is out of the table meanwhile
+fn with_handle<R>(fd: u64, f: impl FnOnce(&mut dyn FileHandle) -> Result<R, FsError>) -> Result<R, FsError> {
+    let mut handle = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current_task_mut().ok_or(FsError::Invalid)?;
+        current.fd_table.take(fd as u32)?
+    };
+    let result = f(handle.as_mut());
+    let mut scheduler = SCHEDULER.lock();
+    if let Some(current) = scheduler.current_task_mut() {
+        current.fd_table.put_back(fd as u32, handle);
+    }
+    result
+}
+
 fn sys_write(fd: u64, buf: *const u8, len: usize) -> u64 {
     if buf.is_null() || len == 0 {
         return 0;
     }
+    let data = match unsafe { user_slice(buf, len) } {
+        Ok(data) => data,
+        Err(e) => return e,
+    };
+    fault_in(buf as *mut u8, len, false);
 
-    let mut scheduler = SCHEDULER.lock();
-    let current = match scheduler.current_task_mut() {
-        Some(task) => task,
-        None => return !0,
+    // May be short (a full screen, a full sound queue); callers loop
+    match with_handle(fd, |handle| handle.write(data)) {
+        Ok(written) => written as u64,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_read(fd: u64, buf: *mut u8, len: usize) -> u64 {
+    if buf.is_null() || len == 0 {
+        return 0;
+    }
+    if let Err(e) = unsafe { user_slice_mut(buf, len) } {
+        return e;
+    }
+
+    // Handles with nothing to read yet (the terminal) say WouldBlock; wait
+    // for input without holding the scheduler lock
+    loop {
+        fault_in(buf, len, true);
+
+        let result = with_handle(fd, |handle| handle.read(unsafe { core::slice::from_raw_parts_mut(buf, len) }));
+        match result {
+            Ok(read) => return read as u64,
+            Err(FsError::WouldBlock) if crate::task::signal::interrupted() => return abi::EINTR.wrapping_neg(),
+            Err(FsError::WouldBlock) => crate::task::scheduler::yield_now(),
+            Err(e) => return e.to_syscall(),
+        }
+    }
+}
+
+fn sys_lseek(fd: u64, offset: i64, whence: u64) -> u64 {
+    let pos = match whence {
+        abi::SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+        abi::SEEK_CUR => SeekFrom::Current(offset),
+        abi::SEEK_END => SeekFrom::End(offset),
+        _ => return abi::EINVAL.wrapping_neg(),
     };
+    match with_handle(fd, |handle| handle.seek(pos)) {
+        Ok(offset) => offset,
+        Err(e) => e.to_syscall(),
+    }
+}
 
-    let handle = match current.fd_table.get_mut(fd as u32) {
-        Ok(h) => h,
-        Err(_) => return !0,
+fn sys_ioctl(fd: u64, request: u64, arg: u64) -> u64 {
+    match with_handle(fd, |handle| handle.ioctl(request, arg)) {
+        Ok(result) => result,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_exit(code: i32) -> u64 {
+    {
+        let mut scheduler = SCHEDULER.lock();
+        // The boot context hosts the kernel shell and cannot go away
+        if scheduler.current_pid() == 0 {
+            return 0;
+        }
+        scheduler.terminate_current(code);
+    }
+    // Does not return: the task is dropped on this switch
+    crate::task::scheduler::yield_now();
+    0
+}
+
+fn sys_fork() -> u64 {
+    use crate::task::pcb::TaskContext;
+
+    // The child returns from the same syscall with rax = 0
+    let frame = entry::current_frame();
+    let selectors = crate::gdt::selectors();
+    let context = TaskContext {
+        r15: frame.r15, r14: frame.r14, r13: frame.r13, r12: frame.r12,
+        r11: frame.r11, r10: frame.r10, r9: frame.r9, r8: frame.r8,
+        rbp: frame.rbp, rdi: frame.rdi, rsi: frame.rsi, rdx: frame.rdx,
+        rcx: frame.rcx, rbx: frame.rbx, rax: 0,
+        rip: frame.rcx,
+        cs: (selectors.user_code.0 | 3) as u64,
+        rflags: frame.r11 | 0x200,
+        rsp: frame.user_rsp,
+        ss: (selectors.user_data.0 | 3) as u64,
     };
 
-    unsafe {
-        let slice = core::slice::from_raw_parts(buf, len);
-        match handle.write(slice) {
-            Ok(written) => written as u64,
-            Err(_) => !0,
+    // Reuse the stack of a task that exited when there is one
+    let mut dead = SCHEDULER.lock().take_dead();
+    let reused = dead.iter_mut().find(|task| task.owns_kernel_stack).map(|task| {
+        task.owns_kernel_stack = false;
+        task.kernel_stack
+    });
+    crate::task::release_dead(dead);
+    let stack = match reused.or_else(crate::task::alloc_kernel_stack) {
+        Some(stack) => stack,
+        None => return abi::ENOMEM.wrapping_neg(),
+    };
+    let result = SCHEDULER.lock().fork_current(context, stack);
+    match result {
+        Ok(pid) => pid as u64,
+        Err(_) => {
+            crate::task::free_kernel_stack(stack);
+            abi::ENOMEM.wrapping_neg()
         }
     }
 }
 
-fn sys_read(fd: u64, buf: *mut u8, len: usize) -> u64 {
-    if buf.is_null() || len == 0 {
-        return 0;
+fn sys_waitpid(pid: u64, status: *mut i32, flags: u64) -> u64 {
+    use crate::task::scheduler::WaitStatus;
+
+    // Checked before reaping, so a bad pointer does not lose the status
+    if !status.is_null() {
+        if let Err(e) = user_ptr(status) {
+            return e;
+        }
+    }
+    let target = if pid == 0 { None } else { Some(pid as u32) };
+    loop {
+        let (result, dead) = {
+            let mut scheduler = SCHEDULER.lock();
+            let parent = scheduler.current_pid();
+            (scheduler.reap(parent, target), scheduler.take_dead())
+        };
+        crate::task::release_dead(dead);
+        match result {
+            WaitStatus::Exited(child, code) => {
+                if !status.is_null() {
+                    unsafe { status.write(code) };
+                }
+                return child as u64;
+            }
+            WaitStatus::Running if flags & abi::WNOHANG != 0 => return 0,
+            WaitStatus::Running if crate::task::signal::interrupted() => return abi::EINTR.wrapping_neg(),
+            WaitStatus::Running => crate::task::scheduler::yield_now(),
+            WaitStatus::NoChildren => return abi::ECHILD.wrapping_neg(),
+        }
+    }
+}
+
+fn sys_sysinfo(info: *mut abi::SysInfo) -> u64 {
+    if info.is_null() {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let info = match user_ptr(info) {
+        Ok(info) => info,
+        Err(e) => return e,
+    };
+    let (total_frames, used_frames, free_frames) = crate::mem::physical::stats();
+    let heap = crate::mm::heap_allocator::stats();
+    let result = abi::SysInfo {
+        uptime_ms: crate::drivers::timer::get_uptime_ms(),
+        mem_total: total_frames as u64 * 4096,
+        mem_used: used_frames as u64 * 4096,
+        mem_free: free_frames as u64 * 4096,
+        heap_total: heap.total as u64,
+        heap_used: heap.in_use as u64,
+        heap_peak: heap.peak as u64,
+        heap_free: heap.free() as u64,
+        heap_fragmentation: heap.fragmentation_percent() as u64,
+        tasks: SCHEDULER.lock().task_count() as u64,
+    };
+    unsafe { info.write(result) };
+    0
+}
+
+fn sys_getrlimit(resource: u64, limit: *mut abi::RLimit) -> u64 {
+    if limit.is_null() || resource != abi::RLIMIT_CPU {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let limit = match user_ptr(limit) {
+        Ok(limit) => limit,
+        Err(e) => return e,
+    };
+    let current = crate::task::cpu_limit();
+    unsafe { limit.write(current) };
+    0
+}
+
+fn sys_setrlimit(resource: u64, limit: *const abi::RLimit) -> u64 {
+    if limit.is_null() || resource != abi::RLIMIT_CPU {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let limit = match user_ptr(limit as *mut abi::RLimit) {
+        Ok(limit) => unsafe { limit.read() },
+        Err(e) => return e,
+    };
+    if limit.cur > limit.max {
+        return abi::EINVAL.wrapping_neg();
+    }
+    // What is left is raising the hard limit without being root
+    match crate::task::set_cpu_limit(limit) {
+        Ok(()) => 0,
+        Err(_) => abi::EPERM.wrapping_neg(),
+    }
+}
+
+fn sys_shm_create(size: usize, flags: u64) -> u64 {
+    let pid = SCHEDULER.lock().current_pid();
+    match crate::ipc::shm::create(pid, size, flags) {
+        Ok(id) => id as u64,
+        Err(e) => e.to_syscall(),
     }
+}
+
+fn sys_shm_map(id: u64, flags: u64) -> u64 {
+    use crate::ipc::shm::{self, ShmError};
 
     let mut scheduler = SCHEDULER.lock();
-    let current = match scheduler.current_task_mut() {
+    let task = match scheduler.current_task_mut() {
         Some(task) => task,
-        None => return !0,
+        None => return ShmError::Invalid.to_syscall(),
+    };
+    let pid = task.pid;
+    let space = match task.address_space.as_mut() {
+        Some(space) => space,
+        None => return ShmError::Invalid.to_syscall(),
     };
+    match shm::map(pid, space, id as u32, flags) {
+        Ok(addr) => addr,
+        Err(e) => e.to_syscall(),
+    }
+}
 
-    let handle = match current.fd_table.get_mut(fd as u32) {
-        Ok(h) => h,
-        Err(_) => return !0,
+fn sys_shm_unmap(id: u64) -> u64 {
+    use crate::ipc::shm::{self, ShmError};
+
+    let mut scheduler = SCHEDULER.lock();
+    let task = match scheduler.current_task_mut() {
+        Some(task) => task,
+        None => return ShmError::Invalid.to_syscall(),
     };
+    let pid = task.pid;
+    let space = match task.address_space.as_mut() {
+        Some(space) => space,
+        None => return ShmError::Invalid.to_syscall(),
+    };
+    match shm::unmap(pid, space, id as u32) {
+        Ok(()) => 0,
+        Err(e) => e.to_syscall(),
+    }
+}
 
-    unsafe {
-        let slice = core::slice::from_raw_parts_mut(buf, len);
-        match handle.read(slice) {
-            Ok(read) => read as u64,
-            Err(_) => !0,
+fn sys_kill(pid: u64, sig: u64) -> u64 {
+    if pid > u32::MAX as u64 || sig > u32::MAX as u64 {
+        return abi::EINVAL.wrapping_neg();
+    }
+    match crate::task::signal::send(pid as u32, sig as u32) {
+        Ok(()) => 0,
+        Err(errno) => errno.wrapping_neg(),
+    }
+}
+
+fn sys_sigaction(sig: u64, handler: u64, restorer: u64) -> u64 {
+    use crate::task::signal::{SigAction, NSIG};
+
+    if sig == 0 || sig >= NSIG as u64 || sig == abi::SIGKILL as u64 || sig == abi::SIGSTOP as u64 {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let action = match handler {
+        abi::SIG_DFL => SigAction::Default,
+        abi::SIG_IGN => SigAction::Ignore,
+        entry if restorer != 0 && entry.max(restorer) <= crate::mem::vmm::USER_SPACE_END => {
+            SigAction::Handler { entry, restorer }
+        }
+        _ => return abi::EINVAL.wrapping_neg(),
+    };
+    match SCHEDULER.lock().current_task_mut() {
+        Some(task) => {
+            task.signals.set_action(sig as u32, action);
+            0
         }
+        None => abi::EINVAL.wrapping_neg(),
+    }
+}
+
+fn sys_futex_wait(addr: u64, expected: u32) -> u64 {
+    match crate::task::futex::wait(addr, expected) {
+        Ok(()) => 0,
+        Err(errno) => errno.wrapping_neg(),
+    }
+}
+
+fn sys_futex_wake(addr: u64, count: u32) -> u64 {
+    match crate::task::futex::wake(addr, count) {
+        Ok(woken) => woken as u64,
+        Err(errno) => errno.wrapping_neg(),
+    }
+}
+
+fn sys_nanosleep(ns: u64) -> u64 {
+    let ns_per_tick = 1_000_000_000 / crate::drivers::timer::TICKS_PER_SECOND;
+    match crate::timers::sleep(ns.div_ceil(ns_per_tick)) {
+        Ok(()) => 0,
+        Err(errno) => errno.wrapping_neg(),
     }
 }
 
-fn sys_exit(_code: i32) -> u64 {
-    SCHEDULER.lock().terminate_current();
+fn sys_alarm(seconds: u64) -> u64 {
+    let pid = SCHEDULER.lock().current_pid();
+    crate::timers::alarm(pid, seconds)
+}
+
+fn sys_beep(freq: u64, ms: u64) -> u64 {
+    use crate::drivers::speaker;
+
+    if freq == 0 {
+        speaker::stop();
+        return 0;
+    }
+    if !(speaker::MIN_FREQ as u64..=speaker::MAX_FREQ as u64).contains(&freq) {
+        return abi::EINVAL.wrapping_neg();
+    }
+    speaker::beep(freq as u32, ms.min(u32::MAX as u64) as u32);
     0
 }
 
+fn sys_audio_write(buf: *const u8, len: usize) -> u64 {
+    use crate::drivers::speaker;
+
+    if buf.is_null() {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let bytes = match unsafe { user_slice(buf, len) } {
+        Ok(bytes) => bytes,
+        Err(e) => return e,
+    };
+    let notes = speaker::parse_notes(bytes);
+    (speaker::play(&notes) * speaker::NOTE_SIZE) as u64
+}
+
 fn sys_getpid() -> u64 {
     SCHEDULER.lock().current_pid() as u64
 }
@@ -205,15 +608,15 @@ fn sys_malloc(size: usize) -> u64 {
     }
 }
 
-fn sys_open(path_ptr: *const u8, _flags: u64) -> u64 {
+fn sys_open(path_ptr: *const u8, flags: u64) -> u64 {
     let path = match read_c_string(path_ptr) {
         Some(p) => p,
         None => return !0,
     };
 
-    let handle = match crate::services::vfs::open(&path, _flags) {
+    let handle = match crate::services::vfs::open(&path, flags) {
         Ok(h) => h,
-        Err(_) => return !0,
+        Err(e) => return e.to_syscall(),
     };
 
     let mut scheduler = SCHEDULER.lock();
@@ -225,13 +628,28 @@ fn sys_open(path_ptr: *const u8, _flags: u64) -> u64 {
     current.fd_table.insert(handle) as u64
 }
 
-fn sys_exec(path_ptr: *const u8) -> u64 {
+fn sys_exec(path_ptr: *const u8, argv_ptr: *const *const u8, envp_ptr: *const *const u8) -> u64 {
     let path = match read_c_string(path_ptr) {
         Some(p) => p,
         None => return !0,
     };
+    // A null argv means just the program name
+    let argv = if argv_ptr.is_null() {
+        alloc::vec![path.clone()]
+    } else {
+        match read_c_string_array(argv_ptr) {
+            Some(v) => v,
+            None => return !0,
+        }
+    };
+    let envp = match read_c_string_array(envp_ptr) {
+        Some(v) => v,
+        None => return !0,
+    };
 
-    match exec_user_path(&path) {
+    let argv: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
+    let envp: Vec<&str> = envp.iter().map(|s| s.as_str()).collect();
+    match exec_user_path(&path, &argv, &envp) {
         Ok(_) => 0,
         Err(_) => !0,
     }
@@ -255,6 +673,7 @@ fn sys_chdir(path_ptr: *const u8) -> u64 {
 
     match crate::services::vfs::process_request(crate::ipc::message::FSRequest::ChangeDir { path }) {
         crate::ipc::message::FSResponse::Success => 0,
+        crate::ipc::message::FSResponse::Error(e, _) => e.to_syscall(),
         _ => !0,
     }
 }
@@ -266,6 +685,7 @@ fn sys_getcwd(buf: *mut u8, len: usize) -> u64 {
 
     let cwd = match crate::services::vfs::process_request(crate::ipc::message::FSRequest::GetCwd) {
         crate::ipc::message::FSResponse::Cwd(path) => path,
+        crate::ipc::message::FSResponse::Error(e, _) => return e.to_syscall(),
         _ => return !0,
     };
 
@@ -284,12 +704,105 @@ fn sys_listdir(path_ptr: *const u8, buf: *mut u8, len: usize) -> u64 {
 
     let listing = match crate::services::vfs::process_request(crate::ipc::message::FSRequest::ListDir { path }) {
         crate::ipc::message::FSResponse::DirListing(entries) => entries.join("\n"),
+        crate::ipc::message::FSResponse::Error(e, _) => return e.to_syscall(),
         _ => return !0,
     };
 
     write_user_string(buf, len, &listing)
 }
 
+/// Metadata in the layout sys_stat hands out
+fn to_stat(metadata: &crate::services::vfs::Metadata) -> abi::Stat {
+    use crate::services::vfs::FileType;
+
+    let file_type = match metadata.file_type {
+        FileType::Regular => abi::S_IFREG,
+        FileType::Directory => abi::S_IFDIR,
+        FileType::Device => abi::S_IFCHR,
+        FileType::Link => abi::S_IFLNK,
+    };
+    abi::Stat {
+        mode: file_type | metadata.mode as u64,
+        size: metadata.size as u64,
+        created_ms: metadata.created,
+        modified_ms: metadata.modified,
+    }
+}
+
+fn sys_stat(path_ptr: *const u8, stat: *mut abi::Stat) -> u64 {
+    if stat.is_null() {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let stat = match user_ptr(stat) {
+        Ok(stat) => stat,
+        Err(e) => return e,
+    };
+    let path = match read_c_string(path_ptr) {
+        Some(p) => p,
+        None => return abi::EINVAL.wrapping_neg(),
+    };
+
+    let metadata = match crate::services::vfs::process_request(crate::ipc::message::FSRequest::Stat { path }) {
+        crate::ipc::message::FSResponse::Metadata(metadata) => metadata,
+        crate::ipc::message::FSResponse::Error(e, _) => return e.to_syscall(),
+        _ => return !0,
+    };
+    unsafe { stat.write(to_stat(&metadata)) };
+    0
+}
+
+fn sys_fstat(fd: u64, stat: *mut abi::Stat) -> u64 {
+    if stat.is_null() {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let stat = match user_ptr(stat) {
+        Ok(stat) => stat,
+        Err(e) => return e,
+    };
+    match with_handle(fd, |handle| handle.metadata().ok_or(FsError::Invalid)) {
+        Ok(metadata) => {
+            unsafe { stat.write(to_stat(&metadata)) };
+            0
+        }
+        Err(e) => e.to_syscall(),
+    }
+}
+
+/// Remove `path`, which has to be a directory if `directory` and must not
+/// be one otherwise
+fn remove(path_ptr: *const u8, directory: bool, recursive: bool) -> u64 {
+    use crate::ipc::message::{FSRequest, FSResponse};
+    use crate::services::vfs::{self, FileType};
+
+    let path = match read_c_string(path_ptr) {
+        Some(p) => p,
+        None => return abi::EINVAL.wrapping_neg(),
+    };
+    let is_dir = match vfs::process_request(FSRequest::Stat { path: path.clone() }) {
+        FSResponse::Metadata(metadata) => metadata.file_type == FileType::Directory,
+        FSResponse::Error(e, _) => return e.to_syscall(),
+        _ => return !0,
+    };
+    if is_dir != directory {
+        let error = if is_dir { FsError::NotFile } else { FsError::NotDir };
+        return error.to_syscall();
+    }
+    let request = if recursive { FSRequest::DeleteTree { path } } else { FSRequest::Delete { path } };
+    match vfs::process_request(request) {
+        FSResponse::Success => 0,
+        FSResponse::Error(e, _) => e.to_syscall(),
+        _ => !0,
+    }
+}
+
+fn sys_unlink(path_ptr: *const u8) -> u64 {
+    remove(path_ptr, false, false)
+}
+
+fn sys_rmdir(path_ptr: *const u8, flags: u64) -> u64 {
+    remove(path_ptr, true, flags & abi::RMDIR_RECURSIVE != 0)
+}
+
 fn sys_uptime() -> u64 {
     crate::drivers::timer::get_uptime_ms()
 }
@@ -304,21 +817,143 @@ fn sys_reboot() -> u64 {
     0
 }
 
+fn sys_setcred(name_ptr: *const u8, password_ptr: *const u8) -> u64 {
+    let name = match read_c_string(name_ptr) {
+        Some(n) => n,
+        None => return abi::EINVAL.wrapping_neg(),
+    };
+    // Null password is allowed when the caller is root
+    let password = read_c_string(password_ptr);
+
+    // An unknown user fails like a wrong password
+    match crate::auth::set_credentials(&name, password.as_deref()) {
+        Ok(user) => user.id as u64,
+        Err(_) => abi::EPERM.wrapping_neg(),
+    }
+}
+
+fn sys_socket(domain: u64, socktype: u64, protocol: u64) -> u64 {
+    use crate::net::socket::{self, SocketDomain, SocketHandle, SocketType};
+
+    if domain != abi::AF_INET {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let socktype = match socktype {
+        abi::SOCK_STREAM => SocketType::Stream,
+        abi::SOCK_DGRAM => SocketType::Dgram,
+        abi::SOCK_RAW => SocketType::Raw,
+        _ => return abi::EINVAL.wrapping_neg(),
+    };
+    let id = match socket::socket(SocketDomain::AfInet, socktype, protocol as i32) {
+        Ok(id) => id,
+        Err(e) => return e.to_syscall(),
+    };
+
+    let mut scheduler = SCHEDULER.lock();
+    match scheduler.current_task_mut() {
+        Some(task) => task.fd_table.insert(alloc::boxed::Box::new(SocketHandle::new(id))) as u64,
+        None => {
+            let _ = socket::close_socket(id);
+            abi::EINVAL.wrapping_neg()
+        }
+    }
+}
+
+/// Look up the network socket behind a descriptor of the current task
+fn socket_for_fd(fd: u64) -> Option<i32> {
+    let mut scheduler = SCHEDULER.lock();
+    let current = scheduler.current_task_mut()?;
+    current.fd_table.get_mut(fd as u32).ok()?.socket_id()
+}
+
+fn sys_bind(fd: u64, addr: u32, port: u16) -> u64 {
+    let id = match socket_for_fd(fd) {
+        Some(id) => id,
+        None => return abi::EBADF.wrapping_neg(),
+    };
+    let addr = crate::net::IpAddress::from_bytes(addr.to_be_bytes());
+    match crate::net::socket::bind(id, addr, port) {
+        Ok(()) => 0,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_connect(fd: u64, addr: u32, port: u16) -> u64 {
+    let id = match socket_for_fd(fd) {
+        Some(id) => id,
+        None => return abi::EBADF.wrapping_neg(),
+    };
+    let addr = crate::net::IpAddress::from_bytes(addr.to_be_bytes());
+    match crate::net::socket::connect(id, addr, port) {
+        Ok(()) => 0,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_send(fd: u64, buf: *const u8, len: usize) -> u64 {
+    if buf.is_null() {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let data = match unsafe { user_slice(buf, len) } {
+        Ok(data) => data,
+        Err(e) => return e,
+    };
+    let id = match socket_for_fd(fd) {
+        Some(id) => id,
+        None => return abi::EBADF.wrapping_neg(),
+    };
+    match crate::net::socket::send(id, data) {
+        Ok(sent) => sent as u64,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_recv(fd: u64, buf: *mut u8, len: usize) -> u64 {
+    if buf.is_null() || len == 0 {
+        return abi::EINVAL.wrapping_neg();
+    }
+    let data = match unsafe { user_slice_mut(buf, len) } {
+        Ok(data) => data,
+        Err(e) => return e,
+    };
+    let id = match socket_for_fd(fd) {
+        Some(id) => id,
+        None => return abi::EBADF.wrapping_neg(),
+    };
+    // Scheduler lock is released while blocking
+    match crate::net::socket::receive_blocking(id, data) {
+        Ok(read) => read as u64,
+        Err(e) => e.to_syscall(),
+    }
+}
+
+fn sys_close(fd: u64) -> u64 {
+    let mut scheduler = SCHEDULER.lock();
+    let current = match scheduler.current_task_mut() {
+        Some(task) => task,
+        None => return abi::EINVAL.wrapping_neg(),
+    };
+    match current.fd_table.close(fd as u32) {
+        Ok(()) => 0,
+        Err(e) => e.to_syscall(),
+    }
+}
+
 fn write_user_string(dst: *mut u8, len: usize, s: &str) -> u64 {
     let bytes = s.as_bytes();
     let max = len.saturating_sub(1);
     let to_copy = core::cmp::min(bytes.len(), max);
-    unsafe {
-        let out = core::slice::from_raw_parts_mut(dst, len);
-        out[..to_copy].copy_from_slice(&bytes[..to_copy]);
-        out[to_copy] = 0;
-    }
+    let out = match unsafe { user_slice_mut(dst, len) } {
+        Ok(out) => out,
+        Err(e) => return e,
+    };
+    out[..to_copy].copy_from_slice(&bytes[..to_copy]);
+    out[to_copy] = 0;
     to_copy as u64
 }
 
-fn exec_user_path(path: &str) -> Result<(), &'static str> {
+fn exec_user_path(path: &str, argv: &[&str], envp: &[&str]) -> Result<(), &'static str> {
     use alloc::vec::Vec;
-    use crate::task::scheduler::SCHEDULER;
 
     let mut handle = crate::services::vfs::open(path, 0).map_err(|_| "open failed")?;
     let mut data = Vec::new();
@@ -331,50 +966,189 @@ fn exec_user_path(path: &str) -> Result<(), &'static str> {
         data.extend_from_slice(&buf[..read]);
     }
 
-    let load = crate::loader::elf::load_user_elf(&data)?;
-
-    let entry = load.entry;
-    let user_stack = load.user_stack;
-    let addr_space = load.address_space;
-    let cr3 = addr_space.cr3.as_u64();
-
-    let mut scheduler = SCHEDULER.lock();
-    let current = scheduler.current_task_mut().ok_or("no current task")?;
-
-    current.user_stack = user_stack;
-    current.page_table = cr3;
-    current.address_space = Some(addr_space);
-
-    unsafe { crate::arch::x86_64::enter_user_mode_with_cr3(entry, user_stack, cr3); }
+    crate::task::exec_image(path, &data, argv, envp)
 }
 
 fn spawn_worker() -> ! {
     loop {
         let path = SPAWN_QUEUE.lock().pop();
         if let Some(path) = path {
-            let _ = crate::shell::exec_path(&path);
+            let _ = crate::shell::exec_path(&path, &[path.as_str()]);
         } else {
             x86_64::instructions::hlt();
         }
     }
 }
 
+/// Read a NUL-terminated string from user memory
 fn read_c_string(ptr: *const u8) -> Option<String> {
+    c_string_below(ptr, crate::mem::vmm::USER_SPACE_END)
+}
+
+/// `read_c_string` for a string that has to lie at or below `end` (tests
+/// read kernel memory with `u64::MAX`)
+fn c_string_below(ptr: *const u8, end: u64) -> Option<String> {
     if ptr.is_null() {
         return None;
     }
 
     const MAX_LEN: usize = 1024;
     let mut bytes = Vec::new();
-    unsafe {
-        for i in 0..MAX_LEN {
-            let b = *ptr.add(i);
-            if b == 0 {
-                break;
-            }
-            bytes.push(b);
+    for i in 0..MAX_LEN {
+        // Running into kernel memory is as bad as starting there
+        if !fits_below(ptr as u64, i + 1, end) {
+            return None;
+        }
+        let b = unsafe { *ptr.add(i) };
+        if b == 0 {
+            break;
         }
+        bytes.push(b);
     }
 
     String::from_utf8(bytes).ok()
 }
+
+/// Read a NULL-terminated array of C string pointers (argv, envp) from
+/// user memory; a null array is empty
+fn read_c_string_array(ptr: *const *const u8) -> Option<Vec<String>> {
+    c_string_array_below(ptr, crate::mem::vmm::USER_SPACE_END)
+}
+
+/// `read_c_string_array` for an array and strings at or below `end`
+fn c_string_array_below(ptr: *const *const u8, end: u64) -> Option<Vec<String>> {
+    const MAX_ENTRIES: usize = 256;
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Some(strings);
+    }
+    for i in 0..MAX_ENTRIES {
+        let slot = ptr.wrapping_add(i);
+        if !fits_below(slot as u64, core::mem::size_of::<*const u8>(), end) {
+            return None;
+        }
+        let entry = unsafe { *slot };
+        if entry.is_null() {
+            return Some(strings);
+        }
+        strings.push(c_string_below(entry, end)?);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use core::mem::MaybeUninit;
+
+    const EINVAL: u64 = abi::EINVAL.wrapping_neg();
+    const EFAULT: u64 = abi::EFAULT.wrapping_neg();
+
+    fn call(num: u64, args: &[u64]) -> u64 {
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        dispatch_syscall(num, arg(0), arg(1), arg(2), arg(3), arg(4))
+    }
+
+    #[test_case]
+    fn unknown_numbers_fail() {
+        assert_eq!(call(46, &[]), !0);
+        assert_eq!(call(u64::MAX, &[1, 2, 3, 4, 5]), !0);
+    }
+
+    #[test_case]
+    fn empty_buffers_transfer_nothing() {
+        let buf = [0u8; 4];
+        assert_eq!(call(2, &[1, 0, 4]), 0);
+        assert_eq!(call(2, &[1, buf.as_ptr() as u64, 0]), 0);
+        assert_eq!(call(3, &[0, 0, 4]), 0);
+    }
+
+    #[test_case]
+    fn lseek_rejects_bad_whence_and_negative_offsets() {
+        assert_eq!(call(42, &[0, 0, 99]), EINVAL);
+        assert_eq!(call(42, &[0, (-1i64) as u64, abi::SEEK_SET]), EINVAL);
+    }
+
+    #[test_case]
+    fn null_pointers_are_rejected() {
+        let mut stat = MaybeUninit::<abi::Stat>::uninit();
+        let path = b"/\0";
+        assert_eq!(call(40, &[path.as_ptr() as u64, 0]), EINVAL);
+        // Refused for the null path before anything is written there
+        assert_eq!(call(40, &[0, 0x40_0000]), EINVAL);
+        assert_eq!(call(41, &[0, 0]), EINVAL);
+        assert_eq!(call(11, &[0, 16]), !0);
+        assert_eq!(call(39, &[0, 8]), EINVAL);
+    }
+
+    #[test_case]
+    fn kernel_pointers_are_refused() {
+        let mut stat = MaybeUninit::<abi::Stat>::uninit();
+        let path = b"/\0";
+        assert_eq!(call(40, &[path.as_ptr() as u64, stat.as_mut_ptr() as u64]), EFAULT);
+        assert_eq!(call(41, &[0, stat.as_mut_ptr() as u64]), EFAULT);
+        let mut info = MaybeUninit::<abi::SysInfo>::uninit();
+        assert_eq!(call(25, &[info.as_mut_ptr() as u64]), EFAULT);
+        let mut status = 0i32;
+        assert_eq!(call(24, &[0, &mut status as *mut i32 as u64]), EFAULT);
+        let buf = [0u8; 4];
+        assert_eq!(call(20, &[0, buf.as_ptr() as u64, 4]), EFAULT);
+        assert_eq!(call(21, &[0, buf.as_ptr() as u64, 4]), EFAULT);
+        // A user range that runs off the end of user space
+        assert_eq!(call(20, &[0, 0x40_0000, usize::MAX as u64]), EFAULT);
+        assert_eq!(read_c_string(path.as_ptr()), None);
+        let argv = [path.as_ptr(), core::ptr::null()];
+        assert_eq!(read_c_string_array(argv.as_ptr()), None);
+        // Straddling the end of user space counts as outside
+        assert!(user_ptr((crate::mem::vmm::USER_SPACE_END - 4) as *mut abi::Stat).is_err());
+        assert!(user_ptr(0x40_0000 as *mut abi::Stat).is_ok());
+    }
+
+    #[test_case]
+    fn rlimits_only_know_cpu() {
+        let mut limit = MaybeUninit::<abi::RLimit>::uninit();
+        assert_eq!(call(26, &[abi::RLIMIT_CPU, 0]), EINVAL);
+        assert_eq!(call(26, &[99, limit.as_mut_ptr() as u64]), EINVAL);
+        assert_eq!(call(27, &[99, limit.as_mut_ptr() as u64]), EINVAL);
+        assert_eq!(call(26, &[abi::RLIMIT_CPU, limit.as_mut_ptr() as u64]), EFAULT);
+        assert_eq!(call(27, &[abi::RLIMIT_CPU, limit.as_mut_ptr() as u64]), EFAULT);
+    }
+
+    #[test_case]
+    fn sigaction_validates_signal_and_handler() {
+        let user = 0x40_0000;
+        assert_eq!(call(32, &[0, abi::SIG_DFL]), EINVAL);
+        assert_eq!(call(32, &[crate::task::signal::NSIG as u64, abi::SIG_DFL]), EINVAL);
+        assert_eq!(call(32, &[abi::SIGKILL as u64, abi::SIG_IGN]), EINVAL);
+        // A handler needs a restorer, and both must be user addresses
+        assert_eq!(call(32, &[2, user, 0]), EINVAL);
+        assert_eq!(call(32, &[2, crate::mem::vmm::KERNEL_SPACE_START, user]), EINVAL);
+        assert_eq!(call(32, &[2, user, crate::mem::vmm::KERNEL_SPACE_START]), EINVAL);
+    }
+
+    #[test_case]
+    fn kill_and_beep_check_ranges() {
+        assert_eq!(call(31, &[u64::MAX, 9]), EINVAL);
+        assert_eq!(call(31, &[1, 1 << 40]), EINVAL);
+        assert_eq!(call(38, &[1, 10]), EINVAL);
+        assert_eq!(call(38, &[100_000, 10]), EINVAL);
+    }
+
+    #[test_case]
+    fn c_strings_stop_at_nul_and_must_be_utf8() {
+        assert_eq!(read_c_string(core::ptr::null()), None);
+        assert_eq!(c_string_below(b"ls\0-l\0".as_ptr(), u64::MAX).as_deref(), Some("ls"));
+        assert_eq!(c_string_below(b"\xff\0".as_ptr(), u64::MAX), None);
+    }
+
+    #[test_case]
+    fn c_string_arrays_stop_at_null() {
+        assert_eq!(read_c_string_array(core::ptr::null()), Some(Vec::new()));
+        let argv = [b"ls\0".as_ptr(), b"-l\0".as_ptr(), core::ptr::null()];
+        assert_eq!(c_string_array_below(argv.as_ptr(), u64::MAX), Some(vec!["ls".to_string(), "-l".to_string()]));
+        let bad = [b"\xff\0".as_ptr(), core::ptr::null()];
+        assert_eq!(c_string_array_below(bad.as_ptr(), u64::MAX), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Syscall tracing (kprobes-lite)
+//!
+//! `strace <pid>` in the shell marks a task as traced; `strace all` sets
+//! the global flag that traces every task. The dispatcher then writes one
+//! line per syscall of a traced task to the kernel log (see `dmesg`): the
+//! name, the arguments and the return value. exit, exec and sigreturn are
+//! logged on entry with `= ?`, since they normally do not return; a failed
+//! exec is logged again with its error. With nothing traced, a syscall
+//! pays one atomic load.
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::abi;
+use crate::task::scheduler::SCHEDULER;
+
+/// Name and argument count, by syscall number
+const SYSCALLS: &[(&str, usize)] = &[
+    ("yield", 0),
+    ("spawn", 2),
+    ("write", 3),
+    ("read", 3),
+    ("exit", 1),
+    ("getpid", 0),
+    ("malloc", 1),
+    ("open", 2),
+    ("exec", 3),
+    ("draw_char", 5),
+    ("chdir", 1),
+    ("getcwd", 2),
+    ("listdir", 3),
+    ("uptime", 0),
+    ("shutdown", 0),
+    ("reboot", 0),
+    ("setcred", 2),
+    ("socket", 3),
+    ("bind", 3),
+    ("connect", 3),
+    ("send", 3),
+    ("recv", 3),
+    ("close", 1),
+    ("fork", 0),
+    ("waitpid", 3),
+    ("sysinfo", 1),
+    ("getrlimit", 2),
+    ("setrlimit", 2),
+    ("shm_create", 2),
+    ("shm_map", 2),
+    ("shm_unmap", 1),
+    ("kill", 2),
+    ("sigaction", 3),
+    ("sigreturn", 0),
+    ("futex_wait", 2),
+    ("futex_wake", 2),
+    ("nanosleep", 1),
+    ("alarm", 1),
+    ("beep", 2),
+    ("audio_write", 2),
+    ("stat", 2),
+    ("fstat", 2),
+    ("lseek", 3),
+    ("unlink", 1),
+    ("rmdir", 2),
+    ("ioctl", 3),
+];
+
+/// Syscalls logged on entry because they normally do not return
+const NO_RETURN: &[u64] = &[abi::SYS_EXIT, abi::SYS_EXEC, abi::SYS_SIGRETURN];
+
+/// Anything is traced; checked before any lock is taken
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Trace every task
+static TRACE_ALL: AtomicBool = AtomicBool::new(false);
+static TRACED: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// A traced syscall in progress
+pub struct Call {
+    pid: u32,
+    num: u64,
+    args: [u64; 5],
+}
+
+fn update_active() {
+    let active = TRACE_ALL.load(Ordering::Relaxed) || !TRACED.lock().is_empty();
+    ACTIVE.store(active, Ordering::Relaxed);
+}
+
+/// Start tracing task `pid`
+pub fn trace(pid: u32) {
+    TRACED.lock().insert(pid);
+    update_active();
+}
+
+/// Stop tracing task `pid`; false if it was not traced
+pub fn untrace(pid: u32) -> bool {
+    let removed = TRACED.lock().remove(&pid);
+    update_active();
+    removed
+}
+
+/// Stop tracing every task, and clear the global flag
+pub fn untrace_all() {
+    TRACED.lock().clear();
+    TRACE_ALL.store(false, Ordering::Relaxed);
+    update_active();
+}
+
+/// Set or clear the global flag that traces every task
+pub fn set_trace_all(enabled: bool) {
+    TRACE_ALL.store(enabled, Ordering::Relaxed);
+    update_active();
+}
+
+pub fn trace_all() -> bool {
+    TRACE_ALL.load(Ordering::Relaxed)
+}
+
+/// Tasks traced individually
+pub fn traced() -> Vec<u32> {
+    TRACED.lock().iter().copied().collect()
+}
+
+/// Forget task `pid` (it exited), so a later task with its pid is not
+/// traced by accident
+pub fn release_task(pid: u32) {
+    if ACTIVE.load(Ordering::Relaxed) && untrace(pid) {
+        crate::klog::write(&format!("[strace] pid {} exited\n", pid));
+    }
+}
+
+/// Called by the dispatcher before a syscall runs; Some if it is traced
+pub fn begin(num: u64, args: [u64; 5]) -> Option<Call> {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return None;
+    }
+    let pid = SCHEDULER.lock().current_pid();
+    if !TRACE_ALL.load(Ordering::Relaxed) && !TRACED.lock().contains(&pid) {
+        return None;
+    }
+    let call = Call { pid, num, args };
+    if NO_RETURN.contains(&num) {
+        log(&call, None);
+    }
+    Some(call)
+}
+
+/// Called by the dispatcher with the return value of a traced syscall
+pub fn end(call: Call, ret: u64) {
+    log(&call, Some(ret));
+}
+
+/// Small values in decimal, addresses in hex
+fn format_value(value: u64) -> String {
+    if value < 0x10000 {
+        format!("{}", value)
+    } else {
+        format!("{:#x}", value)
+    }
+}
+
+fn format_ret(ret: u64) -> String {
+    match ret as i64 {
+        // Negated errnos and the legacy !0 failure
+        err @ -4095..=-1 => format!("{}", err),
+        _ => format_value(ret),
+    }
+}
+
+fn log(call: &Call, ret: Option<u64>) {
+    let (name, argc) = match SYSCALLS.get(call.num as usize) {
+        Some(&(name, argc)) => (String::from(name), argc),
+        None => (format!("syscall_{}", call.num), 5),
+    };
+    let args: Vec<String> = call.args[..argc].iter().map(|&arg| format_value(arg)).collect();
+    let ret = ret.map_or(String::from("?"), format_ret);
+    crate::klog::write(&format!("[strace] pid {} {}({}) = {}\n", call.pid, name, args.join(", "), ret));
+}
@@ -0,0 +1,117 @@
+//! Runtime tunables (sysctl)
+//!
+//! A few kernel settings can be changed while it runs. Each is a number
+//! with a range, named `kernel.<name>`, and also a file /sys/kernel/<name>
+//! holding it, so both of these work:
+//!
+//! ```text
+//! sysctl kernel.sched_timeslice_ms=50
+//! echo 50 > /sys/kernel/sched_timeslice_ms
+//! ```
+//!
+//! Only root may change them. Values are read where they take effect, so
+//! a change applies from the next use on.
+
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Level of every kernel message; the serial port shows them while
+/// `kernel.log_level` is above it
+pub const LOG_LEVEL_INFO: u64 = 6;
+
+pub struct Tunable {
+    /// "kernel.log_level"
+    pub name: &'static str,
+    pub description: &'static str,
+    pub min: u64,
+    pub max: u64,
+    value: &'static AtomicU64,
+}
+
+impl Tunable {
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Name of its file in /sys/kernel
+    pub fn file_name(&self) -> &'static str {
+        self.name.strip_prefix("kernel.").unwrap_or(self.name)
+    }
+}
+
+static LOG_LEVEL: AtomicU64 = AtomicU64::new(7);
+static TIMESLICE_MS: AtomicU64 = AtomicU64::new(10);
+static CURSOR_BLINK_MS: AtomicU64 = AtomicU64::new(500);
+static TMP_QUOTA_KB: AtomicU64 = AtomicU64::new(0);
+
+pub static TUNABLES: [Tunable; 4] = [
+    Tunable {
+        name: "kernel.log_level",
+        description: "kernel messages go to the serial port above 6; dmesg keeps them all",
+        min: 0,
+        max: 7,
+        value: &LOG_LEVEL,
+    },
+    Tunable {
+        name: "kernel.sched_timeslice_ms",
+        description: "how long a task runs before the timer switches to the next",
+        min: 10,
+        max: 1000,
+        value: &TIMESLICE_MS,
+    },
+    Tunable {
+        name: "kernel.cursor_blink_ms",
+        description: "shell cursor blink period, 0 for a steady cursor",
+        min: 0,
+        max: 5000,
+        value: &CURSOR_BLINK_MS,
+    },
+    Tunable {
+        name: "kernel.tmp_quota_kb",
+        description: "most /tmp may hold, 0 for half of the kernel heap",
+        min: 0,
+        max: 16 * 1024 * 1024,
+        value: &TMP_QUOTA_KB,
+    },
+];
+
+/// The tunable called `name`, with or without the `kernel.` prefix
+pub fn find(name: &str) -> Option<&'static Tunable> {
+    TUNABLES.iter().find(|tunable| tunable.name == name || tunable.file_name() == name)
+}
+
+/// Set tunable `name` from its text form
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    let tunable = find(name).ok_or_else(|| format!("{}: no such tunable", name))?;
+    if crate::auth::current_user_id() != 0 {
+        return Err(format!("{}: permission denied", tunable.name));
+    }
+    let value: u64 = value.trim().parse().map_err(|_| format!("{}: '{}' is not a number", tunable.name, value.trim()))?;
+    if value < tunable.min || value > tunable.max {
+        return Err(format!("{}: must be {} to {}", tunable.name, tunable.min, tunable.max));
+    }
+    tunable.value.store(value, Ordering::Relaxed);
+    crate::serial_println!("[SYSCTL] {} = {}", tunable.name, value);
+    Ok(())
+}
+
+/// Whether kernel messages go to the serial port
+pub fn log_to_serial() -> bool {
+    LOG_LEVEL.load(Ordering::Relaxed) > LOG_LEVEL_INFO
+}
+
+/// Timer ticks a task runs before it is preempted
+pub fn timeslice_ticks() -> u64 {
+    crate::timers::ms_to_ticks(TIMESLICE_MS.load(Ordering::Relaxed)).max(1)
+}
+
+/// Cursor blink period in ms; 0 when it does not blink
+pub fn cursor_blink_ms() -> u64 {
+    CURSOR_BLINK_MS.load(Ordering::Relaxed)
+}
+
+/// Quota of /tmp in KiB; 0 for the default
+pub fn tmp_quota_kb() -> u64 {
+    TMP_QUOTA_KB.load(Ordering::Relaxed)
+}
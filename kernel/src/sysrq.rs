@@ -0,0 +1,180 @@
+//! Emergency keys, usable when the shell is wedged
+//!
+//! Hold Alt+SysRq (Alt+PrintScreen) and press a command key, or send a
+//! break on COM1 followed by the key:
+//!
+//! ```text
+//! s - sync filesystems
+//! i - kill all user tasks
+//! t - dump the task list
+//! m - dump memory statistics
+//! b - reboot immediately
+//! h - list the commands
+//! ```
+//!
+//! Commands run right in the interrupt that delivered the key. They never
+//! wait on a lock (a lock that is taken is reported as busy) and never
+//! allocate; output goes straight to the COM1 registers.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::port::Port;
+use crate::task::scheduler::SCHEDULER;
+
+const COM1: u16 = 0x3F8;
+/// Line status: data ready, break received, transmitter empty
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_BREAK: u8 = 0x10;
+const LSR_THR_EMPTY: u8 = 0x20;
+
+/// A break arrived on COM1; the next byte is a command
+static SERIAL_ARMED: AtomicBool = AtomicBool::new(false);
+
+/// Polled COM1 output that bypasses the serial driver's lock
+struct RawSerial;
+
+impl Write for RawSerial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            unsafe {
+                let mut status: Port<u8> = Port::new(COM1 + 5);
+                for _ in 0..10000 {
+                    if status.read() & LSR_THR_EMPTY != 0 {
+                        break;
+                    }
+                }
+                Port::<u8>::new(COM1).write(b);
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => {{
+        let _ = writeln!(RawSerial, $($arg)*);
+    }};
+}
+
+/// Command key for a set 1 make code, if it is one
+pub fn key_for_scancode(scancode: u8) -> Option<u8> {
+    Some(match scancode {
+        0x1F => b's',
+        0x17 => b'i',
+        0x14 => b't',
+        0x32 => b'm',
+        0x30 => b'b',
+        0x23 => b'h',
+        _ => return None,
+    })
+}
+
+/// Run the command for `key`
+pub fn handle(key: u8) {
+    match key.to_ascii_lowercase() {
+        b's' => sync(),
+        b'i' => kill_all(),
+        b't' => show_tasks(),
+        b'm' => show_memory(),
+        b'b' => reboot(),
+        _ => help(),
+    }
+}
+
+/// Watch COM1 for a break; called on every timer tick
+///
+/// Bytes are only consumed after a break, so programs reading the port
+/// (YMODEM transfers) are not disturbed.
+pub fn poll_serial() {
+    let mut status: Port<u8> = Port::new(COM1 + 5);
+    let lsr = unsafe { status.read() };
+    if lsr & LSR_BREAK != 0 {
+        // The break also leaves a NUL in the receive buffer
+        if lsr & LSR_DATA_READY != 0 {
+            unsafe { Port::<u8>::new(COM1).read() };
+        }
+        SERIAL_ARMED.store(true, Ordering::Relaxed);
+        out!("\r\nSysRq: waiting for command (h for help)\r");
+        return;
+    }
+    if lsr & LSR_DATA_READY != 0 && SERIAL_ARMED.swap(false, Ordering::Relaxed) {
+        let key = unsafe { Port::<u8>::new(COM1).read() };
+        handle(key);
+    }
+}
+
+fn help() {
+    out!("\r\nSysRq: s=sync i=kill-all t=tasks m=memory b=reboot h=help\r");
+}
+
+fn sync() {
+    // The VFS keeps everything in RAM and /host is read-only
+    out!("\r\nSysRq: sync: all filesystems are memory-backed, nothing to write back\r");
+}
+
+fn kill_all() {
+    match SCHEDULER.try_lock() {
+        Some(mut scheduler) => {
+            let count = scheduler.kill_user_tasks(128 + crate::syscall::abi::SIGKILL);
+            out!("\r\nSysRq: killing {} user task(s)\r", count);
+        }
+        None => out!("\r\nSysRq: scheduler busy, try again\r"),
+    }
+}
+
+fn show_tasks() {
+    let scheduler = match SCHEDULER.try_lock() {
+        Some(scheduler) => scheduler,
+        None => {
+            out!("\r\nSysRq: scheduler busy, try again\r");
+            return;
+        }
+    };
+    out!("\r\nSysRq: {} task(s)\r", scheduler.task_count());
+    out!("  PID  PPID  STATE       TICKS  NAME\r");
+    scheduler.for_each_task(|task| {
+        let mode = if task.address_space.is_some() { "user" } else { "kernel" };
+        out!(
+            "{:>5} {:>5}  {:<8} {:>8}  {} ({}){}\r",
+            task.pid,
+            task.parent_pid.unwrap_or(0),
+            task.state.as_str(),
+            task.cpu_ticks,
+            task.name,
+            mode,
+            if task.pending_kill.is_some() { " [killed]" } else { "" },
+        );
+    });
+}
+
+fn show_memory() {
+    out!("\r\nSysRq: memory\r");
+    match crate::mem::physical::FRAME_ALLOCATOR.try_lock() {
+        Some(frames) => {
+            let (total, used, free) = frames.stats();
+            out!("  frames: {} total, {} used, {} free (4 KiB each)\r", total, used, free);
+        }
+        None => out!("  frames: allocator busy\r"),
+    }
+    match crate::mm::heap_allocator::try_stats() {
+        Some(heap) => out!(
+            "  heap:   {} KiB in use of {} KiB, peak {} KiB, {} allocs / {} frees\r",
+            heap.in_use / 1024,
+            heap.total / 1024,
+            heap.peak / 1024,
+            heap.allocations,
+            heap.frees
+        ),
+        None => out!("  heap:   allocator busy\r"),
+    }
+}
+
+fn reboot() {
+    out!("\r\nSysRq: rebooting\r");
+    // Pulse the reset line through the keyboard controller, as `power`
+    // does, but without its delay or console output
+    unsafe { Port::<u8>::new(0x64).write(0xFE) };
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,117 @@
+//! Futexes: blocking waits on a user word
+//!
+//! sys_futex_wait blocks while a 32-bit word still holds the value the
+//! caller last saw, and sys_futex_wake wakes tasks blocked on it; mutexes
+//! and condition variables are built on that in user space. Waiters are
+//! queued by the physical address of the word, so a futex in a shared
+//! memory region works across tasks.
+//!
+//! Both sides run under the scheduler lock: a wake cannot slip in between
+//! a waiter checking the word and blocking.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+use super::scheduler::{self, SCHEDULER};
+use crate::syscall::abi;
+
+/// Tasks blocked on each futex, oldest first
+static WAITERS: Mutex<BTreeMap<u64, VecDeque<u32>>> = Mutex::new(BTreeMap::new());
+
+/// Physical address of the user word at `addr` in the current task
+fn key(scheduler: &mut scheduler::Scheduler, addr: u64) -> Result<u64, u64> {
+    let space = scheduler
+        .current_task_mut()
+        .and_then(|task| task.address_space.as_ref())
+        .ok_or(abi::EINVAL)?;
+    space.translate(addr).ok_or(abi::EINVAL)
+}
+
+/// Check `addr` and write-fault its page in before the scheduler lock is
+/// taken (the fault handler needs it). The write also breaks copy-on-write
+/// sharing, so the word's physical address stays put; an atomic add of 0
+/// leaves its value alone even if another task is changing it.
+fn touch(addr: u64) -> Result<(), u64> {
+    if addr % 4 != 0 || addr > crate::mem::vmm::USER_SPACE_END - 4 {
+        return Err(abi::EINVAL);
+    }
+    unsafe { (*(addr as *const AtomicU32)).fetch_add(0, Ordering::SeqCst) };
+    Ok(())
+}
+
+fn dequeue(key: u64, pid: u32) -> bool {
+    let mut waiters = WAITERS.lock();
+    let Some(queue) = waiters.get_mut(&key) else { return false };
+    let Some(position) = queue.iter().position(|&p| p == pid) else { return false };
+    queue.remove(position);
+    if queue.is_empty() {
+        waiters.remove(&key);
+    }
+    true
+}
+
+/// Block until woken if the word at `addr` equals `expected`
+///
+/// The word must be writable. Fails with EAGAIN if it does not, and with EINTR if a signal arrives
+/// first. Wakeups may be spurious; callers re-check the word.
+pub fn wait(addr: u64, expected: u32) -> Result<(), u64> {
+    touch(addr)?;
+
+    let (key, pid) = {
+        let mut scheduler = SCHEDULER.lock();
+        let key = key(&mut scheduler, addr)?;
+        let value = unsafe { (*(addr as *const AtomicU32)).load(Ordering::SeqCst) };
+        if value != expected {
+            return Err(abi::EAGAIN);
+        }
+        let pid = scheduler.current_pid();
+        WAITERS.lock().entry(key).or_default().push_back(pid);
+        scheduler.block_current();
+        (key, pid)
+    };
+
+    loop {
+        scheduler::yield_now();
+        let mut scheduler = SCHEDULER.lock();
+        // `wake` takes us off the queue before unblocking us
+        if !WAITERS.lock().get(&key).is_some_and(|queue| queue.contains(&pid)) {
+            return Ok(());
+        }
+        if scheduler.current_task_mut().is_some_and(|task| task.signals.is_pending()) {
+            dequeue(key, pid);
+            return Err(abi::EINTR);
+        }
+        // Nothing else was ready, so the switch came straight back
+        scheduler.block_current();
+    }
+}
+
+/// Wake up to `count` tasks waiting on the word at `addr`; returns how
+/// many were woken
+pub fn wake(addr: u64, count: u32) -> Result<u32, u64> {
+    touch(addr)?;
+
+    let mut scheduler = SCHEDULER.lock();
+    let key = key(&mut scheduler, addr)?;
+    let mut waiters = WAITERS.lock();
+    let Some(queue) = waiters.get_mut(&key) else { return Ok(0) };
+    let mut woken = 0;
+    while woken < count {
+        let Some(pid) = queue.pop_front() else { break };
+        scheduler.unblock(pid);
+        woken += 1;
+    }
+    if queue.is_empty() {
+        waiters.remove(&key);
+    }
+    Ok(woken)
+}
+
+/// Drop task `pid` from every queue (it exited while waiting)
+pub fn release_task(pid: u32) {
+    WAITERS.lock().retain(|_, queue| {
+        queue.retain(|&p| p != pid);
+        !queue.is_empty()
+    });
+}
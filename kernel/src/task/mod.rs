@@ -1,10 +1,18 @@
 //! Task Management for ospabOS v0.1.0
 //! Implements preemptive multitasking with TSS and context switching
 
+use alloc::collections::BTreeMap;
 use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::fs::vfs::FileBytes;
 
+pub mod futex;
 pub mod pcb;
 pub mod scheduler;
+pub mod signal;
+pub mod switch;
 pub mod tss;
 
 use scheduler::SCHEDULER;
@@ -20,23 +28,298 @@ pub fn init() {
     crate::serial_println!("[TASK] Scheduler initialized with idle task");
 }
 
+/// Size of every kernel stack allocated by `alloc_kernel_stack`
+const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16 KB
+
+/// Allocate a kernel stack with an unmapped guard page below it and
+/// return its top
+pub fn alloc_kernel_stack() -> Option<u64> {
+    let mut vmm = crate::mem::vmm::VMM.lock();
+    vmm.as_mut()?.alloc_kernel_stack(KERNEL_STACK_SIZE).ok()
+}
+
+/// Free a stack returned by `alloc_kernel_stack`
+pub fn free_kernel_stack(top: u64) {
+    if let Some(vmm) = crate::mem::vmm::VMM.lock().as_mut() {
+        vmm.free_kernel_stack(top, KERNEL_STACK_SIZE);
+    }
+}
+
+/// Free tasks taken with `Scheduler::take_dead`: their kernel stacks, and
+/// the PCBs with whatever they still hold. Call with the scheduler lock
+/// released, as dropping a PCB can close sockets.
+pub fn release_dead(dead: Vec<alloc::boxed::Box<pcb::ProcessControlBlock>>) {
+    for task in dead {
+        if task.owns_kernel_stack {
+            free_kernel_stack(task.kernel_stack);
+        }
+    }
+}
+
 /// Spawn a new kernel task
 pub fn spawn_kernel_task(name: &str, entry: fn() -> !) -> u32 {
-    const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16 KB
-    
-    // Allocate kernel stack
-    let stack = unsafe {
-        let layout = alloc::alloc::Layout::from_size_align(KERNEL_STACK_SIZE, 16).unwrap();
-        let ptr = alloc::alloc::alloc(layout);
-        if ptr.is_null() {
-            panic!("Failed to allocate kernel stack");
-        }
-        ptr as u64 + KERNEL_STACK_SIZE as u64
-    };
+    let stack = alloc_kernel_stack().expect("Failed to allocate kernel stack");
     
     SCHEDULER.lock().spawn(
         alloc::string::String::from(name),
         entry as u64,
         stack
     )
-}
\ No newline at end of file
+}
+
+/// Executable waiting to be loaded by a task started with `spawn_user`
+struct PendingExec {
+    name: String,
+    data: FileBytes,
+    argv: Vec<String>,
+    envp: Vec<String>,
+}
+
+/// Images for tasks that have been spawned but not run yet, by pid
+static PENDING_EXEC: Mutex<BTreeMap<u32, PendingExec>> = Mutex::new(BTreeMap::new());
+
+/// Start the ELF executable in `data` as a new child of the current task
+///
+/// The child is a kernel task that loads the image on its first run, so
+/// the caller keeps running meanwhile. Collect it with `wait_child`.
+pub fn spawn_user(name: &str, data: FileBytes, argv: &[&str], envp: &[&str]) -> Result<u32, &'static str> {
+    let stack = alloc_kernel_stack().ok_or("out of kernel stacks")?;
+    let pending = PendingExec {
+        name: String::from(name),
+        data,
+        argv: argv.iter().map(|s| String::from(*s)).collect(),
+        envp: envp.iter().map(|s| String::from(*s)).collect(),
+    };
+
+    // The child cannot be scheduled before the scheduler lock is released,
+    // so its image is always queued by the time it looks for it
+    let mut scheduler = SCHEDULER.lock();
+    let pid = scheduler.spawn_child(String::from(name), exec_pending as u64, stack);
+    PENDING_EXEC.lock().insert(pid, pending);
+    Ok(pid)
+}
+
+/// Entry point of tasks started by `spawn_user`
+fn exec_pending() -> ! {
+    let pid = SCHEDULER.lock().current_pid();
+    if let Some(job) = PENDING_EXEC.lock().remove(&pid) {
+        let argv: Vec<&str> = job.argv.iter().map(|s| s.as_str()).collect();
+        let envp: Vec<&str> = job.envp.iter().map(|s| s.as_str()).collect();
+        // Only returns if the image could not be loaded
+        if let Err(e) = exec_image(&job.name, &job.data, &argv, &envp) {
+            crate::serial_println!("[TASK] {}: {}", job.name, e);
+        }
+    }
+
+    SCHEDULER.lock().terminate_current(127);
+    loop {
+        scheduler::yield_now();
+    }
+}
+
+/// Stop the current task until SIGCONT or SIGKILL makes it runnable; its
+/// parent finds out through `Scheduler::take_stopped`
+pub(crate) fn stop_current() {
+    let pid = {
+        let mut scheduler = SCHEDULER.lock();
+        let Some(task) = scheduler.current_task_mut() else { return };
+        task.state = pcb::TaskState::Stopped;
+        task.stop_reported = false;
+        task.pid
+    };
+    crate::serial_println!("[TASK] pid {} stopped", pid);
+    while SCHEDULER.lock().current_task_mut().is_some_and(|task| task.state == pcb::TaskState::Stopped) {
+        scheduler::yield_now();
+    }
+}
+
+/// Where the scheduler sends a task with a pending kill
+///
+/// Kills come from the CPU-time limit, from SysRq, from signals and from
+/// CPU exceptions in user mode.
+pub(crate) fn killed() -> ! {
+    let (pid, name, ticks, status, fault) = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current_task_mut().expect("no current task");
+        let status = current.pending_kill.unwrap_or(128 + crate::syscall::abi::SIGKILL);
+        let fault = current.fault.take();
+        if let (Some(fault), Ok(stderr)) = (&fault, current.fd_table.get_mut(2)) {
+            let _ = stderr.write(format!("{}: {}\n", current.name, fault).as_bytes());
+        }
+        (current.pid, current.name.clone(), current.cpu_ticks, status, fault)
+    };
+    if let Some(fault) = fault {
+        crate::serial_println!("[TASK] {} (pid {}): {}, exit status {}", name, pid, fault, status);
+    } else if status == 128 + crate::syscall::abi::SIGXCPU {
+        crate::serial_println!(
+            "[TASK] {} (pid {}) exceeded its CPU time limit after {} ticks, terminating",
+            name, pid, ticks
+        );
+    } else {
+        crate::serial_println!("[TASK] {} (pid {}) killed, exit status {}", name, pid, status);
+    }
+
+    SCHEDULER.lock().terminate_current(status);
+    loop {
+        scheduler::yield_now();
+    }
+}
+
+/// RLIMIT_CPU of the current task
+pub fn cpu_limit() -> crate::syscall::abi::RLimit {
+    SCHEDULER.lock()
+        .current_task_mut()
+        .map_or(crate::syscall::abi::RLimit::UNLIMITED, |task| task.cpu_limit)
+}
+
+/// Set RLIMIT_CPU of the current task
+///
+/// `cur` may not exceed `max`; only root may raise `max`.
+pub fn set_cpu_limit(limit: crate::syscall::abi::RLimit) -> Result<(), &'static str> {
+    if limit.cur > limit.max {
+        return Err("soft limit above hard limit");
+    }
+    let is_root = crate::auth::current_user_id() == 0;
+    let mut scheduler = SCHEDULER.lock();
+    let task = scheduler.current_task_mut().ok_or("no current task")?;
+    if limit.max > task.cpu_limit.max && !is_root {
+        return Err("only root may raise the hard limit");
+    }
+    task.cpu_limit = limit;
+    Ok(())
+}
+
+/// A task as reported by `list`
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    pub state: pcb::TaskState,
+    pub cpu_ticks: u64,
+    /// Runs in its own address space
+    pub user: bool,
+    /// Bytes of user memory mapped, and how many of them are shared with
+    /// other tasks; 0 for kernel tasks
+    pub resident: usize,
+    pub shared: usize,
+}
+
+/// Snapshot of every task, the running one first
+pub fn list() -> Vec<TaskInfo> {
+    let mut tasks = Vec::new();
+    SCHEDULER.lock().for_each_task(|task| {
+        let (resident, shared) = task.address_space.as_ref().map_or((0, 0), |space| space.resident_pages());
+        tasks.push(TaskInfo {
+            pid: task.pid,
+            parent: task.parent_pid,
+            name: task.name.clone(),
+            state: task.state,
+            cpu_ticks: task.cpu_ticks,
+            user: task.address_space.is_some(),
+            resident: resident * 4096,
+            shared: shared * 4096,
+        });
+    });
+    tasks
+}
+
+/// Open descriptors of task `pid` and what each refers to; None if there
+/// is no such task
+pub fn descriptors(pid: u32) -> Option<Vec<(u32, String)>> {
+    let mut scheduler = SCHEDULER.lock();
+    let task = scheduler.task_mut(pid)?;
+    Some(task.fd_table.describe())
+}
+
+/// What `wait_job` saw happen to a child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildStatus {
+    Exited(i32),
+    Stopped,
+}
+
+/// Block until the child `pid` exits and return its exit status
+///
+/// None if `pid` is not a child of the current task.
+pub fn wait_child(pid: u32) -> Option<i32> {
+    match wait(pid, true, false)? {
+        ChildStatus::Exited(status) => Some(status),
+        ChildStatus::Stopped => None,
+    }
+}
+
+/// `wait_child` for a kernel task that reads the keyboard itself: the
+/// terminal is not polled meanwhile, so it gets every key
+pub fn wait_child_raw(pid: u32) -> Option<i32> {
+    match wait(pid, false, false)? {
+        ChildStatus::Exited(status) => Some(status),
+        ChildStatus::Stopped => None,
+    }
+}
+
+/// `wait_child` that also returns when the child stops, for job control
+pub fn wait_job(pid: u32) -> Option<ChildStatus> {
+    wait(pid, true, true)
+}
+
+fn wait(pid: u32, poll_terminal: bool, stops: bool) -> Option<ChildStatus> {
+    use scheduler::WaitStatus;
+
+    loop {
+        // Ctrl+C has to reach a child that is not reading its input
+        if poll_terminal {
+            crate::services::terminal::poll();
+        }
+        let (result, dead) = {
+            let mut scheduler = SCHEDULER.lock();
+            let parent = scheduler.current_pid();
+            let result = scheduler.reap(parent, Some(pid));
+            if result == WaitStatus::Running && stops && scheduler.take_stopped(parent, Some(pid)).is_some() {
+                return Some(ChildStatus::Stopped);
+            }
+            (result, scheduler.take_dead())
+        };
+        release_dead(dead);
+        match result {
+            WaitStatus::Exited(_, status) => return Some(ChildStatus::Exited(status)),
+            WaitStatus::Running => scheduler::yield_now(),
+            WaitStatus::NoChildren => return None,
+        }
+    }
+}
+
+/// Replace the current task's image with the ELF executable in `data`,
+/// passing it `argv` and `envp`
+///
+/// The new address space is built before anything is torn down, so on
+/// error the caller keeps running its old image. On success this does not
+/// return: the old address space is freed and the task enters user mode.
+pub fn exec_image(name: &str, data: &[u8], argv: &[&str], envp: &[&str]) -> Result<(), &'static str> {
+    let load = crate::loader::elf::load_user_elf(data, argv, envp)?;
+    let cr3 = load.address_space.cr3.as_u64();
+    
+    let (pid, old_space) = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = match scheduler.current_task_mut() {
+            Some(task) => task,
+            None => {
+                load.address_space.destroy();
+                return Err("no current task");
+            }
+        };
+        unsafe { load.address_space.switch_to() };
+        current.name = alloc::string::String::from(name);
+        current.signals.exec();
+        current.user_stack = load.user_stack;
+        current.page_table = cr3;
+        (current.pid, current.address_space.replace(load.address_space))
+    };
+    if let Some(space) = old_space {
+        space.destroy();
+    }
+    // Shared memory is not carried over into the new image
+    crate::ipc::shm::release_task(pid, false);
+    
+    unsafe { crate::arch::x86_64::enter_user_mode_with_cr3(load.entry, load.user_stack, cr3) }
+}
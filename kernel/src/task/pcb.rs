@@ -9,43 +9,127 @@ pub enum TaskState {
     Running,
     Ready,
     Blocked,
+    /// Stopped by a signal until SIGCONT
+    Stopped,
     Terminated,
 }
 
+impl TaskState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskState::Running => "running",
+            TaskState::Ready => "ready",
+            TaskState::Blocked => "blocked",
+            TaskState::Stopped => "stopped",
+            TaskState::Terminated => "exiting",
+        }
+    }
+}
+
+/// A fatal user-mode fault, recorded by the exception handler in fixed
+/// fields (it runs with interrupts off and must not allocate) and turned
+/// into a message by `task::killed`
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    /// Signal the task dies of
+    pub signal: i32,
+    /// What the CPU reported, e.g. "general protection fault"
+    pub what: &'static str,
+    /// Why a page fault could not be resolved
+    pub reason: Option<&'static str>,
+    /// Faulting address, for page faults
+    pub addr: Option<u64>,
+    pub rip: u64,
+}
+
+impl core::fmt::Display for Fault {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.what)?;
+        if let Some(reason) = self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        match self.addr {
+            Some(addr) => write!(f, " at {:#x} (rip {:#x})", addr, self.rip),
+            None => write!(f, " at rip {:#x}", self.rip),
+        }
+    }
+}
+
 /// CPU context saved during task switch
+///
+/// Mirrors the stack layout built by the switch stubs in `task::switch`:
+/// general-purpose registers pushed by the stub on top of the frame the
+/// CPU pushes on interrupt entry.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct TaskContext {
-    // Callee-saved registers (as per x86_64 calling convention)
     pub r15: u64,
     pub r14: u64,
     pub r13: u64,
     pub r12: u64,
-    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
     pub rbp: u64,
-    // Return address (rip)
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    // Interrupt frame (popped by iretq)
     pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
+/// RFLAGS for a fresh task: IF set, reserved bit 1 set
+const INITIAL_RFLAGS: u64 = 0x202;
+
 impl TaskContext {
-    pub const fn new() -> Self {
+    /// Context that starts executing `entry` in ring 0 on `stack_top`
+    pub fn new_kernel(entry: u64, stack_top: u64) -> Self {
+        let selectors = crate::gdt::selectors();
         TaskContext {
             r15: 0, r14: 0, r13: 0, r12: 0,
-            rbx: 0, rbp: 0, rip: 0,
+            r11: 0, r10: 0, r9: 0, r8: 0,
+            rbp: 0, rdi: 0, rsi: 0, rdx: 0,
+            rcx: 0, rbx: 0, rax: 0,
+            rip: entry,
+            cs: selectors.kernel_code.0 as u64,
+            rflags: INITIAL_RFLAGS,
+            rsp: stack_top,
+            ss: selectors.kernel_data.0 as u64,
         }
     }
 }
 
+/// Place `context` just below `top` on a kernel stack, 16-byte aligned, and
+/// return its address (the `saved_rsp` that resumes it)
+pub unsafe fn push_context(top: u64, context: TaskContext) -> u64 {
+    let frame = (top - core::mem::size_of::<TaskContext>() as u64) & !0xF;
+    (frame as *mut TaskContext).write(context);
+    frame
+}
+
 /// Process Control Block (Task descriptor)
 pub struct ProcessControlBlock {
     pub pid: u32,
+    /// Task that forked this one; None for kernel-spawned tasks and orphans
+    pub parent_pid: Option<u32>,
     pub state: TaskState,
     pub priority: u8,
     pub name: String,
     
     // Context switching
-    pub context: TaskContext,
+    /// Kernel stack pointer at the last switch, points at a `TaskContext`
+    pub saved_rsp: u64,
     pub kernel_stack: u64,
+    /// Kernel stack came from `task::alloc_kernel_stack` and is freed on exit
+    pub owns_kernel_stack: bool,
     pub user_stack: u64,
     
     // Memory management
@@ -55,6 +139,19 @@ pub struct ProcessControlBlock {
     // File descriptors
     pub fd_table: crate::fs::fd::FdTable,
     
+    // Accounting
+    /// Timer ticks this task was running for
+    pub cpu_ticks: u64,
+    /// RLIMIT_CPU, in seconds
+    pub cpu_limit: crate::syscall::abi::RLimit,
+    /// Exit status to terminate with at the next return to user mode
+    pub pending_kill: Option<i32>,
+    /// What a fatal user-mode fault was, for `killed` to print on stderr
+    pub fault: Option<Fault>,
+    pub signals: super::signal::Signals,
+    /// The parent has been told about the current stop
+    pub stop_reported: bool,
+    
     // Linked list for scheduler
     pub next: *mut ProcessControlBlock,
 }
@@ -67,21 +164,38 @@ impl ProcessControlBlock {
     pub fn new(pid: u32, name: String, entry_point: u64, stack: u64) -> Box<Self> {
         let mut pcb = Box::new(ProcessControlBlock {
             pid,
+            parent_pid: None,
             state: TaskState::Ready,
             priority: 0,
             name,
-            context: TaskContext::new(),
+            saved_rsp: 0,
             kernel_stack: stack,
+            owns_kernel_stack: false,
             user_stack: 0,
             page_table: 0, // Use kernel page table for now
             address_space: None, // Will be set later
-            fd_table: crate::fs::fd::FdTable::with_stdio(),
+            fd_table: crate::fs::fd::FdTable::with_stdio(pid),
+            cpu_ticks: 0,
+            cpu_limit: crate::syscall::abi::RLimit::UNLIMITED,
+            pending_kill: None,
+            fault: None,
+            signals: super::signal::Signals::new(),
+            stop_reported: false,
             next: ptr::null_mut(),
         });
         
-        // Initialize context for first run
-        pcb.context.rip = entry_point;
-        pcb.context.rbp = stack;
+        // Build the initial frame so the first switch "returns" into the entry point.
+        // The idle task has no stack of its own: it is the boot context and
+        // gets its frame saved on the first switch away from it.
+        if stack != 0 {
+            // Entry sees the stack as if it had been called: a null return
+            // address just below the 16-byte aligned top
+            let entry_rsp = (stack & !0xF) - 8;
+            unsafe {
+                (entry_rsp as *mut u64).write(0);
+                pcb.saved_rsp = push_context(entry_rsp, TaskContext::new_kernel(entry_point, entry_rsp));
+            }
+        }
         
         pcb
     }
@@ -98,38 +212,3 @@ fn idle_task() -> ! {
         x86_64::instructions::hlt();
     }
 }
-
-/// Switch from old task to new task
-/// 
-/// This is called from scheduler with interrupts disabled
-/// 
-/// # Safety
-/// Must be called with valid task contexts
-#[unsafe(naked)]
-pub unsafe extern "C" fn switch_context(old: *mut TaskContext, new: *const TaskContext) {
-    core::arch::naked_asm!(
-        // Save old context (callee-saved registers)
-        "mov [rdi + 0x00], r15",
-        "mov [rdi + 0x08], r14",
-        "mov [rdi + 0x10], r13",
-        "mov [rdi + 0x18], r12",
-        "mov [rdi + 0x20], rbx",
-        "mov [rdi + 0x28], rbp",
-        
-        // Save return address (rip)
-        "mov rax, [rsp]",
-        "mov [rdi + 0x30], rax",
-        
-        // Restore new context
-        "mov r15, [rsi + 0x00]",
-        "mov r14, [rsi + 0x08]",
-        "mov r13, [rsi + 0x10]",
-        "mov r12, [rsi + 0x18]",
-        "mov rbx, [rsi + 0x20]",
-        "mov rbp, [rsi + 0x28]",
-        
-        // Jump to new task
-        "mov rax, [rsi + 0x30]",
-        "jmp rax"
-    );
-}
\ No newline at end of file
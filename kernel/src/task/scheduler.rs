@@ -1,11 +1,40 @@
 //! Preemptive Round-Robin Scheduler for ospabOS v0.1.0
+//!
+//! The timer IRQ rotates through the ready queue every tick; tasks can
+//! also give up the CPU early with `yield_now`.
 
 use super::pcb::{ProcessControlBlock, TaskState};
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::string::String;
+use alloc::vec::Vec;
 use spin::Mutex;
 
+/// Exit statuses kept for parents that never wait; the oldest are dropped
+const MAX_ZOMBIES: usize = 64;
+
+/// Terminated tasks parked until task context frees them; past this many
+/// uncollected ones, the rest are leaked
+const DEAD_SLOTS: usize = 16;
+
+/// A task that has exited but has not been waited for
+struct Zombie {
+    pid: u32,
+    parent: u32,
+    status: i32,
+}
+
+/// Outcome of `Scheduler::reap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// A child exited with this status and has been reaped
+    Exited(u32, i32),
+    /// Matching children exist but none has exited yet
+    Running,
+    /// No such child
+    NoChildren,
+}
+
 /// Global scheduler instance
 pub static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
 
@@ -21,6 +50,20 @@ pub struct Scheduler {
     
     /// Total number of tasks
     task_count: usize,
+    
+    /// Page table used by tasks without an address space of their own
+    kernel_cr3: u64,
+    
+    /// Exited tasks waiting to be reaped by their parent
+    zombies: VecDeque<Zombie>,
+    
+    /// Terminated tasks. The switch away from one runs in the timer IRQ,
+    /// where neither its kernel stack (still in use) nor the PCB (the heap,
+    /// its sockets) can be freed, so they are handed back by `take_dead`.
+    dead: [Option<Box<ProcessControlBlock>>; DEAD_SLOTS],
+    
+    /// Ticks the current task has run since it was switched in
+    slice_ticks: u64,
 }
 
 impl Scheduler {
@@ -30,12 +73,25 @@ impl Scheduler {
             ready_queue: VecDeque::new(),
             next_pid: 1,
             task_count: 0,
+            kernel_cr3: 0,
+            zombies: VecDeque::new(),
+            dead: [const { None }; DEAD_SLOTS],
+            slice_ticks: 0,
         }
     }
     
     /// Initialize scheduler with idle task
+    ///
+    /// The idle task is the boot context that calls this; its registers are
+    /// saved the first time another task is switched in.
     pub fn init(&mut self) {
-        let idle = ProcessControlBlock::new_idle();
+        let (frame, _) = x86_64::registers::control::Cr3::read();
+        self.kernel_cr3 = frame.start_address().as_u64();
+        
+        let mut idle = ProcessControlBlock::new_idle();
+        idle.state = TaskState::Running;
+        idle.page_table = self.kernel_cr3;
+        idle.kernel_stack = crate::gdt::kernel_stack().as_u64();
         self.current = Some(idle);
         self.task_count = 1;
     }
@@ -52,83 +108,310 @@ impl Scheduler {
         pid
     }
     
-    /// Schedule next task (called from timer interrupt)
-    pub fn schedule(&mut self) {
-        // If no current task, just pick from queue
-        if self.current.is_none() {
-            if let Some(next) = self.ready_queue.pop_front() {
-                self.current = Some(next);
-            }
-            return;
+    /// Spawn a task as a child of the current one, to be collected with `reap`
+    ///
+    /// `stack` must come from `task::alloc_kernel_stack`; it is freed when
+    /// the child exits.
+    pub fn spawn_child(&mut self, name: String, entry: u64, stack: u64) -> u32 {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        
+        let mut task = ProcessControlBlock::new(pid, name, entry, stack);
+        task.parent_pid = Some(self.current_pid());
+        task.owns_kernel_stack = true;
+        if let Some(parent) = &self.current {
+            task.cpu_limit = parent.cpu_limit;
         }
+        self.ready_queue.push_back(task);
+        self.task_count += 1;
+        
+        pid
+    }
+    
+    /// Pick the next task to run (called from the switch stubs)
+    ///
+    /// `rsp` points at the interrupted task's saved `TaskContext`. Returns
+    /// the stack pointer of the context to resume, which is `rsp` itself
+    /// when nothing else is ready.
+    pub fn schedule(&mut self, rsp: u64) -> u64 {
+        let mut current = match self.current.take() {
+            Some(task) => task,
+            None => return rsp,
+        };
+        current.saved_rsp = rsp;
+        self.slice_ticks = 0;
         
-        // Move current to back of queue if it's still ready
-        let current = self.current.take().unwrap();
+        // Blocked and stopped tasks stay queued but are skipped until woken
+        let next_pos = self.ready_queue.iter()
+            .position(|task| task.state == TaskState::Ready);
+        let mut next = match next_pos.and_then(|pos| self.ready_queue.remove(pos)) {
+            Some(task) => task,
+            None if current.state != TaskState::Terminated => {
+                // Nothing else to run, keep going
+                self.current = Some(current);
+                return rsp;
+            }
+            None => panic!("scheduler: last task terminated"),
+        };
         
-        let should_requeue = match current.state {
-            TaskState::Running => {
-                // Still running, requeue
-                true
-            },
+        match current.state {
             TaskState::Terminated => {
-                // Drop the task
                 self.task_count -= 1;
-                false
-            },
-            TaskState::Blocked => {
-                // TODO: Keep in separate blocked list
-                true
-            },
-            _ => true,
+                // Moving the box does not allocate; with every slot taken
+                // it is leaked rather than dropped here
+                match self.dead.iter_mut().find(|slot| slot.is_none()) {
+                    Some(slot) => *slot = Some(current),
+                    None => core::mem::forget(current),
+                }
+            }
+            TaskState::Blocked | TaskState::Stopped => self.ready_queue.push_back(current),
+            _ => {
+                current.state = TaskState::Ready;
+                self.ready_queue.push_back(current);
+            }
+        }
+        
+        next.state = TaskState::Running;
+        
+        // Switch address space
+        let cr3 = if next.page_table != 0 { next.page_table } else { self.kernel_cr3 };
+        unsafe { load_cr3(cr3) };
+        
+        // Interrupts from ring 3 land on the task's own kernel stack
+        if next.kernel_stack != 0 {
+            super::tss::set_kernel_stack(x86_64::VirtAddr::new(next.kernel_stack));
+            crate::syscall::entry::set_kernel_stack(next.kernel_stack);
+        }
+        
+        let next_rsp = next.saved_rsp;
+        self.current = Some(next);
+        next_rsp
+    }
+    
+    /// Charge a timer tick to the current task
+    ///
+    /// A task over its CPU limit gets a pending kill with exit status
+    /// 128 + SIGXCPU.
+    fn charge_tick(&mut self) {
+        use crate::syscall::abi::{RLIM_INFINITY, SIGXCPU};
+        
+        let current = match self.current.as_deref_mut() {
+            Some(task) => task,
+            None => return,
         };
+        current.cpu_ticks += 1;
+        
+        // The boot context hosts the kernel shell and cannot be killed
+        let limit = current.cpu_limit.cur;
+        if limit == RLIM_INFINITY || current.pid == 0 {
+            return;
+        }
+        let hz = crate::drivers::timer::TICKS_PER_SECOND;
+        if current.cpu_ticks >= limit.saturating_mul(hz) {
+            current.pending_kill.get_or_insert(128 + SIGXCPU);
+        }
+    }
+    
+    /// Send the task about to resume (`rsp` is its saved context) to
+    /// `task::killed` if a kill is pending for it, or to its handler for
+    /// the next pending signal
+    ///
+    /// That only happens when it was stopped in user mode: in the kernel it
+    /// may hold locks the exit path needs, so it is caught at a later
+    /// switch (or on its way out of the syscall) instead.
+    fn deliver_signals(&mut self, rsp: u64) {
+        use super::pcb::TaskContext;
+        use super::signal::Delivery;
         
-        if should_requeue {
-            // Save current task
-            let mut saved_current = current;
-            saved_current.state = TaskState::Ready;
-            self.ready_queue.push_back(saved_current);
-        }
-        
-        // Pick next task from queue
-        if let Some(mut next) = self.ready_queue.pop_front() {
-            next.state = TaskState::Running;
-            
-            // Switch to task's address space if available
-            if let Some(ref addr_space) = next.address_space {
-                unsafe {
-                    addr_space.switch_to();
+        let current = match self.current.as_deref_mut() {
+            Some(task) if task.pending_kill.is_some() || task.signals.is_pending() => task,
+            _ => return,
+        };
+        let context = unsafe { &mut *(rsp as *mut TaskContext) };
+        if context.cs & 3 != 3 {
+            return;
+        }
+        let entry = if current.pending_kill.is_some() {
+            super::killed as *const () as u64
+        } else {
+            match current.signals.take() {
+                Some(Delivery::Terminate(status)) => {
+                    current.pending_kill = Some(status);
+                    super::killed as *const () as u64
+                }
+                Some(handle) => {
+                    current.signals.set_frame(handle, *context);
+                    super::signal::handler_entry as *const () as u64
                 }
+                None => return,
+            }
+        };
+        // The user frame sits at the top of the kernel stack; iretq into
+        // the kernel entry with the stack reset to that top
+        let entry_rsp = (current.kernel_stack & !0xF) - 8;
+        *context = TaskContext::new_kernel(entry, entry_rsp);
+    }
+    
+    /// Mark every user task for termination with `status`
+    ///
+    /// Takes effect as each one next resumes in user mode; returns how many
+    /// were marked. The boot context is spared.
+    pub fn kill_user_tasks(&mut self, status: i32) -> usize {
+        let mut killed = 0;
+        let tasks = self.current.iter_mut().chain(self.ready_queue.iter_mut());
+        for task in tasks.filter(|t| t.pid != 0 && t.address_space.is_some()) {
+            if task.state != TaskState::Terminated {
+                task.pending_kill.get_or_insert(status);
+                killed += 1;
             }
-            
-            self.current = Some(next);
-            
-            // Note: Context switch would happen here in real implementation
-            // For now, we just update the scheduler state
         }
+        killed
     }
     
-    /// Yield CPU voluntarily
-    pub fn yield_task(&mut self) {
-        if let Some(current) = &mut self.current {
-            current.state = TaskState::Ready;
+    /// Call `f` for every task, the running one first
+    pub fn for_each_task(&self, mut f: impl FnMut(&ProcessControlBlock)) {
+        for task in self.current.iter().chain(self.ready_queue.iter()) {
+            f(task);
         }
-        self.schedule();
     }
     
-    /// Block current task
+    /// Mark the current task as blocked; it will not run again until
+    /// `unblock` is called. The caller should `yield_now` afterwards.
     pub fn block_current(&mut self) {
         if let Some(current) = &mut self.current {
             current.state = TaskState::Blocked;
         }
-        self.schedule();
     }
     
-    /// Terminate current task
-    pub fn terminate_current(&mut self) {
-        if let Some(current) = &mut self.current {
-            current.state = TaskState::Terminated;
+    /// Make a blocked task runnable again
+    ///
+    /// The task may still be the current one if it has not yielded yet
+    /// after `block_current`.
+    pub fn unblock(&mut self, pid: u32) {
+        if let Some(current) = self.current.as_deref_mut().filter(|t| t.pid == pid) {
+            if current.state == TaskState::Blocked {
+                current.state = TaskState::Running;
+            }
+            return;
+        }
+        if let Some(task) = self.ready_queue.iter_mut().find(|t| t.pid == pid) {
+            if task.state == TaskState::Blocked {
+                task.state = TaskState::Ready;
+            }
         }
-        self.schedule();
+    }
+    
+    /// Mark the current task as terminated; it is dropped on the next switch.
+    ///
+    /// Its address space is freed right away and `status` is kept for the
+    /// parent to collect with `reap`.
+    pub fn terminate_current(&mut self, status: i32) {
+        let kernel_cr3 = self.kernel_cr3;
+        let (pid, parent, address_space) = match &mut self.current {
+            Some(current) => {
+                current.state = TaskState::Terminated;
+                current.page_table = 0;
+                (current.pid, current.parent_pid, current.address_space.take())
+            }
+            None => return,
+        };
+        
+        // Keep running on the kernel mappings while the user pages go away
+        if let Some(space) = address_space {
+            unsafe { load_cr3(kernel_cr3) };
+            space.destroy();
+        }
+        crate::ipc::shm::release_task(pid, true);
+        super::futex::release_task(pid);
+        crate::timers::release_task(pid);
+        crate::syscall::trace::release_task(pid);
+        
+        // Orphans are not waited for by anyone
+        for task in self.ready_queue.iter_mut().filter(|t| t.parent_pid == Some(pid)) {
+            task.parent_pid = None;
+        }
+        self.zombies.retain(|z| z.parent != pid);
+        
+        if let Some(parent) = parent {
+            if self.zombies.len() >= MAX_ZOMBIES {
+                self.zombies.pop_front();
+            }
+            self.zombies.push_back(Zombie { pid, parent, status });
+        }
+    }
+    
+    /// Duplicate the current task for fork
+    ///
+    /// The child resumes from `context` on its own `kernel_stack` with a copy
+    /// of the parent's address space and descriptors. Returns its pid.
+    pub fn fork_current(
+        &mut self,
+        context: super::pcb::TaskContext,
+        kernel_stack: u64,
+    ) -> Result<u32, &'static str> {
+        let pid = self.next_pid;
+        let parent = self.current.as_ref().ok_or("no current task")?;
+        let space = parent.address_space.as_ref().ok_or("task has no address space")?;
+        let space = space.clone_user_space()?;
+        
+        let mut child = ProcessControlBlock::new(pid, parent.name.clone(), 0, 0);
+        child.parent_pid = Some(parent.pid);
+        child.priority = parent.priority;
+        child.cpu_limit = parent.cpu_limit;
+        child.signals = parent.signals.fork();
+        child.user_stack = parent.user_stack;
+        child.fd_table = parent.fd_table.fork();
+        child.page_table = space.cr3.as_u64();
+        child.address_space = Some(space);
+        child.kernel_stack = kernel_stack;
+        child.owns_kernel_stack = true;
+        child.saved_rsp = unsafe { super::pcb::push_context(kernel_stack, context) };
+        
+        crate::ipc::shm::fork(parent.pid, pid);
+        self.next_pid += 1;
+        self.ready_queue.push_back(child);
+        self.task_count += 1;
+        Ok(pid)
+    }
+    
+    /// Terminated tasks parked since the last call, for
+    /// `task::release_dead` to free outside the scheduler lock
+    pub fn take_dead(&mut self) -> Vec<Box<ProcessControlBlock>> {
+        self.dead.iter_mut().filter_map(Option::take).collect()
+    }
+    
+    /// Collect the exit status of a child of `parent` (`pid` None = any child)
+    pub fn reap(&mut self, parent: u32, pid: Option<u32>) -> WaitStatus {
+        let matches = |task_pid: u32| pid.map_or(true, |p| p == task_pid);
+        
+        if let Some(pos) = self.zombies.iter().position(|z| z.parent == parent && matches(z.pid)) {
+            let zombie = self.zombies.remove(pos).unwrap();
+            return WaitStatus::Exited(zombie.pid, zombie.status);
+        }
+        
+        let running = self.ready_queue.iter()
+            .any(|t| t.parent_pid == Some(parent) && matches(t.pid));
+        if running {
+            WaitStatus::Running
+        } else {
+            WaitStatus::NoChildren
+        }
+    }
+    
+    /// A child of `parent` (`pid` None = any child) that stopped and has
+    /// not been reported yet; each stop is reported once
+    pub fn take_stopped(&mut self, parent: u32, pid: Option<u32>) -> Option<u32> {
+        let task = self.current
+            .iter_mut()
+            .chain(self.ready_queue.iter_mut())
+            .find(|t| {
+                t.parent_pid == Some(parent)
+                    && pid.map_or(true, |p| p == t.pid)
+                    && t.state == TaskState::Stopped
+                    && !t.stop_reported
+            })?;
+        task.stop_reported = true;
+        Some(task.pid)
     }
     
     /// Get current PID
@@ -146,6 +429,15 @@ impl Scheduler {
         self.current.as_deref_mut()
     }
     
+    /// Live task with this pid, running or queued
+    pub fn task_mut(&mut self, pid: u32) -> Option<&mut ProcessControlBlock> {
+        self.current
+            .iter_mut()
+            .chain(self.ready_queue.iter_mut())
+            .map(|task| &mut **task)
+            .find(|task| task.pid == pid && task.state != TaskState::Terminated)
+    }
+    
     /// Spawn a task with its own address space
     pub fn spawn_with_address_space(
         &mut self,
@@ -175,7 +467,135 @@ impl Scheduler {
     }
 }
 
-/// Called from timer interrupt to trigger scheduling
-pub fn timer_tick() {
-    SCHEDULER.lock().schedule();
-}
\ No newline at end of file
+/// Load CR3 if it differs from the active one (avoids a needless TLB flush)
+unsafe fn load_cr3(cr3: u64) {
+    let active: u64;
+    core::arch::asm!("mov {}, cr3", out(reg) active, options(nomem, nostack, preserves_flags));
+    if cr3 != 0 && active & !0xFFF != cr3 & !0xFFF {
+        core::arch::asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+    }
+}
+
+/// Switch tasks from interrupt context
+///
+/// Called by the timer and yield stubs with the saved context of the
+/// interrupted task. Never spins on the scheduler lock: if the interrupted
+/// code holds it, the switch is simply skipped this time.
+pub fn preempt(rsp: u64) -> u64 {
+    let mut scheduler = match SCHEDULER.try_lock() {
+        Some(scheduler) => scheduler,
+        None => return rsp,
+    };
+    let next_rsp = scheduler.schedule(rsp);
+    scheduler.deliver_signals(next_rsp);
+    next_rsp
+}
+
+/// Timer switch: charge the tick to the interrupted task, then preempt it
+/// once its time slice (`kernel.sched_timeslice_ms`) is used up
+///
+/// Ticks that land while the scheduler lock is held are not charged.
+pub fn timer_tick(rsp: u64) -> u64 {
+    let mut scheduler = match SCHEDULER.try_lock() {
+        Some(scheduler) => scheduler,
+        None => return rsp,
+    };
+    scheduler.charge_tick();
+    crate::timers::expire(&mut scheduler);
+    scheduler.slice_ticks += 1;
+    if scheduler.slice_ticks < crate::sysctl::timeslice_ticks() {
+        scheduler.deliver_signals(rsp);
+        return rsp;
+    }
+    let next_rsp = scheduler.schedule(rsp);
+    scheduler.deliver_signals(next_rsp);
+    next_rsp
+}
+
+/// Give up the CPU to the next ready task
+pub fn yield_now() {
+    unsafe {
+        core::arch::asm!("int {}", const super::switch::YIELD_VECTOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    /// A scheduler whose tasks have no stacks: `schedule` only moves
+    /// them between the queue and `current`, it never runs one
+    fn scheduler() -> Scheduler {
+        let mut scheduler = Scheduler::new();
+        scheduler.init();
+        scheduler
+    }
+
+    #[test_case]
+    fn spawn_assigns_increasing_pids() {
+        let mut s = scheduler();
+        assert_eq!(s.spawn("a".to_string(), 0, 0), 1);
+        assert_eq!(s.spawn("b".to_string(), 0, 0), 2);
+        assert_eq!(s.task_count(), 3);
+        assert_eq!(s.current_pid(), 0);
+    }
+
+    #[test_case]
+    fn schedule_is_round_robin() {
+        let mut s = scheduler();
+        let a = s.spawn("a".to_string(), 0, 0);
+        let b = s.spawn("b".to_string(), 0, 0);
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            s.schedule(0x1000);
+            order.push(s.current_pid());
+        }
+        assert_eq!(order, [a, b, 0, a]);
+    }
+
+    #[test_case]
+    fn schedule_keeps_the_only_task() {
+        let mut s = scheduler();
+        assert_eq!(s.schedule(0x1234), 0x1234);
+        assert_eq!(s.current_pid(), 0);
+    }
+
+    #[test_case]
+    fn blocked_tasks_are_skipped_until_unblocked() {
+        let mut s = scheduler();
+        let a = s.spawn("a".to_string(), 0, 0);
+        let b = s.spawn("b".to_string(), 0, 0);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), a);
+        s.block_current();
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), b);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), 0);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), b);
+        s.unblock(a);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), a);
+    }
+
+    #[test_case]
+    fn terminated_children_are_reaped_once() {
+        let mut s = scheduler();
+        let child = s.spawn_child("child".to_string(), 0, 0);
+        assert_eq!(s.reap(0, None), WaitStatus::Running);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), child);
+        s.terminate_current(7);
+        s.schedule(0x1000);
+        assert_eq!(s.current_pid(), 0);
+        assert_eq!(s.task_count(), 1);
+        // The PCB is parked, not dropped, by the switch
+        assert_eq!(s.take_dead().len(), 1);
+        assert!(s.take_dead().is_empty());
+        assert_eq!(s.reap(0, Some(child)), WaitStatus::Exited(child, 7));
+        assert_eq!(s.reap(0, None), WaitStatus::NoChildren);
+    }
+}
@@ -0,0 +1,264 @@
+//! Signals
+//!
+//! Each task has a bitmap of pending signals and an action per signal.
+//! Signals are acted on just before the task runs user code again: when a
+//! switch resumes it in user mode (`Scheduler::deliver_signals`) and on the
+//! way out of every syscall. Unless a task says otherwise with
+//! sys_sigaction, a signal terminates it with exit status 128 + the signal;
+//! SIGKILL always does. SIGSTOP, and SIGTSTP by default, stop the task
+//! instead, until SIGCONT (or SIGKILL) arrives.
+//!
+//! A handler runs on the task's user stack. Delivery saves the interrupted
+//! registers below the red zone and pushes the restorer given to
+//! sys_sigaction as the return address, so returning from the handler ends
+//! in sys_sigreturn, which resumes where the signal arrived.
+
+use super::pcb::TaskContext;
+use super::scheduler::SCHEDULER;
+use super::pcb::TaskState;
+use crate::syscall::abi::{self, SIGCONT, SIGKILL, SIGSEGV, SIGSTOP, SIGTSTP};
+
+/// Signals are numbered 1 to NSIG - 1
+pub const NSIG: usize = 32;
+
+/// Bytes below the user stack pointer that leaf functions may use
+const RED_ZONE: u64 = 128;
+
+/// What a signal does to the task receiving it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigAction {
+    /// Terminate
+    Default,
+    Ignore,
+    /// Call `entry(sig)`, which returns into `restorer`
+    Handler { entry: u64, restorer: u64 },
+}
+
+/// What to do with a task about to run user code
+pub enum Delivery {
+    /// Terminate with this exit status
+    Terminate(i32),
+    /// Stop until continued
+    Stop,
+    /// Run a handler
+    Handle { sig: u32, entry: u64, restorer: u64 },
+}
+
+/// Signal state of one task
+pub struct Signals {
+    pending: u32,
+    actions: [SigAction; NSIG],
+    /// Handler the scheduler picked, with the user context it interrupted
+    frame: Option<(Delivery, TaskContext)>,
+}
+
+impl Signals {
+    pub const fn new() -> Self {
+        Signals { pending: 0, actions: [SigAction::Default; NSIG], frame: None }
+    }
+
+    /// Mark `sig` pending; ignored signals are dropped right away
+    pub fn raise(&mut self, sig: u32) {
+        if self.actions[sig as usize] != SigAction::Ignore {
+            self.pending |= 1 << sig;
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending != 0
+    }
+
+    pub fn set_action(&mut self, sig: u32, action: SigAction) {
+        self.actions[sig as usize] = action;
+        if action == SigAction::Ignore {
+            self.pending &= !(1 << sig);
+        }
+    }
+
+    /// Take the next pending signal, SIGKILL first, then by number
+    pub fn take(&mut self) -> Option<Delivery> {
+        while self.pending != 0 {
+            let sig = if self.pending & (1 << SIGKILL) != 0 {
+                SIGKILL as u32
+            } else {
+                self.pending.trailing_zeros()
+            };
+            self.pending &= !(1 << sig);
+            return Some(match (sig as i32, self.actions[sig as usize]) {
+                (SIGSTOP, _) | (SIGTSTP, SigAction::Default) => Delivery::Stop,
+                // Continuing happened when it was sent
+                (SIGCONT, SigAction::Default) => continue,
+                (_, SigAction::Handler { entry, restorer }) => Delivery::Handle { sig, entry, restorer },
+                _ => Delivery::Terminate(128 + sig as i32),
+            });
+        }
+        None
+    }
+
+    /// State of a forked child: same actions, nothing pending
+    pub fn fork(&self) -> Self {
+        Signals { pending: 0, actions: self.actions, frame: None }
+    }
+
+    /// Handlers point into the old image; exec resets them to the default
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let SigAction::Handler { .. } = action {
+                *action = SigAction::Default;
+            }
+        }
+    }
+
+    /// Keep the handler the scheduler picked until `handler_entry` runs it
+    pub fn set_frame(&mut self, delivery: Delivery, context: TaskContext) {
+        self.frame = Some((delivery, context));
+    }
+}
+
+/// Send `sig` to task `pid`; a blocked task is woken so it can act on it
+pub fn send(pid: u32, sig: u32) -> Result<(), u64> {
+    if sig as usize >= NSIG {
+        return Err(abi::EINVAL);
+    }
+    // The boot context hosts the kernel shell and takes no signals
+    if pid == 0 {
+        return Err(abi::EPERM);
+    }
+    let mut scheduler = SCHEDULER.lock();
+    let task = scheduler.task_mut(pid).ok_or(abi::ESRCH)?;
+    if sig == 0 {
+        return Ok(());
+    }
+    task.signals.raise(sig);
+    match sig as i32 {
+        // A stop still pending is cancelled, a stopped task runs again
+        SIGCONT => {
+            task.signals.pending &= !(1 << SIGSTOP | 1 << SIGTSTP);
+            if task.state == TaskState::Stopped {
+                task.state = TaskState::Ready;
+            }
+        }
+        SIGKILL if task.state == TaskState::Stopped => task.state = TaskState::Ready,
+        _ => {}
+    }
+    scheduler.unblock(pid);
+    Ok(())
+}
+
+/// Whether the current task has a signal waiting; blocking syscalls give
+/// up with EINTR when it does
+pub fn interrupted() -> bool {
+    SCHEDULER.lock().current_task_mut().is_some_and(|task| task.signals.is_pending())
+}
+
+/// Called with the return value of every syscall before going back to
+/// user mode. Returns if no signal is pending; otherwise terminates the
+/// task or enters its handler.
+pub fn on_syscall_exit(ret: u64) {
+    let delivery = {
+        let mut scheduler = SCHEDULER.lock();
+        match scheduler.current_task_mut() {
+            Some(task) if task.address_space.is_some() => task.signals.take(),
+            _ => None,
+        }
+    };
+    let Some(delivery) = delivery else { return };
+
+    let frame = crate::syscall::entry::current_frame();
+    let selectors = crate::gdt::selectors();
+    let context = TaskContext {
+        r15: frame.r15, r14: frame.r14, r13: frame.r13, r12: frame.r12,
+        r11: frame.r11, r10: frame.r10, r9: frame.r9, r8: frame.r8,
+        rbp: frame.rbp, rdi: frame.rdi, rsi: frame.rsi, rdx: frame.rdx,
+        rcx: frame.rcx, rbx: frame.rbx, rax: ret,
+        rip: frame.rcx,
+        cs: (selectors.user_code.0 | 3) as u64,
+        rflags: frame.r11 | 0x200,
+        rsp: frame.user_rsp,
+        ss: (selectors.user_data.0 | 3) as u64,
+    };
+    deliver(delivery, context)
+}
+
+/// Where the scheduler sends a task whose pending signal has a handler
+pub(crate) fn handler_entry() -> ! {
+    let frame = SCHEDULER
+        .lock()
+        .current_task_mut()
+        .and_then(|task| task.signals.frame.take());
+    match frame {
+        Some((delivery, context)) => deliver(delivery, context),
+        None => super::killed(),
+    }
+}
+
+fn deliver(delivery: Delivery, context: TaskContext) -> ! {
+    let (sig, entry, restorer) = match delivery {
+        Delivery::Terminate(status) => terminate(status),
+        Delivery::Stop => {
+            super::stop_current();
+            // Continued: act on whatever arrived meanwhile, then carry on
+            let next = SCHEDULER.lock().current_task_mut().and_then(|task| task.signals.take());
+            match next {
+                Some(delivery) => deliver(delivery, context),
+                None => unsafe { super::switch::resume(&context) },
+            }
+        }
+        Delivery::Handle { sig, entry, restorer } => (sig, entry, restorer),
+    };
+
+    // [restorer][saved context], 16-byte aligned at the context so the
+    // handler starts with the stack as after a call
+    let size = core::mem::size_of::<TaskContext>() as u64;
+    let saved = (context.rsp - RED_ZONE - size) & !0xF;
+    let sp = saved - 8;
+    crate::syscall::fault_in(sp as *mut u8, (size + 8) as usize, true);
+    unsafe {
+        (saved as *mut TaskContext).write(context);
+        (sp as *mut u64).write(restorer);
+    }
+
+    let mut handler = context;
+    handler.rip = entry;
+    handler.rsp = sp;
+    handler.rdi = sig as u64;
+    // Clear DF as the ABI expects on function entry, and TF
+    handler.rflags &= !0x500;
+    unsafe { super::switch::resume(&handler) }
+}
+
+/// sys_sigreturn: resume the context saved below the restorer's stack
+pub fn sigreturn() -> ! {
+    let frame = crate::syscall::entry::current_frame();
+    let saved = frame.user_rsp;
+    let size = core::mem::size_of::<TaskContext>();
+    if saved & 0xF != 0 || saved > crate::mem::vmm::USER_SPACE_END - size as u64 {
+        terminate(128 + SIGSEGV);
+    }
+    crate::syscall::fault_in(saved as *mut u8, size, false);
+    let mut context = unsafe { (saved as *const TaskContext).read() };
+
+    // Only the user-visible state is taken from user memory
+    if context.rip > crate::mem::vmm::USER_SPACE_END || context.rsp > crate::mem::vmm::USER_SPACE_END {
+        terminate(128 + SIGSEGV);
+    }
+    let selectors = crate::gdt::selectors();
+    context.cs = (selectors.user_code.0 | 3) as u64;
+    context.ss = (selectors.user_data.0 | 3) as u64;
+    // Arithmetic flags, TF and DF; IF always on
+    context.rflags = (context.rflags & 0xDD5) | 0x202;
+
+    // Another signal may have arrived while the handler ran
+    let next = SCHEDULER.lock().current_task_mut().and_then(|task| task.signals.take());
+    match next {
+        Some(delivery) => deliver(delivery, context),
+        None => unsafe { super::switch::resume(&context) },
+    }
+}
+
+fn terminate(status: i32) -> ! {
+    if let Some(task) = SCHEDULER.lock().current_task_mut() {
+        task.pending_kill.get_or_insert(status);
+    }
+    super::killed()
+}
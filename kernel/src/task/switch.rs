@@ -0,0 +1,106 @@
+//! Context switch entry points for ospabOS v0.1.0
+//!
+//! Every switch happens on an interrupt frame. The stubs push the
+//! general-purpose registers on top of the frame the CPU pushed, hand the
+//! resulting stack pointer (a `TaskContext`) to the scheduler and resume
+//! from whatever stack pointer it returns. RIP, RSP and RFLAGS travel in
+//! the iret frame; CR3 is switched by the scheduler.
+
+use core::arch::naked_asm;
+
+/// Software interrupt used for voluntary yields
+pub const YIELD_VECTOR: u8 = 0x81;
+
+/// Generate an interrupt entry that saves all registers, calls
+/// `$handler(rsp) -> rsp` and restores the (possibly different) task.
+macro_rules! switch_entry {
+    ($name:ident, $handler:path) => {
+        #[unsafe(naked)]
+        pub unsafe extern "C" fn $name() -> ! {
+            naked_asm!(
+                "push rax",
+                "push rbx",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push rbp",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+                // The CPU aligned the stack before its 5-slot frame, so after
+                // 15 more pushes it is 16-byte aligned again for the call
+                "mov rdi, rsp",
+                "cld",
+                "call {handler}",
+                "mov rsp, rax",
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rbp",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rbx",
+                "pop rax",
+                "iretq",
+                handler = sym $handler,
+            )
+        }
+    };
+}
+
+switch_entry!(timer_entry, timer_switch);
+switch_entry!(yield_entry, yield_switch);
+
+extern "C" fn timer_switch(rsp: u64) -> u64 {
+    crate::irq::run(0);
+    crate::profile::sample(unsafe { &*(rsp as *const super::pcb::TaskContext) });
+    crate::sysrq::poll_serial();
+    // Acknowledge before switching: the next task may run for a whole slice
+    crate::interrupts::notify_end_of_interrupt(0);
+    super::scheduler::timer_tick(rsp)
+}
+
+extern "C" fn yield_switch(rsp: u64) -> u64 {
+    super::scheduler::preempt(rsp)
+}
+
+/// Load `context` and iretq into it, abandoning the current stack
+///
+/// Used to enter signal handlers and return from them, where the task
+/// continues somewhere other than where its kernel entry came from.
+#[unsafe(naked)]
+pub unsafe extern "C" fn resume(context: *const super::pcb::TaskContext) -> ! {
+    naked_asm!(
+        "cli",
+        "mov rsp, rdi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "iretq",
+    )
+}
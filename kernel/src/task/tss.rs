@@ -37,6 +37,8 @@ pub fn get_tss() -> &'static TaskStateSegment {
 /// Set kernel stack for current task (called during context switch)
 pub fn set_kernel_stack(stack_top: VirtAddr) {
     TSS.lock().privilege_stack_table[0] = stack_top;
+    // The TSS actually loaded into TR is the one owned by the GDT
+    crate::gdt::set_kernel_stack(stack_top);
 }
 
 use core::ops::Deref;
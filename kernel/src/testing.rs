@@ -0,0 +1,96 @@
+//! Kernel tests, run under QEMU
+//!
+//! `cargo test` builds the library, and each file in tests/, as a kernel
+//! of its own whose `#[test_case]` functions `test_runner` calls in turn.
+//! The runner in .cargo/config.toml (scripts/qemu-test.sh) boots it in
+//! QEMU with an isa-debug-exit device at port 0xF4; results are printed
+//! on COM1 and the outcome written to that port, which stops QEMU with
+//! exit status `(code << 1) | 1`: 33 when everything passed, 35 when a
+//! test panicked.
+//!
+//! ```text
+//! cargo +nightly test -Z build-std=core,alloc --target x86_64-ospab.json
+//! ```
+
+use alloc::format;
+use core::panic::PanicInfo;
+
+/// Port of QEMU's isa-debug-exit device
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+
+/// What to tell QEMU when the tests are over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Stop QEMU with `code`; outside QEMU, halt
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    unsafe {
+        x86_64::instructions::port::Port::<u32>::new(DEBUG_EXIT_PORT).write(code as u32);
+    }
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// A test the runner can call: any `fn()`, reported by its path
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        crate::serial_print!("{} ... ", core::any::type_name::<T>());
+        self();
+        crate::serial_println!("ok");
+    }
+}
+
+/// Bring up what the tests need: descriptor tables, serial output, the
+/// heap, the frame allocator and the VMM. No timer, tasks or services.
+pub fn init() {
+    crate::gdt::init();
+    crate::interrupts::init_idt();
+    crate::drivers::serial::init();
+    crate::mm::init();
+    crate::arch::x86_64::cpu::init();
+    crate::random::init();
+    crate::mem::physical::init();
+    if let Err(e) = crate::mem::init_vmm() {
+        crate::serial_println!("[TEST] VMM not available: {}", e);
+    }
+}
+
+/// Run every test, then stop QEMU; a failing test panics into
+/// `panic_handler` instead
+pub fn test_runner(tests: &[&dyn Testable]) -> ! {
+    crate::serial_println!("running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    crate::serial_println!("test result: ok. {} passed", tests.len());
+    exit_qemu(QemuExitCode::Success)
+}
+
+/// Panic handler for test kernels: report the failure and stop QEMU
+pub fn panic_handler(info: &PanicInfo) -> ! {
+    crate::serial_println!("FAILED\n\n{}\n", info);
+    exit_qemu(QemuExitCode::Failed)
+}
+
+#[cfg(test)]
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init();
+    crate::test_main();
+    exit_qemu(QemuExitCode::Success)
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    panic_handler(info)
+}
@@ -0,0 +1,229 @@
+//! Kernel timers
+//!
+//! A timer wheel indexed by tick (`drivers::timer` jiffies). A timer
+//! either wakes a task, raises SIGALRM for `alarm`, or runs a callback.
+//! The timer interrupt expires them: wakeups and signals happen right
+//! there, under the scheduler lock it already holds, while callbacks are
+//! queued for the `ktimerd` kernel task so they can take ordinary locks.
+//!
+//! The interrupt only ever try-locks the wheel and catches up on the ticks
+//! it skipped, so task code can hold the wheel lock with interrupts on.
+//! Take the scheduler lock first when both are needed. It never allocates
+//! or frees either: timers are taken out of their slot in place, and the
+//! callback queue has a fixed capacity reserved at `init`. When the queue
+//! is full the tick is retried on the next interrupt.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::drivers::timer::{self, TICKS_PER_SECOND};
+use crate::syscall::abi;
+use crate::task::scheduler::{self, Scheduler, SCHEDULER};
+
+const SLOTS: usize = 256;
+
+/// Callbacks that can wait for ktimerd at once
+const DUE_CAPACITY: usize = 64;
+
+pub type Callback = fn(u64);
+
+/// Handle for `cancel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+enum Action {
+    Wake(u32),
+    Alarm(u32),
+    Call(Callback, u64),
+}
+
+struct Timer {
+    id: u64,
+    expires: u64,
+    action: Action,
+}
+
+struct Wheel {
+    /// Timers by `expires % SLOTS`; a slot may hold ones for later laps
+    slots: [Vec<Timer>; SLOTS],
+    next_id: u64,
+    /// Last tick whose slot was expired
+    now: u64,
+    /// Expired callbacks waiting for ktimerd; never grown past the
+    /// capacity `init` reserves
+    due: VecDeque<(Callback, u64)>,
+    /// Pending `alarm` of each task; an alarm that fired stays here until
+    /// replaced, as the interrupt must not free the entry
+    alarms: BTreeMap<u32, TimerId>,
+    daemon: Option<u32>,
+}
+
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel {
+    slots: [const { Vec::new() }; SLOTS],
+    next_id: 1,
+    now: 0,
+    due: VecDeque::new(),
+    alarms: BTreeMap::new(),
+    daemon: None,
+});
+
+impl Wheel {
+    fn add(&mut self, expires: u64, action: Action) -> TimerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        // A tick that already passed would otherwise wait a whole lap
+        let expires = expires.max(self.now + 1);
+        self.slots[expires as usize % SLOTS].push(Timer { id, expires, action });
+        TimerId(id)
+    }
+
+    fn remove(&mut self, id: TimerId) -> Option<Timer> {
+        self.slots.iter_mut().find_map(|slot| {
+            let position = slot.iter().position(|t| t.id == id.0)?;
+            Some(slot.swap_remove(position))
+        })
+    }
+}
+
+/// Start the task that runs callbacks
+pub fn init() {
+    let pid = crate::task::spawn_kernel_task("ktimerd", daemon);
+    let mut wheel = WHEEL.lock();
+    wheel.due.reserve(DUE_CAPACITY);
+    wheel.now = timer::get_jiffies();
+    wheel.daemon = Some(pid);
+}
+
+fn daemon() -> ! {
+    loop {
+        // Popped one at a time so the queue keeps its capacity
+        let next = {
+            let mut scheduler = SCHEDULER.lock();
+            let next = WHEEL.lock().due.pop_front();
+            if next.is_none() {
+                scheduler.block_current();
+            }
+            next
+        };
+        match next {
+            Some((callback, arg)) => callback(arg),
+            None => scheduler::yield_now(),
+        }
+    }
+}
+
+/// Expire timers up to the current tick; called by the timer interrupt
+pub fn expire(scheduler: &mut Scheduler) {
+    let Some(mut guard) = WHEEL.try_lock() else { return };
+    let wheel = &mut *guard;
+    let now = timer::get_jiffies();
+    while wheel.now < now {
+        let tick = wheel.now + 1;
+        let slot = &mut wheel.slots[tick as usize % SLOTS];
+        let mut stalled = false;
+        let mut i = 0;
+        while i < slot.len() {
+            let queue_full = wheel.due.len() == wheel.due.capacity();
+            if slot[i].expires > tick || (queue_full && matches!(slot[i].action, Action::Call(..))) {
+                stalled |= slot[i].expires <= tick;
+                i += 1;
+                continue;
+            }
+            match slot.swap_remove(i).action {
+                Action::Wake(pid) => scheduler.unblock(pid),
+                Action::Alarm(pid) => {
+                    if let Some(task) = scheduler.task_mut(pid) {
+                        task.signals.raise(abi::SIGALRM as u32);
+                        scheduler.unblock(pid);
+                    }
+                }
+                Action::Call(callback, arg) => wheel.due.push_back((callback, arg)),
+            }
+        }
+        if stalled {
+            // The rest of this tick's callbacks wait for room in the queue
+            break;
+        }
+        wheel.now = tick;
+    }
+    if !wheel.due.is_empty() {
+        if let Some(daemon) = wheel.daemon {
+            scheduler.unblock(daemon);
+        }
+    }
+}
+
+/// Run `callback(arg)` from ktimerd at tick `at`
+pub fn call_at(at: u64, callback: Callback, arg: u64) -> TimerId {
+    WHEEL.lock().add(at, Action::Call(callback, arg))
+}
+
+/// Run `callback(arg)` from ktimerd `ticks` from now
+pub fn call_in(ticks: u64, callback: Callback, arg: u64) -> TimerId {
+    call_at(timer::get_jiffies() + ticks, callback, arg)
+}
+
+/// Cancel a timer; false if it already fired
+pub fn cancel(id: TimerId) -> bool {
+    WHEEL.lock().remove(id).is_some()
+}
+
+/// Ticks covering at least `ms` milliseconds
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms.div_ceil(1000 / TICKS_PER_SECOND)
+}
+
+/// Block the current task for `ticks` ticks
+///
+/// Fails with EINTR if a signal arrives first.
+pub fn sleep(ticks: u64) -> Result<(), u64> {
+    if ticks == 0 {
+        scheduler::yield_now();
+        return Ok(());
+    }
+    let deadline = timer::get_jiffies() + ticks;
+    loop {
+        let id = {
+            let mut scheduler = SCHEDULER.lock();
+            let pid = scheduler.current_pid();
+            let id = WHEEL.lock().add(deadline, Action::Wake(pid));
+            scheduler.block_current();
+            id
+        };
+        scheduler::yield_now();
+        // Woken early by a signal, or nothing else was ready to run
+        cancel(id);
+        if timer::get_jiffies() >= deadline {
+            return Ok(());
+        }
+        if crate::task::signal::interrupted() {
+            return Err(abi::EINTR);
+        }
+    }
+}
+
+/// Raise SIGALRM in task `pid` after `seconds`, replacing its pending
+/// alarm; 0 just cancels it. Returns the seconds the old alarm had left.
+pub fn alarm(pid: u32, seconds: u64) -> u64 {
+    let mut wheel = WHEEL.lock();
+    let remaining = match wheel.alarms.remove(&pid).and_then(|id| wheel.remove(id)) {
+        Some(old) => old.expires.saturating_sub(wheel.now).div_ceil(TICKS_PER_SECOND),
+        None => 0,
+    };
+    if seconds > 0 {
+        let expires = wheel.now + seconds.saturating_mul(TICKS_PER_SECOND);
+        let id = wheel.add(expires, Action::Alarm(pid));
+        wheel.alarms.insert(pid, id);
+    }
+    remaining
+}
+
+/// Drop every timer of task `pid` (it exited)
+pub fn release_task(pid: u32) {
+    let mut wheel = WHEEL.lock();
+    wheel.alarms.remove(&pid);
+    for slot in wheel.slots.iter_mut() {
+        slot.retain(|t| !matches!(t.action, Action::Wake(p) | Action::Alarm(p) if p == pid));
+    }
+}
@@ -0,0 +1,67 @@
+//! A kernel that only boots: the heap, the frame allocator and the VMM
+//! work once `testing::init` has run, without the rest of `_start`
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ospab_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use ospab_os::mem::physical;
+use ospab_os::testing::{self, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    testing::init();
+    test_main();
+    testing::exit_qemu(QemuExitCode::Success)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::panic_handler(info)
+}
+
+#[test_case]
+fn heap_allocations_keep_their_contents() {
+    let boxed = Box::new(41u64);
+    let vec: Vec<u64> = (0..1000).collect();
+    assert_eq!(*boxed + 1, 42);
+    assert_eq!(vec.iter().sum::<u64>(), 999 * 1000 / 2);
+}
+
+#[test_case]
+fn freed_heap_memory_is_reused() {
+    // Far more than the heap holds if nothing were freed
+    for i in 0..100_000u64 {
+        let block = Box::new([i; 64]);
+        assert_eq!(block[63], i);
+    }
+}
+
+#[test_case]
+fn frames_are_page_aligned_and_distinct() {
+    let a = physical::allocate_page().expect("no free frame");
+    let b = physical::allocate_page().expect("no free frame");
+    assert_eq!(a % 4096, 0);
+    assert_eq!(b % 4096, 0);
+    assert_ne!(a, b);
+    physical::free_page(a);
+    physical::free_page(b);
+}
+
+#[test_case]
+fn kernel_stacks_are_mapped_and_writable() {
+    let top = ospab_os::task::alloc_kernel_stack().expect("no kernel stack");
+    let word = (top - 8) as *mut u64;
+    unsafe {
+        word.write_volatile(0xDEAD_BEEF);
+        assert_eq!(word.read_volatile(), 0xDEAD_BEEF);
+    }
+    ospab_os::task::free_kernel_stack(top);
+}
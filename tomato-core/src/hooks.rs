@@ -0,0 +1,90 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Run after a package's files are in place
+pub const POSTINST: &str = "postinst";
+/// Run before a package's files are deleted
+pub const PRERM: &str = "prerm";
+
+/// Commands hooks may run when there is no allowlist file
+pub const DEFAULT_ALLOWED: &[&str] = &["echo", "mkdir", "touch", "cp", "ln"];
+
+/// The allowlist file (/etc/tomato/hooks.allow): one command name per line,
+/// `#` comments
+pub fn parse_allowlist(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The default allowlist, owned
+pub fn default_allowlist() -> Vec<String> {
+    DEFAULT_ALLOWED.iter().map(|name| name.to_string()).collect()
+}
+
+/// The commands of hook `script`, each split into words, if every one is
+/// allowed.
+///
+/// A hook is one simple command per line (`#` comments); words are split
+/// at spaces, and '...' or "..." quote them. Anything that could run a
+/// command the allowlist has not seen is refused: `;`, `&`, `|`, `<`, `>`,
+/// backquotes and `$(` outside quotes, and a command word that is not
+/// exactly one of `allowed`.
+pub fn check_script(script: &str, allowed: &[String]) -> Result<Vec<Vec<String>>, String> {
+    let mut commands = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = split_words(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        let command = &words[0];
+        if !allowed.iter().any(|name| name == command) {
+            return Err(format!("line {}: {} is not allowed in hooks", number + 1, command));
+        }
+        commands.push(words);
+    }
+    Ok(commands)
+}
+
+/// Split `line` into words, refusing shell syntax
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(inner) => word.push(inner),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+            }
+            ';' | '&' | '|' | '<' | '>' | '`' => return Err(format!("{} is not allowed in hooks", c)),
+            '$' if chars.peek() == Some(&'(') => return Err("$( is not allowed in hooks".to_string()),
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(core::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
@@ -0,0 +1,86 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// An installed package as recorded in packages.txt, one per line:
+///
+/// ```text
+/// hello=1.2: libgreet base
+/// libgreet=0.3 auto: base
+/// base
+/// ```
+///
+/// The version follows `=` and may be missing in older databases. `auto` marks packages pulled in only as dependencies, which
+/// autoremove may delete once nothing needs them. A bare name (the
+/// original format) is an explicit install with no recorded deps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Installed {
+    pub name: String,
+    /// Empty if not recorded
+    pub version: String,
+    pub deps: Vec<String>,
+    pub auto: bool,
+}
+
+pub fn parse_installed(content: &str) -> Vec<Installed> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (head, deps) = line.split_once(':').unwrap_or((line, ""));
+            let mut words = head.split_whitespace();
+            let first = words.next().unwrap_or_default();
+            let (name, version) = first.split_once('=').unwrap_or((first, ""));
+            Installed {
+                name: name.to_string(),
+                version: version.to_string(),
+                deps: deps.split_whitespace().map(String::from).collect(),
+                auto: words.any(|flag| flag == "auto"),
+            }
+        })
+        .collect()
+}
+
+pub fn serialize_installed(packages: &[Installed]) -> String {
+    packages
+        .iter()
+        .map(|package| {
+            let mut line = package.name.clone();
+            if !package.version.is_empty() {
+                line = format!("{}={}", line, package.version);
+            }
+            if package.auto {
+                line.push_str(" auto");
+            }
+            if !package.deps.is_empty() {
+                line = format!("{}: {}", line, package.deps.join(" "));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Installed packages that depend on `package`
+pub fn dependents(packages: &[Installed], package: &str) -> Vec<String> {
+    packages
+        .iter()
+        .filter(|p| p.deps.iter().any(|dep| dep == package))
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+/// Automatically installed packages nothing installed needs any more,
+/// including ones only needed by other orphans
+pub fn orphans(packages: &[Installed]) -> Vec<String> {
+    let mut kept: Vec<&Installed> = packages.iter().collect();
+    let mut orphaned = Vec::new();
+    while let Some(position) = kept
+        .iter()
+        .position(|p| p.auto && !kept.iter().any(|other| other.deps.contains(&p.name)))
+    {
+        orphaned.push(kept.remove(position).name.clone());
+    }
+    orphaned
+}
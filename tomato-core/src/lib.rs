@@ -0,0 +1,16 @@
+//! The parts of tomato that need no operating system: the TOML reader,
+//! the dependency resolver, version comparison, the installed-package
+//! records, the hook checks and where everything lives.
+//!
+//! Built without std so that both tomato-pm and the kernel's tomato
+//! (kernel/src/apps/tomato) use it; stick to `alloc` types here.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod hooks;
+pub mod installed;
+pub mod paths;
+pub mod solver;
+pub mod toml;
+pub mod version;
@@ -0,0 +1,32 @@
+// Where tomato keeps things on a running system. tomato-pm puts its root
+// directory in front of each; the kernel's tomato uses them as they are.
+
+/// The database: index, archives, installed packages and journal
+pub const DB_DIR: &str = "/var/lib/tomato";
+/// The repository index
+pub const INDEX: &str = "/var/lib/tomato/available.toml";
+/// Installed packages, see `installed`
+pub const INSTALLED: &str = "/var/lib/tomato/packages.txt";
+/// Downloaded package archives
+pub const ARCHIVE_DIR: &str = "/var/lib/tomato/packages";
+/// `<name>.list`: the files each package put down
+pub const FILES_DIR: &str = "/var/lib/tomato/files";
+/// `<name>.<hook>`: the hook scripts of installed packages
+pub const SCRIPTS_DIR: &str = "/var/lib/tomato/scripts";
+/// Undo log of the transaction in progress
+pub const JOURNAL: &str = "/var/lib/tomato/journal";
+/// What transactions noted, e.g. failed hooks
+pub const JOURNAL_LOG: &str = "/var/lib/tomato/journal.log";
+/// Configuration
+pub const CONFIG_DIR: &str = "/etc/tomato";
+/// Where `update` fetches the index from by default
+pub const SOURCE: &str = "/etc/tomato/source";
+/// Commands hooks may run, see `hooks::parse_allowlist`
+pub const HOOKS_ALLOW: &str = "/etc/tomato/hooks.allow";
+/// Keys package signatures are checked against
+pub const TRUSTED_KEYS: &str = "/etc/tomato/trusted_keys";
+/// Where package contents are unpacked
+pub const PREFIX: &str = "/usr";
+
+/// Directories a fresh system starts with
+pub const DIRS: &[&str] = &[DB_DIR, ARCHIVE_DIR, FILES_DIR, SCRIPTS_DIR, CONFIG_DIR];
@@ -0,0 +1,155 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// What to do with suggested dependencies, the ones a package works better
+/// with but does not need
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Suggested {
+    /// Leave them out
+    #[default]
+    Skip,
+    /// Install them like required ones; a cycle through one is an error
+    Install,
+    /// Install them, but where one closes a cycle drop that edge and
+    /// install the suggested package after the one suggesting it
+    BreakCycles,
+}
+
+/// Packages to install for `package`, dependencies before the packages
+/// needing them. Every package involved must be in `available`, and the
+/// dependencies must not go round in a cycle.
+pub fn resolve_dependencies(package: &str, available: &BTreeMap<String, Vec<String>>) -> Result<Vec<String>, String> {
+    resolve(package, available, &BTreeMap::new(), Suggested::Skip)
+}
+
+/// `resolve_dependencies` with the `suggested` dependencies of each package
+/// too, as `mode` says. A missing suggested package is left out; a missing
+/// required one is an error.
+pub fn resolve(
+    package: &str,
+    available: &BTreeMap<String, Vec<String>>,
+    suggested: &BTreeMap<String, Vec<String>>,
+    mode: Suggested,
+) -> Result<Vec<String>, String> {
+    let no_suggestions = BTreeMap::new();
+    let suggested = if mode == Suggested::Skip { &no_suggestions } else { suggested };
+    let mut dropped: Vec<(String, String)> = Vec::new();
+    loop {
+        match walk(package, available, suggested, &dropped) {
+            Ok(order) => return Ok(order),
+            Err(Failure::Missing(name)) => return Err(format!("package {} not found", name)),
+            Err(Failure::Cycle(cycle)) => {
+                // cycle[i + 1] depends on cycle[i], suggested or not
+                let weak = cycle.windows(2).find(|pair| pair[1].1).map(|pair| (pair[0].0.clone(), pair[1].0.clone()));
+                match weak {
+                    Some(edge) if mode == Suggested::BreakCycles => dropped.push(edge),
+                    _ => {
+                        let names: Vec<&str> = cycle.iter().map(|(name, _)| name.as_str()).collect();
+                        return Err(format!("dependency cycle: {}", names.join(" -> ")));
+                    }
+                }
+            }
+        }
+    }
+}
+
+enum Failure {
+    Missing(String),
+    /// Packages round the cycle, starting and ending with the same one,
+    /// each with whether it was reached as a suggestion
+    Cycle(Vec<(String, bool)>),
+}
+
+/// Where a package is in the walk: on the stack (grey) or done (black)
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Colour {
+    Grey,
+    Black,
+}
+
+/// A package being visited: its dependencies, how far through them the
+/// walk is, and whether it was reached as a suggestion
+struct Frame {
+    name: String,
+    deps: Vec<(String, bool)>,
+    next: usize,
+    suggested: bool,
+}
+
+/// Depth-first post-order over `package`'s dependencies, leaving out the
+/// `dropped` edges. The targets of dropped edges are walked afterwards, as
+/// roots of their own, once what suggested them is in.
+fn walk(
+    package: &str,
+    available: &BTreeMap<String, Vec<String>>,
+    suggested: &BTreeMap<String, Vec<String>>,
+    dropped: &[(String, String)],
+) -> Result<Vec<String>, Failure> {
+    let mut colour: BTreeMap<String, Colour> = BTreeMap::new();
+    let mut order = Vec::new();
+    let mut roots = vec![package.to_string()];
+    let mut rooted = BTreeSet::new();
+
+    while let Some(root) = roots.pop() {
+        if colour.contains_key(&root) {
+            continue;
+        }
+        let mut stack = vec![frame(&root, false, available, suggested, dropped)?];
+        colour.insert(root, Colour::Grey);
+
+        while let Some(top) = stack.last_mut() {
+            let Some((dep, weak)) = top.deps.get(top.next).cloned() else {
+                let done = stack.pop().unwrap();
+                colour.insert(done.name.clone(), Colour::Black);
+                order.push(done.name);
+                continue;
+            };
+            top.next += 1;
+            match colour.get(&dep) {
+                Some(Colour::Black) => {}
+                Some(Colour::Grey) => {
+                    let start = stack.iter().position(|f| f.name == dep).unwrap();
+                    let mut cycle: Vec<(String, bool)> =
+                        stack[start..].iter().map(|f| (f.name.clone(), f.suggested)).collect();
+                    cycle.push((dep, weak));
+                    return Err(Failure::Cycle(cycle));
+                }
+                None => {
+                    if weak && !available.contains_key(&dep) {
+                        continue;
+                    }
+                    stack.push(frame(&dep, weak, available, suggested, dropped)?);
+                    colour.insert(dep, Colour::Grey);
+                }
+            }
+        }
+
+        // Suggestions cut out of cycles, now that their suggesters are in
+        for (from, to) in dropped {
+            if colour.get(from) == Some(&Colour::Black) && rooted.insert(to.clone()) {
+                roots.push(to.clone());
+            }
+        }
+    }
+    Ok(order)
+}
+
+fn frame(
+    name: &str,
+    weak: bool,
+    available: &BTreeMap<String, Vec<String>>,
+    suggested: &BTreeMap<String, Vec<String>>,
+    dropped: &[(String, String)],
+) -> Result<Frame, Failure> {
+    let required = available.get(name).ok_or_else(|| Failure::Missing(name.to_string()))?;
+    let optional = suggested.get(name).into_iter().flatten();
+    let deps = required
+        .iter()
+        .map(|dep| (dep.clone(), false))
+        .chain(optional.filter(|dep| !dropped.iter().any(|(from, to)| from == name && to == *dep)).map(|dep| (dep.clone(), true)))
+        .collect();
+    Ok(Frame { name: name.to_string(), deps, next: 0, suggested: weak })
+}
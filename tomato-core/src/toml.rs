@@ -0,0 +1,524 @@
+// The TOML tomato needs: tables ([a], [a.b], [[a]]), dotted and quoted
+// keys, basic and literal strings (also multi-line) with escapes,
+// integers, floats, booleans, arrays and inline tables. Dates and times
+// are kept as their text.
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// An array of strings as a list
+    pub fn as_str_list(&self) -> Option<Vec<String>> {
+        self.as_array()?.iter().map(|v| v.as_str().map(String::from)).collect()
+    }
+}
+
+pub fn parse_toml(content: &str) -> Result<Table, String> {
+    let mut parser = Parser { src: content, pos: 0, line: 1 };
+    let mut root = Table::new();
+    // Path of the table that key = value lines go into
+    let mut current: Vec<String> = Vec::new();
+
+    loop {
+        parser.skip_blank();
+        let Some(c) = parser.peek() else { break };
+        if c == '[' {
+            parser.bump();
+            let array = parser.eat('[');
+            parser.skip_ws();
+            let path = parser.key()?;
+            parser.skip_ws();
+            if !parser.eat(']') || (array && !parser.eat(']')) {
+                return Err(parser.error("expected ']' after table name"));
+            }
+            parser.end_of_line()?;
+            if array {
+                let (last, parent) = path.split_last().unwrap();
+                let parent = table_at(&mut root, parent).map_err(|e| parser.error(&e))?;
+                match parent.entry(last.clone()).or_insert_with(|| Value::Array(Vec::new())) {
+                    Value::Array(tables) => tables.push(Value::Table(Table::new())),
+                    _ => return Err(parser.error(&format!("{} is not an array of tables", last))),
+                }
+            } else {
+                table_at(&mut root, &path).map_err(|e| parser.error(&e))?;
+            }
+            current = path;
+        } else {
+            let line = parser.line;
+            let (key, value) = parser.key_value()?;
+            parser.end_of_line()?;
+            let table = table_at(&mut root, &current).map_err(|e| parser.error(&e))?;
+            insert(table, &key, value).map_err(|e| format!("line {}: {}", line, e))?;
+        }
+    }
+
+    Ok(root)
+}
+
+/// The table at `path` below `root`, created if missing; for an array of
+/// tables, its last element
+fn table_at<'t>(root: &'t mut Table, path: &[String]) -> Result<&'t mut Table, String> {
+    let mut table = root;
+    for part in path {
+        let value = table.entry(part.clone()).or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(t) => t,
+            Value::Array(a) => match a.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("{} is not a table", part)),
+            },
+            _ => return Err(format!("{} is not a table", part)),
+        };
+    }
+    Ok(table)
+}
+
+/// Set dotted `key` in `table`
+fn insert(table: &mut Table, key: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = key.split_last().unwrap();
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key {}", key.join(".")));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.src[self.pos..].starts_with(s)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    /// Spaces and tabs
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Whitespace, newlines and comments
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            if !(self.eat('\n') || (self.starts_with("\r\n") && self.eat('\r'))) {
+                break;
+            }
+        }
+    }
+
+    /// Nothing but a comment may follow on the line
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_ws();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.starts_with("\r\n") => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Dotted key: bare or quoted parts separated by '.'
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            let part = match self.peek() {
+                Some('"') => {
+                    self.bump();
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.bump();
+                    self.literal_string()?
+                }
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.bump();
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.src[start..self.pos].to_string()
+                }
+            };
+            parts.push(part);
+            self.skip_ws();
+            if !self.eat('.') {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(Vec<String>, Value), String> {
+        let key = self.key()?;
+        self.skip_ws();
+        if !self.eat('=') {
+            return Err(self.error(&format!("expected '=' after {}", key.join("."))));
+        }
+        self.skip_ws();
+        Ok((key, self.value()?))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.pos += 3;
+                self.multiline_string(true).map(Value::String)
+            }
+            Some('\'') if self.starts_with("'''") => {
+                self.pos += 3;
+                self.multiline_string(false).map(Value::String)
+            }
+            Some('"') => {
+                self.bump();
+                self.basic_string().map(Value::String)
+            }
+            Some('\'') => {
+                self.bump();
+                self.literal_string().map(Value::String)
+            }
+            Some('[') => {
+                self.bump();
+                self.array()
+            }
+            Some('{') => {
+                self.bump();
+                self.inline_table()
+            }
+            Some(_) => self.scalar(),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Rest of a "..." string
+    fn basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.escape()?),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Rest of a '...' string
+    fn literal_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(self.src[start..self.pos - 1].to_string()),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Rest of a """...""" (`escapes`) or '''...''' string
+    fn multiline_string(&mut self, escapes: bool) -> Result<String, String> {
+        let delimiter = if escapes { "\"\"\"" } else { "'''" };
+        // A newline right after the opening delimiter is dropped
+        if !self.eat('\n') && self.starts_with("\r\n") {
+            self.pos += 2;
+            self.line += 1;
+        }
+        let mut s = String::new();
+        loop {
+            if self.starts_with(delimiter) {
+                self.pos += 3;
+                return Ok(s);
+            }
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('\\') if escapes => {
+                    // Line-ending backslash: skip the newline and indentation
+                    if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.bump();
+                        }
+                    } else {
+                        s.push(self.escape()?);
+                    }
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// The character for the escape after a backslash
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.bump() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('e') => '\u{1b}',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ ('u' | 'U')) => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let hex = self.src.get(self.pos..self.pos + len).ok_or_else(|| self.error("short unicode escape"))?;
+                let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("bad unicode escape"))?;
+                self.pos += len;
+                char::from_u32(code).ok_or_else(|| self.error("bad unicode escape"))?
+            }
+            Some(c) => return Err(self.error(&format!("unknown escape \\{}", c))),
+            None => return Err(self.error("unterminated string")),
+        };
+        Ok(c)
+    }
+
+    /// Rest of an array; may span lines and end with a comma
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            if !self.eat(',') {
+                self.skip_blank();
+                if self.eat(']') {
+                    return Ok(Value::Array(items));
+                }
+                return Err(self.error("expected ',' or ']' in array"));
+            }
+        }
+    }
+
+    /// Rest of a { key = value, ... } table, all on one line
+    fn inline_table(&mut self) -> Result<Value, String> {
+        let mut table = Table::new();
+        self.skip_ws();
+        if self.eat('}') {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            let (key, value) = self.key_value()?;
+            insert(&mut table, &key, value).map_err(|e| self.error(&e))?;
+            self.skip_ws();
+            if self.eat('}') {
+                return Ok(Value::Table(table));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected ',' or '}' in inline table"));
+            }
+        }
+    }
+
+    /// Boolean, integer, float, or date/time (kept as text)
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_' | ':')) {
+            self.bump();
+        }
+        let token = &self.src[start..self.pos];
+        match token {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            _ => {}
+        }
+        let digits = token.replace('_', "");
+        let (sign, unsigned) = match digits.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+        };
+        let radix = match unsigned.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return i64::from_str_radix(&unsigned[2..], radix)
+                .map(|n| Value::Integer(sign * n))
+                .map_err(|_| self.error(&format!("invalid number {}", token)));
+        }
+        if let Ok(n) = digits.parse::<i64>() {
+            return Ok(Value::Integer(n));
+        }
+        let float = match unsigned {
+            "inf" => Some(f64::INFINITY),
+            "nan" => Some(f64::NAN),
+            _ if unsigned.starts_with(|c: char| c.is_ascii_digit()) => unsigned.parse::<f64>().ok(),
+            _ => None,
+        };
+        if let Some(f) = float {
+            return Ok(Value::Float(sign as f64 * f));
+        }
+        // 1979-05-27, 07:32:00, 1979-05-27T07:32:00Z
+        if token.starts_with(|c: char| c.is_ascii_digit()) && token.contains(['-', ':']) {
+            return Ok(Value::String(token.to_string()));
+        }
+        if token.is_empty() {
+            return Err(self.error("expected a value"));
+        }
+        Err(self.error(&format!("invalid value {}", token)))
+    }
+}
+
+pub fn serialize_toml(table: &Table) -> String {
+    let mut output = String::new();
+    write_table(&mut output, &mut Vec::new(), table);
+    output
+}
+
+/// Plain values of `table`, then its tables as [sections]
+fn write_table(output: &mut String, path: &mut Vec<String>, table: &Table) {
+    for (key, value) in table {
+        if !is_section(value) {
+            output.push_str(&format!("{} = {}\n", format_key(key), format_value(value)));
+        }
+    }
+    for (key, value) in table {
+        path.push(format_key(key));
+        match value {
+            Value::Table(t) => {
+                output.push_str(&format!("\n[{}]\n", path.join(".")));
+                write_table(output, path, t);
+            }
+            Value::Array(items) if is_section(value) => {
+                for item in items {
+                    output.push_str(&format!("\n[[{}]]\n", path.join(".")));
+                    if let Value::Table(t) = item {
+                        write_table(output, path, t);
+                    }
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+/// Tables and non-empty arrays of tables get their own headers
+fn is_section(value: &Value) -> bool {
+    match value {
+        Value::Table(_) => true,
+        Value::Array(items) => !items.is_empty() && items.iter().all(|v| matches!(v, Value::Table(_))),
+        _ => false,
+    }
+}
+
+fn format_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        format_string(key)
+    }
+}
+
+fn format_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format_string(s),
+        Value::Integer(n) => n.to_string(),
+        Value::Float(f) if f.is_nan() => "nan".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { "inf" } else { "-inf" }.to_string(),
+        // Keep the point so it reads back as a float
+        Value::Float(f) if *f == (*f as i64) as f64 => format!("{:.1}", f),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Array(items) => format!("[{}]", items.iter().map(format_value).collect::<Vec<_>>().join(", ")),
+        Value::Table(t) => {
+            let fields: Vec<String> = t.iter().map(|(k, v)| format!("{} = {}", format_key(k), format_value(v))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use crate::installed::Installed;
+
+/// Compare dotted versions part by part: numeric parts as numbers, others
+/// as text, a missing part as older ("1.2" < "1.2.1")
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut left = a.split(['.', '-']);
+    let mut right = b.split(['.', '-']);
+    loop {
+        let ordering = match (left.next(), right.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// An installed package with a newer version available
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    pub name: String,
+    /// Empty when the installed version was not recorded
+    pub from: String,
+    pub to: String,
+}
+
+/// Upgrades for `installed` given the version of each available package.
+/// Packages installed without a recorded version count as out of date.
+pub fn upgrades(installed: &[Installed], versions: &BTreeMap<String, String>) -> Vec<Upgrade> {
+    installed
+        .iter()
+        .filter_map(|package| {
+            let to = versions.get(&package.name).filter(|v| !v.is_empty())?;
+            let newer = package.version.is_empty()
+                || compare_versions(to, &package.version) == Ordering::Greater;
+            newer.then(|| Upgrade {
+                name: package.name.clone(),
+                from: package.version.clone(),
+                to: to.clone(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use tomato_core::solver::{resolve, resolve_dependencies, Suggested};
+
+/// Dependency lists from `name: dep dep` pairs
+fn graph(edges: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+    edges
+        .iter()
+        .map(|(name, deps)| (name.to_string(), deps.iter().map(|d| d.to_string()).collect()))
+        .collect()
+}
+
+#[test]
+fn dependencies_come_first() {
+    let available = graph(&[("app", &["lib", "base"]), ("lib", &["base"]), ("base", &[])]);
+    let order = resolve_dependencies("app", &available).unwrap();
+    assert_eq!(order, ["base", "lib", "app"]);
+}
+
+#[test]
+fn shared_dependencies_are_listed_once() {
+    let available = graph(&[("app", &["a", "b"]), ("a", &["base"]), ("b", &["base"]), ("base", &[])]);
+    let order = resolve_dependencies("app", &available).unwrap();
+    assert_eq!(order.iter().filter(|p| *p == "base").count(), 1);
+    assert_eq!(order.last().unwrap(), "app");
+}
+
+#[test]
+fn missing_package_is_named() {
+    let available = graph(&[("app", &["ghost"])]);
+    assert_eq!(resolve_dependencies("app", &available).unwrap_err(), "package ghost not found");
+    assert_eq!(resolve_dependencies("nothing", &available).unwrap_err(), "package nothing not found");
+}
+
+#[test]
+fn two_package_cycle_is_an_error() {
+    let available = graph(&[("a", &["b"]), ("b", &["a"])]);
+    assert_eq!(resolve_dependencies("a", &available).unwrap_err(), "dependency cycle: a -> b -> a");
+}
+
+#[test]
+fn cycle_path_leaves_out_the_way_in() {
+    let available = graph(&[("app", &["x"]), ("x", &["y"]), ("y", &["z"]), ("z", &["x"])]);
+    assert_eq!(resolve_dependencies("app", &available).unwrap_err(), "dependency cycle: x -> y -> z -> x");
+}
+
+#[test]
+fn self_dependency_is_a_cycle() {
+    let available = graph(&[("a", &["a"])]);
+    assert_eq!(resolve_dependencies("a", &available).unwrap_err(), "dependency cycle: a -> a");
+}
+
+#[test]
+fn suggestions_are_skipped_by_default() {
+    let available = graph(&[("app", &[]), ("extra", &[])]);
+    let suggested = graph(&[("app", &["extra"])]);
+    assert_eq!(resolve("app", &available, &suggested, Suggested::Skip).unwrap(), ["app"]);
+    assert_eq!(resolve("app", &available, &suggested, Suggested::Install).unwrap(), ["extra", "app"]);
+}
+
+#[test]
+fn missing_suggestion_is_left_out() {
+    let available = graph(&[("app", &[])]);
+    let suggested = graph(&[("app", &["ghost"])]);
+    assert_eq!(resolve("app", &available, &suggested, Suggested::Install).unwrap(), ["app"]);
+}
+
+#[test]
+fn cycle_through_a_suggestion_is_an_error_unless_broken() {
+    // editor needs plugins; plugins only suggest editor
+    let available = graph(&[("editor", &["plugins"]), ("plugins", &[])]);
+    let suggested = graph(&[("plugins", &["editor"])]);
+    assert_eq!(
+        resolve("editor", &available, &suggested, Suggested::Install).unwrap_err(),
+        "dependency cycle: editor -> plugins -> editor"
+    );
+    assert_eq!(resolve("editor", &available, &suggested, Suggested::BreakCycles).unwrap(), ["plugins", "editor"]);
+}
+
+#[test]
+fn broken_suggestion_is_installed_after_its_suggester() {
+    // app suggests docs, docs needs app
+    let available = graph(&[("app", &["base"]), ("docs", &["app"]), ("base", &[])]);
+    let suggested = graph(&[("app", &["docs"])]);
+    assert_eq!(
+        resolve("app", &available, &suggested, Suggested::BreakCycles).unwrap(),
+        ["base", "app", "docs"]
+    );
+}
+
+#[test]
+fn required_cycles_are_not_broken() {
+    let available = graph(&[("a", &["b"]), ("b", &["a"]), ("c", &[])]);
+    let suggested = graph(&[("a", &["c"])]);
+    assert_eq!(
+        resolve("a", &available, &suggested, Suggested::BreakCycles).unwrap_err(),
+        "dependency cycle: a -> b -> a"
+    );
+}
@@ -5,19 +5,52 @@ pub fn parse_command(args: &[String]) -> Result<Command, String> {
 
     match args[1].as_str() {
         "install" => {
-            if args.len() < 3 {
-                Err("install requires a package name".to_string())
-            } else {
-                Ok(Command::Install(args[2].clone()))
+            let suggested = args[2..].iter().any(|a| a == "--suggested" || a == "-s");
+            match args[2..].iter().find(|a| !a.starts_with('-')) {
+                Some(package) => Ok(Command::Install { package: package.clone(), suggested }),
+                None => Err("install requires a package name".to_string()),
             }
         }
         "remove" | "uninstall" => {
-            if args.len() < 3 {
-                Err("remove requires a package name".to_string())
-            } else {
-                Ok(Command::Remove(args[2].clone()))
+            let force = args[2..].iter().any(|a| a == "--force" || a == "-f");
+            match args[2..].iter().find(|a| !a.starts_with('-')) {
+                Some(package) => Ok(Command::Remove { package: package.clone(), force }),
+                None => Err("remove requires a package name".to_string()),
+            }
+        }
+        "autoremove" => Ok(Command::Autoremove),
+        "repair" => Ok(Command::Repair),
+        "verify" => Ok(Command::Verify(args.get(2).cloned())),
+        "pack" => {
+            let mut key = None;
+            let mut scripts = Vec::new();
+            let mut positional = Vec::new();
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                if arg == "--key" {
+                    key = rest.next().cloned();
+                } else if let Some(hook) = arg.strip_prefix("--").filter(|hook| ["postinst", "prerm"].contains(hook)) {
+                    let path = rest.next().ok_or_else(|| format!("{} requires a script file", arg))?;
+                    scripts.push((hook.to_string(), path.clone()));
+                } else {
+                    positional.push(arg);
+                }
+            }
+            match positional[..] {
+                [payload, name, version] => Ok(Command::Pack {
+                    payload: payload.clone(),
+                    name: name.clone(),
+                    version: version.clone(),
+                    key,
+                    scripts,
+                }),
+                _ => Err("pack requires <payload.tar> <name> <version> [--key <secret-key-file>] [--postinst|--prerm <script>]".to_string()),
             }
         }
+        "update" => Ok(Command::Update(args.get(2).cloned())),
+        "upgrade" => Ok(Command::Upgrade {
+            dry_run: args[2..].iter().any(|a| a == "--dry-run" || a == "-n"),
+        }),
         "list" => Ok(Command::List),
         "search" => {
             if args.len() < 3 {
@@ -32,8 +65,26 @@ pub fn parse_command(args: &[String]) -> Result<Command, String> {
 
 #[derive(Debug)]
 pub enum Command {
-    Install(String),
-    Remove(String),
+    /// `suggested` installs the packages' suggestions too, breaking any
+    /// cycle they close
+    Install { package: String, suggested: bool },
+    /// `force` removes even when installed packages depend on it
+    Remove { package: String, force: bool },
+    /// Remove automatically installed packages nothing needs
+    Autoremove,
+    /// Roll back a transaction an earlier run did not finish
+    Repair,
     List,
     Search(String),
+    /// Refresh available.toml from a URL or file, by default the one in
+    /// /etc/tomato/source
+    Update(Option<String>),
+    /// Bring installed packages up to the versions in available.toml
+    Upgrade { dry_run: bool },
+    /// Check a .tpkg file, a package's archive, or every downloaded archive
+    Verify(Option<String>),
+    /// Wrap a tar archive into `<name>-<version>.tpkg`, with the hook
+    /// `scripts` (hook, script file), signed with the hex secret key in
+    /// `key` if given
+    Pack { payload: String, name: String, version: String, key: Option<String>, scripts: Vec<(String, String)> },
 }
\ No newline at end of file
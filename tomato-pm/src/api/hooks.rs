@@ -0,0 +1,51 @@
+//! Running package hooks
+//!
+//! A package's postinst and prerm scripts (see `tomato_core::hooks` for
+//! what they may contain) are checked against the allowlist, then handed
+//! to a `HookRunner`. `Processes` runs each command as a process of its
+//! own, never through a shell, in the root tomato works on.
+
+use std::process::{Command, Stdio};
+
+/// Runs the checked commands of one hook
+pub trait HookRunner: Sync {
+    /// Run `commands` (each split into words) for `package`'s `hook`, in
+    /// order, stopping at the first that fails
+    fn run(&self, package: &str, hook: &str, commands: &[Vec<String>]) -> Result<(), String>;
+}
+
+/// Commands as processes, with a minimal environment
+pub struct Processes {
+    /// Working directory, and `TOMATO_ROOT` for the commands
+    root: String,
+}
+
+impl Processes {
+    pub fn new(root: &str) -> Self {
+        Processes { root: if root.is_empty() { "/".to_string() } else { root.to_string() } }
+    }
+}
+
+impl HookRunner for Processes {
+    fn run(&self, package: &str, hook: &str, commands: &[Vec<String>]) -> Result<(), String> {
+        for words in commands {
+            let status = Command::new(&words[0])
+                .args(&words[1..])
+                .current_dir(&self.root)
+                .env_clear()
+                .env("PATH", "/usr/bin:/bin")
+                .env("TOMATO_ROOT", &self.root)
+                .env("TOMATO_PACKAGE", package)
+                .env("TOMATO_HOOK", hook)
+                .stdin(Stdio::null())
+                .status()
+                .map_err(|e| format!("{}: {}", words[0], e))?;
+            match status.code() {
+                Some(0) => {}
+                Some(code) => return Err(format!("{} exited with status {}", words[0], code)),
+                None => return Err(format!("{} was killed", words[0])),
+            }
+        }
+        Ok(())
+    }
+}
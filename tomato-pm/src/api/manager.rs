@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use tomato_core::hooks::{check_script, default_allowlist, parse_allowlist, POSTINST, PRERM};
+use tomato_core::installed::{dependents, orphans, Installed};
+use tomato_core::solver::{resolve, resolve_dependencies, Suggested};
+use tomato_core::paths;
+use tomato_core::toml::{parse_toml, Value};
+use tomato_core::version::{upgrades, Upgrade};
+use crate::error::TomatoError;
+use crate::storage::backend::{FileStorage, Storage};
+use crate::api::hooks::{HookRunner, Processes};
+use crate::api::pipeline::{Job, Pipeline, Progress, Quiet};
+use crate::storage::disk_io::{load_trusted_keys, read_package, Journal, PackageDB, Recovery};
+use crate::storage::fetch::fetch;
+use crate::storage::tpkg::Trust;
+use crate::storage::transaction::Transaction;
+
+/// A package in available.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub version: String,
+    pub deps: Vec<String>,
+    /// Packages this one works better with, installed on request
+    pub suggests: Vec<String>,
+    /// What changed in this version, shown by upgrade
+    pub changelog: String,
+    /// Where to download the .tpkg: a URL, or a path relative to where the
+    /// index came from; empty for packages with nothing to download
+    pub archive: String,
+}
+
+/// What `upgrade` does (or, with dry_run, would do)
+#[derive(Debug, Clone, Default)]
+pub struct UpgradePlan {
+    /// Each upgrade with the new version's changelog
+    pub upgrades: Vec<(Upgrade, String)>,
+    /// Packages the new versions need that are not installed yet
+    pub new_deps: Vec<String>,
+}
+
+/// A checked .tpkg file and the outcome
+pub type Verified = (String, Result<Trust, TomatoError>);
+
+/// The package manager over one database and index, kept in `S`: the
+/// filesystem, or memory for tests
+pub struct Tomato<S: Storage = FileStorage> {
+    storage: S,
+    db: PackageDB,
+    /// Undo log of the transaction in progress, if any
+    journal: Journal,
+    index_path: String,
+    /// Where `update` fetches the index from when not given a source
+    source_path: String,
+    /// Downloaded .tpkg files, `<name>-<version>.tpkg`
+    archive_dir: String,
+    /// `<name>.list`: the files each installed package put down
+    files_dir: String,
+    /// `<name>.<hook>`: hook scripts of installed packages
+    scripts_dir: String,
+    /// Commands hooks may run, one per line
+    hooks_allow_path: String,
+    hook_runner: Box<dyn HookRunner>,
+    /// Where package contents are unpacked
+    prefix: String,
+    /// Public keys whose package signatures are accepted
+    keys_path: String,
+}
+
+impl Default for Tomato {
+    fn default() -> Self {
+        Tomato::with_root("")
+    }
+}
+
+impl Tomato {
+    /// The system database in /var/lib/tomato
+    pub fn new() -> Self {
+        Tomato::default()
+    }
+
+    /// Files under `root` instead of /, for images and chroots
+    pub fn with_root(root: &str) -> Self {
+        Tomato::with_storage(FileStorage, root)
+    }
+}
+
+impl<S: Storage> Tomato<S> {
+    /// Files under `root` in `storage`
+    pub fn with_storage(storage: S, root: &str) -> Self {
+        let root = root.trim_end_matches('/');
+        Tomato {
+            storage,
+            db: PackageDB::new(&format!("{}{}", root, paths::INSTALLED)),
+            journal: Journal::new(&format!("{}{}", root, paths::JOURNAL)),
+            index_path: format!("{}{}", root, paths::INDEX),
+            source_path: format!("{}{}", root, paths::SOURCE),
+            archive_dir: format!("{}{}", root, paths::ARCHIVE_DIR),
+            files_dir: format!("{}{}", root, paths::FILES_DIR),
+            scripts_dir: format!("{}{}", root, paths::SCRIPTS_DIR),
+            hooks_allow_path: format!("{}{}", root, paths::HOOKS_ALLOW),
+            hook_runner: Box::new(Processes::new(root)),
+            prefix: format!("{}{}", root, paths::PREFIX),
+            keys_path: format!("{}{}", root, paths::TRUSTED_KEYS),
+        }
+    }
+
+    /// Run hooks with `runner` instead of as processes
+    pub fn with_hook_runner(mut self, runner: impl HookRunner + 'static) -> Self {
+        self.hook_runner = Box::new(runner);
+        self
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Available packages; a missing index leaves just the defaults
+    pub fn index(&self) -> Result<BTreeMap<String, IndexEntry>, TomatoError> {
+        let mut index: BTreeMap<String, IndexEntry> = BTreeMap::new();
+        let content = match self.storage.read_to_string(&self.index_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(TomatoError::io(&self.index_path, e)),
+        };
+        let parsed = parse_toml(&content).map_err(|message| TomatoError::Parse {
+            path: self.index_path.clone(),
+            message,
+        })?;
+        for (pkg_name, value) in parsed {
+            let Some(table) = value.as_table() else { continue };
+            let text = |field: &str| table.get(field).and_then(Value::as_str).unwrap_or_default().to_string();
+            index.insert(pkg_name, IndexEntry {
+                version: text("version"),
+                deps: table.get("deps").map(dep_list).unwrap_or_default(),
+                suggests: table.get("suggests").map(dep_list).unwrap_or_default(),
+                changelog: text("changelog"),
+                archive: text("archive"),
+            });
+        }
+        // Add defaults
+        index.entry("base".to_string()).or_default();
+        index.entry("kernel".to_string()).or_insert_with(|| IndexEntry {
+            deps: vec!["base".to_string()],
+            ..IndexEntry::default()
+        });
+        Ok(index)
+    }
+
+    fn load(&self) -> Result<Vec<Installed>, TomatoError> {
+        self.db.load(&self.storage)
+    }
+
+    /// Run `change` as one transaction: committed if it succeeds, rolled
+    /// back if it fails
+    fn transaction<T>(&self, change: impl FnOnce(&Transaction) -> Result<T, TomatoError>) -> Result<T, TomatoError> {
+        let transaction = Transaction::begin(&self.storage, &self.journal)?;
+        match change(&transaction) {
+            Ok(value) => {
+                transaction.commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                if let Err(undo) = transaction.rollback() {
+                    eprintln!("tomato: rolling back: {}", undo);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Finish what an earlier run left: roll back a transaction it did not
+    /// finish, or clean up after one it did
+    pub fn repair(&self) -> Result<Recovery, TomatoError> {
+        self.journal.recover(&self.storage)
+    }
+
+    /// Install `pkg` and its dependencies; returns the packages newly
+    /// installed, dependencies first
+    pub fn install(&self, pkg: &str) -> Result<Vec<String>, TomatoError> {
+        self.install_with(pkg, Suggested::Skip, &Quiet)
+    }
+
+    /// `install`, with the suggested packages of everything installed as
+    /// `suggested` says. The new packages are fetched, verified and
+    /// unpacked side by side, reporting to `progress`; if any fails, every
+    /// file written so far is put back and nothing is recorded.
+    pub fn install_with(&self, pkg: &str, suggested: Suggested, progress: &dyn Progress) -> Result<Vec<String>, TomatoError> {
+        let index = self.index()?;
+        let available = deps_of(&index);
+        let suggestions = index.iter().map(|(name, entry)| (name.clone(), entry.suggests.clone())).collect();
+        let deps = resolve(pkg, &available, &suggestions, suggested).map_err(TomatoError::Resolution)?;
+
+        let mut installed = self.load()?;
+        let new: Vec<String> = deps.into_iter().filter(|dep| !installed.iter().any(|p| &p.name == dep)).collect();
+        let jobs: Vec<Job> = new
+            .iter()
+            .map(|name| {
+                let entry = index.get(name).cloned().unwrap_or_default();
+                Job { name: name.clone(), source: self.archive_source(&entry.archive), version: entry.version }
+            })
+            .collect();
+
+        let trusted = load_trusted_keys(&self.storage, &self.keys_path)?;
+        self.transaction(|transaction| {
+            let pipeline = Pipeline {
+                storage: &self.storage,
+                transaction,
+                trusted: &trusted,
+                archive_dir: &self.archive_dir,
+                prefix: &self.prefix,
+                files_dir: &self.files_dir,
+                scripts_dir: &self.scripts_dir,
+                progress,
+            };
+            pipeline.run(&jobs)?;
+            for name in &new {
+                self.run_hook(transaction, name, POSTINST)?;
+            }
+            for dep in &new {
+                let entry = index.get(dep).cloned().unwrap_or_default();
+                installed.push(Installed { name: dep.clone(), version: entry.version, deps: entry.deps, auto: dep != pkg });
+            }
+            // Asked for by name now: no longer autoremovable
+            if let Some(existing) = installed.iter_mut().find(|p| p.name == pkg) {
+                existing.auto = false;
+            }
+            self.db.save(transaction, &installed)
+        })?;
+        Ok(new)
+    }
+
+    /// Where to fetch an index entry's `archive` from
+    fn archive_source(&self, archive: &str) -> Option<String> {
+        if archive.is_empty() {
+            return None;
+        }
+        if archive.contains("://") || archive.starts_with('/') {
+            return Some(archive.to_string());
+        }
+        let source = self.storage.read_to_string(&self.source_path).unwrap_or_default();
+        match source.trim().rsplit_once('/') {
+            Some((base, _)) => Some(format!("{}/{}", base, archive)),
+            None => Some(archive.to_string()),
+        }
+    }
+
+    /// Commands hooks may run: /etc/tomato/hooks.allow, or the defaults
+    /// when there is none
+    fn hook_allowlist(&self) -> Result<Vec<String>, TomatoError> {
+        match self.storage.read_to_string(&self.hooks_allow_path) {
+            Ok(content) => Ok(parse_allowlist(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(default_allowlist()),
+            Err(e) => Err(TomatoError::io(&self.hooks_allow_path, e)),
+        }
+    }
+
+    /// Run `name`'s `hook` script, if it has one. A hook that is refused or
+    /// fails does not stop the transaction; it is reported and noted in
+    /// the transaction log.
+    fn run_hook(&self, transaction: &Transaction, name: &str, hook: &str) -> Result<(), TomatoError> {
+        let path = format!("{}/{}.{}", self.scripts_dir, name, hook);
+        let script = match transaction.read_to_string(&path) {
+            Ok(script) => script,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(TomatoError::io(&path, e)),
+        };
+        let allowed = self.hook_allowlist()?;
+        let outcome = check_script(&script, &allowed).and_then(|commands| self.hook_runner.run(name, hook, &commands));
+        if let Err(e) = outcome {
+            let note = format!("{} {} failed: {}", name, hook, e);
+            eprintln!("tomato: {}", note);
+            transaction.note(&note).map_err(|e| TomatoError::io(self.journal.path(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Delete the files recorded for `name`, its hook scripts and the
+    /// record
+    fn delete_files(&self, transaction: &Transaction, name: &str) -> Result<(), TomatoError> {
+        let list = format!("{}/{}.list", self.files_dir, name);
+        let files = match transaction.read_to_string(&list) {
+            Ok(files) => files,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(TomatoError::io(&list, e)),
+        };
+        let scripts = [POSTINST, PRERM].map(|hook| format!("{}/{}.{}", self.scripts_dir, name, hook));
+        let records = scripts.iter().map(String::as_str).chain([list.as_str()]);
+        for file in files.lines().filter(|line| !line.is_empty()).chain(records) {
+            match transaction.remove(file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(TomatoError::io(file, e)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn archive_path(&self, name: &str, version: &str) -> String {
+        format!("{}/{}-{}.tpkg", self.archive_dir, name, version)
+    }
+
+    /// Check .tpkg files: `target` is a file path, or an installed or
+    /// available package name for its downloaded archive; with no target,
+    /// every archive in /var/lib/tomato/packages. Returns each file with
+    /// its outcome.
+    pub fn verify(&self, target: Option<&str>) -> Result<Vec<Verified>, TomatoError> {
+        let paths = match target {
+            Some(path) if path.contains('/') || path.ends_with(".tpkg") => vec![path.to_string()],
+            Some(name) => {
+                let version = match self.load()?.into_iter().find(|p| p.name == name) {
+                    Some(package) if !package.version.is_empty() => package.version,
+                    _ => self.index()?.get(name).map(|entry| entry.version.clone()).unwrap_or_default(),
+                };
+                vec![self.archive_path(name, &version)]
+            }
+            None => self
+                .storage
+                .list(&self.archive_dir)
+                .map_err(|e| TomatoError::io(&self.archive_dir, e))?
+                .into_iter()
+                .filter(|path| path.ends_with(".tpkg"))
+                .collect(),
+        };
+        let trusted = load_trusted_keys(&self.storage, &self.keys_path)?;
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let outcome = read_package(&self.storage, &path, &trusted).map(|(_, trust)| trust);
+                (path, outcome)
+            })
+            .collect())
+    }
+
+    /// Uninstall `pkg`; fails with `Conflict` while installed packages
+    /// depend on it, unless `force`
+    pub fn remove(&self, pkg: &str, force: bool) -> Result<(), TomatoError> {
+        let mut installed = self.load()?;
+        let pos = installed
+            .iter()
+            .position(|p| p.name == pkg)
+            .ok_or_else(|| TomatoError::NotInstalled(pkg.to_string()))?;
+        let dependents = dependents(&installed, pkg);
+        if !dependents.is_empty() && !force {
+            return Err(TomatoError::Conflict { package: pkg.to_string(), dependents });
+        }
+        installed.remove(pos);
+        self.transaction(|transaction| {
+            self.run_hook(transaction, pkg, PRERM)?;
+            self.delete_files(transaction, pkg)?;
+            self.db.save(transaction, &installed)
+        })
+    }
+
+    /// Remove automatically installed packages nothing needs; returns
+    /// their names
+    pub fn autoremove(&self) -> Result<Vec<String>, TomatoError> {
+        let mut installed = self.load()?;
+        let unused = orphans(&installed);
+        if !unused.is_empty() {
+            installed.retain(|p| !unused.contains(&p.name));
+            self.transaction(|transaction| {
+                for name in &unused {
+                    self.run_hook(transaction, name, PRERM)?;
+                    self.delete_files(transaction, name)?;
+                }
+                self.db.save(transaction, &installed)
+            })?;
+        }
+        Ok(unused)
+    }
+
+    pub fn list(&self) -> Result<Vec<Installed>, TomatoError> {
+        self.load()
+    }
+
+    /// Available package names containing `query`
+    pub fn search(&self, query: &str) -> Result<Vec<String>, TomatoError> {
+        Ok(self.index()?.into_keys().filter(|name| name.contains(query)).collect())
+    }
+
+    /// Replace the index with the one at `source` (default: the URL or path
+    /// in /etc/tomato/source); returns how many packages it lists
+    pub fn update(&self, source: Option<&str>) -> Result<usize, TomatoError> {
+        let source = match source {
+            Some(source) => source.to_string(),
+            None => self
+                .storage
+                .read_to_string(&self.source_path)
+                .map_err(|e| TomatoError::io(&self.source_path, e))?
+                .trim()
+                .to_string(),
+        };
+        let content = fetch(&self.storage, &source).map_err(|e| TomatoError::io(&source, e))?;
+        // Refuse to replace a working index with a broken one
+        parse_toml(&content).map_err(|message| TomatoError::Parse { path: source.clone(), message })?;
+        self.storage
+            .write(&self.index_path, content.as_bytes())
+            .map_err(|e| TomatoError::io(&self.index_path, e))?;
+        Ok(self.index()?.len())
+    }
+
+    /// Bring installed packages up to the index's versions; with `dry_run`
+    /// only work out what would change
+    pub fn upgrade(&self, dry_run: bool) -> Result<UpgradePlan, TomatoError> {
+        let index = self.index()?;
+        let versions: BTreeMap<String, String> =
+            index.iter().map(|(name, entry)| (name.clone(), entry.version.clone())).collect();
+        let available = deps_of(&index);
+        let mut installed = self.load()?;
+
+        let mut plan = UpgradePlan::default();
+        for upgrade in upgrades(&installed, &versions) {
+            // Dependencies the new versions bring in
+            for dep in resolve_dependencies(&upgrade.name, &available).map_err(TomatoError::Resolution)? {
+                if !installed.iter().any(|p| p.name == dep) && !plan.new_deps.contains(&dep) {
+                    plan.new_deps.push(dep);
+                }
+            }
+            let changelog = index[&upgrade.name].changelog.clone();
+            plan.upgrades.push((upgrade, changelog));
+        }
+        if dry_run || plan.upgrades.is_empty() {
+            return Ok(plan);
+        }
+
+        for package in installed.iter_mut() {
+            if let Some((upgrade, _)) = plan.upgrades.iter().find(|(u, _)| u.name == package.name) {
+                package.version = upgrade.to.clone();
+                package.deps = index[&upgrade.name].deps.clone();
+            }
+        }
+        for dep in &plan.new_deps {
+            let entry = index.get(dep).cloned().unwrap_or_default();
+            installed.push(Installed { name: dep.clone(), version: entry.version, deps: entry.deps, auto: true });
+        }
+        self.transaction(|transaction| self.db.save(transaction, &installed))?;
+        Ok(plan)
+    }
+}
+
+/// `deps = ["a", "b"]`, or the older `deps = "a, b"`
+fn dep_list(value: &Value) -> Vec<String> {
+    match value.as_str() {
+        Some(list) => list.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => value.as_str_list().unwrap_or_default(),
+    }
+}
+
+/// Dependency lists for the resolver
+fn deps_of(index: &BTreeMap<String, IndexEntry>) -> BTreeMap<String, Vec<String>> {
+    index.iter().map(|(name, entry)| (name.clone(), entry.deps.clone())).collect()
+}
@@ -1 +1,4 @@
-pub mod cli;
\ No newline at end of file
+pub mod cli;
+pub mod hooks;
+pub mod manager;
+pub mod pipeline;
\ No newline at end of file
@@ -0,0 +1,271 @@
+//! The install pipeline
+//!
+//! Every package an install brings in goes through three stages: fetch
+//! its .tpkg into /var/lib/tomato/packages (unless it is already there),
+//! verify it, and unpack its files under /usr. Once resolved the packages
+//! do not depend on each other's files, so a few worker threads take them
+//! off a shared queue and run the stages side by side, reporting to a
+//! `Progress` as they go. Every write goes through one `Transaction`; the
+//! caller rolls it back if any package fails. Hook scripts are only put
+//! aside here; the caller runs them, in dependency order, once every
+//! package is unpacked.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use ed25519_dalek::VerifyingKey;
+use tomato_core::hooks::{POSTINST, PRERM};
+use crate::error::TomatoError;
+use crate::storage::backend::Storage;
+use crate::storage::disk_io::read_package;
+use crate::storage::fetch::fetch_bytes;
+use crate::storage::tar;
+use crate::storage::transaction::Transaction;
+
+/// Most packages worked on at once
+pub const MAX_WORKERS: usize = 4;
+
+/// Where a package is in the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Queued,
+    Fetching,
+    Verifying,
+    Unpacking,
+    Done,
+    Failed,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Queued => "queued",
+            Stage::Fetching => "fetching",
+            Stage::Verifying => "verifying",
+            Stage::Unpacking => "unpacking",
+            Stage::Done => "done",
+            Stage::Failed => "failed",
+        }
+    }
+}
+
+/// Told about each step of each package, from any worker
+pub trait Progress: Sync {
+    /// The pipeline is about to work on `packages`
+    fn start(&self, _packages: &[String]) {}
+
+    /// `package` is at `stage`, `done` of `total` through it: bytes when
+    /// fetching, files when unpacking. `total` is `None` when not known.
+    fn update(&self, package: &str, stage: Stage, done: u64, total: Option<u64>);
+}
+
+/// Progress that goes nowhere
+pub struct Quiet;
+
+impl Progress for Quiet {
+    fn update(&self, _package: &str, _stage: Stage, _done: u64, _total: Option<u64>) {}
+}
+
+struct Bar {
+    package: String,
+    stage: Stage,
+    done: u64,
+    total: Option<u64>,
+}
+
+impl Bar {
+    /// How far through all three stages, 0 to 1: fetching is the first
+    /// half, unpacking the last third
+    fn fraction(&self) -> f64 {
+        let part = match self.total {
+            Some(total) if total > 0 => (self.done as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        };
+        match self.stage {
+            Stage::Queued | Stage::Failed => 0.0,
+            Stage::Fetching => part / 2.0,
+            Stage::Verifying => 0.6,
+            Stage::Unpacking => 0.7 + part * 0.3,
+            Stage::Done => 1.0,
+        }
+    }
+}
+
+/// A progress bar per package on stderr, redrawn in place
+#[derive(Default)]
+pub struct Bars {
+    bars: Mutex<(Vec<Bar>, bool)>,
+}
+
+impl Bars {
+    const WIDTH: usize = 24;
+
+    pub fn new() -> Self {
+        Bars::default()
+    }
+
+    /// Bars when stderr is a terminal, nothing otherwise
+    pub fn for_terminal() -> Box<dyn Progress> {
+        if io::stderr().is_terminal() {
+            Box::new(Bars::new())
+        } else {
+            Box::new(Quiet)
+        }
+    }
+}
+
+impl Progress for Bars {
+    fn start(&self, packages: &[String]) {
+        let mut guard = self.bars.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *guard = (
+            packages
+                .iter()
+                .map(|package| Bar { package: package.clone(), stage: Stage::Queued, done: 0, total: None })
+                .collect(),
+            false,
+        );
+    }
+
+    fn update(&self, package: &str, stage: Stage, done: u64, total: Option<u64>) {
+        let mut guard = self.bars.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (bars, drawn) = &mut *guard;
+        let Some(bar) = bars.iter_mut().find(|bar| bar.package == package) else { return };
+        bar.stage = stage;
+        bar.done = done;
+        bar.total = total;
+
+        let name_width = bars.iter().map(|bar| bar.package.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        if *drawn {
+            out.push_str(&format!("\x1b[{}A", bars.len()));
+        }
+        for bar in bars.iter() {
+            let filled = (bar.fraction() * Self::WIDTH as f64) as usize;
+            out.push_str(&format!(
+                "\r\x1b[K{:<width$} [{}{}] {:>3}% {}\n",
+                bar.package,
+                "#".repeat(filled),
+                "-".repeat(Self::WIDTH - filled),
+                (bar.fraction() * 100.0) as u32,
+                bar.stage.name(),
+                width = name_width,
+            ));
+        }
+        *drawn = true;
+        let _ = io::stderr().write_all(out.as_bytes());
+    }
+}
+
+/// One package to put through the pipeline
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub version: String,
+    /// Where to download the .tpkg from when it is not in the archive
+    /// directory yet; `None` for packages with nothing to download
+    pub source: Option<String>,
+}
+
+/// Where the pipeline reads and writes
+pub struct Pipeline<'a> {
+    pub storage: &'a dyn Storage,
+    pub transaction: &'a Transaction<'a>,
+    pub trusted: &'a [VerifyingKey],
+    /// Downloaded .tpkg files, `<name>-<version>.tpkg`
+    pub archive_dir: &'a str,
+    /// Where package contents are unpacked
+    pub prefix: &'a str,
+    /// `<name>.list`: the files each package put down
+    pub files_dir: &'a str,
+    /// `<name>.<hook>`: the hook scripts of installed packages
+    pub scripts_dir: &'a str,
+    pub progress: &'a dyn Progress,
+}
+
+impl Pipeline<'_> {
+    /// Fetch, verify and unpack every job on up to `MAX_WORKERS` threads.
+    /// After the first failure no new package is started; the error
+    /// returned is that of the earliest failed job.
+    pub fn run(&self, jobs: &[Job]) -> Result<(), TomatoError> {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get()).clamp(1, MAX_WORKERS).min(jobs.len());
+        let names: Vec<String> = jobs.iter().map(|job| job.name.clone()).collect();
+        self.progress.start(&names);
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let errors: Mutex<Vec<(usize, TomatoError)>> = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(i) else { break };
+                        if let Err(e) = self.install(job) {
+                            self.progress.update(&job.name, Stage::Failed, 0, None);
+                            failed.store(true, Ordering::Relaxed);
+                            errors.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push((i, e));
+                        }
+                    }
+                });
+            }
+        });
+
+        let errors = errors.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match errors.into_iter().min_by_key(|(i, _)| *i) {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn install(&self, job: &Job) -> Result<(), TomatoError> {
+        let archive = format!("{}/{}-{}.tpkg", self.archive_dir, job.name, job.version);
+        if !self.storage.exists(&archive) {
+            let Some(source) = &job.source else {
+                self.progress.update(&job.name, Stage::Done, 0, None);
+                return Ok(());
+            };
+            self.progress.update(&job.name, Stage::Fetching, 0, None);
+            let data = fetch_bytes(self.storage, source, &mut |done, total| {
+                self.progress.update(&job.name, Stage::Fetching, done, total)
+            })
+            .map_err(|e| TomatoError::io(source, e))?;
+            self.transaction.write(&archive, &data).map_err(|e| TomatoError::io(&archive, e))?;
+        }
+
+        self.progress.update(&job.name, Stage::Verifying, 0, None);
+        let (package, _) = read_package(self.storage, &archive, self.trusted)?;
+        if package.name() != job.name || package.version() != job.version {
+            return Err(TomatoError::Verify {
+                path: archive,
+                message: format!("contains {} {}", package.name(), package.version()),
+            });
+        }
+
+        let entries: Vec<_> = tar::index_tar(&package.payload).into_iter().filter(|entry| !entry.is_dir).collect();
+        let total = Some(entries.len() as u64);
+        let mut files = Vec::new();
+        self.progress.update(&job.name, Stage::Unpacking, 0, total);
+        for entry in &entries {
+            let relative = entry.path.trim_start_matches("./").trim_matches('/');
+            if relative.is_empty() || relative.split('/').any(|part| part == "..") {
+                return Err(TomatoError::Verify { path: archive, message: format!("bad path {}", entry.path) });
+            }
+            let path = format!("{}/{}", self.prefix, relative);
+            self.transaction.write(&path, entry.data).map_err(|e| TomatoError::io(&path, e))?;
+            files.push(path);
+            self.progress.update(&job.name, Stage::Unpacking, files.len() as u64, total);
+        }
+        for hook in [POSTINST, PRERM] {
+            if let Some(script) = package.script(hook) {
+                let path = format!("{}/{}.{}", self.scripts_dir, job.name, hook);
+                self.transaction.write(&path, script.as_bytes()).map_err(|e| TomatoError::io(&path, e))?;
+            }
+        }
+        let list = format!("{}/{}.list", self.files_dir, job.name);
+        self.transaction.write(&list, files.join("\n").as_bytes()).map_err(|e| TomatoError::io(&list, e))?;
+
+        self.progress.update(&job.name, Stage::Done, 1, Some(1));
+        Ok(())
+    }
+}
@@ -1 +0,0 @@
-pub mod solver;
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::fmt;
+use std::io;
+
+/// Why a tomato operation failed
+#[derive(Debug)]
+pub enum TomatoError {
+    /// Reading or writing the database, the index or a source failed
+    Io { path: String, source: io::Error },
+    /// A file did not parse
+    Parse { path: String, message: String },
+    /// Dependencies could not be resolved
+    Resolution(String),
+    /// Removing `package` would break the installed `dependents`
+    Conflict { package: String, dependents: Vec<String> },
+    NotInstalled(String),
+    /// A package failed its checksum or signature check
+    Verify { path: String, message: String },
+    /// Bad command line
+    Usage(String),
+    /// The journal at this path belongs to a transaction that never
+    /// finished; `tomato repair` rolls it back
+    Unfinished(String),
+}
+
+impl TomatoError {
+    pub(crate) fn io(path: &str, source: io::Error) -> Self {
+        TomatoError::Io { path: path.to_string(), source }
+    }
+}
+
+impl fmt::Display for TomatoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TomatoError::Io { path, source } => write!(f, "{}: {}", path, source),
+            TomatoError::Parse { path, message } => write!(f, "{}: {}", path, message),
+            TomatoError::Resolution(message) => write!(f, "dependency error: {}", message),
+            TomatoError::Conflict { package, dependents } => {
+                write!(f, "{} is needed by {} (use --force to remove anyway)", package, dependents.join(", "))
+            }
+            TomatoError::NotInstalled(package) => write!(f, "package {} not installed", package),
+            TomatoError::Verify { path, message } => write!(f, "{}: verification failed: {}", path, message),
+            TomatoError::Usage(message) => write!(f, "{}", message),
+            TomatoError::Unfinished(path) => {
+                write!(f, "{}: an earlier transaction did not finish (run `tomato repair`)", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TomatoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TomatoError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
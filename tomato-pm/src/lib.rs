@@ -1,99 +1,139 @@
-use std::collections::HashMap;
+extern crate alloc;
+
 use crate::api::cli::{parse_command, Command};
-use crate::storage::disk_io::PackageDB;
-use crate::core::solver::resolve_dependencies;
-use crate::parser::toml::parse_toml;
+use crate::api::pipeline::Bars;
+use crate::storage::disk_io::Recovery;
+use crate::storage::tpkg::{parse_secret_key, Trust, Tpkg};
+use tomato_core::solver::Suggested;
 
-pub mod core;
 pub mod storage;
 pub mod api;
-pub mod parser;
+pub mod error;
 
-pub fn run(args: &[String]) {
-    let db = PackageDB::new("/var/lib/tomato/packages.txt");
-    match parse_command(args) {
-        Ok(Command::Install(pkg)) => {
-            let mut available = HashMap::new();
-            // Load available packages from /var/lib/tomato/available.toml
-            if let Ok(content) = std::fs::read_to_string("/var/lib/tomato/available.toml") {
-                if let Ok(parsed) = parse_toml(&content) {
-                    for (key, value) in &parsed {
-                        if key.ends_with(".deps") {
-                            let pkg_name = key.trim_end_matches(".deps");
-                            let deps: Vec<String> = value.split(',').map(|s: &str| s.trim().to_string()).collect();
-                            available.insert(pkg_name.to_string(), deps);
-                        }
-                    }
-                }
-            }
-            // Add defaults
-            available.entry("base".to_string()).or_insert(vec![]);
-            available.entry("kernel".to_string()).or_insert(vec!["base".to_string()]);
+pub use crate::api::manager::Tomato;
+pub use crate::error::TomatoError;
+pub use crate::storage::backend::{FileStorage, MemoryStorage, Storage};
 
-            match resolve_dependencies(&pkg, &available) {
-                Ok(deps) => {
-                    match db.load_installed() {
-                        Ok(mut installed) => {
-                            for dep in deps {
-                                if !installed.contains(&dep) {
-                                    println!("Installing {}", dep);
-                                    installed.push(dep);
-                                }
-                            }
-                            if let Err(e) = db.save_installed(&installed) {
-                                println!("Error saving: {}", e);
-                            }
-                        }
-                        Err(e) => println!("Error loading: {}", e),
-                    }
-                }
-                Err(e) => println!("Dependency error: {}", e),
+/// The `tomato` command line: run `args` against the system database and
+/// print the outcome
+pub fn run(args: &[String]) -> Result<(), TomatoError> {
+    run_with(&Tomato::new(), args)
+}
+
+/// `run` against any tomato, e.g. one over `MemoryStorage`
+pub fn run_with<S: Storage>(tomato: &Tomato<S>, args: &[String]) -> Result<(), TomatoError> {
+    let storage = tomato.storage();
+    let command = parse_command(args).map_err(TomatoError::Usage)?;
+    // A crash part way through the last run is put right before anything else
+    if !matches!(command, Command::Repair) {
+        if let Recovery::RolledBack(changes) = tomato.repair()? {
+            eprintln!("tomato: rolled back an unfinished transaction ({} changes)", changes);
+        }
+    }
+    match command {
+        Command::Install { package, suggested } => {
+            let suggested = if suggested { Suggested::BreakCycles } else { Suggested::Skip };
+            for dep in tomato.install_with(&package, suggested, &*Bars::for_terminal())? {
+                println!("Installing {}", dep);
             }
         }
-        Ok(Command::Remove(pkg)) => {
-            match db.load_installed() {
-                Ok(mut installed) => {
-                    if let Some(pos) = installed.iter().position(|p| p == &pkg) {
-                        installed.remove(pos);
-                        if let Err(e) = db.save_installed(&installed) {
-                            println!("Error saving: {}", e);
-                        } else {
-                            println!("Removed {}", pkg);
-                        }
-                    } else {
-                        println!("Package {} not installed", pkg);
-                    }
-                }
-                Err(e) => println!("Error loading: {}", e),
+        Command::Remove { package, force } => {
+            tomato.remove(&package, force)?;
+            println!("Removed {}", package);
+        }
+        Command::Autoremove => {
+            let removed = tomato.autoremove()?;
+            if removed.is_empty() {
+                println!("Nothing to remove");
+            }
+            for pkg in removed {
+                println!("Removed {}", pkg);
             }
         }
-        Ok(Command::List) => {
-            match db.load_installed() {
-                Ok(installed) => {
-                    if installed.is_empty() {
-                        println!("No packages installed");
-                    } else {
-                        for pkg in installed {
-                            println!("{}", pkg);
-                        }
-                    }
+        Command::Repair => match tomato.repair()? {
+            Recovery::Clean => println!("Nothing to repair"),
+            Recovery::Committed => println!("Cleaned up after the last transaction"),
+            Recovery::RolledBack(changes) => println!("Rolled back an unfinished transaction ({} changes)", changes),
+        },
+        Command::List => {
+            let installed = tomato.list()?;
+            if installed.is_empty() {
+                println!("No packages installed");
+            }
+            for pkg in installed {
+                println!("{}", pkg.name);
+            }
+        }
+        Command::Search(query) => {
+            for name in tomato.search(&query)? {
+                println!("{}", name);
+            }
+        }
+        Command::Update(source) => {
+            let count = tomato.update(source.as_deref())?;
+            println!("Updated index ({} packages)", count);
+        }
+        Command::Upgrade { dry_run } => {
+            let plan = tomato.upgrade(dry_run)?;
+            if plan.upgrades.is_empty() {
+                println!("All packages are up to date");
+                return Ok(());
+            }
+            for (upgrade, changelog) in &plan.upgrades {
+                let from = if upgrade.from.is_empty() { "?" } else { &upgrade.from };
+                println!("{} {} -> {}", upgrade.name, from, upgrade.to);
+                for line in changelog.lines() {
+                    println!("    {}", line);
                 }
-                Err(e) => println!("Error loading: {}", e),
+            }
+            for dep in &plan.new_deps {
+                println!("{} (new dependency)", dep);
+            }
+            if dry_run {
+                println!("Dry run: nothing changed");
+            } else {
+                println!("Upgraded {} packages", plan.upgrades.len());
             }
         }
-        Ok(Command::Search(query)) => {
-            // Simple search in available
-            let mut available: HashMap<String, Vec<String>> = HashMap::new();
-            if let Ok(content) = std::fs::read_to_string("/var/lib/tomato/available.toml") {
-                if let Ok(parsed) = parse_toml(&content) {
-                    for (key, _) in &parsed {
-                        if key.contains(&query) {
-                            println!("{}", key);
-                        }
+        Command::Verify(target) => {
+            let results = tomato.verify(target.as_deref())?;
+            if results.is_empty() {
+                println!("No packages to verify");
+            }
+            let mut failed = 0;
+            for (path, outcome) in &results {
+                match outcome {
+                    Ok(Trust::Unsigned) => println!("{}: checksum ok, unsigned", path),
+                    Ok(Trust::Signed(key)) => println!("{}: checksum ok, signed by {}", path, key),
+                    Err(e) => {
+                        println!("{}", e);
+                        failed += 1;
                     }
                 }
             }
+            if failed > 0 {
+                return Err(TomatoError::Verify {
+                    path: target.unwrap_or_else(|| "packages".to_string()),
+                    message: format!("{} of {} failed", failed, results.len()),
+                });
+            }
+        }
+        Command::Pack { payload, name, version, key, scripts } => {
+            let tar = storage.read(&payload).map_err(|e| TomatoError::io(&payload, e))?;
+            let mut package = Tpkg::new(&name, &version, tar);
+            for (hook, path) in scripts {
+                let script = storage.read_to_string(&path).map_err(|e| TomatoError::io(&path, e))?;
+                package.set_script(&hook, &script);
+            }
+            if let Some(key_path) = key {
+                let hex = storage.read_to_string(&key_path).map_err(|e| TomatoError::io(&key_path, e))?;
+                let secret = parse_secret_key(&hex).map_err(|message| TomatoError::Parse { path: key_path, message })?;
+                package.sign(&secret);
+            }
+            let out = format!("{}-{}.tpkg", name, version);
+            storage.write(&out, &package.to_bytes()).map_err(|e| TomatoError::io(&out, e))?;
+            println!("Wrote {}", out);
         }
-        Err(e) => println!("Command error: {}", e),
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -1,6 +1,10 @@
 use std::env;
+use std::process;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    tomato_pm::run(&args);
-}
\ No newline at end of file
+    if let Err(e) = tomato_pm::run(&args) {
+        eprintln!("tomato: {}", e);
+        process::exit(1);
+    }
+}
@@ -1 +0,0 @@
-pub mod toml;
\ No newline at end of file
@@ -0,0 +1,237 @@
+//! Where tomato's files live
+//!
+//! Everything `Tomato` reads or writes (the database, the index, keys,
+//! downloaded archives) goes through a `Storage`: `FileStorage` is the
+//! real filesystem, `MemoryStorage` a map of paths to contents for tests
+//! and dry runs. Installs read and write from several threads at once, so
+//! both are `Sync`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+pub trait Storage: Sync {
+    /// Contents of the file at `path`; `NotFound` if there is none
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Create or replace the file at `path`, and the directories above it
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Add `data` to the end of the file at `path`, creating it if need be
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Delete the file at `path`; `NotFound` if there is none
+    fn remove(&self, path: &str) -> io::Result<()>;
+
+    /// Make sure what was written to `path` survives a crash
+    fn sync(&self, _path: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> bool;
+
+    /// Paths of the files directly in `dir`, sorted; empty if `dir` does
+    /// not exist
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+
+    /// The file at `path` as text; `InvalidData` if it is not UTF-8
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not UTF-8"))
+    }
+}
+
+impl<T: Storage + ?Sized> Storage for &T {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        (**self).read(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        (**self).write(path, data)
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        (**self).append(path, data)
+    }
+
+    fn remove(&self, path: &str) -> io::Result<()> {
+        (**self).remove(path)
+    }
+
+    fn sync(&self, path: &str) -> io::Result<()> {
+        (**self).sync(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        (**self).exists(path)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        (**self).list(dir)
+    }
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        create_parent(path)?;
+        fs::write(path, data)
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        create_parent(path)?;
+        fs::OpenOptions::new().create(true).append(true).open(path)?.write_all(data)
+    }
+
+    fn remove(&self, path: &str) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn sync(&self, path: &str) -> io::Result<()> {
+        fs::File::open(path)?.sync_all()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut paths: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+fn create_parent(path: &str) -> io::Result<()> {
+    match std::path::Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Files kept in memory, by path
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// Storage holding `files` (path, contents)
+    pub fn with_files<'a>(files: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> Self {
+        let storage = MemoryStorage::new();
+        for (path, data) in files {
+            storage.files().insert(path.to_string(), data.to_vec());
+        }
+        storage
+    }
+
+    /// Every stored path, sorted
+    pub fn paths(&self) -> Vec<String> {
+        self.files().keys().cloned().collect()
+    }
+
+    fn files(&self) -> MutexGuard<'_, BTreeMap<String, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{}: no such file", path))
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files().get(path).cloned().ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.files().insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.files().entry(path.to_string()).or_default().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> io::Result<()> {
+        self.files().remove(path).map(|_| ()).ok_or_else(|| not_found(path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files().contains_key(path)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let dir = dir.trim_end_matches('/');
+        Ok(self
+            .files()
+            .keys()
+            .filter(|path| path.rsplit_once('/').is_some_and(|(parent, _)| parent == dir))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage_reads_back_what_was_written() {
+        let storage = MemoryStorage::new();
+        storage.write("/a/b.txt", b"hello").unwrap();
+        assert_eq!(storage.read("/a/b.txt").unwrap(), b"hello");
+        assert_eq!(storage.read_to_string("/a/b.txt").unwrap(), "hello");
+        assert!(storage.exists("/a/b.txt"));
+        storage.append("/a/b.txt", b", world").unwrap();
+        assert_eq!(storage.read("/a/b.txt").unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn memory_storage_reports_missing_files() {
+        let storage = MemoryStorage::new();
+        assert_eq!(storage.read("/nope").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(storage.remove("/nope").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!storage.exists("/nope"));
+    }
+
+    #[test]
+    fn memory_storage_lists_only_direct_children() {
+        let storage = MemoryStorage::with_files([
+            ("/d/one", &b""[..]),
+            ("/d/two", &b""[..]),
+            ("/d/sub/three", &b""[..]),
+            ("/other", &b""[..]),
+        ]);
+        assert_eq!(storage.list("/d/").unwrap(), ["/d/one", "/d/two"]);
+        assert!(storage.list("/missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_utf8_is_invalid_data() {
+        let storage = MemoryStorage::with_files([("/bin", &b"\xff\xfe"[..])]);
+        assert_eq!(storage.read_to_string("/bin").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
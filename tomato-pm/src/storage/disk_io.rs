@@ -1,34 +1,448 @@
-use std::fs;
 use std::io;
-use std::path::Path;
+use ed25519_dalek::VerifyingKey;
+use tomato_core::installed::{self, Installed};
+use crate::error::TomatoError;
+use crate::storage::backend::Storage;
+use crate::storage::tpkg::{parse_public_key, Trust, Tpkg};
 
+/// packages.txt, and a copy of it at packages.txt.bak to recover from when
+/// the original gets truncated or scribbled over
 pub struct PackageDB {
     path: String,
+    backup_path: String,
 }
 
 impl PackageDB {
     pub fn new(path: &str) -> Self {
         PackageDB {
             path: path.to_string(),
+            backup_path: format!("{}.bak", path),
         }
     }
 
-    pub fn load_installed(&self) -> io::Result<Vec<String>> {
-        if Path::new(&self.path).exists() {
-            let content = fs::read_to_string(&self.path)?;
-            Ok(content.lines().map(|s| s.to_string()).collect())
-        } else {
-            Ok(Vec::new())
+    /// Installed packages with their dependencies; none if there is no
+    /// database yet. A corrupt database is replaced by its backup when the
+    /// backup is sound, and is an error when it is not.
+    pub fn load(&self, storage: &dyn Storage) -> Result<Vec<Installed>, TomatoError> {
+        let problem = match read_database(storage, &self.path) {
+            Ok(Some(packages)) => return Ok(packages),
+            Ok(None) if !storage.exists(&self.backup_path) => return Ok(Vec::new()),
+            Ok(None) => "missing".to_string(),
+            Err(message) => message,
+        };
+        match read_database(storage, &self.backup_path) {
+            Ok(Some(packages)) => {
+                eprintln!("tomato: {}: {}, restored from {}", self.path, problem, self.backup_path);
+                self.save(storage, &packages)?;
+                Ok(packages)
+            }
+            _ => Err(TomatoError::Parse { path: self.path.clone(), message: problem }),
         }
     }
 
-    pub fn save_installed(&self, packages: &[String]) -> io::Result<()> {
-        let content = packages.join("\n");
-        fs::write(&self.path, content)
+    /// Write the database, then its backup; a crash part way leaves at
+    /// least one of them whole
+    pub fn save(&self, storage: &dyn Storage, packages: &[Installed]) -> Result<(), TomatoError> {
+        let content = installed::serialize_installed(packages);
+        storage.write(&self.path, content.as_bytes()).map_err(|e| TomatoError::io(&self.path, e))?;
+        storage
+            .write(&self.backup_path, content.as_bytes())
+            .map_err(|e| TomatoError::io(&self.backup_path, e))
     }
 
-    pub fn is_installed(&self, package: &str) -> io::Result<bool> {
-        let installed = self.load_installed()?;
+    pub fn load_installed(&self, storage: &dyn Storage) -> Result<Vec<String>, TomatoError> {
+        Ok(self.load(storage)?.into_iter().map(|p| p.name).collect())
+    }
+
+    pub fn is_installed(&self, storage: &dyn Storage, package: &str) -> Result<bool, TomatoError> {
+        let installed = self.load_installed(storage)?;
         Ok(installed.contains(&package.to_string()))
     }
-}
\ No newline at end of file
+
+    /// Installed packages that depend on `package`
+    pub fn reverse_dependencies(&self, storage: &dyn Storage, package: &str) -> Result<Vec<String>, TomatoError> {
+        Ok(installed::dependents(&self.load(storage)?, package))
+    }
+}
+
+/// The packages in the database at `path`, `None` if it does not exist, or
+/// what is wrong with it
+fn read_database(storage: &dyn Storage, path: &str) -> Result<Option<Vec<Installed>>, String> {
+    let content = match storage.read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    for (number, line) in content.lines().enumerate() {
+        check_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+    }
+    Ok(Some(installed::parse_installed(&content)))
+}
+
+/// Whether `line` reads as `name[=version] [auto][: deps]`; parse_installed
+/// accepts anything, so garbage has to be caught here
+fn check_line(line: &str) -> Result<(), String> {
+    let word = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "._+-~".contains(c));
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(());
+    }
+    let (head, deps) = line.split_once(':').unwrap_or((line, ""));
+    let mut words = head.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    let (name, version) = first.split_once('=').unwrap_or((first, ""));
+    if !word(name) || !(version.is_empty() || word(version)) {
+        return Err(format!("bad package {:?}", first));
+    }
+    if let Some(flag) = words.find(|&flag| flag != "auto") {
+        return Err(format!("unknown flag {:?}", flag));
+    }
+    match deps.split_whitespace().find(|dep| !word(dep)) {
+        Some(dep) => Err(format!("bad dependency {:?}", dep)),
+        None => Ok(()),
+    }
+}
+
+/// Undo log for a transaction, at /var/lib/tomato/journal
+///
+/// Before a transaction changes a file it records the change here, with a
+/// copy of what the file held saved under journal.d, and syncs both; only
+/// then is the file touched. Finishing appends `commit`. A journal found
+/// without `commit` means a crash part way, and replaying its records
+/// backwards puts every file back. `note` lines (hook failures) are kept
+/// in the transaction log, journal.log, once the transaction is over:
+///
+/// ```text
+/// begin
+/// created /usr/bin/hello
+/// saved 1 /var/lib/tomato/packages.txt
+/// note hello postinst failed: false exited with status 1
+/// commit
+/// ```
+pub struct Journal {
+    path: String,
+    saved_dir: String,
+    log_path: String,
+}
+
+/// What `Journal::recover` found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// No journal: the last transaction finished
+    Clean,
+    /// The last transaction committed but was not cleaned up
+    Committed,
+    /// The last transaction did not finish; this many changes were undone
+    RolledBack(usize),
+}
+
+/// One record: `path` did not exist, or held what was saved as `saved`,
+/// or a note for the log
+enum Record {
+    Created(String),
+    Saved(usize, String),
+    Note(String),
+}
+
+impl Journal {
+    pub fn new(path: &str) -> Self {
+        Journal { path: path.to_string(), saved_dir: format!("{}.d", path), log_path: format!("{}.log", path) }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The transaction log, where notes end up
+    pub fn log_path(&self) -> &str {
+        &self.log_path
+    }
+
+    /// Start a transaction; refused while an earlier one is unfinished
+    pub fn begin(&self, storage: &dyn Storage) -> Result<(), TomatoError> {
+        if storage.exists(&self.path) {
+            return Err(TomatoError::Unfinished(self.path.clone()));
+        }
+        storage.write(&self.path, b"begin\n").map_err(|e| TomatoError::io(&self.path, e))?;
+        storage.sync(&self.path).map_err(|e| TomatoError::io(&self.path, e))
+    }
+
+    /// Record that `path` is about to change, saving what it holds as
+    /// record `id`; durable before this returns
+    pub fn record(&self, storage: &dyn Storage, id: usize, path: &str) -> io::Result<()> {
+        let line = match storage.read(path) {
+            Ok(old) => {
+                let saved = format!("{}/{}", self.saved_dir, id);
+                storage.write(&saved, &old)?;
+                storage.sync(&saved)?;
+                format!("saved {} {}\n", id, path)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => format!("created {}\n", path),
+            Err(e) => return Err(e),
+        };
+        storage.append(&self.path, line.as_bytes())?;
+        storage.sync(&self.path)
+    }
+
+    /// Note `text` (one line) for the transaction log
+    pub fn note(&self, storage: &dyn Storage, text: &str) -> io::Result<()> {
+        let line = format!("note {}\n", text.replace('\n', " "));
+        storage.append(&self.path, line.as_bytes())?;
+        storage.sync(&self.path)
+    }
+
+    /// Mark the transaction finished, then throw the journal away
+    pub fn commit(&self, storage: &dyn Storage) -> Result<(), TomatoError> {
+        storage.append(&self.path, b"commit\n").map_err(|e| TomatoError::io(&self.path, e))?;
+        storage.sync(&self.path).map_err(|e| TomatoError::io(&self.path, e))?;
+        let (records, _) = self.records(storage)?;
+        self.discard(storage, &records, false)
+    }
+
+    /// Undo every recorded change, newest first, then throw the journal
+    /// away; returns how many were undone
+    pub fn roll_back(&self, storage: &dyn Storage) -> Result<usize, TomatoError> {
+        let (records, _) = self.records(storage)?;
+        let mut undone = 0;
+        for record in records.iter().rev() {
+            match record {
+                Record::Created(path) => match storage.remove(path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(TomatoError::io(path, e)),
+                    _ => undone += 1,
+                },
+                Record::Saved(id, path) => {
+                    let saved = format!("{}/{}", self.saved_dir, id);
+                    let old = storage.read(&saved).map_err(|e| TomatoError::io(&saved, e))?;
+                    storage.write(path, &old).map_err(|e| TomatoError::io(path, e))?;
+                    storage.sync(path).map_err(|e| TomatoError::io(path, e))?;
+                    undone += 1;
+                }
+                Record::Note(_) => {}
+            }
+        }
+        self.discard(storage, &records, true)?;
+        Ok(undone)
+    }
+
+    /// Finish what the last run left: roll back an unfinished transaction,
+    /// or clean up after a committed one
+    pub fn recover(&self, storage: &dyn Storage) -> Result<Recovery, TomatoError> {
+        if !storage.exists(&self.path) {
+            return Ok(Recovery::Clean);
+        }
+        let (records, committed) = self.records(storage)?;
+        if committed {
+            self.discard(storage, &records, false)?;
+            return Ok(Recovery::Committed);
+        }
+        self.roll_back(storage).map(Recovery::RolledBack)
+    }
+
+    /// The records, and whether `commit` follows them. A line cut short by
+    /// a crash is left out: the change it was about never happened.
+    fn records(&self, storage: &dyn Storage) -> Result<(Vec<Record>, bool), TomatoError> {
+        let content = storage.read(&self.path).map_err(|e| TomatoError::io(&self.path, e))?;
+        let content = String::from_utf8_lossy(&content);
+        let mut records = Vec::new();
+        let mut committed = false;
+        for line in content.split_inclusive('\n').filter_map(|line| line.strip_suffix('\n')) {
+            match line.split_once(' ') {
+                Some(("created", path)) => records.push(Record::Created(path.to_string())),
+                Some(("saved", rest)) => {
+                    if let Some((id, path)) = rest.split_once(' ').and_then(|(id, path)| Some((id.parse().ok()?, path))) {
+                        records.push(Record::Saved(id, path.to_string()));
+                    }
+                }
+                Some(("note", text)) => records.push(Record::Note(text.to_string())),
+                _ if line == "commit" => committed = true,
+                _ => {}
+            }
+        }
+        Ok((records, committed))
+    }
+
+    /// Move the notes to the log, delete the saved copies, then the
+    /// journal. A crash part way repeats the notes at worst.
+    fn discard(&self, storage: &dyn Storage, records: &[Record], rolled_back: bool) -> Result<(), TomatoError> {
+        let mut log = String::new();
+        for record in records {
+            if let Record::Note(text) = record {
+                log.push_str(text);
+                log.push_str(if rolled_back { " (rolled back)\n" } else { "\n" });
+            }
+        }
+        if !log.is_empty() {
+            storage.append(&self.log_path, log.as_bytes()).map_err(|e| TomatoError::io(&self.log_path, e))?;
+            storage.sync(&self.log_path).map_err(|e| TomatoError::io(&self.log_path, e))?;
+        }
+        for record in records {
+            if let Record::Saved(id, _) = record {
+                let saved = format!("{}/{}", self.saved_dir, id);
+                match storage.remove(&saved) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(TomatoError::io(&saved, e)),
+                    _ => {}
+                }
+            }
+        }
+        storage.remove(&self.path).map_err(|e| TomatoError::io(&self.path, e))
+    }
+}
+
+/// Trusted signing keys, hex-encoded one per line (`#` comments); none if
+/// the file does not exist
+pub fn load_trusted_keys(storage: &dyn Storage, path: &str) -> Result<Vec<VerifyingKey>, TomatoError> {
+    let content = match storage.read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(TomatoError::io(path, e)),
+    };
+    content
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            parse_public_key(line).map_err(|e| TomatoError::Parse {
+                path: path.to_string(),
+                message: format!("line {}: {}", number + 1, e),
+            })
+        })
+        .collect()
+}
+
+/// Read the .tpkg at `path` and check it against its checksum and, when
+/// signed, the `trusted` keys; nothing unverified gets past here
+pub fn read_package(storage: &dyn Storage, path: &str, trusted: &[VerifyingKey]) -> Result<(Tpkg, Trust), TomatoError> {
+    let bytes = storage.read(path).map_err(|e| TomatoError::io(path, e))?;
+    let verify_error = |message: String| TomatoError::Verify { path: path.to_string(), message };
+    let package = Tpkg::parse(&bytes).map_err(verify_error)?;
+    let trust = package.verify(trusted).map_err(verify_error)?;
+    Ok((package, trust))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryStorage;
+
+    const DB: &str = "/var/lib/tomato/packages.txt";
+    const BACKUP: &str = "/var/lib/tomato/packages.txt.bak";
+
+    fn package(name: &str, deps: &[&str]) -> Installed {
+        Installed {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+            auto: false,
+        }
+    }
+
+    #[test]
+    fn no_database_is_no_packages() {
+        assert!(PackageDB::new(DB).load(&MemoryStorage::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let storage = MemoryStorage::new();
+        let db = PackageDB::new(DB);
+        let packages = vec![package("base", &[]), package("hello", &["base"])];
+        db.save(&storage, &packages).unwrap();
+        assert_eq!(db.load(&storage).unwrap(), packages);
+        assert_eq!(storage.read(DB).unwrap(), storage.read(BACKUP).unwrap());
+    }
+
+    #[test]
+    fn corrupt_database_is_restored_from_backup() {
+        let storage = MemoryStorage::new();
+        let db = PackageDB::new(DB);
+        let packages = vec![package("base", &[])];
+        db.save(&storage, &packages).unwrap();
+
+        storage.write(DB, b"base=1.0\n\x00\x17\xff garbage").unwrap();
+        assert_eq!(db.load(&storage).unwrap(), packages);
+        assert_eq!(storage.read_to_string(DB).unwrap(), "base=1.0");
+    }
+
+    #[test]
+    fn lost_database_is_restored_from_backup() {
+        let storage = MemoryStorage::new();
+        let db = PackageDB::new(DB);
+        db.save(&storage, &[package("base", &[])]).unwrap();
+        storage.remove(DB).unwrap();
+        assert_eq!(db.load(&storage).unwrap().len(), 1);
+        assert!(storage.exists(DB));
+    }
+
+    #[test]
+    fn corrupt_database_without_backup_is_an_error() {
+        let storage = MemoryStorage::with_files([(DB, &b"hello=1.0 auto: base\n{not a package}"[..])]);
+        match PackageDB::new(DB).load(&storage) {
+            Err(TomatoError::Parse { path, message }) => {
+                assert_eq!(path, DB);
+                assert!(message.starts_with("line 2:"), "{}", message);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    const JOURNAL: &str = "/var/lib/tomato/journal";
+
+    #[test]
+    fn unfinished_journal_is_rolled_back() {
+        let storage = MemoryStorage::with_files([("/etc/motd", &b"hello"[..])]);
+        let journal = Journal::new(JOURNAL);
+        journal.begin(&storage).unwrap();
+        journal.record(&storage, 0, "/etc/motd").unwrap();
+        storage.write("/etc/motd", b"changed").unwrap();
+        journal.record(&storage, 1, "/usr/bin/new").unwrap();
+        storage.write("/usr/bin/new", b"binary").unwrap();
+        // and a record the crash cut short
+        storage.append(JOURNAL, b"saved 2 /etc/pa").unwrap();
+
+        assert_eq!(journal.recover(&storage).unwrap(), Recovery::RolledBack(2));
+        assert_eq!(storage.paths(), ["/etc/motd"]);
+        assert_eq!(storage.read("/etc/motd").unwrap(), b"hello");
+        assert_eq!(journal.recover(&storage).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn committed_journal_is_only_cleaned_up() {
+        let storage = MemoryStorage::with_files([("/etc/motd", &b"hello"[..])]);
+        let journal = Journal::new(JOURNAL);
+        journal.begin(&storage).unwrap();
+        journal.record(&storage, 0, "/etc/motd").unwrap();
+        storage.write("/etc/motd", b"changed").unwrap();
+        storage.append(JOURNAL, b"commit\n").unwrap();
+
+        assert_eq!(journal.recover(&storage).unwrap(), Recovery::Committed);
+        assert_eq!(storage.paths(), ["/etc/motd"]);
+        assert_eq!(storage.read("/etc/motd").unwrap(), b"changed");
+    }
+
+    #[test]
+    fn notes_end_up_in_the_log() {
+        let storage = MemoryStorage::new();
+        let journal = Journal::new(JOURNAL);
+        journal.begin(&storage).unwrap();
+        journal.note(&storage, "hello postinst failed:\nbadly").unwrap();
+        journal.commit(&storage).unwrap();
+        journal.begin(&storage).unwrap();
+        journal.note(&storage, "world prerm failed").unwrap();
+        assert_eq!(journal.roll_back(&storage).unwrap(), 0);
+
+        assert_eq!(
+            storage.read_to_string(journal.log_path()).unwrap(),
+            "hello postinst failed: badly\nworld prerm failed (rolled back)\n"
+        );
+        assert_eq!(storage.paths(), [journal.log_path()]);
+    }
+
+    #[test]
+    fn unknown_flags_are_corruption() {
+        assert!(check_line("hello=1.0 auto: base").is_ok());
+        assert!(check_line("hello").is_ok());
+        assert!(check_line("hello=1.0 sometimes").is_err());
+        assert!(check_line("hello: base !!").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use crate::storage::backend::Storage;
+
+/// Read `source`: an `http://` URL, or a `file://` URL or plain path in
+/// `storage`
+pub fn fetch(storage: &dyn Storage, source: &str) -> io::Result<String> {
+    String::from_utf8(fetch_bytes(storage, source, &mut |_, _| {})?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not UTF-8"))
+}
+
+/// `fetch` for binary files, calling `progress` with the bytes read so far
+/// and the total when it is known
+pub fn fetch_bytes(
+    storage: &dyn Storage,
+    source: &str,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> io::Result<Vec<u8>> {
+    if let Some(rest) = source.strip_prefix("http://") {
+        return http_get(rest, progress);
+    }
+    if source.contains("://") && !source.starts_with("file://") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("unsupported source: {}", source)));
+    }
+    let data = storage.read(source.trim_start_matches("file://"))?;
+    progress(data.len() as u64, Some(data.len() as u64));
+    Ok(data)
+}
+
+/// Plain HTTP/1.0 GET of `host[:port]/path`
+fn http_get(url: &str, progress: &mut dyn FnMut(u64, Option<u64>)) -> io::Result<Vec<u8>> {
+    let (host, path) = match url.find('/') {
+        Some(slash) => (&url[..slash], &url[slash..]),
+        None => (url, "/"),
+    };
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let mut stream = TcpStream::connect(address)?;
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host)?;
+
+    let mut response = Vec::new();
+    let mut body_start = None;
+    let mut length = None;
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        response.extend_from_slice(&buf[..n]);
+        if body_start.is_none() {
+            if let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&response[..end]).into_owned();
+                let status = head.lines().next().unwrap_or_default();
+                if status.split_whitespace().nth(1) != Some("200") {
+                    return Err(io::Error::other(format!("{}: {}", url, status)));
+                }
+                length = head.lines().find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse().ok())?
+                });
+                body_start = Some(end + 4);
+            }
+        }
+        if let Some(start) = body_start {
+            progress((response.len() - start) as u64, length);
+        }
+    }
+    let start = body_start.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))?;
+    Ok(response.split_off(start))
+}
@@ -1 +1,7 @@
-pub mod disk_io;
\ No newline at end of file
+pub mod backend;
+pub mod disk_io;
+pub mod fetch;
+#[path = "../../../kernel/src/fs/tar.rs"]
+pub mod tar;
+pub mod tpkg;
+pub mod transaction;
\ No newline at end of file
@@ -0,0 +1,165 @@
+//! .tpkg packages
+//!
+//! A .tpkg file is the 4-byte magic `TPKG`, the header length as a
+//! little-endian u32, a TOML header, then the package contents as a ustar
+//! archive:
+//!
+//! ```text
+//! name = "hello"
+//! version = "1.0"
+//! sha256 = "9f86d08..."      # of the tar payload
+//! signature = "3a1b..."      # optional ed25519, see `signed_message`
+//! postinst = "mkdir /var/lib/hello"   # optional hooks, see tomato_core::hooks
+//! prerm = "..."
+//! ```
+//!
+//! Trusted signers are ed25519 public keys, hex-encoded one per line in
+//! /etc/tomato/trusted_keys.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use tomato_core::toml::{parse_toml, serialize_toml, Table, Value};
+
+pub const MAGIC: &[u8; 4] = b"TPKG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tpkg {
+    pub header: Table,
+    /// ustar archive of the package contents
+    pub payload: Vec<u8>,
+}
+
+/// Who vouches for a verified package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trust {
+    /// Checksum only
+    Unsigned,
+    /// Signed by this trusted key (hex)
+    Signed(String),
+}
+
+impl Tpkg {
+    pub fn new(name: &str, version: &str, payload: Vec<u8>) -> Self {
+        let mut header = Table::new();
+        header.insert("name".to_string(), Value::String(name.to_string()));
+        header.insert("version".to_string(), Value::String(version.to_string()));
+        header.insert("sha256".to_string(), Value::String(sha256_hex(&payload)));
+        Tpkg { header, payload }
+    }
+
+    fn field(&self, key: &str) -> &str {
+        self.header.get(key).and_then(Value::as_str).unwrap_or_default()
+    }
+
+    pub fn name(&self) -> &str {
+        self.field("name")
+    }
+
+    pub fn version(&self) -> &str {
+        self.field("version")
+    }
+
+    /// The `hook` script (`postinst`, `prerm`), if the package has one
+    pub fn script(&self, hook: &str) -> Option<&str> {
+        Some(self.field(hook)).filter(|script| !script.is_empty())
+    }
+
+    /// Attach `script` as the `hook` script; before signing, which covers it
+    pub fn set_script(&mut self, hook: &str, script: &str) {
+        self.header.insert(hook.to_string(), Value::String(script.to_string()));
+    }
+
+    /// The bytes a signature covers: the header without `signature`, which
+    /// includes the payload checksum
+    fn signed_message(&self) -> Vec<u8> {
+        let mut header = self.header.clone();
+        header.remove("signature");
+        serialize_toml(&header).into_bytes()
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        let signature = key.sign(&self.signed_message());
+        self.header.insert("signature".to_string(), Value::String(to_hex(&signature.to_bytes())));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = serialize_toml(&self.header);
+        let mut bytes = Vec::with_capacity(8 + header.len() + self.payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err("not a .tpkg file".to_string());
+        }
+        let header_len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        let header = bytes.get(8..8 + header_len).ok_or("truncated header")?;
+        let header = std::str::from_utf8(header).map_err(|_| "header is not UTF-8")?;
+        Ok(Tpkg {
+            header: parse_toml(header)?,
+            payload: bytes[8 + header_len..].to_vec(),
+        })
+    }
+
+    /// Check the payload checksum and, if the package is signed, that one
+    /// of `trusted` signed it
+    pub fn verify(&self, trusted: &[VerifyingKey]) -> Result<Trust, String> {
+        let expected = self.field("sha256");
+        if expected.is_empty() {
+            return Err("no sha256 in header".to_string());
+        }
+        if !sha256_hex(&self.payload).eq_ignore_ascii_case(expected) {
+            return Err("sha256 mismatch".to_string());
+        }
+
+        let Some(signature) = self.header.get("signature") else {
+            return Ok(Trust::Unsigned);
+        };
+        let signature = signature
+            .as_str()
+            .and_then(|hex| from_hex(hex).ok())
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or("malformed signature")?;
+        let message = self.signed_message();
+        trusted
+            .iter()
+            .find(|key| key.verify_strict(&message, &signature).is_ok())
+            .map(|key| Trust::Signed(to_hex(key.as_bytes())))
+            .ok_or_else(|| "signature does not match any trusted key".to_string())
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err("odd-length hex".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("bad hex: {}", hex)))
+        .collect()
+}
+
+/// A hex ed25519 public key
+pub fn parse_public_key(hex: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = from_hex(hex)?.try_into().map_err(|_| "public key must be 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
+/// A hex ed25519 secret key (seed)
+pub fn parse_secret_key(hex: &str) -> Result<SigningKey, String> {
+    let bytes: [u8; 32] = from_hex(hex)?.try_into().map_err(|_| "secret key must be 32 bytes".to_string())?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
@@ -0,0 +1,144 @@
+//! All-or-nothing file changes
+//!
+//! An install writes archives, package files, file lists and the database
+//! from several workers. Each write goes through a `Transaction`, which
+//! records it in the journal (see `disk_io::Journal`) before making it; if
+//! any package fails, `rollback` puts every path back, and if tomato dies
+//! part way the next run does the same from the journal. A failed install
+//! leaves the system as it found it.
+
+use std::io;
+use std::sync::Mutex;
+use crate::error::TomatoError;
+use crate::storage::backend::Storage;
+use crate::storage::disk_io::Journal;
+
+pub struct Transaction<'a> {
+    storage: &'a dyn Storage,
+    journal: &'a Journal,
+    /// Changes recorded so far, which numbers the saved copies
+    recorded: Mutex<usize>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Start a transaction over `storage`, logged to `journal`
+    pub fn begin(storage: &'a dyn Storage, journal: &'a Journal) -> Result<Self, TomatoError> {
+        journal.begin(storage)?;
+        Ok(Transaction { storage, journal, recorded: Mutex::new(0) })
+    }
+
+    /// Journal the change about to be made to `path`
+    fn record(&self, path: &str) -> io::Result<()> {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.journal.record(self.storage, *recorded, path)?;
+        *recorded += 1;
+        Ok(())
+    }
+
+    /// Note `text` in the transaction log, e.g. a failed hook
+    pub fn note(&self, text: &str) -> io::Result<()> {
+        // One journal append at a time
+        let _recorded = self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.journal.note(self.storage, text)
+    }
+
+    /// How many changes would be undone
+    pub fn len(&self) -> usize {
+        *self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keep the changes
+    pub fn commit(self) -> Result<(), TomatoError> {
+        self.journal.commit(self.storage)
+    }
+
+    /// Undo every change, newest first; returns how many there were
+    pub fn rollback(self) -> Result<usize, TomatoError> {
+        self.journal.roll_back(self.storage)
+    }
+}
+
+/// Reads see the transaction's changes; writes are journaled first
+impl Storage for Transaction<'_> {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.storage.read(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.record(path)?;
+        self.storage.write(path, data)
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.record(path)?;
+        self.storage.append(path, data)
+    }
+
+    fn remove(&self, path: &str) -> io::Result<()> {
+        if !self.storage.exists(path) {
+            return self.storage.remove(path);
+        }
+        self.record(path)?;
+        self.storage.remove(path)
+    }
+
+    fn sync(&self, path: &str) -> io::Result<()> {
+        self.storage.sync(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.storage.exists(path)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        self.storage.list(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::MemoryStorage;
+
+    const JOURNAL: &str = "/var/lib/tomato/journal";
+
+    #[test]
+    fn rollback_restores_and_removes() {
+        let storage = MemoryStorage::with_files([("/etc/motd", &b"hello"[..]), ("/etc/old", &b"gone"[..])]);
+        let journal = Journal::new(JOURNAL);
+        let transaction = Transaction::begin(&storage, &journal).unwrap();
+        transaction.write("/etc/motd", b"changed").unwrap();
+        transaction.write("/usr/bin/new", b"binary").unwrap();
+        transaction.write("/usr/bin/new", b"binary, again").unwrap();
+        transaction.remove("/etc/old").unwrap();
+        assert_eq!(transaction.len(), 4);
+
+        assert_eq!(transaction.rollback().unwrap(), 4);
+        assert_eq!(storage.read("/etc/motd").unwrap(), b"hello");
+        assert_eq!(storage.read("/etc/old").unwrap(), b"gone");
+        assert_eq!(storage.paths(), ["/etc/motd", "/etc/old"]);
+    }
+
+    #[test]
+    fn commit_keeps_the_changes_and_drops_the_journal() {
+        let storage = MemoryStorage::with_files([("/a", &b"0"[..])]);
+        let journal = Journal::new(JOURNAL);
+        let transaction = Transaction::begin(&storage, &journal).unwrap();
+        transaction.write("/a", b"1").unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(storage.paths(), ["/a"]);
+        assert_eq!(storage.read("/a").unwrap(), b"1");
+    }
+
+    #[test]
+    fn only_one_transaction_at_a_time() {
+        let storage = MemoryStorage::new();
+        let journal = Journal::new(JOURNAL);
+        let _first = Transaction::begin(&storage, &journal).unwrap();
+        assert!(matches!(Transaction::begin(&storage, &journal), Err(TomatoError::Unfinished(_))));
+    }
+}
@@ -0,0 +1,50 @@
+use tomato_pm::api::hooks::{HookRunner, Processes};
+use tomato_core::hooks::{check_script, default_allowlist, parse_allowlist};
+
+fn words(commands: &[Vec<String>]) -> Vec<Vec<&str>> {
+    commands.iter().map(|words| words.iter().map(String::as_str).collect()).collect()
+}
+
+#[test]
+fn script_is_split_into_commands() {
+    let script = "# set up\nmkdir /var/lib/hello\n\n  echo 'hello, world' \"and more\"\n";
+    let commands = check_script(script, &default_allowlist()).unwrap();
+    assert_eq!(words(&commands), [vec!["mkdir", "/var/lib/hello"], vec!["echo", "hello, world", "and more"]]);
+}
+
+#[test]
+fn commands_must_be_allowed() {
+    let error = check_script("echo ok\nrm -rf /", &default_allowlist()).unwrap_err();
+    assert_eq!(error, "line 2: rm is not allowed in hooks");
+    assert!(check_script("/bin/echo hi", &default_allowlist()).is_err());
+}
+
+#[test]
+fn shell_syntax_is_refused() {
+    let allowed = default_allowlist();
+    for script in ["echo a; rm b", "echo a && rm b", "echo a | sh", "echo a > /etc/passwd", "echo `id`", "echo $(id)"] {
+        assert!(check_script(script, &allowed).is_err(), "{}", script);
+    }
+    // Quoted, it is just text
+    assert!(check_script("echo 'a; b | c'", &allowed).is_ok());
+    assert_eq!(check_script("echo 'open", &allowed).unwrap_err(), "line 1: unterminated quote");
+}
+
+#[test]
+fn allowlist_file_lists_commands() {
+    let allowed = parse_allowlist("# hooks may run\nldconfig\n  useradd  # for daemons\n\n");
+    assert_eq!(allowed, ["ldconfig", "useradd"]);
+    assert!(check_script("ldconfig", &allowed).is_ok());
+    assert!(check_script("echo hi", &allowed).is_err());
+}
+
+#[test]
+fn processes_report_failures() {
+    let runner = Processes::new("/");
+    assert!(runner.run("hello", "postinst", &[vec!["true".to_string()]]).is_ok());
+    assert_eq!(
+        runner.run("hello", "postinst", &[vec!["false".to_string()], vec!["true".to_string()]]).unwrap_err(),
+        "false exited with status 1"
+    );
+    assert!(runner.run("hello", "postinst", &[vec!["no-such-command-here".to_string()]]).is_err());
+}
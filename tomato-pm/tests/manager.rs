@@ -0,0 +1,475 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tomato_pm::api::hooks::HookRunner;
+use tomato_pm::api::pipeline::{Progress, Quiet, Stage};
+use tomato_core::solver::Suggested;
+use tomato_pm::storage::disk_io::Recovery;
+use tomato_pm::storage::tar::{build_tar, TarEntry};
+use tomato_pm::storage::tpkg::Tpkg;
+use tomato_pm::{MemoryStorage, Storage, Tomato, TomatoError};
+
+const INDEX: &str = "/var/lib/tomato/available.toml";
+const DB: &str = "/var/lib/tomato/packages.txt";
+
+/// A tomato over memory holding `index` as available.toml
+fn tomato(index: &str) -> Tomato<MemoryStorage> {
+    Tomato::with_storage(MemoryStorage::with_files([(INDEX, index.as_bytes())]), "")
+}
+
+fn installed(tomato: &Tomato<MemoryStorage>) -> Vec<String> {
+    tomato.list().unwrap().into_iter().map(|p| p.name).collect()
+}
+
+const HELLO: &str = r#"
+[hello]
+version = "1.0"
+deps = ["libgreet"]
+
+[libgreet]
+version = "0.3"
+deps = ["base"]
+"#;
+
+#[test]
+fn install_puts_dependencies_first() {
+    let tomato = tomato(HELLO);
+    assert_eq!(tomato.install("hello").unwrap(), ["base", "libgreet", "hello"]);
+    let packages = tomato.list().unwrap();
+    assert_eq!(packages.len(), 3);
+    assert!(packages.iter().find(|p| p.name == "libgreet").unwrap().auto);
+    assert!(!packages.iter().find(|p| p.name == "hello").unwrap().auto);
+}
+
+#[test]
+fn install_is_recorded_in_storage_only() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    let db = tomato.storage().read_to_string(DB).unwrap();
+    assert!(db.contains("hello=1.0: libgreet"), "{}", db);
+}
+
+#[test]
+fn dependency_cycle_is_refused() {
+    let tomato = tomato(
+        r#"
+[a]
+deps = ["b"]
+
+[b]
+deps = ["a"]
+"#,
+    );
+    match tomato.install("a") {
+        Err(TomatoError::Resolution(message)) => assert_eq!(message, "dependency cycle: a -> b -> a"),
+        other => panic!("expected a resolution error, got {:?}", other),
+    }
+    assert!(!tomato.storage().exists(DB));
+}
+
+#[test]
+fn suggestions_install_on_request() {
+    let tomato = tomato(
+        r#"
+[editor]
+deps = ["plugins"]
+suggests = ["spell"]
+
+[plugins]
+suggests = ["editor"]
+
+[spell]
+"#,
+    );
+    assert_eq!(tomato.install("editor").unwrap(), ["plugins", "editor"]);
+    assert_eq!(tomato.install_with("spell", Suggested::Skip, &Quiet).unwrap(), ["spell"]);
+    tomato.remove("spell", false).unwrap();
+    assert_eq!(tomato.install_with("editor", Suggested::BreakCycles, &Quiet).unwrap(), ["spell"]);
+}
+
+#[test]
+fn missing_package_is_a_resolution_error() {
+    let tomato = tomato(HELLO);
+    match tomato.install("nonexistent") {
+        Err(TomatoError::Resolution(message)) => assert!(message.contains("nonexistent"), "{}", message),
+        other => panic!("expected a resolution error, got {:?}", other),
+    }
+    assert!(!tomato.storage().exists(DB));
+}
+
+#[test]
+fn missing_dependency_leaves_the_database_alone() {
+    let tomato = tomato(
+        r#"
+[broken]
+deps = ["ghost"]
+"#,
+    );
+    tomato.install("base").unwrap();
+    let before = tomato.storage().read(DB).unwrap();
+    match tomato.install("broken") {
+        Err(TomatoError::Resolution(message)) => assert!(message.contains("ghost"), "{}", message),
+        other => panic!("expected a resolution error, got {:?}", other),
+    }
+    assert_eq!(tomato.storage().read(DB).unwrap(), before);
+}
+
+#[test]
+fn corrupted_database_is_recovered_from_backup() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    tomato.storage().write(DB, b"hel\xffo=1.0: \x00").unwrap();
+    assert_eq!(installed(&tomato), ["base", "libgreet", "hello"]);
+    // The next operation works from the restored copy
+    tomato.remove("hello", false).unwrap();
+    assert_eq!(installed(&tomato), ["base", "libgreet"]);
+}
+
+#[test]
+fn corrupted_database_and_backup_is_an_error() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    tomato.storage().write(DB, b"<<<<<<<").unwrap();
+    tomato.storage().write(&format!("{}.bak", DB), b">>>>>>>").unwrap();
+    assert!(matches!(tomato.list(), Err(TomatoError::Parse { .. })));
+    assert!(matches!(tomato.install("base"), Err(TomatoError::Parse { .. })));
+}
+
+#[test]
+fn remove_refuses_to_break_dependents() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    match tomato.remove("libgreet", false) {
+        Err(TomatoError::Conflict { package, dependents }) => {
+            assert_eq!(package, "libgreet");
+            assert_eq!(dependents, ["hello"]);
+        }
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert!(matches!(tomato.remove("nonexistent", false), Err(TomatoError::NotInstalled(_))));
+}
+
+#[test]
+fn autoremove_removes_what_nothing_needs() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    tomato.remove("hello", false).unwrap();
+    let mut removed = tomato.autoremove().unwrap();
+    removed.sort();
+    assert_eq!(removed, ["base", "libgreet"]);
+    assert!(installed(&tomato).is_empty());
+}
+
+#[test]
+fn update_reads_a_source_from_storage() {
+    let tomato = tomato("");
+    tomato.storage().write("/srv/index.toml", HELLO.as_bytes()).unwrap();
+    tomato.storage().write("/etc/tomato/source", b"file:///srv/index.toml\n").unwrap();
+    // hello, libgreet, and the built-in base and kernel
+    assert_eq!(tomato.update(None).unwrap(), 4);
+    assert_eq!(tomato.search("greet").unwrap(), ["libgreet"]);
+}
+
+#[test]
+fn update_keeps_the_index_when_the_source_is_broken() {
+    let tomato = tomato(HELLO);
+    tomato.storage().write("/srv/index.toml", b"[unterminated").unwrap();
+    assert!(matches!(tomato.update(Some("/srv/index.toml")), Err(TomatoError::Parse { .. })));
+    assert_eq!(tomato.storage().read_to_string(INDEX).unwrap(), HELLO);
+}
+
+#[test]
+fn upgrade_moves_to_the_index_version() {
+    let tomato = tomato(HELLO);
+    tomato.install("hello").unwrap();
+    tomato
+        .storage()
+        .write(INDEX, HELLO.replace("\"1.0\"", "\"1.1\"\nchangelog = \"Louder\"").as_bytes())
+        .unwrap();
+
+    let plan = tomato.upgrade(true).unwrap();
+    assert_eq!(plan.upgrades.len(), 1);
+    assert_eq!(plan.upgrades[0].1, "Louder");
+    assert_eq!(tomato.list().unwrap().iter().find(|p| p.name == "hello").unwrap().version, "1.0");
+
+    tomato.upgrade(false).unwrap();
+    assert_eq!(tomato.list().unwrap().iter().find(|p| p.name == "hello").unwrap().version, "1.1");
+}
+
+#[test]
+fn run_with_uses_the_given_storage() {
+    let tomato = tomato(HELLO);
+    let args: Vec<String> = ["tomato", "install", "hello"].iter().map(|s| s.to_string()).collect();
+    tomato_pm::run_with(&tomato, &args).unwrap();
+    assert_eq!(installed(&tomato).len(), 3);
+}
+
+/// A .tpkg of `name` `version` holding `files` (path, contents)
+fn tpkg(name: &str, version: &str, files: &[(&str, &str)]) -> Vec<u8> {
+    let entries: Vec<TarEntry> = files
+        .iter()
+        .map(|(path, data)| TarEntry { path: path.to_string(), data: data.as_bytes().to_vec(), is_dir: false })
+        .collect();
+    Tpkg::new(name, version, build_tar(&entries)).to_bytes()
+}
+
+const MIRRORED: &str = r#"
+[hello]
+version = "1.0"
+deps = ["libgreet"]
+archive = "hello-1.0.tpkg"
+
+[libgreet]
+version = "0.3"
+archive = "libgreet-0.3.tpkg"
+"#;
+
+/// A tomato whose index came from /srv/mirror, holding both packages
+fn mirrored() -> Tomato<MemoryStorage> {
+    let tomato = tomato(MIRRORED);
+    let storage = tomato.storage();
+    storage.write("/etc/tomato/source", b"/srv/mirror/available.toml").unwrap();
+    storage
+        .write("/srv/mirror/hello-1.0.tpkg", &tpkg("hello", "1.0", &[("bin/hello", "#!hello"), ("share/hello.txt", "hi")]))
+        .unwrap();
+    storage.write("/srv/mirror/libgreet-0.3.tpkg", &tpkg("libgreet", "0.3", &[("lib/libgreet.so", "greet")])).unwrap();
+    tomato
+}
+
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(String, Stage)>>);
+
+impl Progress for Recorder {
+    fn update(&self, package: &str, stage: Stage, _done: u64, _total: Option<u64>) {
+        self.0.lock().unwrap().push((package.to_string(), stage));
+    }
+}
+
+#[test]
+fn install_fetches_verifies_and_unpacks() {
+    let tomato = mirrored();
+    let progress = Recorder::default();
+    assert_eq!(tomato.install_with("hello", Suggested::Skip, &progress).unwrap(), ["libgreet", "hello"]);
+
+    let storage = tomato.storage();
+    assert_eq!(storage.read_to_string("/usr/bin/hello").unwrap(), "#!hello");
+    assert_eq!(storage.read_to_string("/usr/lib/libgreet.so").unwrap(), "greet");
+    assert!(storage.exists("/var/lib/tomato/packages/hello-1.0.tpkg"));
+    assert_eq!(
+        storage.read_to_string("/var/lib/tomato/files/hello.list").unwrap(),
+        "/usr/bin/hello\n/usr/share/hello.txt"
+    );
+
+    let stages = progress.0.into_inner().unwrap();
+    for package in ["hello", "libgreet"] {
+        let seen: Vec<Stage> = stages.iter().filter(|(p, _)| p == package).map(|(_, s)| *s).collect();
+        assert_eq!(seen.first(), Some(&Stage::Fetching), "{}", package);
+        assert!(seen.contains(&Stage::Verifying) && seen.contains(&Stage::Unpacking), "{}", package);
+        assert_eq!(seen.last(), Some(&Stage::Done), "{}", package);
+    }
+}
+
+#[test]
+fn failed_package_rolls_back_the_others() {
+    let tomato = mirrored();
+    tomato.storage().write("/usr/lib/libgreet.so", b"older").unwrap();
+    // A tampered archive fails its checksum; libgreet, before it, is done
+    let mut bad = tpkg("hello", "1.0", &[("bin/hello", "#!hello")]);
+    *bad.last_mut().unwrap() ^= 1;
+    tomato.storage().write("/srv/mirror/hello-1.0.tpkg", &bad).unwrap();
+    let before = tomato.storage().paths();
+
+    match tomato.install("hello") {
+        Err(TomatoError::Verify { path, .. }) => assert_eq!(path, "/var/lib/tomato/packages/hello-1.0.tpkg"),
+        other => panic!("expected a verification error, got {:?}", other),
+    }
+    assert_eq!(tomato.storage().paths(), before);
+    assert_eq!(tomato.storage().read_to_string("/usr/lib/libgreet.so").unwrap(), "older");
+    assert!(installed(&tomato).is_empty());
+}
+
+#[test]
+fn archive_with_the_wrong_package_is_refused() {
+    let tomato = mirrored();
+    tomato.storage().write("/srv/mirror/libgreet-0.3.tpkg", &tpkg("evil", "0.3", &[])).unwrap();
+    assert!(matches!(tomato.install("libgreet"), Err(TomatoError::Verify { .. })));
+    assert!(!tomato.storage().exists("/var/lib/tomato/packages/libgreet-0.3.tpkg"));
+}
+
+#[test]
+fn remove_deletes_the_unpacked_files() {
+    let tomato = mirrored();
+    tomato.install("hello").unwrap();
+    tomato.remove("hello", false).unwrap();
+    assert!(!tomato.storage().exists("/usr/bin/hello"));
+    assert!(!tomato.storage().exists("/var/lib/tomato/files/hello.list"));
+    assert!(tomato.storage().exists("/usr/lib/libgreet.so"));
+    tomato.autoremove().unwrap();
+    assert!(!tomato.storage().exists("/usr/lib/libgreet.so"));
+}
+
+/// Storage that loses power after a number of changes: every write,
+/// append and remove after that fails
+struct Crashing<'a> {
+    inner: &'a MemoryStorage,
+    changes_left: AtomicUsize,
+}
+
+impl Crashing<'_> {
+    fn change(&self) -> io::Result<()> {
+        self.changes_left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+            .map(|_| ())
+            .map_err(|_| io::Error::other("power cut"))
+    }
+}
+
+impl Storage for Crashing<'_> {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.change()?;
+        self.inner.write(path, data)
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.change()?;
+        self.inner.append(path, data)
+    }
+
+    fn remove(&self, path: &str) -> io::Result<()> {
+        self.change()?;
+        self.inner.remove(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        self.inner.list(dir)
+    }
+}
+
+/// Every file and what it holds
+fn snapshot(storage: &MemoryStorage) -> Vec<(String, Vec<u8>)> {
+    storage.paths().into_iter().map(|path| (path.clone(), storage.read(&path).unwrap())).collect()
+}
+
+#[test]
+fn crash_at_any_point_is_repaired_to_before_or_after() {
+    let finished = mirrored();
+    finished.install("hello").unwrap();
+    let after = snapshot(finished.storage());
+
+    for changes in 0.. {
+        let memory = mirrored();
+        let before = snapshot(memory.storage());
+        let crashing = Crashing { inner: memory.storage(), changes_left: AtomicUsize::new(changes) };
+        let outcome = Tomato::with_storage(&crashing, "").install("hello");
+
+        let repaired = Tomato::with_storage(memory.storage(), "");
+        let recovery = repaired.repair().unwrap();
+        let state = snapshot(memory.storage());
+        if outcome.is_ok() {
+            assert_eq!(recovery, Recovery::Clean);
+            assert_eq!(state, after);
+            break;
+        }
+        assert!(state == before || state == after, "crash after {} changes left {:?}", changes, recovery);
+    }
+}
+
+#[test]
+fn unfinished_transaction_blocks_changes_until_repaired() {
+    let memory = mirrored();
+    let crashing = Crashing { inner: memory.storage(), changes_left: AtomicUsize::new(6) };
+    assert!(Tomato::with_storage(&crashing, "").install("hello").is_err());
+
+    let tomato = Tomato::with_storage(memory.storage(), "");
+    assert!(matches!(tomato.install("hello"), Err(TomatoError::Unfinished(_))));
+    assert!(matches!(tomato.repair().unwrap(), Recovery::RolledBack(_)));
+    assert_eq!(tomato.repair().unwrap(), Recovery::Clean);
+    assert_eq!(tomato.install("hello").unwrap(), ["libgreet", "hello"]);
+}
+
+#[test]
+fn run_repairs_before_anything_else() {
+    let memory = mirrored();
+    let crashing = Crashing { inner: memory.storage(), changes_left: AtomicUsize::new(6) };
+    assert!(Tomato::with_storage(&crashing, "").install("hello").is_err());
+
+    let tomato = Tomato::with_storage(memory.storage(), "");
+    let args: Vec<String> = ["tomato", "install", "hello"].iter().map(|s| s.to_string()).collect();
+    tomato_pm::run_with(&tomato, &args).unwrap();
+    assert_eq!(tomato.list().unwrap().len(), 2);
+}
+
+/// Hooks it was asked to run, failing the ones whose first command is
+/// `false`
+#[derive(Clone, Default)]
+struct Hooks(std::sync::Arc<Mutex<Vec<String>>>);
+
+impl HookRunner for Hooks {
+    fn run(&self, package: &str, hook: &str, commands: &[Vec<String>]) -> Result<(), String> {
+        self.0.lock().unwrap().push(format!("{} {}: {}", package, hook, commands[0].join(" ")));
+        if commands[0][0] == "false" {
+            return Err("false exited with status 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// `mirrored` with hooks on both packages and `hooks` running them
+fn hooked(hooks: &Hooks, hello_postinst: &str) -> Tomato<MemoryStorage> {
+    let tomato = mirrored().with_hook_runner(hooks.clone());
+    let storage = tomato.storage();
+    storage.write("/etc/tomato/hooks.allow", b"echo\nfalse\n").unwrap();
+    let mut hello = Tpkg::parse(&tpkg("hello", "1.0", &[("bin/hello", "#!hello")])).unwrap();
+    hello.set_script("postinst", hello_postinst);
+    hello.set_script("prerm", "echo bye");
+    storage.write("/srv/mirror/hello-1.0.tpkg", &hello.to_bytes()).unwrap();
+    let mut libgreet = Tpkg::parse(&tpkg("libgreet", "0.3", &[])).unwrap();
+    libgreet.set_script("postinst", "echo greet");
+    storage.write("/srv/mirror/libgreet-0.3.tpkg", &libgreet.to_bytes()).unwrap();
+    tomato
+}
+
+#[test]
+fn hooks_run_in_dependency_order() {
+    let hooks = Hooks::default();
+    let tomato = hooked(&hooks, "echo 'hello installed'");
+    tomato.install("hello").unwrap();
+    tomato.remove("hello", false).unwrap();
+    assert_eq!(
+        *hooks.0.lock().unwrap(),
+        ["libgreet postinst: echo greet", "hello postinst: echo hello installed", "hello prerm: echo bye"]
+    );
+    assert!(!tomato.storage().exists("/var/lib/tomato/scripts/hello.prerm"));
+    assert!(tomato.storage().exists("/var/lib/tomato/scripts/libgreet.postinst"));
+}
+
+#[test]
+fn failed_hook_is_logged_and_the_install_stands() {
+    let hooks = Hooks::default();
+    let tomato = hooked(&hooks, "false");
+    assert_eq!(tomato.install("hello").unwrap(), ["libgreet", "hello"]);
+    assert_eq!(
+        tomato.storage().read_to_string("/var/lib/tomato/journal.log").unwrap(),
+        "hello postinst failed: false exited with status 1\n"
+    );
+}
+
+#[test]
+fn hooks_outside_the_allowlist_do_not_run() {
+    let hooks = Hooks::default();
+    let tomato = hooked(&hooks, "rm -rf /");
+    tomato.install("hello").unwrap();
+    assert_eq!(*hooks.0.lock().unwrap(), ["libgreet postinst: echo greet"]);
+    assert_eq!(
+        tomato.storage().read_to_string("/var/lib/tomato/journal.log").unwrap(),
+        "hello postinst failed: line 1: rm is not allowed in hooks\n"
+    );
+}
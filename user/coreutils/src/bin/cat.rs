@@ -0,0 +1,38 @@
+//! cat [file...] - copy files (or stdin) to stdout
+
+#![no_std]
+#![no_main]
+
+use ospab::eprintln;
+use ospab::fs::{File, O_RDONLY};
+use ospab::io::{self, STDIN, STDOUT};
+
+ospab::entry!(main);
+
+/// Copy everything readable from `fd` to stdout
+fn copy(fd: u64) -> ospab::Result<()> {
+    let mut buf = [0u8; 512];
+    loop {
+        let n = io::read(fd, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        io::write_all(STDOUT, &buf[..n])?;
+    }
+}
+
+fn main() -> i32 {
+    if ospab::env::argc() < 2 {
+        return if copy(STDIN).is_ok() { 0 } else { 1 };
+    }
+
+    let mut status = 0;
+    for path in ospab::env::args().skip(1) {
+        let result = File::open(path, O_RDONLY).and_then(|file| copy(file.fd()));
+        if let Err(e) = result {
+            eprintln!("cat: {}: {}", path, e);
+            status = 1;
+        }
+    }
+    status
+}
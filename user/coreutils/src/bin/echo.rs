@@ -0,0 +1,29 @@
+//! echo [-n] [string...] - print the arguments separated by spaces
+
+#![no_std]
+#![no_main]
+
+use ospab::{print, println};
+
+ospab::entry!(main);
+
+fn main() -> i32 {
+    let mut args = ospab::env::args().skip(1).peekable();
+    let newline = args.peek() != Some(&"-n");
+    if !newline {
+        args.next();
+    }
+
+    let mut first = true;
+    for arg in args {
+        if !first {
+            print!(" ");
+        }
+        print!("{}", arg);
+        first = false;
+    }
+    if newline {
+        println!();
+    }
+    0
+}
@@ -0,0 +1,146 @@
+//! ls [-a] [-l] [path...] - list directory entries, one per line
+//!
+//! -a also lists names starting with '.', -l prints the type and
+//! permissions, size and modification time (since boot) of each entry.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use ospab::fs::Metadata;
+use ospab::{eprintln, println};
+
+ospab::entry!(main);
+
+#[derive(Default)]
+struct Options {
+    all: bool,
+    long: bool,
+}
+
+/// `drwxr-xr-x` for a directory with mode 0o755
+fn mode_string(metadata: &Metadata) -> String {
+    let kind = if metadata.is_dir() {
+        'd'
+    } else if metadata.is_device() {
+        'c'
+    } else if metadata.is_symlink() {
+        'l'
+    } else {
+        '-'
+    };
+    let mut text = String::from(kind);
+    let mode = metadata.permissions();
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    text
+}
+
+/// Milliseconds since boot as h:mm:ss
+fn time_string(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn print_entry(name: &str, path: &str, options: &Options) {
+    if !options.long {
+        println!("{}", name);
+        return;
+    }
+    match ospab::fs::metadata(path) {
+        Ok(metadata) => println!(
+            "{} {:>8} {:>9} {}",
+            mode_string(&metadata),
+            metadata.len(),
+            time_string(metadata.modified_ms()),
+            name
+        ),
+        // Listed but gone, or a generated file that cannot be read
+        Err(e) => println!("?????????? {:>8} {:>9} {} ({})", "?", "?", name, e),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn list(path: &str, options: &Options) -> bool {
+    // A file lists as itself
+    if let Ok(metadata) = ospab::fs::metadata(path) {
+        if !metadata.is_dir() {
+            print_entry(path, path, options);
+            return true;
+        }
+    }
+    match ospab::fs::read_dir(path) {
+        Ok(entries) => {
+            if options.all {
+                print_entry(".", path, options);
+                print_entry("..", &join(path, ".."), options);
+            }
+            for entry in entries {
+                if entry.starts_with('.') && !options.all {
+                    continue;
+                }
+                print_entry(&entry, &join(path, &entry), options);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("ls: {}: {}", path, e);
+            false
+        }
+    }
+}
+
+fn main() -> i32 {
+    let mut options = Options::default();
+    let mut paths: Vec<&str> = Vec::new();
+    for arg in ospab::env::args().skip(1) {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'a' => options.all = true,
+                        'l' => options.long = true,
+                        _ => {
+                            eprintln!("ls: unknown option -{}", flag);
+                            eprintln!("usage: ls [-a] [-l] [path...]");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    let mut status = 0;
+    for (i, path) in paths.iter().enumerate() {
+        if paths.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", path);
+        }
+        if !list(path, &options) {
+            status = 1;
+        }
+    }
+    status
+}
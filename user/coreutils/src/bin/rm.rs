@@ -0,0 +1,78 @@
+//! rm [-r] [-f] path... - remove files, and with -r directories and
+//! everything in them
+//!
+//! -f ignores paths that do not exist.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use ospab::eprintln;
+use ospab::sys::ENOENT;
+
+ospab::entry!(main);
+
+#[derive(Default)]
+struct Options {
+    recursive: bool,
+    force: bool,
+}
+
+fn remove(path: &str, options: &Options) -> bool {
+    let result = match ospab::fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            if !options.recursive {
+                eprintln!("rm: {}: is a directory", path);
+                return false;
+            }
+            ospab::fs::remove_dir_all(path)
+        }
+        Ok(_) => ospab::fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) if e.0 == ENOENT && options.force => true,
+        Err(e) => {
+            eprintln!("rm: {}: {}", path, e);
+            false
+        }
+    }
+}
+
+fn main() -> i32 {
+    let mut options = Options::default();
+    let mut paths: Vec<&str> = Vec::new();
+    for arg in ospab::env::args().skip(1) {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'r' | 'R' => options.recursive = true,
+                        'f' => options.force = true,
+                        _ => {
+                            eprintln!("rm: unknown option -{}", flag);
+                            eprintln!("usage: rm [-r] [-f] path...");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() && !options.force {
+        eprintln!("usage: rm [-r] [-f] path...");
+        return 2;
+    }
+
+    let mut status = 0;
+    for path in paths {
+        if !remove(path, &options) {
+            status = 1;
+        }
+    }
+    status
+}
@@ -0,0 +1,29 @@
+//! rmdir dir... - remove empty directories
+
+#![no_std]
+#![no_main]
+
+use ospab::eprintln;
+
+ospab::entry!(main);
+
+fn main() -> i32 {
+    if ospab::env::argc() < 2 {
+        eprintln!("usage: rmdir dir...");
+        return 2;
+    }
+
+    let mut status = 0;
+    for path in ospab::env::args().skip(1) {
+        let result = match ospab::fs::metadata(path) {
+            Ok(metadata) if !metadata.is_dir() => Err(ospab::Errno(ospab::sys::ENOTDIR)),
+            Ok(_) => ospab::fs::remove_dir(path),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("rmdir: {}: {}", path, e);
+            status = 1;
+        }
+    }
+    status
+}
@@ -0,0 +1,132 @@
+//! wc [-lwc] [file...] - count lines, words and bytes
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use ospab::fs::{File, O_RDONLY};
+use ospab::io::{self, STDIN};
+use ospab::{eprintln, print, println};
+
+ospab::entry!(main);
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    lines: u64,
+    words: u64,
+    bytes: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Which columns to print
+struct Show {
+    lines: bool,
+    words: bool,
+    bytes: bool,
+}
+
+fn count(fd: u64) -> ospab::Result<Counts> {
+    let mut counts = Counts::default();
+    let mut in_word = false;
+    let mut buf = [0u8; 512];
+    loop {
+        let n = io::read(fd, &mut buf)?;
+        if n == 0 {
+            return Ok(counts);
+        }
+        counts.bytes += n as u64;
+        for &b in &buf[..n] {
+            if b == b'\n' {
+                counts.lines += 1;
+            }
+            let space = b.is_ascii_whitespace();
+            if !space && !in_word {
+                counts.words += 1;
+            }
+            in_word = !space;
+        }
+    }
+}
+
+fn report(counts: Counts, show: &Show, name: &str) {
+    if show.lines {
+        print!("{:>8}", counts.lines);
+    }
+    if show.words {
+        print!("{:>8}", counts.words);
+    }
+    if show.bytes {
+        print!("{:>8}", counts.bytes);
+    }
+    if name.is_empty() {
+        println!();
+    } else {
+        println!(" {}", name);
+    }
+}
+
+fn main() -> i32 {
+    let mut show = Show { lines: false, words: false, bytes: false };
+    let mut files = alloc::vec::Vec::new();
+    for arg in ospab::env::args().skip(1) {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => show.lines = true,
+                        'w' => show.words = true,
+                        'c' => show.bytes = true,
+                        _ => {
+                            eprintln!("wc: unknown option -{}", flag);
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => files.push(arg),
+        }
+    }
+    if !(show.lines || show.words || show.bytes) {
+        show = Show { lines: true, words: true, bytes: true };
+    }
+
+    if files.is_empty() {
+        return match count(STDIN) {
+            Ok(counts) => {
+                report(counts, &show, "");
+                0
+            }
+            Err(e) => {
+                eprintln!("wc: {}", e);
+                1
+            }
+        };
+    }
+
+    let mut status = 0;
+    let mut total = Counts::default();
+    for path in &files {
+        match File::open(path, O_RDONLY).and_then(|file| count(file.fd())) {
+            Ok(counts) => {
+                report(counts, &show, path);
+                total.add(counts);
+            }
+            Err(e) => {
+                eprintln!("wc: {}: {}", path, e);
+                status = 1;
+            }
+        }
+    }
+    if files.len() > 1 {
+        report(total, &show, "total");
+    }
+    status
+}
@@ -0,0 +1,266 @@
+//! xxd [-s offset] [-l length] [file] - show bytes as hex and text
+//! xxd -w offset hexbytes file - overwrite bytes in a file
+//!
+//! Each line is the offset, 16 bytes in hex and the same bytes as text,
+//! with '.' for anything unprintable:
+//!
+//! ```text
+//! 00000000: 7f45 4c46 0201 0100 0000 0000 0000 0000  .ELF............
+//! ```
+//!
+//! Reads stdin when no file is given. On the terminal a file is shown a
+//! screen at a time: Space for the next screen, Enter for one more line,
+//! q to stop. -w writes the bytes (e.g. `deadbeef`) at the offset, past
+//! the end extending the file. Offsets and lengths are decimal or 0x hex.
+//! Also installed as hexdump.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use ospab::fs::{File, SeekFrom, O_RDONLY};
+use ospab::io::{self, STDIN, STDOUT};
+use ospab::{eprintln, print};
+
+ospab::entry!(main);
+
+const BYTES_PER_LINE: usize = 16;
+
+#[derive(Default)]
+struct Options {
+    offset: u64,
+    length: Option<u64>,
+}
+
+/// Waits between screens of output
+struct Pager {
+    rows: usize,
+    /// Lines left to print before asking again
+    budget: usize,
+}
+
+impl Pager {
+    /// A pager when both the output and the keyboard are the terminal
+    fn new() -> Option<Pager> {
+        if !io::is_terminal(STDIN) {
+            return None;
+        }
+        let (_, rows) = io::terminal_size(STDOUT).ok()?;
+        let rows = rows.saturating_sub(1).max(1);
+        Some(Pager { rows, budget: rows })
+    }
+
+    /// Count a line about to be printed; false once the user quits
+    fn line(&mut self, offset: u64) -> bool {
+        if self.budget == 0 {
+            print!("--More-- ({:08x})", offset);
+            let _ = io::set_raw(true);
+            let mut key = [0u8; 1];
+            self.budget = loop {
+                match io::read(STDIN, &mut key) {
+                    Ok(1) => match key[0] {
+                        b' ' => break self.rows,
+                        b'\n' | b'\r' => break 1,
+                        b'q' | b'Q' | 0x03 => break 0,
+                        _ => {}
+                    },
+                    _ => break 0,
+                }
+            };
+            let _ = io::set_raw(false);
+            // Erase the prompt
+            print!("\r{:19}\r", "");
+            if self.budget == 0 {
+                return false;
+            }
+        }
+        self.budget -= 1;
+        true
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| *b != b' ' && *b != b':').collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// One line of output for `bytes` starting at `offset`
+fn format_line(offset: u64, bytes: &[u8]) -> String {
+    let mut line = format!("{:08x}:", offset);
+    for i in 0..BYTES_PER_LINE {
+        if i % 2 == 0 {
+            line.push(' ');
+        }
+        match bytes.get(i) {
+            Some(b) => line += &format!("{:02x}", b),
+            None => line += "  ",
+        }
+    }
+    line += "  ";
+    for &b in bytes {
+        line.push(if (0x20..0x7f).contains(&b) { b as char } else { '.' });
+    }
+    line.push('\n');
+    line
+}
+
+/// Read until `buf` is full or the input ends
+fn read_full(fd: u64, buf: &mut [u8]) -> ospab::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = io::read(fd, &mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Skip `count` bytes of input that cannot seek
+fn skip(fd: u64, mut count: u64) -> ospab::Result<()> {
+    let mut buf = [0u8; 512];
+    while count > 0 {
+        let want = count.min(buf.len() as u64) as usize;
+        let n = io::read(fd, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        count -= n as u64;
+    }
+    Ok(())
+}
+
+fn dump(fd: u64, options: &Options, mut pager: Option<Pager>) -> ospab::Result<()> {
+    let mut offset = options.offset;
+    let mut remaining = options.length.unwrap_or(u64::MAX);
+    let mut buf = [0u8; BYTES_PER_LINE];
+    while remaining > 0 {
+        let want = remaining.min(BYTES_PER_LINE as u64) as usize;
+        let n = read_full(fd, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        if let Some(pager) = pager.as_mut() {
+            if !pager.line(offset) {
+                break;
+            }
+        }
+        io::write_all(STDOUT, format_line(offset, &buf[..n]).as_bytes())?;
+        offset += n as u64;
+        remaining -= n as u64;
+        if n < want {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn show(path: Option<&str>, options: &Options) -> ospab::Result<()> {
+    let Some(path) = path else {
+        skip(STDIN, options.offset)?;
+        return dump(STDIN, options, None);
+    };
+    let mut file = File::open(path, O_RDONLY)?;
+    if options.offset > 0 {
+        file.seek(SeekFrom::Start(options.offset))?;
+    }
+    dump(file.fd(), options, Pager::new())
+}
+
+/// Overwrite the bytes at `offset` and write the file back
+fn patch(path: &str, offset: u64, bytes: &[u8]) -> Result<(), String> {
+    let mut data = ospab::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let offset = offset as usize;
+    if offset > data.len() {
+        return Err(format!("{}: offset {:#x} is past the end ({} bytes)", path, offset, data.len()));
+    }
+    let end = offset + bytes.len();
+    if end > data.len() {
+        data.resize(end, 0);
+    }
+    data[offset..end].copy_from_slice(bytes);
+    ospab::fs::write(path, &data).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn usage(name: &str) -> i32 {
+    eprintln!("usage: {} [-s offset] [-l length] [file]", name);
+    eprintln!("       {} -w offset hexbytes file", name);
+    2
+}
+
+fn main() -> i32 {
+    let mut args = ospab::env::args();
+    let name = args.next().and_then(|arg| arg.rsplit('/').next()).unwrap_or("xxd");
+    let mut options = Options::default();
+    let mut write = false;
+    let mut operands: Vec<&str> = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-s" | "-l" => {
+                let Some(value) = args.next().and_then(parse_number) else {
+                    eprintln!("{}: {} needs a number", name, arg);
+                    return usage(name);
+                };
+                if arg == "-s" {
+                    options.offset = value;
+                } else {
+                    options.length = Some(value);
+                }
+            }
+            "-w" => write = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                eprintln!("{}: unknown option {}", name, arg);
+                return usage(name);
+            }
+            _ => operands.push(arg),
+        }
+    }
+
+    if write {
+        let [offset, bytes, path] = operands[..] else { return usage(name) };
+        let Some(offset) = parse_number(offset) else {
+            eprintln!("{}: bad offset '{}'", name, offset);
+            return 2;
+        };
+        let Some(bytes) = parse_hex_bytes(bytes) else {
+            eprintln!("{}: bad hex bytes '{}'", name, bytes);
+            return 2;
+        };
+        return match patch(path, offset, &bytes) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                1
+            }
+        };
+    }
+
+    if operands.len() > 1 {
+        return usage(name);
+    }
+    match show(operands.first().copied(), &options) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}: {}: {}", name, operands.first().unwrap_or(&"stdin"), e);
+            1
+        }
+    }
+}
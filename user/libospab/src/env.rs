@@ -0,0 +1,114 @@
+//! Program arguments and environment
+//!
+//! exec leaves argc, the argv pointers, a NULL, the envp pointers,
+//! another NULL and the auxiliary vector at the initial stack pointer;
+//! `rt::start` records where. The strings stay on the stack for the life
+//! of the program.
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Auxiliary vector entry: address of 16 random bytes
+pub const AT_RANDOM: u64 = 25;
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+static AUXV: AtomicPtr<u64> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Record the argument block at the initial stack pointer `sp`
+///
+/// # Safety
+/// `sp` must point at the block laid out by exec.
+pub unsafe fn init(sp: *const u64) {
+    let argc = *sp as usize;
+    let argv = sp.add(1) as *mut *const u8;
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv, Ordering::Relaxed);
+    let envp = argv.add(argc + 1);
+    ENVP.store(envp, Ordering::Relaxed);
+    let mut end = envp;
+    while !(*end).is_null() {
+        end = end.add(1);
+    }
+    AUXV.store(end.add(1) as *mut u64, Ordering::Relaxed);
+}
+
+/// Value of the auxiliary vector entry `kind` (AT_*), if exec passed one
+pub fn auxv(kind: u64) -> Option<u64> {
+    let mut entry = AUXV.load(Ordering::Relaxed) as *const u64;
+    if entry.is_null() {
+        return None;
+    }
+    unsafe {
+        // (type, value) pairs up to AT_NULL
+        while *entry != 0 {
+            if *entry == kind {
+                return Some(*entry.add(1));
+            }
+            entry = entry.add(2);
+        }
+    }
+    None
+}
+
+/// The 16 random bytes exec put on the stack (AT_RANDOM), different for
+/// every program run; the stack canary is made from them
+pub fn random_bytes() -> Option<[u8; 16]> {
+    let ptr = auxv(AT_RANDOM)? as *const [u8; 16];
+    Some(unsafe { ptr.read_unaligned() })
+}
+
+/// NUL-terminated string at `ptr` (invalid UTF-8 reads as "")
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+/// Iterator over a NULL-terminated pointer array
+pub struct Strings {
+    next: *const *const u8,
+}
+
+impl Iterator for Strings {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.next.is_null() {
+            return None;
+        }
+        unsafe {
+            let ptr = *self.next;
+            if ptr.is_null() {
+                return None;
+            }
+            self.next = self.next.add(1);
+            Some(c_str(ptr))
+        }
+    }
+}
+
+/// Number of arguments, including the program name
+pub fn argc() -> usize {
+    ARGC.load(Ordering::Relaxed)
+}
+
+/// The arguments, starting with the program name
+pub fn args() -> Strings {
+    Strings { next: ARGV.load(Ordering::Relaxed) }
+}
+
+/// The environment as `KEY=value` strings
+pub fn vars() -> Strings {
+    Strings { next: ENVP.load(Ordering::Relaxed) }
+}
+
+/// Value of the environment variable `key`
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find_map(|entry| {
+        let (k, v) = entry.split_once('=')?;
+        if k == key { Some(v) } else { None }
+    })
+}
@@ -0,0 +1,186 @@
+//! Files and directories
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::{check, io, sys, with_c_str, Result};
+
+/// Open flags for `File::open`: one access mode, plus any of the others
+pub const O_RDONLY: u64 = 0;
+pub const O_WRONLY: u64 = 1;
+pub const O_RDWR: u64 = 2;
+pub use crate::sys::{O_APPEND, O_CREAT, O_TRUNC};
+
+/// Position for `File::seek`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// An open file descriptor, closed on drop
+pub struct File {
+    fd: u64,
+}
+
+impl File {
+    pub fn open(path: &str, flags: u64) -> Result<File> {
+        let fd = check(with_c_str(path, |p| unsafe { sys::open(p, flags) })?)?;
+        Ok(File { fd })
+    }
+
+    /// Open `path` for writing, creating it or emptying it
+    pub fn create(path: &str) -> Result<File> {
+        File::open(path, O_WRONLY | O_CREAT | O_TRUNC)
+    }
+
+    pub fn fd(&self) -> u64 {
+        self.fd
+    }
+
+    /// Move the read/write position; returns it, from the start
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset as i64, sys::SEEK_SET),
+            SeekFrom::Current(offset) => (offset, sys::SEEK_CUR),
+            SeekFrom::End(offset) => (offset, sys::SEEK_END),
+        };
+        check(unsafe { sys::lseek(self.fd, offset, whence) })
+    }
+
+    /// Metadata of the open file, as it was when opened
+    pub fn metadata(&self) -> Result<Metadata> {
+        let mut stat = sys::Stat::default();
+        check(unsafe { sys::fstat(self.fd, &mut stat) })?;
+        Ok(Metadata(stat))
+    }
+
+    /// Read into `buf`; 0 means end of file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        check(unsafe { sys::read(self.fd, buf.as_mut_ptr(), buf.len()) }).map(|n| n as usize)
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        check(unsafe { sys::write(self.fd, buf.as_ptr(), buf.len()) }).map(|n| n as usize)
+    }
+
+    pub fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        io::write_all(self.fd, buf)
+    }
+
+    /// Append the rest of the file to `out`
+    pub fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        let mut buf = [0u8; 512];
+        let start = out.len();
+        loop {
+            let n = self.read(&mut buf)?;
+            if n == 0 {
+                return Ok(out.len() - start);
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe { sys::close(self.fd) };
+    }
+}
+
+/// Whole contents of the file at `path`
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path, O_RDONLY)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Replace the contents of the file at `path` with `data`
+pub fn write(path: &str, data: &[u8]) -> Result<()> {
+    File::create(path)?.write_all(data)
+}
+
+/// Remove the file or device node at `path`
+pub fn remove_file(path: &str) -> Result<()> {
+    check(with_c_str(path, |p| unsafe { sys::unlink(p) })?).map(|_| ())
+}
+
+/// Remove the empty directory at `path`
+pub fn remove_dir(path: &str) -> Result<()> {
+    check(with_c_str(path, |p| unsafe { sys::rmdir(p, 0) })?).map(|_| ())
+}
+
+/// Remove the directory at `path` and everything in it
+pub fn remove_dir_all(path: &str) -> Result<()> {
+    check(with_c_str(path, |p| unsafe { sys::rmdir(p, sys::RMDIR_RECURSIVE) })?).map(|_| ())
+}
+
+pub fn chdir(path: &str) -> Result<()> {
+    check(with_c_str(path, |p| unsafe { sys::chdir(p) })?).map(|_| ())
+}
+
+pub fn current_dir() -> Result<String> {
+    let mut buf = [0u8; crate::PATH_MAX];
+    let len = check(unsafe { sys::getcwd(buf.as_mut_ptr(), buf.len()) })? as usize;
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Names of the entries in the directory at `path`
+pub fn read_dir(path: &str) -> Result<Vec<String>> {
+    let mut buf = alloc::vec![0u8; 4096];
+    let len = check(with_c_str(path, |p| unsafe { sys::listdir(p, buf.as_mut_ptr(), buf.len()) })?)? as usize;
+    let listing = String::from_utf8_lossy(&buf[..len]);
+    Ok(listing.lines().filter(|l| !l.is_empty()).map(String::from).collect())
+}
+
+/// Type, size, permissions and times of a file, from sys_stat
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata(sys::Stat);
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.0.mode & sys::S_IFMT == sys::S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.0.mode & sys::S_IFMT == sys::S_IFREG
+    }
+
+    /// Device node in /dev
+    pub fn is_device(&self) -> bool {
+        self.0.mode & sys::S_IFMT == sys::S_IFCHR
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.0.mode & sys::S_IFMT == sys::S_IFLNK
+    }
+
+    pub fn len(&self) -> u64 {
+        self.0.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.size == 0
+    }
+
+    /// Permission bits, as in `0o644`
+    pub fn permissions(&self) -> u32 {
+        (self.0.mode & 0o7777) as u32
+    }
+
+    /// Milliseconds since boot; 0 for initrd and generated files
+    pub fn created_ms(&self) -> u64 {
+        self.0.created_ms
+    }
+
+    pub fn modified_ms(&self) -> u64 {
+        self.0.modified_ms
+    }
+}
+
+/// Metadata of the file or directory at `path`
+pub fn metadata(path: &str) -> Result<Metadata> {
+    let mut stat = sys::Stat::default();
+    check(with_c_str(path, |p| unsafe { sys::stat(p, &mut stat) })?)?;
+    Ok(Metadata(stat))
+}
@@ -0,0 +1,153 @@
+//! Heap over sys_malloc
+//!
+//! The kernel only hands out memory, it cannot take it back, so the heap
+//! grows in chunks of at least `CHUNK_SIZE` and keeps freed blocks on an
+//! address-ordered free list, merging neighbours. Allocation is first fit.
+//! Programs are single-threaded; the lock only guards against reentrancy.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::sys;
+
+/// Smallest amount requested from the kernel at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Every block is a multiple of this and aligned to it
+const BLOCK_ALIGN: usize = 16;
+
+/// Header of a free block, stored in the block itself
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+pub struct Heap {
+    locked: AtomicBool,
+    free: core::cell::UnsafeCell<*mut FreeBlock>,
+}
+
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Heap {
+            locked: AtomicBool::new(false),
+            free: core::cell::UnsafeCell::new(ptr::null_mut()),
+        }
+    }
+
+    fn lock(&self) {
+        while self.locked.swap(true, Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    /// Bytes a block for `layout` takes
+    fn block_size(layout: Layout) -> usize {
+        let size = layout.size().max(core::mem::size_of::<FreeBlock>());
+        (size + BLOCK_ALIGN - 1) & !(BLOCK_ALIGN - 1)
+    }
+
+    /// Put `[addr, addr + size)` on the free list, merging with neighbours
+    unsafe fn insert(&self, addr: usize, size: usize) {
+        let head = &mut *self.free.get();
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut cur = *head;
+        while !cur.is_null() && (cur as usize) < addr {
+            prev = cur;
+            cur = (*cur).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next: cur });
+        if !cur.is_null() && addr + size == cur as usize {
+            (*block).size += (*cur).size;
+            (*block).next = (*cur).next;
+        }
+        if prev.is_null() {
+            *head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Carve a block for `size` bytes aligned to `align` out of the free list
+    unsafe fn take(&self, size: usize, align: usize) -> *mut u8 {
+        let head = &mut *self.free.get();
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut cur = *head;
+        while !cur.is_null() {
+            let start = cur as usize;
+            let end = start + (*cur).size;
+            let aligned = (start + align - 1) & !(align - 1);
+            if aligned + size <= end {
+                let next = (*cur).next;
+                if prev.is_null() {
+                    *head = next;
+                } else {
+                    (*prev).next = next;
+                }
+                // Give back what is left on either side
+                if aligned > start {
+                    self.insert(start, aligned - start);
+                }
+                if aligned + size < end {
+                    self.insert(aligned + size, end - aligned - size);
+                }
+                return aligned as *mut u8;
+            }
+            prev = cur;
+            cur = (*cur).next;
+        }
+        ptr::null_mut()
+    }
+
+    /// Get at least `size` more bytes from the kernel
+    unsafe fn grow(&self, size: usize) -> bool {
+        let size = size.max(CHUNK_SIZE);
+        let addr = sys::malloc(size);
+        if addr == 0 || sys::is_error(addr) {
+            return false;
+        }
+        self.insert(addr as usize, (size + 4095) & !4095);
+        true
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = Self::block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+        self.lock();
+        let mut ptr = self.take(size, align);
+        if ptr.is_null() && self.grow(size + align) {
+            ptr = self.take(size, align);
+        }
+        self.unlock();
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock();
+        self.insert(ptr as usize, Self::block_size(layout));
+        self.unlock();
+    }
+}
+
+#[cfg(feature = "global-allocator")]
+#[global_allocator]
+static HEAP: Heap = Heap::new();
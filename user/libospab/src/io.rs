@@ -0,0 +1,102 @@
+//! Standard streams and the print macros
+
+use core::fmt;
+use crate::{check, sys, Result};
+
+pub const STDIN: u64 = 0;
+pub const STDOUT: u64 = 1;
+pub const STDERR: u64 = 2;
+
+/// Read into `buf` from `fd`; 0 means end of input
+pub fn read(fd: u64, buf: &mut [u8]) -> Result<usize> {
+    check(unsafe { sys::read(fd, buf.as_mut_ptr(), buf.len()) }).map(|n| n as usize)
+}
+
+/// Write all of `buf` to `fd`, however many writes it takes; ENOSPC if
+/// a write makes no progress
+pub fn write_all(fd: u64, mut buf: &[u8]) -> Result<()> {
+    while !buf.is_empty() {
+        let written = check(unsafe { sys::write(fd, buf.as_ptr(), buf.len()) })? as usize;
+        if written == 0 {
+            return Err(crate::Errno(sys::ENOSPC));
+        }
+        buf = &buf[written.min(buf.len())..];
+    }
+    Ok(())
+}
+
+/// Read one line from stdin into `buf`, without the newline
+///
+/// Stops at a newline, end of input or when `buf` is full; returns the
+/// number of bytes stored.
+pub fn read_line(buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        let mut byte = 0u8;
+        if check(unsafe { sys::read(STDIN, &mut byte, 1) })? == 0 {
+            break;
+        }
+        if byte == b'\n' || byte == b'\r' {
+            break;
+        }
+        buf[len] = byte;
+        len += 1;
+    }
+    Ok(len)
+}
+
+/// Switch the terminal between raw mode, where reads return keys as they
+/// are typed (arrows as escape sequences) without echoing them, and
+/// canonical mode, where they return lines edited with the cursor keys,
+/// history and Tab
+pub fn set_raw(raw: bool) -> Result<()> {
+    let mode = if raw { sys::TTY_RAW } else { sys::TTY_CANONICAL };
+    check(unsafe { sys::ioctl(STDIN, sys::TTY_SETMODE, mode) }).map(|_| ())
+}
+
+/// Whether `fd` is the terminal
+pub fn is_terminal(fd: u64) -> bool {
+    check(unsafe { sys::ioctl(fd, sys::TTY_GETMODE, 0) }).is_ok()
+}
+
+/// Text size of the terminal on `fd`, as (columns, rows)
+pub fn terminal_size(fd: u64) -> Result<(usize, usize)> {
+    let size = check(unsafe { sys::ioctl(fd, sys::TTY_GETSIZE, 0) })?;
+    Ok(((size & 0xffff_ffff) as usize, (size >> 32) as usize))
+}
+
+/// A file descriptor as a `fmt::Write` sink
+pub struct Writer(pub u64);
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(self.0, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(fd: u64, args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut Writer(fd), args);
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDOUT, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDERR, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,89 @@
+//! libospab - runtime for ospabOS userland programs
+//!
+//! Wraps the kernel syscall ABI so programs need no assembly of their own:
+//!
+//! - `sys`: raw syscalls, one per kernel entry point
+//! - `fs`, `process`, `net`, `shm`, `signal`, `sync`, `system`: safe
+//!   wrappers returning `Result`
+//! - `print!`/`println!` (and `eprint!`/`eprintln!`) over sys_write
+//! - `heap`: a global allocator over sys_malloc
+//! - `env`: argv, the environment and the auxiliary vector passed by exec
+//! - a panic handler that reports the panic and exits
+//!
+//! A complete program:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use ospab::println;
+//!
+//! ospab::entry!(main);
+//!
+//! fn main() -> i32 {
+//!     for arg in ospab::env::args() {
+//!         println!("{}", arg);
+//!     }
+//!     0
+//! }
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod env;
+pub mod fs;
+pub mod heap;
+pub mod io;
+pub mod net;
+pub mod process;
+pub mod rt;
+pub mod shm;
+pub mod signal;
+pub mod sync;
+pub mod sys;
+pub mod system;
+
+/// Error returned by a syscall, as an errno value (see `sys::E*`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub u64);
+
+impl Errno {
+    pub fn as_str(self) -> &'static str {
+        sys::strerror(self.0.wrapping_neg())
+    }
+}
+
+impl core::fmt::Display for Errno {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Errno>;
+
+/// Turn a raw syscall return value into a `Result`
+///
+/// Syscalls without an errno report failure as !0, which reads as EPERM.
+pub fn check(ret: u64) -> Result<u64> {
+    if sys::is_error(ret) {
+        Err(Errno(ret.wrapping_neg()))
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Longest path accepted by the path-taking wrappers, including the NUL
+pub const PATH_MAX: usize = 256;
+
+/// Run `f` with `s` as a NUL-terminated string
+pub(crate) fn with_c_str<R>(s: &str, f: impl FnOnce(*const u8) -> R) -> Result<R> {
+    let mut buf = [0u8; PATH_MAX];
+    let bytes = s.as_bytes();
+    if bytes.len() >= buf.len() || bytes.contains(&0) {
+        return Err(Errno(sys::EINVAL));
+    }
+    buf[..bytes.len()].copy_from_slice(bytes);
+    Ok(f(buf.as_ptr()))
+}
@@ -0,0 +1,48 @@
+//! Sockets
+
+use crate::{check, sys, Result};
+
+pub use crate::sys::{AF_INET, SOCK_DGRAM, SOCK_RAW, SOCK_STREAM};
+
+/// IPv4 address in host order, e.g. `ipv4(10, 0, 2, 2)`
+pub const fn ipv4(a: u8, b: u8, c: u8, d: u8) -> u32 {
+    u32::from_be_bytes([a, b, c, d])
+}
+
+/// A socket descriptor, closed on drop
+pub struct Socket {
+    fd: u64,
+}
+
+impl Socket {
+    pub fn new(socktype: u64) -> Result<Socket> {
+        let fd = check(unsafe { sys::socket(AF_INET, socktype, 0) })?;
+        Ok(Socket { fd })
+    }
+
+    pub fn fd(&self) -> u64 {
+        self.fd
+    }
+
+    pub fn bind(&self, addr: u32, port: u16) -> Result<()> {
+        check(unsafe { sys::bind(self.fd, addr, port) }).map(|_| ())
+    }
+
+    pub fn connect(&self, addr: u32, port: u16) -> Result<()> {
+        check(unsafe { sys::connect(self.fd, addr, port) }).map(|_| ())
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        check(unsafe { sys::send(self.fd, buf.as_ptr(), buf.len()) }).map(|n| n as usize)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        check(unsafe { sys::recv(self.fd, buf.as_mut_ptr(), buf.len()) }).map(|n| n as usize)
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { sys::close(self.fd) };
+    }
+}
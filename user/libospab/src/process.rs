@@ -0,0 +1,88 @@
+//! Processes
+
+use alloc::vec::Vec;
+use crate::{check, sys, with_c_str, Errno, Result};
+
+/// Result of `fork`
+pub enum Fork {
+    Parent { child: u64 },
+    Child,
+}
+
+pub fn exit(code: i32) -> ! {
+    unsafe { sys::exit(code) }
+}
+
+pub fn getpid() -> u64 {
+    unsafe { sys::getpid() }
+}
+
+pub fn yield_now() {
+    unsafe { sys::yield_now() };
+}
+
+pub fn fork() -> Result<Fork> {
+    match check(unsafe { sys::fork() })? {
+        0 => Ok(Fork::Child),
+        child => Ok(Fork::Parent { child }),
+    }
+}
+
+/// Wait for `pid` (0 = any child) and return its pid and exit status
+pub fn waitpid(pid: u64, flags: u64) -> Result<(u64, i32)> {
+    let mut status = 0i32;
+    let pid = check(unsafe { sys::waitpid(pid, &mut status, flags) })?;
+    Ok((pid, status))
+}
+
+/// Replace this program with the executable at `path`
+///
+/// `args` becomes argv (conventionally starting with the program name)
+/// and `vars` the environment. Only returns on failure.
+pub fn exec(path: &str, args: &[&str], vars: &[&str]) -> Errno {
+    // NUL-terminated copies, then the NULL-terminated pointer arrays
+    let owned: Vec<Vec<u8>> = args
+        .iter()
+        .chain(vars)
+        .map(|s| s.bytes().chain(core::iter::once(0)).collect())
+        .collect();
+    let mut argv: Vec<*const u8> = owned[..args.len()].iter().map(|s| s.as_ptr()).collect();
+    argv.push(core::ptr::null());
+    let mut envp: Vec<*const u8> = owned[args.len()..].iter().map(|s| s.as_ptr()).collect();
+    envp.push(core::ptr::null());
+
+    match with_c_str(path, |p| unsafe { sys::exec(p, argv.as_ptr(), envp.as_ptr()) }) {
+        Ok(ret) => Errno(ret.wrapping_neg()),
+        Err(e) => e,
+    }
+}
+
+/// Fork, exec `path` in the child and wait for it; returns its exit status
+pub fn run(path: &str, args: &[&str], vars: &[&str]) -> Result<i32> {
+    match fork()? {
+        Fork::Child => {
+            exec(path, args, vars);
+            exit(127)
+        }
+        Fork::Parent { child } => waitpid(child, 0).map(|(_, status)| status),
+    }
+}
+
+/// Have the kernel start the executable at `path` as a new task
+pub fn spawn(path: &str) -> Result<()> {
+    check(with_c_str(path, |p| unsafe { sys::spawn(p, path.len()) })?).map(|_| ())
+}
+
+pub use sys::{RLimit, RLIMIT_CPU, RLIM_INFINITY};
+
+/// Current limit on `resource` (`RLIMIT_CPU`: seconds of CPU time)
+pub fn getrlimit(resource: u64) -> Result<RLimit> {
+    let mut limit = RLimit { cur: 0, max: 0 };
+    check(unsafe { sys::getrlimit(resource, &mut limit) })?;
+    Ok(limit)
+}
+
+/// Change the limit on `resource`; inherited by children started afterwards
+pub fn setrlimit(resource: u64, limit: RLimit) -> Result<()> {
+    check(unsafe { sys::setrlimit(resource, &limit) }).map(|_| ())
+}
@@ -0,0 +1,61 @@
+//! Program startup and the panic handler
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Stack canary checked by code built with `-Z stack-protector`, set from
+/// the AT_RANDOM bytes before `main` runs
+#[no_mangle]
+pub static __stack_chk_guard: AtomicU64 = AtomicU64::new(0x00AB_5C0D_E5AF_E000);
+
+/// Called by stack-protector code when a canary was overwritten
+#[no_mangle]
+pub extern "C" fn __stack_chk_fail() -> ! {
+    crate::eprintln!("stack smashing detected");
+    unsafe { crate::sys::exit(134) }
+}
+
+/// Define `_start` for a program whose entry point is `main: fn() -> i32`
+///
+/// The stack pointer at `_start` points at argc; it is handed to
+/// `rt::start`, which fills in `env` before calling `main` and exits with
+/// its return value.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[no_mangle]
+        extern "C" fn __ospab_start(sp: *const u64) -> ! {
+            unsafe { $crate::rt::start(sp, $main) }
+        }
+
+        core::arch::global_asm!(
+            ".globl _start",
+            "_start:",
+            "mov rdi, rsp",
+            "and rsp, -16",
+            "call __ospab_start",
+            "ud2",
+        );
+    };
+}
+
+/// Set up the runtime and run `main`
+///
+/// # Safety
+/// `sp` must be the initial stack pointer of the program.
+pub unsafe fn start(sp: *const u64, main: fn() -> i32) -> ! {
+    crate::env::init(sp);
+    if let Some(random) = crate::env::auxv(crate::env::AT_RANDOM) {
+        // The low byte stays 0 so string overflows cannot copy the canary
+        let canary = (random as *const u64).read_unaligned() & !0xFF;
+        __stack_chk_guard.store(canary, Ordering::Relaxed);
+    }
+    let code = main();
+    crate::sys::exit(code)
+}
+
+#[cfg(feature = "panic-handler")]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    crate::eprintln!("panic: {}", info);
+    unsafe { crate::sys::exit(101) }
+}
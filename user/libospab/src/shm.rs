@@ -0,0 +1,35 @@
+//! Memory shared between tasks
+//!
+//! A region is zero-filled when created and mapped at the same address in
+//! every task that maps it. It stays around while its creator runs or any
+//! task has it mapped; forked children inherit the mappings.
+//!
+//! ```ignore
+//! let id = shm::create(4096, false)?;
+//! let buf = shm::map(id, true)?;
+//! if let process::Fork::Child = process::fork()? {
+//!     // sees what the parent writes to buf, and vice versa
+//! }
+//! ```
+
+use crate::{check, sys, Result};
+
+pub use crate::sys::SHM_MAX_SIZE;
+
+/// Create a region of `size` bytes (at most `SHM_MAX_SIZE`). Other tasks
+/// may map it writable only with `others_write`.
+pub fn create(size: usize, others_write: bool) -> Result<u64> {
+    let flags = if others_write { sys::SHM_OTHERS_WRITE } else { 0 };
+    check(unsafe { sys::shm_create(size, flags) })
+}
+
+/// Map region `id` into this task and return its start
+pub fn map(id: u64, writable: bool) -> Result<*mut u8> {
+    let flags = if writable { sys::SHM_WRITE } else { 0 };
+    check(unsafe { sys::shm_map(id, flags) }).map(|addr| addr as *mut u8)
+}
+
+/// Unmap region `id`; pointers into it are dangling afterwards
+pub fn unmap(id: u64) -> Result<()> {
+    check(unsafe { sys::shm_unmap(id) }).map(|_| ())
+}
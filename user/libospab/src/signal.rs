@@ -0,0 +1,62 @@
+//! Signals
+//!
+//! Every signal terminates the task by default. A handler runs on the
+//! task's own stack between two instructions of whatever it interrupted,
+//! so it should stick to setting a flag or calling async-signal-safe
+//! syscalls.
+//!
+//! ```ignore
+//! static STOP: AtomicBool = AtomicBool::new(false);
+//!
+//! extern "C" fn on_interrupt(_sig: u64) {
+//!     STOP.store(true, Ordering::Relaxed);
+//! }
+//!
+//! signal::set(signal::SIGINT, signal::Action::Handler(on_interrupt))?;
+//! ```
+
+use crate::{check, sys, Result};
+
+pub use crate::sys::{SIGALRM, SIGBUS, SIGFPE, SIGILL, SIGINT, SIGKILL, SIGSEGV, SIGTERM};
+
+/// What a signal does to this task
+#[derive(Clone, Copy)]
+pub enum Action {
+    /// Terminate with exit status 128 + the signal
+    Default,
+    Ignore,
+    /// Call the function with the signal number
+    Handler(extern "C" fn(u64)),
+}
+
+/// Set what `sig` does; SIGKILL cannot be changed
+pub fn set(sig: u64, action: Action) -> Result<()> {
+    let (handler, restorer) = match action {
+        Action::Default => (sys::SIG_DFL, 0),
+        Action::Ignore => (sys::SIG_IGN, 0),
+        Action::Handler(f) => (f as usize as u64, restorer as *const () as u64),
+    };
+    check(unsafe { sys::sigaction(sig, handler, restorer) }).map(|_| ())
+}
+
+/// Send `sig` to task `pid`
+pub fn kill(pid: u64, sig: u64) -> Result<()> {
+    check(unsafe { sys::kill(pid, sig) }).map(|_| ())
+}
+
+/// Deliver SIGALRM to this task after `seconds`, replacing any pending
+/// alarm (0 cancels it); returns the seconds the old one had left
+pub fn alarm(seconds: u64) -> u64 {
+    unsafe { sys::alarm(seconds) }
+}
+
+/// Handlers return here; the kernel restores what they interrupted
+#[unsafe(naked)]
+unsafe extern "C" fn restorer() -> ! {
+    core::arch::naked_asm!(
+        "mov eax, {sigreturn}",
+        "syscall",
+        "ud2",
+        sigreturn = const sys::SYS_SIGRETURN,
+    )
+}
@@ -0,0 +1,23 @@
+//! Blocking on a shared word
+//!
+//! The building blocks for mutexes and condition variables: `wait` sleeps
+//! in the kernel while a word still holds the value last seen, and `wake`
+//! rouses tasks sleeping on it. The word may live in a `shm` region to
+//! synchronize separate tasks.
+
+use core::sync::atomic::AtomicU32;
+use crate::{check, sys, Result};
+
+/// Sleep until woken if `word` still equals `expected`
+///
+/// Returns at once with `Errno(sys::EAGAIN)` if it does not. Wakeups may be spurious and
+/// a signal ends the wait with EINTR, so re-check the word in a loop.
+pub fn wait(word: &AtomicU32, expected: u32) -> Result<()> {
+    check(unsafe { sys::futex_wait(word.as_ptr(), expected) }).map(|_| ())
+}
+
+/// Wake up to `count` tasks waiting on `word`; returns how many woke
+pub fn wake(word: &AtomicU32, count: u32) -> Result<u32> {
+    check(unsafe { sys::futex_wake(word.as_ptr(), count) }).map(|n| n as u32)
+}
+
@@ -0,0 +1,868 @@
+//! Raw syscalls
+//!
+//! One function per kernel syscall, taking raw pointers and returning the
+//! raw value from RAX; see the kernel's `syscall::abi` for the contract of
+//! each. The safe modules (`fs`, `process`, ...) are built on these.
+//!
+//! The `syscall` instruction leaves the return address in RCX and RFLAGS
+//! in R11, so every wrapper marks both as clobbered.
+
+// The contract of each call is in the kernel ABI, not repeated here
+#![allow(clippy::missing_safety_doc)]
+
+use core::arch::asm;
+
+pub const SYS_YIELD: u64 = 0;
+pub const SYS_SPAWN: u64 = 1;
+pub const SYS_WRITE: u64 = 2;
+pub const SYS_READ: u64 = 3;
+pub const SYS_EXIT: u64 = 4;
+pub const SYS_GETPID: u64 = 5;
+pub const SYS_MALLOC: u64 = 6;
+pub const SYS_OPEN: u64 = 7;
+pub const SYS_EXEC: u64 = 8;
+pub const SYS_DRAW_CHAR: u64 = 9;
+pub const SYS_CHDIR: u64 = 10;
+pub const SYS_GETCWD: u64 = 11;
+pub const SYS_LISTDIR: u64 = 12;
+pub const SYS_UPTIME: u64 = 13;
+pub const SYS_SHUTDOWN: u64 = 14;
+pub const SYS_REBOOT: u64 = 15;
+pub const SYS_SETCRED: u64 = 16;
+pub const SYS_SOCKET: u64 = 17;
+pub const SYS_BIND: u64 = 18;
+pub const SYS_CONNECT: u64 = 19;
+pub const SYS_SEND: u64 = 20;
+pub const SYS_RECV: u64 = 21;
+pub const SYS_CLOSE: u64 = 22;
+pub const SYS_FORK: u64 = 23;
+pub const SYS_WAITPID: u64 = 24;
+pub const SYS_SYSINFO: u64 = 25;
+pub const SYS_GETRLIMIT: u64 = 26;
+pub const SYS_SETRLIMIT: u64 = 27;
+pub const SYS_SHM_CREATE: u64 = 28;
+pub const SYS_SHM_MAP: u64 = 29;
+pub const SYS_SHM_UNMAP: u64 = 30;
+pub const SYS_KILL: u64 = 31;
+pub const SYS_SIGACTION: u64 = 32;
+pub const SYS_SIGRETURN: u64 = 33;
+pub const SYS_FUTEX_WAIT: u64 = 34;
+pub const SYS_FUTEX_WAKE: u64 = 35;
+pub const SYS_NANOSLEEP: u64 = 36;
+pub const SYS_ALARM: u64 = 37;
+pub const SYS_BEEP: u64 = 38;
+pub const SYS_AUDIO_WRITE: u64 = 39;
+pub const SYS_STAT: u64 = 40;
+pub const SYS_FSTAT: u64 = 41;
+pub const SYS_LSEEK: u64 = 42;
+pub const SYS_UNLINK: u64 = 43;
+pub const SYS_RMDIR: u64 = 44;
+pub const SYS_IOCTL: u64 = 45;
+
+pub const RMDIR_RECURSIVE: u64 = 1;
+
+pub const O_CREAT: u64 = 0o100;
+pub const O_TRUNC: u64 = 0o1000;
+pub const O_APPEND: u64 = 0o2000;
+
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+pub const TTY_GETMODE: u64 = 1;
+pub const TTY_SETMODE: u64 = 2;
+pub const TTY_GETSIZE: u64 = 3;
+pub const TTY_CANONICAL: u64 = 0;
+pub const TTY_RAW: u64 = 1;
+
+pub const AF_INET: u64 = 2;
+pub const SOCK_STREAM: u64 = 1;
+pub const SOCK_DGRAM: u64 = 2;
+pub const SOCK_RAW: u64 = 3;
+
+pub const WNOHANG: u64 = 1;
+
+pub const RLIMIT_CPU: u64 = 0;
+pub const RLIM_INFINITY: u64 = !0;
+pub const SIGINT: u64 = 2;
+pub const SIGILL: u64 = 4;
+pub const SIGBUS: u64 = 7;
+pub const SIGFPE: u64 = 8;
+pub const SIGKILL: u64 = 9;
+pub const SIGSEGV: u64 = 11;
+pub const SIGALRM: u64 = 14;
+pub const SIGTERM: u64 = 15;
+pub const SIGXCPU: i32 = 24;
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+pub const S_IFMT: u64 = 0o170000;
+pub const S_IFCHR: u64 = 0o020000;
+pub const S_IFDIR: u64 = 0o040000;
+pub const S_IFREG: u64 = 0o100000;
+pub const S_IFLNK: u64 = 0o120000;
+
+pub const SHM_MAX_SIZE: usize = 16 * 1024 * 1024;
+pub const SHM_OTHERS_WRITE: u64 = 1;
+pub const SHM_WRITE: u64 = 1;
+
+// Filesystem, process (fork, waitpid, rlimit, sysinfo, setcred), socket, shm,
+// signal, futex and sleep syscalls return the negated errno on failure; the others !0
+pub const EPERM: u64 = 1;
+pub const ENOENT: u64 = 2;
+pub const ESRCH: u64 = 3;
+pub const EINTR: u64 = 4;
+pub const EIO: u64 = 5;
+pub const EBADF: u64 = 9;
+pub const ECHILD: u64 = 10;
+pub const EAGAIN: u64 = 11;
+pub const ENOMEM: u64 = 12;
+pub const EACCES: u64 = 13;
+pub const EFAULT: u64 = 14;
+pub const EBUSY: u64 = 16;
+pub const EEXIST: u64 = 17;
+pub const ENOTDIR: u64 = 20;
+pub const EISDIR: u64 = 21;
+pub const EINVAL: u64 = 22;
+pub const ENODEV: u64 = 19;
+pub const ENOTTY: u64 = 25;
+pub const ENOSPC: u64 = 28;
+pub const ESPIPE: u64 = 29;
+pub const EROFS: u64 = 30;
+pub const ENOSYS: u64 = 38;
+pub const ENOTEMPTY: u64 = 39;
+pub const ELOOP: u64 = 40;
+pub const ETIMEDOUT: u64 = 110;
+pub const ECONNREFUSED: u64 = 111;
+
+pub fn is_error(ret: u64) -> bool {
+    ret > !0 - 4096
+}
+
+/// Message for an error return value
+pub fn strerror(ret: u64) -> &'static str {
+    match ret.wrapping_neg() {
+        EPERM => "Operation not permitted",
+        ENOENT => "No such file or directory",
+        ESRCH => "No such process",
+        EINTR => "Interrupted system call",
+        EIO => "I/O error",
+        EBADF => "Bad file descriptor",
+        ECHILD => "No child processes",
+        EAGAIN => "Resource temporarily unavailable",
+        ENOMEM => "Cannot allocate memory",
+        EACCES => "Permission denied",
+        EFAULT => "Bad address",
+        EBUSY => "Device or resource busy",
+        EEXIST => "File exists",
+        ENOTDIR => "Not a directory",
+        EISDIR => "Not a regular file",
+        ENODEV => "No such device",
+        EINVAL => "Invalid argument",
+        ENOTTY => "Inappropriate ioctl for device",
+        ENOSPC => "No space left on device",
+        ESPIPE => "Illegal seek",
+        EROFS => "Read-only file system",
+        ENOSYS => "Function not implemented",
+        ENOTEMPTY => "Directory not empty",
+        ELOOP => "Too many levels of symbolic links",
+        ETIMEDOUT => "Connection timed out",
+        ECONNREFUSED => "Connection refused",
+        _ => "Operation failed",
+    }
+}
+
+/// Filled in by sys_sysinfo; all sizes are in bytes
+#[repr(C)]
+#[derive(Default)]
+pub struct SysInfo {
+    pub uptime_ms: u64,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    pub mem_free: u64,
+    pub heap_total: u64,
+    pub heap_used: u64,
+    pub heap_peak: u64,
+    pub heap_free: u64,
+    pub heap_fragmentation: u64,
+    pub tasks: u64,
+}
+
+/// Filled in by sys_stat and sys_fstat; times are milliseconds since boot
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub mode: u64,
+    pub size: u64,
+    pub created_ms: u64,
+    pub modified_ms: u64,
+}
+
+pub unsafe fn yield_now() -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_YIELD,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// Queue the executable at `path` to be started by the kernel
+pub unsafe fn spawn(path: *const u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SPAWN,
+        in("rdi") path,
+        in("rsi") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn write(fd: u64, buf: *const u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_WRITE,
+        in("rdi") fd,
+        in("rsi") buf,
+        in("rdx") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn read(fd: u64, buf: *mut u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_READ,
+        in("rdi") fd,
+        in("rsi") buf,
+        in("rdx") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn getpid() -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_GETPID,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// Map `size` more bytes (rounded up to pages) of zeroed memory; there is
+/// no way to give them back
+pub unsafe fn malloc(size: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_MALLOC,
+        in("rdi") size,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn open(path: *const u8, flags: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_OPEN,
+        in("rdi") path,
+        in("rsi") flags,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// `argv` and `envp` are NULL-terminated arrays of C strings, or null
+pub unsafe fn exec(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_EXEC,
+        in("rdi") path,
+        in("rsi") argv,
+        in("rdx") envp,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// Returns 0 in the child and the child's pid in the parent
+pub unsafe fn fork() -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_FORK,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// Wait for a child (pid 0 = any); its exit code is stored in `status`
+pub unsafe fn waitpid(pid: u64, status: *mut i32, flags: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_WAITPID,
+        in("rdi") pid,
+        in("rsi") status,
+        in("rdx") flags,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn sysinfo(info: *mut SysInfo) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SYSINFO,
+        in("rdi") info,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// Soft and hard limit, as read and written by getrlimit/setrlimit
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    pub cur: u64,
+    pub max: u64,
+}
+
+pub unsafe fn getrlimit(resource: u64, limit: *mut RLimit) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_GETRLIMIT,
+        in("rdi") resource,
+        in("rsi") limit,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn setrlimit(resource: u64, limit: *const RLimit) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SETRLIMIT,
+        in("rdi") resource,
+        in("rsi") limit,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn shm_create(size: usize, flags: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SHM_CREATE,
+        in("rdi") size,
+        in("rsi") flags,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn shm_map(id: u64, flags: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SHM_MAP,
+        in("rdi") id,
+        in("rsi") flags,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn shm_unmap(id: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SHM_UNMAP,
+        in("rdi") id,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn kill(pid: u64, sig: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_KILL,
+        in("rdi") pid,
+        in("rsi") sig,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn sigaction(sig: u64, handler: u64, restorer: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SIGACTION,
+        in("rdi") sig,
+        in("rsi") handler,
+        in("rdx") restorer,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn futex_wait(addr: *const u32, expected: u32) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_FUTEX_WAIT,
+        in("rdi") addr,
+        in("rsi") expected as u64,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn futex_wake(addr: *const u32, count: u32) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_FUTEX_WAKE,
+        in("rdi") addr,
+        in("rsi") count as u64,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn nanosleep(ns: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_NANOSLEEP,
+        in("rdi") ns,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn alarm(seconds: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_ALARM,
+        in("rdi") seconds,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn beep(freq: u64, ms: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_BEEP,
+        in("rdi") freq,
+        in("rsi") ms,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn audio_write(buf: *const u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_AUDIO_WRITE,
+        in("rdi") buf,
+        in("rsi") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn draw_char(x: u64, y: u64, ch: u64, fg: u64, bg: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_DRAW_CHAR,
+        in("rdi") x,
+        in("rsi") y,
+        in("rdx") ch,
+        in("r10") fg,
+        in("r8") bg,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn chdir(path: *const u8) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_CHDIR,
+        in("rdi") path,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn getcwd(buf: *mut u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_GETCWD,
+        in("rdi") buf,
+        in("rsi") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn listdir(path: *const u8, buf: *mut u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_LISTDIR,
+        in("rdi") path,
+        in("rsi") buf,
+        in("rdx") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn uptime() -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_UPTIME,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn socket(domain: u64, socktype: u64, protocol: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SOCKET,
+        in("rdi") domain,
+        in("rsi") socktype,
+        in("rdx") protocol,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn bind(fd: u64, addr: u32, port: u16) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_BIND,
+        in("rdi") fd,
+        in("rsi") addr as u64,
+        in("rdx") port as u64,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn connect(fd: u64, addr: u32, port: u16) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_CONNECT,
+        in("rdi") fd,
+        in("rsi") addr as u64,
+        in("rdx") port as u64,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn send(fd: u64, buf: *const u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SEND,
+        in("rdi") fd,
+        in("rsi") buf,
+        in("rdx") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn recv(fd: u64, buf: *mut u8, len: usize) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_RECV,
+        in("rdi") fd,
+        in("rsi") buf,
+        in("rdx") len,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn close(fd: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_CLOSE,
+        in("rdi") fd,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn setcred(name: *const u8, password: *const u8) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_SETCRED,
+        in("rdi") name,
+        in("rsi") password,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn shutdown() -> ! {
+    asm!(
+        "syscall",
+        in("rax") SYS_SHUTDOWN,
+        options(noreturn)
+    );
+}
+
+pub unsafe fn reboot() -> ! {
+    asm!(
+        "syscall",
+        in("rax") SYS_REBOOT,
+        options(noreturn)
+    );
+}
+
+pub unsafe fn exit(code: i32) -> ! {
+    asm!(
+        "syscall",
+        in("rax") SYS_EXIT,
+        in("rdi") code as u64,
+        options(noreturn)
+    );
+}
+
+pub unsafe fn stat(path: *const u8, stat: *mut Stat) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_STAT,
+        in("rdi") path,
+        in("rsi") stat,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn fstat(fd: u64, stat: *mut Stat) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_FSTAT,
+        in("rdi") fd,
+        in("rsi") stat,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn lseek(fd: u64, offset: i64, whence: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_LSEEK,
+        in("rdi") fd,
+        in("rsi") offset,
+        in("rdx") whence,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn ioctl(fd: u64, request: u64, arg: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_IOCTL,
+        in("rdi") fd,
+        in("rsi") request,
+        in("rdx") arg,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn unlink(path: *const u8) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_UNLINK,
+        in("rdi") path,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn rmdir(path: *const u8, flags: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "syscall",
+        in("rax") SYS_RMDIR,
+        in("rdi") path,
+        in("rsi") flags,
+        lateout("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
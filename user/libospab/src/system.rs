@@ -0,0 +1,74 @@
+//! Machine-wide services: time and sleeping, memory statistics, power, credentials,
+//! the text console and the PC speaker
+
+use crate::{check, sys, with_c_str, Result};
+
+pub use crate::sys::SysInfo;
+
+/// Milliseconds since boot
+pub fn uptime_ms() -> u64 {
+    unsafe { sys::uptime() }
+}
+
+/// Block for at least `duration` (the kernel ticks every 10 ms); a signal
+/// cuts it short with EINTR
+pub fn sleep(duration: core::time::Duration) -> Result<()> {
+    let ns = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    check(unsafe { sys::nanosleep(ns) }).map(|_| ())
+}
+
+pub fn sysinfo() -> Result<SysInfo> {
+    let mut info = SysInfo::default();
+    check(unsafe { sys::sysinfo(&mut info) })?;
+    Ok(info)
+}
+
+/// Switch the session to `name`; `password` may be None when running as root
+pub fn setcred(name: &str, password: Option<&str>) -> Result<u64> {
+    let mut pass_buf = [0u8; crate::PATH_MAX];
+    let pass_ptr = match password {
+        Some(p) if p.len() < pass_buf.len() => {
+            pass_buf[..p.len()].copy_from_slice(p.as_bytes());
+            pass_buf.as_ptr()
+        }
+        Some(_) => return Err(crate::Errno(sys::EINVAL)),
+        None => core::ptr::null(),
+    };
+    check(with_c_str(name, |n| unsafe { sys::setcred(n, pass_ptr) })?)
+}
+
+/// Draw `ch` in the console cell at column `x`, row `y`
+pub fn draw_char(x: usize, y: usize, ch: char, fg: u32, bg: u32) {
+    unsafe { sys::draw_char(x as u64, y as u64, ch as u64, fg as u64, bg as u64) };
+}
+
+/// Sound the PC speaker at `freq` Hz for `ms` milliseconds, after whatever
+/// it is already playing; returns at once
+pub fn beep(freq: u32, ms: u32) -> Result<()> {
+    check(unsafe { sys::beep(freq as u64, ms as u64) }).map(|_| ())
+}
+
+/// Silence the PC speaker and drop the notes queued for it
+pub fn stop_sound() {
+    unsafe { sys::beep(0, 0) };
+}
+
+/// Queue `(freq, ms)` notes for the PC speaker, 0 Hz being a rest; returns
+/// how many fit in the kernel's queue
+pub fn play(notes: &[(u16, u16)]) -> Result<usize> {
+    let mut buf = alloc::vec::Vec::with_capacity(notes.len() * 4);
+    for &(freq, ms) in notes {
+        buf.extend_from_slice(&freq.to_le_bytes());
+        buf.extend_from_slice(&ms.to_le_bytes());
+    }
+    let written = check(unsafe { sys::audio_write(buf.as_ptr(), buf.len()) })?;
+    Ok(written as usize / 4)
+}
+
+pub fn shutdown() -> ! {
+    unsafe { sys::shutdown() }
+}
+
+pub fn reboot() -> ! {
+    unsafe { sys::reboot() }
+}
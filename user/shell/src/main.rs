@@ -1,35 +1,32 @@
 #![no_std]
 #![no_main]
 
-mod syscall;
+use ospab::io;
+use ospab::sys as syscall;
 
 const COLS: usize = 80;
-const ROWS: usize = 25;
-const FG: u32 = 0x00E0E0E0;
-const BG: u32 = 0x00000000;
 const ACCENT: u32 = 0x00FFA500;
 const INPUT_BUF_LEN: usize = 256;
 
-static mut INPUT_BUF: [u8; INPUT_BUF_LEN] = [0; INPUT_BUF_LEN];
-
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
-
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     let mut term = Terminal::new();
+    // Lines come edited from the TTY (cursor keys, history, Tab)
+    let _ = io::set_raw(false);
     term.clear();
     term.banner();
 
+    let mut input = [0u8; INPUT_BUF_LEN];
     loop {
         term.prompt();
-        let len = term.read_line();
+        let len = term.read_line(&mut input);
         if len == 0 {
             continue;
         }
-        let line = unsafe { core::str::from_utf8_unchecked(&INPUT_BUF[..len]) };
+        let Ok(line) = core::str::from_utf8(&input[..len]) else {
+            term.write_str("input is not UTF-8\n");
+            continue;
+        };
         if !handle_command(line, &mut term) {
             term.write_str("unknown command. try: help\n");
         }
@@ -45,7 +42,7 @@ fn handle_command(line: &str, term: &mut Terminal) -> bool {
 
     match cmd {
         "help" => {
-            term.write_str("commands: help clear echo ls cat cd pwd uptime version exec shutdown reboot doom tomato grape history\n");
+            term.write_str("commands: help clear echo ls cat cd pwd uptime free version exec shutdown reboot doom tomato grape history\n");
             true
         }
         "clear" => {
@@ -91,6 +88,29 @@ fn handle_command(line: &str, term: &mut Terminal) -> bool {
             term.write_str(" seconds\n");
             true
         }
+        "free" => {
+            let human = parts.any(|arg| arg == "-h");
+            let mut info = syscall::SysInfo::default();
+            if unsafe { syscall::sysinfo(&mut info) } != 0 {
+                term.write_str("free: sysinfo failed\n");
+                return true;
+            }
+            term.write_str("              total        used        free\n");
+            term.write_str("Mem:   ");
+            for value in [info.mem_total, info.mem_used, info.mem_free] {
+                term.write_size(value, human);
+            }
+            term.write_str("\nHeap:  ");
+            for value in [info.heap_total, info.heap_used, info.heap_free] {
+                term.write_size(value, human);
+            }
+            term.write_str("\nHeap peak ");
+            term.write_size(info.heap_peak, human);
+            term.write_str(", ");
+            term.write_u64(info.heap_fragmentation);
+            term.write_str("% fragmented\n");
+            true
+        }
         "version" => {
             term.write_str("ospabOS user shell (Ring3)\n");
             true
@@ -121,21 +141,52 @@ fn handle_command(line: &str, term: &mut Terminal) -> bool {
             syscall::exit(0);
         },
         "exec" => {
+            const MAX_ARGS: usize = 16;
             let path = match parts.next() {
                 Some(p) => p,
                 None => {
-                    term.write_str("usage: exec /bin/app\n");
+                    term.write_str("usage: exec /bin/app [args...]\n");
                     return true;
                 }
             };
-            let mut c_buf = [0u8; 256];
-            let bytes = path.as_bytes();
-            let count = core::cmp::min(bytes.len(), c_buf.len().saturating_sub(1));
-            c_buf[..count].copy_from_slice(&bytes[..count]);
-            c_buf[count] = 0;
-            let ret = unsafe { syscall::exec(c_buf.as_ptr()) };
-            if ret != 0 {
-                term.write_str("exec failed\n");
+            // argv[0] is the path; every string is NUL-terminated in c_buf
+            let mut c_buf = [0u8; INPUT_BUF_LEN + MAX_ARGS];
+            let mut offsets = [0usize; MAX_ARGS];
+            let mut argc = 0;
+            let mut used = 0;
+            for arg in core::iter::once(path).chain(parts) {
+                let bytes = arg.as_bytes();
+                if argc == MAX_ARGS || used + bytes.len() + 1 > c_buf.len() {
+                    term.write_str("exec: too many arguments\n");
+                    return true;
+                }
+                c_buf[used..used + bytes.len()].copy_from_slice(bytes);
+                offsets[argc] = used;
+                used += bytes.len() + 1;
+                argc += 1;
+            }
+            let mut argv = [core::ptr::null::<u8>(); MAX_ARGS + 1];
+            for i in 0..argc {
+                argv[i] = unsafe { c_buf.as_ptr().add(offsets[i]) };
+            }
+            let pid = unsafe { syscall::fork() };
+            if syscall::is_error(pid) {
+                term.write_str("fork failed\n");
+                return true;
+            }
+            if pid == 0 {
+                // Child: only comes back if the image could not be loaded
+                unsafe {
+                    syscall::exec(argv[0], argv.as_ptr(), core::ptr::null());
+                    term.write_str("exec failed\n");
+                    syscall::exit(127);
+                }
+            }
+            let mut status: i32 = 0;
+            if unsafe { syscall::waitpid(pid, &mut status, 0) } == pid && status != 0 {
+                term.write_str("exit status ");
+                term.write_u64(status as u32 as u64);
+                term.write_str("\n");
             }
             true
         }
@@ -143,152 +194,101 @@ fn handle_command(line: &str, term: &mut Terminal) -> bool {
     }
 }
 
-struct Terminal {
-    row: usize,
-    col: usize,
-}
+struct Terminal;
 
 impl Terminal {
     fn new() -> Self {
-        Self { row: 0, col: 0 }
+        Self
     }
 
     fn banner(&mut self) {
-        self.draw_bar();
-        self.row = 2;
-        self.col = 2;
-        self.write_str("ospabshell — userland\n");
-        self.write_str("type help to list commands\n");
-        self.row += 1;
-        self.col = 0;
+        self.write_str("  ospabshell — userland\n");
+        self.write_str("  type help to list commands\n\n");
     }
 
+    /// Accent bar over the top two rows; the text below starts at row 2
     fn draw_bar(&mut self) {
-        self.row = 0;
-        self.col = 0;
-        for _ in 0..COLS {
-            self.put_char(' ', ACCENT, ACCENT);
+        for row in 0..2 {
+            for col in 0..COLS {
+                let bg = if row == 0 { ACCENT } else { 0 };
+                unsafe { syscall::draw_char(col as u64, row, ' ' as u64, bg as u64, bg as u64); }
+            }
         }
-        self.row = 1;
-        self.col = 0;
-        for _ in 0..COLS {
-            self.put_char(' ', BG, BG);
+        for (col, ch) in "OSPAB OS".chars().enumerate() {
+            unsafe { syscall::draw_char(col as u64 + 2, 0, ch as u64, 0, ACCENT as u64); }
         }
-        self.row = 0;
-        self.col = 2;
-        self.write_str_colored("OSPAB OS", 0x00000000, ACCENT);
-        self.row = 1;
-        self.col = 0;
     }
 
     fn prompt(&mut self) {
-        self.write_str_colored("ospab> ", ACCENT, BG);
+        self.write_str("ospab> ");
     }
 
+    /// A form feed clears the console; the bar goes over the two lines left
+    /// free at the top
     fn clear(&mut self) {
-        for r in 0..ROWS {
-            for c in 0..COLS {
-                unsafe { syscall::draw_char(c as u64, r as u64, ' ' as u64, BG as u64, BG as u64); }
-            }
-        }
-        self.row = 2;
-        self.col = 0;
-    }
-
-    fn read_line(&mut self) -> usize {
-        let mut len = 0usize;
-        loop {
-            let mut ch: u8 = 0;
-            let read = unsafe { syscall::read(0, &mut ch as *mut u8, 1) };
-            if read == 0 {
-                continue;
-            }
-            match ch {
-                b'\r' | b'\n' => {
-                    self.new_line();
-                    unsafe { INPUT_BUF[len] = 0; }
-                    return len;
-                }
-                8 | 127 => {
-                    if len > 0 {
-                        len -= 1;
-                        self.backspace();
-                    }
-                }
-                _ => {
-                    if len + 1 >= INPUT_BUF_LEN {
-                        continue;
-                    }
-                    unsafe { INPUT_BUF[len] = ch; }
-                    len += 1;
-                    self.put_char(ch as char, FG, BG);
-                }
-            }
-        }
+        self.write_str("\x0c\n\n");
+        self.draw_bar();
     }
 
-    fn backspace(&mut self) {
-        if self.col == 0 {
-            return;
-        }
-        self.col -= 1;
-        self.put_char(' ', BG, BG);
-        self.col -= 1;
+    /// Read an edited line into `buf`, without the newline
+    fn read_line(&mut self, buf: &mut [u8]) -> usize {
+        io::read_line(buf).unwrap_or(0)
     }
 
-    fn new_line(&mut self) {
-        self.col = 0;
-        if self.row + 1 < ROWS {
-            self.row += 1;
-        }
+    fn write_str(&mut self, s: &str) {
+        let _ = io::write_all(io::STDOUT, s.as_bytes());
     }
 
-    fn write_str(&mut self, s: &str) {
-        self.write_str_colored(s, FG, BG);
+    fn write_u64(&mut self, value: u64) {
+        ospab::print!("{}", value);
     }
 
-    fn write_str_colored(&mut self, s: &str, fg: u32, bg: u32) {
-        for ch in s.chars() {
-            if ch == '\n' {
-                self.new_line();
-            } else {
-                self.put_char(ch, fg, bg);
+    /// Right-aligned 12-column size: kibibytes, or with `human` the largest
+    /// fitting unit (1.5Gi, 512Mi, 100B)
+    fn write_size(&mut self, bytes: u64, human: bool) {
+        const UNITS: [&str; 4] = ["Ki", "Mi", "Gi", "Ti"];
+        let (whole, tenth, unit) = if !human {
+            (bytes / 1024, None, "")
+        } else if bytes < 1024 {
+            (bytes, None, "B")
+        } else {
+            let mut unit = 0;
+            let mut scaled = bytes * 10 / 1024;
+            while scaled >= 10240 && unit + 1 < UNITS.len() {
+                scaled /= 1024;
+                unit += 1;
             }
-        }
-    }
+            let tenth = if scaled < 100 { Some(scaled % 10) } else { None };
+            (scaled / 10, tenth, UNITS[unit])
+        };
 
-    fn write_u64(&mut self, mut value: u64) {
-        if value == 0 {
-            self.put_char('0', FG, BG);
-            return;
-        }
-        let mut buf = [0u8; 20];
-        let mut i = 0;
-        while value > 0 {
-            buf[i] = b'0' + (value % 10) as u8;
+        let mut text = [0u8; 24];
+        let mut len = 0;
+        let mut digits = [0u8; 20];
+        let mut i = digits.len();
+        let mut value = whole;
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (value % 10) as u8;
             value /= 10;
-            i += 1;
+            if value == 0 {
+                break;
+            }
         }
-        while i > 0 {
-            i -= 1;
-            self.put_char(buf[i] as char, FG, BG);
+        text[..digits.len() - i].copy_from_slice(&digits[i..]);
+        len += digits.len() - i;
+        if let Some(tenth) = tenth {
+            text[len] = b'.';
+            text[len + 1] = b'0' + tenth as u8;
+            len += 2;
         }
-    }
+        text[len..len + unit.len()].copy_from_slice(unit.as_bytes());
+        len += unit.len();
 
-    fn put_char(&mut self, ch: char, fg: u32, bg: u32) {
-        if self.row >= ROWS {
-            return;
-        }
-        unsafe {
-            syscall::draw_char(self.col as u64, self.row as u64, ch as u64, fg as u64, bg as u64);
-        }
-        self.col += 1;
-        if self.col >= COLS {
-            self.col = 0;
-            if self.row + 1 < ROWS {
-                self.row += 1;
-            }
+        for _ in len..12 {
+            self.write_str(" ");
         }
+        self.write_str(core::str::from_utf8(&text[..len]).unwrap_or("?"));
     }
 
     fn chdir(&mut self, path: &str) {
@@ -299,18 +299,20 @@ impl Terminal {
         c_buf[count] = 0;
         let ret = unsafe { syscall::chdir(c_buf.as_ptr()) };
         if ret != 0 {
-            self.write_str("cd failed\n");
+            self.write_str("cd: ");
+            self.write_str(syscall::strerror(ret));
+            self.write_str("\n");
         }
     }
 
     fn print_cwd(&mut self) {
         let mut buf = [0u8; 256];
-        let written = unsafe { syscall::getcwd(buf.as_mut_ptr(), buf.len()) } as usize;
-        if written == 0 || written == !0usize {
+        let written = unsafe { syscall::getcwd(buf.as_mut_ptr(), buf.len()) };
+        if written == 0 || syscall::is_error(written) {
             self.write_str("cwd unavailable\n");
             return;
         }
-        let s = unsafe { core::str::from_utf8_unchecked(&buf[..written]) };
+        let s = unsafe { core::str::from_utf8_unchecked(&buf[..written as usize]) };
         self.write_str(s);
         self.write_str("\n");
     }
@@ -323,12 +325,17 @@ impl Terminal {
         path_buf[count] = 0;
 
         let mut out = [0u8; 1024];
-        let written = unsafe { syscall::listdir(path_buf.as_ptr(), out.as_mut_ptr(), out.len()) } as usize;
-        if written == 0 || written == !0usize {
-            self.write_str("ls failed\n");
+        let written = unsafe { syscall::listdir(path_buf.as_ptr(), out.as_mut_ptr(), out.len()) };
+        if syscall::is_error(written) {
+            self.write_str("ls: ");
+            self.write_str(syscall::strerror(written));
+            self.write_str("\n");
+            return;
+        }
+        if written == 0 {
             return;
         }
-        let s = unsafe { core::str::from_utf8_unchecked(&out[..written]) };
+        let s = unsafe { core::str::from_utf8_unchecked(&out[..written as usize]) };
         self.write_str(s);
         self.write_str("\n");
     }
@@ -341,17 +348,20 @@ impl Terminal {
         path_buf[count] = 0;
 
         let fd = unsafe { syscall::open(path_buf.as_ptr(), 0) };
-        if fd == !0 {
-            self.write_str("open failed\n");
+        if syscall::is_error(fd) {
+            self.write_str("cat: ");
+            self.write_str(syscall::strerror(fd));
+            self.write_str("\n");
             return;
         }
 
         let mut buf = [0u8; 256];
         loop {
-            let read = unsafe { syscall::read(fd, buf.as_mut_ptr(), buf.len()) } as usize;
-            if read == 0 || read == !0usize {
+            let read = unsafe { syscall::read(fd, buf.as_mut_ptr(), buf.len()) };
+            if read == 0 || syscall::is_error(read) {
                 break;
             }
+            let read = read as usize;
             let s = unsafe { core::str::from_utf8_unchecked(&buf[..read]) };
             self.write_str(s);
         }